@@ -0,0 +1,58 @@
+//! RGBA to luma conversion of a 1080p frame, before and after the move to
+//! fixed-point weights.
+//!
+//! `cargo bench --bench luma` times the original conversion (float weights,
+//! one `put_pixel` per pixel) against `qr::rgba_to_gray`, which fills a
+//! `Vec<u8>` from 8.8 fixed-point weights, and checks that the two agree
+//! within one gray level.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use image::GrayImage;
+use veloqr::qr;
+
+const WIDTH: u32 = 1920;
+const HEIGHT: u32 = 1080;
+const ROUNDS: u32 = 30;
+
+/// The conversion as it was: float weights, bounds-checked per pixel
+fn float_put_pixel(rgba: &[u8], width: u32, height: u32) -> GrayImage {
+    let mut gray = GrayImage::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            let i = ((y * width + x) * 4) as usize;
+            let (r, g, b) = (rgba[i] as f32, rgba[i + 1] as f32, rgba[i + 2] as f32);
+            gray.put_pixel(x, y, image::Luma([(0.299 * r + 0.587 * g + 0.114 * b) as u8]));
+        }
+    }
+    gray
+}
+
+/// Mean time per frame of `convert`, and its last output
+fn time(convert: impl Fn() -> GrayImage) -> (Duration, GrayImage) {
+    let mut gray = convert();
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        gray = black_box(convert());
+    }
+    (start.elapsed() / ROUNDS, gray)
+}
+
+fn main() {
+    // Colors changing from pixel to pixel, opaque like camera frames
+    let rgba: Vec<u8> = (0..WIDTH * HEIGHT)
+        .flat_map(|i| [(i % 251) as u8, (i % 241) as u8, (i % 239) as u8, 255])
+        .collect();
+
+    let (before, float) = time(|| float_put_pixel(black_box(&rgba), WIDTH, HEIGHT));
+    let (after, fixed) = time(|| qr::rgba_to_gray(black_box(&rgba), WIDTH, HEIGHT).unwrap());
+
+    let worst = float.iter().zip(fixed.iter()).map(|(&a, &b)| a.abs_diff(b)).max().unwrap();
+    assert!(worst <= 1, "fixed-point luma is {} levels off", worst);
+
+    println!("{}x{} frame, {} rounds", WIDTH, HEIGHT, ROUNDS);
+    println!("float put_pixel {:>8.2} ms", before.as_secs_f64() * 1000.0);
+    println!("rgba_to_gray    {:>8.2} ms", after.as_secs_f64() * 1000.0);
+    println!("speedup         {:>8.2}x", before.as_secs_f64() / after.as_secs_f64());
+}
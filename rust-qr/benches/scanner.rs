@@ -0,0 +1,94 @@
+//! Heap allocations per frame of the free decode functions against a
+//! reused `QrScanner`.
+//!
+//! `cargo bench --bench scanner` decodes the same 640x480 frame both ways
+//! under a counting allocator. The free functions allocate a fresh
+//! grayscale image and result vector every frame; the scanner keeps its
+//! buffers, so after the first frame what is left is rqrr's working memory
+//! and the strings of the results themselves.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use image::GrayImage;
+use veloqr::qr;
+
+const WIDTH: u32 = 640;
+const HEIGHT: u32 = 480;
+const ROUNDS: u32 = 100;
+
+/// The system allocator, counting allocations and bytes requested
+struct Counting;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static BYTES: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        // SAFETY: forwarded unchanged from the caller
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // SAFETY: `ptr` came from `alloc` above, which is `System.alloc`
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(new_size as u64, Ordering::Relaxed);
+        // SAFETY: forwarded unchanged from the caller
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// An RGBA frame with one code in it
+fn frame() -> Vec<u8> {
+    let mut frame = GrayImage::from_pixel(WIDTH, HEIGHT, image::Luma([255]));
+    let png = qr::encode_png("https://example.com/scanner", "M", 6, 4).unwrap();
+    let symbol = image::load_from_memory(&png).unwrap().into_luma8();
+    image::imageops::replace(&mut frame, &symbol, 200, 120);
+    frame.pixels().flat_map(|p| [p.0[0], p.0[0], p.0[0], 255]).collect()
+}
+
+/// Mean allocations, bytes and time per call of `scan` after a warm-up
+/// call, checking that every call finds the code
+fn measure(mut scan: impl FnMut() -> usize) -> (u64, u64, Duration) {
+    assert_eq!(scan(), 1, "the code decodes");
+    let (allocations, bytes) = (ALLOCATIONS.load(Ordering::Relaxed), BYTES.load(Ordering::Relaxed));
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        assert_eq!(scan(), 1, "the code decodes");
+    }
+    let elapsed = start.elapsed();
+    (
+        (ALLOCATIONS.load(Ordering::Relaxed) - allocations) / ROUNDS as u64,
+        (BYTES.load(Ordering::Relaxed) - bytes) / ROUNDS as u64,
+        elapsed / ROUNDS,
+    )
+}
+
+fn main() {
+    let rgba = frame();
+    let free = measure(|| qr::decode_image(qr::rgba_to_gray(&rgba, WIDTH, HEIGHT).unwrap()).len());
+    let mut scanner = qr::QrScanner::new(WIDTH, HEIGHT);
+    let reused = measure(|| scanner.scan(&rgba, None, None).unwrap().len());
+
+    assert!(reused.1 < free.1, "the scanner allocates less per frame");
+    println!("{}x{} frame, {} rounds", WIDTH, HEIGHT, ROUNDS);
+    for (name, (allocations, bytes, time)) in [("free functions", free), ("QrScanner", reused)] {
+        println!(
+            "{:<15} {:>6} allocations {:>10} bytes {:>8.2} ms per frame",
+            name,
+            allocations,
+            bytes,
+            time.as_secs_f64() * 1000.0
+        );
+    }
+}
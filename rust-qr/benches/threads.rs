@@ -0,0 +1,78 @@
+//! Serial against parallel decoding of one frame holding eight QR codes.
+//!
+//! `cargo bench --features threads --bench threads` runs both on the same
+//! build: once inside a one-thread rayon pool, which converts the bands and
+//! decodes the grids one after another, and once on the global pool
+//! (`RAYON_NUM_THREADS` threads, or one per core). The results must be
+//! identical; the timings show the speedup.
+
+use std::time::{Duration, Instant};
+
+use image::{GrayImage, ImageReader};
+use veloqr::qr;
+
+const CODES: u32 = 8;
+const COLUMNS: u32 = 4;
+const CELL: u32 = 480;
+const ROUNDS: u32 = 20;
+
+/// An RGBA frame with `CODES` different codes laid out on a grid
+fn frame() -> (Vec<u8>, u32, u32) {
+    let (width, height) = (COLUMNS * CELL, CODES.div_ceil(COLUMNS) * CELL);
+    let mut frame = GrayImage::from_pixel(width, height, image::Luma([255]));
+    for code in 0..CODES {
+        let data = format!("https://example.com/shelf/{}?batch=2026-10-14", code);
+        let png = qr::encode_png(&data, "M", 8, 4).unwrap();
+        let reader = ImageReader::new(std::io::Cursor::new(png)).with_guessed_format().unwrap();
+        let symbol = reader.decode().unwrap().into_luma8();
+        let (x, y) = ((code % COLUMNS) * CELL, (code / COLUMNS) * CELL);
+        image::imageops::replace(&mut frame, &symbol, x.into(), y.into());
+    }
+    let rgba = frame.pixels().flat_map(|p| [p.0[0], p.0[0], p.0[0], 255]).collect();
+    (rgba, width, height)
+}
+
+/// Mean conversion and decode time per frame, and the last results
+fn run(rgba: &[u8], width: u32, height: u32) -> (Duration, Duration, Vec<qr::QRCodeResult>) {
+    let (mut convert, mut decode) = (Duration::ZERO, Duration::ZERO);
+    let mut results = Vec::new();
+    for _ in 0..ROUNDS {
+        let start = Instant::now();
+        let gray = qr::rgba_to_gray(rgba, width, height).unwrap();
+        convert += start.elapsed();
+        let start = Instant::now();
+        results = qr::decode_image(gray);
+        decode += start.elapsed();
+    }
+    (convert / ROUNDS, decode / ROUNDS, results)
+}
+
+fn summary(results: &[qr::QRCodeResult]) -> Vec<(String, Vec<(f64, f64)>)> {
+    results.iter().map(|code| (code.data.clone(), code.bounds.clone())).collect()
+}
+
+fn main() {
+    let (rgba, width, height) = frame();
+    let serial = rayon::ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+    let (serial_convert, serial_decode, serial_results) =
+        serial.install(|| run(&rgba, width, height));
+    let (convert, decode, results) = run(&rgba, width, height);
+
+    assert_eq!(serial_results.len(), CODES as usize, "every code decodes");
+    assert_eq!(summary(&results), summary(&serial_results), "parallel results differ");
+
+    let threads = rayon::current_num_threads();
+    println!("{}x{} frame, {} codes, {} rounds", width, height, CODES, ROUNDS);
+    for (stage, serial, parallel) in
+        [("rgba_to_gray", serial_convert, convert), ("decode_image", serial_decode, decode)]
+    {
+        println!(
+            "{:<13} 1 thread {:>8.2} ms   {} threads {:>8.2} ms   {:.2}x",
+            stage,
+            serial.as_secs_f64() * 1000.0,
+            threads,
+            parallel.as_secs_f64() * 1000.0,
+            serial.as_secs_f64() / parallel.as_secs_f64()
+        );
+    }
+}
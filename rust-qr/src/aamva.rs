@@ -0,0 +1,408 @@
+//! AAMVA DL/ID card payloads, as carried in the PDF417 on North American
+//! driver licenses: `@` compliance indicator, the three separators, the
+//! `ANSI ` header, subfile designators and `IDvalue` data elements
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::mrz::CivilDate;
+use crate::VeloQrError;
+
+/// First byte of every AAMVA file, followed by the three separators
+const COMPLIANCE_INDICATOR: char = '@';
+
+/// One subfile (`DL`, `ID` or a jurisdiction-specific `Z?`) with its elements
+/// keyed by three-letter element ID
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct AamvaSubfile {
+    pub subfile_type: String,
+    pub elements: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct AamvaAddress {
+    pub street: Option<String>,       // DAG
+    pub street_2: Option<String>,     // DAH
+    pub city: Option<String>,         // DAI
+    pub jurisdiction: Option<String>, // DAJ, e.g. "CA"
+    pub postal_code: Option<String>,  // DAK, ZIP+4 as 12345-6789
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct AamvaData {
+    /// Issuer identification number of the jurisdiction, e.g. 636014
+    pub issuer_id: String,
+    pub version: u32,
+    /// Absent in version 01 headers
+    pub jurisdiction_version: Option<u32>,
+    /// `DL` or `ID`
+    pub document_type: String,
+    pub license_number: Option<String>, // DAQ
+    pub family_name: Option<String>,
+    pub first_name: Option<String>,
+    pub middle_name: Option<String>,
+    pub date_of_birth: Option<String>, // YYYY-MM-DD
+    pub expiry: Option<String>,        // YYYY-MM-DD
+    pub issue_date: Option<String>,    // YYYY-MM-DD
+    pub sex: Option<String>,           // M, F or X
+    pub address: AamvaAddress,
+    pub country: Option<String>, // DCG, USA or CAN
+    /// DDA: `F` for REAL ID compliant cards, `N` otherwise
+    pub compliance_type: Option<String>,
+    /// Every subfile in file order, jurisdiction-specific ones included
+    pub subfiles: Vec<AamvaSubfile>,
+}
+
+/// Separators declared by the header; LF, RS and CR in every standard file
+struct Separators {
+    element: char,
+    segment: char,
+}
+
+/// Parse the raw bytes of an AAMVA card payload. Bytes are read as
+/// ISO-8859-1; a leading `]L2` symbology identifier is skipped.
+pub fn parse(data: &[u8]) -> Result<AamvaData, VeloQrError> {
+    let text: String = data.iter().map(|&b| char::from(b)).collect();
+    let text = text.trim_start_matches(|c: char| c.is_ascii_whitespace());
+    let text = text.strip_prefix("]L2").unwrap_or(text);
+    let chars: Vec<char> = text.chars().collect();
+
+    if chars.len() < 4 || chars[0] != COMPLIANCE_INDICATOR {
+        return Err(invalid("missing @ compliance indicator"));
+    }
+    let separators = Separators { element: chars[1], segment: chars[3] };
+
+    let mut header = Header { chars: &chars, pos: 4 };
+    if !(header.take_literal("ANSI") || header.take_literal("AAMVA")) {
+        return Err(invalid("missing ANSI file type"));
+    }
+    header.take_literal(" ");
+    let issuer_id = header.take_digits(6).ok_or_else(|| invalid("bad issuer number"))?;
+    let version: u32 = header.number(2).ok_or_else(|| invalid("bad AAMVA version"))?;
+    let jurisdiction_version = if version >= 2 {
+        Some(header.number(2).ok_or_else(|| invalid("bad jurisdiction version"))?)
+    } else {
+        None
+    };
+    let entries: usize = header.number(2).ok_or_else(|| invalid("bad number of entries"))?;
+
+    let mut designators = Vec::new();
+    for _ in 0..entries {
+        let subfile_type = header.take(2).ok_or_else(|| invalid("truncated subfile designator"))?;
+        let offset: usize = header.number(4).ok_or_else(|| invalid("bad subfile offset"))?;
+        let length: usize = header.number(4).ok_or_else(|| invalid("bad subfile length"))?;
+        designators.push((subfile_type, offset, length));
+    }
+
+    let subfiles = designators
+        .iter()
+        .map(|(subfile_type, offset, length)| {
+            let body = subfile_body(&chars, header.pos, subfile_type, *offset, *length)
+                .ok_or_else(|| invalid(&format!("subfile {} not found", subfile_type)))?;
+            Ok(AamvaSubfile {
+                subfile_type: subfile_type.clone(),
+                elements: parse_elements(body, &separators),
+            })
+        })
+        .collect::<Result<Vec<_>, VeloQrError>>()?;
+
+    let card = subfiles
+        .iter()
+        .find(|s| s.subfile_type == "DL" || s.subfile_type == "ID")
+        .ok_or_else(|| invalid("no DL or ID subfile"))?;
+    Ok(card_data(card, issuer_id, version, jurisdiction_version, &subfiles))
+}
+
+fn invalid(message: &str) -> VeloQrError {
+    VeloQrError::InvalidPayload(format!("AAMVA: {}", message))
+}
+
+/// Cursor over the fixed-width header fields
+struct Header<'a> {
+    chars: &'a [char],
+    pos: usize,
+}
+
+impl Header<'_> {
+    fn take(&mut self, count: usize) -> Option<String> {
+        let field = self.chars.get(self.pos..self.pos + count)?;
+        self.pos += count;
+        Some(field.iter().collect())
+    }
+
+    fn take_literal(&mut self, literal: &str) -> bool {
+        let count = literal.chars().count();
+        let matches = self
+            .chars
+            .get(self.pos..self.pos + count)
+            .is_some_and(|field| field.iter().copied().eq(literal.chars()));
+        if matches {
+            self.pos += count;
+        }
+        matches
+    }
+
+    fn take_digits(&mut self, count: usize) -> Option<String> {
+        let start = self.pos;
+        let field = self.take(count)?;
+        if field.chars().all(|c| c.is_ascii_digit()) {
+            Some(field)
+        } else {
+            self.pos = start;
+            None
+        }
+    }
+
+    fn number<T: std::str::FromStr>(&mut self, count: usize) -> Option<T> {
+        self.take_digits(count)?.parse().ok()
+    }
+}
+
+/// Text of a subfile after its type code. Offsets are counted from the
+/// compliance indicator; when an issuer got them wrong, fall back to the
+/// first occurrence of the type code after the designators.
+fn subfile_body<'a>(
+    chars: &'a [char],
+    header_end: usize,
+    subfile_type: &str,
+    offset: usize,
+    length: usize,
+) -> Option<&'a [char]> {
+    let starts_with_type = |at: usize| {
+        chars
+            .get(at..at + 2)
+            .is_some_and(|c| c.iter().copied().eq(subfile_type.chars()))
+    };
+    let start = if starts_with_type(offset) {
+        offset
+    } else {
+        (header_end..chars.len().saturating_sub(1)).find(|&at| starts_with_type(at))?
+    };
+    let end = (start + length).min(chars.len());
+    chars.get(start + 2..end.max(start + 2))
+}
+
+/// `IDvalue` elements split on the element separator, up to the segment
+/// terminator. The first occurrence of an ID wins.
+fn parse_elements(body: &[char], separators: &Separators) -> BTreeMap<String, String> {
+    let body: String = body.iter().collect();
+    let body = body.split(separators.segment).next().unwrap_or_default();
+    let mut elements = BTreeMap::new();
+    for element in body.split(separators.element) {
+        let element = element.trim_matches(|c: char| c.is_control());
+        let Some(id) = element.get(..3).filter(|id| id.bytes().all(|b| b.is_ascii_alphanumeric()))
+        else {
+            continue;
+        };
+        let value = element[3..].trim();
+        elements.entry(id.to_string()).or_insert_with(|| value.to_string());
+    }
+    elements
+}
+
+fn card_data(
+    card: &AamvaSubfile,
+    issuer_id: String,
+    version: u32,
+    jurisdiction_version: Option<u32>,
+    subfiles: &[AamvaSubfile],
+) -> AamvaData {
+    let get = |id: &str| {
+        card.elements
+            .get(id)
+            .filter(|value| !value.is_empty())
+            .cloned()
+    };
+    let country = get("DCG");
+    // Canadian cards and version 01 files write CCYYMMDD, US cards MMDDCCYY
+    let year_first = country.as_deref() == Some("CAN") || version < 2;
+    let date = |id: &str| get(id).and_then(|value| aamva_date(&value, year_first));
+
+    let (family_name, first_name, middle_name) = names(&get);
+
+    AamvaData {
+        issuer_id,
+        version,
+        jurisdiction_version,
+        document_type: card.subfile_type.clone(),
+        license_number: get("DAQ"),
+        family_name,
+        first_name,
+        middle_name,
+        date_of_birth: date("DBB"),
+        expiry: date("DBA"),
+        issue_date: date("DBD"),
+        sex: get("DBC").and_then(|sex| match sex.as_str() {
+            "1" | "M" => Some("M".to_string()),
+            "2" | "F" => Some("F".to_string()),
+            "9" | "X" => Some("X".to_string()),
+            _ => None,
+        }),
+        address: AamvaAddress {
+            street: get("DAG"),
+            street_2: get("DAH"),
+            city: get("DAI"),
+            jurisdiction: get("DAJ"),
+            postal_code: get("DAK").map(|code| postal_code(&code)),
+        },
+        country,
+        compliance_type: get("DDA"),
+        subfiles: subfiles.to_vec(),
+    }
+}
+
+/// Family, first and middle names across the versions: DCS/DAC/DAD since
+/// 2009, DCS/DCT (given names) in 2005 and DAB or a comma-separated DAA in
+/// version 01. `NONE` and `unavl` mark a missing name.
+fn names(
+    get: &dyn Fn(&str) -> Option<String>,
+) -> (Option<String>, Option<String>, Option<String>) {
+    let present = |name: Option<String>| {
+        name.filter(|name| !["NONE", "unavl"].iter().any(|m| name.eq_ignore_ascii_case(m)))
+    };
+    let split_given = |given: String| {
+        let mut parts = given.splitn(2, [',', ' ']);
+        let first = parts.next().map(|s| s.trim().to_string());
+        let middle = parts.next().map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+        (first, middle)
+    };
+
+    let mut family = get("DCS").or_else(|| get("DAB"));
+    let (mut first, mut middle) = (get("DAC"), get("DAD"));
+    if first.is_none() {
+        if let Some(given) = get("DCT") {
+            (first, middle) = split_given(given);
+        }
+    }
+    if family.is_none() || first.is_none() {
+        if let Some(full) = get("DAA") {
+            let mut parts = full.split([',', '$']).map(|s| s.trim().to_string());
+            family = family.or(parts.next());
+            first = first.or(parts.next());
+            middle = middle.or(parts.next().filter(|s| !s.is_empty()));
+        }
+    }
+    (present(family), present(first), present(middle))
+}
+
+/// MMDDCCYY or CCYYMMDD as ISO, trying the other order when the expected
+/// one is not a valid date
+fn aamva_date(value: &str, year_first: bool) -> Option<String> {
+    if value.len() != 8 || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let field = |range: std::ops::Range<usize>| value[range].parse().ok();
+    let month_first = || CivilDate::new(field(4..8)?, field(0..2)? as u32, field(2..4)? as u32);
+    let ccyy_first = || CivilDate::new(field(0..4)?, field(4..6)? as u32, field(6..8)? as u32);
+    let date = if year_first {
+        ccyy_first().or_else(month_first)
+    } else {
+        month_first().or_else(ccyy_first)
+    };
+    date.map(CivilDate::to_iso)
+}
+
+/// US codes are 9 digits padded with `0000` when the +4 is unknown
+fn postal_code(code: &str) -> String {
+    let code = code.trim();
+    if code.len() == 9 && code.bytes().all(|b| b.is_ascii_digit()) {
+        match &code[5..] {
+            "0000" => code[..5].to_string(),
+            plus_four => format!("{}-{}", &code[..5], plus_four),
+        }
+    } else {
+        code.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A payload with the header and subfile designators laid out as
+    /// AAMVA DL/ID-2020 defines them; version 01 has no jurisdiction version
+    fn file(version: &str, subfiles: &[(&str, &str)]) -> Vec<u8> {
+        let mut header = format!("@\n\x1e\rANSI 636014{}", version);
+        if version != "01" {
+            header.push_str("00");
+        }
+        header.push_str(&format!("{:02}", subfiles.len()));
+        let mut offset = header.len() + subfiles.len() * 10;
+        let mut designators = String::new();
+        let mut bodies = String::new();
+        for (kind, body) in subfiles {
+            let text = format!("{}{}\r", kind, body);
+            designators.push_str(&format!("{}{:04}{:04}", kind, offset, text.len()));
+            offset += text.len();
+            bodies.push_str(&text);
+        }
+        format!("{}{}{}", header, designators, bodies).into_bytes()
+    }
+
+    #[test]
+    fn reads_a_version_9_license() {
+        let dl = "DAQD1234567\nDCSPUBLIC\nDACJOHN\nDADQUINCY\nDBB01311990\nDBA01312030\n\
+                  DBD02012022\nDBC1\nDAG123 MAIN ST\nDAISACRAMENTO\nDAJCA\nDAK958230000  \n\
+                  DCGUSA\nDDAF";
+        let data = parse(&file("09", &[("DL", dl), ("ZC", "ZCAFOO\nZCBBAR")])).unwrap();
+        assert_eq!(data.issuer_id, "636014");
+        assert_eq!(data.version, 9);
+        assert_eq!(data.jurisdiction_version, Some(0));
+        assert_eq!(data.document_type, "DL");
+        assert_eq!(data.license_number.as_deref(), Some("D1234567"));
+        assert_eq!(data.family_name.as_deref(), Some("PUBLIC"));
+        assert_eq!(data.first_name.as_deref(), Some("JOHN"));
+        assert_eq!(data.middle_name.as_deref(), Some("QUINCY"));
+        assert_eq!(data.date_of_birth.as_deref(), Some("1990-01-31"));
+        assert_eq!(data.expiry.as_deref(), Some("2030-01-31"));
+        assert_eq!(data.issue_date.as_deref(), Some("2022-02-01"));
+        assert_eq!(data.sex.as_deref(), Some("M"));
+        assert_eq!(data.address.street.as_deref(), Some("123 MAIN ST"));
+        assert_eq!(data.address.city.as_deref(), Some("SACRAMENTO"));
+        assert_eq!(data.address.jurisdiction.as_deref(), Some("CA"));
+        assert_eq!(data.address.postal_code.as_deref(), Some("95823"));
+        assert_eq!(data.compliance_type.as_deref(), Some("F"));
+        assert_eq!(data.subfiles.len(), 2);
+        assert_eq!(data.subfiles[1].elements.get("ZCB").map(String::as_str), Some("BAR"));
+    }
+
+    #[test]
+    fn tolerates_canadian_dates_and_bad_offsets() {
+        let dl = "DAQ123\nDCSSMITH\nDCTJANE ANN\nDBB19851224\nDBA20300101\nDBC2\n\
+                  DAKK1A 0B1\nDCGCAN";
+        let text = String::from_utf8(file("03", &[("DL", dl)])).unwrap();
+        // Scanners prefix the AIM identifier; the offset points into the header
+        let data = format!("]L2{}", text.replacen("DL00", "DL07", 1));
+        let data = parse(data.as_bytes()).unwrap();
+        assert_eq!(data.first_name.as_deref(), Some("JANE"));
+        assert_eq!(data.middle_name.as_deref(), Some("ANN"));
+        assert_eq!(data.date_of_birth.as_deref(), Some("1985-12-24"));
+        assert_eq!(data.sex.as_deref(), Some("F"));
+        assert_eq!(data.address.postal_code.as_deref(), Some("K1A 0B1"));
+    }
+
+    #[test]
+    fn splits_the_version_1_full_name() {
+        let id = "DAAPUBLIC,JOHN,Q\nDAQX9\nDBB19700704\nDAK941031234";
+        let data = parse(&file("01", &[("ID", id)])).unwrap();
+        assert_eq!(data.jurisdiction_version, None);
+        assert_eq!(data.document_type, "ID");
+        assert_eq!(data.family_name.as_deref(), Some("PUBLIC"));
+        assert_eq!(data.first_name.as_deref(), Some("JOHN"));
+        assert_eq!(data.middle_name.as_deref(), Some("Q"));
+        assert_eq!(data.date_of_birth.as_deref(), Some("1970-07-04"));
+        assert_eq!(data.address.postal_code.as_deref(), Some("94103-1234"));
+
+        let data = parse(&file("08", &[("DL", "DCSA\nDACB\nDADNONE")])).unwrap();
+        assert_eq!(data.middle_name, None);
+    }
+
+    #[test]
+    fn rejects_other_payloads() {
+        for bad in [&b""[..], b"hello", b"@\n\x1e\rANSI 63", b"@\n\x1e\rANSI 6360140100"] {
+            assert_eq!(parse(bad).unwrap_err().code(), "INVALID_PAYLOAD");
+        }
+        // A jurisdiction subfile alone carries no card
+        assert!(parse(&file("08", &[("ZC", "ZCAX")])).is_err());
+    }
+}
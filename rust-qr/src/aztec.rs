@@ -0,0 +1,1186 @@
+//! Aztec code detection and decoding.
+//!
+//! Candidates are bullseye centers: a dark module with three equal rings on
+//! either side, checked along the row, the column and the diagonals. Lines
+//! fitted to the ring edges meet at corners that give a homography in module
+//! units, refined with the reference grid crossings of large full-range
+//! symbols. The mode message around the bullseye (Reed-Solomon over GF(16))
+//! gives the layer and data codeword counts; the layers are read outermost
+//! first, corrected over GF(2^6) to GF(2^12), unstuffed and decoded from the
+//! character tables.
+
+use image::GrayImage;
+
+use crate::qr::{self, transform, Homography, PointPair, QRCodeResult, Symbology};
+use crate::reed_solomon::{self, GaloisField};
+
+/// Mode message field, x^4 + x + 1
+const MODE_PRIMITIVE: u32 = 0x13;
+
+/// Smallest bullseye module, in pixels
+const MIN_MODULE_PX: f64 = 1.5;
+
+/// Fraction of the bullseye modules that must show their color in the
+/// binarized image, once the frame is fitted to the ring edges
+const MIN_BULLSEYE_MATCH: f64 = 0.9;
+
+/// The same through the first frame, which can be half a module off at
+/// the second ring of a blurred, rotated symbol
+const MIN_FRAME_MATCH: f64 = 0.8;
+
+/// Longest to shortest ring span through the center; sqrt(2) for a square,
+/// with room for pixel rounding
+const MAX_SPAN_RATIO: f64 = 1.7;
+
+/// Orientation modules (of twelve) that must match their expected color
+const MIN_ORIENTATION_MATCH: usize = 10;
+
+/// Fraction of the light/dark contrast a ring edge or grid crossing must show
+const MIN_EDGE_CONTRAST: f64 = 0.1;
+const MIN_CROSSING_CONTRAST: f64 = 0.35;
+
+/// Crossing offsets scoring within this fraction of the contrast of the best
+const PLATEAU_TOLERANCE: f64 = 0.05;
+
+/// Module-space points and their image positions for [`Homography::fit`]
+type Pairs = Vec<PointPair>;
+
+/// Side length in modules for a version: the layer count, negative for
+/// compact symbols
+pub(crate) fn dimensions(version: i32) -> Option<u32> {
+    match version {
+        -4..=-1 => Some(11 + 4 * version.unsigned_abs()),
+        1..=32 => {
+            let base = 14 + 4 * version as u32;
+            Some(base + 1 + 2 * ((base / 2 - 1) / 15))
+        }
+        _ => None,
+    }
+}
+
+/// Find and decode every Aztec symbol in a grayscale image
+pub(crate) fn decode(gray: &GrayImage) -> Vec<QRCodeResult> {
+    let (width, height) = gray.dimensions();
+    if width.min(height) < 15 {
+        return Vec::new();
+    }
+
+    let mut binary = gray.clone();
+    let window = (width.min(height) / 8).max(15);
+    qr::local_threshold(&mut binary, window, |mean, _| mean - qr::ADAPTIVE_OFFSET);
+
+    let mut results: Vec<QRCodeResult> = Vec::new();
+    for bullseye in find_bullseyes(&binary) {
+        if results.iter().any(|r| qr::contains(&r.bounds, bullseye.center)) {
+            continue;
+        }
+        if let Some(result) = decode_candidate(gray, &binary, &bullseye) {
+            results.push(result);
+        }
+    }
+
+    log_debug!("Decoded {} Aztec symbols", results.len());
+    results
+}
+
+// ==================== Detection ====================
+
+struct Bullseye {
+    center: (f64, f64),
+    module: f64,
+}
+
+/// Seven runs of about the same width, light to light, between dark runs:
+/// the center module and the first three rings on either side of it.
+/// Returns the middle of the center run and the module width.
+fn ring_pattern(runs: &[(usize, usize)], first: usize) -> Option<(f64, f64)> {
+    if first == 0 || first + 7 >= runs.len() {
+        return None;
+    }
+    let window = &runs[first..first + 7];
+    let module = window.iter().map(|r| r.1).sum::<usize>() as f64 / 7.0;
+    let even = window
+        .iter()
+        .all(|r| (r.1 as f64 - module).abs() <= module * 0.5 + 0.5);
+    let bounded = [runs[first - 1].1, runs[first + 7].1]
+        .iter()
+        .all(|&length| length as f64 >= module * 0.5);
+    (even && bounded && module >= MIN_MODULE_PX)
+        .then(|| (window[3].0 as f64 + window[3].1 as f64 / 2.0, module))
+}
+
+/// Bullseye centers found on a row and confirmed on the column and the
+/// diagonals through them
+fn find_bullseyes(binary: &GrayImage) -> Vec<Bullseye> {
+    let (width, height) = (binary.width() as usize, binary.height() as usize);
+    let raw = binary.as_raw();
+    let mut found: Vec<Bullseye> = Vec::new();
+    let mut row = Vec::new();
+    for y in 0..height {
+        let first_dark = qr::runs(raw[y * width..(y + 1) * width].iter().copied(), &mut row);
+        // Light runs are the odd ones when the row starts dark
+        let start = if first_dark { 1 } else { 0 };
+        for first in (start..row.len()).step_by(2) {
+            let Some((cx, module)) = ring_pattern(&row, first) else {
+                continue;
+            };
+            // Rows through the center module repeat the same pattern
+            let seen = |b: &Bullseye| {
+                (b.center.0 - cx).abs() < b.module && (b.center.1 - y as f64).abs() < b.module
+            };
+            // Found in row order, so only the last few rows can hold a repeat
+            let recent = found
+                .iter()
+                .rev()
+                .take_while(|b| b.center.1 > y as f64 - 2.0 * b.module - 1.0);
+            if recent.clone().any(seen) {
+                continue;
+            }
+            if let Some(bullseye) = confirm_bullseye(binary, (cx, y), module) {
+                found.push(bullseye);
+            }
+        }
+    }
+    found
+}
+
+/// Module width along a line through the center, given the steps to the
+/// ring edges both ways, when the seven runs are about the same width
+fn even_runs(forward: [i64; 4], backward: [i64; 4]) -> Option<f64> {
+    let lengths = [
+        forward[3] - forward[2],
+        forward[2] - forward[1],
+        forward[1] - forward[0],
+        forward[0] + backward[0] - 1,
+        backward[1] - backward[0],
+        backward[2] - backward[1],
+        backward[3] - backward[2],
+    ];
+    let module = (forward[3] + backward[3] - 1) as f64 / 7.0;
+    lengths
+        .iter()
+        .all(|&length| (length as f64 - module).abs() <= module * 0.5 + 0.5)
+        .then_some(module)
+}
+
+/// The row pattern checked down the column and along both diagonals. The
+/// rings are squares rather than stripes when the outer edge of the third
+/// ring is never more than about sqrt(2) times as far from the center in
+/// one direction as in another; stripes of any angle fail one of the four.
+fn confirm_bullseye(binary: &GrayImage, (cx, y): (f64, usize), module: f64) -> Option<Bullseye> {
+    let x = cx as i64;
+    let limit = (module * 8.0) as i64 + 2;
+    let up = qr::color_steps(binary, (x, y as i64), (0, -1), limit)?;
+    let down = qr::color_steps(binary, (x, y as i64), (0, 1), limit)?;
+    let vertical = even_runs(down, up)?;
+    let center = (cx, y as f64 + (down[0] - up[0] + 1) as f64 / 2.0);
+
+    let (mut shortest, mut longest) = (module.min(vertical), module.max(vertical));
+    for (dx, dy) in [(1, 1), (1, -1)] {
+        if longest > shortest * MAX_SPAN_RATIO {
+            return None;
+        }
+        let start = (x, center.1 as i64);
+        let forward = qr::color_steps(binary, start, (dx, dy), limit)?;
+        let backward = qr::color_steps(binary, start, (-dx, -dy), limit)?;
+        let diagonal = even_runs(forward, backward)? * std::f64::consts::SQRT_2;
+        shortest = shortest.min(diagonal);
+        longest = longest.max(diagonal);
+    }
+    (longest <= shortest * MAX_SPAN_RATIO).then_some(Bullseye {
+        center,
+        module: (module + vertical) / 2.0,
+    })
+}
+
+/// Ring corners at the given radii, even rings being dark
+fn ring_corners(gray: &GrayImage, map: &Homography, radii: &[f64], contrast: f64) -> Pairs {
+    let edges: Vec<(f64, bool)> = radii.iter().map(|&r| (r, (r - 0.5) as i32 % 2 == 0)).collect();
+    qr::ring_corners(gray, map, &edges, contrast * MIN_EDGE_CONTRAST)
+}
+
+/// Light-dark decision for modules of one candidate
+struct Sampler<'a> {
+    gray: &'a GrayImage,
+    map: Homography,
+    threshold: f64,
+}
+
+impl Sampler<'_> {
+    /// Module at symbol offset `(x, y)` from the center under a symmetry
+    fn dark(&self, symmetry: usize, x: i32, y: i32) -> bool {
+        let (u, v) = transform(symmetry, x as f64, y as f64);
+        qr::sample(self.gray, self.map.apply(u, v)) < self.threshold
+    }
+
+    /// Dark fraction of the square ring at Chebyshev radius `r`
+    fn ring_darkness(&self, r: i32) -> f64 {
+        let cells = ring(r);
+        let dark = cells.iter().filter(|&&(x, y)| self.dark(0, x, y)).count();
+        dark as f64 / cells.len() as f64
+    }
+}
+
+/// Modules of the square ring at Chebyshev radius `r`, clockwise
+fn ring(r: i32) -> Vec<(i32, i32)> {
+    if r == 0 {
+        return vec![(0, 0)];
+    }
+    (-r..r)
+        .flat_map(|t| [(t, -r), (r, t), (-t, r), (-r, -t)])
+        .collect()
+}
+
+/// Mean gray level of the square rings at the given radii
+fn ring_mean(gray: &GrayImage, map: &Homography, radii: &[i32]) -> f64 {
+    let cells: Vec<(i32, i32)> = radii.iter().flat_map(|&r| ring(r)).collect();
+    let total: f64 = cells
+        .iter()
+        .map(|&(x, y)| qr::sample(gray, map.apply(x as f64, y as f64)))
+        .sum();
+    total / cells.len() as f64
+}
+
+fn decode_candidate(
+    gray: &GrayImage,
+    binary: &GrayImage,
+    bullseye: &Bullseye,
+) -> Option<QRCodeResult> {
+    // The first frame only follows the second ring closely
+    // The outer edge of the first dark ring is a square at +-2.5 modules
+    let mut map = qr::square_frame(binary, bullseye.center, bullseye.module * 8.0, 2.5)?;
+    if !bullseye_matches(binary, &map, 2, MIN_FRAME_MATCH) {
+        return None;
+    }
+    let mut contrast = ring_mean(gray, &map, &[1, 3]) - ring_mean(gray, &map, &[0, 2]);
+    for _ in 0..2 {
+        map = Homography::fit(&ring_corners(gray, &map, &[1.5, 2.5, 3.5], contrast))?;
+        contrast = ring_mean(gray, &map, &[1, 3]) - ring_mean(gray, &map, &[2, 4]);
+    }
+    if contrast <= 0.0 || !bullseye_matches(binary, &map, 4, MIN_BULLSEYE_MATCH) {
+        return None;
+    }
+    let threshold = ring_mean(gray, &map, &[2, 4]) + contrast / 2.0;
+    let mut sampler = Sampler { gray, map, threshold };
+
+    // Full-range symbols have two more rings: light at 5, dark at 6
+    let full = sampler.ring_darkness(5) < 0.15 && sampler.ring_darkness(6) > 0.85;
+    if full {
+        let radii = [1.5, 2.5, 3.5, 4.5, 5.5];
+        for _ in 0..2 {
+            sampler.map = Homography::fit(&ring_corners(gray, &sampler.map, &radii, contrast))?;
+        }
+    }
+
+    let mut orientations: Vec<(usize, usize)> = (0..8)
+        .map(|symmetry| (orientation_score(&sampler, symmetry, full), symmetry))
+        .filter(|&(score, _)| score >= MIN_ORIENTATION_MATCH)
+        .collect();
+    orientations.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+    orientations
+        .into_iter()
+        .find_map(|(_, symmetry)| read_symbol(&sampler, symmetry, full, contrast))
+}
+
+/// Whether the squares up to radius `rings` alternate dark and light in
+/// the binarized image, a cheap check before the slower steps
+fn bullseye_matches(binary: &GrayImage, map: &Homography, rings: i32, min_match: f64) -> bool {
+    let cells: Vec<((i32, i32), bool)> = (0..=rings)
+        .flat_map(|r| ring(r).into_iter().map(move |cell| (cell, r % 2 == 0)))
+        .collect();
+    let matching = cells
+        .iter()
+        .filter(|&&((x, y), dark)| qr::is_dark(binary, map.apply(x as f64, y as f64)) == dark)
+        .count();
+    matching as f64 >= cells.len() as f64 * min_match
+}
+
+/// Matching modules of the orientation marks at the corners of the mode
+/// message ring: three dark at the top left, two at the top right, one at
+/// the bottom right and none at the bottom left
+fn orientation_score(sampler: &Sampler, symmetry: usize, full: bool) -> usize {
+    let s = if full { 7 } else { 5 };
+    let expected = [
+        ((-s, -s), true),
+        ((-s + 1, -s), true),
+        ((-s, -s + 1), true),
+        ((s, -s), true),
+        ((s, -s + 1), true),
+        ((s, s - 1), true),
+        ((s - 1, -s), false),
+        ((s, s), false),
+        ((s - 1, s), false),
+        ((-s, s), false),
+        ((-s + 1, s), false),
+        ((-s, s - 1), false),
+    ];
+    expected
+        .iter()
+        .filter(|&&((x, y), dark)| sampler.dark(symmetry, x, y) == dark)
+        .count()
+}
+
+// ==================== Symbol ====================
+
+struct Layout {
+    compact: bool,
+    layers: usize,
+    data_words: usize,
+}
+
+impl Layout {
+    fn base_size(&self) -> usize {
+        (if self.compact { 11 } else { 14 }) + 4 * self.layers
+    }
+
+    fn size(&self) -> usize {
+        let base = self.base_size();
+        if self.compact {
+            base
+        } else {
+            base + 1 + 2 * ((base / 2 - 1) / 15)
+        }
+    }
+
+    fn total_bits(&self) -> usize {
+        ((if self.compact { 88 } else { 112 }) + 16 * self.layers) * self.layers
+    }
+
+    /// Codeword size in bits and the primitive polynomial of its field
+    fn word(&self) -> (usize, u32) {
+        match self.layers {
+            0..=2 => (6, 0x43),
+            3..=8 => (8, 0x12D),
+            9..=22 => (10, 0x409),
+            _ => (12, 0x1069),
+        }
+    }
+
+    /// Symbol offset of every base-matrix index, skipping the reference grid
+    fn alignment_map(&self) -> Vec<i32> {
+        let base = self.base_size();
+        if self.compact {
+            let center = (base / 2) as i32;
+            return (0..base as i32).map(|i| i - center).collect();
+        }
+        let half = base / 2;
+        let mut map = vec![0i32; base];
+        for i in 0..half {
+            let offset = (i + i / 15) as i32;
+            map[half - i - 1] = -offset - 1;
+            map[half + i] = offset + 1;
+        }
+        map
+    }
+}
+
+/// Mode message bits in symbol order: around the ring clockwise from the
+/// top left, skipping the reference grid in full-range symbols
+fn mode_message(sampler: &Sampler, symmetry: usize, full: bool) -> Vec<bool> {
+    let dark = |x: i32, y: i32| sampler.dark(symmetry, x, y);
+    let (s, side) = if full { (7, 10) } else { (5, 7) };
+    let offset = |i: i32| if full { -5 + i + i / 5 } else { -3 + i };
+    let mut bits = vec![false; 4 * side as usize];
+    for i in 0..side {
+        let o = offset(i);
+        bits[i as usize] = dark(o, -s);
+        bits[(side + i) as usize] = dark(s, o);
+        bits[(3 * side - 1 - i) as usize] = dark(o, s);
+        bits[(4 * side - 1 - i) as usize] = dark(-s, o);
+    }
+    bits
+}
+
+fn read_layout(sampler: &Sampler, symmetry: usize, full: bool) -> Option<Layout> {
+    let bits = mode_message(sampler, symmetry, full);
+    let mut words: Vec<u16> = bits
+        .chunks(4)
+        .map(|chunk| chunk.iter().fold(0, |word, &bit| word << 1 | bit as u16))
+        .collect();
+    let check = if full { 6 } else { 5 };
+    reed_solomon::correct(&GaloisField::new(MODE_PRIMITIVE, 16), &mut words, check)?;
+    let data = words[..words.len() - check]
+        .iter()
+        .fold(0usize, |value, &word| value << 4 | word as usize);
+    let layout = if full {
+        Layout { compact: false, layers: (data >> 11) + 1, data_words: (data & 0x7FF) + 1 }
+    } else {
+        Layout { compact: true, layers: (data >> 6) + 1, data_words: (data & 0x3F) + 1 }
+    };
+    (layout.data_words < layout.total_bits() / layout.word().0).then_some(layout)
+}
+
+fn read_symbol(
+    sampler: &Sampler,
+    symmetry: usize,
+    full: bool,
+    contrast: f64,
+) -> Option<QRCodeResult> {
+    let layout = read_layout(sampler, symmetry, full)?;
+    let half = (layout.size() / 2) as i32;
+    let refined;
+    let sampler = if full {
+        refined = Sampler {
+            map: refine_with_grid(sampler, symmetry, &layout, contrast),
+            ..*sampler
+        };
+        &refined
+    } else {
+        sampler
+    };
+
+    let codewords = read_codewords(sampler, symmetry, &layout);
+    let (word_size, primitive) = layout.word();
+    let mut words = codewords;
+    let check = words.len() - layout.data_words;
+    let field = GaloisField::new(primitive, 1 << word_size);
+    let corrected = reed_solomon::correct(&field, &mut words, check)?;
+    log_debug!("Aztec symbol corrected {} codewords", corrected);
+    let bits = unstuff(&words[..layout.data_words], word_size)?;
+    let payload = decode_payload(&bits);
+
+    // Without an ECI, Aztec text is ISO-8859-1; UTF-8 is common in practice
+    let eci = payload
+        .eci
+        .or_else(|| std::str::from_utf8(&payload.bytes).is_err().then_some(3));
+    let text = qr::payload_text(&payload.bytes, eci, false);
+
+    let edge = half as f64 + 0.5;
+    let corner = |x: f64, y: f64| {
+        let (u, v) = transform(symmetry, x, y);
+        sampler.map.apply(u, v)
+    };
+    let bounds = vec![
+        corner(-edge, -edge),
+        corner(edge, -edge),
+        corner(edge, edge),
+        corner(-edge, edge),
+    ];
+    let layers = layout.layers as i32;
+    let payload_hash = qr::hash_payload(&payload.bytes);
+    let mut result = QRCodeResult {
+        symbology: Symbology::Aztec,
+        data: text.data,
+        version: if layout.compact { -layers } else { layers },
+        mirrored: is_mirrored(&bounds),
+        bounds,
+        ecc_level: format!("{}%", 100 * check / words.len()),
+        mask: 0,
+        raw_bytes: payload.bytes,
+        inverted: false,
+        gs1: payload.gs1,
+        strategy: String::new(),
+        orientation_degrees: 0.0,
+        center: (0.0, 0.0),
+        area: 0.0,
+        angle_degrees: 0.0,
+        module_size_px: 0.0,
+        structured_append: None,
+        encoding: text.encoding,
+        encoding_error: text.failed,
+        check_digit_valid: None,
+        crop_png: Vec::new(),
+        modules: None,
+        track_id: None,
+        smoothed_bounds: Vec::new(),
+        corrected_codewords: corrected as u32,
+        quality: qr::correction_quality([(corrected, check / 2)]),
+        payload_hash,
+    };
+    result.update_geometry();
+    Some(result)
+}
+
+/// Counter-clockwise corners in image space (y down) mean a reflected symbol
+fn is_mirrored(bounds: &[(f64, f64)]) -> bool {
+    let (a, b, d) = (bounds[0], bounds[1], bounds[3]);
+    (b.0 - a.0) * (d.1 - a.1) - (b.1 - a.1) * (d.0 - a.0) < 0.0
+}
+
+/// The homography moved onto the reference grid crossings, nearest first.
+/// Every crossing is a dark module with alternating modules along both grid
+/// lines, searched within a module of its predicted position.
+fn refine_with_grid(
+    sampler: &Sampler,
+    symmetry: usize,
+    layout: &Layout,
+    contrast: f64,
+) -> Homography {
+    let mut map = sampler.map;
+    let lines = (1..).take_while(|k| 16 * k <= layout.size() / 2).count() as i32;
+    let mut pairs = ring_corners(sampler.gray, &map, &[1.5, 2.5, 3.5, 4.5, 5.5], contrast);
+
+    let template: Vec<((i32, i32), bool)> = std::iter::once(((0, 0), true))
+        .chain((1..=2).flat_map(|d: i32| {
+            let dark = d % 2 == 0;
+            [((d, 0), dark), ((-d, 0), dark), ((0, d), dark), ((0, -d), dark)]
+        }))
+        .collect();
+    for level in 1..=lines {
+        for a in -level..=level {
+            for b in -level..=level {
+                if a.abs().max(b.abs()) != level {
+                    continue;
+                }
+                let (cx, cy) = ((16 * a) as f64, (16 * b) as f64);
+                let mut scores = Vec::with_capacity(441);
+                for i in -10..=10 {
+                    for j in -10..=10 {
+                        let (du, dv) = (i as f64 * 0.1, j as f64 * 0.1);
+                        let (mut light, mut dark, mut lights, mut darks) = (0.0, 0.0, 0, 0);
+                        for &((x, y), is_dark) in &template {
+                            let (u, v) =
+                                transform(symmetry, cx + du + x as f64, cy + dv + y as f64);
+                            let value = qr::sample(sampler.gray, map.apply(u, v));
+                            if is_dark {
+                                dark += value;
+                                darks += 1;
+                            } else {
+                                light += value;
+                                lights += 1;
+                            }
+                        }
+                        scores.push((light / lights as f64 - dark / darks as f64, du, dv));
+                    }
+                }
+                let best = scores.iter().map(|s| s.0).fold(f64::MIN, f64::max);
+                if best < contrast * MIN_CROSSING_CONTRAST {
+                    continue;
+                }
+                // Sharp images score the same over a plateau; take its middle
+                let plateau: Vec<_> = scores
+                    .iter()
+                    .filter(|s| s.0 >= best - contrast * PLATEAU_TOLERANCE)
+                    .collect();
+                let count = plateau.len() as f64;
+                let du = plateau.iter().map(|s| s.1).sum::<f64>() / count;
+                let dv = plateau.iter().map(|s| s.2).sum::<f64>() / count;
+                let point = transform(symmetry, cx, cy);
+                let (u, v) = transform(symmetry, cx + du, cy + dv);
+                pairs.push((point, map.apply(u, v)));
+            }
+        }
+        if let Some(fitted) = Homography::fit(&pairs) {
+            map = fitted;
+        }
+    }
+    map
+}
+
+/// Codewords of the data layers, outermost layer first, after the leading
+/// bits that do not fill a whole codeword
+fn read_codewords(sampler: &Sampler, symmetry: usize, layout: &Layout) -> Vec<u16> {
+    let map = layout.alignment_map();
+    let dark = |column: usize, row: usize| sampler.dark(symmetry, map[column], map[row]);
+    let base = layout.base_size();
+    let mut bits = Vec::with_capacity(layout.total_bits());
+    for i in 0..layout.layers {
+        let row_size = (layout.layers - i) * 4 + if layout.compact { 9 } else { 12 };
+        let (low, high) = (i * 2, base - 1 - i * 2);
+        let mut sides: [Vec<bool>; 4] = Default::default();
+        for j in 0..row_size {
+            for k in 0..2 {
+                sides[0].push(dark(low + k, low + j));
+                sides[1].push(dark(low + j, high - k));
+                sides[2].push(dark(high - k, high - j));
+                sides[3].push(dark(high - j, low + k));
+            }
+        }
+        bits.extend(sides.into_iter().flatten());
+    }
+
+    let (word_size, _) = layout.word();
+    let skip = bits.len() % word_size;
+    bits[skip..]
+        .chunks(word_size)
+        .map(|chunk| chunk.iter().fold(0, |word, &bit| word << 1 | bit as u16))
+        .collect()
+}
+
+/// Remove the bit stuffed after every codeword whose other bits are equal;
+/// all-zero and all-one codewords never appear in valid data
+fn unstuff(words: &[u16], word_size: usize) -> Option<Vec<bool>> {
+    let all_ones = (1u16 << word_size) - 1;
+    let mut bits = Vec::with_capacity(words.len() * word_size);
+    for &word in words {
+        if word == 0 || word == all_ones {
+            return None;
+        }
+        let stuffed = word == 1 || word == all_ones - 1;
+        let length = if stuffed { word_size - 1 } else { word_size };
+        bits.extend((0..length).map(|i| word >> (word_size - 1 - i) & 1 == 1));
+    }
+    Some(bits)
+}
+
+// ==================== Decodation ====================
+
+#[derive(Clone, Copy, PartialEq)]
+enum Table {
+    Upper,
+    Lower,
+    Mixed,
+    Punct,
+    Digit,
+}
+
+enum Entry {
+    Text(&'static [u8]),
+    Shift(Table),
+    Latch(Table),
+    BinaryShift,
+    Flag,
+}
+
+const UPPER: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const LOWER: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+const MIXED: &[u8] =
+    b"\x01\x02\x03\x04\x05\x06\x07\x08\x09\x0a\x0b\x0c\x0d\x1b\x1c\x1d\x1e\x1f@\\^_`|~\x7f";
+const PUNCT: &[u8] = b"!\"#$%&'()*+,-./:;<=>?[]{}";
+const DIGITS: &[u8] = b"0123456789,.";
+
+fn entry(table: Table, code: usize) -> Entry {
+    let one = |set: &'static [u8], i: usize| Entry::Text(&set[i..i + 1]);
+    match (table, code) {
+        (Table::Punct, 0) => Entry::Flag,
+        (_, 0) => Entry::Shift(Table::Punct),
+        (Table::Punct, 1) => Entry::Text(b"\r"),
+        (Table::Punct, 2) => Entry::Text(b"\r\n"),
+        (Table::Punct, 3) => Entry::Text(b". "),
+        (Table::Punct, 4) => Entry::Text(b", "),
+        (Table::Punct, 5) => Entry::Text(b": "),
+        (Table::Punct, 31) => Entry::Latch(Table::Upper),
+        (Table::Punct, _) => one(PUNCT, code - 6),
+        (_, 1) => Entry::Text(b" "),
+        (Table::Upper, 2..=27) => one(UPPER, code - 2),
+        (Table::Lower, 2..=27) => one(LOWER, code - 2),
+        (Table::Upper, 28) => Entry::Latch(Table::Lower),
+        (Table::Lower, 28) => Entry::Shift(Table::Upper),
+        (Table::Upper | Table::Lower, 29) => Entry::Latch(Table::Mixed),
+        (Table::Upper | Table::Lower, 30) => Entry::Latch(Table::Digit),
+        (Table::Mixed, 2..=27) => one(MIXED, code - 2),
+        (Table::Mixed, 28) => Entry::Latch(Table::Lower),
+        (Table::Mixed, 29) => Entry::Latch(Table::Upper),
+        (Table::Mixed, 30) => Entry::Latch(Table::Punct),
+        (Table::Digit, 2..=13) => one(DIGITS, code - 2),
+        (Table::Digit, 14) => Entry::Latch(Table::Upper),
+        (Table::Digit, _) => Entry::Shift(Table::Upper),
+        (_, _) => Entry::BinaryShift,
+    }
+}
+
+struct Payload {
+    bytes: Vec<u8>,
+    eci: Option<u32>,
+    gs1: bool,
+}
+
+/// Bit reader over the unstuffed data bits
+struct Bits<'a> {
+    bits: &'a [bool],
+    position: usize,
+}
+
+impl Bits<'_> {
+    fn read(&mut self, count: usize) -> Option<usize> {
+        let bits = self.bits.get(self.position..self.position + count)?;
+        self.position += count;
+        Some(bits.iter().fold(0, |value, &bit| value << 1 | bit as usize))
+    }
+}
+
+/// Decode the character-table modes; trailing padding that does not form a
+/// whole code is ignored
+fn decode_payload(bits: &[bool]) -> Payload {
+    let mut bits = Bits { bits, position: 0 };
+    let mut payload = Payload { bytes: Vec::new(), eci: None, gs1: false };
+    let (mut latch, mut shift) = (Table::Upper, Table::Upper);
+    loop {
+        let size = if shift == Table::Digit { 4 } else { 5 };
+        let Some(code) = bits.read(size) else {
+            break;
+        };
+        match entry(shift, code) {
+            Entry::Text(text) => {
+                payload.bytes.extend_from_slice(text);
+                shift = latch;
+            }
+            Entry::Shift(table) => {
+                latch = shift;
+                shift = table;
+            }
+            Entry::Latch(table) => {
+                latch = table;
+                shift = table;
+            }
+            Entry::BinaryShift => {
+                let Some(mut length) = bits.read(5) else {
+                    break;
+                };
+                if length == 0 {
+                    let Some(long) = bits.read(11) else {
+                        break;
+                    };
+                    length = long + 31;
+                }
+                for _ in 0..length {
+                    let Some(byte) = bits.read(8) else {
+                        break;
+                    };
+                    payload.bytes.push(byte as u8);
+                }
+                latch = shift;
+            }
+            Entry::Flag => {
+                let Some(digits) = bits.read(3) else {
+                    break;
+                };
+                match digits {
+                    // FNC1: GS1 data in first position, a separator after that
+                    0 if payload.bytes.is_empty() => payload.gs1 = true,
+                    0 => payload.bytes.push(0x1D),
+                    7 => break,
+                    _ => {
+                        let mut eci = 0;
+                        for _ in 0..digits {
+                            match bits.read(4) {
+                                Some(digit @ 2..=11) => eci = eci * 10 + (digit - 2) as u32,
+                                _ => return payload,
+                            }
+                        }
+                        payload.eci.get_or_insert(eci);
+                    }
+                }
+                shift = latch;
+            }
+        }
+    }
+    payload
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    /// One run of the high-level encoding
+    enum Segment<'a> {
+        Upper(&'a str),
+        Lower(&'a str),
+        Digits(&'a str),
+        Bytes(&'a [u8]),
+        Punctuation(u32),
+        Eci(u32),
+        Fnc1,
+    }
+
+    fn push(bits: &mut Vec<bool>, value: u32, count: usize) {
+        bits.extend((0..count).rev().map(|i| value >> i & 1 == 1));
+    }
+
+    /// Character-table codes for the segments, latching between upper,
+    /// lower and digit as needed
+    fn encode_segments(segments: &[Segment]) -> Vec<bool> {
+        let mut bits = Vec::new();
+        let mut table = Table::Upper;
+        let code = |bits: &mut Vec<bool>, table: Table, value: u32| {
+            push(bits, value, if table == Table::Digit { 4 } else { 5 });
+        };
+        for segment in segments {
+            let target = match segment {
+                Segment::Upper(_) => Table::Upper,
+                Segment::Lower(_) => Table::Lower,
+                Segment::Digits(_) => Table::Digit,
+                // Binary shift is not available from the digit table
+                _ if table == Table::Digit => Table::Upper,
+                _ => table,
+            };
+            match (table, target) {
+                (from, to) if from == to => {}
+                (Table::Upper, Table::Lower) => code(&mut bits, table, 28),
+                (_, Table::Digit) => code(&mut bits, table, 30),
+                (Table::Digit, Table::Upper) => code(&mut bits, table, 14),
+                (Table::Digit, Table::Lower) => {
+                    code(&mut bits, table, 14);
+                    code(&mut bits, Table::Upper, 28);
+                }
+                // Lower to upper goes through the digit table
+                _ => {
+                    code(&mut bits, table, 30);
+                    code(&mut bits, Table::Digit, 14);
+                }
+            }
+            table = target;
+            match segment {
+                Segment::Upper(text) | Segment::Lower(text) => {
+                    for byte in text.bytes() {
+                        let value = if byte == b' ' { 1 } else { (byte | 0x20) - b'a' + 2 };
+                        code(&mut bits, table, value as u32);
+                    }
+                }
+                Segment::Digits(text) => {
+                    for byte in text.bytes() {
+                        let value = match byte {
+                            b' ' => 1,
+                            b',' => 12,
+                            b'.' => 13,
+                            digit => digit - b'0' + 2,
+                        };
+                        code(&mut bits, table, value as u32);
+                    }
+                }
+                Segment::Bytes(bytes) => {
+                    code(&mut bits, table, 31);
+                    if bytes.len() < 32 {
+                        push(&mut bits, bytes.len() as u32, 5);
+                    } else {
+                        push(&mut bits, 0, 5);
+                        push(&mut bits, bytes.len() as u32 - 31, 11);
+                    }
+                    for &byte in bytes.iter() {
+                        push(&mut bits, byte as u32, 8);
+                    }
+                }
+                Segment::Punctuation(value) => {
+                    code(&mut bits, table, 0);
+                    push(&mut bits, *value, 5);
+                }
+                Segment::Eci(eci) => {
+                    code(&mut bits, table, 0);
+                    push(&mut bits, 0, 5);
+                    let digits = eci.to_string();
+                    push(&mut bits, digits.len() as u32, 3);
+                    for digit in digits.bytes() {
+                        push(&mut bits, (digit - b'0') as u32 + 2, 4);
+                    }
+                }
+                Segment::Fnc1 => {
+                    code(&mut bits, table, 0);
+                    push(&mut bits, 0, 8);
+                }
+            }
+        }
+        bits
+    }
+
+    /// Codewords of `word_size` bits, stuffing a bit after any whose first
+    /// `word_size - 1` bits are equal; the last one is padded with ones
+    fn stuff(bits: &[bool], word_size: usize) -> Vec<u16> {
+        let mask = (1u16 << word_size) - 2;
+        let mut words = Vec::new();
+        let mut i = 0;
+        while i < bits.len() {
+            let mut word = 0u16;
+            for j in 0..word_size - 1 {
+                word = word << 1 | bits.get(i + j).copied().unwrap_or(true) as u16;
+            }
+            word <<= 1;
+            if word == mask || word == 0 {
+                words.push(word | (word == 0) as u16);
+                i += word_size - 1;
+            } else {
+                words.push(word | bits.get(i + word_size - 1).copied().unwrap_or(true) as u16);
+                i += word_size;
+            }
+        }
+        words
+    }
+
+    fn check_words(primitive: u32, word_size: usize, data: &[u16], count: usize) -> Vec<u16> {
+        let field = GaloisField::new(primitive, 1 << word_size);
+        let mut generator = vec![1u16];
+        for j in 1..=count {
+            let mut next = generator.clone();
+            next.push(0);
+            for (i, &c) in generator.iter().enumerate() {
+                next[i + 1] ^= field.mul(c, field.exp(j));
+            }
+            generator = next;
+        }
+        let mut remainder = vec![0u16; count];
+        for &word in data {
+            let factor = word ^ remainder[0];
+            remainder.rotate_left(1);
+            remainder[count - 1] = 0;
+            for (value, &g) in remainder.iter_mut().zip(&generator[1..]) {
+                *value ^= field.mul(g, factor);
+            }
+        }
+        data.iter().copied().chain(remainder).collect()
+    }
+
+    fn word_bits(words: &[u16], word_size: usize) -> Vec<bool> {
+        let mut bits = Vec::new();
+        for &word in words {
+            push(&mut bits, word as u32, word_size);
+        }
+        bits
+    }
+
+    /// Module matrix of the segments in the smallest symbol of the requested
+    /// kind holding them with about a third of check words, or in exactly
+    /// `layers` layers
+    fn symbol(segments: &[Segment], compact: bool, layers: Option<usize>) -> Vec<Vec<bool>> {
+        let bits = encode_segments(segments);
+        let fits = |layers: usize| {
+            let layout = Layout { compact, layers, data_words: 0 };
+            let (word_size, _) = layout.word();
+            let words = stuff(&bits, word_size);
+            let capacity = layout.total_bits() / word_size;
+            let room = words.len() * 4 / 3 + 3 <= capacity;
+            (room && (!compact || words.len() <= 64)).then_some(words)
+        };
+        let (layers, words) = match layers {
+            Some(layers) => (layers, fits(layers).expect("too long for the layers")),
+            None => (1..=32).find_map(|l| fits(l).map(|w| (l, w))).expect("too long"),
+        };
+        let layout = Layout { compact, layers, data_words: words.len() };
+        let (word_size, primitive) = layout.word();
+        let capacity = layout.total_bits() / word_size;
+        let codewords = check_words(primitive, word_size, &words, capacity - words.len());
+        // The data starts at the innermost layer after the leftover bits
+        let mut message = vec![false; layout.total_bits() % word_size];
+        message.extend(word_bits(&codewords, word_size));
+
+        let mut mode = Vec::new();
+        if compact {
+            push(&mut mode, layers as u32 - 1, 2);
+            push(&mut mode, words.len() as u32 - 1, 6);
+        } else {
+            push(&mut mode, layers as u32 - 1, 5);
+            push(&mut mode, words.len() as u32 - 1, 11);
+        }
+        let mode_words: Vec<u16> = mode
+            .chunks(4)
+            .map(|word| word.iter().fold(0, |acc, &bit| acc << 1 | bit as u16))
+            .collect();
+        let mode_check = if compact { 5 } else { 6 };
+        let mode = word_bits(&check_words(MODE_PRIMITIVE, 4, &mode_words, mode_check), 4);
+
+        // Layer coordinates skip the reference grid lines of full symbols
+        let base = (if compact { 11 } else { 14 }) + layers * 4;
+        let size = layout.size();
+        let center = size / 2;
+        let position: Vec<usize> = if compact {
+            (0..base).collect()
+        } else {
+            let half = base / 2;
+            let mut position = vec![0; base];
+            for i in 0..half {
+                let offset = i + i / 15;
+                position[half - i - 1] = center - offset - 1;
+                position[half + i] = center + offset + 1;
+            }
+            position
+        };
+        let mut matrix = vec![vec![false; size]; size];
+        let mut set = |x: usize, y: usize| matrix[y][x] = true;
+        let mut offset = 0;
+        for layer in 0..layers {
+            let length = (layers - layer) * 4 + if compact { 9 } else { 12 };
+            let (low, high) = (layer * 2, base - 1 - layer * 2);
+            for j in 0..length {
+                for k in 0..2 {
+                    let bit = |side: usize| message[offset + side * length * 2 + j * 2 + k];
+                    if bit(0) {
+                        set(position[low + k], position[low + j]);
+                    }
+                    if bit(1) {
+                        set(position[low + j], position[high - k]);
+                    }
+                    if bit(2) {
+                        set(position[high - k], position[high - j]);
+                    }
+                    if bit(3) {
+                        set(position[high - j], position[low + k]);
+                    }
+                }
+            }
+            offset += length * 8;
+        }
+
+        if compact {
+            for i in 0..7 {
+                let p = center - 3 + i;
+                for (bit, x, y) in [
+                    (mode[i], p, center - 5),
+                    (mode[i + 7], center + 5, p),
+                    (mode[20 - i], p, center + 5),
+                    (mode[27 - i], center - 5, p),
+                ] {
+                    if bit {
+                        set(x, y);
+                    }
+                }
+            }
+        } else {
+            for i in 0..10 {
+                let p = center - 5 + i + i / 5;
+                for (bit, x, y) in [
+                    (mode[i], p, center - 7),
+                    (mode[i + 10], center + 7, p),
+                    (mode[29 - i], p, center + 7),
+                    (mode[39 - i], center - 7, p),
+                ] {
+                    if bit {
+                        set(x, y);
+                    }
+                }
+            }
+        }
+
+        // Bullseye rings, orientation marks and the reference grid
+        let ring = if compact { 5 } else { 7 };
+        for r in (0..ring).step_by(2) {
+            for p in center - r..=center + r {
+                set(p, center - r);
+                set(p, center + r);
+                set(center - r, p);
+                set(center + r, p);
+            }
+        }
+        let (low, high) = (center - ring, center + ring);
+        for (x, y) in [(low, low), (low + 1, low), (low, low + 1), (high, low), (high, low + 1)] {
+            set(x, y);
+        }
+        set(high, high - 1);
+        if !compact {
+            for line in (0..=center).step_by(16) {
+                for p in (center % 2..size).step_by(2) {
+                    set(center - line, p);
+                    set(center + line, p);
+                    set(p, center - line);
+                    set(p, center + line);
+                }
+            }
+        }
+        matrix
+    }
+
+    /// `module` pixels per module inside a three-module quiet zone, turned
+    /// by `degrees` about the center
+    fn render(modules: &[Vec<bool>], module: f64, degrees: f64) -> GrayImage {
+        let size = modules.len() as f64;
+        let side = ((size * 2f64.sqrt() + 6.0) * module).ceil() as u32;
+        let center = side as f64 / 2.0;
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        GrayImage::from_fn(side, side, |x, y| {
+            let (dx, dy) = (x as f64 + 0.5 - center, y as f64 + 0.5 - center);
+            let col = (cos * dx + sin * dy) / module + size / 2.0;
+            let row = (cos * dy - sin * dx) / module + size / 2.0;
+            let inside = (0.0..size).contains(&col) && (0.0..size).contains(&row);
+            Luma([if inside && modules[row as usize][col as usize] { 0 } else { 255 }])
+        })
+    }
+
+    #[test]
+    fn dimensions_follow_the_layers() {
+        assert_eq!(dimensions(-1), Some(15));
+        assert_eq!(dimensions(-4), Some(27));
+        assert_eq!(dimensions(1), Some(19));
+        assert_eq!(dimensions(5), Some(37));
+        assert_eq!(dimensions(32), Some(151));
+        assert_eq!(dimensions(0), None);
+    }
+
+    #[test]
+    fn decodes_compact_and_full_symbols() {
+        for layers in 1..=4 {
+            let modules = symbol(&[Segment::Upper("HELLO WORLD")], true, Some(layers));
+            let results = decode(&render(&modules, 4.0, 0.0));
+            assert_eq!(results.len(), 1, "compact {}", layers);
+            assert_eq!(results[0].data, "HELLO WORLD");
+            assert_eq!(results[0].version, -(layers as i32));
+            assert_eq!(results[0].symbology, Symbology::Aztec);
+            assert!(results[0].ecc_level.ends_with('%'));
+        }
+        for layers in [1, 2, 5, 12] {
+            let modules = symbol(&[Segment::Upper("FOX")], false, Some(layers));
+            let results = decode(&render(&modules, 3.0, 0.0));
+            assert_eq!(results.len(), 1, "full {}", layers);
+            assert_eq!(results[0].data, "FOX");
+            assert_eq!(results[0].version, layers as i32);
+        }
+        // Enough data for the reference grid to matter
+        let payload: Vec<u8> = (0..1500u32).map(|i| (i * 7 + 3) as u8).collect();
+        let modules = symbol(&[Segment::Bytes(&payload)], false, None);
+        assert!(modules.len() > 80);
+        let results = decode(&render(&modules, 3.0, 0.0));
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].raw_bytes, payload);
+    }
+
+    #[test]
+    fn character_tables_decode() {
+        let segments = [
+            Segment::Upper("AB"),
+            Segment::Lower("cd ef"),
+            Segment::Digits("12,5."),
+            Segment::Upper("X"),
+            Segment::Punctuation(11), // &
+            Segment::Lower("z"),
+            Segment::Punctuation(2), // CR LF
+            Segment::Upper("END"),
+        ];
+        let payload = decode_payload(&encode_segments(&segments));
+        assert_eq!(payload.bytes, b"ABcd ef12,5.X&z\r\nEND");
+        assert_eq!((payload.eci, payload.gs1), (None, false));
+
+        let segments = [Segment::Eci(26), Segment::Bytes("Grüße".as_bytes())];
+        let payload = decode_payload(&encode_segments(&segments));
+        assert_eq!(payload.eci, Some(26));
+        assert_eq!(payload.bytes, "Grüße".as_bytes());
+        let segments = [Segment::Fnc1, Segment::Digits("0109501101530003"), Segment::Fnc1];
+        let payload = decode_payload(&encode_segments(&segments));
+        assert!(payload.gs1);
+        assert_eq!(payload.bytes, b"0109501101530003\x1d");
+    }
+
+    #[test]
+    fn text_falls_back_to_latin_1() {
+        let latin = symbol(&[Segment::Bytes(&[0x47, 0xE9])], true, None);
+        let results = decode(&render(&latin, 4.0, 0.0));
+        assert_eq!(results[0].data, "Gé");
+        assert_eq!(results[0].encoding, "ISO-8859-1");
+        let utf8 = symbol(&[Segment::Eci(26), Segment::Bytes("Grüße".as_bytes())], true, None);
+        let results = decode(&render(&utf8, 4.0, 0.0));
+        assert_eq!(results[0].data, "Grüße");
+    }
+
+    #[test]
+    fn rotated_and_mirrored_symbols_decode() {
+        let segments = [Segment::Upper("ROTATE ME"), Segment::Digits("2024")];
+        let compact = symbol(&segments, true, Some(2));
+        let mirrored: Vec<Vec<bool>> =
+            compact.iter().map(|row| row.iter().rev().copied().collect()).collect();
+        for degrees in [0.0, 17.0, 90.0, 133.0, 180.0, 270.0, 301.0] {
+            let results = decode(&render(&compact, 5.0, degrees));
+            assert_eq!(results.len(), 1, "{} degrees", degrees);
+            assert_eq!(results[0].data, "ROTATE ME2024");
+            assert!(!results[0].mirrored);
+            let results = decode(&render(&mirrored, 5.0, degrees));
+            assert_eq!(results.len(), 1, "mirrored, {} degrees", degrees);
+            assert!(results[0].mirrored);
+            assert_eq!(results[0].data, "ROTATE ME2024");
+        }
+        let full = symbol(&[Segment::Lower("full range")], false, Some(6));
+        for degrees in [33.0, 200.0] {
+            assert_eq!(decode(&render(&full, 4.0, degrees))[0].data, "full range");
+        }
+    }
+
+    #[test]
+    fn damaged_symbols_are_corrected() {
+        let mut modules = symbol(&[Segment::Upper("DAMAGED SYMBOL TEST")], false, Some(3));
+        for row in &mut modules[1..4] {
+            for module in &mut row[1..8] {
+                *module = false;
+            }
+        }
+        let results = decode(&render(&modules, 4.0, 0.0));
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].data, "DAMAGED SYMBOL TEST");
+        assert!(results[0].corrected_codewords > 0);
+        assert!(decode(&GrayImage::from_pixel(200, 200, Luma([255]))).is_empty());
+    }
+}
@@ -0,0 +1,407 @@
+//! IATA Bar Coded Boarding Pass (Resolution 792), the `M` format printed
+//! as Aztec, PDF417 or QR: a fixed mandatory block per leg, hex-sized
+//! conditional blocks and optional `^` security data
+
+use serde::Serialize;
+
+use crate::mrz::{days_in_month, CivilDate};
+use crate::VeloQrError;
+
+/// Format code of the only BCBP format in use (multiple legs included)
+const FORMAT_CODE: char = 'M';
+
+/// Marks the start of the version number and the unique conditional items
+const VERSION_BEGIN: char = '>';
+
+/// Marks the start of the airline security data after the last leg
+const SECURITY_BEGIN: char = '^';
+
+/// Length of the header before the first leg: format code, number of legs,
+/// passenger name and electronic ticket indicator
+const HEADER_LENGTH: usize = 23;
+
+/// Single flight segment with its mandatory and repeated conditional items.
+/// Numeric fields keep their printed form without padding.
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct BcbpLeg {
+    pub pnr: String,
+    pub from_airport: String,
+    pub to_airport: String,
+    pub operating_carrier: String,
+    pub flight_number: String,
+    /// Day of the year as printed (1-366)
+    pub flight_day_of_year: u32,
+    /// YYYY-MM-DD, placed on or after the issue date when known, otherwise
+    /// the occurrence nearest to today
+    pub flight_date: Option<String>,
+    pub compartment: String,
+    pub seat: String,
+    pub sequence_number: String,
+    pub passenger_status: String,
+    pub airline_numeric_code: Option<String>,
+    pub document_serial: Option<String>,
+    pub selectee: Option<String>,
+    pub international_document_verification: Option<String>,
+    pub marketing_carrier: Option<String>,
+    pub frequent_flyer_airline: Option<String>,
+    pub frequent_flyer_number: Option<String>,
+    pub id_ad_indicator: Option<String>,
+    pub free_baggage_allowance: Option<String>,
+    pub fast_track: Option<bool>,
+    /// Free-form data after the structured items, for the carrier's own use
+    pub airline_data: Option<String>,
+}
+
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct BcbpBoardingPass {
+    /// Name field as printed, `FAMILY/GIVEN TITLE`
+    pub passenger_name: String,
+    pub family_name: Option<String>,
+    pub given_names: Option<String>,
+    pub electronic_ticket: bool,
+    /// Absent when the pass has no conditional items
+    pub version: Option<u32>,
+    pub passenger_description: Option<String>,
+    pub checkin_source: Option<String>,
+    pub issuance_source: Option<String>,
+    pub issue_date: Option<String>, // YYYY-MM-DD
+    /// `B` for a boarding pass, `I` for an itinerary receipt
+    pub document_type: Option<String>,
+    pub issuer: Option<String>,
+    /// Baggage tag license plate numbers, the non-consecutive ones included
+    pub bag_tags: Vec<String>,
+    pub legs: Vec<BcbpLeg>,
+    pub security_data_type: Option<String>,
+    pub security_data: Option<String>,
+}
+
+/// Parse a boarding pass; Julian dates resolve against `today`
+pub fn parse(text: &str, today: CivilDate) -> Result<BcbpBoardingPass, VeloQrError> {
+    let chars: Vec<char> = text.trim_end_matches(['\r', '\n']).chars().collect();
+    if chars.first() != Some(&FORMAT_CODE) {
+        return Err(invalid("format code is not M"));
+    }
+    if chars.len() < HEADER_LENGTH {
+        return Err(invalid("truncated header"));
+    }
+    let leg_count = chars[1]
+        .to_digit(10)
+        .filter(|&count| count > 0)
+        .ok_or_else(|| invalid("bad number of legs"))?;
+
+    let mut cursor = Cursor { chars: &chars, pos: 2, end: chars.len() };
+    let passenger_name = cursor.text(20).unwrap_or_default();
+    let (family_name, given_names) = split_name(&passenger_name);
+    let electronic_ticket = cursor.text(1).as_deref() == Some("E");
+
+    let mut pass = BcbpBoardingPass {
+        passenger_name,
+        family_name,
+        given_names,
+        electronic_ticket,
+        ..BcbpBoardingPass::default()
+    };
+    let mut issued = None;
+
+    for index in 0..leg_count {
+        let mut leg = mandatory_items(&mut cursor)
+            .ok_or_else(|| invalid(&format!("truncated leg {}", index + 1)))?;
+        let size = cursor.hex_size().ok_or_else(|| invalid("bad conditional field size"))?;
+        let mut conditional = cursor.sub(size);
+
+        if index == 0 && conditional.peek() == Some(VERSION_BEGIN) {
+            conditional.pos += 1;
+            pass.version = conditional.text(1).and_then(|v| v.parse().ok());
+            if let Some(mut unique) = conditional.hex_size().map(|size| conditional.sub(size)) {
+                issued = unique_items(&mut unique, &mut pass, today);
+            }
+        }
+        if let Some(mut repeated) = conditional.hex_size().map(|size| conditional.sub(size)) {
+            repeated_items(&mut repeated, &mut leg);
+        }
+        leg.airline_data = conditional.rest();
+        leg.flight_date = flight_date(leg.flight_day_of_year, issued, today).map(CivilDate::to_iso);
+        pass.legs.push(leg);
+    }
+
+    if cursor.peek() == Some(SECURITY_BEGIN) {
+        cursor.pos += 1;
+        pass.security_data_type = cursor.text(1);
+        if let Some(mut security) = cursor.hex_size().map(|size| cursor.sub(size)) {
+            pass.security_data = security.rest();
+        }
+    }
+    Ok(pass)
+}
+
+fn invalid(message: &str) -> VeloQrError {
+    VeloQrError::InvalidPayload(format!("BCBP: {}", message))
+}
+
+/// Cursor over fixed-width fields, bounded by the size of the enclosing
+/// block. Conditional blocks may end partway through their items, so a
+/// field cut short keeps whatever characters are left.
+struct Cursor<'a> {
+    chars: &'a [char],
+    pos: usize,
+    end: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, count: usize) -> Option<String> {
+        if self.pos >= self.end {
+            return None;
+        }
+        let stop = (self.pos + count).min(self.end);
+        let field = self.chars[self.pos..stop].iter().collect();
+        self.pos = stop;
+        Some(field)
+    }
+
+    /// Field with padding removed, `None` when blank
+    fn text(&mut self, count: usize) -> Option<String> {
+        Some(self.take(count)?.trim().to_string()).filter(|field| !field.is_empty())
+    }
+
+    fn peek(&self) -> Option<char> {
+        (self.pos < self.end).then(|| self.chars[self.pos])
+    }
+
+    /// Two hex digits giving the length of the block that follows
+    fn hex_size(&mut self) -> Option<usize> {
+        let field = self.take(2).filter(|field| field.len() == 2)?;
+        usize::from_str_radix(&field, 16).ok()
+    }
+
+    /// Next `size` characters as their own block; a block running past the
+    /// end of its parent is clipped rather than rejected
+    fn sub(&mut self, size: usize) -> Cursor<'a> {
+        let start = self.pos;
+        self.pos = (start + size).min(self.end);
+        Cursor { chars: self.chars, pos: start, end: self.pos }
+    }
+
+    fn rest(&mut self) -> Option<String> {
+        self.text(self.end.saturating_sub(self.pos))
+    }
+}
+
+/// The 35 fixed characters of a leg, before its conditional field size
+fn mandatory_items(cursor: &mut Cursor) -> Option<BcbpLeg> {
+    if cursor.end - cursor.pos < 35 {
+        return None;
+    }
+    let mut field = |count| cursor.take(count).unwrap_or_default().trim().to_string();
+    let pnr = field(7);
+    let from_airport = field(3);
+    let to_airport = field(3);
+    let operating_carrier = field(3);
+    let flight_number = unpadded(&field(5));
+    let flight_day_of_year = field(3).parse().unwrap_or(0);
+    let compartment = field(1);
+    let seat = unpadded(&field(4));
+    let sequence_number = unpadded(&field(5));
+    let passenger_status = field(1);
+    Some(BcbpLeg {
+        pnr,
+        from_airport,
+        to_airport,
+        operating_carrier,
+        flight_number,
+        flight_day_of_year,
+        compartment,
+        seat,
+        sequence_number,
+        passenger_status,
+        ..BcbpLeg::default()
+    })
+}
+
+/// Items printed once, in the first leg's conditional field. Returns the
+/// issue date for placing the flight dates.
+fn unique_items(
+    cursor: &mut Cursor,
+    pass: &mut BcbpBoardingPass,
+    today: CivilDate,
+) -> Option<CivilDate> {
+    pass.passenger_description = cursor.text(1);
+    pass.checkin_source = cursor.text(1);
+    pass.issuance_source = cursor.text(1);
+    let issued = cursor.text(4).and_then(|date| issue_date(&date, today));
+    pass.issue_date = issued.map(CivilDate::to_iso);
+    pass.document_type = cursor.text(1);
+    pass.issuer = cursor.text(3);
+    while let Some(tag) = cursor.take(13) {
+        if let Some(tag) = Some(tag.trim().to_string()).filter(|tag| !tag.is_empty()) {
+            pass.bag_tags.push(tag);
+        }
+    }
+    issued
+}
+
+/// Items printed for every leg, in its conditional field
+fn repeated_items(cursor: &mut Cursor, leg: &mut BcbpLeg) {
+    leg.airline_numeric_code = cursor.text(3);
+    leg.document_serial = cursor.text(10);
+    leg.selectee = cursor.text(1);
+    leg.international_document_verification = cursor.text(1);
+    leg.marketing_carrier = cursor.text(3);
+    leg.frequent_flyer_airline = cursor.text(3);
+    leg.frequent_flyer_number = cursor.text(16);
+    leg.id_ad_indicator = cursor.text(1);
+    leg.free_baggage_allowance = cursor.text(3);
+    leg.fast_track = cursor.text(1).and_then(|flag| match flag.as_str() {
+        "Y" => Some(true),
+        "N" => Some(false),
+        _ => None,
+    });
+}
+
+/// `FAMILY/GIVEN` split at the slash; a name without one is all family name
+fn split_name(name: &str) -> (Option<String>, Option<String>) {
+    let present = |part: &str| Some(part.trim().to_string()).filter(|part| !part.is_empty());
+    match name.split_once('/') {
+        Some((family, given)) => (present(family), present(given)),
+        None => (present(name), None),
+    }
+}
+
+/// Flight numbers, seats and sequence numbers without their zero padding:
+/// `0123` is `123` and `001A` is `1A`, keeping one zero before a letter
+fn unpadded(field: &str) -> String {
+    let trimmed = field.trim_start_matches('0');
+    if trimmed.starts_with(|c: char| c.is_ascii_digit()) || trimmed.len() == field.len() {
+        trimmed.to_string()
+    } else {
+        format!("0{}", trimmed)
+    }
+}
+
+/// Date of a day of the year, `None` past the end of that year
+fn from_day_of_year(year: i32, day_of_year: u32) -> Option<CivilDate> {
+    let mut day = day_of_year;
+    for month in 1..=12 {
+        let length = days_in_month(year, month);
+        if day <= length {
+            return CivilDate::new(year, month, day);
+        }
+        day -= length;
+    }
+    None
+}
+
+fn day_of_year(date: CivilDate) -> u32 {
+    (1..date.month).map(|month| days_in_month(date.year, month)).sum::<u32>() + date.day
+}
+
+/// Issue date `YDDD`: last digit of the year and day of the year, in the
+/// latest matching year not after tomorrow (issuing clocks run ahead of UTC)
+fn issue_date(value: &str, today: CivilDate) -> Option<CivilDate> {
+    if value.len() != 4 || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let digit: i32 = value[..1].parse().ok()?;
+    let day: u32 = value[1..].parse().ok()?;
+    let latest = (today.year, day_of_year(today) + 1);
+    let mut year = today.year - (today.year - digit).rem_euclid(10);
+    if (year, day) > latest {
+        year -= 10;
+    }
+    from_day_of_year(year, day).or_else(|| from_day_of_year(year - 10, day))
+}
+
+/// First occurrence of the flight day on or after the issue date, or
+/// without one the occurrence nearest to today
+fn flight_date(day: u32, issued: Option<CivilDate>, today: CivilDate) -> Option<CivilDate> {
+    if let Some(issued) = issued {
+        return (0..=4)
+            .filter_map(|offset| from_day_of_year(issued.year + offset, day))
+            .find(|&date| date >= issued);
+    }
+    let ordinal = |date: CivilDate| date.year as i64 * 366 + day_of_year(date) as i64;
+    (-1..=1)
+        .filter_map(|offset| from_day_of_year(today.year + offset, day))
+        .min_by_key(|&date| (ordinal(date) - ordinal(today)).abs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn today() -> CivilDate {
+        CivilDate::new(2026, 10, 14).unwrap()
+    }
+
+    #[test]
+    fn reads_the_mandatory_fields() {
+        let text = "M1DESMARAIS/LUC       EABC123 YULFRAAC 0834 326J001A0025 100";
+        let pass = parse(text, today()).unwrap();
+        assert_eq!(pass.family_name.as_deref(), Some("DESMARAIS"));
+        assert_eq!(pass.given_names.as_deref(), Some("LUC"));
+        assert!(pass.electronic_ticket);
+        assert_eq!(pass.version, None);
+        let leg = &pass.legs[0];
+        assert_eq!(leg.pnr, "ABC123");
+        assert_eq!((leg.from_airport.as_str(), leg.to_airport.as_str()), ("YUL", "FRA"));
+        assert_eq!((leg.operating_carrier.as_str(), leg.flight_number.as_str()), ("AC", "834"));
+        assert_eq!((leg.flight_day_of_year, leg.compartment.as_str()), (326, "J"));
+        assert_eq!((leg.seat.as_str(), leg.sequence_number.as_str()), ("1A", "25"));
+        assert_eq!(leg.passenger_status, "1");
+        assert_eq!(leg.flight_date.as_deref(), Some("2026-11-22"));
+    }
+
+    #[test]
+    fn reads_conditional_fields_of_every_leg() {
+        let text = "M2DESMARAIS/LUC       EABC123 YULFRAAC 0834 326J001A0025 14D>5181W 6225BAC \
+                    00141234560032A0141234567890 1AC AC 1234567890123    20KYLX58ZDEF456 \
+                    FRAGVALH 3664 327C012C0002 12E2A0140987654321 1AC AC 1234567890123    \
+                    2PCNWQ^164GIWVC5EH7JNT684FVNJ91W2QA4DVN5J8K4F0L0GEQ3DF5TGBN8709HKT5D3DW3\
+                    GBHFCVHMY7J5T6HFR41W2QA4DVN5J8K4F0L0GE";
+        let pass = parse(text, today()).unwrap();
+        assert_eq!(pass.version, Some(5));
+        assert_eq!(pass.passenger_description.as_deref(), Some("1"));
+        assert_eq!(pass.checkin_source.as_deref(), Some("W"));
+        assert_eq!(pass.issuance_source, None);
+        assert_eq!(pass.issue_date.as_deref(), Some("2026-08-13"));
+        assert_eq!(pass.document_type.as_deref(), Some("B"));
+        assert_eq!(pass.issuer.as_deref(), Some("AC"));
+        assert_eq!(pass.bag_tags, ["0014123456003"]);
+        assert_eq!(pass.legs.len(), 2);
+
+        let first = &pass.legs[0];
+        assert_eq!(first.airline_numeric_code.as_deref(), Some("014"));
+        assert_eq!(first.document_serial.as_deref(), Some("1234567890"));
+        assert_eq!(first.frequent_flyer_airline.as_deref(), Some("AC"));
+        assert_eq!(first.frequent_flyer_number.as_deref(), Some("1234567890123"));
+        assert_eq!(first.free_baggage_allowance.as_deref(), Some("20K"));
+        assert_eq!(first.fast_track, Some(true));
+        let second = &pass.legs[1];
+        assert_eq!((second.pnr.as_str(), second.to_airport.as_str()), ("DEF456", "GVA"));
+        assert_eq!((second.flight_number.as_str(), second.seat.as_str()), ("3664", "12C"));
+        assert_eq!(second.free_baggage_allowance.as_deref(), Some("2PC"));
+        assert_eq!(pass.security_data_type.as_deref(), Some("1"));
+        assert_eq!(pass.security_data.as_ref().map(String::len), Some(0x64));
+    }
+
+    #[test]
+    fn flight_days_resolve_near_the_issue_date() {
+        // Day 010 after an issue on day 360 of 2025 is in January 2026
+        let text = "M1DOE/JANE            EXYZ789 LHRJFKBA 0117 010Y010C0001 111>30B1W 5360BBA 00";
+        let pass = parse(text, today()).unwrap();
+        assert_eq!(pass.issue_date.as_deref(), Some("2025-12-26"));
+        assert_eq!(pass.legs[0].flight_date.as_deref(), Some("2026-01-10"));
+        assert_eq!(pass.issuer.as_deref(), Some("BA"));
+    }
+
+    #[test]
+    fn rejects_truncated_passes() {
+        for bad in [
+            "X1DOE",
+            "M1DOE/JANE            E",
+            "M0DOE/JANE            EXYZ789 LHRJFKBA 0117 010Y010C0001 100",
+            "M2DOE/JANE            EXYZ789 LHRJFKBA 0117 010Y010C0001 100",
+        ] {
+            assert_eq!(parse(bad, today()).unwrap_err().code(), "INVALID_PAYLOAD", "{}", bad);
+        }
+    }
+}
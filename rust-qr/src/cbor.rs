@@ -0,0 +1,146 @@
+//! CBOR envelopes of decode results for byte channels between a worker and
+//! native code. Struct fields become text-keyed maps, so generic CBOR tools
+//! can read the envelope, and `raw_bytes`, `crop_png` and module bits stay
+//! byte strings instead of growing into base64 or number arrays.
+
+use ciborium::Value;
+use serde::{Deserialize, Serialize};
+
+use crate::qr::QRCodeResult;
+use crate::VeloQrError;
+
+/// Written into every envelope. Bumped when a field is removed, renamed or
+/// changes type; new fields keep it, and readers ignore fields they do not
+/// know.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// `{ "schema_version": 1, "data": [result, ...] }`
+#[derive(Serialize)]
+struct Envelope<'a> {
+    schema_version: u32,
+    data: &'a [QRCodeResult],
+}
+
+/// Read side of `Envelope`; `data` is only interpreted once the version is
+/// known to match
+#[derive(Deserialize)]
+struct ReceivedEnvelope {
+    schema_version: u32,
+    data: Value,
+}
+
+pub fn encode(results: &[QRCodeResult]) -> Result<Vec<u8>, VeloQrError> {
+    let envelope = Envelope { schema_version: SCHEMA_VERSION, data: results };
+    let mut bytes = Vec::new();
+    ciborium::into_writer(&envelope, &mut bytes)
+        .map_err(|e| VeloQrError::SerializationFailed(e.to_string()))?;
+    Ok(bytes)
+}
+
+/// Results of an `encode`d envelope; one of another `schema_version` is
+/// refused rather than misread
+pub fn decode(bytes: &[u8]) -> Result<Vec<QRCodeResult>, VeloQrError> {
+    let envelope: ReceivedEnvelope =
+        ciborium::from_reader(bytes).map_err(|e| failed(&e.to_string()))?;
+    if envelope.schema_version != SCHEMA_VERSION {
+        return Err(failed(&format!(
+            "schema_version {}, this build reads {}",
+            envelope.schema_version, SCHEMA_VERSION
+        )));
+    }
+    envelope.data.deserialized().map_err(|e| failed(&e.to_string()))
+}
+
+fn failed(message: &str) -> VeloQrError {
+    VeloQrError::SerializationFailed(format!("CBOR: {}", message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::qr::{decode_gray_with_options, encode_png, DecodeOptions};
+
+    /// Results of decoding a QR code of `data`, with every optional field filled
+    fn results(data: &str) -> Vec<QRCodeResult> {
+        let png = encode_png(data, "L", 4, 4).unwrap();
+        let image = image::load_from_memory(&png).unwrap().to_luma8();
+        let options = DecodeOptions {
+            return_raw_bytes: true,
+            return_crop: true,
+            return_module_grid: true,
+            ..DecodeOptions::default()
+        };
+        decode_gray_with_options(image, &options).unwrap()
+    }
+
+    /// Value of a text key in a CBOR map
+    fn field<'a>(map: &'a Value, key: &str) -> &'a Value {
+        let entries = map.as_map().unwrap();
+        entries.iter().find(|(name, _)| name.as_text() == Some(key)).map(|(_, v)| v).unwrap()
+    }
+
+    fn envelope(version: u32, data: Vec<Value>) -> Vec<u8> {
+        let envelope = Value::Map(vec![
+            (Value::Text("schema_version".to_string()), Value::Integer(version.into())),
+            (Value::Text("data".to_string()), Value::Array(data)),
+        ]);
+        let mut bytes = Vec::new();
+        ciborium::into_writer(&envelope, &mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn results_round_trip() {
+        let results = results(&"x".repeat(1500));
+        assert_eq!(results.len(), 1);
+        let bytes = encode(&results).unwrap();
+        let back = decode(&bytes).unwrap();
+        assert_eq!(back.len(), 1);
+        let json = |result: &QRCodeResult| serde_json::to_string(result).unwrap();
+        assert_eq!(json(&back[0]), json(&results[0]));
+        assert_eq!(back[0].raw_bytes, results[0].raw_bytes);
+        assert_eq!(back[0].crop_png, results[0].crop_png);
+
+        // Text keys, and byte strings rather than number arrays
+        let value: Value = ciborium::from_reader(&bytes[..]).unwrap();
+        let version = field(&value, "schema_version").as_integer().map(i128::from);
+        assert_eq!(version, Some(SCHEMA_VERSION as i128));
+        let first = &field(&value, "data").as_array().unwrap()[0];
+        assert!(first.as_map().unwrap().iter().all(|(name, _)| name.is_text()));
+        assert_eq!(field(first, "raw_bytes").as_bytes(), Some(&results[0].raw_bytes));
+        assert!(field(first, "crop_png").is_bytes());
+        assert!(field(field(first, "modules"), "bits").is_bytes());
+        assert!(bytes.len() * 2 < serde_json::to_string(&results).unwrap().len());
+    }
+
+    #[test]
+    fn other_versions_and_damaged_envelopes_are_refused() {
+        assert!(decode(&encode(&[]).unwrap()).unwrap().is_empty());
+        assert!(decode(&envelope(SCHEMA_VERSION, Vec::new())).unwrap().is_empty());
+
+        let error = decode(&envelope(2, Vec::new())).err().unwrap();
+        assert_eq!(error.code(), "SERIALIZATION_FAILED");
+        assert!(error.to_string().contains("schema_version 2"), "{}", error);
+
+        assert!(decode(b"\xff\x00garbage").is_err());
+        let bytes = encode(&results("hello")).unwrap();
+        assert!(decode(&bytes[..bytes.len() - 3]).is_err());
+    }
+
+    #[test]
+    fn unknown_fields_are_ignored_and_missing_ones_defaulted() {
+        let bytes = encode(&results("hello")).unwrap();
+        let value: Value = ciborium::from_reader(&bytes[..]).unwrap();
+        let mut result = field(&value, "data").as_array().unwrap()[0].clone();
+        let Value::Map(fields) = &mut result else {
+            panic!("results are maps");
+        };
+        fields.retain(|(name, _)| !matches!(name.as_text(), Some("payload_hash" | "quality")));
+        fields.push((Value::Text("future_field".to_string()), Value::Bool(true)));
+
+        let back = decode(&envelope(SCHEMA_VERSION, vec![result])).unwrap();
+        assert_eq!(back[0].data, "hello");
+        assert_eq!(back[0].quality, 1.0);
+        assert!(back[0].payload_hash.is_empty());
+    }
+}
@@ -0,0 +1,164 @@
+//! `data:` URL input (RFC 2397) for `decode_qr_from_data_url`: the image
+//! bytes are base64-decoded here so callers do not need a JS decoder
+
+use crate::VeloQrError;
+
+/// Media types `encoded_to_gray` can decode
+const IMAGE_TYPES: [&str; 3] = ["image/png", "image/jpeg", "image/jpg"];
+
+/// Image bytes of a base64 `data:` URL with a PNG or JPEG media type
+pub(crate) fn decode(url: &str) -> Result<Vec<u8>, VeloQrError> {
+    let url = url.trim();
+    let rest = url
+        .get(..5)
+        .filter(|scheme| scheme.eq_ignore_ascii_case("data:"))
+        .map(|_| &url[5..])
+        .ok_or_else(|| VeloQrError::InvalidDataUrl("missing data: scheme".to_string()))?;
+    let (header, data) = rest
+        .split_once(',')
+        .ok_or_else(|| VeloQrError::InvalidDataUrl("missing ',' before the data".to_string()))?;
+
+    // `image/png;charset=...;base64`: the media type comes first, `base64` last
+    let mut params = header.split(';').map(str::trim);
+    let media_type = params.next().unwrap_or_default().to_ascii_lowercase();
+    let params: Vec<&str> = params.collect();
+    if !params.last().is_some_and(|p| p.eq_ignore_ascii_case("base64")) {
+        return Err(VeloQrError::InvalidDataUrl("only base64 data URLs are supported".to_string()));
+    }
+    if !IMAGE_TYPES.contains(&media_type.as_str()) {
+        let shown = if media_type.is_empty() { "text/plain" } else { &media_type };
+        return Err(VeloQrError::UnsupportedMediaType(shown.to_string()));
+    }
+
+    decode_base64(data)
+}
+
+/// Decode standard or URL-safe base64, ignoring whitespace; padding is optional
+fn decode_base64(data: &str) -> Result<Vec<u8>, VeloQrError> {
+    let mut out = Vec::with_capacity(data.len() / 4 * 3);
+    let (mut bits, mut count) = (0u32, 0u32);
+    let mut padding = 0;
+
+    for (index, byte) in data.bytes().enumerate() {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            b'=' => {
+                padding += 1;
+                continue;
+            }
+            b' ' | b'\t' | b'\r' | b'\n' => continue,
+            _ => {
+                return Err(VeloQrError::InvalidBase64(format!(
+                    "unexpected character {:?} at offset {}",
+                    byte as char, index
+                )))
+            }
+        };
+        if padding > 0 {
+            return Err(VeloQrError::InvalidBase64(format!("data after padding at offset {index}")));
+        }
+
+        bits = (bits << 6) | value as u32;
+        count += 1;
+        if count == 4 {
+            out.extend_from_slice(&bits.to_be_bytes()[1..]);
+            (bits, count) = (0, 0);
+        }
+    }
+
+    // A final group of 2 or 3 symbols carries 1 or 2 bytes; 1 symbol is truncated
+    match count {
+        0 if padding == 0 => {}
+        2 if padding <= 2 => out.push((bits >> 4) as u8),
+        3 if padding <= 1 => out.extend_from_slice(&((bits >> 2) as u16).to_be_bytes()),
+        _ => return Err(VeloQrError::InvalidBase64("truncated or misplaced padding".to_string())),
+    }
+    if out.is_empty() {
+        return Err(VeloQrError::InvalidBase64("no data".to_string()));
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::qr::{data_url_to_gray, decode_image, encode_png};
+
+    /// Standard or URL-safe base64 with padding
+    fn base64(data: &[u8], url_safe: bool) -> String {
+        let alphabet: &[u8] = if url_safe {
+            b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_"
+        } else {
+            b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/"
+        };
+        let mut encoded = String::new();
+        for chunk in data.chunks(3) {
+            let group = chunk.iter().enumerate().fold(0u32, |group, (i, &byte)| {
+                group | (byte as u32) << (16 - 8 * i)
+            });
+            for i in 0..4 {
+                if i <= chunk.len() {
+                    encoded.push(alphabet[(group >> (18 - 6 * i) & 63) as usize] as char);
+                } else {
+                    encoded.push('=');
+                }
+            }
+        }
+        encoded
+    }
+
+    #[test]
+    fn base64_of_every_tail_length_decodes() {
+        for length in 1..40u8 {
+            let data: Vec<u8> = (0..length).map(|i| i.wrapping_mul(37) ^ 0xa5).collect();
+            let standard = format!("data:image/png;base64,{}", base64(&data, false));
+            assert_eq!(decode(&standard).unwrap(), data, "{}", standard);
+            let unpadded = base64(&data, true).trim_end_matches('=').to_string();
+            let url_safe = format!("data:image/jpeg;charset=x;base64,{}", unpadded);
+            assert_eq!(decode(&url_safe).unwrap(), data, "{}", url_safe);
+        }
+    }
+
+    #[test]
+    fn data_urls_of_pngs_decode() {
+        let png = encode_png("data url", "M", 4, 4).unwrap();
+        let url = format!("data:image/png;base64,{}", base64(&png, false));
+        assert_eq!(decode_image(data_url_to_gray(&url).unwrap())[0].data, "data url");
+
+        // Surrounding spaces, a mixed-case header and line-wrapped URL-safe data
+        let wrapped: Vec<String> = base64(&png, true)
+            .trim_end_matches('=')
+            .as_bytes()
+            .chunks(76)
+            .map(|line| String::from_utf8(line.to_vec()).unwrap())
+            .collect();
+        let url = format!("  DATA:Image/PNG;BASE64,{}\r\n", wrapped.join("\r\n"));
+        assert_eq!(decode_image(data_url_to_gray(&url).unwrap())[0].data, "data url");
+    }
+
+    #[test]
+    fn malformed_data_urls_are_rejected_by_kind() {
+        let code = |url: &str| data_url_to_gray(url).unwrap_err().code();
+        for url in [
+            "image/png;base64,AAAA",
+            "data:image/png;base64",
+            "data:image/png,AAAA",
+            "data:é",
+        ] {
+            assert_eq!(code(url), "INVALID_DATA_URL", "{}", url);
+        }
+        assert_eq!(code("data:image/gif;base64,AAAA"), "UNSUPPORTED_MEDIA_TYPE");
+        assert_eq!(code("data:;base64,AAAA"), "UNSUPPORTED_MEDIA_TYPE");
+        for data in ["AA*A", "AAAAA", "AA=A", ""] {
+            let url = format!("data:image/png;base64,{}", data);
+            assert_eq!(code(&url), "INVALID_BASE64", "{}", url);
+        }
+        // Valid base64 that is not an image
+        assert_eq!(code("data:image/png;base64,AAAA"), "UNSUPPORTED_IMAGE_FORMAT");
+    }
+}
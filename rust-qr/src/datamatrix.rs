@@ -0,0 +1,1494 @@
+//! Data Matrix (ECC 200) detection and decoding.
+//!
+//! Candidates are the connected dark regions of a locally thresholded copy
+//! of the image. The solid "L" finder of a symbol shows up as two adjacent
+//! sides of the region's smallest enclosing rectangle that are dark all
+//! along; the other two sides carry the alternating timing pattern, whose
+//! runs give the module count. Modules are sampled through the homography
+//! of the four corners, read back with the ECC 200 placement, corrected with
+//! Reed-Solomon over GF(256) and decoded from the codeword encodation modes.
+
+use image::GrayImage;
+
+use crate::qr::{self, Homography, Line, QRCodeResult, Symbology};
+use crate::reed_solomon::{self, GaloisField};
+
+/// ECC 200 field, x^8 + x^5 + x^3 + x^2 + 1
+const PRIMITIVE: u32 = 0x12D;
+
+/// Smallest symbol side in pixels worth looking at (10 modules, 1 px each)
+const MIN_SYMBOL_PX: f64 = 10.0;
+
+/// Fewest dark pixels in a component that can hold a finder
+const MIN_COMPONENT_PIXELS: usize = 24;
+
+/// Fraction of samples along a finder side that must be dark
+const SOLID_SIDE: f64 = 0.85;
+
+/// Looser bound for the bounding rectangle, which perspective tilts off the finder
+const ROUGH_SIDE: f64 = 0.6;
+
+/// Fraction of border modules that must match the finder and timing pattern
+const MIN_BORDER_MATCH: f64 = 0.8;
+
+/// Corners of a candidate or symbol
+type Quad = [(f64, f64); 4];
+
+/// Rows, columns, data region size and codeword split of one symbol size
+struct SymbolSize {
+    rows: u32,
+    cols: u32,
+    region_rows: u32,
+    region_cols: u32,
+    data_codewords: usize,
+    ecc_per_block: usize,
+    blocks: usize,
+}
+
+const fn size(
+    rows: u32,
+    cols: u32,
+    region_rows: u32,
+    region_cols: u32,
+    data_codewords: usize,
+    ecc_per_block: usize,
+    blocks: usize,
+) -> SymbolSize {
+    SymbolSize {
+        rows,
+        cols,
+        region_rows,
+        region_cols,
+        data_codewords,
+        ecc_per_block,
+        blocks,
+    }
+}
+
+/// The 24 square and 6 rectangular ECC 200 sizes; `version` is the 1-based index
+const SYMBOL_SIZES: [SymbolSize; 30] = [
+    size(10, 10, 8, 8, 3, 5, 1),
+    size(12, 12, 10, 10, 5, 7, 1),
+    size(14, 14, 12, 12, 8, 10, 1),
+    size(16, 16, 14, 14, 12, 12, 1),
+    size(18, 18, 16, 16, 18, 14, 1),
+    size(20, 20, 18, 18, 22, 18, 1),
+    size(22, 22, 20, 20, 30, 20, 1),
+    size(24, 24, 22, 22, 36, 24, 1),
+    size(26, 26, 24, 24, 44, 28, 1),
+    size(32, 32, 14, 14, 62, 36, 1),
+    size(36, 36, 16, 16, 86, 42, 1),
+    size(40, 40, 18, 18, 114, 48, 1),
+    size(44, 44, 20, 20, 144, 56, 1),
+    size(48, 48, 22, 22, 174, 68, 1),
+    size(52, 52, 24, 24, 204, 42, 2),
+    size(64, 64, 14, 14, 280, 56, 2),
+    size(72, 72, 16, 16, 368, 36, 4),
+    size(80, 80, 18, 18, 456, 48, 4),
+    size(88, 88, 20, 20, 576, 56, 4),
+    size(96, 96, 22, 22, 696, 68, 4),
+    size(104, 104, 24, 24, 816, 56, 6),
+    size(120, 120, 18, 18, 1050, 68, 6),
+    size(132, 132, 20, 20, 1304, 62, 8),
+    size(144, 144, 22, 22, 1558, 62, 10),
+    size(8, 18, 6, 16, 5, 7, 1),
+    size(8, 32, 6, 14, 10, 11, 1),
+    size(12, 26, 10, 24, 16, 14, 1),
+    size(12, 36, 10, 16, 22, 18, 1),
+    size(16, 36, 14, 16, 32, 24, 1),
+    size(16, 48, 14, 22, 49, 28, 1),
+];
+
+/// `(columns, rows)` of the symbol with the given version
+pub(crate) fn dimensions(version: i32) -> Option<(u32, u32)> {
+    let index = usize::try_from(version).ok()?.checked_sub(1)?;
+    SYMBOL_SIZES.get(index).map(|s| (s.cols, s.rows))
+}
+
+/// Find and decode every Data Matrix symbol in a grayscale image
+pub(crate) fn decode(gray: &GrayImage) -> Vec<QRCodeResult> {
+    let (width, height) = gray.dimensions();
+    if (width.min(height) as f64) < MIN_SYMBOL_PX {
+        return Vec::new();
+    }
+
+    let mut binary = gray.clone();
+    let window = (width.min(height) / 8).max(15);
+    qr::local_threshold(&mut binary, window, |mean, _| mean - qr::ADAPTIVE_OFFSET);
+
+    let field = GaloisField::new(PRIMITIVE, 256);
+    let mut results: Vec<QRCodeResult> = Vec::new();
+    let mut components = Components::new(&binary);
+    while let Some(pixels) = components.next_component() {
+        if pixels.len() < MIN_COMPONENT_PIXELS {
+            continue;
+        }
+        for rect in enclosing_rectangles(pixels, width) {
+            let center = quad_center(&rect);
+            if results.iter().any(|r| qr::contains(&r.bounds, center)) {
+                break;
+            }
+            if let Some(result) = decode_candidate(gray, &binary, &field, rect) {
+                results.push(result);
+                break;
+            }
+        }
+    }
+
+    log_debug!("Decoded {} Data Matrix symbols", results.len());
+    results
+}
+
+// ==================== Detection ====================
+
+/// 8-connected regions of dark pixels, in raster order of their first pixel
+struct Components<'a> {
+    binary: &'a GrayImage,
+    visited: Vec<bool>,
+    next: usize,
+    stack: Vec<usize>,
+    pixels: Vec<usize>,
+}
+
+impl<'a> Components<'a> {
+    fn new(binary: &'a GrayImage) -> Self {
+        Components {
+            binary,
+            visited: vec![false; binary.as_raw().len()],
+            next: 0,
+            stack: Vec::new(),
+            pixels: Vec::new(),
+        }
+    }
+
+    /// Pixel indices of the next region; the slice is reused between calls
+    fn next_component(&mut self) -> Option<&[usize]> {
+        let raw = self.binary.as_raw();
+        let (width, height) = (self.binary.width() as usize, self.binary.height() as usize);
+        let start = (self.next..raw.len()).find(|&i| raw[i] == 0 && !self.visited[i])?;
+        self.next = start + 1;
+
+        self.pixels.clear();
+        self.visited[start] = true;
+        self.stack.push(start);
+        while let Some(i) = self.stack.pop() {
+            self.pixels.push(i);
+            let (x, y) = (i % width, i / width);
+            for dy in -1isize..=1 {
+                for dx in -1isize..=1 {
+                    let (nx, ny) = (x as isize + dx, y as isize + dy);
+                    if (dx, dy) == (0, 0)
+                        || nx < 0
+                        || ny < 0
+                        || nx as usize >= width
+                        || ny as usize >= height
+                    {
+                        continue;
+                    }
+                    let j = ny as usize * width + nx as usize;
+                    if raw[j] == 0 && !self.visited[j] {
+                        self.visited[j] = true;
+                        self.stack.push(j);
+                    }
+                }
+            }
+        }
+        Some(&self.pixels)
+    }
+}
+
+/// Smallest rectangles around a region, best first: one per convex hull edge
+/// direction within a quarter more than the minimum area. A bare L (timing
+/// pattern not connected) is a right triangle, whose hypotenuse-aligned box
+/// ties with the leg-aligned one, so more than one has to be tried.
+fn enclosing_rectangles(pixels: &[usize], width: u32) -> Vec<Quad> {
+    let width = width as usize;
+    let (mut x_min, mut x_max, mut y_min, mut y_max) = (usize::MAX, 0, usize::MAX, 0);
+    for &i in pixels {
+        let (x, y) = (i % width, i / width);
+        x_min = x_min.min(x);
+        x_max = x_max.max(x);
+        y_min = y_min.min(y);
+        y_max = y_max.max(y);
+    }
+    if ((x_max - x_min + 1).max(y_max - y_min + 1) as f64) < MIN_SYMBOL_PX {
+        return Vec::new();
+    }
+
+    // Leftmost and rightmost pixel corner on every row boundary, which come
+    // out sorted by y then x; rows of an 8-connected region never have gaps
+    let mut rows = vec![(usize::MAX, 0usize); y_max - y_min + 1];
+    for &i in pixels {
+        let row = &mut rows[i / width - y_min];
+        row.0 = row.0.min(i % width);
+        row.1 = row.1.max(i % width);
+    }
+    let mut points = Vec::with_capacity(rows.len() * 2 + 2);
+    for boundary in 0..=rows.len() {
+        let above = boundary.checked_sub(1).map(|i| rows[i]);
+        let below = rows.get(boundary).copied();
+        let (left, right) = [above, below]
+            .into_iter()
+            .flatten()
+            .fold((usize::MAX, 0), |(l, r), (left, right)| (l.min(left), r.max(right)));
+        let y = (y_min + boundary) as f64;
+        points.extend([(left as f64, y), (right as f64 + 1.0, y)]);
+    }
+    let hull = convex_hull(&points);
+
+    let mut boxes: Vec<(f64, f64, Quad)> = Vec::new();
+    for i in 0..hull.len() {
+        let (p, q) = (hull[i], hull[(i + 1) % hull.len()]);
+        let length = (q.0 - p.0).hypot(q.1 - p.1);
+        if length < f64::EPSILON {
+            continue;
+        }
+        let u = ((q.0 - p.0) / length, (q.1 - p.1) / length);
+        let n = (-u.1, u.0);
+        let (mut s0, mut s1, mut t0, mut t1) = (f64::MAX, f64::MIN, f64::MAX, f64::MIN);
+        for &(x, y) in &hull {
+            let (s, t) = (x * u.0 + y * u.1, x * n.0 + y * n.1);
+            s0 = s0.min(s);
+            s1 = s1.max(s);
+            t0 = t0.min(t);
+            t1 = t1.max(t);
+        }
+        let short = (s1 - s0).min(t1 - t0);
+        if short < MIN_SYMBOL_PX || (s1 - s0).max(t1 - t0) > short * 6.5 {
+            continue;
+        }
+        let point = |s: f64, t: f64| (s * u.0 + t * n.0, s * u.1 + t * n.1);
+        let corners = [point(s0, t0), point(s1, t0), point(s1, t1), point(s0, t1)];
+        let angle = u.1.atan2(u.0).to_degrees().rem_euclid(90.0);
+        boxes.push(((s1 - s0) * (t1 - t0), angle, clockwise(corners)));
+    }
+    boxes.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let Some(&(min_area, ..)) = boxes.first() else {
+        return Vec::new();
+    };
+    let mut picked: Vec<(f64, Quad)> = Vec::new();
+    for (area, angle, corners) in boxes {
+        if area > min_area * 1.25 || picked.len() == 4 {
+            break;
+        }
+        let distinct = picked.iter().all(|&(a, _)| {
+            let d = (a - angle).abs();
+            d.min(90.0 - d) > 2.0
+        });
+        if distinct {
+            picked.push((angle, corners));
+        }
+    }
+    picked.into_iter().map(|(_, corners)| corners).collect()
+}
+
+/// Andrew's monotone chain over points sorted by y, then x
+fn convex_hull(points: &[(f64, f64)]) -> Vec<(f64, f64)> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+    let cross = |o: (f64, f64), a: (f64, f64), b: (f64, f64)| {
+        (a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0)
+    };
+    let extend = |hull: &mut Vec<(f64, f64)>, p: (f64, f64), start: usize| {
+        while hull.len() >= start + 2 && cross(hull[hull.len() - 2], hull[hull.len() - 1], p) <= 0.0
+        {
+            hull.pop();
+        }
+        hull.push(p);
+    };
+
+    // Lower chain over increasing y, then the upper one back
+    let mut hull: Vec<(f64, f64)> = Vec::with_capacity(points.len() + 1);
+    for &p in points {
+        extend(&mut hull, p, 0);
+    }
+    hull.pop();
+    let start = hull.len();
+    for &p in points.iter().rev() {
+        extend(&mut hull, p, start);
+    }
+    hull.pop();
+    hull
+}
+
+/// Order corners so they turn clockwise on screen (image y points down)
+fn clockwise(mut corners: Quad) -> Quad {
+    let doubled: f64 = (0..4)
+        .map(|i| {
+            let (p, q) = (corners[i], corners[(i + 1) % 4]);
+            p.0 * q.1 - q.0 * p.1
+        })
+        .sum();
+    if doubled < 0.0 {
+        corners.reverse();
+    }
+    corners
+}
+
+fn quad_center(quad: &Quad) -> (f64, f64) {
+    let (x, y) = quad.iter().fold((0.0, 0.0), |acc, p| (acc.0 + p.0, acc.1 + p.1));
+    (x / 4.0, y / 4.0)
+}
+
+/// One side of a candidate rectangle with its inward unit normal
+struct Side {
+    from: (f64, f64),
+    to: (f64, f64),
+    normal: (f64, f64),
+    length: f64,
+}
+
+impl Side {
+    /// Side from `from` to `to` whose normal points towards `inside`
+    fn new(from: (f64, f64), to: (f64, f64), inside: (f64, f64)) -> Side {
+        let length = (to.0 - from.0).hypot(to.1 - from.1).max(f64::EPSILON);
+        let mut normal = (-(to.1 - from.1) / length, (to.0 - from.0) / length);
+        let mid = ((from.0 + to.0) / 2.0, (from.1 + to.1) / 2.0);
+        if (inside.0 - mid.0) * normal.0 + (inside.1 - mid.1) * normal.1 < 0.0 {
+            normal = (-normal.0, -normal.1);
+        }
+        Side {
+            from,
+            to,
+            normal,
+            length,
+        }
+    }
+
+    /// Point at fraction `t` along the side, `inset` pixels inside
+    fn at(&self, t: f64, inset: f64) -> (f64, f64) {
+        (
+            self.from.0 + (self.to.0 - self.from.0) * t + self.normal.0 * inset,
+            self.from.1 + (self.to.1 - self.from.1) * t + self.normal.1 * inset,
+        )
+    }
+
+    /// The symbol's outer edge along this side. Walking in from outside every
+    /// pixel along the side, the first dark pixel gives a point; the edge is
+    /// the line through two points of their outer hull that most points lie
+    /// on. On a timing side only the dark modules reach the edge, the light
+    /// ones hit data further in.
+    fn fit_edge(&self, binary: &GrayImage) -> Line {
+        let step = 0.5;
+        let (outside, depth) = (self.length * 0.06 + 2.0, self.length * 0.12 + 2.0);
+        let tolerance = 0.75 + self.length / 300.0;
+        let samples = self.length.ceil() as usize;
+        let hits: Vec<(f64, f64)> = (0..samples)
+            .filter_map(|k| {
+                let t = 0.03 + 0.94 * (k as f64 + 0.5) / samples as f64;
+                let mut inset = -outside;
+                while inset < depth {
+                    if qr::is_dark(binary, self.at(t, inset)) {
+                        return Some((t * self.length, inset));
+                    }
+                    inset += step;
+                }
+                None
+            })
+            .collect();
+
+        // Outer hull of the hits; inset grows inwards
+        let mut hull: Vec<(f64, f64)> = Vec::new();
+        for &p in &hits {
+            while let [.., a, b] = hull[..] {
+                if (b.0 - a.0) * (p.1 - a.1) - (b.1 - a.1) * (p.0 - a.0) > 0.0 {
+                    break;
+                }
+                hull.pop();
+            }
+            hull.push(p);
+        }
+
+        // Inset as a + b * distance along the side
+        let mut best: Option<(usize, f64, f64)> = None;
+        for (i, &(s0, d0)) in hull.iter().enumerate() {
+            for &(s1, d1) in &hull[i + 1..] {
+                if s1 - s0 < self.length * 0.25 {
+                    continue;
+                }
+                let b = (d1 - d0) / (s1 - s0);
+                let a = d0 - b * s0;
+                let (mut inliers, mut outliers) = (0, 0);
+                for &(s, d) in &hits {
+                    let residual = d - (a + b * s);
+                    if residual.abs() <= tolerance {
+                        inliers += 1;
+                    } else if residual < 0.0 {
+                        outliers += 1;
+                    }
+                }
+                if outliers * 20 <= hits.len() && best.is_none_or(|(n, ..)| inliers > n) {
+                    best = Some((inliers, a, b));
+                }
+            }
+        }
+
+        let tangent = (
+            (self.to.0 - self.from.0) / self.length,
+            (self.to.1 - self.from.1) / self.length,
+        );
+        let (a, b) = match best {
+            Some((inliers, a, b)) if inliers * 5 >= samples => {
+                // Least squares over the inliers of the best pair
+                let inliers: Vec<&(f64, f64)> = hits
+                    .iter()
+                    .filter(|&&(s, d)| (d - (a + b * s)).abs() <= tolerance)
+                    .collect();
+                let n = inliers.len() as f64;
+                let (ms, md) = inliers
+                    .iter()
+                    .fold((0.0, 0.0), |acc, p| (acc.0 + p.0 / n, acc.1 + p.1 / n));
+                let (sss, ssd) = inliers.iter().fold((0.0, 0.0), |acc, p| {
+                    (acc.0 + (p.0 - ms).powi(2), acc.1 + (p.0 - ms) * (p.1 - md))
+                });
+                let b = if sss > 0.0 { ssd / sss } else { b };
+                (md - b * ms, b)
+            }
+            _ => (0.0, 0.0),
+        };
+        let direction = (tangent.0 + self.normal.0 * b, tangent.1 + self.normal.1 * b);
+        let length = direction.0.hypot(direction.1);
+        Line {
+            point: self.at(0.0, a),
+            direction: (direction.0 / length, direction.1 / length),
+        }
+    }
+
+    /// Dark fraction of the side just inside the edge, at the best of a few
+    /// insets so both 1 px modules and blurred edges pass
+    fn solidity(&self, binary: &GrayImage) -> f64 {
+        const SAMPLES: usize = 40;
+        [0.5, 1.0, 1.5, 2.5, 3.5]
+            .into_iter()
+            .filter(|&inset| inset < self.length / 12.0)
+            .map(|inset| {
+                let dark = (0..SAMPLES)
+                    .filter(|&k| {
+                        let t = 0.05 + 0.9 * (k as f64 + 0.5) / SAMPLES as f64;
+                        qr::is_dark(binary, self.at(t, inset))
+                    })
+                    .count();
+                dark as f64 / SAMPLES as f64
+            })
+            .fold(0.0, f64::max)
+    }
+
+    /// Depth of the dark band along a finder side, taken at the lower quartile
+    /// so data modules touching the finder don't inflate it. The band may
+    /// start a pixel or so in where a rotated edge steps away from the hull.
+    fn thickness(&self, binary: &GrayImage) -> f64 {
+        let step = 0.5;
+        let limit = self.length / 4.0;
+        let mut depths: Vec<f64> = (0..16)
+            .map(|k| {
+                let t = 0.15 + 0.7 * k as f64 / 15.0;
+                let mut depth = step / 2.0;
+                while depth < 2.0 && !qr::is_dark(binary, self.at(t, depth)) {
+                    depth += step;
+                }
+                let start = depth;
+                while depth < limit && qr::is_dark(binary, self.at(t, depth)) {
+                    depth += step;
+                }
+                depth - start
+            })
+            .collect();
+        depths.sort_by(f64::total_cmp);
+        depths[depths.len() / 4]
+    }
+
+    /// Dark runs along the side `inset` pixels inside the edge, ignoring
+    /// flickers shorter than `min_run` pixels
+    fn dark_runs(&self, binary: &GrayImage, inset: f64, min_run: f64) -> u32 {
+        let step = 0.25;
+        let samples = (self.length / step).ceil() as usize;
+        let needed = ((min_run / step).round() as usize).max(1);
+        let (mut state, mut pending, mut runs) = (None, 0usize, 0u32);
+        for k in 0..samples {
+            let dark = qr::is_dark(binary, self.at((k as f64 + 0.5) / samples as f64, inset));
+            if state == Some(dark) {
+                pending = 0;
+                continue;
+            }
+            pending += 1;
+            if state.is_none() || pending >= needed {
+                state = Some(dark);
+                pending = 0;
+                runs += dark as u32;
+            }
+        }
+        runs
+    }
+}
+
+/// Validate the finder of a candidate rectangle and try to read it in both
+/// the plain and the mirrored module order
+fn decode_candidate(
+    gray: &GrayImage,
+    binary: &GrayImage,
+    field: &GaloisField,
+    rect: Quad,
+) -> Option<QRCodeResult> {
+    // The rectangle is flush with at least one finder arm, which rules out
+    // most texture before the more expensive edge fits
+    let center = quad_center(&rect);
+    let rect_sides: Vec<Side> = (0..4)
+        .map(|i| Side::new(rect[i], rect[(i + 1) % 4], center))
+        .collect();
+    if rect_sides.iter().all(|side| side.solidity(binary) < ROUGH_SIDE) {
+        return None;
+    }
+
+    // Perspective tilts the edges away from the rectangle; intersect the
+    // fitted edges instead, within reason
+    let edges: Vec<Line> = rect_sides.iter().map(|side| side.fit_edge(binary)).collect();
+    let corners: Quad = std::array::from_fn(|i| {
+        let reach = 3.0 + 0.4 * (rect[i].0 - center.0).hypot(rect[i].1 - center.1);
+        edges[(i + 3) % 4]
+            .intersect(&edges[i])
+            .filter(|p| (p.0 - rect[i].0).hypot(p.1 - rect[i].1) <= reach)
+            .unwrap_or(rect[i])
+    });
+    let center = quad_center(&corners);
+    let sides: Vec<Side> = (0..4)
+        .map(|i| Side::new(corners[i], corners[(i + 1) % 4], center))
+        .collect();
+    let solidity: Vec<f64> = sides.iter().map(|s| s.solidity(binary)).collect();
+
+    // The finder corner joins two solid sides opposite two patterned ones
+    let (corner, score) = (0..4)
+        .map(|k| {
+            let finder = solidity[(k + 3) % 4].min(solidity[k]);
+            let timing = solidity[(k + 1) % 4].max(solidity[(k + 2) % 4]);
+            (k, if finder >= SOLID_SIDE { finder - timing } else { f64::MIN })
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))?;
+    if score < 0.15 {
+        return None;
+    }
+
+    let module = sides[(corner + 3) % 4]
+        .thickness(binary)
+        .min(sides[corner].thickness(binary));
+    let at = |i: usize| corners[(corner + i) % 4];
+    // Corner order top-left, top-right, bottom-right, bottom-left; the
+    // mirrored order runs counter-clockwise
+    let orders = [
+        ([at(1), at(2), at(3), at(0)], false),
+        ([at(3), at(2), at(1), at(0)], true),
+    ];
+    for (quad, mirrored) in orders {
+        let top = Side::new(quad[0], quad[1], center);
+        let right = Side::new(quad[1], quad[2], center);
+        let cols = 2 * top.dark_runs(binary, module / 2.0, module * 0.3);
+        let rows = 2 * right.dark_runs(binary, module / 2.0, module * 0.3);
+
+        for (version, symbol) in nearest_sizes(rows, cols) {
+            if let Some(result) = read_symbol(gray, field, quad, version, symbol, mirrored) {
+                return Some(result);
+            }
+        }
+    }
+    None
+}
+
+/// Symbol sizes within two modules of the counted ones, closest first
+fn nearest_sizes(rows: u32, cols: u32) -> Vec<(i32, &'static SymbolSize)> {
+    let mut sizes: Vec<(u32, i32, &SymbolSize)> = SYMBOL_SIZES
+        .iter()
+        .enumerate()
+        .filter_map(|(i, s)| {
+            let distance = s.rows.abs_diff(rows) + s.cols.abs_diff(cols);
+            (s.rows.abs_diff(rows) <= 2 && s.cols.abs_diff(cols) <= 2)
+                .then_some((distance, i as i32 + 1, s))
+        })
+        .collect();
+    sizes.sort_by_key(|&(distance, ..)| distance);
+    sizes.into_iter().take(3).map(|(_, v, s)| (v, s)).collect()
+}
+
+/// Sample the module grid inside `quad` (top-left first) and decode it as
+/// the given symbol size
+fn read_symbol(
+    gray: &GrayImage,
+    field: &GaloisField,
+    quad: Quad,
+    version: i32,
+    symbol: &SymbolSize,
+    mirrored: bool,
+) -> Option<QRCodeResult> {
+    let (rows, cols) = (symbol.rows as usize, symbol.cols as usize);
+    let map = Homography::square_to_quad(quad);
+    let values: Vec<f64> = (0..rows * cols)
+        .map(|i| {
+            let (r, c) = (i / cols, i % cols);
+            let u = (c as f64 + 0.5) / cols as f64;
+            let v = (r as f64 + 0.5) / rows as f64;
+            qr::sample(gray, map.apply(u, v))
+        })
+        .collect();
+
+    // Threshold halfway between the finder (dark) and the light timing modules
+    let index = |r: usize, c: usize| r * cols + c;
+    let finder: Vec<usize> = (0..rows)
+        .map(|r| index(r, 0))
+        .chain((0..cols).map(|c| index(rows - 1, c)))
+        .collect();
+    let light: Vec<usize> = (0..cols)
+        .filter(|c| c % 2 == 1)
+        .map(|c| index(0, c))
+        .chain((0..rows).filter(|r| (rows - 1 - r) % 2 == 1).map(|r| index(r, cols - 1)))
+        .collect();
+    let mean = |cells: &[usize]| cells.iter().map(|&i| values[i]).sum::<f64>() / cells.len() as f64;
+    let threshold = (mean(&finder) + mean(&light)) / 2.0;
+    let dark: Vec<bool> = values.iter().map(|&v| v < threshold).collect();
+
+    let expected = (0..rows)
+        .flat_map(|r| [(index(r, 0), true), (index(r, cols - 1), (rows - 1 - r) % 2 == 0)])
+        .chain((0..cols).flat_map(|c| [(index(rows - 1, c), true), (index(0, c), c % 2 == 0)]));
+    let (matches, total) = expected.fold((0, 0), |(matches, total), (i, want)| {
+        (matches + (dark[i] == want) as usize, total + 1)
+    });
+    if (matches as f64) < total as f64 * MIN_BORDER_MATCH {
+        return None;
+    }
+
+    // Drop the finder and timing of every data region
+    let (region_rows, region_cols) = (symbol.region_rows as usize, symbol.region_cols as usize);
+    let (mapping_rows, mapping_cols) = (
+        rows / (region_rows + 2) * region_rows,
+        cols / (region_cols + 2) * region_cols,
+    );
+    let mapping: Vec<bool> = (0..mapping_rows * mapping_cols)
+        .map(|i| {
+            let (r, c) = (i / mapping_cols, i % mapping_cols);
+            let row = r / region_rows * (region_rows + 2) + 1 + r % region_rows;
+            let col = c / region_cols * (region_cols + 2) + 1 + c % region_cols;
+            dark[index(row, col)]
+        })
+        .collect();
+
+    let codewords = read_codewords(&mapping, mapping_rows, mapping_cols);
+    let total_codewords = symbol.data_codewords + symbol.ecc_per_block * symbol.blocks;
+    if codewords.len() < total_codewords {
+        return None;
+    }
+    let (data, corrected) = correct_blocks(field, &codewords[..total_codewords], symbol)?;
+    let payload = decode_payload(&data)?;
+
+    // Without an ECI, ECC 200 text is ISO-8859-1; UTF-8 is common in practice
+    let eci = payload
+        .eci
+        .or_else(|| std::str::from_utf8(&payload.bytes).is_err().then_some(3));
+    let text = qr::payload_text(&payload.bytes, eci, false);
+    let payload_hash = qr::hash_payload(&payload.bytes);
+    let mut result = QRCodeResult {
+        symbology: Symbology::DataMatrix,
+        data: text.data,
+        version,
+        bounds: quad.to_vec(),
+        ecc_level: "ECC200".to_string(),
+        mask: 0,
+        raw_bytes: payload.bytes,
+        inverted: false,
+        mirrored,
+        gs1: payload.gs1,
+        strategy: String::new(),
+        orientation_degrees: 0.0,
+        center: (0.0, 0.0),
+        area: 0.0,
+        angle_degrees: 0.0,
+        module_size_px: 0.0,
+        structured_append: None,
+        encoding: text.encoding,
+        encoding_error: text.failed,
+        check_digit_valid: None,
+        crop_png: Vec::new(),
+        modules: None,
+        track_id: None,
+        smoothed_bounds: Vec::new(),
+        corrected_codewords: corrected.iter().sum::<usize>() as u32,
+        quality: qr::correction_quality(corrected.iter().map(|&c| (c, symbol.ecc_per_block / 2))),
+        payload_hash,
+    };
+    result.update_geometry();
+    Some(result)
+}
+
+// ==================== Codewords ====================
+
+/// Read the codewords of a mapping matrix with the ECC 200 placement
+/// (ISO/IEC 16022 annex F), most significant bit first
+fn read_codewords(mapping: &[bool], rows: usize, cols: usize) -> Vec<u8> {
+    let (nrow, ncol) = (rows as i32, cols as i32);
+    let mut used = vec![false; rows * cols];
+    let mut codewords = Vec::with_capacity(rows * cols / 8);
+
+    let mut read = |cells: [(i32, i32); 8], used: &mut Vec<bool>| {
+        let mut value = 0u8;
+        for (mut row, mut col) in cells {
+            // Modules falling off one edge wrap to the opposite one
+            if row < 0 {
+                row += nrow;
+                col += 4 - ((nrow + 4) % 8);
+            }
+            if col < 0 {
+                col += ncol;
+                row += 4 - ((ncol + 4) % 8);
+            }
+            let i = (row.rem_euclid(nrow) * ncol + col.rem_euclid(ncol)) as usize;
+            used[i] = true;
+            value = value << 1 | mapping[i] as u8;
+        }
+        codewords.push(value);
+    };
+    let utah = |row: i32, col: i32| {
+        [
+            (row - 2, col - 2),
+            (row - 2, col - 1),
+            (row - 1, col - 2),
+            (row - 1, col - 1),
+            (row - 1, col),
+            (row, col - 2),
+            (row, col - 1),
+            (row, col),
+        ]
+    };
+
+    let (mut row, mut col) = (4i32, 0i32);
+    loop {
+        let corner = if row == nrow && col == 0 {
+            Some([
+                (nrow - 1, 0),
+                (nrow - 1, 1),
+                (nrow - 1, 2),
+                (0, ncol - 2),
+                (0, ncol - 1),
+                (1, ncol - 1),
+                (2, ncol - 1),
+                (3, ncol - 1),
+            ])
+        } else if row == nrow - 2 && col == 0 && ncol % 4 != 0 {
+            Some([
+                (nrow - 3, 0),
+                (nrow - 2, 0),
+                (nrow - 1, 0),
+                (0, ncol - 4),
+                (0, ncol - 3),
+                (0, ncol - 2),
+                (0, ncol - 1),
+                (1, ncol - 1),
+            ])
+        } else if row == nrow - 2 && col == 0 && ncol % 8 == 4 {
+            Some([
+                (nrow - 3, 0),
+                (nrow - 2, 0),
+                (nrow - 1, 0),
+                (0, ncol - 2),
+                (0, ncol - 1),
+                (1, ncol - 1),
+                (2, ncol - 1),
+                (3, ncol - 1),
+            ])
+        } else if row == nrow + 4 && col == 2 && ncol % 8 == 0 {
+            Some([
+                (nrow - 1, 0),
+                (nrow - 1, ncol - 1),
+                (0, ncol - 3),
+                (0, ncol - 2),
+                (0, ncol - 1),
+                (1, ncol - 3),
+                (1, ncol - 2),
+                (1, ncol - 1),
+            ])
+        } else {
+            None
+        };
+        if let Some(cells) = corner {
+            read(cells, &mut used);
+        }
+
+        // Sweep up and to the right, then down and to the left
+        loop {
+            if row < nrow && col >= 0 && !used[(row * ncol + col) as usize] {
+                read(utah(row, col), &mut used);
+            }
+            row -= 2;
+            col += 2;
+            if row < 0 || col >= ncol {
+                break;
+            }
+        }
+        row += 1;
+        col += 3;
+        loop {
+            if row >= 0 && col < ncol && !used[(row * ncol + col) as usize] {
+                read(utah(row, col), &mut used);
+            }
+            row += 2;
+            col -= 2;
+            if row >= nrow || col < 0 {
+                break;
+            }
+        }
+        row += 3;
+        col += 1;
+
+        if row >= nrow && col >= ncol {
+            break;
+        }
+    }
+    codewords
+}
+
+/// Split the interleaved codewords into blocks, correct each and return the
+/// data codewords in their original order with the corrections per block
+fn correct_blocks(
+    field: &GaloisField,
+    codewords: &[u8],
+    symbol: &SymbolSize,
+) -> Option<(Vec<u8>, Vec<usize>)> {
+    let blocks = symbol.blocks;
+    let data_len = symbol.data_codewords;
+    let mut data = vec![0u8; data_len];
+    let mut corrected = Vec::with_capacity(blocks);
+    for block in 0..blocks {
+        // Codeword k of either part belongs to block k % blocks, counting the
+        // check codewords from their own start: 144x144 has 1558 data codewords
+        let data_positions: Vec<usize> = (block..data_len).step_by(blocks).collect();
+        let ecc_positions = (data_len + block..codewords.len()).step_by(blocks);
+        let mut words: Vec<u16> = data_positions
+            .iter()
+            .copied()
+            .chain(ecc_positions)
+            .map(|i| codewords[i] as u16)
+            .collect();
+        corrected.push(reed_solomon::correct(field, &mut words, symbol.ecc_per_block)?);
+        for (&position, &word) in data_positions.iter().zip(&words) {
+            data[position] = word as u8;
+        }
+    }
+    if corrected.iter().any(|&c| c > 0) {
+        log_debug!("Corrected {} Data Matrix codewords", corrected.iter().sum::<usize>());
+    }
+    Some((data, corrected))
+}
+
+// ==================== Decodation ====================
+
+/// Bytes, charset designator and GS1 flag of a decoded symbol
+struct Payload {
+    bytes: Vec<u8>,
+    eci: Option<u32>,
+    gs1: bool,
+}
+
+/// Encodation modes latched from ASCII
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Ascii,
+    C40,
+    Text,
+    AnsiX12,
+    Edifact,
+    Base256,
+}
+
+/// Codeword stream that can also be read in 6-bit EDIFACT units
+struct Bits<'a> {
+    bytes: &'a [u8],
+    position: usize, // in bits
+}
+
+impl Bits<'_> {
+    fn available(&self) -> usize {
+        self.bytes.len() * 8 - self.position
+    }
+
+    fn read(&mut self, count: usize) -> Option<u32> {
+        if count > self.available() {
+            return None;
+        }
+        let mut value = 0u32;
+        for _ in 0..count {
+            let byte = self.bytes[self.position / 8];
+            value = value << 1 | ((byte >> (7 - self.position % 8)) & 1) as u32;
+            self.position += 1;
+        }
+        Some(value)
+    }
+
+    fn byte(&mut self) -> Option<u8> {
+        self.read(8).map(|v| v as u8)
+    }
+
+    /// 1-based position of the next codeword, used by the Base 256 randomizer
+    fn codeword_position(&self) -> usize {
+        self.position / 8 + 1
+    }
+}
+
+/// Decode the data codewords into bytes
+fn decode_payload(codewords: &[u8]) -> Option<Payload> {
+    let mut bits = Bits {
+        bytes: codewords,
+        position: 0,
+    };
+    let mut payload = Payload {
+        bytes: Vec::new(),
+        eci: None,
+        gs1: false,
+    };
+    let mut trailer: &[u8] = &[];
+    let mut mode = Mode::Ascii;
+    loop {
+        mode = match mode {
+            Mode::Ascii => match decode_ascii(&mut bits, &mut payload, &mut trailer)? {
+                Some(mode) => mode,
+                None => break,
+            },
+            Mode::C40 | Mode::Text => {
+                decode_c40_text(&mut bits, &mut payload.bytes, mode == Mode::Text)?;
+                Mode::Ascii
+            }
+            Mode::AnsiX12 => {
+                decode_ansi_x12(&mut bits, &mut payload.bytes)?;
+                Mode::Ascii
+            }
+            Mode::Edifact => {
+                decode_edifact(&mut bits, &mut payload.bytes);
+                Mode::Ascii
+            }
+            Mode::Base256 => {
+                decode_base256(&mut bits, &mut payload.bytes)?;
+                Mode::Ascii
+            }
+        };
+        if bits.available() < 8 {
+            break;
+        }
+    }
+    payload.bytes.extend_from_slice(trailer);
+    Some(payload)
+}
+
+/// ASCII codewords until a latch (returned) or the end of data (`None`)
+fn decode_ascii(
+    bits: &mut Bits,
+    payload: &mut Payload,
+    trailer: &mut &'static [u8],
+) -> Option<Option<Mode>> {
+    let mut upper_shift = false;
+    while let Some(codeword) = bits.byte() {
+        let first = bits.codeword_position() == 2;
+        match codeword {
+            0 => return None,
+            1..=128 => {
+                let value = codeword - 1;
+                payload.bytes.push(if upper_shift { value + 128 } else { value });
+                upper_shift = false;
+            }
+            129 => return Some(None), // pad
+            130..=229 => {
+                let value = codeword - 130;
+                payload.bytes.extend([b'0' + value / 10, b'0' + value % 10]);
+            }
+            230 => return Some(Some(Mode::C40)),
+            231 => return Some(Some(Mode::Base256)),
+            232 => {
+                // FNC1 first marks GS1 data; later ones separate elements
+                if first {
+                    payload.gs1 = true;
+                } else {
+                    payload.bytes.push(0x1D);
+                }
+            }
+            233 => {
+                // Structured append: sequence indicator and two file ids
+                bits.read(24)?;
+            }
+            234 => {} // Reader programming
+            235 => upper_shift = true,
+            236 | 237 => {
+                let header: &[u8] = if codeword == 236 {
+                    b"[)>\x1E05\x1D"
+                } else {
+                    b"[)>\x1E06\x1D"
+                };
+                payload.bytes.extend_from_slice(header);
+                *trailer = b"\x1E\x04";
+            }
+            238 => return Some(Some(Mode::AnsiX12)),
+            239 => return Some(Some(Mode::Text)),
+            240 => return Some(Some(Mode::Edifact)),
+            241 => {
+                let eci = decode_eci(bits)?;
+                payload.eci.get_or_insert(eci);
+            }
+            // 254 after a C40/Text/X12 latch is an unlatch already consumed there
+            254 => {}
+            _ => return None,
+        }
+    }
+    Some(None)
+}
+
+/// ECI assignment number in one to three codewords
+fn decode_eci(bits: &mut Bits) -> Option<u32> {
+    let c1 = bits.byte()? as u32;
+    if c1 <= 127 {
+        return c1.checked_sub(1);
+    }
+    let c2 = bits.byte()? as u32;
+    if c1 <= 191 {
+        return Some((c1 - 128) * 254 + c2.checked_sub(1)? + 127);
+    }
+    let c3 = bits.byte()? as u32;
+    Some((c1 - 192) * 64516 + c2.checked_sub(1)? * 254 + c3.checked_sub(1)? + 16383)
+}
+
+/// Three values 0-39 packed in every codeword pair; 254 unlatches
+fn triples(bits: &mut Bits) -> Option<[u8; 3]> {
+    if bits.available() < 16 {
+        return None;
+    }
+    let c1 = bits.byte()?;
+    if c1 == 254 {
+        return None;
+    }
+    let value = (c1 as u32 * 256 + bits.byte()? as u32).checked_sub(1)?;
+    Some([(value / 1600) as u8, (value / 40 % 40) as u8, (value % 40) as u8])
+}
+
+/// C40 (upper case basic set) and Text (lower case basic set)
+fn decode_c40_text(bits: &mut Bits, out: &mut Vec<u8>, text: bool) -> Option<()> {
+    const SHIFT2: &[u8; 27] = b"!\"#$%&'()*+,-./:;<=>?@[\\]^_";
+    let (mut shift, mut upper_shift) = (0, false);
+    while let Some(values) = triples(bits) {
+        for value in values {
+            let mut push = |byte: u8, upper_shift: &mut bool| {
+                out.push(if *upper_shift { byte + 128 } else { byte });
+                *upper_shift = false;
+            };
+            match shift {
+                0 => match value {
+                    0..=2 => shift = value + 1,
+                    3 => push(b' ', &mut upper_shift),
+                    4..=13 => push(b'0' + value - 4, &mut upper_shift),
+                    _ if text => push(b'a' + value - 14, &mut upper_shift),
+                    _ => push(b'A' + value - 14, &mut upper_shift),
+                },
+                1 => {
+                    push(value, &mut upper_shift);
+                    shift = 0;
+                }
+                2 => {
+                    match value {
+                        0..=26 => push(SHIFT2[value as usize], &mut upper_shift),
+                        27 => push(0x1D, &mut upper_shift), // FNC1
+                        30 => upper_shift = true,
+                        _ => return None,
+                    }
+                    shift = 0;
+                }
+                _ => {
+                    let byte = match value {
+                        _ if !text => value + 96,
+                        0 => b'`',
+                        1..=26 => b'A' + value - 1,
+                        27..=31 => b"{|}~\x7F"[value as usize - 27],
+                        _ => return None,
+                    };
+                    push(byte, &mut upper_shift);
+                    shift = 0;
+                }
+            }
+        }
+    }
+    Some(())
+}
+
+/// ANSI X12: CR, `*`, `>`, space, digits and capitals
+fn decode_ansi_x12(bits: &mut Bits, out: &mut Vec<u8>) -> Option<()> {
+    while let Some(values) = triples(bits) {
+        for value in values {
+            out.push(match value {
+                0 => b'\r',
+                1 => b'*',
+                2 => b'>',
+                3 => b' ',
+                4..=13 => b'0' + value - 4,
+                14..=39 => b'A' + value - 14,
+                _ => return None,
+            });
+        }
+    }
+    Some(())
+}
+
+/// EDIFACT: 6-bit values until the unlatch value or the last two codewords,
+/// which are always ASCII
+fn decode_edifact(bits: &mut Bits, out: &mut Vec<u8>) {
+    while bits.available() > 16 {
+        for _ in 0..4 {
+            let Some(value) = bits.read(6) else {
+                return;
+            };
+            if value == 0x1F {
+                // Resume ASCII on the next codeword boundary
+                bits.position = bits.position.div_ceil(8) * 8;
+                return;
+            }
+            let value = if value & 0x20 == 0 { value | 0x40 } else { value };
+            out.push(value as u8);
+        }
+    }
+}
+
+/// Base 256: a length field, then bytes, all under the 255-state randomizer
+fn decode_base256(bits: &mut Bits, out: &mut Vec<u8>) -> Option<()> {
+    let next = |bits: &mut Bits| {
+        let position = bits.codeword_position();
+        let pseudo = (149 * position % 255 + 1) as i32;
+        bits.byte().map(|codeword| (codeword as i32 - pseudo).rem_euclid(256) as usize)
+    };
+    let d1 = next(bits)?;
+    let count = match d1 {
+        0 => bits.available() / 8,
+        1..=249 => d1,
+        _ => 250 * (d1 - 249) + next(bits)?,
+    };
+    for _ in 0..count {
+        out.push(next(bits)? as u8);
+    }
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    /// ASCII encodation: digit pairs, characters + 1, upper shift above 127
+    fn ascii_codewords(data: &[u8]) -> Vec<u8> {
+        let mut codewords = Vec::new();
+        let mut i = 0;
+        while i < data.len() {
+            let pair = data.get(i..i + 2).filter(|pair| pair.iter().all(u8::is_ascii_digit));
+            if let Some(pair) = pair {
+                codewords.push(130 + (pair[0] - b'0') * 10 + (pair[1] - b'0'));
+                i += 2;
+                continue;
+            }
+            match data[i] {
+                byte @ 0..=127 => codewords.push(byte + 1),
+                byte => codewords.extend([235, byte - 127]),
+            }
+            i += 1;
+        }
+        codewords
+    }
+
+    /// Data codewords padded to the symbol's capacity, then the interleaved
+    /// check codewords of each block
+    fn symbol_codewords(text: &[u8], symbol: &SymbolSize) -> Vec<u8> {
+        let mut data = ascii_codewords(text);
+        assert!(data.len() <= symbol.data_codewords);
+        if data.len() < symbol.data_codewords {
+            data.push(129);
+        }
+        while data.len() < symbol.data_codewords {
+            // The 253-state randomized pad
+            let pseudo = (149 * (data.len() + 1)) % 253 + 1;
+            data.push(((129 + pseudo - 1) % 254 + 1) as u8);
+        }
+
+        let field = GaloisField::new(PRIMITIVE, 256);
+        let check_len = symbol.ecc_per_block;
+        let mut generator = vec![1u16];
+        for j in 1..=check_len {
+            let mut next = generator.clone();
+            next.push(0);
+            for (i, &c) in generator.iter().enumerate() {
+                next[i + 1] ^= field.mul(c, field.exp(j));
+            }
+            generator = next;
+        }
+        let mut codewords = data.clone();
+        codewords.resize(symbol.data_codewords + check_len * symbol.blocks, 0);
+        for block in 0..symbol.blocks {
+            let mut remainder = vec![0u16; check_len];
+            for &word in data.iter().skip(block).step_by(symbol.blocks) {
+                let factor = word as u16 ^ remainder[0];
+                remainder.rotate_left(1);
+                remainder[check_len - 1] = 0;
+                for (value, &g) in remainder.iter_mut().zip(&generator[1..]) {
+                    *value ^= field.mul(g, factor);
+                }
+            }
+            for (k, &r) in remainder.iter().enumerate() {
+                codewords[symbol.data_codewords + block + k * symbol.blocks] = r as u8;
+            }
+        }
+        codewords
+    }
+
+    /// The ISO/IEC 16022 annex F placement, writing codeword and bit numbers
+    /// (`10 * codeword + bit`, 1-based) into a mapping matrix
+    struct Placement {
+        rows: i32,
+        cols: i32,
+        cells: Vec<i32>,
+    }
+
+    impl Placement {
+        fn module(&mut self, mut row: i32, mut col: i32, codeword: i32, bit: i32) {
+            if row < 0 {
+                row += self.rows;
+                col += 4 - ((self.rows + 4) % 8);
+            }
+            if col < 0 {
+                col += self.cols;
+                row += 4 - ((self.cols + 4) % 8);
+            }
+            self.cells[(row * self.cols + col) as usize] = 10 * codeword + bit;
+        }
+
+        fn place(&mut self, cells: [(i32, i32); 8], codeword: i32) {
+            for (bit, (row, col)) in cells.into_iter().enumerate() {
+                self.module(row, col, codeword, bit as i32 + 1);
+            }
+        }
+
+        fn utah(&mut self, row: i32, col: i32, codeword: i32) {
+            let cells = [
+                (row - 2, col - 2),
+                (row - 2, col - 1),
+                (row - 1, col - 2),
+                (row - 1, col - 1),
+                (row - 1, col),
+                (row, col - 2),
+                (row, col - 1),
+                (row, col),
+            ];
+            self.place(cells, codeword);
+        }
+
+        fn new(rows: i32, cols: i32) -> Placement {
+            let mut placement = Placement { rows, cols, cells: vec![0; (rows * cols) as usize] };
+            let (r, c) = (rows, cols);
+            let corners = [
+                [
+                    (r - 1, 0), (r - 1, 1), (r - 1, 2), (0, c - 2),
+                    (0, c - 1), (1, c - 1), (2, c - 1), (3, c - 1),
+                ],
+                [
+                    (r - 3, 0), (r - 2, 0), (r - 1, 0), (0, c - 4),
+                    (0, c - 3), (0, c - 2), (0, c - 1), (1, c - 1),
+                ],
+                [
+                    (r - 3, 0), (r - 2, 0), (r - 1, 0), (0, c - 2),
+                    (0, c - 1), (1, c - 1), (2, c - 1), (3, c - 1),
+                ],
+                [
+                    (r - 1, 0), (r - 1, c - 1), (0, c - 3), (0, c - 2),
+                    (0, c - 1), (1, c - 3), (1, c - 2), (1, c - 1),
+                ],
+            ];
+            let mut codeword = 1;
+            let (mut row, mut col) = (4, 0);
+            loop {
+                let corner = [
+                    row == r && col == 0,
+                    row == r - 2 && col == 0 && c % 4 != 0,
+                    row == r - 2 && col == 0 && c % 8 == 4,
+                    row == r + 4 && col == 2 && c % 8 == 0,
+                ];
+                for (cells, _) in corners.iter().zip(corner).filter(|(_, hit)| *hit) {
+                    placement.place(*cells, codeword);
+                    codeword += 1;
+                }
+                while row >= 0 && col < c {
+                    if row < r && col >= 0 && placement.cells[(row * c + col) as usize] == 0 {
+                        placement.utah(row, col, codeword);
+                        codeword += 1;
+                    }
+                    row -= 2;
+                    col += 2;
+                }
+                row += 1;
+                col += 3;
+                while row < r && col >= 0 {
+                    if row >= 0 && col < c && placement.cells[(row * c + col) as usize] == 0 {
+                        placement.utah(row, col, codeword);
+                        codeword += 1;
+                    }
+                    row += 2;
+                    col -= 2;
+                }
+                row += 3;
+                col += 1;
+                if row >= r && col >= c {
+                    break;
+                }
+            }
+            // The fixed pattern in the lower right corner of some sizes
+            if placement.cells[(r * c - 1) as usize] == 0 {
+                placement.cells[(r * c - 1) as usize] = 1;
+                placement.cells[(r * c - c - 2) as usize] = 1;
+            }
+            placement
+        }
+    }
+
+    /// Dark modules of the symbol, finder and timing patterns around each region
+    fn modules(text: &[u8], version: i32) -> Vec<Vec<bool>> {
+        let symbol = &SYMBOL_SIZES[version as usize - 1];
+        let codewords = symbol_codewords(text, symbol);
+        let (region_rows, region_cols) = (symbol.region_rows as usize, symbol.region_cols as usize);
+        let (rows, cols) = (symbol.rows as usize, symbol.cols as usize);
+        let mapping_cols = cols / (region_cols + 2) * region_cols;
+        let mapping_rows = rows / (region_rows + 2) * region_rows;
+        let placement = Placement::new(mapping_rows as i32, mapping_cols as i32);
+        let mapped = |row: usize, col: usize| match placement.cells[row * mapping_cols + col] {
+            0 => false,
+            1 => true,
+            cell => codewords[cell as usize / 10 - 1] >> (8 - cell % 10) & 1 == 1,
+        };
+        (0..rows)
+            .map(|r| {
+                (0..cols)
+                    .map(|c| {
+                        let (row, col) = (r % (region_rows + 2), c % (region_cols + 2));
+                        if col == 0 || row == region_rows + 1 {
+                            true
+                        } else if row == 0 {
+                            c % 2 == 0
+                        } else if col == region_cols + 1 {
+                            (region_rows + 1 - row) % 2 == 0
+                        } else {
+                            let row = r / (region_rows + 2) * region_rows + row - 1;
+                            mapped(row, c / (region_cols + 2) * region_cols + col - 1)
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// `module` pixels per module inside a four-module quiet zone, turned
+    /// by `degrees` about the center
+    fn render(modules: &[Vec<bool>], module: f64, degrees: f64) -> GrayImage {
+        let (rows, cols) = (modules.len() as f64, modules[0].len() as f64);
+        let side = ((rows.hypot(cols) + 8.0) * module).ceil() as u32;
+        let center = side as f64 / 2.0;
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        GrayImage::from_fn(side, side, |x, y| {
+            let (dx, dy) = (x as f64 + 0.5 - center, y as f64 + 0.5 - center);
+            let col = (cos * dx + sin * dy) / module + cols / 2.0;
+            let row = (cos * dy - sin * dx) / module + rows / 2.0;
+            let inside = (0.0..cols).contains(&col) && (0.0..rows).contains(&row);
+            let dark = inside && modules[row as usize][col as usize];
+            Luma([if dark { 0 } else { 255 }])
+        })
+    }
+
+    fn decode_words(codewords: &[u8]) -> Payload {
+        decode_payload(codewords).unwrap()
+    }
+
+    #[test]
+    fn decodes_every_symbol_size() {
+        let sample = b"Hello, Data Matrix! 0123456789";
+        for version in 1..=30 {
+            let symbol = &SYMBOL_SIZES[version as usize - 1];
+            let mut text: Vec<u8> =
+                sample.iter().cycle().take(symbol.data_codewords).copied().collect();
+            while ascii_codewords(&text).len() > symbol.data_codewords {
+                text.pop();
+            }
+            let results = decode(&render(&modules(&text, version), 3.0, 0.0));
+            assert_eq!(results.len(), 1, "version {}", version);
+            let result = &results[0];
+            assert_eq!(result.data.as_bytes(), text, "version {}", version);
+            assert_eq!(result.version, version);
+            assert_eq!(result.symbology, Symbology::DataMatrix);
+            assert_eq!(result.ecc_level, "ECC200");
+            assert!(!result.mirrored);
+            assert!((result.module_size_px - 3.0).abs() < 0.2, "{}", result.module_size_px);
+        }
+    }
+
+    #[test]
+    fn rotated_and_mirrored_symbols_decode() {
+        let url = "https://example.com/track/123456";
+        let symbol = modules(url.as_bytes(), 8);
+        for degrees in [0.0, 17.0, 45.0, 90.0, 133.0, 180.0, 250.0, 301.0] {
+            let results = decode(&render(&symbol, 5.0, degrees));
+            assert_eq!(results.len(), 1, "{} degrees", degrees);
+            assert_eq!(results[0].data, url);
+            let difference = (results[0].orientation_degrees - degrees).rem_euclid(360.0);
+            assert!(difference.min(360.0 - difference) < 3.0, "{} degrees", degrees);
+        }
+        let flipped: Vec<Vec<bool>> =
+            symbol.iter().map(|row| row.iter().rev().copied().collect()).collect();
+        let results = decode(&render(&flipped, 5.0, 0.0));
+        assert_eq!(results.len(), 1);
+        assert!(results[0].mirrored);
+        assert_eq!(results[0].data, url);
+    }
+
+    #[test]
+    fn damaged_symbols_are_corrected() {
+        let text = b"damaged symbol test 12345";
+        let mut symbol = modules(text, 10);
+        for row in &mut symbol[10..14] {
+            for module in &mut row[10..14] {
+                *module = !*module;
+            }
+        }
+        let results = decode(&render(&symbol, 4.0, 0.0));
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].raw_bytes, text);
+        assert!(results[0].corrected_codewords > 0);
+        assert!(results[0].quality < 1.0);
+    }
+
+    #[test]
+    fn encodation_modes_decode() {
+        assert_eq!(decode_words(&[b'a' + 1, b'b' + 1, b'c' + 1]).bytes, b"abc");
+        assert_eq!(decode_words(&[130, 165, 229, 229, 129, 20]).bytes, b"00359999");
+        // C40 "AIM", unlatch, ASCII "x"
+        assert_eq!(decode_words(&[230, 91, 11, 254, b'x' + 1]).bytes, b"AIMx");
+        // Text "aim" and ANSI X12 "AIM"
+        assert_eq!(decode_words(&[239, 91, 11, 254]).bytes, b"aim");
+        assert_eq!(decode_words(&[238, 91, 11]).bytes, b"AIM");
+
+        // Base 256 with the 255-state randomizer, then ASCII "z"
+        let randomized = |value: u8, position: usize| {
+            ((value as usize + (149 * position) % 255 + 1) % 256) as u8
+        };
+        let mut codewords = vec![231, randomized(3, 2)];
+        codewords.extend([0xFF, 0x00, 0x80].iter().enumerate().map(|(i, &b)| randomized(b, 3 + i)));
+        codewords.push(b'z' + 1);
+        assert_eq!(decode_words(&codewords).bytes, [0xFF, 0x00, 0x80, b'z']);
+
+        // EDIFACT "ABCD" and unlatch (0x1F), six bits each
+        let edifact: u32 = [1, 2, 3, 4].iter().fold(0, |acc, &c| acc << 6 | c);
+        let mut codewords = vec![240];
+        codewords.extend(edifact.to_be_bytes()[1..].iter());
+        codewords.extend([0x7C, b'e' + 1, b'f' + 1]);
+        assert_eq!(decode_words(&codewords).bytes, b"ABCDef");
+
+        let gs1 = decode_words(&[232, 131, b'A' + 1, 232, 140]);
+        assert!(gs1.gs1);
+        assert_eq!(gs1.bytes, b"01A\x1d10");
+        // ECI 26 (UTF-8), then an upper shifted byte
+        let eci = decode_words(&[241, 27, 235, 0xE9 - 127]);
+        assert_eq!(eci.eci, Some(26));
+        assert_eq!(eci.bytes, [0xE9]);
+        assert_eq!(decode_words(&[236, b'1' + 1]).bytes, b"[)>\x1e05\x1d1\x1e\x04");
+    }
+}
@@ -0,0 +1,152 @@
+//! Document number formats of common issuing countries, behind
+//! `MRZResult.document_number_plausible`. A compensating OCR error can make a
+//! misread number pass its check digit; it rarely also keeps the format.
+
+/// Format of one country's document numbers for some document types
+struct NumberRule {
+    /// Issuing country code as it appears in the MRZ (`D` for Germany)
+    country: &'static str,
+    /// First letters of the document codes the rule covers (`P`, `I`, ...)
+    documents: &'static str,
+    /// Accepted shapes, one character class per position: `9` a digit,
+    /// `A` a letter, `*` either
+    patterns: &'static [&'static str],
+    /// Every character must also be one of these, when set
+    alphabet: Option<&'static str>,
+}
+
+const fn rule(
+    country: &'static str,
+    documents: &'static str,
+    patterns: &'static [&'static str],
+) -> NumberRule {
+    NumberRule { country, documents, patterns, alphabet: None }
+}
+
+const fn with_alphabet(rule: NumberRule, alphabet: &'static str) -> NumberRule {
+    NumberRule { alphabet: Some(alphabet), ..rule }
+}
+
+/// Letters and digits of German passport and ID card numbers: no vowels,
+/// and none of B, D, Q or S, which OCR confuses with digits
+const DEU_ALPHABET: &str = "0123456789CFGHJKLMNPRTVWXYZ";
+
+/// Dutch numbers never contain the letter O, to keep it apart from zero
+const NLD_ALPHABET: &str = "0123456789ABCDEFGHIJKLMNPQRSTUVWXYZ";
+
+const NUMBER_RULES: &[NumberRule] = &[
+    // Authority letter, then 8 more characters from the same alphabet
+    with_alphabet(rule("D", "PI", &["A********"]), DEU_ALPHABET),
+    // Passport card and book: 9 digits, or a letter and 8 digits since 2021
+    rule("USA", "P", &["999999999", "A99999999"]),
+    rule("GBR", "P", &["999999999"]),
+    rule("CAN", "P", &["AA999999"]),
+    // Passport: 2 digits, 2 letters, 5 digits; ID card (2021): 9 characters;
+    // CNI before 2021: issue YYMM, department (2A/2B in Corsica), 5 digits
+    rule("FRA", "P", &["99AA99999"]),
+    rule("FRA", "I", &["*********", "9999***99999"]),
+    // 2 letters, 6 letters or digits, a digit
+    with_alphabet(rule("NLD", "PI", &["AA******9"]), NLD_ALPHABET),
+];
+
+/// Whether `number` fits its country's format, or `None` when there is no
+/// rule for the country and document type
+pub(crate) fn plausible(country: &str, document_code: &str, number: &str) -> Option<bool> {
+    let kind = document_code.chars().next()?;
+    let rule = NUMBER_RULES
+        .iter()
+        .find(|rule| rule.country == country && rule.documents.contains(kind))?;
+
+    let fits = |pattern: &str| {
+        pattern.len() == number.len()
+            && pattern.chars().zip(number.chars()).all(|(class, c)| match class {
+                '9' => c.is_ascii_digit(),
+                'A' => c.is_ascii_uppercase(),
+                _ => c.is_ascii_digit() || c.is_ascii_uppercase(),
+            })
+    };
+    let in_alphabet = rule
+        .alphabet
+        .is_none_or(|alphabet| number.chars().all(|c| alphabet.contains(c)));
+    Some(in_alphabet && rule.patterns.iter().any(|pattern| fits(pattern)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mrz::{self, MrzFields, MrzOptions, MrzStatus, MrzWarningCode};
+
+    #[test]
+    fn numbers_are_checked_against_their_country_format() {
+        let cases = [
+            ("USA", "P", "123456789", Some(true)),
+            ("USA", "P", "A12345678", Some(true)),
+            ("USA", "P", "12345678", Some(false)),
+            ("USA", "P", "12A456789", Some(false)),
+            ("D", "P", "C01X00T47", Some(true)),
+            ("D", "I", "L01X00T47", Some(true)),
+            // B is not in the German alphabet, and the first is a letter
+            ("D", "P", "C01B00T47", Some(false)),
+            ("D", "P", "101X00T47", Some(false)),
+            ("GBR", "P", "925076473", Some(true)),
+            ("CAN", "P", "AB123456", Some(true)),
+            ("CAN", "P", "A1234567", Some(false)),
+            ("FRA", "P", "18AA12345", Some(true)),
+            ("FRA", "P", "18A012345", Some(false)),
+            ("FRA", "I", "X4RTBPFW4", Some(true)),
+            ("FRA", "I", "0506932020432A", Some(false)),
+            ("FRA", "I", "05062A020438", Some(true)),
+            ("NLD", "P", "SPECI2021", Some(true)),
+            ("NLD", "I", "SPOCI2021", Some(false)),
+        ];
+        for (country, code, number, expected) in cases {
+            assert_eq!(plausible(country, code, number), expected, "{} {}", country, number);
+        }
+    }
+
+    #[test]
+    fn unlisted_countries_and_documents_have_no_rule() {
+        assert_eq!(plausible("SWE", "P", "ANYTHING1"), None);
+        assert_eq!(plausible("USA", "I", "X"), None);
+        assert_eq!(plausible("GBR", "V", "925076473"), None);
+        assert_eq!(plausible("USA", "", "123456789"), None);
+    }
+
+    #[test]
+    fn implausible_numbers_make_the_mrz_suspect() {
+        let parse = |number: &str| {
+            let fields = MrzFields {
+                document_code: "P".to_string(),
+                issuing_country: "USA".to_string(),
+                surname: "DOE".to_string(),
+                given_names: "JANE".to_string(),
+                document_number: number.to_string(),
+                nationality: "USA".to_string(),
+                date_of_birth: "850310".to_string(),
+                sex: "F".to_string(),
+                date_of_expiry: "310802".to_string(),
+                optional_data: String::new(),
+                personal_number: String::new(),
+            };
+            let text = mrz::generate_mrz_lines(&fields, "TD3").unwrap().text;
+            let reference = mrz::CivilDate::new(2026, 1, 1).unwrap();
+            mrz::parse_with_options(&text, reference, &MrzOptions::default()).unwrap()
+        };
+
+        let result = parse("123456789");
+        assert_eq!(result.document_number_plausible, Some(true));
+        assert_eq!(result.status, MrzStatus::Valid);
+        assert_eq!(result.confidence, 1.0);
+
+        // Check digits pass, but a US passport has no letter in the middle
+        let result = parse("12A456789");
+        assert!(result.check_digits.valid);
+        assert_eq!(result.document_number_plausible, Some(false));
+        assert_eq!(result.status, MrzStatus::Suspect);
+        assert!(result.confidence < 1.0);
+        let warning = &result.warnings[0];
+        assert_eq!(warning.code, MrzWarningCode::ImplausibleDocumentNumber);
+        assert_eq!(warning.field, "document_number");
+        assert_eq!(warning.value, "12A456789");
+    }
+}
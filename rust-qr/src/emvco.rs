@@ -0,0 +1,275 @@
+//! EMVCo merchant-presented QR (MPM) payloads: VietQR, PromptPay, SGQR, ...
+//! Nested `ID(2) LENGTH(2) VALUE` records, checksummed by tag 63.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::VeloQrError;
+
+/// One TLV record; `tags` holds the parsed children of template records
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct EmvcoField {
+    pub name: Option<&'static str>,
+    pub value: String,
+    pub tags: Option<BTreeMap<String, EmvcoField>>,
+}
+
+/// A merchant account information template (tags 02-51)
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct EmvcoMerchantAccount {
+    pub tag: String,
+    /// Sub-tag 00 of the template (AID or reverse domain name); empty for
+    /// the primitive card-network tags 02-25
+    pub globally_unique_id: String,
+    pub value: String,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct EmvcoQr {
+    pub payload_format_indicator: String,
+    /// `static` (11) or `dynamic` (12); empty when tag 01 is absent
+    pub point_of_initiation: String,
+    pub merchant_accounts: Vec<EmvcoMerchantAccount>,
+    pub merchant_category_code: Option<String>,
+    pub transaction_currency: Option<String>, // ISO 4217 numeric
+    pub transaction_amount: Option<String>,
+    pub country_code: Option<String>,
+    pub merchant_name: Option<String>,
+    pub merchant_city: Option<String>,
+    pub postal_code: Option<String>,
+    pub crc: Option<String>,
+    /// CRC-16/CCITT over everything up to and including `6304` matched tag 63.
+    /// Reported rather than enforced because some issuers get it wrong.
+    pub crc_valid: bool,
+    pub tags: BTreeMap<String, EmvcoField>,
+}
+
+pub fn parse(data: &str) -> Result<EmvcoQr, VeloQrError> {
+    let chars: Vec<char> = data.trim_end().chars().collect();
+    let records = parse_tlv(&chars)?;
+    if records.first().map(|(tag, _)| tag.as_str()) != Some("00") {
+        return Err(VeloQrError::InvalidPayload(
+            "EMVCo payload must start with tag 00".to_string(),
+        ));
+    }
+
+    let mut tags = BTreeMap::new();
+    let mut merchant_accounts = Vec::new();
+    for (tag, value) in &records {
+        let number: u32 = tag.parse().unwrap_or(0);
+        let children = if is_template(number) {
+            Some(parse_children(value, number)?)
+        } else {
+            None
+        };
+        if (2..=51).contains(&number) {
+            let globally_unique_id = children
+                .as_ref()
+                .and_then(|c| c.get("00"))
+                .map(|f| f.value.clone())
+                .unwrap_or_default();
+            merchant_accounts.push(EmvcoMerchantAccount {
+                tag: tag.clone(),
+                globally_unique_id,
+                value: value.clone(),
+            });
+        }
+        tags.insert(
+            tag.clone(),
+            EmvcoField {
+                name: tag_name(number),
+                value: value.clone(),
+                tags: children,
+            },
+        );
+    }
+
+    let get = |tag: &str| tags.get(tag).map(|f: &EmvcoField| f.value.clone());
+    let crc = get("63");
+    let crc_valid = crc.as_deref().is_some_and(|crc| {
+        // The checksum covers the payload through the `6304` header of tag 63
+        let text: String = chars.iter().collect();
+        let covered = text.len() - crc.len();
+        crc.eq_ignore_ascii_case(&format!("{:04X}", crc16_ccitt(&text.as_bytes()[..covered])))
+    });
+
+    Ok(EmvcoQr {
+        payload_format_indicator: get("00").unwrap_or_default(),
+        point_of_initiation: match get("01").as_deref() {
+            Some("11") => "static".to_string(),
+            Some("12") => "dynamic".to_string(),
+            _ => String::new(),
+        },
+        merchant_accounts,
+        merchant_category_code: get("52"),
+        transaction_currency: get("53"),
+        transaction_amount: get("54"),
+        country_code: get("58"),
+        merchant_name: get("59"),
+        merchant_city: get("60"),
+        postal_code: get("61"),
+        crc_valid: crc_valid && records.last().is_some_and(|(tag, _)| tag == "63"),
+        crc,
+        tags,
+    })
+}
+
+/// Split `ID LENGTH VALUE` records; lengths count characters
+fn parse_tlv(chars: &[char]) -> Result<Vec<(String, String)>, VeloQrError> {
+    let mut records = Vec::new();
+    let mut pos = 0;
+    while pos < chars.len() {
+        let header: String = chars.get(pos..pos + 4).unwrap_or(&[]).iter().collect();
+        if header.len() != 4 || !header.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(VeloQrError::InvalidPayload(format!(
+                "malformed TLV header at position {}",
+                pos
+            )));
+        }
+        let length: usize = header[2..].parse().unwrap_or(0);
+        let value = chars.get(pos + 4..pos + 4 + length).ok_or_else(|| {
+            VeloQrError::InvalidPayload(format!(
+                "tag {} overruns the payload at position {}",
+                &header[..2],
+                pos
+            ))
+        })?;
+        records.push((header[..2].to_string(), value.iter().collect()));
+        pos += 4 + length;
+    }
+    Ok(records)
+}
+
+fn parse_children(value: &str, parent: u32) -> Result<BTreeMap<String, EmvcoField>, VeloQrError> {
+    let chars: Vec<char> = value.chars().collect();
+    Ok(parse_tlv(&chars)?
+        .into_iter()
+        .map(|(tag, value)| {
+            let name = sub_tag_name(parent, tag.parse().unwrap_or(0));
+            (tag, EmvcoField { name, value, tags: None })
+        })
+        .collect())
+}
+
+/// Tags whose value is itself a list of TLV records
+fn is_template(tag: u32) -> bool {
+    matches!(tag, 26..=51 | 62 | 64 | 80..=99)
+}
+
+fn tag_name(tag: u32) -> Option<&'static str> {
+    Some(match tag {
+        0 => "Payload Format Indicator",
+        1 => "Point of Initiation Method",
+        2..=51 => "Merchant Account Information",
+        52 => "Merchant Category Code",
+        53 => "Transaction Currency",
+        54 => "Transaction Amount",
+        55 => "Tip or Convenience Indicator",
+        56 => "Value of Convenience Fee Fixed",
+        57 => "Value of Convenience Fee Percentage",
+        58 => "Country Code",
+        59 => "Merchant Name",
+        60 => "Merchant City",
+        61 => "Postal Code",
+        62 => "Additional Data Field Template",
+        63 => "CRC",
+        64 => "Merchant Information - Language Template",
+        80..=99 => "Unreserved Template",
+        _ => return None,
+    })
+}
+
+fn sub_tag_name(parent: u32, tag: u32) -> Option<&'static str> {
+    Some(match (parent, tag) {
+        (26..=51 | 80..=99, 0) => "Globally Unique Identifier",
+        (62, 1) => "Bill Number",
+        (62, 2) => "Mobile Number",
+        (62, 3) => "Store Label",
+        (62, 4) => "Loyalty Number",
+        (62, 5) => "Reference Label",
+        (62, 6) => "Customer Label",
+        (62, 7) => "Terminal Label",
+        (62, 8) => "Purpose of Transaction",
+        (62, 9) => "Additional Consumer Data Request",
+        (62, 10) => "Merchant Tax ID",
+        (62, 11) => "Merchant Channel",
+        (62, 50..=99) => "Payment System Specific Template",
+        (64, 0) => "Language Preference",
+        (64, 1) => "Merchant Name - Alternate Language",
+        (64, 2) => "Merchant City - Alternate Language",
+        _ => return None,
+    })
+}
+
+/// CRC-16/CCITT-FALSE: polynomial 0x1021, initial value 0xFFFF
+fn crc16_ccitt(bytes: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for &byte in bytes {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A static Thai PromptPay payload to a mobile number
+    const PROMPTPAY: &str =
+        "00020101021129370016A000000677010111011300660000000005802TH530376463048956";
+
+    #[test]
+    fn crc_matches_the_ccitt_false_check_value() {
+        assert_eq!(crc16_ccitt(b"123456789"), 0x29B1);
+        assert_eq!(crc16_ccitt(b""), 0xFFFF);
+        assert_eq!(crc16_ccitt(&PROMPTPAY.as_bytes()[..PROMPTPAY.len() - 4]), 0x8956);
+    }
+
+    #[test]
+    fn reads_a_promptpay_payload() {
+        let qr = parse(PROMPTPAY).unwrap();
+        assert!(qr.crc_valid);
+        assert_eq!(qr.payload_format_indicator, "01");
+        assert_eq!(qr.point_of_initiation, "static");
+        assert_eq!(qr.country_code.as_deref(), Some("TH"));
+        assert_eq!(qr.transaction_currency.as_deref(), Some("764"));
+        assert_eq!(qr.merchant_accounts.len(), 1);
+        assert_eq!(qr.merchant_accounts[0].tag, "29");
+        assert_eq!(qr.merchant_accounts[0].globally_unique_id, "A000000677010111");
+        let account = qr.tags["29"].tags.as_ref().unwrap();
+        assert_eq!(account["00"].name, Some("Globally Unique Identifier"));
+        assert_eq!(account["01"].value, "0066000000000");
+
+        let tampered = PROMPTPAY.replace("5802TH", "5802VN");
+        assert!(!parse(&tampered).unwrap().crc_valid);
+    }
+
+    #[test]
+    fn lengths_count_characters() {
+        let data = "000201010212540510.0053037045912Cửa hàng ABC6005Hanoi62070503ABC";
+        let qr = parse(data).unwrap();
+        assert_eq!(qr.point_of_initiation, "dynamic");
+        assert_eq!(qr.transaction_amount.as_deref(), Some("10.00"));
+        assert_eq!(qr.merchant_name.as_deref(), Some("Cửa hàng ABC"));
+        assert_eq!(qr.merchant_city.as_deref(), Some("Hanoi"));
+        let additional = qr.tags["62"].tags.as_ref().unwrap();
+        assert_eq!(additional["05"].name, Some("Reference Label"));
+        assert_eq!(additional["05"].value, "ABC");
+        assert_eq!(qr.crc, None);
+        assert!(!qr.crc_valid);
+    }
+
+    #[test]
+    fn rejects_malformed_records() {
+        for bad in ["hello", "0002019999", "0102110002", "000201012"] {
+            assert_eq!(parse(bad).unwrap_err().code(), "INVALID_PAYLOAD", "{}", bad);
+        }
+    }
+}
@@ -0,0 +1,403 @@
+//! EPC069-12 "SEPA credit transfer" QR codes (the GiroCode of European
+//! banking apps): newline-separated elements after the `BCD` service tag.
+//! Violations of the guideline are listed rather than rejected, since
+//! generators in the wild bend it and banking apps still accept them.
+
+use serde::Serialize;
+
+use crate::VeloQrError;
+
+const SERVICE_TAG: &str = "BCD";
+
+/// The whole payload, in bytes of its character set
+const MAX_PAYLOAD_LENGTH: usize = 331;
+
+/// Largest amount allowed, in euro cents (999999999.99)
+const MAX_AMOUNT_CENTS: u64 = 99_999_999_999;
+
+/// One rule of the guideline the payload breaks
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct EpcViolation {
+    pub field: &'static str,
+    pub message: String,
+}
+
+/// Elements as printed, empty optional lines as `None`
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct EpcPayment {
+    pub version: String,       // 001 or 002
+    pub character_set: String, // 1-8
+    /// Name of `character_set`: UTF-8 or one of ISO 8859-1, 2, 4, 5, 7, 10, 15
+    pub encoding: Option<&'static str>,
+    pub identification: String, // SCT (or INST)
+    /// Mandatory in version 001 only
+    pub bic: Option<String>,
+    pub name: String,
+    pub iban: String,
+    pub iban_valid: bool, // ISO 13616 mod-97 checksum
+    pub currency: Option<String>,
+    /// Decimal as printed after the currency, e.g. `12.3`
+    pub amount: Option<String>,
+    pub amount_cents: Option<u64>,
+    pub purpose: Option<String>, // ISO 20022 purpose code, e.g. CHAR
+    /// Creditor reference (ISO 11649 `RF..` or a national one)
+    pub structured_reference: Option<String>,
+    pub unstructured_remittance: Option<String>,
+    /// Beneficiary to originator information
+    pub information: Option<String>,
+    pub violations: Vec<EpcViolation>,
+    pub valid: bool, // No violations
+}
+
+/// Parse an EPC QR payload, LF or CRLF separated, trailing empty elements
+/// optional. Only a payload that is not EPC at all (no `BCD` service tag,
+/// no IBAN line) is an error; everything else ends up in `violations`.
+pub fn parse(data: &str) -> Result<EpcPayment, VeloQrError> {
+    let lines: Vec<&str> = data
+        .trim_start_matches('\u{feff}')
+        .split('\n')
+        .map(|line| line.strip_suffix('\r').unwrap_or(line))
+        .collect();
+    if lines[0].trim() != SERVICE_TAG {
+        return Err(invalid("missing BCD service tag"));
+    }
+    if lines.len() < 7 {
+        return Err(invalid(&format!("{} lines, the IBAN is line 7", lines.len())));
+    }
+
+    let mut violations = Vec::new();
+    let mut violation = |field: &'static str, message: String| {
+        violations.push(EpcViolation { field, message });
+    };
+    let line = |index: usize| lines.get(index).map_or("", |line| line.trim());
+    let optional = |index: usize| Some(line(index).to_string()).filter(|value| !value.is_empty());
+
+    let version = line(1).to_string();
+    if version != "001" && version != "002" {
+        violation("version", format!("unknown version {:?}", version));
+    }
+    let character_set = line(2).to_string();
+    let encoding = match character_set.as_str() {
+        "1" => Some("UTF-8"),
+        "2" => Some("ISO-8859-1"),
+        "3" => Some("ISO-8859-2"),
+        "4" => Some("ISO-8859-4"),
+        "5" => Some("ISO-8859-5"),
+        "6" => Some("ISO-8859-7"),
+        "7" => Some("ISO-8859-10"),
+        "8" => Some("ISO-8859-15"),
+        _ => None,
+    };
+    if encoding.is_none() {
+        violation("character_set", format!("unknown character set {:?}", character_set));
+    }
+    // The limit counts bytes of the declared set, one per character but in UTF-8
+    let length = match encoding {
+        Some("UTF-8") => data.len(),
+        _ => data.chars().count(),
+    };
+    if length > MAX_PAYLOAD_LENGTH {
+        violation("payload", format!("{} bytes, at most {}", length, MAX_PAYLOAD_LENGTH));
+    }
+    let identification = line(3).to_string();
+    if identification != "SCT" && identification != "INST" {
+        violation("identification", format!("expected SCT, got {:?}", identification));
+    }
+
+    let bic = optional(4);
+    match &bic {
+        Some(bic) if !is_bic(bic) => violation("bic", format!("malformed BIC {:?}", bic)),
+        None if version == "001" => violation("bic", "mandatory in version 001".to_string()),
+        _ => {}
+    }
+    let name = line(5).to_string();
+    if name.is_empty() {
+        violation("name", "missing beneficiary name".to_string());
+    }
+    check_length(&mut violation, "name", &name, 70);
+
+    let iban = line(6).to_string();
+    let iban_valid = is_iban(&iban);
+    if !iban_valid {
+        violation("iban", format!("{:?} fails the IBAN checksum", iban));
+    }
+
+    let (currency, amount, amount_cents) = match optional(7) {
+        Some(text) => {
+            let split = text.char_indices().nth(3).map_or(text.len(), |(index, _)| index);
+            let (currency, amount) = text.split_at(split);
+            if currency != "EUR" {
+                violation("amount", format!("currency must be EUR, got {:?}", currency));
+            }
+            let cents = parse_cents(amount);
+            match cents {
+                None => violation("amount", format!("malformed amount {:?}", amount)),
+                Some(cents) if !(1..=MAX_AMOUNT_CENTS).contains(&cents) => {
+                    violation("amount", format!("{} outside 0.01 to 999999999.99", amount))
+                }
+                _ => {}
+            }
+            (Some(currency.to_string()), Some(amount.to_string()), cents)
+        }
+        None => (None, None, None),
+    };
+
+    let purpose = optional(8);
+    if let Some(purpose) = &purpose {
+        let code = purpose.len() == 4
+            && purpose.bytes().all(|b| b.is_ascii_uppercase() || b.is_ascii_digit());
+        if !code {
+            violation("purpose", format!("{:?} is not a 4-character purpose code", purpose));
+        }
+    }
+
+    let structured_reference = optional(9);
+    let unstructured_remittance = optional(10);
+    if let Some(reference) = &structured_reference {
+        check_length(&mut violation, "structured_reference", reference, 35);
+        if reference.starts_with("RF") && !is_creditor_reference(reference) {
+            violation(
+                "structured_reference",
+                format!("{:?} fails the ISO 11649 checksum", reference),
+            );
+        }
+        if unstructured_remittance.is_some() {
+            violation(
+                "unstructured_remittance",
+                "only one of structured and unstructured remittance allowed".to_string(),
+            );
+        }
+    }
+    if let Some(remittance) = &unstructured_remittance {
+        check_length(&mut violation, "unstructured_remittance", remittance, 140);
+    }
+    let information = optional(11);
+    if let Some(information) = &information {
+        check_length(&mut violation, "information", information, 70);
+    }
+    // Ignore the trailing newline of generators that end every element
+    let extra = lines.len() > 12 && lines[12..].iter().any(|line| !line.trim().is_empty());
+    if extra {
+        violation("payload", format!("{} lines, at most 12", lines.len()));
+    }
+
+    let valid = violations.is_empty();
+    Ok(EpcPayment {
+        version,
+        character_set,
+        encoding,
+        identification,
+        bic,
+        name,
+        iban,
+        iban_valid,
+        currency,
+        amount,
+        amount_cents,
+        purpose,
+        structured_reference,
+        unstructured_remittance,
+        information,
+        violations,
+        valid,
+    })
+}
+
+fn invalid(message: &str) -> VeloQrError {
+    VeloQrError::InvalidPayload(format!("EPC: {}", message))
+}
+
+fn check_length(
+    violation: &mut impl FnMut(&'static str, String),
+    field: &'static str,
+    value: &str,
+    max: usize,
+) {
+    let length = value.chars().count();
+    if length > max {
+        violation(field, format!("{} characters, at most {}", length, max));
+    }
+}
+
+/// `12`, `12.3` or `12.30` in cents; no sign, exponent or third decimal
+fn parse_cents(amount: &str) -> Option<u64> {
+    let (whole, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+    let digits = |text: &str| text.bytes().all(|b| b.is_ascii_digit());
+    if whole.is_empty() || whole.len() > 9 || fraction.len() > 2 || !digits(whole) {
+        return None;
+    }
+    if amount.contains('.') && fraction.is_empty() || !digits(fraction) {
+        return None;
+    }
+    let cents = format!("{:0<2}", fraction).parse::<u64>().ok()?;
+    Some(whole.parse::<u64>().ok()? * 100 + cents)
+}
+
+/// 4-letter bank, 2-letter country, 2 location and optionally 3 branch
+/// characters
+fn is_bic(bic: &str) -> bool {
+    let bytes = bic.as_bytes();
+    (bytes.len() == 8 || bytes.len() == 11)
+        && bytes[..6].iter().all(u8::is_ascii_uppercase)
+        && bytes[6..].iter().all(|b| b.is_ascii_uppercase() || b.is_ascii_digit())
+}
+
+/// Country code, two check digits and up to 30 alphanumerics, the
+/// rearranged number being 1 mod 97 (ISO 13616)
+fn is_iban(iban: &str) -> bool {
+    let bytes = iban.as_bytes();
+    (15..=34).contains(&bytes.len())
+        && bytes[..2].iter().all(u8::is_ascii_uppercase)
+        && bytes[2..4].iter().all(u8::is_ascii_digit)
+        && bytes[4..].iter().all(|b| b.is_ascii_uppercase() || b.is_ascii_digit())
+        && mod_97(&bytes[4..], &bytes[..4]) == Some(1)
+}
+
+/// `RF`, two check digits and up to 21 alphanumerics, checked like an IBAN
+fn is_creditor_reference(reference: &str) -> bool {
+    let bytes = reference.as_bytes();
+    (5..=25).contains(&bytes.len())
+        && bytes[2..4].iter().all(u8::is_ascii_digit)
+        && mod_97(&bytes[4..], &bytes[..4]) == Some(1)
+}
+
+/// `body` then `head`, letters counting as 10-35, mod 97; letters in either
+/// case, `None` for anything else
+fn mod_97(body: &[u8], head: &[u8]) -> Option<u32> {
+    let mut remainder = 0;
+    for &byte in body.iter().chain(head) {
+        let value = match byte {
+            b'0'..=b'9' => (byte - b'0') as u32,
+            b'A'..=b'Z' => (byte - b'A') as u32 + 10,
+            b'a'..=b'z' => (byte - b'a') as u32 + 10,
+            _ => return None,
+        };
+        let scale = if value < 10 { 10 } else { 100 };
+        remainder = (remainder * scale + value) % 97;
+    }
+    Some(remainder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IBAN: &str = "DE89370400440532013000";
+
+    /// Fields of the violations of a payload, in order
+    fn violations(data: &str) -> Vec<&'static str> {
+        parse(data).unwrap().violations.into_iter().map(|violation| violation.field).collect()
+    }
+
+    /// A valid version 002 payload without a BIC, then `rest` from line 8
+    fn payload(rest: &str) -> String {
+        format!("BCD\n002\n1\nSCT\n\nX\n{}\n{}", IBAN, rest)
+    }
+
+    #[test]
+    fn parses_the_guideline_example() {
+        let data = "BCD\n001\n1\nSCT\nBPOTBEB1\nRed Cross of Belgium\nBE72000000001616\n\
+                    EUR1\nCHAR\n\nUrgency fund\nSample EPC QR code";
+        let payment = parse(data).unwrap();
+        assert!(payment.valid);
+        assert!(payment.violations.is_empty());
+        assert_eq!((payment.version.as_str(), payment.encoding), ("001", Some("UTF-8")));
+        assert_eq!(payment.bic.as_deref(), Some("BPOTBEB1"));
+        assert_eq!(payment.name, "Red Cross of Belgium");
+        assert!(payment.iban_valid);
+        assert_eq!(payment.currency.as_deref(), Some("EUR"));
+        assert_eq!((payment.amount.as_deref(), payment.amount_cents), (Some("1"), Some(100)));
+        assert_eq!(payment.purpose.as_deref(), Some("CHAR"));
+        assert_eq!(payment.structured_reference, None);
+        assert_eq!(payment.unstructured_remittance.as_deref(), Some("Urgency fund"));
+        assert_eq!(payment.information.as_deref(), Some("Sample EPC QR code"));
+    }
+
+    #[test]
+    fn crlf_references_and_trailing_newlines_are_accepted() {
+        let data = "\u{feff}BCD\r\n002\r\n1\r\nSCT\r\n\r\nFranz Mustermänn\r\n\
+                    DE71110220330123456789\r\nEUR12.3\r\nGDDS\r\nRF18539007547034\r\n";
+        let payment = parse(data).unwrap();
+        assert!(payment.valid);
+        assert_eq!(payment.bic, None);
+        assert_eq!(payment.name, "Franz Mustermänn");
+        assert_eq!((payment.amount.as_deref(), payment.amount_cents), (Some("12.3"), Some(1230)));
+        assert_eq!(payment.structured_reference.as_deref(), Some("RF18539007547034"));
+
+        let payment = parse(&format!("BCD\n002\n2\nSCT\nGENODEF1ABC\nX\n{}\n", IBAN)).unwrap();
+        assert!(payment.valid);
+        assert_eq!((payment.encoding, payment.amount), (Some("ISO-8859-1"), None));
+        let euros = parse(&payload("EUR999999999.99")).unwrap().amount_cents;
+        assert_eq!(euros, Some(99_999_999_999));
+    }
+
+    #[test]
+    fn violations_are_listed_by_field() {
+        assert_eq!(violations(&format!("BCD\n001\n1\nSCT\n\nX\n{}", IBAN)), ["bic"]);
+        assert_eq!(violations("BCD\n002\n1\nSCT\n\nX\nDE89370400440532013001"), ["iban"]);
+        let data = "BCD\n003\n9\nSCX\nbad\n\nDE89370400440532013000\nUSD1.234\nchar\n\
+                    RF19539007547034\nalso";
+        let expected = [
+            "version",
+            "character_set",
+            "identification",
+            "bic",
+            "name",
+            "amount",
+            "amount",
+            "purpose",
+            "structured_reference",
+            "unstructured_remittance",
+        ];
+        assert_eq!(violations(data), expected);
+        let payment = parse(data).unwrap();
+        assert!(!payment.valid);
+        assert_eq!(payment.violations[0].message, "unknown version \"003\"");
+    }
+
+    #[test]
+    fn amounts_outside_the_guideline_are_violations() {
+        let amounts = ["EUR0", "EUR0.00", "EUR1000000000", "EUR-1", "EUR1.", "EUR.5", "EUR1,50"];
+        for amount in amounts.into_iter().chain(["EUR", "13.50"]) {
+            assert_eq!(violations(&payload(amount)), ["amount"], "{}", amount);
+        }
+        assert_eq!(parse_cents("0.5"), Some(50));
+        assert_eq!(parse_cents("7.05"), Some(705));
+        assert_eq!(parse_cents("1e3"), None);
+    }
+
+    #[test]
+    fn lengths_are_limited() {
+        let long = "x".repeat(141);
+        assert_eq!(violations(&payload(&format!("\n\n\n{}", long))), ["unstructured_remittance"]);
+        // Every element at its limit, which together overrun the payload
+        let data = format!(
+            "BCD\n002\n1\nSCT\nGENODEF1ABC\n{}\n{}\n\n\n\n{}\n{}",
+            "n".repeat(70),
+            IBAN,
+            "a".repeat(140),
+            "b".repeat(70)
+        );
+        assert_eq!(violations(&data), ["payload"]);
+        assert_eq!(violations(&payload("\n\n\n\n\nmore")), ["payload"]);
+    }
+
+    #[test]
+    fn iban_bic_and_creditor_reference_checks() {
+        assert!(is_iban("BE72000000001616"));
+        assert!(is_iban("GB82WEST12345698765432"));
+        assert!(!is_iban("GB82WEST12345698765433"));
+        assert!(!is_iban("GB82 WEST 1234 5698 7654 32"));
+        assert!(is_bic("DEUTDEFF") && is_bic("DEUTDEFF500"));
+        assert!(!is_bic("DEUTDEF") && !is_bic("DEU1DEFF"));
+        assert!(is_creditor_reference("RF18539007547034"));
+        assert!(!is_creditor_reference("RF19539007547034"));
+    }
+
+    #[test]
+    fn other_payloads_are_errors() {
+        assert!(parse("BCD\n002\n1\nSCT\n\nX").is_err());
+        assert!(parse("WIFI:S:x;;").is_err());
+        assert!(matches!(parse(""), Err(VeloQrError::InvalidPayload(_))));
+    }
+}
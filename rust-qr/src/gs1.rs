@@ -0,0 +1,392 @@
+//! GS1 element strings: `(01)09506000134352(17)260331(10)ABC123` or the raw
+//! form with FNC1 (`<GS>`, 0x1D) after variable-length values
+
+use serde::Serialize;
+
+use crate::mrz::{days_in_month, CivilDate};
+use crate::VeloQrError;
+
+/// Group separator, how FNC1 appears inside the decoded data
+const GS: char = '\u{1d}';
+
+/// One application identifier and its value
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Gs1Element {
+    pub ai: String,
+    pub title: &'static str,
+    pub value: String,
+    /// YYYY-MM-DD for date AIs (11, 12, 13, 15, 16, 17); day `00` means end of month
+    pub date: Option<String>,
+    /// Mod-10 check digit result for GTIN/SSCC/GLN-bearing AIs
+    pub check_digit_valid: Option<bool>,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Gs1Data {
+    pub elements: Vec<Gs1Element>,
+    pub gtin: Option<String>,
+    pub expiry: Option<String>, // AI 17 as YYYY-MM-DD
+    pub batch: Option<String>,
+    pub serial: Option<String>,
+    /// Every check digit present passed
+    pub valid: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Numeric,
+    Alphanumeric,
+    Date,
+}
+
+/// Application identifier definition. `ai` may end in `n` for the decimal
+/// point position digit of the 31xx-39xx measures.
+struct AiSpec {
+    ai: &'static str,
+    title: &'static str,
+    kind: Kind,
+    /// Data length when fixed, otherwise the maximum
+    length: usize,
+    fixed: bool,
+    /// Leading digits covered by a GS1 mod-10 check digit
+    check_digit_over: Option<usize>,
+}
+
+const fn fixed(ai: &'static str, title: &'static str, kind: Kind, length: usize) -> AiSpec {
+    AiSpec { ai, title, kind, length, fixed: true, check_digit_over: None }
+}
+
+const fn variable(ai: &'static str, title: &'static str, kind: Kind, max: usize) -> AiSpec {
+    AiSpec { ai, title, kind, length: max, fixed: false, check_digit_over: None }
+}
+
+const fn checked(spec: AiSpec, digits: usize) -> AiSpec {
+    AiSpec { check_digit_over: Some(digits), ..spec }
+}
+
+use Kind::{Alphanumeric as An, Date, Numeric as N};
+
+/// Commonly used AIs from the GS1 General Specifications
+const AI_TABLE: &[AiSpec] = &[
+    checked(fixed("00", "SSCC", N, 18), 18),
+    checked(fixed("01", "GTIN", N, 14), 14),
+    checked(fixed("02", "CONTENT", N, 14), 14),
+    variable("10", "BATCH/LOT", An, 20),
+    fixed("11", "PROD DATE", Date, 6),
+    fixed("12", "DUE DATE", Date, 6),
+    fixed("13", "PACK DATE", Date, 6),
+    fixed("15", "BEST BEFORE or BEST BY", Date, 6),
+    fixed("16", "SELL BY", Date, 6),
+    fixed("17", "USE BY OR EXPIRY", Date, 6),
+    fixed("20", "VARIANT", N, 2),
+    variable("21", "SERIAL", An, 20),
+    variable("22", "CPV", An, 20),
+    variable("235", "TPX", An, 28),
+    variable("240", "ADDITIONAL ID", An, 30),
+    variable("241", "CUST. PART No.", An, 30),
+    variable("242", "MTO VARIANT", N, 6),
+    variable("243", "PCN", An, 20),
+    variable("250", "SECONDARY SERIAL", An, 30),
+    variable("251", "REF. TO SOURCE", An, 30),
+    checked(variable("253", "GDTI", An, 30), 13),
+    variable("254", "GLN EXTENSION COMPONENT", An, 20),
+    checked(variable("255", "GCN", N, 25), 13),
+    variable("30", "VAR. COUNT", N, 8),
+    fixed("310n", "NET WEIGHT (kg)", N, 6),
+    fixed("311n", "LENGTH (m)", N, 6),
+    fixed("312n", "WIDTH (m)", N, 6),
+    fixed("313n", "HEIGHT (m)", N, 6),
+    fixed("314n", "AREA (m2)", N, 6),
+    fixed("315n", "NET VOLUME (l)", N, 6),
+    fixed("316n", "NET VOLUME (m3)", N, 6),
+    fixed("320n", "NET WEIGHT (lb)", N, 6),
+    fixed("330n", "GROSS WEIGHT (kg)", N, 6),
+    fixed("331n", "LENGTH (m), log", N, 6),
+    fixed("332n", "WIDTH (m), log", N, 6),
+    fixed("333n", "HEIGHT (m), log", N, 6),
+    fixed("334n", "AREA (m2), log", N, 6),
+    fixed("335n", "VOLUME (l), log", N, 6),
+    fixed("336n", "VOLUME (m3), log", N, 6),
+    variable("37", "COUNT", N, 8),
+    variable("390n", "AMOUNT", N, 15),
+    variable("391n", "AMOUNT", N, 18),
+    variable("392n", "PRICE", N, 15),
+    variable("393n", "PRICE", N, 18),
+    variable("400", "ORDER NUMBER", An, 30),
+    variable("401", "GINC", An, 30),
+    checked(fixed("402", "GSIN", N, 17), 17),
+    variable("403", "ROUTE", An, 30),
+    checked(fixed("410", "SHIP TO LOC", N, 13), 13),
+    checked(fixed("411", "BILL TO", N, 13), 13),
+    checked(fixed("412", "PURCHASE FROM", N, 13), 13),
+    checked(fixed("413", "SHIP FOR LOC", N, 13), 13),
+    checked(fixed("414", "LOC No.", N, 13), 13),
+    checked(fixed("415", "PAY TO", N, 13), 13),
+    checked(fixed("416", "PROD/SERV LOC", N, 13), 13),
+    checked(fixed("417", "PARTY", N, 13), 13),
+    variable("420", "SHIP TO POST", An, 20),
+    variable("421", "SHIP TO POST", An, 12),
+    fixed("422", "ORIGIN", N, 3),
+    variable("423", "COUNTRY - INITIAL PROCESS", N, 15),
+    fixed("424", "COUNTRY - PROCESS", N, 3),
+    variable("425", "COUNTRY - DISASSEMBLY", N, 15),
+    fixed("426", "COUNTRY - FULL PROCESS", N, 3),
+    fixed("7003", "EXPIRY TIME", N, 10),
+    checked(variable("8003", "GRAI", An, 30), 14),
+    variable("8004", "GIAI", An, 30),
+    fixed("8005", "PRICE PER UNIT", N, 6),
+    checked(fixed("8006", "ITIP", N, 18), 14),
+    variable("8007", "IBAN", An, 34),
+    variable("8008", "PROD TIME", N, 12),
+    variable("8010", "CPID", An, 30),
+    variable("8011", "CPID SERIAL", N, 12),
+    variable("8012", "VERSION", An, 20),
+    checked(fixed("8017", "GSRN - PROVIDER", N, 18), 18),
+    checked(fixed("8018", "GSRN - RECIPIENT", N, 18), 18),
+    variable("8020", "REF No.", An, 25),
+    variable("90", "INTERNAL", An, 30),
+    variable("91", "INTERNAL", An, 90),
+    variable("92", "INTERNAL", An, 90),
+    variable("93", "INTERNAL", An, 90),
+    variable("94", "INTERNAL", An, 90),
+    variable("95", "INTERNAL", An, 90),
+    variable("96", "INTERNAL", An, 90),
+    variable("97", "INTERNAL", An, 90),
+    variable("98", "INTERNAL", An, 90),
+    variable("99", "INTERNAL", An, 90),
+];
+
+/// Parse either the bracketed human-readable form or the raw FNC1-separated
+/// form. A leading `]Q3`/`]C1`/`]d2` symbology identifier or FNC1 is skipped.
+pub fn parse(data: &str, reference: CivilDate) -> Result<Gs1Data, VeloQrError> {
+    let data = data.trim();
+    let data = ["]Q3", "]C1", "]d2", "]e0"]
+        .iter()
+        .find_map(|prefix| data.strip_prefix(prefix))
+        .unwrap_or(data)
+        .trim_start_matches(GS);
+
+    let pairs = if data.starts_with('(') {
+        split_bracketed(data)?
+    } else {
+        split_raw(data)?
+    };
+    if pairs.is_empty() {
+        return Err(VeloQrError::InvalidPayload("no GS1 element strings".to_string()));
+    }
+
+    let mut elements = Vec::new();
+    for (spec, ai, value) in pairs {
+        validate_value(spec, &ai, &value)?;
+        let date = if spec.kind == Kind::Date {
+            Some(gs1_date(&value, reference).ok_or_else(|| {
+                VeloQrError::InvalidPayload(format!("AI {} has invalid date {}", ai, value))
+            })?)
+        } else {
+            None
+        };
+        let check_digit_valid = spec.check_digit_over.map(|digits| {
+            value
+                .get(..digits)
+                .is_some_and(|prefix| prefix.len() == digits && mod10_check_digit_valid(prefix))
+        });
+        elements.push(Gs1Element {
+            ai,
+            title: spec.title,
+            value,
+            date,
+            check_digit_valid,
+        });
+    }
+
+    let find = |ai: &str| elements.iter().find(|e| e.ai == ai);
+    Ok(Gs1Data {
+        gtin: find("01").map(|e| e.value.clone()),
+        expiry: find("17").and_then(|e| e.date.clone()),
+        batch: find("10").map(|e| e.value.clone()),
+        serial: find("21").map(|e| e.value.clone()),
+        valid: elements.iter().all(|e| e.check_digit_valid != Some(false)),
+        elements,
+    })
+}
+
+/// `(AI)value(AI)value...`
+fn split_bracketed(data: &str) -> Result<Vec<(&'static AiSpec, String, String)>, VeloQrError> {
+    let mut pairs = Vec::new();
+    let mut rest = data;
+    while let Some(after_open) = rest.strip_prefix('(') {
+        let close = after_open
+            .find(')')
+            .ok_or_else(|| VeloQrError::InvalidPayload("unterminated AI bracket".to_string()))?;
+        let ai = &after_open[..close];
+        let spec = lookup(ai)
+            .filter(|(_, length)| *length == ai.len())
+            .map(|(spec, _)| spec)
+            .ok_or_else(|| VeloQrError::InvalidPayload(format!("unknown AI ({})", ai)))?;
+        let value_and_rest = &after_open[close + 1..];
+        let end = value_and_rest.find('(').unwrap_or(value_and_rest.len());
+        let value = value_and_rest[..end].trim_end_matches(GS);
+        pairs.push((spec, ai.to_string(), value.to_string()));
+        rest = &value_and_rest[end..];
+    }
+    if !rest.is_empty() {
+        return Err(VeloQrError::InvalidPayload(format!("unexpected text {:?}", rest)));
+    }
+    Ok(pairs)
+}
+
+/// Concatenated element strings, variable-length values ended by FNC1
+fn split_raw(data: &str) -> Result<Vec<(&'static AiSpec, String, String)>, VeloQrError> {
+    let mut pairs = Vec::new();
+    let mut rest = data;
+    while !rest.is_empty() {
+        let (spec, ai_length) = lookup(rest).ok_or_else(|| {
+            VeloQrError::InvalidPayload(format!("unknown AI at {:?}", rest))
+        })?;
+        let ai = &rest[..ai_length];
+        let after_ai = &rest[ai_length..];
+        let end = if spec.fixed {
+            after_ai
+                .char_indices()
+                .nth(spec.length)
+                .map_or(after_ai.len(), |(index, _)| index)
+        } else {
+            after_ai.find(GS).unwrap_or(after_ai.len())
+        };
+        pairs.push((spec, ai.to_string(), after_ai[..end].to_string()));
+        rest = after_ai[end..].trim_start_matches(GS);
+    }
+    Ok(pairs)
+}
+
+/// Find the AI at the start of `text`, returning its spec and digit count
+fn lookup(text: &str) -> Option<(&'static AiSpec, usize)> {
+    AI_TABLE.iter().find_map(|spec| {
+        let ai = text.get(..spec.ai.len())?;
+        let matches = spec.ai.chars().zip(ai.chars()).all(|(pattern, c)| {
+            c.is_ascii_digit() && (pattern == 'n' || pattern == c)
+        });
+        matches.then_some((spec, spec.ai.len()))
+    })
+}
+
+fn validate_value(spec: &AiSpec, ai: &str, value: &str) -> Result<(), VeloQrError> {
+    let length = value.chars().count();
+    let length_ok = if spec.fixed {
+        length == spec.length
+    } else {
+        (1..=spec.length).contains(&length)
+    };
+    let chars_ok = match spec.kind {
+        Kind::Numeric | Kind::Date => value.bytes().all(|b| b.is_ascii_digit()),
+        // GS1 AI encodable character set 82: printable ASCII minus a few
+        Kind::Alphanumeric => value.chars().all(|c| c.is_ascii_graphic()),
+    };
+    if length_ok && chars_ok {
+        Ok(())
+    } else {
+        Err(VeloQrError::InvalidPayload(format!("AI {} has invalid value {:?}", ai, value)))
+    }
+}
+
+/// GS1 mod-10: weights 3,1 alternating from the rightmost data digit
+fn mod10_check_digit_valid(digits: &str) -> bool {
+    let values: Vec<u32> = digits.chars().filter_map(|c| c.to_digit(10)).collect();
+    let Some((&check, data)) = values.split_last() else {
+        return false;
+    };
+    if values.len() != digits.len() {
+        return false;
+    }
+    let sum: u32 = data
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &d)| if i % 2 == 0 { d * 3 } else { d })
+        .sum();
+    (10 - sum % 10) % 10 == check
+}
+
+/// YYMMDD with the GS1 sliding century window (-49/+50 years around the
+/// reference); day `00` stands for the last day of the month
+fn gs1_date(value: &str, reference: CivilDate) -> Option<String> {
+    let yy: i32 = value.get(0..2)?.parse().ok()?;
+    let month: u32 = value.get(2..4)?.parse().ok()?;
+    let day: u32 = value.get(4..6)?.parse().ok()?;
+
+    let century = reference.year - reference.year.rem_euclid(100);
+    let difference = yy - reference.year.rem_euclid(100);
+    let year = match difference {
+        51.. => century - 100 + yy,
+        ..=-50 => century + 100 + yy,
+        _ => century + yy,
+    };
+    if !(1..=12).contains(&month) {
+        return None;
+    }
+    let day = if day == 0 { days_in_month(year, month) } else { day };
+    CivilDate::new(year, month, day).map(CivilDate::to_iso)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reference() -> CivilDate {
+        CivilDate::new(2024, 6, 1).unwrap()
+    }
+
+    #[test]
+    fn bracketed_and_raw_forms_agree() {
+        let bracketed = parse("(01)09506000134352(17)260331(10)ABC123(21)S1", reference());
+        let raw = parse("]Q3\u{1d}01095060001343521726033110ABC123\u{1d}21S1", reference());
+        let data = bracketed.unwrap();
+        assert_eq!(raw.unwrap(), data);
+        assert!(data.valid);
+        assert_eq!(data.gtin.as_deref(), Some("09506000134352"));
+        assert_eq!(data.expiry.as_deref(), Some("2026-03-31"));
+        assert_eq!(data.batch.as_deref(), Some("ABC123"));
+        assert_eq!(data.serial.as_deref(), Some("S1"));
+        assert_eq!(data.elements[0].title, "GTIN");
+        assert_eq!(data.elements[0].check_digit_valid, Some(true));
+        assert_eq!(data.elements[2].check_digit_valid, None);
+    }
+
+    #[test]
+    fn check_digits_are_reported() {
+        assert!(mod10_check_digit_valid("09506000134352"));
+        assert!(mod10_check_digit_valid("4006381333931"));
+        assert!(!mod10_check_digit_valid("09506000134353"));
+        assert!(!mod10_check_digit_valid("0950600013435x"));
+        let data = parse("(01)09506000134353", reference()).unwrap();
+        assert!(!data.valid);
+        assert_eq!(data.elements[0].check_digit_valid, Some(false));
+    }
+
+    #[test]
+    fn dates_slide_around_the_reference_year() {
+        let expiry = |text: &str| parse(text, reference()).unwrap().expiry;
+        assert_eq!(expiry("(17)240200").as_deref(), Some("2024-02-29"));
+        assert_eq!(expiry("(17)740101").as_deref(), Some("2074-01-01"));
+        assert_eq!(expiry("(17)750101").as_deref(), Some("1975-01-01"));
+        let produced = parse("(11)760101", reference()).unwrap();
+        assert_eq!(produced.elements[0].date.as_deref(), Some("1976-01-01"));
+        assert_eq!(produced.expiry, None);
+    }
+
+    #[test]
+    fn measures_take_any_decimal_position() {
+        let data = parse("(3103)000750(3922)1999", reference()).unwrap();
+        assert_eq!(data.elements[0].ai, "3103");
+        assert_eq!(data.elements[0].value, "000750");
+        assert_eq!(data.elements[1].ai, "3922");
+    }
+
+    #[test]
+    fn rejects_invalid_element_strings() {
+        for bad in ["", "(17)241301", "(01)123", "(77)x", "(01", "(10)A B", "0109506(10)"] {
+            let error = parse(bad, reference()).unwrap_err();
+            assert_eq!(error.code(), "INVALID_PAYLOAD", "{}", bad);
+        }
+    }
+}
@@ -0,0 +1,890 @@
+//! 1D barcode detection and decoding: EAN-13, UPC-A and Code 128.
+//!
+//! Scanlines cross the image every 15 degrees, so symbols are read at any
+//! rotation, and each line becomes alternating light and dark runs.
+//! Symbols start at their guard or start pattern; characters are matched
+//! on the sums of neighbouring runs (edge to similar edge), which ink
+//! spread and blur leave unchanged. Reads of one value on at least two
+//! lines make a result, bounded by lines fitted through its end bars.
+
+use std::f64::consts::PI;
+
+use image::GrayImage;
+
+use crate::qr::{self, Line, QRCodeResult, Symbology};
+
+/// The symbologies read from one set of scanlines
+pub(crate) const SYMBOLOGIES: [Symbology; 3] =
+    [Symbology::Ean13, Symbology::UpcA, Symbology::Code128];
+
+/// Scanline directions over half a turn; every line is also read backwards
+pub(crate) const DIRECTIONS: usize = 12;
+
+/// Sampling step along a scanline, in pixels
+const STEP: f64 = 1.0;
+
+/// Samples on either side of a sample giving the gray range it is
+/// thresholded against, enough to reach past a four module bar at the
+/// smallest readable size
+const EDGE_REACH: usize = 8;
+
+/// Gray range below which a stretch of scanline shows no edge
+const MIN_CONTRAST: f64 = 40.0;
+
+/// Lines that must agree on a value before it is reported
+const MIN_READS: usize = 2;
+
+/// Light run required before the first and after the last bar, in modules.
+/// The standards ask for more; cropped photos rarely keep it.
+const MIN_QUIET_ZONE: f64 = 3.0;
+
+/// Mean distance per run, in modules, from the closest pattern
+const MAX_PATTERN_ERROR: f64 = 0.4;
+
+/// EAN/UPC digit widths in modules, light first (L-code). The right half
+/// uses the same widths dark first (R-code); G-codes are them reversed.
+const DIGITS: [u32; 10] = [3211, 2221, 2122, 1411, 1132, 1231, 1114, 1312, 1213, 3112];
+const DIGITS_G: [u32; 10] = [1123, 1222, 2212, 1141, 2311, 1321, 4111, 2131, 3121, 2113];
+
+/// G-coded digits of the left half, first digit in the high bit, indexed
+/// by the leading digit they encode
+const PARITY: [u8; 10] = [
+    0b000000, 0b001011, 0b001101, 0b001110, 0b010011, 0b011001, 0b011100, 0b010101, 0b010110,
+    0b011010,
+];
+
+/// Modules from the first to the last guard bar of EAN-13 and UPC-A
+const EAN_MODULES: i32 = 95;
+
+/// Code 128 symbol widths for values 0-106, dark first. Only the first six
+/// runs of the stop pattern (2331112) are listed; its final bar is checked
+/// on its own.
+const CODE128: [u32; 107] = [
+    212222, 222122, 222221, 121223, 121322, 131222, 122213, 122312, 132212, 221213,
+    221312, 231212, 112232, 122132, 122231, 113222, 123122, 123221, 223211, 221132,
+    221231, 213212, 223112, 312131, 311222, 321122, 321221, 312212, 322112, 322211,
+    212123, 212321, 232121, 111323, 131123, 131321, 112313, 132113, 132311, 211313,
+    231113, 231311, 112133, 112331, 132131, 113123, 113321, 133121, 313121, 211331,
+    231131, 213113, 213311, 213131, 311123, 311321, 331121, 312113, 312311, 332111,
+    314111, 221411, 431111, 111224, 111422, 121124, 121421, 141122, 141221, 112214,
+    112412, 122114, 122411, 142112, 142211, 241211, 221114, 413111, 241112, 134111,
+    111242, 121142, 121241, 114212, 124112, 124211, 411212, 421112, 421211, 212141,
+    214121, 412121, 111143, 111341, 131141, 114113, 114311, 411113, 411311, 113141,
+    114131, 311141, 411131, 211412, 211214, 211232, 233111,
+];
+
+const FNC1: usize = 102;
+const START_A: usize = 103;
+const START_C: usize = 105;
+const STOP: usize = 106;
+
+#[derive(Clone, Copy, PartialEq)]
+enum CodeSet {
+    A,
+    B,
+    C,
+}
+
+/// A symbol read along one scanline, `last_bar` being its final run
+struct Symbol {
+    symbology: Symbology,
+    bytes: Vec<u8>,
+    check_digit_valid: bool,
+    gs1: bool,
+    modules: i32,
+    last_bar: usize,
+}
+
+/// A symbol with the image positions of its outer bar edges
+struct Read {
+    symbol: Symbol,
+    start: (f64, f64),
+    end: (f64, f64),
+    direction: usize,
+}
+
+impl Read {
+    fn middle(&self) -> (f64, f64) {
+        ((self.start.0 + self.end.0) / 2.0, (self.start.1 + self.end.1) / 2.0)
+    }
+
+    fn length(&self) -> f64 {
+        (self.end.0 - self.start.0).hypot(self.end.1 - self.start.1)
+    }
+}
+
+/// Straight line through the image, clipped to it
+pub(crate) struct Scanline {
+    origin: (f64, f64),
+    pub(crate) direction: (f64, f64),
+    pub(crate) length: f64,
+}
+
+impl Scanline {
+    /// The line through `point` along the unit vector `direction`, clipped to
+    /// a `width` x `height` image; `None` when under 15 pixels remain
+    pub(crate) fn through(
+        point: (f64, f64),
+        direction: (f64, f64),
+        width: u32,
+        height: u32,
+    ) -> Option<Scanline> {
+        let (w, h) = (width as f64, height as f64);
+        let reach = 2.0 * w.hypot(h);
+        let start = (point.0 - reach * direction.0, point.1 - reach * direction.1);
+
+        // Clip to the image one axis at a time
+        let (mut low, mut high) = (0.0, 2.0 * reach);
+        for (position, step, size) in [(start.0, direction.0, w), (start.1, direction.1, h)] {
+            if step.abs() < 1e-9 {
+                if position < 0.0 || position >= size {
+                    high = low;
+                }
+                continue;
+            }
+            let (a, b) = ((0.0 - position) / step, (size - position) / step);
+            low = f64::max(low, a.min(b));
+            high = f64::min(high, a.max(b));
+        }
+        (high - low >= 15.0).then_some(Scanline {
+            origin: (start.0 + low * direction.0, start.1 + low * direction.1),
+            direction,
+            length: high - low,
+        })
+    }
+
+    pub(crate) fn point(&self, t: f64) -> (f64, f64) {
+        (self.origin.0 + t * self.direction.0, self.origin.1 + t * self.direction.1)
+    }
+
+    /// Positions where the color changes, with `0` and `length` at the
+    /// ends, so that runs alternate light and dark starting and ending with
+    /// a light run (empty when a bar touches the end). A sample is dark
+    /// below the middle of the gray range around it; where that range is
+    /// too flat to tell, inside a wide bar or a quiet zone, the color
+    /// carries on. Edges fall where the profile crosses the middle, between
+    /// samples, for bars under two pixels.
+    pub(crate) fn edges(&self, gray: &GrayImage) -> Vec<f64> {
+        let samples = (self.length / STEP) as usize + 1;
+        let profile: Vec<f64> =
+            (0..samples).map(|k| bilinear(gray, self.point(k as f64 * STEP))).collect();
+
+        let ranges = window_ranges(&profile, EDGE_REACH);
+
+        let mut edges = vec![0.0];
+        let mut dark = false;
+        for k in 0..samples {
+            let (low, high) = ranges[k];
+            let middle = (low + high) / 2.0;
+            if high - low < MIN_CONTRAST || (profile[k] < middle) == dark {
+                continue;
+            }
+            dark = !dark;
+            // Crossing between the previous sample and this one
+            let edge = match k {
+                0 => 0.0,
+                _ => {
+                    let (a, b) = (profile[k - 1], profile[k]);
+                    k as f64 - 1.0 + ((middle - a) / (b - a)).clamp(0.0, 1.0)
+                }
+            };
+            edges.push((edge * STEP).max(*edges.last().unwrap_or(&0.0)));
+        }
+        edges.push(self.length);
+        if dark {
+            edges.push(self.length);
+        }
+        edges
+    }
+}
+
+/// Lowest and highest of `values` within `reach` of each one, three
+/// comparisons apiece whatever the reach: running extremes from the
+/// start and from the end of each block of one window's length, where
+/// every window spans the end of one block and the start of the next.
+/// NaN padding, which `min` and `max` skip, clips the windows at the ends.
+fn window_ranges(values: &[f64], reach: usize) -> Vec<(f64, f64)> {
+    let size = 2 * reach + 1;
+    let padding = std::iter::repeat_n(f64::NAN, reach);
+    let padded: Vec<f64> =
+        padding.clone().chain(values.iter().copied()).chain(padding).collect();
+
+    let mut ahead = padded.iter().map(|&v| (v, v)).collect::<Vec<_>>();
+    let mut behind = ahead.clone();
+    for i in 1..padded.len() {
+        if i % size != 0 {
+            let (low, high) = ahead[i - 1];
+            ahead[i] = (low.min(padded[i]), high.max(padded[i]));
+        }
+    }
+    for i in (0..padded.len() - 1).rev() {
+        if (i + 1) % size != 0 {
+            let (low, high) = behind[i + 1];
+            behind[i] = (low.min(padded[i]), high.max(padded[i]));
+        }
+    }
+    (0..values.len())
+        .map(|i| {
+            let (a, b) = (behind[i], ahead[i + size - 1]);
+            (a.0.min(b.0), a.1.max(b.1))
+        })
+        .collect()
+}
+
+/// [`qr::sample`] without the border clamping away from the border, as
+/// every scanline sample near an edge goes through here
+fn bilinear(gray: &GrayImage, (x, y): (f64, f64)) -> f64 {
+    let (width, height) = (gray.width() as usize, gray.height() as usize);
+    let (x, y) = (x - 0.5, y - 0.5);
+    if x < 0.0 || y < 0.0 || x >= (width - 1) as f64 || y >= (height - 1) as f64 {
+        return qr::sample(gray, (x + 0.5, y + 0.5));
+    }
+    let (x0, y0) = (x as usize, y as usize);
+    let (fx, fy) = (x - x0 as f64, y - y0 as f64);
+    let raw = gray.as_raw();
+    let at = |i: usize| raw[i] as f64;
+    let i = y0 * width + x0;
+    let top = at(i) + (at(i + 1) - at(i)) * fx;
+    let bottom = at(i + width) + (at(i + width + 1) - at(i + width)) * fx;
+    top + (bottom - top) * fy
+}
+
+/// Find and decode the 1D symbols of `formats` in a grayscale image
+pub(crate) fn decode(gray: &GrayImage, formats: &[Symbology]) -> Vec<QRCodeResult> {
+    let wanted: Vec<Symbology> =
+        SYMBOLOGIES.into_iter().filter(|symbology| formats.contains(symbology)).collect();
+    let (width, height) = gray.dimensions();
+    if wanted.is_empty() || width.min(height) < 15 {
+        return Vec::new();
+    }
+
+    let spacing = (width.min(height) as f64 / 120.0).max(2.0);
+    let mut reads = Vec::new();
+    for direction in 0..DIRECTIONS {
+        let angle = PI * direction as f64 / DIRECTIONS as f64;
+        for line in scanlines(width, height, angle, spacing) {
+            reads.extend(read_line(gray, &line, direction, &wanted));
+        }
+    }
+    merge(reads, spacing)
+}
+
+/// Parallel lines at `angle` every `spacing` pixels, covering the image
+pub(crate) fn scanlines(width: u32, height: u32, angle: f64, spacing: f64) -> Vec<Scanline> {
+    let (w, h) = (width as f64, height as f64);
+    let direction = (angle.cos(), angle.sin());
+    let normal = (-direction.1, direction.0);
+    let radius = w.hypot(h) / 2.0;
+
+    let mut lines = Vec::new();
+    let mut offset = -radius;
+    while offset <= radius {
+        let middle = (w / 2.0 + offset * normal.0, h / 2.0 + offset * normal.1);
+        offset += spacing;
+        lines.extend(Scanline::through(middle, direction, width, height));
+    }
+    lines
+}
+
+/// Every symbol along a line, read forwards and backwards
+fn read_line(
+    gray: &GrayImage,
+    line: &Scanline,
+    direction: usize,
+    wanted: &[Symbology],
+) -> Vec<Read> {
+    let forward = line.edges(gray);
+    let mut reads = Vec::new();
+    for reversed in [false, true] {
+        let edges: Vec<f64> = if reversed {
+            forward.iter().rev().map(|t| line.length - t).collect()
+        } else {
+            forward.clone()
+        };
+        let point = |t: f64| line.point(if reversed { line.length - t } else { t });
+        let widths: Vec<f64> = edges.windows(2).map(|pair| pair[1] - pair[0]).collect();
+
+        // Dark runs are the odd ones
+        let mut bar = 1;
+        while bar + 1 < widths.len() {
+            let symbol = read_ean(&widths, bar, wanted).or_else(|| {
+                wanted
+                    .contains(&Symbology::Code128)
+                    .then(|| read_code128(&widths, bar))
+                    .flatten()
+            });
+            match symbol {
+                Some(symbol) => {
+                    bar = symbol.last_bar + 2;
+                    reads.push(Read {
+                        start: point(edges[symbol.last_bar + 1 - symbol_runs(&symbol)]),
+                        end: point(edges[symbol.last_bar + 1]),
+                        symbol,
+                        direction,
+                    });
+                }
+                None => bar += 2,
+            }
+        }
+    }
+    reads
+}
+
+/// Runs from the first to the last bar of a symbol
+fn symbol_runs(symbol: &Symbol) -> usize {
+    match symbol.symbology {
+        // Three guards of 3, 5 and 3 runs and twelve digits of 4
+        Symbology::Ean13 | Symbology::UpcA => 59,
+        // Six runs per symbol character, seven for the stop pattern
+        _ => 6 * (symbol.modules as usize - 13) / 11 + 7,
+    }
+}
+
+/// Closest of `patterns` (run widths as decimal digits) to `runs`, compared
+/// on the sums of neighbouring runs with the runs themselves as a tie break,
+/// which splits e.g. the EAN digits 1 and 7. `None` when even the closest
+/// is too far off.
+pub(crate) fn best_match(runs: &[f64], patterns: &[u32], modules: f64) -> Option<(usize, f64)> {
+    let unit = runs.iter().sum::<f64>() / modules;
+    if unit <= 0.0 {
+        return None;
+    }
+    let mut widths = [0.0; 8];
+    patterns
+        .iter()
+        .enumerate()
+        .map(|(index, &pattern)| {
+            let mut digits = pattern;
+            for width in widths[..runs.len()].iter_mut().rev() {
+                *width = (digits % 10) as f64;
+                digits /= 10;
+            }
+            let mut error = 0.0;
+            for k in 0..runs.len() {
+                error += (runs[k] / unit - widths[k]).abs() / 2.0;
+                if k + 1 < runs.len() {
+                    error += ((runs[k] + runs[k + 1]) / unit - widths[k] - widths[k + 1]).abs();
+                }
+            }
+            (index, error)
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .filter(|&(_, error)| error <= MAX_PATTERN_ERROR * runs.len() as f64)
+}
+
+/// Whether a character of `modules` keeps to the module width `unit` of the
+/// one before it, which perspective only changes slowly. Checked before
+/// matching, this ends reads running into texture early.
+pub(crate) fn steady(runs: &[f64], modules: f64, unit: &mut f64) -> bool {
+    let size = runs.iter().sum::<f64>() / modules;
+    let steady = size > 0.75 * *unit && size < *unit / 0.75;
+    *unit = size;
+    steady
+}
+
+/// Guard runs of one module each, `module` measured on nearby characters
+fn is_guard(runs: &[f64], module: f64) -> bool {
+    runs.iter().all(|&run| run > 0.3 * module && run < 2.0 * module)
+}
+
+/// EAN-13 starting at the guard bar `bar`, reported as UPC-A (its subset
+/// with a leading 0) when that is wanted
+fn read_ean(widths: &[f64], bar: usize, wanted: &[Symbology]) -> Option<Symbol> {
+    let upc = wanted.contains(&Symbology::UpcA);
+    if !upc && !wanted.contains(&Symbology::Ean13) {
+        return None;
+    }
+    // Quiet zone, start guard, six digits, middle guard, six digits, end
+    // guard and quiet zone
+    let runs = widths.get(bar - 1..bar + 60)?;
+    let module = runs[1..4].iter().sum::<f64>() / 3.0;
+    if runs[0] < MIN_QUIET_ZONE * module || !is_guard(&runs[1..4], module) {
+        return None;
+    }
+
+    let mut digits = Vec::with_capacity(13);
+    let mut parity = 0u8;
+    let mut unit = module;
+    for character in runs[4..28].chunks(4) {
+        if !steady(character, 7.0, &mut unit) {
+            return None;
+        }
+        let odd = best_match(character, &DIGITS, 7.0);
+        let even = best_match(character, &DIGITS_G, 7.0);
+        let (digit, g_coded) = match (odd, even) {
+            (Some(odd), Some(even)) if even.1 < odd.1 => (even.0, true),
+            (Some(odd), _) => (odd.0, false),
+            (None, Some(even)) => (even.0, true),
+            (None, None) => return None,
+        };
+        parity = parity << 1 | g_coded as u8;
+        digits.push(digit as u8);
+    }
+    if !is_guard(&runs[28..33], runs[4..28].iter().sum::<f64>() / 42.0) {
+        return None;
+    }
+    for character in runs[33..57].chunks(4) {
+        if !steady(character, 7.0, &mut unit) {
+            return None;
+        }
+        digits.push(best_match(character, &DIGITS, 7.0)?.0 as u8);
+    }
+    let module = runs[33..57].iter().sum::<f64>() / 42.0;
+    if !is_guard(&runs[57..60], module) || runs[60] < MIN_QUIET_ZONE * module {
+        return None;
+    }
+
+    let leading = PARITY.iter().position(|&p| p == parity)? as u8;
+    digits.insert(0, leading);
+    let check_digit_valid = ean_check_digit(&digits[..12]) == digits[12];
+    let (symbology, digits) = if leading == 0 && upc {
+        (Symbology::UpcA, &digits[1..])
+    } else if wanted.contains(&Symbology::Ean13) {
+        (Symbology::Ean13, &digits[..])
+    } else {
+        return None;
+    };
+    Some(Symbol {
+        symbology,
+        bytes: digits.iter().map(|d| b'0' + d).collect(),
+        check_digit_valid,
+        gs1: false,
+        modules: EAN_MODULES,
+        last_bar: bar + 58,
+    })
+}
+
+/// Weights 1 and 3 alternating from the left, the total rounded up to a
+/// multiple of ten
+fn ean_check_digit(digits: &[u8]) -> u8 {
+    let sum: u32 = digits
+        .iter()
+        .enumerate()
+        .map(|(i, &d)| d as u32 * if i % 2 == 0 { 1 } else { 3 })
+        .sum();
+    ((10 - sum % 10) % 10) as u8
+}
+
+/// Code 128 starting at the start character's first bar `bar`, up to the
+/// stop pattern
+fn read_code128(widths: &[f64], bar: usize) -> Option<Symbol> {
+    let start = widths.get(bar..bar + 6)?;
+    if widths[bar - 1] < MIN_QUIET_ZONE * start.iter().sum::<f64>() / 11.0 {
+        return None;
+    }
+    let (offset, _) = best_match(start, &CODE128[START_A..=START_C], 11.0)?;
+
+    let mut values = vec![START_A + offset];
+    let mut pos = bar + 6;
+    let mut unit = start.iter().sum::<f64>() / 11.0;
+    loop {
+        let character = widths.get(pos..pos + 6)?;
+        if !steady(character, 11.0, &mut unit) {
+            return None;
+        }
+        let (value, _) = best_match(character, &CODE128, 11.0)?;
+        match value {
+            STOP => break,
+            START_A.. => return None,
+            _ => values.push(value),
+        }
+        pos += 6;
+    }
+    let (final_bar, quiet) = (*widths.get(pos + 6)?, *widths.get(pos + 7)?);
+    if values.len() < 3 || !(unit..3.0 * unit).contains(&final_bar) {
+        return None;
+    }
+    if quiet < MIN_QUIET_ZONE * unit {
+        return None;
+    }
+
+    // The start character and the first data character both weigh 1
+    let (&check, characters) = values.split_last()?;
+    let sum: usize = characters.iter().enumerate().map(|(i, &v)| v * i.max(1)).sum();
+    let (bytes, gs1) = code128_bytes(characters)?;
+    Some(Symbol {
+        symbology: Symbology::Code128,
+        bytes,
+        check_digit_valid: sum % 103 == check,
+        gs1,
+        modules: 11 * values.len() as i32 + 13,
+        last_bar: pos + 6,
+    })
+}
+
+/// Bytes of the data characters after the start character. A leading FNC1
+/// marks GS1-128 and later ones become GS; FNC4 adds 128 to the next
+/// character, or to all of them between two pairs.
+fn code128_bytes(values: &[usize]) -> Option<(Vec<u8>, bool)> {
+    let mut set = match values[0] {
+        START_A => CodeSet::A,
+        START_C => CodeSet::C,
+        _ => CodeSet::B,
+    };
+    let (mut bytes, mut gs1) = (Vec::new(), false);
+    let (mut shifted, mut fnc4, mut extended) = (false, false, false);
+    for (i, &value) in values.iter().enumerate().skip(1) {
+        let current = match (shifted, set) {
+            (true, CodeSet::A) => CodeSet::B,
+            (true, _) => CodeSet::A,
+            (false, set) => set,
+        };
+        shifted = false;
+        match (current, value) {
+            (_, FNC1) if i == 1 => gs1 = true,
+            (_, FNC1) => bytes.push(0x1d),
+            (CodeSet::C, 0..=99) => bytes.extend(format!("{:02}", value).bytes()),
+            (CodeSet::C, 100) => set = CodeSet::B,
+            (CodeSet::C, 101) => set = CodeSet::A,
+            // FNC3 and FNC2 are instructions to the reader
+            (_, 96 | 97) => {}
+            (_, 98) => shifted = true,
+            (_, 99) => set = CodeSet::C,
+            (CodeSet::A, 100) => set = CodeSet::B,
+            (CodeSet::B, 101) => set = CodeSet::A,
+            (_, 100 | 101) => {
+                extended ^= fnc4;
+                fnc4 = !fnc4;
+            }
+            (_, 0..=95) => {
+                let byte = match current {
+                    CodeSet::A if value >= 64 => value - 64,
+                    _ => value + 32,
+                };
+                let high = if extended != fnc4 { 128 } else { 0 };
+                bytes.push((byte + high) as u8);
+                fnc4 = false;
+            }
+            _ => return None,
+        }
+    }
+    Some((bytes, gs1))
+}
+
+/// One result per value read on enough nearby lines. A value failing its
+/// check digit is dropped where a valid symbol was read.
+fn merge(reads: Vec<Read>, spacing: f64) -> Vec<QRCodeResult> {
+    let mut groups: Vec<Vec<Read>> = Vec::new();
+    for read in reads {
+        // Near any read of the group, as bars taller than the symbol is
+        // wide put its last lines further than a length from the first
+        let group = groups.iter_mut().find(|group| {
+            group[0].symbol.symbology == read.symbol.symbology
+                && group[0].symbol.bytes == read.symbol.bytes
+                && group.iter().any(|other| {
+                    let (a, b) = (other.middle(), read.middle());
+                    (a.0 - b.0).hypot(a.1 - b.1) < read.length()
+                })
+        });
+        match group {
+            Some(group) => group.push(read),
+            None => groups.push(vec![read]),
+        }
+    }
+
+    let results: Vec<QRCodeResult> = groups
+        .iter()
+        .filter(|group| group.len() >= MIN_READS)
+        .map(|group| result(group, spacing))
+        .collect();
+    let valid: Vec<&QRCodeResult> =
+        results.iter().filter(|result| result.check_digit_valid == Some(true)).collect();
+    results
+        .iter()
+        .filter(|result| {
+            result.check_digit_valid == Some(true)
+                || !valid.iter().any(|valid| qr::contains(&valid.bounds, result.center))
+        })
+        .cloned()
+        .collect()
+}
+
+/// Result from the reads of one symbol. Its sides are lines fitted through
+/// where the reads enter the first bar and leave the last, which scanlines
+/// at any angle share, ending at the outermost reads widened by half the
+/// line spacing. Reads too close together for a fit fall back to the
+/// outermost lines of the direction with the most reads.
+fn result(group: &[Read], spacing: f64) -> QRCodeResult {
+    let mut counts = [0usize; DIRECTIONS];
+    for read in group {
+        counts[read.direction] += 1;
+    }
+    let direction = (0..DIRECTIONS).max_by_key(|&d| counts[d]).unwrap_or(0);
+    let reads: Vec<&Read> = group.iter().filter(|read| read.direction == direction).collect();
+
+    // In the symbol's own frame x runs from the first bar to the last and
+    // y points down the bars
+    let first = reads[0];
+    let across = (
+        (first.end.0 - first.start.0) / first.length(),
+        (first.end.1 - first.start.1) / first.length(),
+    );
+    let down = (-across.1, across.0);
+    let half = spacing / 2.0;
+    let starts = group.iter().map(|read| read.start).collect();
+    let ends = group.iter().map(|read| read.end).collect();
+    let bounds = fitted_bounds(starts, ends, down, half).unwrap_or_else(|| {
+        let depth = |read: &&Read| read.middle().0 * down.0 + read.middle().1 * down.1;
+        let top = reads.iter().min_by(|a, b| depth(a).total_cmp(&depth(b))).unwrap_or(&first);
+        let bottom = reads.iter().max_by(|a, b| depth(a).total_cmp(&depth(b))).unwrap_or(&first);
+        let shift = |(x, y): (f64, f64), by: f64| (x + by * down.0, y + by * down.1);
+        vec![
+            shift(top.start, -half),
+            shift(top.end, -half),
+            shift(bottom.end, half),
+            shift(bottom.start, half),
+        ]
+    });
+
+    let symbol = &first.symbol;
+    let text = qr::payload_text(&symbol.bytes, Some(3), false);
+    let mut result = QRCodeResult {
+        symbology: symbol.symbology,
+        data: text.data,
+        version: symbol.modules,
+        bounds,
+        ecc_level: String::new(),
+        mask: 0,
+        raw_bytes: symbol.bytes.clone(),
+        inverted: false,
+        mirrored: false,
+        gs1: symbol.gs1,
+        strategy: String::new(),
+        orientation_degrees: 0.0,
+        center: (0.0, 0.0),
+        area: 0.0,
+        angle_degrees: 0.0,
+        module_size_px: 0.0,
+        structured_append: None,
+        encoding: text.encoding,
+        encoding_error: text.failed,
+        check_digit_valid: Some(symbol.check_digit_valid),
+        crop_png: Vec::new(),
+        modules: None,
+        track_id: None,
+        smoothed_bounds: Vec::new(),
+        corrected_codewords: 0,
+        quality: 1.0,
+        payload_hash: qr::hash_payload(&symbol.bytes),
+    };
+    result.update_geometry();
+    result
+}
+
+/// Corners on lines fitted through the first and last bar edges of the
+/// reads, both pointing `down` the bars, each running between its outermost
+/// points widened by `margin`. `None` when the points of a side are too
+/// close together to give its direction.
+pub(crate) fn fitted_bounds(
+    starts: Vec<(f64, f64)>,
+    ends: Vec<(f64, f64)>,
+    down: (f64, f64),
+    margin: f64,
+) -> Option<Vec<(f64, f64)>> {
+    let side = |points: Vec<(f64, f64)>| {
+        let mut line = Line::fit(&points)?;
+        if line.direction.0 * down.0 + line.direction.1 * down.1 < 0.0 {
+            line.direction = (-line.direction.0, -line.direction.1);
+        }
+        let along = |p: &(f64, f64)| {
+            (p.0 - line.point.0) * line.direction.0 + (p.1 - line.point.1) * line.direction.1
+        };
+        let low = points.iter().map(along).fold(f64::INFINITY, f64::min);
+        let high = points.iter().map(along).fold(f64::NEG_INFINITY, f64::max);
+        if high - low < 2.0 * margin {
+            return None;
+        }
+        let (point, direction) = (line.point, line.direction);
+        let at = |t: f64| (point.0 + t * direction.0, point.1 + t * direction.1);
+        Some((at(low - margin), at(high + margin)))
+    };
+    let (top_left, bottom_left) = side(starts)?;
+    let (top_right, bottom_right) = side(ends)?;
+    Some(vec![top_left, top_right, bottom_right, bottom_left])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    fn push_widths(modules: &mut Vec<bool>, pattern: u32, dark: bool) {
+        let mut dark = dark;
+        for width in pattern.to_string().bytes() {
+            modules.extend(std::iter::repeat_n(dark, (width - b'0') as usize));
+            dark = !dark;
+        }
+    }
+
+    /// Modules of an EAN-13 from its 13 digits, check digit included
+    fn ean_modules(digits: &str) -> Vec<bool> {
+        let digits: Vec<usize> = digits.bytes().map(|d| (d - b'0') as usize).collect();
+        let mut modules = Vec::new();
+        push_widths(&mut modules, 111, true);
+        for (i, &digit) in digits[1..7].iter().enumerate() {
+            let even = PARITY[digits[0]] >> (5 - i) & 1 == 1;
+            push_widths(&mut modules, if even { DIGITS_G[digit] } else { DIGITS[digit] }, false);
+        }
+        push_widths(&mut modules, 11111, false);
+        for &digit in &digits[7..] {
+            push_widths(&mut modules, DIGITS[digit], true);
+        }
+        push_widths(&mut modules, 111, true);
+        modules
+    }
+
+    /// Modules of a Code 128 symbol from its symbol values, start included;
+    /// the check character is computed unless `check` is given
+    fn code128_modules(values: &[usize], check: Option<usize>) -> Vec<bool> {
+        let sum: usize = values.iter().enumerate().map(|(i, &v)| v * i.max(1)).sum();
+        let mut modules = Vec::new();
+        for &value in values.iter().chain([check.unwrap_or(sum % 103), STOP].iter()) {
+            push_widths(&mut modules, CODE128[value], true);
+        }
+        // The stop pattern ends with a two-module bar
+        modules.extend([true, true]);
+        modules
+    }
+
+    fn code_b(text: &str) -> Vec<usize> {
+        std::iter::once(104).chain(text.bytes().map(|b| (b - 32) as usize)).collect()
+    }
+
+    /// Bars `module` pixels wide (fractions allowed) and 60 modules tall in a
+    /// 12-module quiet zone, turned by `degrees`, 4x4 supersampled
+    fn render(modules: &[bool], module: f64, degrees: f64) -> GrayImage {
+        let (length, height) = (modules.len() as f64, 60.0);
+        let side = ((length + 24.0).hypot(height + 24.0) * module).ceil() as u32;
+        let center = side as f64 / 2.0;
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        GrayImage::from_fn(side, side, |x, y| {
+            let mut dark = 0;
+            for sample in 0..16 {
+                let dx = x as f64 + (sample % 4) as f64 / 4.0 + 0.125 - center;
+                let dy = y as f64 + (sample / 4) as f64 / 4.0 + 0.125 - center;
+                let along = (cos * dx + sin * dy) / module + length / 2.0;
+                let across = (cos * dy - sin * dx) / module;
+                let inside = (0.0..length).contains(&along) && across.abs() < height / 2.0;
+                dark += (inside && modules[along as usize]) as u32;
+            }
+            Luma([(255 - dark * 255 / 16) as u8])
+        })
+    }
+
+    fn only(image: &GrayImage, symbology: Symbology) -> Vec<QRCodeResult> {
+        decode(image, &[symbology])
+    }
+
+    #[test]
+    fn reads_ean13_with_its_geometry() {
+        let results = only(&render(&ean_modules("4006381333931"), 2.0, 0.0), Symbology::Ean13);
+        assert_eq!(results.len(), 1);
+        let result = &results[0];
+        assert_eq!(result.symbology, Symbology::Ean13);
+        assert_eq!(result.data, "4006381333931");
+        assert_eq!(result.raw_bytes, b"4006381333931");
+        assert_eq!(result.check_digit_valid, Some(true));
+        assert_eq!(result.version, EAN_MODULES);
+        assert_eq!(result.quality, 1.0);
+        assert!((result.module_size_px - 2.0).abs() < 0.2, "{}", result.module_size_px);
+        assert!(result.angle_degrees.abs() < 1.0);
+        let width = result.bounds[1].0 - result.bounds[0].0;
+        assert!((width - 190.0).abs() < 3.0, "{:?}", result.bounds);
+    }
+
+    #[test]
+    fn upc_a_is_an_ean13_with_a_leading_zero() {
+        let image = render(&ean_modules("0036000291452"), 2.0, 0.0);
+        let upc = only(&image, Symbology::UpcA);
+        assert_eq!(upc.len(), 1);
+        assert_eq!((upc[0].symbology, upc[0].data.as_str()), (Symbology::UpcA, "036000291452"));
+        let ean = only(&image, Symbology::Ean13);
+        assert_eq!((ean[0].symbology, ean[0].data.as_str()), (Symbology::Ean13, "0036000291452"));
+        assert_eq!(decode(&image, &SYMBOLOGIES)[0].symbology, Symbology::UpcA);
+        assert!(only(&image, Symbology::Code128).is_empty());
+        let other = render(&ean_modules("4006381333931"), 2.0, 0.0);
+        assert!(only(&other, Symbology::UpcA).is_empty());
+    }
+
+    #[test]
+    fn wrong_check_digits_are_reported() {
+        assert_eq!(ean_check_digit(&[4, 0, 0, 6, 3, 8, 1, 3, 3, 3, 9, 3]), 1);
+        assert_eq!(ean_check_digit(&[0, 0, 3, 6, 0, 0, 0, 2, 9, 1, 4, 5]), 2);
+        let results = only(&render(&ean_modules("4006381333932"), 2.0, 0.0), Symbology::Ean13);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].data, "4006381333932");
+        assert_eq!(results[0].check_digit_valid, Some(false));
+
+        let symbol = render(&code128_modules(&code_b("AB"), Some(5)), 2.0, 0.0);
+        let results = only(&symbol, Symbology::Code128);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].data, "AB");
+        assert_eq!(results[0].check_digit_valid, Some(false));
+    }
+
+    #[test]
+    fn code128_switches_code_sets() {
+        let value = |c: u8| (c - 32) as usize;
+        let cases: [(Vec<usize>, &[u8]); 5] = [
+            (code_b("Hello-128"), b"Hello-128"),
+            (vec![START_C, 12, 34, 56, 78, 90], b"1234567890"),
+            // Code C, then code B for one letter
+            (vec![START_C, 12, 34, 100, value(b'X')], b"1234X"),
+            // Code A control character, then a shift to code B
+            (vec![START_A, value(b'A'), 73, 98, value(b'a'), value(b'B')], b"A\taB"),
+            // FNC4 in code B adds 128
+            (vec![104, value(b'n'), 100, value(b'i'), value(b'!')], b"n\xe9!"),
+        ];
+        for (values, expected) in &cases {
+            let modules = code128_modules(values, None);
+            let results = only(&render(&modules, 2.0, 0.0), Symbology::Code128);
+            assert_eq!(results.len(), 1, "{:?}", values);
+            assert_eq!(results[0].raw_bytes, *expected);
+            assert_eq!(results[0].check_digit_valid, Some(true));
+            assert_eq!(results[0].version as usize, modules.len());
+            assert!(!results[0].gs1);
+        }
+        // Code 128 text is ISO-8859-1
+        let modules = code128_modules(&cases[4].0, None);
+        let results = only(&render(&modules, 2.0, 0.0), Symbology::Code128);
+        let text = (results[0].data.as_str(), results[0].encoding.as_str());
+        assert_eq!(text, ("né!", "ISO-8859-1"));
+    }
+
+    #[test]
+    fn gs1_128_separates_fields() {
+        let values = [START_C, FNC1, 1, 9, 50, 60, 0, 13, 43, 52, 10, 100, 33, 34, FNC1, 18, 17];
+        let results = only(&render(&code128_modules(&values, None), 2.0, 0.0), Symbology::Code128);
+        assert_eq!(results.len(), 1);
+        assert!(results[0].gs1);
+        assert_eq!(results[0].raw_bytes, b"010950600013435210AB\x1d21");
+    }
+
+    #[test]
+    fn rotated_symbols_decode() {
+        let ean = ean_modules("5901234123457");
+        let code = code128_modules(&code_b("ROTATE-me 42"), None);
+        for degrees in [0.0, 19.0, 90.0, 154.0, 199.0, 274.0, 334.0] {
+            let results = only(&render(&ean, 2.5, degrees), Symbology::Ean13);
+            assert_eq!(results.len(), 1, "{} degrees", degrees);
+            assert_eq!(results[0].data, "5901234123457");
+            let turn = (results[0].angle_degrees - degrees).rem_euclid(360.0);
+            let read = results[0].angle_degrees;
+            assert!(turn.min(360.0 - turn) < 3.0, "{} read as {}", degrees, read);
+            let results = only(&render(&code, 2.5, degrees), Symbology::Code128);
+            assert_eq!(results.len(), 1, "{} degrees", degrees);
+            assert_eq!(results[0].data, "ROTATE-me 42");
+        }
+    }
+
+    #[test]
+    fn stripes_and_noise_are_not_barcodes() {
+        let stripes =
+            GrayImage::from_fn(400, 300, |x, _| Luma([if (x / 3) % 2 == 0 { 0 } else { 255 }]));
+        assert!(decode(&stripes, &SYMBOLOGIES).is_empty());
+        let noise = GrayImage::from_fn(400, 300, |x, y| {
+            Luma([if (x * 7 + y * 13 + x * y) % 5 < 2 { 0 } else { 255 }])
+        });
+        assert!(decode(&noise, &SYMBOLOGIES).is_empty());
+    }
+}
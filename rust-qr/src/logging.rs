@@ -0,0 +1,110 @@
+//! Runtime-configurable logging
+//!
+//! Events carry a level, the emitting module and a message. The level is
+//! checked before anything is formatted, so disabled logging costs one atomic
+//! load. With the `wasm` feature events go to the callback registered through
+//! `set_log_callback`, or to the console in debug builds.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Log verbosity, from silent to everything
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+// Only the JS bindings can change the level or consume events
+#[cfg_attr(not(all(feature = "wasm", target_arch = "wasm32")), allow(dead_code))]
+impl LogLevel {
+    /// `off`, `error`, `warn`, `info` or `debug`
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "off" => Some(LogLevel::Off),
+            "error" => Some(LogLevel::Error),
+            "warn" => Some(LogLevel::Warn),
+            "info" => Some(LogLevel::Info),
+            "debug" => Some(LogLevel::Debug),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LogLevel::Off => "off",
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+        }
+    }
+}
+
+// Debug builds log everything by default, release builds nothing
+const DEFAULT_LEVEL: LogLevel = if cfg!(debug_assertions) {
+    LogLevel::Debug
+} else {
+    LogLevel::Off
+};
+
+static LEVEL: AtomicU8 = AtomicU8::new(DEFAULT_LEVEL as u8);
+
+/// Emit events at `level` and below from now on
+#[cfg_attr(not(all(feature = "wasm", target_arch = "wasm32")), allow(dead_code))]
+pub fn set_log_level(level: LogLevel) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Whether an event at `level` would be emitted
+#[inline]
+pub(crate) fn enabled(level: LogLevel) -> bool {
+    level != LogLevel::Off && level as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+/// Format and deliver one event; callers check `enabled` first
+#[cold]
+pub(crate) fn emit(level: LogLevel, module: &str, args: fmt::Arguments) {
+    let module = module.strip_prefix("veloqr::").unwrap_or(module);
+
+    #[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+    crate::wasm::forward_log(level, module, &args.to_string());
+
+    // No sink outside the JS bindings
+    #[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
+    let _ = (level, module, args);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEVELS: [LogLevel; 5] =
+        [LogLevel::Off, LogLevel::Error, LogLevel::Warn, LogLevel::Info, LogLevel::Debug];
+
+    #[test]
+    fn level_names_round_trip() {
+        for level in LEVELS {
+            assert_eq!(LogLevel::parse(level.name()), Some(level));
+        }
+        for name in ["WARN", "trace", "warning", ""] {
+            assert_eq!(LogLevel::parse(name), None, "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn a_level_enables_itself_and_everything_more_severe() {
+        set_log_level(LogLevel::Warn);
+        let on: Vec<LogLevel> = LEVELS.into_iter().filter(|&level| enabled(level)).collect();
+        assert_eq!(on, [LogLevel::Error, LogLevel::Warn]);
+        set_log_level(LogLevel::Off);
+        assert!(LEVELS.iter().all(|&level| !enabled(level)));
+        // Events are never at `Off`, even when everything is logged
+        set_log_level(LogLevel::Debug);
+        assert!(!enabled(LogLevel::Off) && enabled(LogLevel::Debug));
+        set_log_level(DEFAULT_LEVEL);
+    }
+}
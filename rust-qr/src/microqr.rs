@@ -0,0 +1,852 @@
+//! Micro QR (M1-M4) detection and decoding.
+//!
+//! Candidates are single finder patterns: a dark 3x3 core inside light and
+//! dark rings, 1:1:3:1:1 along the row and checked on the column and the
+//! diagonals. Lines fitted to the ring edges give a homography in module
+//! units, which the timing patterns along the top and the left side of the
+//! symbol then extend over the whole symbol. The format information names
+//! the symbol and its mask; the one Reed-Solomon block is corrected over
+//! the QR field and decoded from numeric, alphanumeric, byte and Kanji
+//! segments.
+
+use image::GrayImage;
+
+use crate::qr::{self, transform, Homography, PointPair, QRCodeResult, Symbology};
+use crate::reed_solomon::{self, GaloisField};
+
+/// QR field, x^8 + x^4 + x^3 + x^2 + 1
+const PRIMITIVE: u32 = 0x11D;
+
+/// Smallest finder module, in pixels
+const MIN_MODULE_PX: f64 = 1.5;
+
+/// Longest to shortest finder span through the center; sqrt(2) for a
+/// square, with room for pixel rounding
+const MAX_SPAN_RATIO: f64 = 1.7;
+
+/// Fraction of the finder modules, with the light ring around them, that
+/// must show their color in the binarized image through the first frame
+/// and once the frame is fitted to the ring edges
+const MIN_FRAME_MATCH: f64 = 0.7;
+const MIN_FINDER_MATCH: f64 = 0.9;
+
+/// Ring edges of the finder: radius in modules from the center and whether
+/// it gets lighter going out
+const FINDER_EDGES: [(f64, bool); 3] = [(1.5, true), (2.5, false), (3.5, true)];
+
+/// Fraction of the light/dark contrast a ring edge must show
+const MIN_EDGE_CONTRAST: f64 = 0.1;
+
+/// Separator and first timing modules (of 21) that must match, and the
+/// share of the whole timing pattern once the symbol size is known
+const MIN_ORIENTATION_MATCH: usize = 18;
+const MIN_TIMING_MATCH: f64 = 0.8;
+
+/// Format information: BCH(15, 5) generator and the mask applied to it
+const FORMAT_GENERATOR: u32 = 0x537;
+const FORMAT_MASK: u32 = 0x4445;
+
+/// Bit errors tolerated in the format information (the code corrects 3)
+const MAX_FORMAT_ERRORS: u32 = 3;
+
+/// One entry per symbol number of the format information
+struct SymbolType {
+    version: usize,
+    ecc_level: &'static str,
+    data_bits: usize,
+    check_words: usize,
+    /// Correctable codewords; the rest of the check words guard against
+    /// misdecodes, and M1 only detects errors
+    capacity: usize,
+}
+
+const fn symbol(
+    version: usize,
+    ecc_level: &'static str,
+    data_bits: usize,
+    check_words: usize,
+    capacity: usize,
+) -> SymbolType {
+    SymbolType { version, ecc_level, data_bits, check_words, capacity }
+}
+
+const SYMBOL_TYPES: [SymbolType; 8] = [
+    symbol(1, "", 20, 2, 0),
+    symbol(2, "L", 40, 5, 1),
+    symbol(2, "M", 32, 6, 2),
+    symbol(3, "L", 84, 6, 2),
+    symbol(3, "M", 68, 8, 4),
+    symbol(4, "L", 128, 8, 3),
+    symbol(4, "M", 112, 10, 5),
+    symbol(4, "Q", 80, 14, 7),
+];
+
+/// Side length in modules for a version: `-1` to `-4` for M1 to M4
+pub(crate) fn dimensions(version: i32) -> Option<u32> {
+    match version {
+        -4..=-1 => Some(9 + 2 * version.unsigned_abs()),
+        _ => None,
+    }
+}
+
+/// Find and decode every Micro QR symbol in a grayscale image
+pub(crate) fn decode(gray: &GrayImage) -> Vec<QRCodeResult> {
+    let (width, height) = gray.dimensions();
+    if width.min(height) < 15 {
+        return Vec::new();
+    }
+
+    let mut binary = gray.clone();
+    // The window must reach past the 3x3 finder core, which takes a fifth of
+    // an image holding little more than an M1 symbol
+    let window = (width.min(height) / 4).max(15);
+    qr::local_threshold(&mut binary, window, |mean, _| mean - qr::ADAPTIVE_OFFSET);
+
+    let mut results: Vec<QRCodeResult> = Vec::new();
+    for finder in find_finders(&binary) {
+        if results.iter().any(|r| qr::contains(&r.bounds, finder.center)) {
+            continue;
+        }
+        if let Some(result) = decode_candidate(gray, &binary, &finder) {
+            results.push(result);
+        }
+    }
+
+    log_debug!("Decoded {} Micro QR symbols", results.len());
+    results
+}
+
+// ==================== Detection ====================
+
+struct Finder {
+    center: (f64, f64),
+    module: f64,
+}
+
+/// Widths of the finder runs through its center, in modules
+const FINDER_RUNS: [f64; 5] = [1.0, 1.0, 3.0, 1.0, 1.0];
+
+/// Whether runs match the finder widths within half a module each
+fn finder_runs(lengths: [f64; 5], module: f64) -> bool {
+    lengths
+        .iter()
+        .zip(FINDER_RUNS)
+        .all(|(&length, width)| (length - width * module).abs() <= module * 0.5 + 0.5)
+}
+
+/// Dark, light, dark, light, dark runs of widths 1:1:3:1:1 with light on
+/// either side, starting at the dark run `first`. Returns the middle of the
+/// core run and the module width.
+fn finder_pattern(runs: &[(usize, usize)], first: usize) -> Option<(f64, f64)> {
+    if first == 0 || first + 5 >= runs.len() {
+        return None;
+    }
+    let window = &runs[first..first + 5];
+    let module = window.iter().map(|r| r.1).sum::<usize>() as f64 / 7.0;
+    let lengths = [0, 1, 2, 3, 4].map(|i| window[i].1 as f64);
+    let bounded = [runs[first - 1].1, runs[first + 5].1]
+        .iter()
+        .all(|&length| length as f64 >= module * 0.5);
+    (finder_runs(lengths, module) && bounded && module >= MIN_MODULE_PX)
+        .then(|| (window[2].0 as f64 + window[2].1 as f64 / 2.0, module))
+}
+
+/// Finder centers found on a row and confirmed on the column and the
+/// diagonals through them
+fn find_finders(binary: &GrayImage) -> Vec<Finder> {
+    let (width, height) = (binary.width() as usize, binary.height() as usize);
+    let raw = binary.as_raw();
+    let mut found: Vec<Finder> = Vec::new();
+    let mut row = Vec::new();
+    for y in 0..height {
+        let first_dark = qr::runs(raw[y * width..(y + 1) * width].iter().copied(), &mut row);
+        // Dark runs are the odd ones when the row starts light
+        let start = if first_dark { 0 } else { 1 };
+        for first in (start..row.len()).step_by(2) {
+            let Some((cx, module)) = finder_pattern(&row, first) else {
+                continue;
+            };
+            // Rows through the core repeat the same pattern
+            let seen = |f: &Finder| {
+                (f.center.0 - cx).abs() < 2.0 * f.module
+                    && (f.center.1 - y as f64).abs() < 2.0 * f.module
+            };
+            let recent = found
+                .iter()
+                .rev()
+                .take_while(|f| f.center.1 > y as f64 - 4.0 * f.module - 1.0);
+            if recent.clone().any(seen) {
+                continue;
+            }
+            if let Some(finder) = confirm_finder(binary, (cx, y), module) {
+                found.push(finder);
+            }
+        }
+    }
+    found
+}
+
+/// Module width along a line through the core, given the steps to the
+/// ring edges both ways, when the runs keep the finder widths
+fn even_runs(forward: [i64; 3], backward: [i64; 3]) -> Option<f64> {
+    let lengths = [
+        forward[2] - forward[1],
+        forward[1] - forward[0],
+        forward[0] + backward[0] - 1,
+        backward[1] - backward[0],
+        backward[2] - backward[1],
+    ];
+    let module = (forward[2] + backward[2] - 1) as f64 / 7.0;
+    finder_runs(lengths.map(|length| length as f64), module).then_some(module)
+}
+
+/// The row pattern checked down the column and along both diagonals, which
+/// stripes of any angle fail in one direction or another
+fn confirm_finder(binary: &GrayImage, (cx, y): (f64, usize), module: f64) -> Option<Finder> {
+    let x = cx as i64;
+    let limit = (module * 6.0) as i64 + 2;
+    let up = qr::color_steps(binary, (x, y as i64), (0, -1), limit)?;
+    let down = qr::color_steps(binary, (x, y as i64), (0, 1), limit)?;
+    let vertical = even_runs(down, up)?;
+    let center = (cx, y as f64 + (down[0] - up[0] + 1) as f64 / 2.0);
+
+    let (mut shortest, mut longest) = (module.min(vertical), module.max(vertical));
+    for (dx, dy) in [(1, 1), (1, -1)] {
+        if longest > shortest * MAX_SPAN_RATIO {
+            return None;
+        }
+        let start = (x, center.1 as i64);
+        let forward = qr::color_steps(binary, start, (dx, dy), limit)?;
+        let backward = qr::color_steps(binary, start, (-dx, -dy), limit)?;
+        let diagonal = even_runs(forward, backward)? * std::f64::consts::SQRT_2;
+        shortest = shortest.min(diagonal);
+        longest = longest.max(diagonal);
+    }
+    (longest <= shortest * MAX_SPAN_RATIO).then_some(Finder {
+        center,
+        module: (module + vertical) / 2.0,
+    })
+}
+
+/// The finder modules and the light ring around them, centered on the
+/// core, with their colors
+fn finder_cells() -> impl Iterator<Item = ((i32, i32), bool)> {
+    (-4..=4).flat_map(|y: i32| {
+        (-4..=4).map(move |x: i32| {
+            let ring = x.abs().max(y.abs());
+            ((x, y), ring != 2 && ring != 4)
+        })
+    })
+}
+
+/// Whether the finder shows its rings in the binarized image, a cheap check
+/// before the slower steps
+fn finder_matches(binary: &GrayImage, map: &Homography, min_match: f64) -> bool {
+    let (total, matching) = finder_cells().fold((0, 0), |(total, matching), ((x, y), dark)| {
+        let hit = qr::is_dark(binary, map.apply(x as f64, y as f64)) == dark;
+        (total + 1, matching + hit as usize)
+    });
+    matching as f64 >= total as f64 * min_match
+}
+
+/// Mean gray levels of the dark and the light finder modules
+fn finder_levels(gray: &GrayImage, map: &Homography) -> (f64, f64) {
+    let (mut sums, mut counts) = ([0.0; 2], [0usize; 2]);
+    for ((x, y), dark) in finder_cells() {
+        sums[dark as usize] += qr::sample(gray, map.apply(x as f64, y as f64));
+        counts[dark as usize] += 1;
+    }
+    (sums[1] / counts[1] as f64, sums[0] / counts[0] as f64)
+}
+
+/// Light-dark decision for modules of one candidate
+struct Sampler<'a> {
+    gray: &'a GrayImage,
+    map: Homography,
+    threshold: f64,
+}
+
+impl Sampler<'_> {
+    /// Image position of symbol module `(x, y)` under a symmetry; the
+    /// finder core, which the frame is centered on, is module (3, 3)
+    fn point(&self, symmetry: usize, x: f64, y: f64) -> (f64, f64) {
+        let (u, v) = transform(symmetry, x - 3.0, y - 3.0);
+        self.map.apply(u, v)
+    }
+
+    fn dark(&self, symmetry: usize, x: usize, y: usize) -> bool {
+        qr::sample(self.gray, self.point(symmetry, x as f64, y as f64)) < self.threshold
+    }
+}
+
+fn decode_candidate(gray: &GrayImage, binary: &GrayImage, finder: &Finder) -> Option<QRCodeResult> {
+    // The outer edge of the finder is a square at +-3.5 modules
+    let mut map = qr::square_frame(binary, finder.center, finder.module * 6.0, 3.5)?;
+    if !finder_matches(binary, &map, MIN_FRAME_MATCH) {
+        return None;
+    }
+    // Seven modules are too few to tell perspective from edge noise, which
+    // the timing patterns resolve later
+    let mut pairs = Vec::new();
+    for _ in 0..2 {
+        let (dark, light) = finder_levels(gray, &map);
+        pairs = qr::ring_corners(gray, &map, &FINDER_EDGES, (light - dark) * MIN_EDGE_CONTRAST);
+        map = Homography::fit_affine(&pairs)?;
+    }
+    let (dark, light) = finder_levels(gray, &map);
+    if light <= dark || !finder_matches(binary, &map, MIN_FINDER_MATCH) {
+        return None;
+    }
+    let sampler = Sampler { gray, map, threshold: (dark + light) / 2.0 };
+
+    let mut orientations: Vec<(usize, usize)> = (0..8)
+        .map(|symmetry| (orientation_score(&sampler, symmetry), symmetry))
+        .filter(|&(score, _)| score >= MIN_ORIENTATION_MATCH)
+        .collect();
+    orientations.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+    orientations
+        .into_iter()
+        .find_map(|(_, symmetry)| read_symbol(&sampler, symmetry, &pairs))
+}
+
+/// Matching modules of the separator, light along the right and the bottom
+/// of the finder, and the first three timing modules on either side
+fn orientation_score(sampler: &Sampler, symmetry: usize) -> usize {
+    let separator = (0..8).flat_map(|i| [(7, i, false), (i, 7, false)]).skip(1);
+    let timing = (8..11).flat_map(|i| [(i, 0, i % 2 == 0), (0, i, i % 2 == 0)]);
+    separator
+        .chain(timing)
+        .filter(|&(x, y, dark)| sampler.dark(symmetry, x, y) == dark)
+        .count()
+}
+
+/// Symbol number and mask of the 15 format bits, around the finder from
+/// the bottom left
+fn read_format(sampler: &Sampler, symmetry: usize) -> Option<(usize, usize)> {
+    let positions = (1..=8).map(|x| (x, 8)).chain((1..=7).rev().map(|y| (8, y)));
+    let bits = positions.fold(0u32, |bits, (x, y)| bits << 1 | sampler.dark(symmetry, x, y) as u32);
+    (0..32u32)
+        .map(|value| (value, (bits ^ format_word(value)).count_ones()))
+        .filter(|&(_, errors)| errors <= MAX_FORMAT_ERRORS)
+        .min_by_key(|&(_, errors)| errors)
+        .map(|(value, _)| ((value >> 2) as usize, (value & 3) as usize))
+}
+
+/// Five format bits with their BCH check bits, masked
+fn format_word(value: u32) -> u32 {
+    let mut remainder = value << 10;
+    for bit in (10..15).rev() {
+        if remainder & 1 << bit != 0 {
+            remainder ^= FORMAT_GENERATOR << (bit - 10);
+        }
+    }
+    (value << 10 | remainder) ^ FORMAT_MASK
+}
+
+/// Where the color along `point(t)` first changes, stepping `t` by a tenth
+/// of a module from `from` for at most `limit` modules, interpolated between
+/// the samples on either side of the threshold
+fn color_change(
+    sampler: &Sampler,
+    point: impl Fn(f64) -> (f64, f64),
+    from: f64,
+    direction: f64,
+    limit: f64,
+) -> Option<f64> {
+    let level = |t: f64| qr::sample(sampler.gray, point(t));
+    let (mut t, mut previous) = (from, level(from));
+    let dark = previous < sampler.threshold;
+    while (t - from).abs() < limit {
+        let next = t + 0.1 * direction;
+        let value = level(next);
+        if (value < sampler.threshold) != dark {
+            let share = (sampler.threshold - previous) / (value - previous);
+            return Some(t + 0.1 * direction * share);
+        }
+        (t, previous) = (next, value);
+    }
+    None
+}
+
+/// The homography moved onto the timing modules, nearest the finder first.
+/// Each dark timing module is placed between its edges: the outer one
+/// against the quiet zone, then the two towards its light neighbours.
+fn refine_with_timing(
+    sampler: &Sampler,
+    symmetry: usize,
+    size: usize,
+    mut pairs: Vec<PointPair>,
+) -> Homography {
+    let mut map = sampler.map;
+    for i in (8..size).step_by(2) {
+        // Along the pattern, and across it away from the quiet zone
+        let sides = [(i, 0, (1.0, 0.0), (0.0, 1.0)), (0, i, (0.0, 1.0), (1.0, 0.0))];
+        for (x, y, along, across) in sides {
+            let moved = Sampler { map, ..*sampler };
+            let (x, y) = (x as f64, y as f64);
+            let point = |s: f64, t: f64| {
+                let (u, v) = (x + s * along.0 + t * across.0, y + s * along.1 + t * across.1);
+                moved.point(symmetry, u, v)
+            };
+            // From the quiet zone, which must be light, onto the module
+            if qr::sample(moved.gray, point(0.0, -1.5)) < moved.threshold {
+                continue;
+            }
+            let Some(outer) = color_change(&moved, |t| point(0.0, t), -1.5, 1.0, 2.0) else {
+                continue;
+            };
+            let t = outer + 0.5;
+            let before = color_change(&moved, |s| point(s, t), 0.0, -1.0, 1.0);
+            let after = color_change(&moved, |s| point(s, t), 0.0, 1.0, 1.0);
+            let (Some(before), Some(after)) = (before, after) else {
+                continue;
+            };
+            if (after - before - 1.0).abs() > 0.5 {
+                continue;
+            }
+            pairs.push((transform(symmetry, x - 3.0, y - 3.0), point((before + after) / 2.0, t)));
+        }
+        if let Some(fitted) = Homography::fit(&pairs) {
+            map = fitted;
+        }
+    }
+    map
+}
+
+/// Offsets in modules to try for the symbol corner opposite the finder,
+/// nearest first
+fn far_corner_offsets() -> impl Iterator<Item = (f64, f64)> {
+    let mut offsets: Vec<(f64, f64)> = (-2..=2)
+        .flat_map(|a| (-2..=2).map(move |b| (a as f64 * 0.5, b as f64 * 0.5)))
+        .collect();
+    offsets.sort_by(|a, b| a.0.hypot(a.1).total_cmp(&b.0.hypot(b.1)));
+    offsets.into_iter()
+}
+
+/// The map with the corner opposite the finder moved by `offset` modules.
+/// The finder and the timing patterns only cover the two sides at the
+/// finder, so small errors along them swing that corner the most.
+fn move_far_corner(
+    sampler: &Sampler,
+    symmetry: usize,
+    size: usize,
+    (du, dv): (f64, f64),
+) -> Option<Homography> {
+    if du == 0.0 && dv == 0.0 {
+        return Some(sampler.map);
+    }
+    let edge = size as f64 - 0.5;
+    let corners = [(-0.5, -0.5), (edge, -0.5), (edge + du, edge + dv), (-0.5, edge)];
+    let pairs: Vec<PointPair> = corners
+        .iter()
+        .zip([(-0.5, -0.5), (edge, -0.5), (edge, edge), (-0.5, edge)])
+        .map(|(&(x, y), (u, v))| {
+            (transform(symmetry, u - 3.0, v - 3.0), sampler.point(symmetry, x, y))
+        })
+        .collect();
+    Homography::fit(&pairs)
+}
+
+fn read_symbol(
+    sampler: &Sampler,
+    symmetry: usize,
+    finder_pairs: &[PointPair],
+) -> Option<QRCodeResult> {
+    let (number, mask) = read_format(sampler, symmetry)?;
+    let symbol = &SYMBOL_TYPES[number];
+    let size = 9 + 2 * symbol.version;
+    let map = refine_with_timing(sampler, symmetry, size, finder_pairs.to_vec());
+    let sampler = Sampler { map, ..*sampler };
+
+    let timing: Vec<(usize, usize, bool)> =
+        (8..size).flat_map(|i| [(i, 0, i % 2 == 0), (0, i, i % 2 == 0)]).collect();
+    let matching = timing
+        .iter()
+        .filter(|&&(x, y, dark)| sampler.dark(symmetry, x, y) == dark)
+        .count();
+    if (matching as f64) < timing.len() as f64 * MIN_TIMING_MATCH {
+        return None;
+    }
+
+    let field = GaloisField::new(PRIMITIVE, 256);
+    let (sampler, words, corrected) = far_corner_offsets().find_map(|offset| {
+        let map = move_far_corner(&sampler, symmetry, size, offset)?;
+        let sampler = Sampler { map, ..sampler };
+        let bits = read_bits(&sampler, symmetry, size, mask);
+        let mut words = codewords(&bits, symbol);
+        let corrected = reed_solomon::correct_from(&field, &mut words, symbol.check_words, 0)?;
+        log_debug!("Micro QR symbol corrected {} codewords", corrected);
+        (corrected <= symbol.capacity).then_some((sampler, words, corrected))
+    })?;
+
+    // Back to the data bit stream, dropping the unused half of a 4-bit word
+    let data: Vec<bool> = words[..words.len() - symbol.check_words]
+        .iter()
+        .flat_map(|&word| (0..8).rev().map(move |bit| word >> bit & 1 == 1))
+        .take(symbol.data_bits)
+        .collect();
+    let (bytes, kanji) = decode_payload(&data, symbol.version)?;
+    let text = qr::payload_text(&bytes, None, kanji);
+
+    let edge = size as f64 - 0.5;
+    let bounds = vec![
+        sampler.point(symmetry, -0.5, -0.5),
+        sampler.point(symmetry, edge, -0.5),
+        sampler.point(symmetry, edge, edge),
+        sampler.point(symmetry, -0.5, edge),
+    ];
+    let payload_hash = qr::hash_payload(&bytes);
+    let mut result = QRCodeResult {
+        symbology: Symbology::MicroQr,
+        data: text.data,
+        version: -(symbol.version as i32),
+        bounds,
+        ecc_level: symbol.ecc_level.to_string(),
+        mask: mask as i32,
+        raw_bytes: bytes,
+        inverted: false,
+        mirrored: symmetry % 2 == 1,
+        gs1: false,
+        strategy: String::new(),
+        orientation_degrees: 0.0,
+        center: (0.0, 0.0),
+        area: 0.0,
+        angle_degrees: 0.0,
+        module_size_px: 0.0,
+        structured_append: None,
+        encoding: text.encoding,
+        encoding_error: text.failed,
+        check_digit_valid: None,
+        crop_png: Vec::new(),
+        modules: None,
+        track_id: None,
+        smoothed_bounds: Vec::new(),
+        corrected_codewords: corrected as u32,
+        quality: qr::correction_quality([(corrected, symbol.capacity)]),
+        payload_hash,
+    };
+    result.update_geometry();
+    Some(result)
+}
+
+// ==================== Codewords ====================
+
+/// Whether module `(x, y)` belongs to the finder, separator, timing or
+/// format information rather than to the data
+fn is_function(x: usize, y: usize) -> bool {
+    x == 0 || y == 0 || (x < 9 && y < 9)
+}
+
+/// The four Micro QR masks, QR masks 1, 4, 6 and 7, at row `y` and column `x`
+fn mask_bit(mask: usize, x: usize, y: usize) -> bool {
+    match mask {
+        0 => y.is_multiple_of(2),
+        1 => (y / 2 + x / 3).is_multiple_of(2),
+        2 => ((x * y) % 2 + (x * y) % 3).is_multiple_of(2),
+        _ => ((x + y) % 2 + (x * y) % 3).is_multiple_of(2),
+    }
+}
+
+/// Unmasked data modules in placement order: two columns wide, upwards
+/// from the bottom right corner and turning at the edges
+fn read_bits(sampler: &Sampler, symmetry: usize, size: usize, mask: usize) -> Vec<bool> {
+    let mut bits = Vec::new();
+    let mut upwards = true;
+    for right in (2..size).rev().step_by(2) {
+        for i in 0..size {
+            let y = if upwards { size - 1 - i } else { i };
+            for x in [right, right - 1] {
+                if !is_function(x, y) {
+                    bits.push(sampler.dark(symmetry, x, y) != mask_bit(mask, x, y));
+                }
+            }
+        }
+        upwards = !upwards;
+    }
+    bits
+}
+
+/// Data then check codewords, the last data codeword of M1 and M3 being 4
+/// bits, kept in the high half
+fn codewords(bits: &[bool], symbol: &SymbolType) -> Vec<u16> {
+    let word = |bits: &[bool]| bits.iter().fold(0u16, |word, &bit| word << 1 | bit as u16);
+    let (data, check) = bits.split_at(symbol.data_bits.min(bits.len()));
+    data.chunks(8)
+        .map(|chunk| word(chunk) << (8 - chunk.len()))
+        .chain(check.chunks_exact(8).take(symbol.check_words).map(word))
+        .collect()
+}
+
+// ==================== Decodation ====================
+
+const ALPHANUMERIC: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+
+struct Bits<'a> {
+    bits: &'a [bool],
+    pos: usize,
+}
+
+impl Bits<'_> {
+    fn read(&mut self, count: usize) -> Option<u32> {
+        let bits = self.bits.get(self.pos..self.pos + count)?;
+        self.pos += count;
+        Some(bits.iter().fold(0, |value, &bit| value << 1 | bit as u32))
+    }
+
+    fn remaining(&self) -> usize {
+        self.bits.len() - self.pos
+    }
+}
+
+/// Segments up to the terminator, or to the end of the data; returns the
+/// payload bytes and whether a Kanji segment (Shift JIS) was among them
+fn decode_payload(data: &[bool], version: usize) -> Option<(Vec<u8>, bool)> {
+    // Mode indicators grow from none (M1, numeric only) to three bits
+    let mode_bits = version - 1;
+    let terminator = 2 * version + 1;
+    let mut bits = Bits { bits: data, pos: 0 };
+    let (mut bytes, mut kanji) = (Vec::new(), false);
+    loop {
+        let rest = &data[bits.pos..];
+        if rest.iter().take(terminator).all(|&bit| !bit) || bits.remaining() < mode_bits {
+            break;
+        }
+        let mode = bits.read(mode_bits)?;
+        // Character count lengths for M1 to M4, by mode
+        let count_bits = match mode {
+            0 => [3, 4, 5, 6][version - 1],
+            1 => [0, 3, 4, 5][version - 1],
+            2 => [0, 0, 4, 5][version - 1],
+            _ => [0, 0, 3, 4][version - 1],
+        };
+        let count = bits.read(count_bits)? as usize;
+        match mode {
+            0 => {
+                for group in (0..count).step_by(3) {
+                    let digits = (count - group).min(3);
+                    let value = bits.read([0, 4, 7, 10][digits])?;
+                    if value >= 10u32.pow(digits as u32) {
+                        return None;
+                    }
+                    bytes.extend(format!("{:0width$}", value, width = digits).bytes());
+                }
+            }
+            1 => {
+                for pair in (0..count).step_by(2) {
+                    if count - pair >= 2 {
+                        let value = bits.read(11)? as usize;
+                        bytes.push(*ALPHANUMERIC.get(value / 45)?);
+                        bytes.push(ALPHANUMERIC[value % 45]);
+                    } else {
+                        bytes.push(*ALPHANUMERIC.get(bits.read(6)? as usize)?);
+                    }
+                }
+            }
+            2 => {
+                for _ in 0..count {
+                    bytes.push(bits.read(8)? as u8);
+                }
+            }
+            _ => {
+                kanji = true;
+                for _ in 0..count {
+                    let value = bits.read(13)?;
+                    let code = ((value / 0xC0) << 8) | (value % 0xC0);
+                    let code = if code + 0x8140 <= 0x9FFC { code + 0x8140 } else { code + 0xC140 };
+                    bytes.extend([(code >> 8) as u8, code as u8]);
+                }
+            }
+        }
+    }
+    Some((bytes, kanji))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+    use qrcode::{bits::Bits as CodeBits, Color, EcLevel, QrCode, Version};
+
+    /// Module rows of a symbol encoded by the `qrcode` crate, dark as true
+    fn modules(code: &QrCode) -> Vec<Vec<bool>> {
+        let colors = code.to_colors();
+        let dark = |row: &[Color]| row.iter().map(|c| *c == Color::Dark).collect();
+        colors.chunks(code.width()).map(dark).collect()
+    }
+
+    fn micro(data: &[u8], version: i16, level: EcLevel) -> Vec<Vec<bool>> {
+        let code = QrCode::with_version(data, Version::Micro(version), level).unwrap();
+        let mut symbol = modules(&code);
+        if (version, level) == (3, EcLevel::L) {
+            halve_last_data_codeword(&mut symbol, 84, 6);
+        }
+        symbol
+    }
+
+    /// The `qrcode` crate places a whole last data codeword in M3-L, where
+    /// the standard has 4 bits as in M1 and M3-M: keep its high half and
+    /// place check codewords over that right behind the data bits
+    fn halve_last_data_codeword(symbol: &mut [Vec<bool>], data_bits: usize, check_words: usize) {
+        let size = symbol.len();
+        let mut positions = Vec::new();
+        let mut upwards = true;
+        for right in (2..size).rev().step_by(2) {
+            for i in 0..size {
+                let y = if upwards { size - 1 - i } else { i };
+                let pair = [(right, y), (right - 1, y)];
+                positions.extend(pair.into_iter().filter(|&(x, y)| !is_function(x, y)));
+            }
+            upwards = !upwards;
+        }
+        // Symbol number 3 with the mask the crate chose
+        let format = (1..=8).map(|x| (x, 8)).chain((1..=7).rev().map(|y| (8, y)));
+        let format = format.fold(0, |bits, (x, y)| bits << 1 | symbol[y][x] as u32);
+        let mask = (0..4).find(|&mask| format_word(3 << 2 | mask as u32) == format).unwrap();
+        let mut bits: Vec<bool> =
+            positions.iter().map(|&(x, y)| symbol[y][x] != mask_bit(mask, x, y)).collect();
+        let word = |bits: &[bool]| bits.iter().fold(0u8, |word, &bit| word << 1 | bit as u8);
+        let mut data: Vec<u8> = bits[..data_bits + 4].chunks(8).map(word).collect();
+        *data.last_mut().unwrap() &= 0xF0;
+        let check = qrcode::ec::create_error_correction_code(&data, check_words);
+        bits.truncate(data_bits);
+        let check_bits = check.iter().flat_map(|&word| (0..8).rev().map(move |bit| word >> bit));
+        bits.extend(check_bits.map(|bit| bit & 1 == 1));
+        bits.resize(positions.len(), false);
+        for (&(x, y), bit) in positions.iter().zip(bits) {
+            symbol[y][x] = bit != mask_bit(mask, x, y);
+        }
+    }
+
+    /// The symbol at `module` pixels, turned by `degrees` around the center
+    /// of an image with room for it at any angle
+    fn render(modules: &[Vec<bool>], module: f64, degrees: f64) -> GrayImage {
+        let size = modules.len() as f64;
+        let side = ((size * 2f64.sqrt() + 8.0) * module).ceil() as u32;
+        let center = side as f64 / 2.0;
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        GrayImage::from_fn(side, side, |x, y| {
+            let (dx, dy) = (x as f64 + 0.5 - center, y as f64 + 0.5 - center);
+            let col = (cos * dx + sin * dy) / module + size / 2.0;
+            let row = (cos * dy - sin * dx) / module + size / 2.0;
+            let inside = (0.0..size).contains(&col) && (0.0..size).contains(&row);
+            let dark = inside && modules[row as usize][col as usize];
+            Luma([if dark { 0 } else { 255 }])
+        })
+    }
+
+    #[test]
+    fn dimensions_cover_m1_to_m4() {
+        assert_eq!(dimensions(-1), Some(11));
+        assert_eq!(dimensions(-4), Some(17));
+        assert_eq!(dimensions(0), None);
+        assert_eq!(dimensions(1), None);
+    }
+
+    #[test]
+    fn decodes_every_symbol_type() {
+        let cases: [(&[u8], i16, EcLevel, &str); 8] = [
+            (b"12345", 1, EcLevel::L, ""),
+            (b"12345", 2, EcLevel::L, "L"),
+            (b"AB12", 2, EcLevel::M, "M"),
+            (b"ABCDEF", 3, EcLevel::L, "L"),
+            (b"bytes!", 3, EcLevel::M, "M"),
+            (b"0123456789012345678901234567890", 4, EcLevel::L, "L"),
+            (b"HELLO MICRO QR", 4, EcLevel::M, "M"),
+            (b"hello", 4, EcLevel::Q, "Q"),
+        ];
+        for (data, version, level, name) in cases {
+            let results = decode(&render(&micro(data, version, level), 5.0, 0.0));
+            assert_eq!(results.len(), 1, "M{} {}", version, name);
+            let result = &results[0];
+            assert_eq!(result.data.as_bytes(), data);
+            assert_eq!(result.version, -(version as i32));
+            assert_eq!(result.ecc_level, name);
+            assert_eq!(result.symbology, Symbology::MicroQr);
+            assert_eq!(result.corrected_codewords, 0);
+            assert!((result.module_size_px - 5.0).abs() < 0.3, "{}", result.module_size_px);
+        }
+    }
+
+    #[test]
+    fn kanji_segments_stay_shift_jis() {
+        // "点茗" in Shift JIS, then digits
+        let sjis = [0x93, 0x5F, 0xE4, 0xAA];
+        let mut bits = CodeBits::new(Version::Micro(4));
+        bits.push_kanji_data(&sjis).unwrap();
+        bits.push_numeric_data(b"42").unwrap();
+        bits.push_terminator(EcLevel::L).unwrap();
+        let code = QrCode::with_bits(bits, EcLevel::L).unwrap();
+        let results = decode(&render(&modules(&code), 5.0, 0.0));
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].data, "点茗42");
+        assert_eq!(results[0].raw_bytes, [0x93, 0x5F, 0xE4, 0xAA, b'4', b'2']);
+    }
+
+    #[test]
+    fn rotated_and_mirrored_symbols_decode() {
+        let symbol = micro(b"MICRO-42", 4, EcLevel::M);
+        for degrees in [0.0, 17.0, 90.0, 133.0, 180.0, 251.0, 300.0] {
+            let results = decode(&render(&symbol, 5.0, degrees));
+            assert_eq!(results.len(), 1, "{} degrees", degrees);
+            assert_eq!(results[0].data, "MICRO-42");
+            assert!(!results[0].mirrored);
+        }
+        let mirrored: Vec<Vec<bool>> =
+            symbol.iter().map(|row| row.iter().rev().copied().collect()).collect();
+        let results = decode(&render(&mirrored, 5.0, 40.0));
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].data, "MICRO-42");
+        assert!(results[0].mirrored);
+    }
+
+    #[test]
+    fn damaged_codewords_are_corrected() {
+        let mut symbol = micro(b"DAMAGED 1234", 4, EcLevel::Q);
+        // Flip a 3x3 block of data modules by the bottom right corner
+        for row in &mut symbol[13..16] {
+            for dark in &mut row[13..16] {
+                *dark = !*dark;
+            }
+        }
+        let results = decode(&render(&symbol, 5.0, 0.0));
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].data, "DAMAGED 1234");
+        assert!(results[0].corrected_codewords > 0);
+        assert!(results[0].quality < 1.0);
+
+        // Past the capacity of M2-L, one codeword
+        let mut symbol = micro(b"4455667788", 2, EcLevel::L);
+        for row in &mut symbol[7..13] {
+            for dark in &mut row[7..13] {
+                *dark = !*dark;
+            }
+        }
+        let results = decode(&render(&symbol, 5.0, 0.0));
+        assert!(results.iter().all(|result| result.data == "4455667788"));
+    }
+
+    #[test]
+    fn two_symbols_in_one_image() {
+        let (left, right) = (micro(b"LEFT", 3, EcLevel::M), micro(b"RIGHT", 4, EcLevel::L));
+        let (left, right) = (render(&left, 4.0, 0.0), render(&right, 5.0, 0.0));
+        let mut frame = GrayImage::from_pixel(left.width() + right.width(), 140, Luma([255]));
+        image::imageops::overlay(&mut frame, &left, 0, 0);
+        image::imageops::overlay(&mut frame, &right, left.width() as i64, 0);
+        let mut data: Vec<String> = decode(&frame).into_iter().map(|result| result.data).collect();
+        data.sort();
+        assert_eq!(data, ["LEFT", "RIGHT"]);
+    }
+
+    #[test]
+    fn qr_codes_and_noise_are_not_micro_qr() {
+        // Three finders, but no timing pattern beside any of them
+        let qr = modules(&QrCode::new(b"regular qr").unwrap());
+        assert!(decode(&render(&qr, 4.0, 0.0)).is_empty());
+        assert!(decode(&GrayImage::from_pixel(200, 200, Luma([255]))).is_empty());
+        let noise = GrayImage::from_fn(300, 300, |x, y| {
+            Luma([if (x * 7 + y * 13 + x * y) % 5 < 2 { 0 } else { 255 }])
+        });
+        assert!(decode(&noise).is_empty());
+        assert!(decode(&GrayImage::new(10, 10)).is_empty());
+    }
+}
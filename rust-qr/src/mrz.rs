@@ -0,0 +1,3354 @@
+//! Machine Readable Zone parsing (ICAO 9303 TD1/TD2/TD3 and visas),
+//! multi-frame OCR fusion, MRZ generation and eMRTD access keys.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use unicode_normalization::UnicodeNormalization;
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::countries;
+use crate::document_numbers;
+use crate::mrz_optional;
+pub use crate::mrz_locate::{find_mrz_regions, MrzRegion};
+pub use crate::mrz_optional::MrzOptionalData;
+
+/// Errors from MRZ parsing and generation; wrapped in `VeloQrError::Mrz`
+/// at the JS boundary with the same codes
+#[derive(Debug, Clone, PartialEq)]
+pub enum MrzError {
+    NoMrzLines,
+    MrzFormatUnknown(String),
+    InvalidMrzCharacters(Vec<char>),
+    InvalidMrzField(String),
+}
+
+impl MrzError {
+    /// Stable machine-readable error code
+    pub fn code(&self) -> &'static str {
+        match self {
+            MrzError::NoMrzLines => "NO_MRZ_LINES",
+            MrzError::MrzFormatUnknown(_) => "MRZ_FORMAT_UNKNOWN",
+            MrzError::InvalidMrzCharacters(_) => "INVALID_MRZ_CHARACTERS",
+            MrzError::InvalidMrzField(_) => "INVALID_MRZ_FIELD",
+        }
+    }
+}
+
+impl std::fmt::Display for MrzError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MrzError::NoMrzLines => write!(f, "No valid MRZ lines found"),
+            MrzError::MrzFormatUnknown(e) => write!(f, "Invalid MRZ format: {}", e),
+            MrzError::InvalidMrzCharacters(chars) => {
+                let listed: Vec<String> = chars.iter().map(|c| format!("{:?}", c)).collect();
+                write!(f, "Invalid MRZ characters: {}", listed.join(", "))
+            }
+            MrzError::InvalidMrzField(e) => write!(f, "Invalid MRZ field: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for MrzError {}
+
+// ==================== MRZ Parsing Implementation ====================
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MRZResult {
+    pub document_type: String,  // TD1, TD2, TD3, MRV-A, MRV-B or FR_CNI
+    pub document_code: String,  // ICAO code from line 1, e.g. P, PD, ID, AC, V
+    pub document_category: String, // passport, id_card, visa or other
+    pub document_number: String,
+    pub document_number_plausible: Option<bool>, // Fits the country's format; null without a rule
+    pub date_of_birth: String,
+    pub date_of_expiry: String,
+    pub nationality: String,
+    pub sex: String, // M, F, X (unspecified) or "unknown"
+    pub sex_raw: String, // The character as read
+    pub surname: String,
+    pub given_names: String,
+    pub given_names_list: Vec<String>, // `given_names` split into individual names
+    pub name_truncated: bool,          // The name field is full, so names may be cut short
+    pub optional_data: String,
+    pub personal_number: String, // TD3 only, empty elsewhere
+    pub optional_parsed: Option<MrzOptionalData>, // Country-specific decoding of the above
+    pub issuing_country: String,
+    pub issuing_country_valid: bool, // Known ISO 3166-1 alpha-3 or ICAO code
+    pub issuing_country_name: Option<String>,
+    pub nationality_valid: bool,
+    pub nationality_name: Option<String>,
+    pub raw_mrz: Vec<String>, // Padded/trimmed and OCR-corrected lines the fields are read from
+    pub raw_input_lines: Vec<String>, // The same lines as cleaned from the input, unpadded
+    pub normalization_applied: Vec<String>, // How each changed line became its `raw_mrz` line
+    pub confidence: f32, // Weighted mean of `field_confidence`, see `score_fields`
+    pub field_confidence: HashMap<String, f32>, // 0 to 1 per field, `names` for the name field
+    pub check_digits: MRZCheckDigits,
+    pub date_of_birth_iso: Option<String>,  // YYYY-MM-DD, null when unparseable
+    pub date_of_expiry_iso: Option<String>, // YYYY-MM-DD, null when unparseable
+    pub warnings: Vec<MrzWarning>,
+    pub status: MrzStatus, // Verdict from `warnings`, see `MrzStatus`
+    pub is_expired: Option<bool>, // As of the reference date, null when expiry is unparseable
+    pub age_years: Option<u32>,   // As of the reference date, null when birth is unparseable
+    pub corrections: Vec<String>, // OCR substitutions applied before parsing
+    pub length_warnings: Vec<String>, // Lines padded or trimmed to the format's length
+    pub field_spans: MRZFieldSpans,
+}
+
+/// A field that parsed but failed validation
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct MrzWarning {
+    pub code: MrzWarningCode,
+    /// `MRZResult` field name, `names` for the name field or `composite`
+    pub field: String,
+    /// The field as read; the check digit itself for `composite`, the
+    /// failed checks for `UNEXPECTED_LAYOUT`
+    pub value: String,
+}
+
+impl MrzWarning {
+    fn new(code: MrzWarningCode, field: &str, value: impl Into<String>) -> Self {
+        MrzWarning { code, field: field.to_string(), value: value.into() }
+    }
+}
+
+impl std::fmt::Display for MrzWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} in {}: {}", self.code.as_str(), self.field, self.value)
+    }
+}
+
+/// What an `MrzWarning` found
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum MrzWarningCode {
+    /// The field's check digit (or the composite one) does not match
+    CheckDigitMismatch,
+    /// A YYMMDD field is not a calendar date
+    InvalidDate,
+    /// Neither an ISO 3166-1 alpha-3 nor an ICAO-specific country code
+    UnknownCountry,
+    /// Digits in an alphabetic field or letters in a numeric one, left over
+    /// after OCR correction
+    InvalidCharset,
+    /// A sex character other than `M`, `F`, `X` or `<`
+    InvalidSex,
+    /// A document number that does not fit its issuing country's format
+    ImplausibleDocumentNumber,
+    /// Three lines that fail several TD1 sanity checks at once, with no
+    /// alternate layout fitting better: most likely not a TD1 MRZ, or one
+    /// OCR misaligned (see `check_td1_layout`)
+    UnexpectedLayout,
+}
+
+impl MrzWarningCode {
+    /// Stable machine-readable code, as serialized
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MrzWarningCode::CheckDigitMismatch => "CHECK_DIGIT_MISMATCH",
+            MrzWarningCode::InvalidDate => "INVALID_DATE",
+            MrzWarningCode::UnknownCountry => "UNKNOWN_COUNTRY",
+            MrzWarningCode::InvalidCharset => "INVALID_CHARSET",
+            MrzWarningCode::InvalidSex => "INVALID_SEX",
+            MrzWarningCode::ImplausibleDocumentNumber => "IMPLAUSIBLE_DOCUMENT_NUMBER",
+            MrzWarningCode::UnexpectedLayout => "UNEXPECTED_LAYOUT",
+        }
+    }
+}
+
+/// Overall verdict on an `MRZResult`, for routing scans to automatic
+/// acceptance, human review or a rescan
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MrzStatus {
+    /// No warnings at all
+    Valid,
+    /// Exactly one field failed its checks (check digit, impossible date,
+    /// forbidden characters, unrecognized sex, implausible document number;
+    /// usually one misread character), or the only warnings are an unknown
+    /// country code, a bad composite check or an unexpected layout
+    Suspect,
+    /// Two or more fields failed those checks. The composite check digit
+    /// does not count: it fails along with whichever field it covers.
+    Invalid,
+}
+
+impl MrzStatus {
+    fn from_warnings(warnings: &[MrzWarning]) -> Self {
+        if warnings.is_empty() {
+            return MrzStatus::Valid;
+        }
+        let mut failed_fields: Vec<&str> = Vec::new();
+        for warning in warnings {
+            let field = warning.field.as_str();
+            let counts = !matches!(
+                warning.code,
+                MrzWarningCode::UnknownCountry | MrzWarningCode::UnexpectedLayout
+            ) && field != "composite";
+            if counts && !failed_fields.contains(&field) {
+                failed_fields.push(field);
+            }
+        }
+        if failed_fields.len() >= 2 {
+            MrzStatus::Invalid
+        } else {
+            MrzStatus::Suspect
+        }
+    }
+}
+
+/// Line index into `raw_mrz` and `[start, end)` character range of a field
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FieldSpan {
+    pub line: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Where each `MRZResult` field was read from, for highlighting in a UI.
+/// A field split across ranges (TD1 long document numbers) has several
+/// spans; a field the format lacks has none.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct MRZFieldSpans {
+    pub document_code: Vec<FieldSpan>,
+    pub issuing_country: Vec<FieldSpan>,
+    pub document_number: Vec<FieldSpan>,
+    pub date_of_birth: Vec<FieldSpan>,
+    pub sex: Vec<FieldSpan>,
+    pub date_of_expiry: Vec<FieldSpan>,
+    pub nationality: Vec<FieldSpan>,
+    pub surname: Vec<FieldSpan>,
+    pub given_names: Vec<FieldSpan>,
+    pub optional_data: Vec<FieldSpan>,
+    pub personal_number: Vec<FieldSpan>,
+}
+
+impl MRZFieldSpans {
+    /// Layout shared by the two-line formats (TD2, TD3, MRV-A, MRV-B)
+    fn two_line(line1: &str, length: usize, optional_end: usize) -> Self {
+        let (surname, given_names) = name_spans(line1, 0, 5, length);
+        MRZFieldSpans {
+            document_code: span(0, 0, 2),
+            issuing_country: span(0, 2, 5),
+            document_number: span(1, 0, 9),
+            date_of_birth: span(1, 13, 19),
+            sex: span(1, 20, 21),
+            date_of_expiry: span(1, 21, 27),
+            nationality: span(1, 10, 13),
+            surname,
+            given_names,
+            optional_data: span(1, 28, optional_end),
+            personal_number: Vec::new(),
+        }
+    }
+}
+
+fn span(line: usize, start: usize, end: usize) -> Vec<FieldSpan> {
+    vec![FieldSpan { line, start, end }]
+}
+
+/// Surname and given name spans within a `<<`-separated name field,
+/// excluding the separator and trailing filler
+fn name_spans(
+    text: &str,
+    line: usize,
+    start: usize,
+    end: usize,
+) -> (Vec<FieldSpan>, Vec<FieldSpan>) {
+    let field = extract_field(text, start, end);
+    let mut parts = field.splitn(2, "<<");
+    let surname = parts.next().unwrap_or("");
+    let given_names = parts.next().unwrap_or("").trim_start_matches('<');
+    let given_start = field.chars().count() - given_names.chars().count();
+
+    let non_empty = |from: usize, part: &str| {
+        let length = part.trim_end_matches('<').chars().count();
+        if length == 0 {
+            Vec::new()
+        } else {
+            span(line, start + from, start + from + length)
+        }
+    };
+    (non_empty(0, surname), non_empty(given_start, given_names))
+}
+
+/// Options for `parse_mrz_text`, deserialized from a JS object.
+/// Every field is optional; missing fields fall back to the defaults.
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct MrzOptions {
+    /// How eagerly to fix OCR lookalike characters (O/0, I/1, S/5, B/8, Z/2)
+    pub correction_level: CorrectionLevel,
+    /// Require the text to be exactly the 2 or 3 MRZ lines instead of
+    /// searching noisy OCR output for the best-scoring candidate lines.
+    /// Strict parsing also ignores `length_tolerance`: the format follows
+    /// from the line count alone and every line is padded or trimmed.
+    pub strict: bool,
+    /// Cleaned lines shorter than this are not MRZ lines (default 20)
+    pub min_line_length: usize,
+    /// How far a line's length may stray from the canonical 30/36/44 of its
+    /// format, e.g. when OCR drops trailing `<` filler (default 4)
+    pub length_tolerance: usize,
+    /// Fill `optional_parsed` for issuing countries with a known layout of
+    /// the optional data (default true)
+    pub decode_optional_data: bool,
+}
+
+impl Default for MrzOptions {
+    fn default() -> Self {
+        MrzOptions {
+            correction_level: CorrectionLevel::default(),
+            strict: false,
+            min_line_length: 20,
+            length_tolerance: 4,
+            decode_optional_data: true,
+        }
+    }
+}
+
+impl MrzOptions {
+    /// `length_tolerance`, or `None` when strict parsing skips the length checks
+    fn tolerance(&self) -> Option<usize> {
+        (!self.strict).then_some(self.length_tolerance)
+    }
+}
+
+/// OCR confusion correction applied to the MRZ lines before parsing
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum CorrectionLevel {
+    /// Fields are parsed exactly as read
+    Off,
+    /// Digits in alphabetic fields become letters; letters in check-digit
+    /// protected fields become digits only when that makes the check pass
+    #[default]
+    Safe,
+    /// Like `Safe`, but dates and check digits are normalized to digits
+    /// even when no check digit confirms the change
+    Aggressive,
+}
+
+/// Physical MRZ layout, decided from line count, length and document code
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum MrzFormat {
+    Td1,
+    Td2,
+    Td3,
+    MrvA,
+    MrvB,
+    FrCni,
+}
+
+impl MrzFormat {
+    /// With a `tolerance`, two lines are classified by the canonical length
+    /// nearest the longer one, and every line must be within `tolerance` of
+    /// the format's length. Without one (strict parsing) any length goes and
+    /// two lines of 40 characters or more on the first are TD3-sized.
+    fn detect(lines: &[String], tolerance: Option<usize>) -> Result<Self, MrzError> {
+        let format = match lines.len() {
+            0 => return Err(MrzError::NoMrzLines),
+            2 => {
+                // Could be TD2 or TD3, or the visa lookalikes MRV-B / MRV-A.
+                // OCR loses characters rather than adding them, so the
+                // longer line says more about the layout.
+                let is_visa = lines[0].starts_with('V');
+                let length = match tolerance {
+                    Some(_) => lines.iter().map(|line| line.chars().count()).max().unwrap_or(0),
+                    None => lines[0].chars().count(),
+                };
+                match (length >= 40, is_visa) {
+                    (true, true) => MrzFormat::MrvA,
+                    (true, false) => MrzFormat::Td3,
+                    (false, true) => MrzFormat::MrvB,
+                    (false, false) if is_fr_cni(lines) => MrzFormat::FrCni,
+                    (false, false) => MrzFormat::Td2,
+                }
+            }
+            3 => MrzFormat::Td1,
+            n => return Err(MrzError::MrzFormatUnknown(format!("{} lines", n))),
+        };
+
+        if let Some(tolerance) = tolerance {
+            let expected = format.line_length();
+            let lengths = lines.iter().map(|line| line.chars().count()).enumerate();
+            for (index, length) in lengths {
+                if length.abs_diff(expected) > tolerance {
+                    return Err(MrzError::MrzFormatUnknown(format!(
+                        "line {} has {} characters, {} expects {}",
+                        index + 1,
+                        length,
+                        format.name(),
+                        expected
+                    )));
+                }
+            }
+        }
+        Ok(format)
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            MrzFormat::Td1 => "TD1",
+            MrzFormat::Td2 => "TD2",
+            MrzFormat::Td3 => "TD3",
+            MrzFormat::MrvA => "MRV-A",
+            MrzFormat::MrvB => "MRV-B",
+            MrzFormat::FrCni => "FR_CNI",
+        }
+    }
+
+    fn line_length(self) -> usize {
+        match self {
+            MrzFormat::Td1 => 30,
+            MrzFormat::Td2 | MrzFormat::MrvB | MrzFormat::FrCni => 36,
+            MrzFormat::Td3 | MrzFormat::MrvA => 44,
+        }
+    }
+
+    /// Line and position of the composite check digit, if the format has one
+    fn composite_check(self) -> Option<(usize, usize)> {
+        let fields = check_digit_fields(self);
+        fields.iter().find(|field| field.name == "composite").map(|field| field.digit)
+    }
+}
+
+/// The pre-2021 French ID card has TD2's size and an `IDFRA` prefix; it is
+/// told apart by the TD2 check digits failing where its own pass
+fn is_fr_cni(lines: &[String]) -> bool {
+    if !lines[0].starts_with("IDFRA") {
+        return false;
+    }
+    let passing = |format| {
+        let reports = check_digit_reports(format, lines);
+        reports.iter().filter(|report| report.field != "composite" && report.passed).count()
+    };
+    let td2 = passing(MrzFormat::Td2);
+    let cni = passing(MrzFormat::FrCni);
+    td2 < 3 && cni >= td2
+}
+
+/// Which characters a field may legitimately contain
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum FieldKind {
+    Alphabetic,
+    Numeric,
+    Alphanumeric,
+}
+
+impl FieldKind {
+    /// Whether `c` may stand in a field of this kind; filler always may
+    fn allows(self, c: char) -> bool {
+        match self {
+            FieldKind::Alphabetic => !c.is_ascii_digit(),
+            FieldKind::Numeric => !c.is_ascii_uppercase(),
+            FieldKind::Alphanumeric => true,
+        }
+    }
+
+    /// The characters `allows`, as `MrzCharsetViolation.allowed` reports them
+    fn allowed(self) -> &'static str {
+        match self {
+            FieldKind::Alphabetic => "A-Z<",
+            FieldKind::Numeric => "0-9<",
+            FieldKind::Alphanumeric => "A-Z0-9<",
+        }
+    }
+}
+
+/// Location of a correctable MRZ field: `line`, `start..end` and an optional check digit
+struct MrzField {
+    name: &'static str,
+    line: usize,
+    start: usize,
+    end: usize,
+    check: Option<usize>,
+    kind: FieldKind,
+}
+
+impl MrzField {
+    const fn new(
+        name: &'static str,
+        line: usize,
+        start: usize,
+        end: usize,
+        check: Option<usize>,
+        kind: FieldKind,
+    ) -> Self {
+        MrzField { name, line, start, end, check, kind }
+    }
+}
+
+/// A check digit of some format: the field it protects, the
+/// `(line, start, end)` ranges of the characters it covers and the
+/// `(line, position)` of the digit itself
+struct CheckDigitField {
+    name: &'static str,
+    data: &'static [(usize, usize, usize)],
+    digit: (usize, usize),
+}
+
+const fn check_digit_field(
+    name: &'static str,
+    data: &'static [(usize, usize, usize)],
+    digit: (usize, usize),
+) -> CheckDigitField {
+    CheckDigitField { name, data, digit }
+}
+
+/// Every check digit of each format, in ICAO 9303 positions. The TD1
+/// document number may continue into the optional data, which moves its
+/// check digit, see `check_digit_reports`.
+fn check_digit_fields(format: MrzFormat) -> &'static [CheckDigitField] {
+    const TD1: &[CheckDigitField] = &[
+        check_digit_field("document_number", &[(0, 5, 14)], (0, 14)),
+        check_digit_field("date_of_birth", &[(1, 0, 6)], (1, 6)),
+        check_digit_field("date_of_expiry", &[(1, 8, 14)], (1, 14)),
+        check_digit_field("composite", &[(0, 5, 30), (1, 0, 7), (1, 8, 15), (1, 18, 29)], (1, 29)),
+    ];
+    const TD2: &[CheckDigitField] = &[
+        check_digit_field("document_number", &[(1, 0, 9)], (1, 9)),
+        check_digit_field("date_of_birth", &[(1, 13, 19)], (1, 19)),
+        check_digit_field("date_of_expiry", &[(1, 21, 27)], (1, 27)),
+        check_digit_field("composite", &[(1, 0, 10), (1, 13, 20), (1, 21, 35)], (1, 35)),
+    ];
+    // The composite's positions 28-43 cover the personal number and its
+    // check digit. An all-filler personal number sums to 0, so `<` and `0`
+    // both pass.
+    const TD3: &[CheckDigitField] = &[
+        check_digit_field("document_number", &[(1, 0, 9)], (1, 9)),
+        check_digit_field("date_of_birth", &[(1, 13, 19)], (1, 19)),
+        check_digit_field("date_of_expiry", &[(1, 21, 27)], (1, 27)),
+        check_digit_field("personal_number", &[(1, 28, 42)], (1, 42)),
+        check_digit_field("composite", &[(1, 0, 10), (1, 13, 20), (1, 21, 43)], (1, 43)),
+    ];
+    const FR_CNI: &[CheckDigitField] = &[
+        check_digit_field("document_number", &[(1, 0, 12)], (1, 12)),
+        check_digit_field("date_of_birth", &[(1, 27, 33)], (1, 33)),
+        check_digit_field("composite", &[(0, 0, 36), (1, 0, 35)], (1, 35)),
+    ];
+    // Visas are laid out like TD2/TD3 without the personal number and
+    // composite checks
+    match format {
+        MrzFormat::Td1 => TD1,
+        MrzFormat::Td2 => TD2,
+        MrzFormat::Td3 => TD3,
+        MrzFormat::MrvA | MrzFormat::MrvB => &TD2[..3],
+        MrzFormat::FrCni => FR_CNI,
+    }
+}
+
+/// One check digit as read and as computed, see `validate_mrz_lines`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct MrzCheckDigitReport {
+    pub field: String, // MRZResult field name, or "composite"
+    pub line: usize,   // Where the check digit itself stands
+    pub position: usize,
+    pub present: String,       // The check digit as read
+    pub computed: Option<u32>, // Null when the data holds characters outside [A-Z0-9<]
+    pub passed: bool,
+}
+
+/// Every check digit of `lines` read as `format`, after padding or trimming
+/// them to the format's length. The parsers fill `MRZResult.check_digits`
+/// from these, so parsing and `validate_mrz_lines` always agree.
+fn check_digit_reports<S: AsRef<str>>(format: MrzFormat, lines: &[S]) -> Vec<MrzCheckDigitReport> {
+    let lines: Vec<String> =
+        lines.iter().map(|line| pad_line(line.as_ref(), format.line_length())).collect();
+    let mut reports = Vec::new();
+    for field in check_digit_fields(format) {
+        let (line, mut position) = field.digit;
+        let mut data: String = field
+            .data
+            .iter()
+            .map(|&(line, start, end)| extract_field(&lines[line], start, end))
+            .collect();
+        if format == MrzFormat::Td1 && field.name == "document_number" {
+            let (number, _) = split_td1_document_number(&lines[0]);
+            let continued = number.chars().count() - 9;
+            if continued > 0 {
+                data = number;
+                position = 15 + continued;
+            }
+        }
+
+        let present = char_at(&lines[line], position);
+        reports.push(MrzCheckDigitReport {
+            field: field.name.to_string(),
+            line,
+            position,
+            present: present.to_string(),
+            computed: compute_check_digit(&data),
+            passed: check_digit_matches(&data, present),
+        });
+    }
+    reports
+}
+
+/// A character where its position does not allow it, see `validate_mrz_lines`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct MrzCharsetViolation {
+    pub line: usize,
+    pub position: usize,
+    pub character: String,
+    pub field: Option<String>, // The field or check digit it falls in, null outside every field
+    pub allowed: String,       // "A-Z<", "0-9<" or "A-Z0-9<"
+}
+
+/// Characters out of place in `lines` read as `format`: anything outside
+/// the MRZ alphabet, digits in alphabetic fields and letters in numeric
+/// fields or check digits, one entry per position
+fn charset_violations(format: MrzFormat, lines: &[String]) -> Vec<MrzCharsetViolation> {
+    let check_digits = check_digit_reports(format, lines);
+    let mut violations = Vec::new();
+    for (line_index, line) in lines.iter().enumerate() {
+        for (position, c) in line.chars().enumerate() {
+            let field = mrz_fields(format).iter().find(|field| {
+                field.line == line_index && (field.start..field.end).contains(&position)
+            });
+            let check_digit = check_digits
+                .iter()
+                .find(|report| report.line == line_index && report.position == position);
+            let (name, kind) = match (field, check_digit) {
+                (Some(field), _) => (Some(field.name), field.kind),
+                (None, Some(report)) => (Some(report.field.as_str()), FieldKind::Numeric),
+                (None, None) => (None, FieldKind::Alphanumeric),
+            };
+            let in_alphabet = c.is_ascii_uppercase() || c.is_ascii_digit() || c == '<';
+            if !in_alphabet || !kind.allows(c) {
+                violations.push(MrzCharsetViolation {
+                    line: line_index,
+                    position,
+                    character: c.to_string(),
+                    field: name.map(str::to_string),
+                    allowed: kind.allowed().to_string(),
+                });
+            }
+        }
+    }
+    violations
+}
+
+/// Verdict of `validate_mrz_lines`, without the parsed fields
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MrzValidation {
+    pub document_type: String, // Detected format: TD1, TD2, TD3, MRV-A, MRV-B or FR_CNI
+    pub check_digits: Vec<MrzCheckDigitReport>,
+    pub charset_violations: Vec<MrzCharsetViolation>,
+    pub composite_valid: Option<bool>, // Null on visas, which have no composite check digit
+    pub length_warnings: Vec<String>,  // As in `MRZResult.length_warnings`
+    pub valid: bool, // Every check digit passes and no character is out of place
+}
+
+/// Validate MRZ lines someone else already extracted fields from, without
+/// extracting them again. Lines are cleaned like parser input (trimmed,
+/// uppercased, spaces removed) and positions index into the cleaned lines;
+/// the format is detected as in strict parsing, and check digits are
+/// computed by the code behind `MRZResult.check_digits`.
+pub fn validate_mrz_lines(lines: &[String]) -> Result<MrzValidation, MrzError> {
+    let lines: Vec<String> = lines.iter().map(|line| clean_mrz_line(line)).collect();
+    let format = MrzFormat::detect(&lines, None)?;
+
+    let check_digits = check_digit_reports(format, &lines);
+    let charset_violations = charset_violations(format, &lines);
+    let composite = check_digits.iter().find(|report| report.field == "composite");
+    let valid = charset_violations.is_empty() && check_digits.iter().all(|report| report.passed);
+    Ok(MrzValidation {
+        document_type: format.name().to_string(),
+        composite_valid: composite.map(|report| report.passed),
+        check_digits,
+        charset_violations,
+        length_warnings: length_warnings(&lines, format.line_length()),
+        valid,
+    })
+}
+
+/// Result of validating each ICAO 9303 check digit
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MRZCheckDigits {
+    pub document_number: bool,
+    pub date_of_birth: bool,
+    pub date_of_expiry: Option<bool>,  // Not present on the French CNI
+    pub personal_number: Option<bool>, // TD3 only
+    pub composite: Option<bool>,       // Not present on visas
+    pub valid: bool,                   // All of the above passed
+}
+
+impl MRZCheckDigits {
+    fn new(
+        document_number: bool,
+        date_of_birth: bool,
+        date_of_expiry: Option<bool>,
+        personal_number: Option<bool>,
+        composite: Option<bool>,
+    ) -> Self {
+        let valid = document_number
+            && date_of_birth
+            && date_of_expiry.unwrap_or(true)
+            && personal_number.unwrap_or(true)
+            && composite.unwrap_or(true);
+
+        MRZCheckDigits {
+            document_number,
+            date_of_birth,
+            date_of_expiry,
+            personal_number,
+            composite,
+            valid,
+        }
+    }
+
+    fn from_reports(reports: &[MrzCheckDigitReport]) -> Self {
+        let passed = |field: &str| {
+            reports.iter().find(|report| report.field == field).map(|report| report.passed)
+        };
+        MRZCheckDigits::new(
+            passed("document_number").unwrap_or(false),
+            passed("date_of_birth").unwrap_or(false),
+            passed("date_of_expiry"),
+            passed("personal_number"),
+            passed("composite"),
+        )
+    }
+}
+
+/// Parse MRZ text with the default options, resolving dates against today
+pub fn parse(mrz_text: &str) -> Result<MRZResult, MrzError> {
+    parse_with_options(mrz_text, CivilDate::today(), &MrzOptions::default())
+}
+
+/// Parse MRZ text; `reference` is "today" for century inference, expiry and age
+pub fn parse_with_options(
+    mrz_text: &str,
+    reference: CivilDate,
+    options: &MrzOptions,
+) -> Result<MRZResult, MrzError> {
+    log_debug!("Parsing MRZ text: {}", mrz_text);
+
+    let mrz_lines = clean_mrz_lines(mrz_text, options.min_line_length)?;
+    log_debug!("Cleaned MRZ lines: {:?}", mrz_lines);
+
+    // Parse MRZ based on format
+    let mut result = if options.strict {
+        parse_mrz_with_corrections(&mrz_lines, options)?
+    } else {
+        select_mrz_lines(&mrz_lines, options)?
+    };
+    resolve_mrz_dates(&mut result, reference);
+    if options.decode_optional_data {
+        result.optional_parsed = mrz_optional::decode(&result);
+    }
+
+    // Describe the cleaning as well, now that the original text is at hand
+    let originals: Vec<&str> = result
+        .raw_input_lines
+        .iter()
+        .map(|input| {
+            let original = mrz_text.lines().find(|line| clean_mrz_line(line) == *input);
+            original.unwrap_or(input)
+        })
+        .collect();
+    result.normalization_applied =
+        describe_normalization(&originals, &result.raw_input_lines, &result.raw_mrz);
+
+    Ok(result)
+}
+
+/// Split OCR text into uppercased, space-free lines, dropping any shorter
+/// than `min_length`
+fn clean_mrz_lines(mrz_text: &str, min_length: usize) -> Result<Vec<String>, MrzError> {
+    let lines: Vec<String> = mrz_text
+        .lines()
+        .map(clean_mrz_line)
+        .filter(|l| l.chars().count() >= min_length)
+        .collect();
+
+    if lines.is_empty() {
+        return Err(MrzError::NoMrzLines);
+    }
+    Ok(lines)
+}
+
+fn clean_mrz_line(line: &str) -> String {
+    line.trim().to_uppercase().replace(" ", "")
+}
+
+/// `MRZResult.normalization_applied`: for each line that changed, what
+/// turned the `original` text line into its cleaned `input` and then into
+/// the `normalized` line the fields are read from
+fn describe_normalization(
+    originals: &[&str],
+    inputs: &[String],
+    normalized: &[String],
+) -> Vec<String> {
+    let mut described = Vec::new();
+    for (index, (input, normalized)) in inputs.iter().zip(normalized).enumerate() {
+        let original = originals.get(index).copied().unwrap_or(input);
+        let mut steps = Vec::new();
+        if original.trim() != original {
+            steps.push("whitespace trimmed".to_string());
+        }
+        if original.chars().any(|c| c.is_lowercase()) {
+            steps.push("uppercased".to_string());
+        }
+        let spaces = original.trim().matches(' ').count();
+        if spaces > 0 {
+            steps.push(format!("{} space{} removed", spaces, if spaces == 1 { "" } else { "s" }));
+        }
+        let corrected = input.chars().zip(normalized.chars()).filter(|(a, b)| a != b).count();
+        if corrected > 0 {
+            let plural = if corrected == 1 { "" } else { "s" };
+            steps.push(format!("{} character{} corrected", corrected, plural));
+        }
+        let (found, length) = (input.chars().count(), normalized.chars().count());
+        if found < length {
+            steps.push(format!("padded from {} to {}", found, length));
+        } else if found > length {
+            steps.push(format!("trimmed from {} to {}", found, length));
+        }
+
+        if !steps.is_empty() {
+            described.push(format!("line {}: {}", index + 1, steps.join(", ")));
+        }
+    }
+    described
+}
+
+/// Parse MRZ lines as `format` (TD1, TD2, TD3, MRV-A, MRV-B or FR_CNI)
+fn parse_mrz_from_lines(lines: &[String], format: MrzFormat) -> Result<MRZResult, MrzError> {
+    let mut result = match format {
+        MrzFormat::Td1 => parse_td1(lines),
+        MrzFormat::Td2 => parse_td2(lines),
+        MrzFormat::Td3 => parse_td3(lines),
+        MrzFormat::MrvA => parse_mrva(lines),
+        MrzFormat::MrvB => parse_mrvb(lines),
+        MrzFormat::FrCni => parse_fr_cni(lines),
+    }?;
+    resolve_country_codes(&mut result);
+    score_fields(&mut result, format);
+    result.warnings = field_warnings(&result, format);
+    result.status = MrzStatus::from_warnings(&result.warnings);
+    Ok(result)
+}
+
+/// Check digit, character set and country code warnings of a parsed result;
+/// dates are checked later, by `resolve_mrz_dates`
+fn field_warnings(result: &MRZResult, format: MrzFormat) -> Vec<MrzWarning> {
+    use MrzWarningCode::*;
+    let mut warnings = Vec::new();
+
+    let checks = &result.check_digits;
+    let fields = [
+        ("document_number", Some(checks.document_number), &result.document_number),
+        ("date_of_birth", Some(checks.date_of_birth), &result.date_of_birth),
+        ("date_of_expiry", checks.date_of_expiry, &result.date_of_expiry),
+        ("personal_number", checks.personal_number, &result.personal_number),
+    ];
+    for (field, passed, value) in fields {
+        if passed == Some(false) {
+            warnings.push(MrzWarning::new(CheckDigitMismatch, field, value.as_str()));
+        }
+    }
+    if let (Some(false), Some((line, pos))) = (checks.composite, format.composite_check()) {
+        let digit = char_at(&result.raw_mrz[line], pos);
+        warnings.push(MrzWarning::new(CheckDigitMismatch, "composite", digit));
+    }
+
+    for field in mrz_fields(format) {
+        let value = extract_field(&result.raw_mrz[field.line], field.start, field.end);
+        if !value.chars().all(|c| field.kind.allows(c)) {
+            warnings.push(MrzWarning::new(InvalidCharset, field.name, value));
+        }
+    }
+
+    if result.document_number_plausible == Some(false) {
+        let number = &result.document_number;
+        warnings.push(MrzWarning::new(ImplausibleDocumentNumber, "document_number", number));
+    }
+    if result.sex == "unknown" {
+        warnings.push(MrzWarning::new(InvalidSex, "sex", &result.sex_raw));
+    }
+    if !result.issuing_country_valid {
+        warnings.push(MrzWarning::new(UnknownCountry, "issuing_country", &result.issuing_country));
+    }
+    if !result.nationality_valid {
+        warnings.push(MrzWarning::new(UnknownCountry, "nationality", &result.nationality));
+    }
+    warnings
+}
+
+/// Normalize the country fields (`D<<` -> `D`), look them up and check the
+/// document number against the issuing country's format
+fn resolve_country_codes(result: &mut MRZResult) {
+    result.issuing_country = result.issuing_country.trim_end_matches('<').to_string();
+    result.nationality = result.nationality.trim_end_matches('<').to_string();
+
+    let issuing_country_name = countries::country_name(&result.issuing_country);
+    let nationality_name = countries::country_name(&result.nationality);
+    result.issuing_country_valid = issuing_country_name.is_some();
+    result.issuing_country_name = issuing_country_name.map(str::to_string);
+    result.nationality_valid = nationality_name.is_some();
+    result.nationality_name = nationality_name.map(str::to_string);
+    result.document_number_plausible = document_numbers::plausible(
+        &result.issuing_country,
+        &result.document_code,
+        &result.document_number,
+    );
+}
+
+/// Weight of each field in `MRZResult.confidence`. The composite check
+/// digit and the document number carry the most: a wrong number makes the
+/// whole scan useless, and the composite check covers every other field.
+const FIELD_WEIGHTS: &[(&str, f32)] = &[
+    ("document_number", 3.0),
+    ("composite", 3.0),
+    ("date_of_birth", 2.0),
+    ("date_of_expiry", 2.0),
+    ("personal_number", 1.0),
+    ("issuing_country", 1.0),
+    ("nationality", 1.0),
+    ("names", 1.0),
+];
+
+/// Fill `field_confidence` and `confidence` from the redundancy in the MRZ:
+/// - check digit protected fields score 1 when the digit passes, else 0;
+///   the document number scores 0.5 when it passes but does not fit its
+///   country's format, and dates score 0 when they are not calendar dates
+/// - country codes score 1 when they are in the ICAO table, else 0
+/// - names score the fraction of their non-filler characters that are
+///   letters, and 0 when they hold no letter at all
+///
+/// `confidence` is the mean of the field scores weighted by `FIELD_WEIGHTS`,
+/// over the fields the format has.
+fn score_fields(result: &mut MRZResult, format: MrzFormat) {
+    let checks = &result.check_digits;
+    let score = |passed: bool| if passed { 1.0 } else { 0.0 };
+    let date = |passed: bool, value: &str| score(passed && is_mrz_date(value));
+
+    let mut fields = HashMap::new();
+    let plausibility = if result.document_number_plausible == Some(false) { 0.5 } else { 1.0 };
+    fields.insert("document_number", score(checks.document_number) * plausibility);
+    fields.insert("date_of_birth", date(checks.date_of_birth, &result.date_of_birth));
+    if let Some(passed) = checks.date_of_expiry {
+        fields.insert("date_of_expiry", date(passed, &result.date_of_expiry));
+    }
+    if let Some(passed) = checks.personal_number {
+        fields.insert("personal_number", score(passed));
+    }
+    if let Some(passed) = checks.composite {
+        fields.insert("composite", score(passed));
+    }
+    fields.insert("issuing_country", score(result.issuing_country_valid));
+    fields.insert("nationality", score(result.nationality_valid));
+
+    // The French CNI splits the name over two fields
+    let names: String = mrz_fields(format)
+        .iter()
+        .filter(|field| field.name == "names")
+        .map(|field| extract_field(&result.raw_mrz[field.line], field.start, field.end))
+        .collect();
+    let letters = names.chars().filter(|c| c.is_ascii_uppercase()).count();
+    let written = names.chars().filter(|&c| c != '<').count();
+    fields.insert("names", if letters == 0 { 0.0 } else { letters as f32 / written as f32 });
+
+    let (weighted, total) = FIELD_WEIGHTS
+        .iter()
+        .filter_map(|(field, weight)| Some((fields.get(field)? * weight, weight)))
+        .fold((0.0, 0.0), |(sum, total), (value, weight)| (sum + value, total + weight));
+    result.confidence = weighted / total;
+    result.field_confidence =
+        fields.into_iter().map(|(field, value)| (field.to_string(), value)).collect();
+}
+
+/// Whether a `YYMMDD` field is a calendar date in either century
+fn is_mrz_date(value: &str) -> bool {
+    CivilDate::from_yymmdd(value, |yy| 2000 + yy).is_some()
+        || CivilDate::from_yymmdd(value, |yy| 1900 + yy).is_some()
+}
+
+/// Find the MRZ among noisy OCR lines: keep lines that look like MRZ text,
+/// then try adjacent pairs/triples of matching length, best-scoring first,
+/// until one parses with passing check digits. Falls back to the most
+/// confident parse, or to parsing every line as-is when nothing fits.
+fn select_mrz_lines(lines: &[String], options: &MrzOptions) -> Result<MRZResult, MrzError> {
+    let candidates: Vec<(&String, f32)> = lines
+        .iter()
+        .map(|line| (line, mrz_line_score(line)))
+        .filter(|&(_, score)| score >= MIN_MRZ_LINE_SCORE)
+        .collect();
+
+    let mut windows: Vec<(f32, Vec<String>)> = Vec::new();
+    for (count, length) in [(3, 30), (2, 36), (2, 44)] {
+        for window in candidates.windows(count) {
+            let fits = window
+                .iter()
+                .all(|(line, _)| line.chars().count().abs_diff(length) <= options.length_tolerance);
+            if fits {
+                let score = window.iter().map(|&(_, score)| score).sum::<f32>() / count as f32;
+                windows.push((score, window.iter().map(|&(line, _)| line.clone()).collect()));
+            }
+        }
+    }
+    windows.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let mut best: Option<MRZResult> = None;
+    for (_, window) in &windows {
+        let Ok(result) = parse_mrz_with_corrections(window, options) else {
+            continue;
+        };
+        if result.check_digits.valid {
+            return Ok(result);
+        }
+        if best.as_ref().is_none_or(|b| result.confidence > b.confidence) {
+            best = Some(result);
+        }
+    }
+
+    match best {
+        Some(result) => Ok(result),
+        None => parse_mrz_with_corrections(lines, options),
+    }
+}
+
+/// Lines scoring below this are not considered MRZ candidates
+const MIN_MRZ_LINE_SCORE: f32 = 0.7;
+
+/// How much a cleaned OCR line looks like an MRZ line, from 0 to 1:
+/// mostly `[A-Z0-9<]` density, then closeness to a canonical length,
+/// then the presence of `<` filler (rare in ordinary text)
+fn mrz_line_score(line: &str) -> f32 {
+    let length = line.chars().count();
+    if length == 0 {
+        return 0.0;
+    }
+    let valid = line
+        .chars()
+        .filter(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || *c == '<')
+        .count();
+    let fillers = line.chars().filter(|&c| c == '<').count();
+    let distance = [30, 36, 44].iter().map(|&l| length.abs_diff(l)).min().unwrap_or(0);
+
+    let density = valid as f32 / length as f32;
+    let length_score = 1.0 - distance.min(10) as f32 / 10.0;
+    let filler_score = fillers.min(4) as f32 / 4.0;
+    0.6 * density + 0.25 * length_score + 0.15 * filler_score
+}
+
+/// Detect the format, then parse with OCR corrections. TD1 offsets fit any
+/// three lines, so a TD1 reading also goes through `check_td1_layout`.
+fn parse_mrz_with_corrections(
+    lines: &[String],
+    options: &MrzOptions,
+) -> Result<MRZResult, MrzError> {
+    let format = MrzFormat::detect(lines, options.tolerance())?;
+    check_mrz_characters(lines)?;
+    let result = parse_format_with_corrections(lines, format, options)?;
+    if format == MrzFormat::Td1 {
+        return Ok(check_td1_layout(result, lines, options));
+    }
+    Ok(result)
+}
+
+/// Fix OCR lookalikes field by field, then parse. The composite check digit
+/// is only known after parsing, so it gets a second pass of its own.
+fn parse_format_with_corrections(
+    lines: &[String],
+    format: MrzFormat,
+    options: &MrzOptions,
+) -> Result<MRZResult, MrzError> {
+    let length_warnings = length_warnings(lines, format.line_length());
+    let level = options.correction_level;
+    if level == CorrectionLevel::Off {
+        let mut result = parse_mrz_from_lines(lines, format)?;
+        result.length_warnings = length_warnings;
+        keep_input_lines(&mut result, lines);
+        return Ok(result);
+    }
+
+    let mut chars: Vec<Vec<char>> = lines
+        .iter()
+        .map(|line| pad_line(line, format.line_length()).chars().collect())
+        .collect();
+    let mut corrections = Vec::new();
+    for field in mrz_fields(format) {
+        // A `<` check digit marks a long TD1 number running on into the
+        // optional data, which its own check digit follows
+        let long_number = format == MrzFormat::Td1 && field.name == "document_number";
+        if long_number && chars[0][14] == '<' {
+            continue;
+        }
+        correct_mrz_field(&mut chars[field.line], field, level, &mut corrections);
+    }
+
+    let to_lines = |chars: &[Vec<char>]| -> Vec<String> {
+        chars.iter().map(|line| line.iter().collect()).collect()
+    };
+    let mut result = parse_mrz_from_lines(&to_lines(&chars), format)?;
+
+    let composite = (result.check_digits.composite, format.composite_check());
+    if let (Some(false), Some((line, pos))) = composite {
+        let found = chars[line][pos];
+        if let Some(digit) = letter_to_digit(found) {
+            chars[line][pos] = digit;
+            let retry = parse_mrz_from_lines(&to_lines(&chars), format)?;
+            if level == CorrectionLevel::Aggressive || retry.check_digits.composite == Some(true) {
+                corrections.push(describe_correction("composite", line, pos, found, digit));
+                result = retry;
+            }
+        }
+    }
+
+    result.corrections = corrections;
+    result.length_warnings = length_warnings;
+    keep_input_lines(&mut result, lines);
+    Ok(result)
+}
+
+/// Sanity checks a TD1 reading fails, by name: a document code not starting
+/// with `A`, `C` or `I`, an unrecognized sex, dates that are not calendar
+/// dates, unknown country codes and failing check digits
+fn td1_sanity_failures(result: &MRZResult) -> Vec<&'static str> {
+    let dates_valid = is_mrz_date(&result.date_of_birth) && is_mrz_date(&result.date_of_expiry);
+    let checks = [
+        ("document_code", result.document_code.starts_with(['A', 'C', 'I'])),
+        ("sex", result.sex != "unknown"),
+        ("dates", dates_valid),
+        ("countries", result.issuing_country_valid && result.nationality_valid),
+        ("check_digits", result.check_digits.valid),
+    ];
+    checks.iter().filter(|(_, passed)| !passed).map(|&(check, _)| check).collect()
+}
+
+/// When two or more `td1_sanity_failures` fail, the TD1 offsets were most
+/// likely applied to the wrong text: try the `td1_alternates` and keep the
+/// one failing the fewest checks, if that is at most one. Otherwise the
+/// TD1 reading stands, with an `UnexpectedLayout` warning listing the
+/// failed checks.
+fn check_td1_layout(result: MRZResult, lines: &[String], options: &MrzOptions) -> MRZResult {
+    let failures = td1_sanity_failures(&result);
+    if failures.len() < 2 {
+        return result;
+    }
+
+    let mut best: Option<(usize, MRZResult)> = None;
+    for (alternate, description) in td1_alternates(lines) {
+        let Ok(mut parsed) = parse_format_with_corrections(&alternate, MrzFormat::Td1, options)
+        else {
+            continue;
+        };
+        let failed = td1_sanity_failures(&parsed).len();
+        if failed < 2 && best.as_ref().is_none_or(|(fewest, _)| failed < *fewest) {
+            parsed.corrections.insert(0, description);
+            best = Some((failed, parsed));
+            if failed == 0 {
+                break;
+            }
+        }
+    }
+    if let Some((_, parsed)) = best {
+        return parsed;
+    }
+
+    use MrzWarningCode::UnexpectedLayout;
+    let mut result = result;
+    let warning = MrzWarning::new(UnexpectedLayout, "document_type", failures.join(", "));
+    result.warnings.push(warning);
+    result.status = MrzStatus::from_warnings(&result.warnings);
+    result
+}
+
+/// Other readings of three TD1-sized lines, each with its
+/// `MRZResult.corrections` entry: the lines in another order, as OCR may
+/// return them, and a line 1 or 2 one character off realigned by inserting
+/// a filler or digit, or removing a character, wherever it fits. Line 3
+/// holds only the names, which have no fixed offsets to lose.
+fn td1_alternates(lines: &[String]) -> Vec<(Vec<String>, String)> {
+    let mut alternates = Vec::new();
+    for order in [[0, 2, 1], [1, 0, 2], [1, 2, 0], [2, 0, 1], [2, 1, 0]] {
+        let reordered = order.iter().map(|&index| lines[index].clone()).collect();
+        let positions: Vec<String> = order.iter().map(|index| (index + 1).to_string()).collect();
+        alternates.push((reordered, format!("layout: lines reordered {}", positions.join(", "))));
+    }
+
+    for index in 0..2 {
+        let line: Vec<char> = lines[index].chars().collect();
+        let mut realigned: Vec<String> = Vec::new();
+        let mut push = |chars: Vec<char>, description: String| {
+            let text: String = chars.into_iter().collect();
+            if !realigned.contains(&text) {
+                realigned.push(text.clone());
+                let mut alternate = lines.to_vec();
+                alternate[index] = text;
+                alternates.push((alternate, description));
+            }
+        };
+        // Padding and trimming at the end already happen in `parse_td1`
+        if line.len() == 29 {
+            for pos in 0..line.len() {
+                for inserted in "<0123456789".chars() {
+                    let mut chars = line.clone();
+                    chars.insert(pos, inserted);
+                    let description = format!(
+                        "layout: '{}' inserted at line {}, position {}",
+                        inserted,
+                        index + 1,
+                        pos + 1
+                    );
+                    push(chars, description);
+                }
+            }
+        } else if line.len() == 31 {
+            for pos in 0..line.len() - 1 {
+                let mut chars = line.clone();
+                let removed = chars.remove(pos);
+                let description = format!(
+                    "layout: '{}' removed at line {}, position {}",
+                    removed,
+                    index + 1,
+                    pos + 1
+                );
+                push(chars, description);
+            }
+        }
+    }
+    alternates
+}
+
+/// Record the lines as received next to the normalized `raw_mrz`;
+/// `parse_with_options` re-describes them against the original text
+fn keep_input_lines(result: &mut MRZResult, lines: &[String]) {
+    let originals: Vec<&str> = lines.iter().map(String::as_str).collect();
+    result.normalization_applied = describe_normalization(&originals, lines, &result.raw_mrz);
+    result.raw_input_lines = lines.to_vec();
+}
+
+/// `MRZResult.length_warnings`: one entry per line that is not `length`
+/// characters long and so gets padded with `<` or trimmed before parsing
+fn length_warnings(lines: &[String], length: usize) -> Vec<String> {
+    let mut warnings = Vec::new();
+    for (index, line) in lines.iter().enumerate() {
+        let found = line.chars().count();
+        if found < length {
+            warnings.push(format!(
+                "line {}: {} of {} characters, padded with {} '<'",
+                index + 1,
+                found,
+                length,
+                length - found
+            ));
+        } else if found > length {
+            warnings.push(format!(
+                "line {}: {} of {} characters, trimmed by {}",
+                index + 1,
+                found,
+                length,
+                found - length
+            ));
+        }
+    }
+    warnings
+}
+
+/// Reject lines containing anything outside `[A-Z0-9<]`, listing each
+/// offending character once, before any field is sliced out
+fn check_mrz_characters(lines: &[String]) -> Result<(), MrzError> {
+    let mut invalid: Vec<char> = Vec::new();
+    for c in lines.iter().flat_map(|line| line.chars()) {
+        let valid = c.is_ascii_uppercase() || c.is_ascii_digit() || c == '<';
+        if !valid && !invalid.contains(&c) {
+            invalid.push(c);
+        }
+    }
+    if invalid.is_empty() {
+        Ok(())
+    } else {
+        Err(MrzError::InvalidMrzCharacters(invalid))
+    }
+}
+
+/// Correctable fields of each format, in ICAO 9303 positions
+fn mrz_fields(format: MrzFormat) -> &'static [MrzField] {
+    use FieldKind::*;
+    const TD1: &[MrzField] = &[
+        MrzField::new("issuing_country", 0, 2, 5, None, Alphabetic),
+        MrzField::new("document_number", 0, 5, 14, Some(14), Alphanumeric),
+        MrzField::new("date_of_birth", 1, 0, 6, Some(6), Numeric),
+        MrzField::new("date_of_expiry", 1, 8, 14, Some(14), Numeric),
+        MrzField::new("nationality", 1, 15, 18, None, Alphabetic),
+        MrzField::new("names", 2, 0, 30, None, Alphabetic),
+    ];
+    const TD2: &[MrzField] = &[
+        MrzField::new("issuing_country", 0, 2, 5, None, Alphabetic),
+        MrzField::new("names", 0, 5, 36, None, Alphabetic),
+        MrzField::new("document_number", 1, 0, 9, Some(9), Alphanumeric),
+        MrzField::new("nationality", 1, 10, 13, None, Alphabetic),
+        MrzField::new("date_of_birth", 1, 13, 19, Some(19), Numeric),
+        MrzField::new("date_of_expiry", 1, 21, 27, Some(27), Numeric),
+    ];
+    const TD3: &[MrzField] = &[
+        MrzField::new("issuing_country", 0, 2, 5, None, Alphabetic),
+        MrzField::new("names", 0, 5, 44, None, Alphabetic),
+        MrzField::new("document_number", 1, 0, 9, Some(9), Alphanumeric),
+        MrzField::new("nationality", 1, 10, 13, None, Alphabetic),
+        MrzField::new("date_of_birth", 1, 13, 19, Some(19), Numeric),
+        MrzField::new("date_of_expiry", 1, 21, 27, Some(27), Numeric),
+        MrzField::new("personal_number", 1, 28, 42, Some(42), Alphanumeric),
+    ];
+    // The department code (line 1, 30..36) may hold the letters of 2A/2B
+    const FR_CNI: &[MrzField] = &[
+        MrzField::new("issuing_country", 0, 2, 5, None, Alphabetic),
+        MrzField::new("names", 0, 5, 30, None, Alphabetic),
+        MrzField::new("document_number", 1, 0, 12, Some(12), Alphanumeric),
+        MrzField::new("names", 1, 13, 27, None, Alphabetic),
+        MrzField::new("date_of_birth", 1, 27, 33, Some(33), Numeric),
+    ];
+    match format {
+        MrzFormat::Td1 => TD1,
+        MrzFormat::Td2 | MrzFormat::MrvB => TD2,
+        MrzFormat::Td3 => TD3,
+        MrzFormat::MrvA => &TD3[..TD3.len() - 1],
+        MrzFormat::FrCni => FR_CNI,
+    }
+}
+
+/// Apply the lookalike substitutions appropriate to one field's kind
+fn correct_mrz_field(
+    line: &mut [char],
+    field: &MrzField,
+    level: CorrectionLevel,
+    corrections: &mut Vec<String>,
+) {
+    let end = field.end.min(line.len());
+    if field.start >= end {
+        return;
+    }
+    let passes = |line: &[char]| match field.check {
+        Some(pos) => check_digit_matches(
+            &line[field.start..end].iter().collect::<String>(),
+            line.get(pos).copied().unwrap_or('<'),
+        ),
+        None => true,
+    };
+
+    let mut candidate = line.to_vec();
+    match field.kind {
+        // Digits never appear in names or country codes
+        FieldKind::Alphabetic => {
+            for c in &mut candidate[field.start..end] {
+                *c = digit_to_letter(*c).unwrap_or(*c);
+            }
+        }
+        FieldKind::Numeric => {
+            for pos in (field.start..end).chain(field.check) {
+                to_digit(&mut candidate, pos);
+            }
+            if level == CorrectionLevel::Safe && (passes(line) || !passes(&candidate)) {
+                return;
+            }
+        }
+        // Letters and digits are both legal, so only a passing check digit
+        // can tell which one was meant: try one substitution at a time
+        FieldKind::Alphanumeric => {
+            if passes(line) {
+                return;
+            }
+            if let Some(pos) = field.check {
+                to_digit(&mut candidate, pos);
+            }
+            let fixed = (field.start..end).find_map(|pos| {
+                let swapped = letter_to_digit(line[pos]).or_else(|| digit_to_letter(line[pos]))?;
+                let mut attempt = candidate.clone();
+                attempt[pos] = swapped;
+                passes(&attempt).then_some(attempt)
+            });
+            match fixed {
+                Some(attempt) => candidate = attempt,
+                None if passes(&candidate) => {}
+                None => return,
+            }
+        }
+    }
+
+    for (pos, (found, &replacement)) in line.iter_mut().zip(&candidate).enumerate() {
+        if *found != replacement {
+            corrections.push(describe_correction(field.name, field.line, pos, *found, replacement));
+            *found = replacement;
+        }
+    }
+}
+
+/// Replace a letter lookalike at `pos` with its digit, if the line is long enough
+fn to_digit(line: &mut [char], pos: usize) {
+    if let Some(c) = line.get_mut(pos) {
+        *c = letter_to_digit(*c).unwrap_or(*c);
+    }
+}
+
+/// Human-readable audit entry for `MRZResult.corrections`
+fn describe_correction(field: &str, line: usize, pos: usize, from: char, to: char) -> String {
+    format!("{}: '{}' -> '{}' at line {}, position {}", field, from, to, line + 1, pos + 1)
+}
+
+/// Digit an OCR engine commonly mistakes for this letter
+fn letter_to_digit(c: char) -> Option<char> {
+    match c {
+        'O' => Some('0'),
+        'I' => Some('1'),
+        'Z' => Some('2'),
+        'S' => Some('5'),
+        'B' => Some('8'),
+        _ => None,
+    }
+}
+
+/// Letter an OCR engine commonly mistakes for this digit
+fn digit_to_letter(c: char) -> Option<char> {
+    match c {
+        '0' => Some('O'),
+        '1' => Some('I'),
+        '2' => Some('Z'),
+        '5' => Some('S'),
+        '8' => Some('B'),
+        _ => None,
+    }
+}
+
+/// Parse TD1 format (ID cards: 3 lines of 30 characters)
+fn parse_td1(lines: &[String]) -> Result<MRZResult, MrzError> {
+    if lines.len() != 3 {
+        return Err(MrzError::MrzFormatUnknown("TD1 requires 3 lines".to_string()));
+    }
+
+    let line1 = pad_line(&lines[0], 30);
+    let line2 = pad_line(&lines[1], 30);
+    let line3 = pad_line(&lines[2], 30);
+
+    let (document_number, optional_data) = split_td1_document_number(&line1);
+    let names = extract_names(&line3);
+
+    let (surname, given_names) = name_spans(&line3, 2, 0, 30);
+    let mut document_number_spans = span(0, 5, 14);
+    let continued = document_number.chars().count().saturating_sub(9);
+    if continued > 0 {
+        document_number_spans.extend(span(0, 15, 15 + continued));
+    }
+    let field_spans = MRZFieldSpans {
+        document_code: span(0, 0, 2),
+        issuing_country: span(0, 2, 5),
+        document_number: document_number_spans,
+        date_of_birth: span(1, 0, 6),
+        sex: span(1, 7, 8),
+        date_of_expiry: span(1, 8, 14),
+        nationality: span(1, 15, 18),
+        surname,
+        given_names,
+        optional_data: span(0, 30 - optional_data.chars().count(), 30),
+        personal_number: Vec::new(),
+    };
+
+    let reports = check_digit_reports(MrzFormat::Td1, &[&line1, &line2, &line3]);
+    let check_digits = MRZCheckDigits::from_reports(&reports);
+
+    Ok(MRZResult {
+        document_type: "TD1".to_string(),
+        document_code: document_code(&line1),
+        document_category: document_category(&line1).to_string(),
+        document_number: document_number.trim_end_matches('<').to_string(),
+        document_number_plausible: None,
+        issuing_country: extract_field(&line1, 2, 5).to_string(),
+        date_of_birth: extract_field(&line2, 0, 6).to_string(),
+        sex: normalize_sex(&extract_field(&line2, 7, 8)).to_string(),
+        sex_raw: extract_field(&line2, 7, 8),
+        date_of_expiry: extract_field(&line2, 8, 14).to_string(),
+        nationality: extract_field(&line2, 15, 18).to_string(),
+        optional_data: optional_data.trim_end_matches('<').to_string(),
+        personal_number: String::new(),
+        optional_parsed: None,
+        surname: names.surname,
+        given_names: names.given_names.join(" "),
+        given_names_list: names.given_names,
+        name_truncated: names.truncated,
+        issuing_country_valid: false,
+        issuing_country_name: None,
+        nationality_valid: false,
+        nationality_name: None,
+        raw_input_lines: Vec::new(),
+        normalization_applied: Vec::new(),
+        raw_mrz: vec![line1, line2, line3],
+        confidence: 0.0,
+        field_confidence: HashMap::new(),
+        check_digits,
+        date_of_birth_iso: None,
+        date_of_expiry_iso: None,
+        warnings: Vec::new(),
+        status: MrzStatus::Valid,
+        is_expired: None,
+        age_years: None,
+        corrections: Vec::new(),
+        length_warnings: Vec::new(),
+        field_spans,
+    })
+}
+
+/// Split the TD1 document number from the optional data. Numbers longer
+/// than 9 characters put `<` in the check digit slot and continue into the
+/// optional data, followed by the real check digit.
+fn split_td1_document_number(line1: &str) -> (String, String) {
+    let number = extract_field(line1, 5, 14);
+    let optional = extract_field(line1, 15, 30);
+    let continuation: String = optional.chars().take_while(|&c| c != '<').collect();
+
+    let continued = continuation.chars().count();
+    if char_at(line1, 14) != '<' || continued < 2 {
+        return (number, optional);
+    }
+
+    let continuation = continuation.chars().take(continued - 1);
+    let full_number: String = number.chars().chain(continuation).collect();
+    let rest: String = optional.chars().skip(continued).collect();
+    (full_number, rest.trim_start_matches('<').to_string())
+}
+
+/// Parse TD2 format (Official documents: 2 lines of 36 characters)
+fn parse_td2(lines: &[String]) -> Result<MRZResult, MrzError> {
+    if lines.len() != 2 {
+        return Err(MrzError::MrzFormatUnknown("TD2 requires 2 lines".to_string()));
+    }
+
+    let line1 = pad_line(&lines[0], 36);
+    let line2 = pad_line(&lines[1], 36);
+
+    let names = extract_names(&extract_field(&line1, 5, 36));
+
+    let reports = check_digit_reports(MrzFormat::Td2, &[&line1, &line2]);
+    let check_digits = MRZCheckDigits::from_reports(&reports);
+
+    let field_spans = MRZFieldSpans::two_line(&line1, 36, 35);
+
+    Ok(MRZResult {
+        document_type: "TD2".to_string(),
+        document_code: document_code(&line1),
+        document_category: document_category(&line1).to_string(),
+        issuing_country: extract_field(&line1, 2, 5).to_string(),
+        surname: names.surname,
+        given_names: names.given_names.join(" "),
+        given_names_list: names.given_names,
+        name_truncated: names.truncated,
+        document_number: extract_field(&line2, 0, 9).trim_end_matches('<').to_string(),
+        document_number_plausible: None,
+        nationality: extract_field(&line2, 10, 13).to_string(),
+        date_of_birth: extract_field(&line2, 13, 19).to_string(),
+        sex: normalize_sex(&extract_field(&line2, 20, 21)).to_string(),
+        sex_raw: extract_field(&line2, 20, 21),
+        date_of_expiry: extract_field(&line2, 21, 27).to_string(),
+        optional_data: extract_field(&line2, 28, 35).trim_end_matches('<').to_string(),
+        personal_number: String::new(),
+        optional_parsed: None,
+        issuing_country_valid: false,
+        issuing_country_name: None,
+        nationality_valid: false,
+        nationality_name: None,
+        raw_input_lines: Vec::new(),
+        normalization_applied: Vec::new(),
+        raw_mrz: vec![line1, line2],
+        confidence: 0.0,
+        field_confidence: HashMap::new(),
+        check_digits,
+        date_of_birth_iso: None,
+        date_of_expiry_iso: None,
+        warnings: Vec::new(),
+        status: MrzStatus::Valid,
+        is_expired: None,
+        age_years: None,
+        corrections: Vec::new(),
+        length_warnings: Vec::new(),
+        field_spans,
+    })
+}
+
+/// Parse the pre-2021 French ID card (CNI): 2 lines of 36 characters with
+/// the surname and issuing department/office on line 1, then the card
+/// number, given names, birth date and sex. There is no nationality (always
+/// French) or expiry date; a final check digit covers both lines.
+fn parse_fr_cni(lines: &[String]) -> Result<MRZResult, MrzError> {
+    if lines.len() != 2 {
+        return Err(MrzError::MrzFormatUnknown("FR_CNI requires 2 lines".to_string()));
+    }
+
+    let line1 = pad_line(&lines[0], 36);
+    let line2 = pad_line(&lines[1], 36);
+
+    // Separate fields, where any run of filler separates words:
+    // `DE<LA<TOUR`, `JEAN<<PIERRE`
+    let surname_field = extract_field(&line1, 5, 30);
+    let given_field = extract_field(&line2, 13, 27);
+    let surname = mrz_words(&surname_field).join(" ");
+    let given_names = mrz_words(&given_field);
+    let name_truncated = name_truncated(&surname_field) || name_truncated(&given_field);
+    let document_number = extract_field(&line2, 0, 12).trim_end_matches('<').to_string();
+
+    let reports = check_digit_reports(MrzFormat::FrCni, &[&line1, &line2]);
+    let check_digits = MRZCheckDigits::from_reports(&reports);
+
+    let name_span = |line: usize, text: &str, start: usize, end: usize| {
+        let length = extract_field(text, start, end).trim_end_matches('<').chars().count();
+        if length == 0 {
+            Vec::new()
+        } else {
+            span(line, start, start + length)
+        }
+    };
+    let field_spans = MRZFieldSpans {
+        document_code: span(0, 0, 2),
+        issuing_country: span(0, 2, 5),
+        document_number: span(1, 0, 12),
+        date_of_birth: span(1, 27, 33),
+        sex: span(1, 34, 35),
+        date_of_expiry: Vec::new(),
+        nationality: Vec::new(),
+        surname: name_span(0, &line1, 5, 30),
+        given_names: name_span(1, &line2, 13, 27),
+        optional_data: span(0, 30, 36),
+        personal_number: Vec::new(),
+    };
+
+    Ok(MRZResult {
+        document_type: "FR_CNI".to_string(),
+        document_code: document_code(&line1),
+        document_category: document_category(&line1).to_string(),
+        issuing_country: extract_field(&line1, 2, 5),
+        surname,
+        given_names: given_names.join(" "),
+        given_names_list: given_names,
+        name_truncated,
+        document_number,
+        document_number_plausible: None,
+        nationality: "FRA".to_string(),
+        date_of_birth: extract_field(&line2, 27, 33),
+        sex: normalize_sex(&extract_field(&line2, 34, 35)).to_string(),
+        sex_raw: extract_field(&line2, 34, 35),
+        date_of_expiry: String::new(),
+        // Issuing department (3 characters) and office (3 digits)
+        optional_data: extract_field(&line1, 30, 36).trim_end_matches('<').to_string(),
+        personal_number: String::new(),
+        optional_parsed: None,
+        issuing_country_valid: false,
+        issuing_country_name: None,
+        nationality_valid: false,
+        nationality_name: None,
+        raw_input_lines: Vec::new(),
+        normalization_applied: Vec::new(),
+        raw_mrz: vec![line1, line2],
+        confidence: 0.0,
+        field_confidence: HashMap::new(),
+        check_digits,
+        date_of_birth_iso: None,
+        date_of_expiry_iso: None,
+        warnings: Vec::new(),
+        status: MrzStatus::Valid,
+        is_expired: None,
+        age_years: None,
+        corrections: Vec::new(),
+        length_warnings: Vec::new(),
+        field_spans,
+    })
+}
+
+/// Parse TD3 format (Passports: 2 lines of 44 characters)
+fn parse_td3(lines: &[String]) -> Result<MRZResult, MrzError> {
+    if lines.len() != 2 {
+        return Err(MrzError::MrzFormatUnknown("TD3 requires 2 lines".to_string()));
+    }
+
+    let line1 = pad_line(&lines[0], 44);
+    let line2 = pad_line(&lines[1], 44);
+
+    let names = extract_names(&extract_field(&line1, 5, 44));
+    let personal_number = extract_field(&line2, 28, 42);
+
+    let reports = check_digit_reports(MrzFormat::Td3, &[&line1, &line2]);
+    let check_digits = MRZCheckDigits::from_reports(&reports);
+
+    let field_spans = MRZFieldSpans {
+        personal_number: span(1, 28, 42),
+        ..MRZFieldSpans::two_line(&line1, 44, 42)
+    };
+
+    Ok(MRZResult {
+        document_type: "TD3".to_string(),
+        document_code: document_code(&line1),
+        document_category: document_category(&line1).to_string(),
+        issuing_country: extract_field(&line1, 2, 5).to_string(),
+        surname: names.surname,
+        given_names: names.given_names.join(" "),
+        given_names_list: names.given_names,
+        name_truncated: names.truncated,
+        document_number: extract_field(&line2, 0, 9).trim_end_matches('<').to_string(),
+        document_number_plausible: None,
+        nationality: extract_field(&line2, 10, 13).to_string(),
+        date_of_birth: extract_field(&line2, 13, 19).to_string(),
+        sex: normalize_sex(&extract_field(&line2, 20, 21)).to_string(),
+        sex_raw: extract_field(&line2, 20, 21),
+        date_of_expiry: extract_field(&line2, 21, 27).to_string(),
+        optional_data: personal_number.trim_end_matches('<').to_string(),
+        personal_number: personal_number.trim_end_matches('<').to_string(),
+        optional_parsed: None,
+        issuing_country_valid: false,
+        issuing_country_name: None,
+        nationality_valid: false,
+        nationality_name: None,
+        raw_input_lines: Vec::new(),
+        normalization_applied: Vec::new(),
+        raw_mrz: vec![line1, line2],
+        confidence: 0.0,
+        field_confidence: HashMap::new(),
+        check_digits,
+        date_of_birth_iso: None,
+        date_of_expiry_iso: None,
+        warnings: Vec::new(),
+        status: MrzStatus::Valid,
+        is_expired: None,
+        age_years: None,
+        corrections: Vec::new(),
+        length_warnings: Vec::new(),
+        field_spans,
+    })
+}
+
+/// Fill in the ISO date fields, expiry and age from the raw YYMMDD values.
+/// Birth dates in the future roll back a century; expiry dates are assumed
+/// 20xx unless that lands more than 50 years after `reference`.
+fn resolve_mrz_dates(result: &mut MRZResult, reference: CivilDate) {
+    let date_of_birth = match CivilDate::from_yymmdd(&result.date_of_birth, |yy| {
+        if 2000 + yy > reference.year {
+            1900 + yy
+        } else {
+            2000 + yy
+        }
+    }) {
+        Some(date) if date > reference => date.minus_century(),
+        other => other,
+    };
+
+    let date_of_expiry = CivilDate::from_yymmdd(&result.date_of_expiry, |yy| {
+        if 2000 + yy > reference.year + 50 {
+            1900 + yy
+        } else {
+            2000 + yy
+        }
+    });
+
+    result.date_of_birth_iso = date_of_birth.map(|date| date.to_iso());
+    result.date_of_expiry_iso = date_of_expiry.map(|date| date.to_iso());
+
+    // A document is still valid on its expiry date
+    result.is_expired = date_of_expiry.map(|date| date < reference);
+    result.age_years = date_of_birth.map(|date| date.years_until(reference));
+
+    if date_of_birth.is_none() {
+        let warning =
+            MrzWarning::new(MrzWarningCode::InvalidDate, "date_of_birth", &result.date_of_birth);
+        result.warnings.push(warning);
+    }
+    // The French CNI has no expiry date to parse
+    if date_of_expiry.is_none() && result.check_digits.date_of_expiry.is_some() {
+        let warning =
+            MrzWarning::new(MrzWarningCode::InvalidDate, "date_of_expiry", &result.date_of_expiry);
+        result.warnings.push(warning);
+    }
+    result.status = MrzStatus::from_warnings(&result.warnings);
+}
+
+/// Calendar date used for MRZ date handling
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CivilDate {
+    pub(crate) year: i32,
+    pub(crate) month: u32,
+    pub(crate) day: u32,
+}
+
+impl CivilDate {
+    pub fn new(year: i32, month: u32, day: u32) -> Option<Self> {
+        if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+            return None;
+        }
+        Some(CivilDate { year, month, day })
+    }
+
+    /// Parse an MRZ `YYMMDD` field, choosing the full year with `century`
+    fn from_yymmdd<F>(value: &str, century: F) -> Option<Self>
+    where
+        F: Fn(i32) -> i32,
+    {
+        if value.len() != 6 || !value.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let yy: i32 = value[0..2].parse().ok()?;
+        let month: u32 = value[2..4].parse().ok()?;
+        let day: u32 = value[4..6].parse().ok()?;
+
+        CivilDate::new(century(yy), month, day)
+    }
+
+    /// Parse `YYYY-MM-DD`, ignoring any trailing time component
+    pub fn parse_iso(value: &str) -> Option<Self> {
+        let date = value.get(0..10)?;
+        let mut parts = date.split('-');
+        let year = parts.next()?.parse().ok()?;
+        let month = parts.next()?.parse().ok()?;
+        let day = parts.next()?.parse().ok()?;
+
+        CivilDate::new(year, month, day)
+    }
+
+    /// Date from days since 1970-01-01 (Howard Hinnant's civil_from_days)
+    fn from_days_since_epoch(days: i64) -> Self {
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = (yoe + era * 400 + if month <= 2 { 1 } else { 0 }) as i32;
+
+        CivilDate { year, month, day }
+    }
+
+    /// UTC date of a Unix timestamp in milliseconds
+    pub fn from_epoch_millis(millis: f64) -> Self {
+        CivilDate::from_days_since_epoch((millis / 86_400_000.0).floor() as i64)
+    }
+
+    /// Current UTC date. wasm32 without the JS bindings has no clock and
+    /// reads 1970-01-01; pass the date to `parse_with_options` there.
+    pub fn today() -> Self {
+        #[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+        let millis = js_sys::Date::now();
+        #[cfg(all(not(feature = "wasm"), target_arch = "wasm32"))]
+        let millis = 0.0;
+        #[cfg(not(target_arch = "wasm32"))]
+        let millis = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as f64)
+            .unwrap_or(0.0);
+
+        CivilDate::from_epoch_millis(millis)
+    }
+
+    /// Whole years from `self` to `later` (0 if `later` is earlier).
+    /// The count increases on the anniversary itself.
+    fn years_until(self, later: CivilDate) -> u32 {
+        let mut years = later.year - self.year;
+        if (later.month, later.day) < (self.month, self.day) {
+            years -= 1;
+        }
+        years.max(0) as u32
+    }
+
+    /// Same day one hundred years earlier (Feb 29 becomes Feb 28 when needed)
+    fn minus_century(self) -> Option<Self> {
+        let year = self.year - 100;
+        let day = self.day.min(days_in_month(year, self.month));
+        CivilDate::new(year, self.month, day)
+    }
+
+    pub fn to_iso(self) -> String {
+        format!("{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+pub(crate) fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        4 | 6 | 9 | 11 => 30,
+        2 if (year % 4 == 0 && year % 100 != 0) || year % 400 == 0 => 29,
+        2 => 28,
+        _ => 31,
+    }
+}
+
+/// Parse MRV-A format (Visas: 2 lines of 44 characters)
+fn parse_mrva(lines: &[String]) -> Result<MRZResult, MrzError> {
+    parse_mrv(lines, MrzFormat::MrvA)
+}
+
+/// Parse MRV-B format (Visas: 2 lines of 36 characters)
+fn parse_mrvb(lines: &[String]) -> Result<MRZResult, MrzError> {
+    parse_mrv(lines, MrzFormat::MrvB)
+}
+
+/// Shared visa layout: like TD3/TD2, but optional data runs from position 28
+/// to the end of line 2 and there is no composite check digit
+fn parse_mrv(lines: &[String], format: MrzFormat) -> Result<MRZResult, MrzError> {
+    if lines.len() != 2 {
+        return Err(MrzError::MrzFormatUnknown(format!("{} requires 2 lines", format.name())));
+    }
+    let length = format.line_length();
+
+    let line1 = pad_line(&lines[0], length);
+    let line2 = pad_line(&lines[1], length);
+
+    let names = extract_names(&extract_field(&line1, 5, length));
+
+    let reports = check_digit_reports(format, &[&line1, &line2]);
+    let check_digits = MRZCheckDigits::from_reports(&reports);
+
+    let field_spans = MRZFieldSpans::two_line(&line1, length, length);
+
+    Ok(MRZResult {
+        document_type: format.name().to_string(),
+        document_code: document_code(&line1),
+        document_category: document_category(&line1).to_string(),
+        issuing_country: extract_field(&line1, 2, 5).to_string(),
+        surname: names.surname,
+        given_names: names.given_names.join(" "),
+        given_names_list: names.given_names,
+        name_truncated: names.truncated,
+        document_number: extract_field(&line2, 0, 9).trim_end_matches('<').to_string(),
+        document_number_plausible: None,
+        nationality: extract_field(&line2, 10, 13).to_string(),
+        date_of_birth: extract_field(&line2, 13, 19).to_string(),
+        sex: normalize_sex(&extract_field(&line2, 20, 21)).to_string(),
+        sex_raw: extract_field(&line2, 20, 21),
+        date_of_expiry: extract_field(&line2, 21, 27).to_string(),
+        optional_data: extract_field(&line2, 28, length)
+            .trim_end_matches('<')
+            .to_string(),
+        personal_number: String::new(),
+        optional_parsed: None,
+        issuing_country_valid: false,
+        issuing_country_name: None,
+        nationality_valid: false,
+        nationality_name: None,
+        raw_input_lines: Vec::new(),
+        normalization_applied: Vec::new(),
+        raw_mrz: vec![line1, line2],
+        confidence: 0.0,
+        field_confidence: HashMap::new(),
+        check_digits,
+        date_of_birth_iso: None,
+        date_of_expiry_iso: None,
+        warnings: Vec::new(),
+        status: MrzStatus::Valid,
+        is_expired: None,
+        age_years: None,
+        corrections: Vec::new(),
+        length_warnings: Vec::new(),
+        field_spans,
+    })
+}
+
+/// ICAO document code from the first two characters of line 1, filler-trimmed
+fn document_code(line1: &str) -> String {
+    extract_field(line1, 0, 2).trim_end_matches('<').to_string()
+}
+
+/// Broad document category derived from the first letter of the document code
+fn document_category(line1: &str) -> &'static str {
+    match line1.chars().next() {
+        Some('P') => "passport",
+        Some('I') | Some('A') | Some('C') => "id_card",
+        Some('V') => "visa",
+        _ => "other",
+    }
+}
+
+/// Numeric value of an MRZ character for check digit purposes:
+/// digits are 0-9, A-Z are 10-35 and the `<` filler is 0
+fn mrz_char_value(c: char) -> Option<u32> {
+    match c {
+        '0'..='9' => Some(c as u32 - '0' as u32),
+        'A'..='Z' => Some(c as u32 - 'A' as u32 + 10),
+        '<' => Some(0),
+        _ => None,
+    }
+}
+
+/// Compute the ICAO 9303 check digit (weights 7, 3, 1 repeating, modulo 10)
+fn compute_check_digit(data: &str) -> Option<u32> {
+    const WEIGHTS: [u32; 3] = [7, 3, 1];
+
+    let mut sum = 0;
+    for (i, c) in data.chars().enumerate() {
+        sum += mrz_char_value(c)? * WEIGHTS[i % 3];
+    }
+
+    Some(sum % 10)
+}
+
+/// Validate `data` against the check digit character that follows it
+fn check_digit_matches(data: &str, check: char) -> bool {
+    match (compute_check_digit(data), mrz_char_value(check)) {
+        (Some(expected), Some(actual)) => expected == actual,
+        _ => false,
+    }
+}
+
+/// Character at a position, or filler when the line is too short
+fn char_at(line: &str, index: usize) -> char {
+    line.chars().nth(index).unwrap_or('<')
+}
+
+/// Pad with `<` filler or trim a line to the specified length
+/// Counts characters, not bytes, so stray non-ASCII input can't split a UTF-8 sequence
+fn pad_line(line: &str, length: usize) -> String {
+    let truncated: String = line.chars().take(length).collect();
+    format!("{:<<width$}", truncated, width = length)
+}
+
+/// Extract a field from a line by character positions `[start, end)`
+fn extract_field(line: &str, start: usize, end: usize) -> String {
+    line.chars().skip(start).take(end.saturating_sub(start)).collect()
+}
+
+/// `MRZResult.sex` for the raw character. Lines are uppercased while being
+/// cleaned, so OCR output in lowercase lands here as `M`/`F`/`X` too.
+fn normalize_sex(raw: &str) -> &'static str {
+    match raw {
+        "M" => "M",
+        "F" => "F",
+        "X" | "<" => "X",
+        _ => "unknown",
+    }
+}
+
+/// Extract surname and given names from name field
+///
+/// The first `<<` ends the surname; without one the field is all surname.
+/// Every later run of filler, single or not, separates two given names.
+fn extract_names(name_field: &str) -> MrzNames {
+    let mut parts = name_field.splitn(2, "<<");
+    MrzNames {
+        surname: mrz_words(parts.next().unwrap_or_default()).join(" "),
+        given_names: mrz_words(parts.next().unwrap_or_default()),
+        truncated: name_truncated(name_field),
+    }
+}
+
+/// Surname and given names of an MRZ name field
+struct MrzNames {
+    surname: String,
+    given_names: Vec<String>,
+    truncated: bool,
+}
+
+/// The words of a `<`-separated field, ignoring empty runs of filler
+fn mrz_words(field: &str) -> Vec<String> {
+    field.split('<').filter(|word| !word.is_empty()).map(str::to_string).collect()
+}
+
+/// ICAO 9303 truncates long names to fill the field exactly, so a field
+/// with no trailing filler may have lost characters
+fn name_truncated(name_field: &str) -> bool {
+    !name_field.is_empty() && !name_field.ends_with('<')
+}
+
+// ==================== MRZ Accumulator ====================
+
+/// Fuses MRZ OCR text from consecutive video frames.
+/// Each frame is parsed on its own; the lines of frames sharing the most
+/// common format are then voted on character by character.
+#[cfg_attr(all(feature = "wasm", target_arch = "wasm32"), wasm_bindgen)]
+pub struct MrzAccumulator {
+    /// Return a fused result after this many frames even if check digits still fail
+    min_frames: u32,
+    /// `document_type` and corrected lines of every frame that parsed
+    frames: Vec<(String, Vec<String>)>,
+}
+
+#[cfg_attr(all(feature = "wasm", target_arch = "wasm32"), wasm_bindgen)]
+impl MrzAccumulator {
+    #[cfg_attr(all(feature = "wasm", target_arch = "wasm32"), wasm_bindgen(constructor))]
+    pub fn new(min_frames: u32) -> MrzAccumulator {
+        MrzAccumulator {
+            min_frames,
+            frames: Vec::new(),
+        }
+    }
+
+    /// Add one frame's OCR text; returns false when it holds no parseable MRZ
+    pub fn push(&mut self, mrz_text: &str) -> bool {
+        let options = MrzOptions::default();
+        let parsed = clean_mrz_lines(mrz_text, options.min_line_length)
+            .and_then(|lines| select_mrz_lines(&lines, &options));
+        match parsed {
+            Ok(result) => {
+                self.frames.push((result.document_type, result.raw_mrz));
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Drop every accumulated frame
+    pub fn reset(&mut self) {
+        self.frames.clear();
+    }
+
+    #[cfg_attr(all(feature = "wasm", target_arch = "wasm32"), wasm_bindgen(getter))]
+    pub fn frame_count(&self) -> u32 {
+        self.frames.len() as u32
+    }
+}
+
+impl MrzAccumulator {
+    /// The fused result, or `None` until it passes every check digit or
+    /// `min_frames` frames have been pushed. `confidence` is scaled by how
+    /// strongly the frames agreed on each character.
+    pub fn best(&self) -> Option<MRZResult> {
+        let mut result = self.fuse()?;
+        resolve_mrz_dates(&mut result, CivilDate::today());
+        result.optional_parsed = mrz_optional::decode(&result);
+        Some(result)
+    }
+
+    fn fuse(&self) -> Option<MRZResult> {
+        let format = most_common(self.frames.iter().map(|(format, _)| format))?;
+        let frames: Vec<Vec<Vec<char>>> = self
+            .frames
+            .iter()
+            .filter(|(f, _)| f == format)
+            .map(|(_, lines)| lines.iter().map(|line| line.chars().collect()).collect())
+            .collect();
+
+        let mut fused = Vec::new();
+        let mut agreement = 0.0;
+        let mut positions = 0;
+        for (index, line) in frames[0].iter().enumerate() {
+            let mut fused_line = String::new();
+            for pos in 0..line.len() {
+                let votes = frames.iter().filter_map(|f| f.get(index)?.get(pos));
+                let Some(winner) = most_common(votes) else {
+                    continue;
+                };
+                let count = frames
+                    .iter()
+                    .filter(|f| f.get(index).and_then(|l| l.get(pos)) == Some(winner))
+                    .count();
+                agreement += count as f32 / frames.len() as f32;
+                positions += 1;
+                fused_line.push(*winner);
+            }
+            fused.push(fused_line);
+        }
+
+        let mut result = parse_mrz_with_corrections(&fused, &MrzOptions::default()).ok()?;
+        if !result.check_digits.valid && (self.frames.len() as u32) < self.min_frames {
+            return None;
+        }
+        if positions > 0 {
+            result.confidence *= agreement / positions as f32;
+        }
+        Some(result)
+    }
+}
+
+/// Most frequent item; ties go to the one seen first
+fn most_common<T: PartialEq>(items: impl Iterator<Item = T>) -> Option<T> {
+    let mut counts: Vec<(T, usize)> = Vec::new();
+    for item in items {
+        match counts.iter_mut().find(|(seen, _)| *seen == item) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((item, 1)),
+        }
+    }
+    let best = counts.iter().map(|(_, count)| *count).max()?;
+    counts.into_iter().find(|(_, count)| *count == best).map(|(item, _)| item)
+}
+
+// ==================== MRZ Generation ====================
+
+/// Input for `generate_mrz`, deserialized from a JS object.
+/// Missing fields are left as filler; dates are YYMMDD.
+#[derive(Deserialize, Clone, Default, Debug)]
+#[serde(default)]
+pub struct MrzFields {
+    pub document_code: String,
+    pub issuing_country: String,
+    pub surname: String,
+    pub given_names: String,
+    pub document_number: String,
+    pub nationality: String,
+    pub date_of_birth: String,
+    pub sex: String,
+    pub date_of_expiry: String,
+    pub optional_data: String,
+    pub personal_number: String, // TD3 only, falls back to optional_data
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct GeneratedMrz {
+    pub lines: Vec<String>,
+    pub text: String,          // Lines joined with newlines, ready for `parse_mrz_text`
+    pub warnings: Vec<String>, // Name characters dropped during transliteration
+}
+
+/// Build TD1, TD2 or TD3 lines from structured fields, computing every check digit
+pub fn generate_mrz_lines(fields: &MrzFields, format: &str) -> Result<GeneratedMrz, MrzError> {
+    let mut warnings = Vec::new();
+    let lines = build_mrz_lines(fields, format, &mut warnings)?;
+    let text = lines.join("\n");
+    Ok(GeneratedMrz { lines, text, warnings })
+}
+
+fn build_mrz_lines(
+    fields: &MrzFields,
+    format: &str,
+    warnings: &mut Vec<String>,
+) -> Result<Vec<String>, MrzError> {
+    let code = mrz_field(&fields.document_code, 2, "document_code")?;
+    let country = mrz_field(&fields.issuing_country, 3, "issuing_country")?;
+    let nationality = mrz_field(&fields.nationality, 3, "nationality")?;
+    let birth = mrz_date(&fields.date_of_birth, "date_of_birth")?;
+    let expiry = mrz_date(&fields.date_of_expiry, "date_of_expiry")?;
+    let sex = mrz_field(&fields.sex, 1, "sex")?;
+
+    match format {
+        "TD1" => {
+            let number = mrz_text(&fields.document_number, "document_number")?;
+            let optional = mrz_text(&fields.optional_data, "optional_data")?;
+            // Long numbers continue into the optional data, see `split_td1_document_number`
+            let (number_field, optional_field) = if number.len() > 9 {
+                let continuation = with_check_digit(&number[9..], &number);
+                let optional = format!("{}<{}", continuation, optional);
+                (format!("{}<", &number[..9]), fit_field(&optional, 15, "optional_data")?)
+            } else {
+                let number = fit_field(&number, 9, "document_number")?;
+                (with_check_digit(&number, &number), fit_field(&optional, 15, "optional_data")?)
+            };
+            let line1 = format!("{}{}{}{}", code, country, number_field, optional_field);
+            let line2 = format!(
+                "{}{}{}{}{}",
+                with_check_digit(&birth, &birth),
+                sex,
+                with_check_digit(&expiry, &expiry),
+                nationality,
+                "<".repeat(11)
+            );
+            let composite = format!(
+                "{}{}{}{}",
+                &line1[5..30],
+                &line2[0..7],
+                &line2[8..15],
+                &line2[18..29]
+            );
+            let line2 = with_check_digit(&line2, &composite);
+            let line3 = mrz_name_field(&fields.surname, &fields.given_names, 30, warnings)?;
+            Ok(vec![line1, line2, line3])
+        }
+        "TD2" | "TD3" => {
+            let length = if format == "TD3" { 44 } else { 36 };
+            let number = mrz_field(&fields.document_number, 9, "document_number")?;
+            let names =
+                mrz_name_field(&fields.surname, &fields.given_names, length - 5, warnings)?;
+            let line1 = format!("{}{}{}", code, country, names);
+
+            let optional = if format == "TD3" {
+                let personal = if fields.personal_number.is_empty() {
+                    &fields.optional_data
+                } else {
+                    &fields.personal_number
+                };
+                let personal = mrz_field(personal, 14, "personal_number")?;
+                // An unused personal number takes a filler check digit
+                if personal.chars().all(|c| c == '<') {
+                    format!("{}<", personal)
+                } else {
+                    with_check_digit(&personal, &personal)
+                }
+            } else {
+                mrz_field(&fields.optional_data, 7, "optional_data")?
+            };
+            let line2 = format!(
+                "{}{}{}{}{}{}",
+                with_check_digit(&number, &number),
+                nationality,
+                with_check_digit(&birth, &birth),
+                sex,
+                with_check_digit(&expiry, &expiry),
+                optional
+            );
+            let composite = format!(
+                "{}{}{}",
+                &line2[0..10],
+                &line2[13..20],
+                &line2[21..length - 1]
+            );
+            let line2 = with_check_digit(&line2, &composite);
+            Ok(vec![line1, line2])
+        }
+        _ => Err(MrzError::MrzFormatUnknown(format!(
+            "cannot generate {}, expected TD1, TD2 or TD3",
+            format
+        ))),
+    }
+}
+
+/// Uppercase a free-text value and map separators to filler: spaces and
+/// hyphens become `<`, apostrophes, commas and periods are dropped
+fn mrz_text(value: &str, name: &str) -> Result<String, MrzError> {
+    let text: String = value
+        .trim()
+        .to_uppercase()
+        .chars()
+        .filter(|c| !matches!(c, '\'' | ',' | '.'))
+        .map(|c| if c == ' ' || c == '-' { '<' } else { c })
+        .collect();
+    if let Some(c) = text
+        .chars()
+        .find(|c| !(c.is_ascii_uppercase() || c.is_ascii_digit() || *c == '<'))
+    {
+        return Err(MrzError::InvalidMrzField(format!(
+            "{} contains {:?}, which has no MRZ representation",
+            name, c
+        )));
+    }
+    Ok(text)
+}
+
+/// Normalize a value and pad it with filler to exactly `width` characters
+fn mrz_field(value: &str, width: usize, name: &str) -> Result<String, MrzError> {
+    fit_field(&mrz_text(value, name)?, width, name)
+}
+
+fn fit_field(text: &str, width: usize, name: &str) -> Result<String, MrzError> {
+    if text.len() > width {
+        return Err(MrzError::InvalidMrzField(format!(
+            "{} is {} characters, the field holds {}",
+            name,
+            text.len(),
+            width
+        )));
+    }
+    Ok(format!("{:<<width$}", text, width = width))
+}
+
+/// YYMMDD date, or all filler when unknown
+fn mrz_date(value: &str, name: &str) -> Result<String, MrzError> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok("<".repeat(6));
+    }
+    if value.len() != 6 || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(MrzError::InvalidMrzField(format!("{} must be YYMMDD", name)));
+    }
+    Ok(value.to_string())
+}
+
+/// `field` followed by the check digit computed over `data`
+fn with_check_digit(field: &str, data: &str) -> String {
+    let check = compute_check_digit(data).unwrap_or(0);
+    format!("{}{}", field, check)
+}
+
+/// `SURNAME<<GIVEN<NAMES` padded to `width`, transliterated. When the names
+/// don't fit, the given names are truncated from the end first, then the surname.
+fn mrz_name_field(
+    surname: &str,
+    given_names: &str,
+    width: usize,
+    warnings: &mut Vec<String>,
+) -> Result<String, MrzError> {
+    let mut transliterate = |value: &str, name: &str| {
+        let (text, dropped) = transliterate_name(value);
+        for c in dropped {
+            warnings.push(format!("{}: dropped {:?}, which has no MRZ transliteration", name, c));
+        }
+        text
+    };
+    let surname = transliterate(surname, "surname");
+    let given_names = transliterate(given_names, "given_names");
+
+    let full = if given_names.is_empty() {
+        surname
+    } else {
+        format!("{}<<{}", surname, given_names)
+    };
+    let truncated = full.chars().take(width).collect::<String>();
+    // Don't leave a dangling separator where the given names were cut off
+    let truncated = if truncated.len() < full.len() {
+        truncated.trim_end_matches('<').to_string()
+    } else {
+        truncated
+    };
+    fit_field(&truncated, width, "names")
+}
+
+/// Transliterate a name, returning the MRZ text and the characters dropped.
+/// Separators follow `mrz_text`; letters outside the 9303 table lose their
+/// diacritics, and anything still not A-Z is dropped.
+pub fn transliterate_name(name: &str) -> (String, Vec<char>) {
+    let mut text = String::new();
+    let mut dropped = Vec::new();
+
+    for c in name.trim().chars().flat_map(char::to_uppercase) {
+        match c {
+            'A'..='Z' | '<' => text.push(c),
+            ' ' | '-' => text.push('<'),
+            '\'' | ',' | '.' => {}
+            _ => match transliterate_char(c) {
+                Some(mapped) => text.push_str(mapped),
+                None => {
+                    let base: String = c.nfd().filter(|b| b.is_ascii_uppercase()).collect();
+                    if base.is_empty() {
+                        dropped.push(c);
+                    } else {
+                        text.push_str(&base);
+                    }
+                }
+            },
+        }
+    }
+
+    (text, dropped)
+}
+
+/// ICAO 9303 Part 3 transliterations that aren't just a dropped diacritic
+fn transliterate_char(c: char) -> Option<&'static str> {
+    Some(match c {
+        'Å' => "AA",
+        'Ä' | 'Æ' => "AE",
+        'Ö' | 'Ø' | 'Œ' => "OE",
+        'Ü' => "UE",
+        'ß' | 'ẞ' => "SS",
+        'Ð' | 'Đ' => "D",
+        'Þ' => "TH",
+        'Ĳ' => "IJ",
+        'Ł' => "L",
+        'Ħ' => "H",
+        'Ŀ' => "L",
+        'Ŋ' => "N",
+        'Ŧ' => "T",
+        _ => return None,
+    })
+}
+
+// ==================== eMRTD Access Keys ====================
+
+/// The MRZ fields Basic Access Control keys are derived from. Deserializes
+/// from an `MRZResult` object as well as from a bare `{ document_number, ... }`.
+#[derive(Deserialize, Clone, Debug)]
+pub struct BacFields {
+    pub document_number: String,
+    pub date_of_birth: String,
+    pub date_of_expiry: String,
+}
+
+/// BAC key material per ICAO 9303-11: the seed and the derived 3DES keys
+#[derive(Serialize, Clone, Debug)]
+pub struct BacKeys {
+    #[serde(with = "serde_bytes")]
+    pub key_seed: Vec<u8>,
+    #[serde(with = "serde_bytes")]
+    pub k_enc: Vec<u8>,
+    #[serde(with = "serde_bytes")]
+    pub k_mac: Vec<u8>,
+}
+
+/// The BAC fields of an MRZ given as text
+pub fn bac_fields_from_text(mrz_text: &str) -> Result<BacFields, MrzError> {
+    let options = MrzOptions::default();
+    let lines = clean_mrz_lines(mrz_text, options.min_line_length)?;
+    let result = select_mrz_lines(&lines, &options)?;
+    Ok(BacFields {
+        document_number: result.document_number,
+        date_of_birth: result.date_of_birth,
+        date_of_expiry: result.date_of_expiry,
+    })
+}
+
+/// The key seed with the derived `k_enc` and `k_mac`
+pub fn derive_bac_keys(fields: &BacFields) -> Result<BacKeys, MrzError> {
+    let seed = bac_key_seed(fields)?;
+    Ok(BacKeys {
+        key_seed: seed.to_vec(),
+        k_enc: derive_3des_key(&seed, 1).to_vec(),
+        k_mac: derive_3des_key(&seed, 2).to_vec(),
+    })
+}
+
+/// `MRZ_information` hashed for the key seed; document numbers shorter than
+/// 9 characters are padded with `<` as they appear in the MRZ
+fn bac_mrz_information(fields: &BacFields) -> Result<String, MrzError> {
+    let number = format!("{:<<9}", fields.document_number.trim());
+    let mut information = String::new();
+    for (name, value) in [
+        ("document_number", number.as_str()),
+        ("date_of_birth", fields.date_of_birth.trim()),
+        ("date_of_expiry", fields.date_of_expiry.trim()),
+    ] {
+        let check = compute_check_digit(value).ok_or_else(|| {
+            MrzError::InvalidMrzField(format!("{} contains non-MRZ characters", name))
+        })?;
+        information.push_str(value);
+        information.push_str(&check.to_string());
+    }
+    Ok(information)
+}
+
+/// BAC/PACE key seed: the first 16 bytes of SHA-1 over document number,
+/// birth date and expiry date, each followed by its check digit
+pub fn bac_key_seed(fields: &BacFields) -> Result<[u8; 16], MrzError> {
+    let digest = Sha1::digest(bac_mrz_information(fields)?.as_bytes());
+    let mut seed = [0u8; 16];
+    seed.copy_from_slice(&digest[..16]);
+    Ok(seed)
+}
+
+/// ICAO 9303-11 key derivation: SHA-1 over seed || counter (1 = enc, 2 = mac),
+/// truncated to a 16-byte two-key 3DES key with DES parity bits set
+fn derive_3des_key(seed: &[u8; 16], counter: u32) -> [u8; 16] {
+    let mut hasher = Sha1::new();
+    hasher.update(seed);
+    hasher.update(counter.to_be_bytes());
+    let digest = hasher.finalize();
+
+    let mut key = [0u8; 16];
+    key.copy_from_slice(&digest[..16]);
+    for byte in &mut key {
+        // Odd parity in the least significant bit of each byte
+        let parity = ((*byte >> 1).count_ones() as u8 + 1) % 2;
+        *byte = (*byte & 0xFE) | parity;
+    }
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// ICAO 9303 part 4 specimen passport
+    const TD3: [&str; 2] = [
+        "P<UTOERIKSSON<<ANNA<MARIA<<<<<<<<<<<<<<<<<<<",
+        "L898902C36UTO7408122F1204159ZE184226B<<<<<10",
+    ];
+
+    /// ICAO 9303 part 5 specimen identity card
+    const TD1: [&str; 3] = [
+        "I<UTOD231458907<<<<<<<<<<<<<<<",
+        "7408122F1204159UTO<<<<<<<<<<<6",
+        "ERIKSSON<<ANNA<MARIA<<<<<<<<<<",
+    ];
+
+    /// ICAO 9303 part 6 specimen official travel document
+    const TD2: [&str; 2] =
+        ["I<UTOERIKSSON<<ANNA<MARIA<<<<<<<<<<<", "D231458907UTO7408122F1204159<<<<<<<6"];
+
+    fn reference() -> CivilDate {
+        CivilDate::new(2026, 1, 1).unwrap()
+    }
+
+    fn at_level(correction_level: CorrectionLevel) -> MrzOptions {
+        MrzOptions { correction_level, ..MrzOptions::default() }
+    }
+
+    fn parse_at(lines: &[&str], options: &MrzOptions) -> MRZResult {
+        parse_with_options(&lines.join("\n"), reference(), options).unwrap()
+    }
+
+    /// The specimen line 2 expiring on `expiry`, with valid check digits
+    fn td3_line2_expiring(expiry: &str) -> String {
+        td3_line2_dated(&TD3[1][13..19], expiry)
+    }
+
+    /// The specimen line 2 with other dates, with valid check digits
+    fn td3_line2_dated(birth: &str, expiry: &str) -> String {
+        let check = |data: &str| compute_check_digit(data).unwrap_or(0);
+        let line = format!(
+            "{}{}{}{}{}{}{}",
+            &TD3[1][..13],
+            birth,
+            check(birth),
+            &TD3[1][20..21],
+            expiry,
+            check(expiry),
+            &TD3[1][28..43]
+        );
+        let composite = [&line[..10], &line[13..20], &line[21..43]].concat();
+        format!("{}{}", line, check(&composite))
+    }
+
+    #[test]
+    fn letter_in_expiry_becomes_digit_under_safe() {
+        let line2 = td3_line2_expiring("300112").replacen("3001", "3O01", 1);
+        let result = parse_at(&[TD3[0], &line2], &MrzOptions::default());
+        assert_eq!(result.date_of_expiry, "300112");
+        assert_eq!(result.date_of_expiry_iso.as_deref(), Some("2030-01-12"));
+        assert_eq!(result.check_digits.date_of_expiry, Some(true));
+        assert_eq!(result.corrections, ["date_of_expiry: 'O' -> '0' at line 2, position 23"]);
+    }
+
+    #[test]
+    fn off_keeps_the_raw_expiry() {
+        let line2 = td3_line2_expiring("300112").replacen("3001", "3O01", 1);
+        let result = parse_at(&[TD3[0], &line2], &at_level(CorrectionLevel::Off));
+        assert_eq!(result.date_of_expiry, "3O0112");
+        assert_eq!(result.date_of_expiry_iso, None);
+        assert!(result.corrections.is_empty());
+        assert!(result.warnings.iter().any(|warning| warning.field == "date_of_expiry"));
+    }
+
+    #[test]
+    fn digit_in_surname_becomes_letter_under_safe() {
+        let line1 = TD3[0].replace("ERIKSSON", "ERIKSS0N");
+        let result = parse_at(&[&line1, TD3[1]], &MrzOptions::default());
+        assert_eq!(result.surname, "ERIKSSON");
+        assert_eq!(result.corrections, ["names: '0' -> 'O' at line 1, position 12"]);
+
+        let result = parse_at(&[&line1, TD3[1]], &at_level(CorrectionLevel::Off));
+        assert_eq!(result.surname, "ERIKSS0N");
+    }
+
+    #[test]
+    fn letters_in_names_are_untouched() {
+        let line1 = TD3[0].replace("ERIKSSON", "OLOFSSON");
+        let result = parse_at(&[&line1, TD3[1]], &MrzOptions::default());
+        assert_eq!(result.surname, "OLOFSSON");
+        assert!(result.corrections.is_empty(), "{:?}", result.corrections);
+    }
+
+    #[test]
+    fn long_td1_document_number_is_not_corrected() {
+        let lines = [
+            "I<UTOD23145890<7349<<<<<<<<<<<",
+            "3407127M9507122UTO<<<<<<<<<<<2",
+            "STEVENSON<<PETER<JOHN<<<<<<<<<",
+        ];
+        for level in [CorrectionLevel::Safe, CorrectionLevel::Aggressive] {
+            let result = parse_at(&lines, &at_level(level));
+            assert_eq!(result.document_number, "D23145890734");
+            assert_eq!(result.optional_data, "");
+            assert!(result.check_digits.valid, "{:?}", result.check_digits);
+            assert!(result.corrections.is_empty(), "{:?}", result.corrections);
+            assert_eq!(result.raw_mrz, lines);
+        }
+    }
+
+    #[test]
+    fn td1_missing_trailing_filler_is_padded() {
+        for missing in 1..=3 {
+            let line1 = &TD1[0][..30 - missing];
+            let line3 = &TD1[2][..30 - missing];
+            let result = parse_at(&[line1, TD1[1], line3], &MrzOptions::default());
+            assert_eq!(result.document_type, "TD1", "{} missing", missing);
+            assert!(result.check_digits.valid, "{} missing", missing);
+            assert_eq!(result.document_number, "D23145890");
+            assert_eq!(result.given_names, "ANNA MARIA");
+            assert_eq!(result.raw_mrz, TD1);
+            let warning = |line| {
+                let found = 30 - missing;
+                format!("line {}: {} of 30 characters, padded with {} '<'", line, found, missing)
+            };
+            assert_eq!(result.length_warnings, [warning(1), warning(3)]);
+        }
+    }
+
+    #[test]
+    fn full_length_lines_have_no_length_warnings() {
+        assert!(parse_at(&TD1, &MrzOptions::default()).length_warnings.is_empty());
+        assert!(parse_at(&TD3, &MrzOptions::default()).length_warnings.is_empty());
+    }
+
+    #[test]
+    fn long_lines_are_trimmed_with_a_warning() {
+        let line1 = format!("{}<<", TD3[0]);
+        let result = parse_at(&[&line1, TD3[1]], &at_level(CorrectionLevel::Off));
+        assert!(result.check_digits.valid);
+        assert_eq!(result.length_warnings, ["line 1: 46 of 44 characters, trimmed by 2"]);
+    }
+
+    #[test]
+    fn lines_beyond_the_length_tolerance_are_rejected() {
+        let line3 = &TD1[2][..25];
+        let lines = [TD1[0], TD1[1], line3];
+        let text = lines.join("\n");
+        let error = parse_with_options(&text, reference(), &MrzOptions::default()).unwrap_err();
+        assert_eq!(error.code(), "MRZ_FORMAT_UNKNOWN");
+
+        let options = MrzOptions { length_tolerance: 5, ..MrzOptions::default() };
+        let result = parse_at(&lines, &options);
+        assert!(result.check_digits.valid);
+        assert_eq!(result.length_warnings, ["line 3: 25 of 30 characters, padded with 5 '<'"]);
+    }
+
+    #[test]
+    fn min_line_length_drops_shorter_lines() {
+        // OCR text around the MRZ: only the header is at least 20 characters
+        let text = "REPUBLIC OF UTOPIA PASSPORT\nNAME\nERIKSSON";
+        let error = parse_with_options(text, reference(), &MrzOptions::default()).unwrap_err();
+        assert_ne!(error, MrzError::NoMrzLines);
+        let options = MrzOptions { min_line_length: 30, ..MrzOptions::default() };
+        let error = parse_with_options(text, reference(), &options).unwrap_err();
+        assert_eq!(error, MrzError::NoMrzLines);
+
+        // A badly cut TD1 name line is kept once the minimum allows it
+        let lines = [TD1[0], TD1[1], "ERIKSSON<<ANNA"];
+        let strict = MrzOptions { strict: true, ..MrzOptions::default() };
+        // Dropped by default, leaving two lines that strict parsing reads as TD2
+        let result = parse_at(&lines, &strict);
+        assert_eq!(result.raw_input_lines.len(), 2);
+        assert_eq!(result.document_type, "TD2");
+        let options = MrzOptions { min_line_length: 10, ..strict };
+        let result = parse_at(&lines, &options);
+        assert_eq!(result.document_type, "TD1");
+        assert_eq!(result.given_names, "ANNA");
+        assert_eq!(result.length_warnings, ["line 3: 14 of 30 characters, padded with 16 '<'"]);
+    }
+
+    #[test]
+    fn check_digits_weigh_7_3_1_with_filler_as_zero() {
+        for (data, digit) in [
+            ("L898902C3", 6),
+            ("740812", 2),
+            ("120415", 9),
+            ("ZE184226B<<<<<", 1),
+            ("D23145890", 7),
+            ("A", 0),        // 10 * 7
+            ("Z", 5),        // 35 * 7
+            ("AB<", 3),      // 10 * 7 + 11 * 3 + 0 * 1
+            ("<<<<<<<<<", 0),
+        ] {
+            assert_eq!(compute_check_digit(data), Some(digit), "{}", data);
+        }
+        assert_eq!(compute_check_digit("D2314-890"), None);
+        assert!(check_digit_matches("<<<<<<", '<'));
+        assert!(!check_digit_matches("740812", 'A'));
+    }
+
+    #[test]
+    fn specimens_pass_every_check_digit() {
+        for lines in [&TD1[..], &TD2, &TD3] {
+            let result = parse_at(lines, &at_level(CorrectionLevel::Off));
+            let checks = &result.check_digits;
+            assert!(checks.valid && checks.document_number && checks.date_of_birth, "{:?}", lines);
+            assert_eq!((checks.date_of_expiry, checks.composite), (Some(true), Some(true)));
+            assert_eq!(result.field_confidence["document_number"], 1.0);
+        }
+        let td3 = parse_at(&TD3, &at_level(CorrectionLevel::Off));
+        assert_eq!(td3.check_digits.personal_number, Some(true));
+        assert_eq!(parse_at(&TD2, &MrzOptions::default()).check_digits.personal_number, None);
+
+        // A wrong birth date digit fails that field and the composite
+        let line2 = TD3[1].replacen("7408122", "7408123", 1);
+        let result = parse_at(&[TD3[0], &line2], &at_level(CorrectionLevel::Off));
+        let checks = &result.check_digits;
+        assert!(!checks.valid && !checks.date_of_birth && checks.document_number);
+        assert_eq!((checks.date_of_expiry, checks.composite), (Some(true), Some(false)));
+        assert!(result.confidence < td3.confidence);
+    }
+
+    #[test]
+    fn dates_resolve_to_iso_with_century_inference() {
+        let dated = |birth: &str, expiry: &str, reference: CivilDate| {
+            let text = format!("{}\n{}", TD3[0], td3_line2_dated(birth, expiry));
+            parse_with_options(&text, reference, &MrzOptions::default()).unwrap()
+        };
+        let result = dated("740812", "120415", reference());
+        assert_eq!(result.date_of_birth_iso.as_deref(), Some("1974-08-12"));
+        assert_eq!(result.date_of_expiry_iso.as_deref(), Some("2012-04-15"));
+
+        // Births after the reference roll back a century, expiries only when
+        // 20xx is more than 50 years out
+        let result = dated("260102", "760101", reference());
+        assert_eq!(result.date_of_birth_iso.as_deref(), Some("1926-01-02"));
+        assert_eq!(result.date_of_expiry_iso.as_deref(), Some("2076-01-01"));
+        let result = dated("260102", "770101", reference());
+        assert_eq!(result.date_of_expiry_iso.as_deref(), Some("1977-01-01"));
+        let later = CivilDate::new(2030, 6, 1).unwrap();
+        let result = dated("260102", "770101", later);
+        assert_eq!(result.date_of_birth_iso.as_deref(), Some("2026-01-02"));
+        assert_eq!(result.date_of_expiry_iso.as_deref(), Some("2077-01-01"));
+
+        // Leap days exist only in leap years
+        let result = dated("000229", "270229", reference());
+        assert_eq!(result.date_of_birth_iso.as_deref(), Some("2000-02-29"));
+        assert_eq!(result.date_of_expiry_iso, None);
+
+        for (birth, expiry) in [("741312", "120415"), ("740832", "120415"), ("740812", "120431")] {
+            let result = dated(birth, expiry, reference());
+            let invalid: Vec<&str> = result
+                .warnings
+                .iter()
+                .filter(|warning| warning.code == MrzWarningCode::InvalidDate)
+                .map(|warning| warning.field.as_str())
+                .collect();
+            if birth == "740812" {
+                assert_eq!(result.date_of_expiry_iso, None);
+                assert_eq!(invalid, ["date_of_expiry"]);
+            } else {
+                assert_eq!(result.date_of_birth_iso, None, "{}", birth);
+                assert_eq!(invalid, ["date_of_birth"], "{}", birth);
+            }
+        }
+    }
+
+    #[test]
+    fn documents_expire_after_their_expiry_date_and_age_on_birthdays() {
+        let on = |birth: &str, expiry: &str, (year, month, day): (i32, u32, u32)| {
+            let text = format!("{}\n{}", TD3[0], td3_line2_dated(birth, expiry));
+            let reference = CivilDate::new(year, month, day).unwrap();
+            parse_with_options(&text, reference, &MrzOptions::default()).unwrap()
+        };
+        // Still valid on the expiry date itself
+        assert_eq!(on("740812", "260315", (2026, 3, 14)).is_expired, Some(false));
+        assert_eq!(on("740812", "260315", (2026, 3, 15)).is_expired, Some(false));
+        assert_eq!(on("740812", "260315", (2026, 3, 16)).is_expired, Some(true));
+
+        // A year older on the birthday itself
+        assert_eq!(on("080315", "300101", (2026, 3, 14)).age_years, Some(17));
+        assert_eq!(on("080315", "300101", (2026, 3, 15)).age_years, Some(18));
+        // Born on a leap day: 18 only from March 1 in common years
+        assert_eq!(on("080229", "300101", (2026, 2, 28)).age_years, Some(17));
+        assert_eq!(on("080229", "300101", (2026, 3, 1)).age_years, Some(18));
+
+        // Unparseable dates give no answer rather than a guess
+        let result = on("081315", "261315", (2026, 3, 15));
+        assert_eq!((result.is_expired, result.age_years), (None, None));
+        let result = on("080315", "26<<<<", (2026, 3, 15));
+        assert_eq!((result.is_expired, result.age_years), (None, Some(18)));
+    }
+
+    #[test]
+    fn visas_parse_as_mrv_a_and_mrv_b() {
+        // ICAO 9303 part 7 specimens
+        let mrv_a = [
+            "V<UTOERIKSSON<<ANNA<MARIA<<<<<<<<<<<<<<<<<<<",
+            "L8988901C4XXX4009078F96121096ZE184226B<<<<<<",
+        ];
+        let mrv_b = [
+            "V<UTOERIKSSON<<ANNA<MARIA<<<<<<<<<<<",
+            "L8988901C4XXX4009078F9612109<<<<<<<<",
+        ];
+        for (lines, format, optional) in [(mrv_a, "MRV-A", "6ZE184226B"), (mrv_b, "MRV-B", "")] {
+            let result = parse_at(&lines, &at_level(CorrectionLevel::Off));
+            assert_eq!(result.document_type, format);
+            assert_eq!((result.document_code.as_str(), result.document_category.as_str()),
+                ("V", "visa"));
+            assert_eq!((result.document_number.as_str(), result.nationality.as_str()),
+                ("L8988901C", "XXX"));
+            assert_eq!((result.surname.as_str(), result.given_names.as_str()),
+                ("ERIKSSON", "ANNA MARIA"));
+            assert_eq!((result.date_of_birth.as_str(), result.date_of_expiry.as_str()),
+                ("400907", "961210"));
+            assert_eq!(result.sex, "F");
+            // No composite check digit: the optional data runs to the end
+            assert_eq!(result.optional_data, optional);
+            assert_eq!(result.personal_number, "");
+            assert_eq!(result.field_spans.optional_data, span(1, 28, lines[1].len()));
+            assert_eq!(result.check_digits.composite, None);
+            assert!(result.check_digits.valid, "{:?}", result.check_digits);
+        }
+    }
+
+    #[test]
+    fn document_codes_drop_trailing_filler_and_map_to_categories() {
+        let cases = [
+            ("P<", "P", "passport"),
+            ("PD", "PD", "passport"),
+            ("I<", "I", "id_card"),
+            ("ID", "ID", "id_card"),
+            ("AC", "AC", "id_card"),
+            ("AR", "AR", "id_card"),
+            ("C<", "C", "id_card"),
+            ("V<", "V", "visa"),
+            ("X<", "X", "other"),
+        ];
+        for (prefix, code, category) in cases {
+            let td3 = format!("{}{}", prefix, &TD3[0][2..]);
+            let td1 = format!("{}{}", prefix, &TD1[0][2..]);
+            // A V code makes the 44-character lines a visa, so the layout varies
+            for lines in [vec![td3.as_str(), TD3[1]], vec![td1.as_str(), TD1[1], TD1[2]]] {
+                let result = parse_at(&lines, &at_level(CorrectionLevel::Off));
+                assert_eq!(result.document_code, code, "{}", lines[0]);
+                assert_eq!(result.document_category, category, "{}", lines[0]);
+            }
+        }
+    }
+
+    #[test]
+    fn td1_document_numbers_continue_into_the_optional_data() {
+        let standard = [
+            "I<UTOD231458907ABC123<<<<<<<<<",
+            "7408122F1204159UTO<<<<<<<<<<<7",
+            "ERIKSSON<<ANNA<MARIA<<<<<<<<<<",
+        ];
+        let result = parse_at(&standard, &at_level(CorrectionLevel::Off));
+        assert!(result.check_digits.valid, "{:?}", result.check_digits);
+        assert_eq!(result.document_number, "D23145890");
+        assert_eq!(result.optional_data, "ABC123");
+        assert_eq!(result.field_spans.document_number, span(0, 5, 14));
+        assert_eq!(result.field_spans.optional_data, span(0, 15, 30));
+
+        // `<` in the check digit slot: the number runs on to the next filler,
+        // whose last character is the relocated check digit
+        let extended = [
+            "I<UTOD23145890<7349<XY12<<<<<<",
+            "3407127M9507122UTO<<<<<<<<<<<0",
+            "STEVENSON<<PETER<JOHN<<<<<<<<<",
+        ];
+        let result = parse_at(&extended, &at_level(CorrectionLevel::Off));
+        assert!(result.check_digits.valid, "{:?}", result.check_digits);
+        assert!(result.check_digits.document_number);
+        assert_eq!(result.document_number, "D23145890734");
+        assert_eq!(result.optional_data, "XY12");
+        let mut number = span(0, 5, 14);
+        number.extend(span(0, 15, 18));
+        assert_eq!(result.field_spans.document_number, number);
+        assert_eq!(result.field_spans.optional_data, span(0, 20, 30));
+    }
+
+    #[test]
+    fn a_td3_is_found_among_fifteen_lines_of_page_text() {
+        let page = [
+            "REPUBLIC OF UTOPIA",
+            "PASSPORT / PASSEPORT",
+            "Type / Type  Code / Code  Passport No. / No. du passeport",
+            "P UTO L898902C3",
+            "Surname / Nom",
+            "ERIKSSON",
+            "Given names / Prenoms",
+            "ANNA MARIA",
+            "Nationality / Nationalite   UTOPIAN",
+            "Date of birth / Date de naissance  12 AUG 74",
+            "Sex / Sexe F   Place of birth / Lieu de naissance ZENITH",
+            "Date of issue / Date de delivrance  16 APR 07",
+            TD3[0],
+            TD3[1],
+            "Authority / Autorite  PASSPORT OFFICE 12 MAIN STREET ZENITH",
+            "<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<",
+            "Signature of bearer / Signature du titulaire",
+        ];
+        // Fifteen lines of page text around the two MRZ lines
+        assert_eq!(page.len(), 17);
+        let text = page.join("\n");
+        let result = parse_with_options(&text, reference(), &MrzOptions::default()).unwrap();
+        assert_eq!(result.document_type, "TD3");
+        assert_eq!(result.raw_mrz, TD3);
+        assert_eq!(result.document_number, "L898902C3");
+        assert_eq!(result.given_names, "ANNA MARIA");
+        assert!(result.check_digits.valid, "{:?}", result.check_digits);
+
+        let strict = MrzOptions { strict: true, ..MrzOptions::default() };
+        assert!(parse_with_options(&text, reference(), &strict).is_err());
+    }
+
+
+    #[test]
+    fn non_ascii_characters_are_listed_instead_of_panicking() {
+        let strict = MrzOptions { strict: true, ..MrzOptions::default() };
+        for options in [MrzOptions::default(), strict] {
+            let error = |lines: &[String]| {
+                parse_with_options(&lines.join("\n"), reference(), &options).unwrap_err()
+            };
+            for specimen in [&TD1[..], &TD3[..]] {
+                let positions = specimen.iter().enumerate().flat_map(|(index, line)| {
+                    (0..line.len()).map(move |pos| (index, pos))
+                });
+                let cases = positions.flat_map(|at| ['É', '«', '😀'].map(|c| (at, c)));
+                for ((index, pos), c) in cases {
+                    let mut lines: Vec<String> = specimen.iter().map(|l| l.to_string()).collect();
+                    lines[index].replace_range(pos..pos + 1, &c.to_string());
+                    assert_eq!(error(&lines), MrzError::InvalidMrzCharacters(vec![c]));
+                }
+            }
+
+            // Lowercase diacritics are uppercased first; each character is listed once
+            let line1 = TD3[0].replacen("ERIKSSON", "érikssön", 1);
+            let line2 = TD3[1].replacen("ZE", "É😀", 1);
+            let listed = vec!['É', 'Ö', '😀'];
+            assert_eq!(error(&[line1, line2]), MrzError::InvalidMrzCharacters(listed));
+        }
+    }
+
+    #[test]
+    fn generated_lines_parse_back_to_their_fields() {
+        let fields = |code: &str, number: &str, optional: &str| MrzFields {
+            document_code: code.to_string(),
+            issuing_country: "UTO".to_string(),
+            surname: "ERIKSSON".to_string(),
+            given_names: "ANNA MARIA".to_string(),
+            document_number: number.to_string(),
+            nationality: "UTO".to_string(),
+            date_of_birth: "740812".to_string(),
+            sex: "F".to_string(),
+            date_of_expiry: "120415".to_string(),
+            optional_data: optional.to_string(),
+            personal_number: String::new(),
+        };
+        let cases = [
+            ("TD1", fields("I", "D23145890", "ABC123")),
+            // Twelve characters: the number continues into the optional data
+            ("TD1", fields("ID", "D23145890734", "XY12")),
+            ("TD2", fields("I", "D23145890", "")),
+            ("TD3", fields("P", "L898902C3", "ZE184226B")),
+        ];
+        let options = MrzOptions { decode_optional_data: false, ..at_level(CorrectionLevel::Off) };
+        for (format, fields) in cases {
+            let generated = generate_mrz_lines(&fields, format).unwrap();
+            let result = parse_with_options(&generated.text, reference(), &options).unwrap();
+            assert_eq!(result.document_type, format);
+            assert_eq!(result.raw_mrz, generated.lines);
+            assert!(result.check_digits.valid, "{:?}", result.check_digits);
+            let parsed = [
+                &result.document_code,
+                &result.issuing_country,
+                &result.surname,
+                &result.given_names,
+                &result.document_number,
+                &result.nationality,
+                &result.date_of_birth,
+                &result.sex,
+                &result.date_of_expiry,
+                &result.optional_data,
+            ];
+            let given = [
+                &fields.document_code,
+                &fields.issuing_country,
+                &fields.surname,
+                &fields.given_names,
+                &fields.document_number,
+                &fields.nationality,
+                &fields.date_of_birth,
+                &fields.sex,
+                &fields.date_of_expiry,
+                &fields.optional_data,
+            ];
+            assert_eq!(parsed, given, "{}", format);
+        }
+    }
+
+    #[test]
+    fn names_transliterate_per_9303_part_3() {
+        let vectors = [
+            ("Ångström", "AANGSTROEM"),
+            ("Müller-Lüdenscheidt", "MUELLER<LUEDENSCHEIDT"),
+            ("Øster Æbelø", "OESTER<AEBELOE"),
+            ("Straße", "STRASSE"),
+            ("Þórunn Guðmundsdóttir", "THORUNN<GUDMUNDSDOTTIR"),
+            ("François D'Arçy", "FRANCOIS<DARCY"),
+            ("Łukasz Żółć", "LUKASZ<ZOLC"),
+            ("Œuvray Ĳsselmeer", "OEUVRAY<IJSSELMEER"),
+            ("Núñez", "NUNEZ"),
+        ];
+        for (name, expected) in vectors {
+            assert_eq!(transliterate_name(name), (expected.to_string(), Vec::new()), "{}", name);
+        }
+        // Characters with no Latin base are dropped and listed, not an error
+        let dropped = transliterate_name("Anna 李 🙂");
+        assert_eq!(dropped, ("ANNA<<".to_string(), vec!['李', '🙂']));
+
+        // Generation transliterates too, recording what it dropped
+        let fields = MrzFields {
+            document_code: "P".to_string(),
+            surname: "Øster".to_string(),
+            given_names: "Ångström 李".to_string(),
+            ..MrzFields::default()
+        };
+        let generated = generate_mrz_lines(&fields, "TD3").unwrap();
+        assert!(generated.lines[0].starts_with("P<<<<OESTER<<AANGSTROEM<<"), "{:?}", generated);
+        assert_eq!(generated.warnings.len(), 1);
+        assert!(generated.warnings[0].contains("'李'"), "{:?}", generated.warnings);
+    }
+
+    #[test]
+    fn bac_keys_match_the_9303_part_11_worked_example() {
+        let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{:02X}", b)).collect::<String>();
+        let fields = |number: &str| BacFields {
+            document_number: number.to_string(),
+            date_of_birth: "690806".to_string(),
+            date_of_expiry: "940623".to_string(),
+        };
+        let information = bac_mrz_information(&fields("L898902C<")).unwrap();
+        assert_eq!(information, "L898902C<369080619406236");
+        let keys = derive_bac_keys(&fields("L898902C<")).unwrap();
+        assert_eq!(hex(&keys.key_seed), "239AB9CB282DAF66231DC5A4DF6BFBAE");
+        assert_eq!(hex(&keys.k_enc), "AB94FDECF2674FDFB9B391F85D7F76F2");
+        assert_eq!(hex(&keys.k_mac), "7962D9ECE03D1ACD4C76089DCE131543");
+
+        // A short number is padded with `<` to 9 characters before hashing
+        let padded = derive_bac_keys(&fields("L898902C")).unwrap();
+        assert_eq!(hex(&padded.key_seed), hex(&keys.key_seed));
+        assert_eq!(bac_mrz_information(&fields("L898902C")).unwrap(), information);
+    }
+
+    #[test]
+    fn status_counts_distinct_failing_fields() {
+        use MrzWarningCode::*;
+        let status = |warnings: &[(MrzWarningCode, &str)]| {
+            let warnings: Vec<MrzWarning> =
+                warnings.iter().map(|&(code, field)| MrzWarning::new(code, field, "")).collect();
+            MrzStatus::from_warnings(&warnings)
+        };
+        assert_eq!(status(&[]), MrzStatus::Valid);
+
+        // One failing field, however many warnings it has
+        assert_eq!(status(&[(CheckDigitMismatch, "date_of_birth")]), MrzStatus::Suspect);
+        let one_field = [(CheckDigitMismatch, "date_of_birth"), (InvalidDate, "date_of_birth")];
+        assert_eq!(status(&one_field), MrzStatus::Suspect);
+        assert_eq!(status(&[(InvalidSex, "sex")]), MrzStatus::Suspect);
+        assert_eq!(status(&[(ImplausibleDocumentNumber, "document_number")]), MrzStatus::Suspect);
+
+        // Warnings that never count towards invalid, alone or together
+        assert_eq!(status(&[(UnknownCountry, "nationality")]), MrzStatus::Suspect);
+        assert_eq!(status(&[(CheckDigitMismatch, "composite")]), MrzStatus::Suspect);
+        assert_eq!(status(&[(UnexpectedLayout, "layout")]), MrzStatus::Suspect);
+        let uncounted = [
+            (UnknownCountry, "issuing_country"),
+            (UnknownCountry, "nationality"),
+            (CheckDigitMismatch, "composite"),
+            (UnexpectedLayout, "layout"),
+        ];
+        assert_eq!(status(&uncounted), MrzStatus::Suspect);
+        let with_one_field = [(CheckDigitMismatch, "composite"), (InvalidCharset, "surname")];
+        assert_eq!(status(&with_one_field), MrzStatus::Suspect);
+
+        // Two distinct failing fields
+        let two_fields = [(CheckDigitMismatch, "date_of_birth"), (InvalidCharset, "surname")];
+        assert_eq!(status(&two_fields), MrzStatus::Invalid);
+        let two_dates = [(InvalidDate, "date_of_birth"), (InvalidDate, "date_of_expiry")];
+        assert_eq!(status(&two_dates), MrzStatus::Invalid);
+
+        // The same rules on parsed results: the composite fails along with each date
+        let one_bad = TD3[1].replacen("7408122", "7408123", 1);
+        let result = parse_at(&[TD3[0], &one_bad], &at_level(CorrectionLevel::Off));
+        assert_eq!(result.status, MrzStatus::Suspect, "{:?}", result.warnings);
+        let two_bad = one_bad.replacen("1204159", "1204150", 1);
+        let result = parse_at(&[TD3[0], &two_bad], &at_level(CorrectionLevel::Off));
+        assert_eq!(result.status, MrzStatus::Invalid, "{:?}", result.warnings);
+    }
+
+    #[test]
+    fn sex_characters_normalize_and_keep_the_raw_character() {
+        let cases = [
+            ('M', "M", "M"),
+            ('F', "F", "F"),
+            ('X', "X", "X"),
+            ('<', "<", "X"),
+            ('m', "M", "M"),
+            ('f', "F", "F"),
+            ('H', "H", "unknown"),
+            ('N', "N", "unknown"),
+            ('1', "1", "unknown"),
+        ];
+        let replaced = |line: &str, pos: usize, c: char| -> String {
+            line.chars().enumerate().map(|(i, found)| if i == pos { c } else { found }).collect()
+        };
+        for (c, raw, sex) in cases {
+            let td3 = parse_at(&[TD3[0], &replaced(TD3[1], 20, c)], &MrzOptions::default());
+            let td1_lines = [TD1[0], &replaced(TD1[1], 7, c), TD1[2]];
+            let td1 = parse_at(&td1_lines, &MrzOptions::default());
+            for (result, pos) in [(td3, 20), (td1, 7)] {
+                assert_eq!((result.sex_raw.as_str(), result.sex.as_str()), (raw, sex), "{:?}", c);
+                // The raw character stays in the line the check digits are computed from
+                assert_eq!(result.raw_mrz[1].chars().nth(pos), raw.chars().next());
+                assert!(result.check_digits.valid, "{:?}: {:?}", c, result.check_digits);
+                let sex_warnings: Vec<&MrzWarning> = result
+                    .warnings
+                    .iter()
+                    .filter(|warning| warning.code == MrzWarningCode::InvalidSex)
+                    .collect();
+                assert_eq!(sex_warnings.len(), usize::from(sex == "unknown"), "{:?}", c);
+                if let Some(warning) = sex_warnings.first() {
+                    assert_eq!((warning.field.as_str(), warning.value.as_str()), ("sex", raw));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn french_identity_cards_parse_as_fr_cni() {
+        let lines = [
+            "IDFRADOUEL<<<<<<<<<<<<<<<<<<<<932013",
+            "0506932020438CHRISTIANE<<NI2906209F3",
+        ];
+        let result = parse_at(&lines, &at_level(CorrectionLevel::Off));
+        assert_eq!(result.document_type, "FR_CNI");
+        assert_eq!((result.document_code.as_str(), result.issuing_country.as_str()), ("ID", "FRA"));
+        assert_eq!(result.document_number, "050693202043");
+        assert_eq!(result.surname, "DOUEL");
+        assert_eq!(result.given_names_list, ["CHRISTIANE", "NI"]);
+        assert_eq!((result.date_of_birth.as_str(), result.sex.as_str()), ("290620", "F"));
+        assert_eq!((result.nationality.as_str(), result.date_of_expiry.as_str()), ("FRA", ""));
+        // Department 932, office 013
+        assert_eq!(result.optional_data, "932013");
+        let checks = &result.check_digits;
+        assert!(checks.valid, "{:?}", checks);
+        assert_eq!(checks.composite, Some(true));
+        assert_eq!(result.field_spans.document_number, span(1, 0, 12));
+        assert_eq!(result.field_spans.given_names, span(1, 13, 27));
+
+        // A broken composite digit still fails only the composite check
+        let line2 = lines[1].replacen("F3", "F4", 1);
+        let result = parse_at(&[lines[0], &line2], &at_level(CorrectionLevel::Off));
+        assert_eq!(result.document_type, "FR_CNI");
+        assert_eq!(result.check_digits.composite, Some(false));
+
+        // An `IDFRA` line whose TD2 check digits pass is an ordinary TD2
+        let td2 = ["IDFRAERIKSSON<<ANNA<MARIA<<<<<<<<<<<", TD2[1]];
+        assert_eq!(parse_at(&td2, &at_level(CorrectionLevel::Off)).document_type, "TD2");
+    }
+
+    #[test]
+    fn names_split_without_a_separator_and_across_filler_runs() {
+        let names = |field: &str| {
+            let names = extract_names(field);
+            (names.surname, names.given_names, names.truncated)
+        };
+        let given = |list: &[&str]| list.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+        // No `<<`: the whole field is the surname
+        assert_eq!(names("ERIKSSON<<<<<<<<"), ("ERIKSSON".to_string(), given(&[]), false));
+        assert_eq!(names("DE<LA<TOUR<<<<<<"), ("DE LA TOUR".to_string(), given(&[]), false));
+        assert_eq!(names("ERIKSSON"), ("ERIKSSON".to_string(), given(&[]), true));
+        // Runs of filler between given names, and before the first one
+        let expected = ("ERIKSSON".to_string(), given(&["ANNA", "MARIA"]), false);
+        assert_eq!(names("ERIKSSON<<ANNA<<MARIA<<<<"), expected);
+        assert_eq!(names("ERIKSSON<<<ANNA<<<<MARIA<"), expected);
+        assert_eq!(names("<<ANNA<MARIA<<<<"), (String::new(), given(&["ANNA", "MARIA"]), false));
+        assert_eq!(names("<<<<<<<<"), (String::new(), given(&[]), false));
+
+        // A name field filled to the last character was probably truncated
+        let full = "ERIKSSON<<ANNA<MARIA<KRISTINA<ELISABETH";
+        assert_eq!(full.len(), 39);
+        let result = parse_at(&[&format!("P<UTO{}", full), TD3[1]], &MrzOptions::default());
+        assert_eq!(result.given_names_list, ["ANNA", "MARIA", "KRISTINA", "ELISABETH"]);
+        assert!(result.name_truncated);
+        assert!(!parse_at(&TD3, &MrzOptions::default()).name_truncated);
+    }
+
+
+    #[test]
+    fn confidence_falls_with_every_corrupted_field() {
+        // SWE: a known country whose number format the specimen number fits
+        let mut lines: Vec<Vec<char>> =
+            TD3.iter().map(|line| line.replacen("UTO", "SWE", 1).chars().collect()).collect();
+        let parse = |lines: &[Vec<char>]| {
+            let lines: Vec<String> = lines.iter().map(|line| line.iter().collect()).collect();
+            let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+            parse_at(&lines, &at_level(CorrectionLevel::Off))
+        };
+        let clean = parse(&lines);
+        assert_eq!(clean.confidence, 1.0, "{:?}", clean.field_confidence);
+
+        // Each step corrupts one more field, cumulatively
+        let steps = [
+            ((0, 11), '0', "names"),
+            ((1, 12), 'Q', "nationality"),
+            ((0, 4), 'Q', "issuing_country"),
+            ((1, 42), '5', "personal_number"),
+            ((1, 19), '3', "date_of_birth"),
+            ((1, 27), '0', "date_of_expiry"),
+            ((1, 2), '7', "document_number"),
+        ];
+        let mut previous = clean.confidence;
+        for ((line, pos), c, field) in steps {
+            lines[line][pos] = c;
+            let result = parse(&lines);
+            let scores = &result.field_confidence;
+            let confidence = result.confidence;
+            assert!(confidence < previous, "{}: {} after {}", field, confidence, previous);
+            assert!(scores[field] < 1.0, "{}: {:?}", field, scores);
+            previous = confidence;
+        }
+        // Only the mostly-letter names still score anything
+        assert!(previous > 0.0 && previous < 0.1, "{}", previous);
+    }
+
+
+    #[test]
+    fn td1_sanity_failures_try_alternate_layouts() {
+        let td1: Vec<String> = TD1.iter().map(|line| line.replacen("UTO", "SWE", 1)).collect();
+        let strict = MrzOptions { strict: true, ..at_level(CorrectionLevel::Off) };
+        for options in [at_level(CorrectionLevel::Off), strict] {
+            // Lines 2 and 3 swapped by the OCR engine
+            let result = parse_at(&[&td1[0], &td1[2], &td1[1]], &options);
+            assert_eq!(result.raw_mrz, td1);
+            assert_eq!(result.corrections, ["layout: lines reordered 1, 3, 2"]);
+            assert!(result.warnings.is_empty(), "{:?}", result.warnings);
+            assert_eq!(result.status, MrzStatus::Valid);
+
+            // Line 2 one character short in the middle, realigned
+            let short = format!("{}{}", &td1[1][..3], &td1[1][4..]);
+            let result = parse_at(&[&td1[0], &short, &td1[2]], &options);
+            assert_eq!(result.raw_mrz, td1);
+            assert_eq!(result.corrections, ["layout: '8' inserted at line 2, position 4"]);
+            assert_eq!(result.status, MrzStatus::Valid);
+
+            // No layout fits: the TD1 reading stands, flagged
+            let unreadable = [
+                "ABCDEFGHIJKLMNOPQRSTUVWXYZ0123",
+                "ZYXWVUTSRQPONMLKJIHGFEDCBA9876",
+                "QWERTYUIOPASDFGHJKLZXCVBNM<<<<",
+            ];
+            let result = parse_at(&unreadable, &options);
+            assert_eq!(result.document_type, "TD1");
+            assert_eq!(result.raw_mrz, unreadable);
+            let layout: Vec<&MrzWarning> = result
+                .warnings
+                .iter()
+                .filter(|warning| warning.code == MrzWarningCode::UnexpectedLayout)
+                .collect();
+            assert_eq!(layout.len(), 1, "{:?}", result.warnings);
+            assert_eq!(layout[0].value, "sex, dates, countries, check_digits");
+            assert_eq!(result.status, MrzStatus::Invalid);
+
+            // UTO and a bad sex fail two checks, but only the sex counts towards invalid
+            let line2 = TD1[1].replacen("F", "H", 1);
+            let result = parse_at(&[TD1[0], &line2, TD1[2]], &options);
+            let codes: Vec<MrzWarningCode> = result.warnings.iter().map(|w| w.code).collect();
+            assert!(codes.contains(&MrzWarningCode::UnexpectedLayout), "{:?}", codes);
+            assert_eq!(result.status, MrzStatus::Suspect, "{:?}", result.warnings);
+        }
+    }
+
+    #[test]
+    fn validation_agrees_with_the_parser() {
+        let td3_bad_birth = TD3[1].replacen("7408122", "7408123", 1);
+        let td1_bad_number = TD1[0].replacen("D23145890", "D23146890", 1);
+        let inputs: Vec<Vec<&str>> = vec![
+            TD1.to_vec(),
+            TD2.to_vec(),
+            TD3.to_vec(),
+            vec![TD3[0], &td3_bad_birth],
+            vec![&td1_bad_number, TD1[1], TD1[2]],
+            vec!["V<UTOERIKSSON<<ANNA<MARIA<<<<<<<<<<<", "L8988901C4XXX4009078F9612109<<<<<<<<"],
+            vec!["IDFRADOUEL<<<<<<<<<<<<<<<<<<<<932013", "0506932020438CHRISTIANE<<NI2906209F3"],
+        ];
+        let strict = MrzOptions { strict: true, ..at_level(CorrectionLevel::Off) };
+        for lines in inputs {
+            let owned: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+            let validation = validate_mrz_lines(&owned).unwrap();
+            let result = parse_at(&lines, &strict);
+            let checks = &result.check_digits;
+            assert_eq!(validation.document_type, result.document_type);
+            assert_eq!(validation.composite_valid, checks.composite, "{:?}", lines);
+            assert_eq!(validation.valid, checks.valid, "{:?}", lines);
+            for report in &validation.check_digits {
+                let parsed = match report.field.as_str() {
+                    "document_number" => Some(checks.document_number),
+                    "date_of_birth" => Some(checks.date_of_birth),
+                    "date_of_expiry" => checks.date_of_expiry,
+                    "personal_number" => checks.personal_number,
+                    "composite" => checks.composite,
+                    field => panic!("unexpected check digit {}", field),
+                };
+                assert_eq!(parsed, Some(report.passed), "{} in {:?}", report.field, lines);
+                let present = result.raw_mrz[report.line].chars().nth(report.position);
+                assert_eq!(report.present, present.unwrap().to_string());
+            }
+        }
+
+        // Characters out of place are reported where the parser would correct them
+        let line1 = TD3[0].replacen("ERIKSSON", "eriks5on", 1);
+        let validation = validate_mrz_lines(&[line1, TD3[1].to_string()]).unwrap();
+        assert!(!validation.valid);
+        assert_eq!(validation.charset_violations.len(), 1, "{:?}", validation.charset_violations);
+        let violation = &validation.charset_violations[0];
+        assert_eq!((violation.line, violation.position), (0, 10));
+        assert_eq!((violation.character.as_str(), violation.allowed.as_str()), ("5", "A-Z<"));
+        assert_eq!(violation.field.as_deref(), Some("names"));
+        let line1 = TD3[0].replacen("ERIKSSON", "ERIKS5ON", 1);
+        let result = parse_at(&[&line1, TD3[1]], &MrzOptions::default());
+        assert_eq!(result.corrections.len(), 1, "{:?}", result.corrections);
+    }
+}
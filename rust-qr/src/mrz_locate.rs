@@ -0,0 +1,590 @@
+//! Locating the MRZ in a photo of a document, for `scan_document`, so OCR
+//! only has to read a crop. Text has a strong morphological gradient; MRZ
+//! lines are long runs of it, two or three of the same length and height
+//! stacked closely near the bottom of the document. Skewed documents are
+//! handled by shearing the rows at each angle in `-MAX_SKEW..=MAX_SKEW`.
+
+use image::imageops::{self, FilterType};
+use image::GrayImage;
+use serde::{Deserialize, Serialize};
+
+use crate::qr;
+
+/// Likely MRZ, in image pixels
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MrzRegion {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+    pub confidence: f32,    // 0 to 1, see `stack_confidence`
+    pub lines: u32,         // 2 (TD2, TD3, visas) or 3 (TD1)
+    pub angle_degrees: f32, // Skew of the lines, clockwise positive
+}
+
+/// Detection runs on a copy downscaled to at most this width
+const WORKING_WIDTH: u32 = 640;
+
+/// Largest skew tried, in whole degrees either way
+const MAX_SKEW: i32 = 12;
+
+/// Gradient below this never counts as an edge, whatever Otsu says
+const MIN_EDGE_GRADIENT: u8 = 24;
+
+/// Edges per pixel along a row of text; rows below a third of it are not text
+const TEXT_EDGE_DENSITY: f32 = 0.15;
+
+/// Length over height of a line of 30 characters, the shortest MRZ line
+const MIN_LINE_ASPECT: f32 = 10.0;
+
+/// Regions scoring below this are not reported
+const MIN_CONFIDENCE: f32 = 0.6;
+
+/// Likely MRZ regions of `gray`, most confident first
+pub fn find_mrz_regions(gray: &GrayImage) -> Vec<MrzRegion> {
+    let (width, height) = gray.dimensions();
+    if width < 32 || height < 32 {
+        return Vec::new();
+    }
+    let scale = width.div_ceil(WORKING_WIDTH);
+    let resized;
+    let working = if scale > 1 {
+        resized = imageops::resize(gray, width / scale, height / scale, FilterType::Triangle);
+        &resized
+    } else {
+        gray
+    };
+    let (w, h) = (working.width() as usize, working.height() as usize);
+    let edges = edge_mask(working);
+
+    let mut regions = Vec::new();
+    for degrees in -MAX_SKEW..=MAX_SKEW {
+        let slope = (degrees as f32).to_radians().tan();
+        let bands = line_bands(&edges, w, h, slope);
+        for stack in stacks(&bands) {
+            let confidence = stack_confidence(stack, h);
+            if confidence >= MIN_CONFIDENCE {
+                regions.push(region(stack, slope, (w, h), (width, height), confidence, degrees));
+            }
+        }
+    }
+
+    // The same MRZ is found at neighbouring angles and as sub-stacks
+    regions.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+    let mut kept: Vec<MrzRegion> = Vec::new();
+    for region in regions {
+        if kept.iter().all(|other| overlap(&region, other) < 0.3) {
+            kept.push(region);
+        }
+    }
+    kept
+}
+
+/// Pixels on a strong edge: the 3x3 morphological gradient (dilation minus
+/// erosion) above the Otsu threshold of its histogram
+fn edge_mask(gray: &GrayImage) -> Vec<bool> {
+    let (w, h) = (gray.width() as usize, gray.height() as usize);
+    let pixels = gray.as_raw();
+
+    // Separable: 3-wide maximum and minimum across, then down
+    let (mut row_max, mut row_min) = (vec![0u8; w * h], vec![0u8; w * h]);
+    for y in 0..h {
+        let row = &pixels[y * w..(y + 1) * w];
+        for x in 0..w {
+            let window = &row[x.saturating_sub(1)..(x + 2).min(w)];
+            row_max[y * w + x] = window.iter().copied().max().unwrap_or(0);
+            row_min[y * w + x] = window.iter().copied().min().unwrap_or(0);
+        }
+    }
+    let mut gradient = vec![0u8; w * h];
+    for y in 0..h {
+        let rows = y.saturating_sub(1)..(y + 2).min(h);
+        for x in 0..w {
+            let high = rows.clone().map(|r| row_max[r * w + x]).max().unwrap_or(0);
+            let low = rows.clone().map(|r| row_min[r * w + x]).min().unwrap_or(0);
+            gradient[y * w + x] = high - low;
+        }
+    }
+
+    let threshold = GrayImage::from_raw(w as u32, h as u32, gradient.clone())
+        .map_or(MIN_EDGE_GRADIENT, |image| qr::otsu_threshold(&image))
+        .max(MIN_EDGE_GRADIENT);
+    gradient.iter().map(|&g| g > threshold).collect()
+}
+
+/// One line of text: consecutive sheared rows whose longest run of edges
+/// spans a good part of the image
+struct Band {
+    top: i32,
+    bottom: i32, // Inclusive
+    start: usize,
+    end: usize,         // Exclusive; start and end are medians over the rows
+    edge_density: f32, // Off-to-on transitions per pixel of the runs
+    fill: f32,         // Fraction of the rows running nearly the whole length
+}
+
+impl Band {
+    fn height(&self) -> f32 {
+        (self.bottom - self.top + 1) as f32
+    }
+
+    fn length(&self) -> f32 {
+        (self.end - self.start) as f32
+    }
+}
+
+/// Edge pixels along a sheared row, allowing gaps between characters
+struct Run {
+    start: usize,
+    end: usize,
+    transitions: usize, // Off-to-on, roughly two per character stroke
+}
+
+/// Lines of text in the rows sheared by `slope`: row `r` follows the pixels
+/// `(x, r + x * slope)`, which is horizontal in a document skewed by `slope`
+fn line_bands(edges: &[bool], width: usize, height: usize, slope: f32) -> Vec<Band> {
+    let shift: Vec<i32> = (0..width).map(|x| (x as f32 * slope).round() as i32).collect();
+    let (low, high) = (shift.iter().min().copied(), shift.iter().max().copied());
+    let (low, high) = (low.unwrap_or(0), high.unwrap_or(0));
+    let gap = (width / 40).max(4);
+    let min_length = width / 5;
+
+    let mut bands = Vec::new();
+    let mut rows: Vec<(i32, Run)> = Vec::new();
+    let mut missed = 0;
+    for row in -high..height as i32 - low {
+        let run = longest_run(edges, width, height, row, &shift, gap).filter(|run| {
+            let length = run.end - run.start;
+            let dense = run.transitions as f32 >= length as f32 * TEXT_EDGE_DENSITY / 3.0;
+            length >= min_length && dense
+        });
+        match run {
+            Some(run) => {
+                rows.push((row, run));
+                missed = 0;
+            }
+            // A single row without a run does not split a line
+            None if !rows.is_empty() => {
+                missed += 1;
+                if missed > 1 {
+                    bands.extend(close_band(&mut rows));
+                }
+            }
+            None => {}
+        }
+    }
+    bands.extend(close_band(&mut rows));
+    bands
+}
+
+/// Longest run of edge pixels along one sheared row, where gaps up to
+/// `gap` pixels (the space between two characters) do not end a run
+fn longest_run(
+    edges: &[bool],
+    width: usize,
+    height: usize,
+    row: i32,
+    shift: &[i32],
+    gap: usize,
+) -> Option<Run> {
+    let mut best: Option<Run> = None;
+    let mut current: Option<Run> = None;
+    let mut last_on = 0;
+    let mut previous_on = false;
+    for (x, &dy) in shift.iter().enumerate() {
+        let y = row + dy;
+        let on = (0..height as i32).contains(&y) && edges[y as usize * width + x];
+        if on {
+            if current.as_ref().is_some_and(|_| x - last_on > gap) {
+                keep_longer(&mut best, current.take());
+            }
+            let run = current.get_or_insert(Run { start: x, end: x, transitions: 0 });
+            if !previous_on {
+                run.transitions += 1;
+            }
+            run.end = x + 1;
+            last_on = x;
+        }
+        previous_on = on;
+    }
+    keep_longer(&mut best, current);
+    best
+}
+
+fn keep_longer(best: &mut Option<Run>, run: Option<Run>) {
+    let Some(run) = run else { return };
+    if best.as_ref().is_none_or(|best| run.end - run.start > best.end - best.start) {
+        *best = Some(run);
+    }
+}
+
+/// Turn the collected rows into a band, if there are enough of them
+fn close_band(rows: &mut Vec<(i32, Run)>) -> Option<Band> {
+    let taken = std::mem::take(rows);
+    if taken.len() < 3 {
+        return None;
+    }
+    let median = |mut values: Vec<usize>| {
+        values.sort_unstable();
+        values[values.len() / 2]
+    };
+    let length: usize = taken.iter().map(|(_, run)| run.end - run.start).sum();
+    let transitions: usize = taken.iter().map(|(_, run)| run.transitions).sum();
+    let start = median(taken.iter().map(|(_, run)| run.start).collect());
+    let end = median(taken.iter().map(|(_, run)| run.end).collect());
+    // At the wrong angle the rows near the top and bottom of a line only
+    // cross part of it
+    let full = taken.iter().filter(|(_, run)| (run.end - run.start) * 10 >= (end - start) * 9);
+    Some(Band {
+        top: taken[0].0,
+        bottom: taken[taken.len() - 1].0,
+        start,
+        end,
+        edge_density: transitions as f32 / length as f32,
+        fill: full.count() as f32 / taken.len() as f32,
+    })
+}
+
+/// Runs of two or three bands that could be one MRZ: each line within 1.5
+/// line heights of the previous one, of a similar height and extent
+fn stacks(bands: &[Band]) -> Vec<&[Band]> {
+    let mut stacks = Vec::new();
+    for start in 0..bands.len() {
+        for count in [2, 3] {
+            let Some(stack) = bands.get(start..start + count) else {
+                continue;
+            };
+            if stack.windows(2).all(|pair| stacked(&pair[0], &pair[1])) {
+                stacks.push(stack);
+            }
+        }
+    }
+    stacks
+}
+
+fn stacked(above: &Band, below: &Band) -> bool {
+    let gap = (below.top - above.bottom) as f32;
+    let (short, tall) = min_max(above.height(), below.height());
+    gap <= 1.5 * tall && short >= 0.5 * tall && extent_overlap(&[above, below]) >= 0.7
+}
+
+/// Intersection over union of the bands' horizontal extents
+fn extent_overlap(bands: &[&Band]) -> f32 {
+    let start_max = bands.iter().map(|band| band.start).max().unwrap_or(0);
+    let start_min = bands.iter().map(|band| band.start).min().unwrap_or(0);
+    let end_max = bands.iter().map(|band| band.end).max().unwrap_or(0);
+    let end_min = bands.iter().map(|band| band.end).min().unwrap_or(0);
+    end_min.saturating_sub(start_max) as f32 / (end_max - start_min).max(1) as f32
+}
+
+fn min_max(a: f32, b: f32) -> (f32, f32) {
+    (a.min(b), a.max(b))
+}
+
+/// How much a stack looks like an MRZ, from 0 to 1, weighting:
+/// - 0.2 lines of the same extent (every MRZ line is filled to its length)
+/// - 0.15 lines of the same height, 0.1 evenly spaced
+/// - 0.15 rows crossing the whole line, which peaks at the right angle
+/// - 0.1 lines long for their height, 0.1 dense with edges like text
+/// - 0.1 the last line low in the image, 0.1 three lines rather than two
+fn stack_confidence(stack: &[Band], image_height: usize) -> f32 {
+    let bands: Vec<&Band> = stack.iter().collect();
+    let count = bands.len() as f32;
+    let heights = bands.iter().map(|band| band.height());
+    let (short, tall) = heights.fold((f32::MAX, 0.0f32), |(lo, hi), h| (lo.min(h), hi.max(h)));
+    let gaps: Vec<f32> =
+        stack.windows(2).map(|pair| (pair[1].top - pair[0].bottom) as f32).collect();
+    let spacing = match gaps.as_slice() {
+        [first, second] => (first.min(*second) + 1.0) / (first.max(*second) + 1.0),
+        _ => 1.0,
+    };
+    let length = bands.iter().map(|band| band.length()).sum::<f32>() / count;
+    let height = bands.iter().map(|band| band.height()).sum::<f32>() / count;
+    let density = bands.iter().map(|band| band.edge_density).sum::<f32>() / count;
+    let fill = bands.iter().map(|band| band.fill).sum::<f32>() / count;
+    let bottom = stack[stack.len() - 1].bottom as f32 / image_height as f32;
+
+    0.2 * extent_overlap(&bands)
+        + 0.15 * short / tall
+        + 0.1 * spacing
+        + 0.15 * fill
+        + 0.1 * (length / height / MIN_LINE_ASPECT).min(1.0)
+        + 0.1 * (density / TEXT_EDGE_DENSITY).min(1.0)
+        + 0.1 * bottom.clamp(0.0, 1.0)
+        + 0.1 * (count - 1.0) / 2.0
+}
+
+/// Axis-aligned box around a stack, with a margin of one line height, in
+/// the pixels of the original image
+fn region(
+    stack: &[Band],
+    slope: f32,
+    (w, h): (usize, usize),
+    (width, height): (u32, u32),
+    confidence: f32,
+    degrees: i32,
+) -> MrzRegion {
+    let margin = stack.iter().map(|band| band.height()).fold(0.0, f32::max);
+    let start = stack.iter().map(|band| band.start).min().unwrap_or(0) as f32 - margin;
+    let end = stack.iter().map(|band| band.end).max().unwrap_or(0) as f32 + margin;
+    let top = stack[0].top as f32 - margin;
+    let bottom = stack[stack.len() - 1].bottom as f32 + margin;
+    // Sheared rows run along the skew, so the corners move by x * slope
+    let ys = [start, end].map(|x| [top + x * slope, bottom + x * slope]).concat();
+    let y0 = ys.iter().copied().fold(f32::MAX, f32::min).max(0.0);
+    let y1 = ys.iter().copied().fold(f32::MIN, f32::max).min(h as f32);
+    let (x0, x1) = (start.max(0.0), end.min(w as f32));
+
+    let (sx, sy) = (width as f32 / w as f32, height as f32 / h as f32);
+    let x = (x0 * sx) as u32;
+    let y = (y0 * sy) as u32;
+    MrzRegion {
+        x,
+        y,
+        w: ((x1 * sx) as u32).min(width).saturating_sub(x),
+        h: ((y1 * sy) as u32).min(height).saturating_sub(y),
+        confidence,
+        lines: stack.len() as u32,
+        angle_degrees: degrees as f32,
+    }
+}
+
+/// Intersection over the smaller area, so a sub-stack inside a full MRZ
+/// counts as the same region
+fn overlap(a: &MrzRegion, b: &MrzRegion) -> f32 {
+    let ix = (a.x + a.w).min(b.x + b.w).saturating_sub(a.x.max(b.x));
+    let iy = (a.y + a.h).min(b.y + b.h).saturating_sub(a.y.max(b.y));
+    let smaller = (a.w * a.h).min(b.w * b.h).max(1);
+    (ix * iy) as f32 / smaller as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic pseudo-random numbers for the fixtures
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (self.0 >> 33) as usize % n
+        }
+    }
+
+    /// (x, y, w, h) in pixels
+    type Rect = (f32, f32, f32, f32);
+
+    /// A flat, light document with dark blocky glyphs standing in for text
+    struct Document {
+        width: usize,
+        height: usize,
+        pixels: Vec<u8>,
+    }
+
+    impl Document {
+        fn new(width: usize, height: usize) -> Self {
+            Document { width, height, pixels: vec![232; width * height] }
+        }
+
+        fn fill(&mut self, x: usize, y: usize, w: usize, h: usize, luma: u8) {
+            for row in y..(y + h).min(self.height) {
+                for column in x..(x + w).min(self.width) {
+                    self.pixels[row * self.width + column] = luma;
+                }
+            }
+        }
+
+        /// `c` in a `w` x `h` cell: a chevron for the `<` filler, otherwise
+        /// a 5x7 block pattern seeded by the character
+        fn glyph(&mut self, c: char, x: usize, y: usize, w: usize, h: usize) {
+            if c == '<' {
+                let stroke = (w / 5).max(2);
+                for i in 0..h / 2 {
+                    let column = (x + i * (w - stroke) / (h / 2).max(1)).min(x + w - stroke);
+                    self.fill(column, y + h / 2 - i, stroke, 1, 20);
+                    self.fill(column, (y + h / 2 + i).min(y + h - 1), stroke, 1, 20);
+                }
+                return;
+            }
+            let mut random = Lcg(c as u64 * 7919 + 13);
+            let (block_w, block_h) = (w / 5, h / 7);
+            let mut any = false;
+            for row in 0..7 {
+                for column in 0..5 {
+                    if random.below(100) < 45 {
+                        self.fill(x + column * block_w, y + row * block_h, block_w, block_h, 25);
+                        any = true;
+                    }
+                }
+            }
+            if !any {
+                self.fill(x, y, w, block_h, 25);
+            }
+        }
+
+        fn text(&mut self, text: &str, x: usize, y: usize, glyph: (usize, usize), pitch: usize) {
+            for (i, c) in text.chars().enumerate().filter(|(_, c)| *c != ' ') {
+                self.glyph(c, x + i * pitch, y, glyph.0, glyph.1);
+            }
+        }
+
+        /// A portrait: a light oval face on a darker gradient
+        fn photo(&mut self, x: usize, y: usize, w: usize, h: usize) {
+            let (wf, hf) = (w as f32, h as f32);
+            for row in 0..h {
+                for column in 0..w {
+                    let dx = (column as f32 - wf / 2.0).powi(2) / wf.powi(2) * 4.0;
+                    let dy = (row as f32 - hf * 0.4).powi(2) / hf.powi(2) * 6.0;
+                    let distance = (dx + dy).sqrt();
+                    let luma = if distance < 0.5 {
+                        150.0 + 40.0 * distance
+                    } else {
+                        90.0 + 60.0 * row as f32 / hf
+                    };
+                    self.pixels[(y + row) * self.width + x + column] = luma as u8;
+                }
+            }
+        }
+
+        /// Rows of one to three random words, as in the visual zone
+        fn body_text(&mut self, seed: u64, x: usize, y: usize, rows: usize, max_width: usize) {
+            let alphabet: Vec<char> = "ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789".chars().collect();
+            let mut random = Lcg(seed);
+            for row in 0..rows {
+                let mut column = x;
+                for _ in 0..1 + random.below(3) {
+                    let length = 3 + random.below(8);
+                    if column + length * 13 > x + max_width {
+                        break;
+                    }
+                    let word: String = (0..length).map(|_| alphabet[random.below(36)]).collect();
+                    self.text(&word, column, y + row * 38, (10, 16), 13);
+                    column += length * 13 + 22 + random.below(30);
+                }
+            }
+        }
+    }
+
+    /// A TD3 (passport) data page and its MRZ
+    fn td3() -> (Document, Rect) {
+        let mut document = Document::new(880, 620);
+        document.text("PASSPORT", 300, 30, (20, 30), 28);
+        document.photo(40, 110, 220, 290);
+        document.body_text(5, 300, 110, 9, 540);
+        document.text("P<UTOERIKSSON<<ANNA<MARIA<<<<<<<<<<<<<<<<<<<", 44, 498, (12, 22), 18);
+        document.text("L898902C36UTO7408122F1204159ZE184226B<<<<<10", 44, 538, (12, 22), 18);
+        (document, (44.0, 498.0, 44.0 * 18.0, 62.0))
+    }
+
+    /// The back of a TD1 (ID card) and its MRZ
+    fn td1() -> (Document, Rect) {
+        let mut document = Document::new(856, 540);
+        document.body_text(7, 40, 40, 7, 700);
+        let lines = [
+            "I<UTOD231458907<<<<<<<<<<<<<<<",
+            "7408122F1204159UTO<<<<<<<<<<<6",
+            "ERIKSSON<<ANNA<MARIA<<<<<<<<<<",
+        ];
+        for (i, line) in lines.iter().enumerate() {
+            document.text(line, 38, 352 + i * 54, (18, 30), 26);
+        }
+        (document, (38.0, 352.0, 30.0 * 26.0, 2.0 * 54.0 + 30.0))
+    }
+
+    /// `document` turned `degrees` clockwise on a dark, noisy 1100x900
+    /// background and slightly blurred, with the bounding box of `mrz` there
+    fn photograph(document: &Document, mrz: Rect, degrees: f32) -> (GrayImage, Rect) {
+        let (width, height) = (1100, 900);
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let (cx, cy) = (width as f32 / 2.0, height as f32 / 2.0);
+        let (dx, dy) = (document.width as f32 / 2.0, document.height as f32 / 2.0);
+        let mut noise = Lcg(99);
+        let photo = GrayImage::from_fn(width, height, |x, y| {
+            let (px, py) = (x as f32 - cx, y as f32 - cy);
+            let (qx, qy) = (cos * px + sin * py + dx, -sin * px + cos * py + dy);
+            let inside = qx >= 0.0 && qy >= 0.0;
+            let (qx, qy) = (qx as usize, qy as usize);
+            if inside && qx < document.width && qy < document.height {
+                image::Luma([document.pixels[qy * document.width + qx]])
+            } else {
+                image::Luma([60 + noise.below(20) as u8])
+            }
+        });
+
+        let (x, y, w, h) = mrz;
+        let corners = [(x, y), (x + w, y), (x, y + h), (x + w, y + h)].map(|(qx, qy)| {
+            let (ux, uy) = (qx - dx, qy - dy);
+            (cos * ux - sin * uy + cx, sin * ux + cos * uy + cy)
+        });
+        let (x0, x1) = corners.iter().fold((f32::MAX, f32::MIN), |(lo, hi), p| {
+            (lo.min(p.0), hi.max(p.0))
+        });
+        let (y0, y1) = corners.iter().fold((f32::MAX, f32::MIN), |(lo, hi), p| {
+            (lo.min(p.1), hi.max(p.1))
+        });
+        (imageops::blur(&photo, 1.0), (x0, y0, x1 - x0, y1 - y0))
+    }
+
+    /// The region covers the true MRZ box and is not much bigger
+    fn fits(region: &MrzRegion, truth: Rect) -> bool {
+        let (x, y, w, h) = (region.x as f32, region.y as f32, region.w as f32, region.h as f32);
+        let overlap_w = (x + w).min(truth.0 + truth.2) - x.max(truth.0);
+        let overlap_h = (y + h).min(truth.1 + truth.3) - y.max(truth.1);
+        let covered = overlap_w.max(0.0) * overlap_h.max(0.0) / (truth.2 * truth.3);
+        covered >= 0.95 && w * h <= 2.5 * truth.2 * truth.3
+    }
+
+    #[test]
+    fn finds_td3_and_td1_zones_at_any_skew() {
+        for (name, (document, mrz), lines) in [("TD3", td3(), 2), ("TD1", td1(), 3)] {
+            for degrees in [-10.0, -3.0, 0.0, 9.0] {
+                let (photo, truth) = photograph(&document, mrz, degrees);
+                let regions = find_mrz_regions(&photo);
+                let best = regions.first().unwrap_or_else(|| panic!("{} {}", name, degrees));
+                assert_eq!(best.lines, lines, "{} {}", name, degrees);
+                assert!(fits(best, truth), "{} {}: {:?} {:?}", name, degrees, best, truth);
+                assert!((best.angle_degrees - degrees).abs() <= 1.5, "{} {}", name, degrees);
+                assert!((MIN_CONFIDENCE..=1.0).contains(&best.confidence));
+            }
+        }
+    }
+
+    #[test]
+    fn small_photos_are_searched_at_full_resolution() {
+        let (document, mrz) = td3();
+        let (photo, truth) = photograph(&document, mrz, -6.0);
+        let small = imageops::resize(&photo, 620, 507, FilterType::Triangle);
+        let factor = 620.0 / 1100.0;
+        let truth = (truth.0 * factor, truth.1 * factor, truth.2 * factor, truth.3 * factor);
+        let regions = find_mrz_regions(&small);
+        assert!(fits(&regions[0], truth), "{:?} {:?}", regions, truth);
+        assert!(find_mrz_regions(&GrayImage::new(31, 400)).is_empty());
+    }
+
+    #[test]
+    fn documents_without_an_mrz_have_no_regions() {
+        let mut document = Document::new(880, 620);
+        document.photo(40, 110, 220, 290);
+        document.body_text(11, 300, 110, 9, 540);
+        let (photo, _) = photograph(&document, (0.0, 0.0, 1.0, 1.0), 4.0);
+        assert_eq!(find_mrz_regions(&photo), []);
+    }
+
+    #[test]
+    fn scan_document_reports_codes_and_regions() {
+        let (mut document, mrz) = td3();
+        let matrix = qr::encode_matrix("HELLO DOCUMENT", "M").unwrap();
+        let (side, scale, x, y) = (matrix.size as usize, 5, 80, 180);
+        document.fill(x - 20, y - 20, side * scale + 40, side * scale + 40, 255);
+        for (i, _) in matrix.modules.iter().enumerate().filter(|(_, &dark)| dark == 1) {
+            document.fill(x + i % side * scale, y + i / side * scale, scale, scale, 0);
+        }
+        let (photo, truth) = photograph(&document, mrz, 0.0);
+
+        let scan = qr::scan_document(photo, &qr::DecodeOptions::default()).unwrap();
+        assert_eq!(scan.qr.len(), 1);
+        assert_eq!(scan.qr[0].data, "HELLO DOCUMENT");
+        assert!(fits(&scan.mrz_regions[0], truth), "{:?}", scan.mrz_regions);
+    }
+}
@@ -0,0 +1,235 @@
+//! Country-specific decoding of the MRZ optional data into
+//! `MRZResult.optional_parsed`. A decoder is a function from the parsed
+//! result to an `MrzOptionalData` variant, listed in `DECODERS` under the
+//! issuing country code it applies to.
+
+use serde::{Deserialize, Serialize};
+
+use crate::mrz::{CivilDate, MRZResult};
+
+/// Structured optional data, tagged by `type` when serialized
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MrzOptionalData {
+    /// Dutch citizen service number (burgerservicenummer)
+    NldBsn {
+        bsn: String,
+        valid: bool, // Passes the "elfproef" (11-test)
+    },
+    /// Polish national identification number
+    PolPesel {
+        pesel: String,
+        date_of_birth: Option<String>, // YYYY-MM-DD, null when not a calendar date
+        sex: String,                   // M or F
+        valid: bool,                   // Check digit passed
+    },
+    /// Issuing details of the pre-2021 French ID card (`FR_CNI`)
+    FraCni {
+        department: String, // As encoded, e.g. 932 or 2A4
+        office: String,
+        issued: Option<String>, // YYYY-MM from the card number
+    },
+}
+
+type Decoder = fn(&MRZResult) -> Option<MrzOptionalData>;
+
+/// Decoders by issuing country code
+const DECODERS: &[(&str, Decoder)] =
+    &[("FRA", decode_fra), ("NLD", decode_nld), ("POL", decode_pol)];
+
+/// The optional data of `result` decoded for its issuing country, when
+/// there is a decoder for it and the data has the expected shape
+pub(crate) fn decode(result: &MRZResult) -> Option<MrzOptionalData> {
+    let (_, decoder) = DECODERS
+        .iter()
+        .find(|(country, _)| *country == result.issuing_country)?;
+    decoder(result)
+}
+
+/// The first of the optional data and personal number fields that is
+/// exactly `length` digits; countries use one or the other by document type
+fn digit_field(result: &MRZResult, length: usize) -> Option<&str> {
+    [&result.optional_data, &result.personal_number]
+        .into_iter()
+        .map(|field| field.as_str())
+        .find(|field| field.len() == length && field.bytes().all(|b| b.is_ascii_digit()))
+}
+
+fn digits(field: &str) -> Vec<u32> {
+    field.bytes().map(|b| (b - b'0') as u32).collect()
+}
+
+/// Department and office from the CNI's line 1 (kept in the optional data)
+/// and the issue month from the start of its card number. Cards date from
+/// 1988, so years 88-99 are 19xx.
+fn decode_fra(result: &MRZResult) -> Option<MrzOptionalData> {
+    if result.document_type != "FR_CNI" || result.optional_data.chars().count() != 6 {
+        return None;
+    }
+    let (department, office) = result.optional_data.split_at(3);
+
+    let issued = result.document_number.get(..4).and_then(|yymm| {
+        let year: i32 = yymm[..2].parse().ok()?;
+        let month: u32 = yymm[2..].parse().ok()?;
+        let year = if year >= 88 { 1900 + year } else { 2000 + year };
+        (1..=12).contains(&month).then(|| format!("{:04}-{:02}", year, month))
+    });
+
+    Some(MrzOptionalData::FraCni {
+        department: department.to_string(),
+        office: office.to_string(),
+        issued,
+    })
+}
+
+/// BSN in the ID card optional data or the passport personal number:
+/// 9 digits whose weighted sum (9..2, and -1 for the last) is 0 mod 11
+fn decode_nld(result: &MRZResult) -> Option<MrzOptionalData> {
+    let bsn = digit_field(result, 9)?;
+    let digits = digits(bsn);
+    let sum: i32 = digits[..8]
+        .iter()
+        .zip((2..=9).rev())
+        .map(|(&d, weight)| d as i32 * weight)
+        .sum::<i32>()
+        - digits[8] as i32;
+
+    Some(MrzOptionalData::NldBsn {
+        bsn: bsn.to_string(),
+        valid: sum % 11 == 0,
+    })
+}
+
+/// PESEL in the passport personal number or the ID card optional data:
+/// YYMMDD with the century folded into the month, a serial whose last digit
+/// is odd for men, and a check digit over weights 1, 3, 7, 9
+fn decode_pol(result: &MRZResult) -> Option<MrzOptionalData> {
+    let pesel = digit_field(result, 11)?;
+    let digits = digits(pesel);
+
+    let checksum: u32 = digits[..10]
+        .iter()
+        .zip([1, 3, 7, 9].iter().cycle())
+        .map(|(d, weight)| d * weight)
+        .sum();
+    let valid = (10 - checksum % 10) % 10 == digits[10];
+
+    let year = digits[0] * 10 + digits[1];
+    let month = digits[2] * 10 + digits[3];
+    let day = digits[4] * 10 + digits[5];
+    // 81-92: 1800s, 01-12: 1900s, 21-32: 2000s, 41-52: 2100s, 61-72: 2200s
+    let (century, month) = match month {
+        81..=92 => (1800, month - 80),
+        61..=72 => (2200, month - 60),
+        41..=52 => (2100, month - 40),
+        21..=32 => (2000, month - 20),
+        _ => (1900, month),
+    };
+    let date_of_birth = CivilDate::new(century + year as i32, month, day).map(CivilDate::to_iso);
+
+    Some(MrzOptionalData::PolPesel {
+        pesel: pesel.to_string(),
+        date_of_birth,
+        sex: if digits[9] % 2 == 1 { "M" } else { "F" }.to_string(),
+        valid,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mrz::{generate_mrz_lines, parse_with_options, MrzFields, MrzOptions};
+
+    /// French CNI specimen: department 932, office 013, issued June 2005
+    const CNI: [&str; 2] =
+        ["IDFRADOUEL<<<<<<<<<<<<<<<<<<<<932013", "0506932020438CHRISTIANE<<NI6512068F5"];
+
+    /// A generated identity card, or a passport when there is a personal
+    /// number, with valid check digits
+    fn parse(country: &str, optional_data: &str, personal_number: &str) -> MRZResult {
+        let (code, format) = if personal_number.is_empty() { ("I", "TD1") } else { ("P", "TD3") };
+        let fields = MrzFields {
+            document_code: code.to_string(),
+            issuing_country: country.to_string(),
+            surname: "DE BRUIJN".to_string(),
+            given_names: "WILLEKE".to_string(),
+            document_number: "SPECI2021".to_string(),
+            nationality: country.to_string(),
+            date_of_birth: "850310".to_string(),
+            sex: "F".to_string(),
+            date_of_expiry: "310802".to_string(),
+            optional_data: optional_data.to_string(),
+            personal_number: personal_number.to_string(),
+        };
+        let text = generate_mrz_lines(&fields, format).unwrap().text;
+        let reference = CivilDate::new(2026, 1, 1).unwrap();
+        parse_with_options(&text, reference, &MrzOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn dutch_bsn_runs_the_eleven_test() {
+        let bsn = |bsn: &str, valid| MrzOptionalData::NldBsn { bsn: bsn.to_string(), valid };
+        let result = parse("NLD", "999999990", "");
+        assert!(result.check_digits.valid);
+        assert_eq!(result.optional_parsed, Some(bsn("999999990", true)));
+        assert_eq!(parse("NLD", "999999991", "").optional_parsed, Some(bsn("999999991", false)));
+        // Passports carry it as the personal number
+        assert_eq!(parse("NLD", "", "999999990").optional_parsed, Some(bsn("999999990", true)));
+
+        let json = serde_json::to_value(&result.optional_parsed).unwrap();
+        let expected = serde_json::json!({"type": "nld_bsn", "bsn": "999999990", "valid": true});
+        assert_eq!(json, expected);
+    }
+
+    #[test]
+    fn polish_pesel_gives_birth_date_and_sex() {
+        let result = parse("POL", "", "44051401458");
+        let expected = MrzOptionalData::PolPesel {
+            pesel: "44051401458".to_string(),
+            date_of_birth: Some("1944-05-14".to_string()),
+            sex: "M".to_string(),
+            valid: true,
+        };
+        assert_eq!(result.optional_parsed, Some(expected));
+
+        // Months 21-32 are the 2000s
+        let Some(MrzOptionalData::PolPesel { date_of_birth, sex, valid, .. }) =
+            parse("POL", "02271001243", "").optional_parsed
+        else {
+            panic!("no PESEL");
+        };
+        assert_eq!(date_of_birth.as_deref(), Some("2002-07-10"));
+        assert_eq!((sex.as_str(), valid), ("F", true));
+
+        let wrong_check = parse("POL", "", "44051401459").optional_parsed;
+        assert!(matches!(wrong_check, Some(MrzOptionalData::PolPesel { valid: false, .. })));
+        let no_date = parse("POL", "", "44133101458").optional_parsed;
+        assert!(matches!(no_date, Some(MrzOptionalData::PolPesel { date_of_birth: None, .. })));
+    }
+
+    #[test]
+    fn french_cni_gives_department_office_and_issue_month() {
+        let reference = CivilDate::new(2026, 1, 1).unwrap();
+        let result = parse_with_options(&CNI.join("\n"), reference, &MrzOptions::default());
+        let expected = MrzOptionalData::FraCni {
+            department: "932".to_string(),
+            office: "013".to_string(),
+            issued: Some("2005-06".to_string()),
+        };
+        assert_eq!(result.unwrap().optional_parsed, Some(expected));
+    }
+
+    #[test]
+    fn other_shapes_and_countries_are_not_decoded() {
+        assert_eq!(parse("NLD", "12345", "").optional_parsed, None);
+        assert_eq!(parse("NLD", "99999999A", "").optional_parsed, None);
+        assert_eq!(parse("POL", "4405140145", "").optional_parsed, None);
+        assert_eq!(parse("SWE", "999999990", "").optional_parsed, None);
+        // A French TD1 is not a CNI
+        assert_eq!(parse("FRA", "932013", "").optional_parsed, None);
+
+        let mut result = parse("NLD", "999999990", "");
+        result.issuing_country = "BEL".to_string();
+        assert_eq!(decode(&result), None);
+    }
+}
@@ -0,0 +1,416 @@
+//! `otpauth://` provisioning URIs of authenticator apps (Google
+//! Authenticator Key Uri Format): TOTP/HOTP type, `issuer:account` label,
+//! base32 secret and the optional algorithm, digits, period and counter.
+
+use serde::{Deserialize, Serialize};
+
+use crate::payload::percent_decode;
+use crate::VeloQrError;
+
+/// A parsed provisioning URI
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct OtpauthUri {
+    pub kind: String,  // totp or hotp
+    pub label: String, // Percent-decoded, as written
+    /// The `issuer` parameter, else the label's `Issuer:` prefix; empty
+    /// without either
+    pub issuer: String,
+    pub account: String,       // The label after the issuer prefix
+    pub issuer_mismatch: bool, // Prefix and parameter both present but different
+    pub secret: String,        // Base32 as written
+    #[serde(with = "serde_bytes")]
+    pub secret_bytes: Vec<u8>,
+    pub algorithm: String,    // SHA1, SHA256 or SHA512
+    pub digits: u32,          // 6 to 8
+    pub period: Option<u32>,  // Seconds, TOTP only
+    pub counter: Option<u64>, // HOTP only
+}
+
+/// Fields of `build`, deserialized from a JS object. `secret` is base32;
+/// everything but `account` and `secret` falls back to the defaults.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct OtpauthParams {
+    pub kind: String, // totp (default) or hotp
+    pub issuer: String,
+    pub account: String,
+    pub secret: String,
+    pub algorithm: String, // SHA1 (default), SHA256 or SHA512
+    pub digits: u32,       // 6 (default) to 8
+    pub period: u32,       // TOTP seconds, default 30
+    pub counter: u64,      // HOTP only
+}
+
+impl Default for OtpauthParams {
+    fn default() -> Self {
+        OtpauthParams {
+            kind: "totp".to_string(),
+            issuer: String::new(),
+            account: String::new(),
+            secret: String::new(),
+            algorithm: "SHA1".to_string(),
+            digits: 6,
+            period: 30,
+            counter: 0,
+        }
+    }
+}
+
+const ALGORITHMS: &[&str] = &["SHA1", "SHA256", "SHA512"];
+
+/// Parse `otpauth://TYPE/LABEL?secret=...&issuer=...`. A missing or empty
+/// secret is `MISSING_OTP_SECRET`, anything else malformed `INVALID_PAYLOAD`.
+pub fn parse(uri: &str) -> Result<OtpauthUri, VeloQrError> {
+    let uri = uri.trim();
+    let rest = uri
+        .get(..10)
+        .filter(|scheme| scheme.eq_ignore_ascii_case("otpauth://"))
+        .map(|_| &uri[10..])
+        .ok_or_else(|| invalid("not an otpauth:// URI"))?;
+    let (kind, rest) = rest.split_once('/').ok_or_else(|| invalid("missing label"))?;
+    let kind = kind.to_ascii_lowercase();
+    if kind != "totp" && kind != "hotp" {
+        return Err(invalid(&format!("unknown type {:?}", kind)));
+    }
+    let (label, query) = rest.split_once('?').unwrap_or((rest, ""));
+    let params = parse_query(query);
+    let param = |name: &str| {
+        params.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value)
+    };
+
+    // `Issuer:account` with a literal or encoded colon, optionally followed
+    // by spaces. The parameter wins over the prefix; they should agree.
+    let label = percent_decode(label);
+    let (prefix, account) = match label.split_once(':') {
+        Some((prefix, account)) => (Some(prefix.to_string()), account.trim_start().to_string()),
+        None => (None, label.clone()),
+    };
+    let issuer_mismatch = matches!((&prefix, param("issuer")), (Some(p), Some(i)) if p != i);
+    let issuer = param("issuer").cloned().or(prefix).unwrap_or_default();
+
+    let secret = param("secret").filter(|secret| !secret.is_empty());
+    let secret = secret.ok_or(VeloQrError::MissingOtpSecret)?.clone();
+    let secret_bytes = base32_decode(&secret)
+        .ok_or_else(|| invalid(&format!("secret {:?} is not base32", secret)))?;
+
+    let algorithm = match param("algorithm") {
+        Some(algorithm) => ALGORITHMS
+            .iter()
+            .find(|known| known.eq_ignore_ascii_case(algorithm))
+            .ok_or_else(|| invalid(&format!("unknown algorithm {:?}", algorithm)))?,
+        None => "SHA1",
+    };
+    let digits = match param("digits") {
+        Some(digits) => digits
+            .parse()
+            .ok()
+            .filter(|digits| (6..=8).contains(digits))
+            .ok_or_else(|| invalid(&format!("digits must be 6 to 8, got {:?}", digits)))?,
+        None => 6,
+    };
+    let (period, counter) = if kind == "totp" {
+        let period = match param("period") {
+            Some(period) => period
+                .parse()
+                .ok()
+                .filter(|&period| period > 0)
+                .ok_or_else(|| invalid(&format!("invalid period {:?}", period)))?,
+            None => 30,
+        };
+        (Some(period), None)
+    } else {
+        let counter = param("counter").ok_or_else(|| invalid("hotp needs a counter"))?;
+        let counter = counter
+            .parse()
+            .map_err(|_| invalid(&format!("invalid counter {:?}", counter)))?;
+        (None, Some(counter))
+    };
+
+    Ok(OtpauthUri {
+        kind,
+        label,
+        issuer,
+        account,
+        issuer_mismatch,
+        secret,
+        secret_bytes,
+        algorithm: algorithm.to_string(),
+        digits,
+        period,
+        counter,
+    })
+}
+
+/// Build the URI `parse` reads back: the label is `Issuer:account` (just
+/// the account without an issuer) and the issuer is repeated as a
+/// parameter, as the spec recommends. The secret is normalized to unpadded
+/// uppercase base32; parameters at their defaults are left out.
+pub fn build(params: &OtpauthParams) -> Result<String, VeloQrError> {
+    let kind = params.kind.to_ascii_lowercase();
+    if kind != "totp" && kind != "hotp" {
+        return Err(invalid(&format!("unknown type {:?}", params.kind)));
+    }
+    if params.account.is_empty() {
+        return Err(invalid("missing account name"));
+    }
+    if params.issuer.contains(':') || params.account.contains(':') {
+        return Err(invalid("issuer and account name may not contain ':'"));
+    }
+    if params.secret.is_empty() {
+        return Err(VeloQrError::MissingOtpSecret);
+    }
+    let secret_bytes = base32_decode(&params.secret)
+        .ok_or_else(|| invalid(&format!("secret {:?} is not base32", params.secret)))?;
+    let algorithm = ALGORITHMS
+        .iter()
+        .find(|known| known.eq_ignore_ascii_case(&params.algorithm))
+        .ok_or_else(|| invalid(&format!("unknown algorithm {:?}", params.algorithm)))?;
+    if !(6..=8).contains(&params.digits) {
+        return Err(invalid(&format!("digits must be 6 to 8, got {}", params.digits)));
+    }
+    if kind == "totp" && params.period == 0 {
+        return Err(invalid("period must be at least 1 second"));
+    }
+
+    let mut uri = format!("otpauth://{}/", kind);
+    if !params.issuer.is_empty() {
+        uri.push_str(&percent_encode(&params.issuer));
+        uri.push(':');
+    }
+    uri.push_str(&percent_encode(&params.account));
+    uri.push_str(&format!("?secret={}", base32_encode(&secret_bytes)));
+    if !params.issuer.is_empty() {
+        uri.push_str(&format!("&issuer={}", percent_encode(&params.issuer)));
+    }
+    if *algorithm != "SHA1" {
+        uri.push_str(&format!("&algorithm={}", algorithm));
+    }
+    if params.digits != 6 {
+        uri.push_str(&format!("&digits={}", params.digits));
+    }
+    match kind.as_str() {
+        "totp" if params.period != 30 => uri.push_str(&format!("&period={}", params.period)),
+        "hotp" => uri.push_str(&format!("&counter={}", params.counter)),
+        _ => {}
+    }
+    Ok(uri)
+}
+
+fn invalid(message: &str) -> VeloQrError {
+    VeloQrError::InvalidPayload(format!("otpauth: {}", message))
+}
+
+/// `a=1&b=2`, percent-decoded; unlike form data `+` stays a plus, since
+/// authenticator apps write spaces in the issuer as `%20`
+fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key), percent_decode(value))
+        })
+        .collect()
+}
+
+/// Percent-encode everything but RFC 3986 unreserved characters
+fn percent_encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// RFC 4648 base32, case-insensitive, ignoring spaces and hyphens (secrets
+/// are often shown in groups). Trailing `=` padding is optional and its
+/// amount is not checked, but a length no byte count can produce (1, 3 or
+/// 6 characters past a multiple of 8) is rejected.
+fn base32_decode(text: &str) -> Option<Vec<u8>> {
+    let cleaned: Vec<u8> = text
+        .bytes()
+        .filter(|byte| !matches!(byte, b' ' | b'-'))
+        .map(|byte| byte.to_ascii_uppercase())
+        .collect();
+    let end = cleaned.iter().rposition(|&byte| byte != b'=').map_or(0, |last| last + 1);
+    let data = &cleaned[..end];
+    if data.is_empty() || matches!(data.len() % 8, 1 | 3 | 6) {
+        return None;
+    }
+
+    let mut bytes = Vec::with_capacity(data.len() * 5 / 8);
+    let (mut buffer, mut bits) = (0u32, 0);
+    for &byte in data {
+        let value = BASE32_ALPHABET.iter().position(|&c| c == byte)? as u32;
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+/// Unpadded RFC 4648 base32, as authenticator URIs carry it
+fn base32_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(5) * 8);
+    let (mut buffer, mut bits) = (0u32, 0);
+    for &byte in bytes {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(BASE32_ALPHABET[(buffer >> bits) as usize & 31] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(BASE32_ALPHABET[(buffer << (5 - bits)) as usize & 31] as char);
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(parsed: &OtpauthUri) -> (&str, &str) {
+        (&parsed.issuer, &parsed.account)
+    }
+
+    #[test]
+    fn parses_the_key_uri_format_examples() {
+        let uri = "otpauth://totp/Example:alice@google.com?secret=JBSWY3DPEHPK3PXP&issuer=Example";
+        let parsed = parse(uri).unwrap();
+        assert_eq!(parsed.kind, "totp");
+        assert_eq!(names(&parsed), ("Example", "alice@google.com"));
+        assert_eq!(parsed.secret_bytes, b"Hello!\xde\xad\xbe\xef");
+        assert_eq!((parsed.algorithm.as_str(), parsed.digits), ("SHA1", 6));
+        assert_eq!((parsed.period, parsed.counter), (Some(30), None));
+        assert!(!parsed.issuer_mismatch);
+
+        let uri = "otpauth://totp/ACME%20Co:john.doe@email.com?\
+                   secret=HXDMVJECJJWSRB3HWIZR4IFUGFTMXBOZ&issuer=ACME%20Co&algorithm=SHA1";
+        let parsed = parse(uri).unwrap();
+        assert_eq!(names(&parsed), ("ACME Co", "john.doe@email.com"));
+        assert_eq!(parsed.secret_bytes.len(), 20);
+
+        let parsed = parse("otpauth://hotp/x?secret=JBSWY3DP&counter=5").unwrap();
+        assert_eq!((parsed.counter, parsed.period), (Some(5), None));
+    }
+
+    #[test]
+    fn labels_parameters_and_secrets_are_lenient() {
+        // Encoded colon with spaces after it, grouped lowercase padded secret
+        let uri = "otpauth://TOTP/Big%20Corp%3A%20%20alice?\
+                   secret=jbsw y3dp ehpk 3pxp====&algorithm=sha512&digits=8&period=60";
+        let parsed = parse(uri).unwrap();
+        assert_eq!((parsed.kind.as_str(), parsed.label.as_str()), ("totp", "Big Corp:  alice"));
+        assert_eq!(names(&parsed), ("Big Corp", "alice"));
+        assert_eq!((parsed.algorithm.as_str(), parsed.digits), ("SHA512", 8));
+        assert_eq!(parsed.period, Some(60));
+
+        let parsed = parse("otpauth://totp/alice?secret=JBSWY3DPEHPK3PXP&issuer=Other").unwrap();
+        assert_eq!((parsed.issuer.as_str(), parsed.issuer_mismatch), ("Other", false));
+        let parsed = parse("otpauth://totp/A:alice?secret=JBSWY3DPEHPK3PXP&issuer=B").unwrap();
+        assert_eq!((parsed.issuer.as_str(), parsed.issuer_mismatch), ("B", true));
+        let parsed = parse("otpauth://totp/alice?secret=JBSWY3DPEHPK3PXP").unwrap();
+        assert_eq!(parsed.issuer, "");
+    }
+
+    #[test]
+    fn malformed_uris_are_rejected() {
+        for uri in ["otpauth://totp/A:b?issuer=A", "otpauth://totp/A:b?secret="] {
+            assert!(matches!(parse(uri), Err(VeloQrError::MissingOtpSecret)), "{}", uri);
+        }
+        let invalid = [
+            "otpauth://hotp/x?secret=JBSWY3DP",
+            "otpauth://totp/x?secret=JBS1",
+            "otpauth://totp/x?secret=A",
+            "otpauth://totp/x?secret=ABC",
+            "otpauth://totp/x?secret=AB=CD",
+            "otpauth://totp/x?secret=JBSWY3DP&digits=9",
+            "otpauth://totp/x?secret=JBSWY3DP&period=0",
+            "otpauth://totp/x?secret=JBSWY3DP&algorithm=MD5",
+            "otpauth://hotp/x?secret=JBSWY3DP&counter=-1",
+            "otpauth://xotp/x?secret=JBSWY3DP",
+            "http://totp/x?secret=JBSWY3DP",
+            "otpauth://totp",
+        ];
+        for uri in invalid {
+            assert!(matches!(parse(uri), Err(VeloQrError::InvalidPayload(_))), "{}", uri);
+        }
+    }
+
+    #[test]
+    fn built_uris_parse_back() {
+        let params = OtpauthParams {
+            issuer: "ACME Co/é".to_string(),
+            account: "john+doe@x.com".to_string(),
+            secret: "jbsw-y3dp-ehpk-3pxp==".to_string(),
+            ..OtpauthParams::default()
+        };
+        let uri = build(&params).unwrap();
+        let expected = "otpauth://totp/ACME%20Co%2F%C3%A9:john%2Bdoe%40x.com?\
+                        secret=JBSWY3DPEHPK3PXP&issuer=ACME%20Co%2F%C3%A9";
+        assert_eq!(uri, expected);
+        let parsed = parse(&uri).unwrap();
+        assert_eq!(names(&parsed), ("ACME Co/é", "john+doe@x.com"));
+        assert!(!parsed.issuer_mismatch);
+
+        let params = OtpauthParams {
+            kind: "hotp".to_string(),
+            account: "bob".to_string(),
+            secret: "GEZDGNBVGY3TQOJQ".to_string(),
+            algorithm: "sha256".to_string(),
+            digits: 8,
+            counter: 42,
+            ..OtpauthParams::default()
+        };
+        let uri = build(&params).unwrap();
+        let expected =
+            "otpauth://hotp/bob?secret=GEZDGNBVGY3TQOJQ&algorithm=SHA256&digits=8&counter=42";
+        assert_eq!(uri, expected);
+        let parsed = parse(&uri).unwrap();
+        assert_eq!(parsed.secret_bytes, b"1234567890");
+        assert_eq!(parsed.counter, Some(42));
+    }
+
+    #[test]
+    fn bad_build_parameters_are_rejected() {
+        let params = |account: &str, secret: &str| OtpauthParams {
+            account: account.to_string(),
+            secret: secret.to_string(),
+            ..OtpauthParams::default()
+        };
+        assert!(matches!(build(&params("a", "")), Err(VeloQrError::MissingOtpSecret)));
+        assert!(build(&params("", "JBSWY3DP")).is_err());
+        assert!(build(&params("a:b", "JBSWY3DP")).is_err());
+        assert!(build(&params("a", "JBS1")).is_err());
+        assert!(build(&OtpauthParams { digits: 5, ..params("a", "JBSWY3DP") }).is_err());
+        assert!(build(&OtpauthParams { period: 0, ..params("a", "JBSWY3DP") }).is_err());
+        let sha = OtpauthParams { algorithm: "MD5".to_string(), ..params("a", "JBSWY3DP") };
+        assert!(build(&sha).is_err());
+    }
+
+    #[test]
+    fn base32_round_trips_every_tail_length() {
+        for length in 0..=12u8 {
+            let bytes: Vec<u8> = (0..length).map(|i| i.wrapping_mul(37)).collect();
+            let encoded = base32_encode(&bytes);
+            assert_eq!(encoded.len(), (bytes.len() * 8).div_ceil(5));
+            if !bytes.is_empty() {
+                assert_eq!(base32_decode(&encoded).unwrap(), bytes);
+            }
+        }
+        assert_eq!(base32_encode(b"foobar"), "MZXW6YTBOI");
+        assert_eq!(base32_decode("mzxw6ytboi======").unwrap(), b"foobar");
+        assert_eq!(base32_decode(""), None);
+    }
+}
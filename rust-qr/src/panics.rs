@@ -0,0 +1,81 @@
+//! Panic capture for the JavaScript boundary
+//!
+//! Release WASM builds abort on panic: the standard library for
+//! `wasm32-unknown-unknown` is built that way, so `catch_unwind` cannot stop
+//! a panic there and the export traps with `RuntimeError: unreachable`. The
+//! panic hook installed by the bindings records the message first, and
+//! `take_last_panic` hands it to JavaScript as an `INTERNAL_PANIC` error once
+//! the trap has been caught.
+
+use std::cell::RefCell;
+use std::panic::PanicHookInfo;
+
+use crate::VeloQrError;
+
+thread_local! {
+    static LAST_PANIC: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Keep the message of a panic for `take_last_panic`, replacing any
+/// earlier one; called from the panic hook
+#[cfg_attr(not(all(feature = "wasm", target_arch = "wasm32")), allow(dead_code))]
+pub fn record(info: &PanicHookInfo<'_>) {
+    let message = info.to_string();
+    LAST_PANIC.with(|slot| *slot.borrow_mut() = Some(message));
+}
+
+/// The last recorded panic as `VeloQrError::InternalPanic`, clearing it
+#[cfg_attr(not(all(feature = "wasm", target_arch = "wasm32")), allow(dead_code))]
+pub fn take_last_panic() -> Option<VeloQrError> {
+    LAST_PANIC.with(|slot| slot.borrow_mut().take()).map(VeloQrError::InternalPanic)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The hook is process-wide; tests swapping it take turns
+    static HOOK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    /// Run `f` with `record` as the panic hook, as the bindings install it
+    fn with_hook<T>(f: impl FnOnce() -> T + std::panic::UnwindSafe) -> std::thread::Result<T> {
+        let _turn = HOOK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(record));
+        let result = std::panic::catch_unwind(f);
+        std::panic::set_hook(previous);
+        result
+    }
+
+    #[test]
+    fn panic_becomes_internal_panic_error() {
+        assert!(take_last_panic().is_none());
+        let result = with_hook(|| {
+            let lines: Vec<&str> = Vec::new();
+            lines[3].len()
+        });
+        assert!(result.is_err());
+
+        let error = take_last_panic().expect("panic recorded");
+        assert_eq!(error.code(), "INTERNAL_PANIC");
+        let message = error.to_string();
+        assert!(message.contains("index out of bounds"), "{}", message);
+        assert!(message.contains("panics.rs"), "{}", message);
+        // Taken once
+        assert!(take_last_panic().is_none());
+    }
+
+    #[test]
+    fn latest_panic_wins() {
+        let _ = with_hook(|| panic!("first"));
+        let _ = with_hook(|| panic!("second"));
+        let message = take_last_panic().unwrap().to_string();
+        assert!(message.contains("second") && !message.contains("first"), "{}", message);
+    }
+
+    #[test]
+    fn calls_without_panic_record_nothing() {
+        assert_eq!(with_hook(|| 7).unwrap(), 7);
+        assert!(take_last_panic().is_none());
+    }
+}
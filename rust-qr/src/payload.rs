@@ -0,0 +1,904 @@
+//! Classification of well-known QR payload formats (WiFi, vCard/MeCard,
+//! vEvent, otpauth, mailto, tel, sms, geo, URLs)
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::url_safety::{self, UrlSafety};
+use crate::VeloQrError;
+
+/// Structured view of a QR payload, tagged by `type` when serialized
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum QrPayload {
+    Wifi {
+        ssid: String,
+        password: String,
+        security: String, // WPA, WEP, nopass, ... as written; empty when absent
+        hidden: bool,
+    },
+    Vcard {
+        name: String,
+        org: String,
+        phones: Vec<String>,
+        emails: Vec<String>,
+    },
+    /// iCalendar `VEVENT`, bare or inside a `VCALENDAR`
+    Event {
+        summary: String,
+        start: Option<EventTime>,
+        end: Option<EventTime>,
+        location: String,
+        description: String,
+        /// Every other property of the event, values as written (repeats in
+        /// order); also DTSTART/DTEND when their value is not a date
+        properties: BTreeMap<String, Vec<String>>,
+    },
+    Otpauth {
+        kind: String, // totp or hotp
+        label: String,
+        secret: String,
+        issuer: String,
+    },
+    Email {
+        to: String,
+        subject: String,
+        body: String,
+    },
+    Phone {
+        number: String,
+    },
+    Sms {
+        number: String,
+        body: String,
+    },
+    Geo {
+        latitude: f64,
+        longitude: f64,
+        altitude: Option<f64>,
+    },
+    Url {
+        url: String,
+        url_safety: UrlSafety,
+    },
+    Text,
+}
+
+/// DTSTART/DTEND of an event as ISO 8601
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct EventTime {
+    /// `2024-03-15T09:00:00Z` in UTC, `2024-03-15T09:00:00` local to `tzid`
+    /// or floating, `2024-03-15` for a whole day
+    pub value: String,
+    pub tzid: Option<String>,
+    /// Neither UTC nor tied to a `tzid`: the same wall-clock time in every
+    /// time zone, as are whole days
+    pub floating: bool,
+    pub all_day: bool,
+}
+
+/// Classify a decoded payload; anything unrecognized is `Text`
+pub fn classify(data: &str) -> QrPayload {
+    let trimmed = data.trim();
+    let (scheme, rest) = match trimmed.split_once(':') {
+        Some((scheme, rest)) => (scheme.to_ascii_uppercase(), rest),
+        None => return QrPayload::Text,
+    };
+
+    let payload = match scheme.as_str() {
+        "WIFI" => Some(parse_wifi(rest)),
+        "MECARD" => Some(parse_mecard(rest)),
+        "BEGIN" if is_vcard(trimmed) => Some(parse_vcard(trimmed)),
+        "BEGIN" => parse_vevent(trimmed),
+        "OTPAUTH" => parse_otpauth(rest),
+        "MAILTO" => Some(parse_mailto(rest)),
+        "MATMSG" => Some(parse_matmsg(rest)),
+        "TEL" => Some(QrPayload::Phone {
+            number: percent_decode(rest),
+        }),
+        "SMS" => Some(parse_sms(rest)),
+        "SMSTO" => {
+            let (number, body) = rest.split_once(':').unwrap_or((rest, ""));
+            Some(QrPayload::Sms {
+                number: number.to_string(),
+                body: body.to_string(),
+            })
+        }
+        "GEO" => parse_geo(rest),
+        "HTTP" | "HTTPS" if rest.starts_with("//") => Some(QrPayload::Url {
+            url: trimmed.to_string(),
+            url_safety: url_safety::classify(trimmed),
+        }),
+        _ => None,
+    };
+    payload.unwrap_or(QrPayload::Text)
+}
+
+fn is_vcard(data: &str) -> bool {
+    data.lines()
+        .next()
+        .is_some_and(|line| line.trim().eq_ignore_ascii_case("BEGIN:VCARD"))
+}
+
+/// `WIFI:T:WPA;S:ssid;P:password;H:true;;` with `\;`, `\,`, `\:` and `\\` escapes
+fn parse_wifi(rest: &str) -> QrPayload {
+    let mut ssid = String::new();
+    let mut password = String::new();
+    let mut security = String::new();
+    let mut hidden = false;
+
+    for (key, value) in split_mecard_fields(rest) {
+        match key.to_ascii_uppercase().as_str() {
+            "S" => ssid = value,
+            "P" => password = value,
+            "T" => security = value,
+            "H" => hidden = value.eq_ignore_ascii_case("true"),
+            _ => {}
+        }
+    }
+
+    QrPayload::Wifi {
+        ssid,
+        password,
+        security,
+        hidden,
+    }
+}
+
+/// Security types `encode_wifi` accepts, as written in the payload
+const WIFI_SECURITY_TYPES: &[&str] = &["WEP", "WPA", "SAE", "nopass"];
+
+/// Build the `WIFI:` payload `parse_wifi` reads. `security` is one of
+/// `WIFI_SECURITY_TYPES` in any case; `nopass` takes no password and the
+/// others require one. SSIDs are 1 to 32 bytes, as in 802.11.
+pub fn encode_wifi(
+    ssid: &str,
+    password: &str,
+    security: &str,
+    hidden: bool,
+) -> Result<String, VeloQrError> {
+    let invalid = |msg: String| VeloQrError::InvalidPayload(format!("WiFi: {}", msg));
+    let security = WIFI_SECURITY_TYPES
+        .iter()
+        .find(|known| known.eq_ignore_ascii_case(security))
+        .ok_or_else(|| invalid(format!("unknown security type {:?}", security)))?;
+    if ssid.is_empty() || ssid.len() > 32 {
+        return Err(invalid(format!("SSID must be 1 to 32 bytes, got {}", ssid.len())));
+    }
+    match (*security, password.is_empty()) {
+        ("nopass", false) => return Err(invalid("nopass networks take no password".to_string())),
+        ("nopass", true) | (_, false) => {}
+        (_, true) => return Err(invalid(format!("{} needs a password", security))),
+    }
+    for (field, value) in [("SSID", ssid), ("password", password)] {
+        if value.chars().any(char::is_control) {
+            return Err(invalid(format!("{} contains a control character", field)));
+        }
+    }
+
+    let mut payload = format!("WIFI:T:{};S:{};", security, escape_wifi(ssid));
+    if !password.is_empty() {
+        payload.push_str(&format!("P:{};", escape_wifi(password)));
+    }
+    if hidden {
+        payload.push_str("H:true;");
+    }
+    payload.push(';');
+    Ok(payload)
+}
+
+/// Backslash-escape the characters that delimit `WIFI:` fields
+fn escape_wifi(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '\\' | ';' | ',' | '"' | ':') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// `MECARD:N:Doe,John;TEL:123;EMAIL:a@b.c;ORG:Acme;;`
+fn parse_mecard(rest: &str) -> QrPayload {
+    let mut name = String::new();
+    let mut org = String::new();
+    let mut phones = Vec::new();
+    let mut emails = Vec::new();
+
+    for (key, value) in split_mecard_fields(rest) {
+        match key.to_ascii_uppercase().as_str() {
+            // Family name first, given name after the comma
+            "N" => {
+                name = match value.split_once(',') {
+                    Some((family, given)) => format!("{} {}", given.trim(), family.trim()),
+                    None => value,
+                }
+            }
+            "ORG" => org = value,
+            "TEL" => phones.push(value),
+            "EMAIL" => emails.push(value),
+            _ => {}
+        }
+    }
+
+    QrPayload::Vcard {
+        name: name.trim().to_string(),
+        org,
+        phones,
+        emails,
+    }
+}
+
+/// `MATMSG:TO:a@b.c;SUB:subject;BODY:body;;`
+fn parse_matmsg(rest: &str) -> QrPayload {
+    let mut to = String::new();
+    let mut subject = String::new();
+    let mut body = String::new();
+
+    for (key, value) in split_mecard_fields(rest) {
+        match key.to_ascii_uppercase().as_str() {
+            "TO" => to = value,
+            "SUB" => subject = value,
+            "BODY" => body = value,
+            _ => {}
+        }
+    }
+
+    QrPayload::Email { to, subject, body }
+}
+
+/// Split `KEY:value;KEY:value;;` on unescaped `;`, unescaping each value
+fn split_mecard_fields(rest: &str) -> Vec<(String, String)> {
+    let mut fields = Vec::new();
+    let mut current = String::new();
+    let mut chars = rest.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                // Keep the escape for now so the key/value split can tell
+                // an escaped `:` from the separator
+                current.push('\\');
+                if let Some(escaped) = chars.next() {
+                    current.push(escaped);
+                }
+            }
+            ';' => fields.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    fields.push(current);
+
+    fields
+        .into_iter()
+        .filter(|field| !field.is_empty())
+        .filter_map(|field| {
+            let separator = find_unescaped(&field, ':')?;
+            let key = field[..separator].to_string();
+            Some((key, unescape(&field[separator + 1..])))
+        })
+        .collect()
+}
+
+/// Byte index of the first `target` not preceded by a backslash escape
+fn find_unescaped(text: &str, target: char) -> Option<usize> {
+    let mut escaped = false;
+    for (index, c) in text.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == target {
+            return Some(index);
+        }
+    }
+    None
+}
+
+/// Drop the backslash from every `\x` escape
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            if let Some(escaped) = chars.next() {
+                out.push(escaped);
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// vCard 2.1/3.0/4.0: unfold continuation lines, then read FN/N, ORG, TEL and EMAIL
+fn parse_vcard(data: &str) -> QrPayload {
+    let mut name = String::new();
+    let mut structured_name = String::new();
+    let mut org = String::new();
+    let mut phones = Vec::new();
+    let mut emails = Vec::new();
+
+    for line in unfold_vcard_lines(data) {
+        let Some(separator) = line.find(':') else {
+            continue;
+        };
+        let (property, value) = (&line[..separator], &line[separator + 1..]);
+        // Parameters (`TEL;TYPE=CELL`) and groups (`item1.EMAIL`) don't matter here
+        let property = property.split(';').next().unwrap_or("");
+        let property = property.rsplit('.').next().unwrap_or("").to_ascii_uppercase();
+
+        match property.as_str() {
+            "FN" => name = unescape_vcard(value),
+            "N" => {
+                // Family;Given;Additional;Prefix;Suffix
+                let parts: Vec<String> = split_vcard_components(value);
+                let given = parts.get(1).map(String::as_str).unwrap_or("");
+                let family = parts.first().map(String::as_str).unwrap_or("");
+                structured_name = format!("{} {}", given, family).trim().to_string();
+            }
+            "ORG" => {
+                org = split_vcard_components(value).into_iter().next().unwrap_or_default()
+            }
+            "TEL" => phones.push(unescape_vcard(value)),
+            "EMAIL" => emails.push(unescape_vcard(value)),
+            _ => {}
+        }
+    }
+
+    if name.is_empty() {
+        name = structured_name;
+    }
+
+    QrPayload::Vcard {
+        name,
+        org,
+        phones,
+        emails,
+    }
+}
+
+/// RFC 6350 line folding: a line starting with a space or tab continues the previous one
+fn unfold_vcard_lines(data: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in data.split('\n') {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continuation), Some(previous)) => previous.push_str(continuation),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+/// Split a structured vCard value on unescaped `;`, unescaping each component
+fn split_vcard_components(value: &str) -> Vec<String> {
+    let mut components = Vec::new();
+    let mut rest = value;
+    while let Some(index) = find_unescaped(rest, ';') {
+        components.push(unescape_vcard(&rest[..index]));
+        rest = &rest[index + 1..];
+    }
+    components.push(unescape_vcard(rest));
+    components
+}
+
+/// vCard escapes: `\n`/`\N` is a newline, anything else drops the backslash
+fn unescape_vcard(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => out.push('\n'),
+            Some(escaped) => out.push(escaped),
+            None => {}
+        }
+    }
+    out
+}
+
+/// RFC 5545 `BEGIN:VEVENT ... END:VEVENT`, the first event when wrapped in
+/// `BEGIN:VCALENDAR`. Properties of nested components (`VALARM`) are skipped.
+fn parse_vevent(data: &str) -> Option<QrPayload> {
+    let mut summary = String::new();
+    let mut location = String::new();
+    let mut description = String::new();
+    let mut start = None;
+    let mut end = None;
+    let mut properties: BTreeMap<String, Vec<String>> = BTreeMap::new();
+
+    // Depth inside the event: 0 before it, 1 for its own properties
+    let mut depth = 0;
+    let mut found = false;
+    for line in unfold_vcard_lines(data) {
+        let Some(separator) = find_unquoted_colon(&line) else {
+            continue;
+        };
+        let (head, value) = (&line[..separator], &line[separator + 1..]);
+        let mut head = head.split(';');
+        let name = head.next().unwrap_or("").trim().to_ascii_uppercase();
+        let parameter = |wanted: &str| {
+            head.clone().find_map(|parameter| {
+                let (key, value) = parameter.split_once('=')?;
+                let value = value.trim_matches('"').to_string();
+                key.trim().eq_ignore_ascii_case(wanted).then_some(value)
+            })
+        };
+
+        match (name.as_str(), depth) {
+            ("BEGIN", 0) if value.trim().eq_ignore_ascii_case("VEVENT") => {
+                depth = 1;
+                found = true;
+            }
+            ("BEGIN", 0) => {}
+            ("BEGIN", _) => depth += 1,
+            ("END", 1) if value.trim().eq_ignore_ascii_case("VEVENT") => break,
+            ("END", 1..) => depth -= 1,
+            (_, 1) => match name.as_str() {
+                "SUMMARY" => summary = unescape_vcard(value),
+                "LOCATION" => location = unescape_vcard(value),
+                "DESCRIPTION" => description = unescape_vcard(value),
+                "DTSTART" | "DTEND" => {
+                    let time = parse_event_time(value.trim(), parameter("TZID"));
+                    match (time, name.as_str()) {
+                        (Some(time), "DTSTART") => start = Some(time),
+                        (Some(time), _) => end = Some(time),
+                        (None, _) => properties.entry(name).or_default().push(value.to_string()),
+                    }
+                }
+                _ => properties.entry(name).or_default().push(value.to_string()),
+            },
+            _ => {}
+        }
+    }
+
+    found.then_some(QrPayload::Event {
+        summary,
+        start,
+        end,
+        location,
+        description,
+        properties,
+    })
+}
+
+/// Byte index of the `:` ending a content line's name and parameters;
+/// parameter values may hold a quoted `:`
+fn find_unquoted_colon(line: &str) -> Option<usize> {
+    let mut quoted = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ':' if !quoted => return Some(index),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// `20240315` (a whole day), `20240315T090000` (floating, or local to
+/// `tzid`) and `20240315T090000Z` (UTC)
+fn parse_event_time(value: &str, tzid: Option<String>) -> Option<EventTime> {
+    let (date, time) = match value.split_once(['T', 't']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (value, None),
+    };
+    let digits = |text: &str, length: usize| {
+        text.len() == length && text.bytes().all(|b| b.is_ascii_digit())
+    };
+    if !digits(date, 8) {
+        return None;
+    }
+    let iso_date = format!("{}-{}-{}", &date[..4], &date[4..6], &date[6..]);
+    let Some(time) = time else {
+        return Some(EventTime { value: iso_date, tzid: None, floating: true, all_day: true });
+    };
+    let (time, utc) = match time.strip_suffix(['Z', 'z']) {
+        Some(time) => (time, true),
+        None => (time, false),
+    };
+    if !digits(time, 6) {
+        return None;
+    }
+    let iso_time = format!("{}:{}:{}", &time[..2], &time[2..4], &time[4..]);
+    // A TZID on a UTC time is invalid; the `Z` wins
+    let tzid = tzid.filter(|_| !utc);
+    Some(EventTime {
+        value: format!("{}T{}{}", iso_date, iso_time, if utc { "Z" } else { "" }),
+        floating: !utc && tzid.is_none(),
+        tzid,
+        all_day: false,
+    })
+}
+
+/// `otpauth://totp/Issuer:account?secret=...&issuer=Issuer`
+fn parse_otpauth(rest: &str) -> Option<QrPayload> {
+    let rest = rest.strip_prefix("//")?;
+    let (kind, rest) = rest.split_once('/')?;
+    let (label, query) = rest.split_once('?').unwrap_or((rest, ""));
+    let params = parse_query(query);
+    let param = |name: &str| {
+        params
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.clone())
+            .unwrap_or_default()
+    };
+
+    Some(QrPayload::Otpauth {
+        kind: kind.to_ascii_lowercase(),
+        label: percent_decode(label),
+        secret: param("secret"),
+        issuer: param("issuer"),
+    })
+}
+
+/// `mailto:a@b.c?subject=...&body=...`
+fn parse_mailto(rest: &str) -> QrPayload {
+    let (to, query) = rest.split_once('?').unwrap_or((rest, ""));
+    let params = parse_query(query);
+    let param = |name: &str| {
+        params
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.clone())
+            .unwrap_or_default()
+    };
+
+    QrPayload::Email {
+        to: percent_decode(to),
+        subject: param("subject"),
+        body: param("body"),
+    }
+}
+
+/// `sms:+123?body=...`, also accepting the older `sms:+123:body`
+fn parse_sms(rest: &str) -> QrPayload {
+    let (number, body) = match rest.split_once('?') {
+        Some((number, query)) => {
+            let body = parse_query(query)
+                .into_iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case("body"))
+                .map(|(_, value)| value)
+                .unwrap_or_default();
+            (number.to_string(), body)
+        }
+        None => match rest.split_once(':') {
+            Some((number, body)) => (number.to_string(), body.to_string()),
+            None => (rest.to_string(), String::new()),
+        },
+    };
+
+    QrPayload::Sms {
+        number: percent_decode(&number),
+        body,
+    }
+}
+
+/// `geo:lat,lon[,alt][;crs=...][?q=...]`
+fn parse_geo(rest: &str) -> Option<QrPayload> {
+    let coordinates = rest.split(['?', ';']).next()?;
+    let mut values = coordinates.split(',').map(|v| v.trim().parse::<f64>());
+    let latitude = values.next()?.ok()?;
+    let longitude = values.next()?.ok()?;
+    let altitude = match values.next() {
+        Some(value) => Some(value.ok()?),
+        None => None,
+    };
+
+    Some(QrPayload::Geo {
+        latitude,
+        longitude,
+        altitude,
+    })
+}
+
+/// `a=1&b=2`, percent-decoded, `+` as space
+fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key), percent_decode(&value.replace('+', " ")))
+        })
+        .collect()
+}
+
+/// Decode `%XX` escapes; malformed escapes are kept verbatim
+pub(crate) fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .filter(|h| h.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|h| std::str::from_utf8(h).ok());
+        match (bytes[i], hex.and_then(|h| u8::from_str_radix(h, 16).ok())) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wifi_fields_are_unescaped() {
+        let payload = classify(r#"WIFI:T:WPA;S:My\;Net\:5G;P:pa\\ss\,word;H:true;;"#);
+        let expected = QrPayload::Wifi {
+            ssid: "My;Net:5G".to_string(),
+            password: r"pa\ss,word".to_string(),
+            security: "WPA".to_string(),
+            hidden: true,
+        };
+        assert_eq!(payload, expected);
+
+        let QrPayload::Wifi { security, hidden, .. } = classify("wifi:S:Open;;") else {
+            panic!("not a WiFi payload");
+        };
+        assert_eq!((security.as_str(), hidden), ("", false));
+    }
+
+    /// `encode_wifi` output, checked to classify back to the same network
+    fn wifi_round_trip(ssid: &str, password: &str, security: &str, hidden: bool) -> String {
+        let payload = encode_wifi(ssid, password, security, hidden).unwrap();
+        let QrPayload::Wifi {
+            ssid: read_ssid,
+            password: read_password,
+            security: read_security,
+            hidden: read_hidden,
+        } = classify(&payload)
+        else {
+            panic!("{:?} is not a WiFi payload", payload);
+        };
+        assert_eq!((read_ssid.as_str(), read_password.as_str()), (ssid, password), "{}", payload);
+        assert!(read_security.eq_ignore_ascii_case(security));
+        assert_eq!(read_hidden, hidden);
+        payload
+    }
+
+    #[test]
+    fn encoded_wifi_payloads_round_trip() {
+        let payload = wifi_round_trip("Home", "secret123", "WPA", true);
+        assert_eq!(payload, "WIFI:T:WPA;S:Home;P:secret123;H:true;;");
+        assert_eq!(wifi_round_trip("Cafe", "", "nopass", false), "WIFI:T:nopass;S:Cafe;;");
+        // Every delimiter is escaped and the security type is written canonically
+        let payload = wifi_round_trip("a;b", "p:w,\"x\"\\", "wpa", false);
+        assert_eq!(payload, r#"WIFI:T:WPA;S:a\;b;P:p\:w\,\"x\"\\;;"#);
+
+        wifi_round_trip(r"back\slash\", r"\;\", "SAE", false);
+        wifi_round_trip("S:P;T:WEP;;", "H:true;", "WPA", false);
+        wifi_round_trip("☕ Café 📶", "пароль🙂", "wep", true);
+        wifi_round_trip("Open", "", "NOPASS", false);
+    }
+
+    #[test]
+    fn wifi_encoding_rejects_bad_networks() {
+        let error = encode_wifi("x", "y", "WPA2", false).unwrap_err();
+        assert_eq!(error.code(), "INVALID_PAYLOAD");
+        // SSIDs are counted in bytes: eight 4-byte emoji fit, one more byte does not
+        let emoji = "🙂".repeat(8);
+        assert!(encode_wifi(&emoji, "y", "WPA", false).is_ok());
+        for ssid in ["", &"a".repeat(33), &format!("{}a", emoji)] {
+            assert!(encode_wifi(ssid, "y", "WPA", false).is_err(), "{:?}", ssid);
+        }
+        assert!(encode_wifi("x", "", "WPA", false).is_err());
+        assert!(encode_wifi("x", "y", "nopass", false).is_err());
+        for (ssid, password) in [("x\n", "y"), ("x", "y\u{7f}"), ("x", "y\u{0}")] {
+            assert!(encode_wifi(ssid, password, "WPA", false).is_err(), "{:?}", password);
+        }
+    }
+
+    #[test]
+    fn mecard_and_vcard_contacts() {
+        let mecard = classify("MECARD:N:Doe,John;TEL:+1555;TEL:+1666;EMAIL:j@x.org;ORG:Acme;;");
+        let expected = QrPayload::Vcard {
+            name: "John Doe".to_string(),
+            org: "Acme".to_string(),
+            phones: vec!["+1555".to_string(), "+1666".to_string()],
+            emails: vec!["j@x.org".to_string()],
+        };
+        assert_eq!(mecard, expected);
+
+        // Folded lines, parameters, groups and a structured ORG
+        let vcard = "BEGIN:VCARD\r\nVERSION:3.0\r\nN:Doe;John;;;\r\nORG:Acme\\, Inc.;Lab\r\n\
+                     TEL;TYPE=CELL:+1\r\n 555\r\nitem1.EMAIL:j@x.org\r\nEND:VCARD\r\n";
+        let expected = QrPayload::Vcard {
+            name: "John Doe".to_string(),
+            org: "Acme, Inc.".to_string(),
+            phones: vec!["+1555".to_string()],
+            emails: vec!["j@x.org".to_string()],
+        };
+        assert_eq!(classify(vcard), expected);
+
+        let QrPayload::Vcard { name, .. } = classify("BEGIN:VCARD\nFN:Dr. J\\nDoe\nN:Doe;John\n")
+        else {
+            panic!("not a vCard");
+        };
+        assert_eq!(name, "Dr. J\nDoe");
+    }
+
+    #[test]
+    fn email_phone_and_sms() {
+        let mailto = classify("mailto:a%40b.c?subject=Hi%20there&body=a+b");
+        let expected = QrPayload::Email {
+            to: "a@b.c".to_string(),
+            subject: "Hi there".to_string(),
+            body: "a b".to_string(),
+        };
+        assert_eq!(mailto, expected);
+
+        let matmsg = classify(r"MATMSG:TO:a@b.c;SUB:Hi;BODY:x\;y;;");
+        let expected = QrPayload::Email {
+            to: "a@b.c".to_string(),
+            subject: "Hi".to_string(),
+            body: "x;y".to_string(),
+        };
+        assert_eq!(matmsg, expected);
+
+        assert_eq!(classify("tel:%2B1555"), QrPayload::Phone { number: "+1555".to_string() });
+        let sms = |number: &str, body: &str| QrPayload::Sms {
+            number: number.to_string(),
+            body: body.to_string(),
+        };
+        assert_eq!(classify("sms:+1555?body=On%20my+way"), sms("+1555", "On my way"));
+        assert_eq!(classify("sms:+1555:hello"), sms("+1555", "hello"));
+        assert_eq!(classify("SMSTO:+1555:hi: there"), sms("+1555", "hi: there"));
+        assert_eq!(classify("sms:+1555"), sms("+1555", ""));
+    }
+
+    #[test]
+    fn geo_and_otpauth() {
+        let geo = classify("geo:48.8584,2.2945,300;crs=wgs84?q=Eiffel");
+        let expected = QrPayload::Geo {
+            latitude: 48.8584,
+            longitude: 2.2945,
+            altitude: Some(300.0),
+        };
+        assert_eq!(geo, expected);
+        let QrPayload::Geo { altitude, .. } = classify("GEO:-33.9,151.2") else {
+            panic!("not a geo payload");
+        };
+        assert_eq!(altitude, None);
+        assert_eq!(classify("geo:north,south"), QrPayload::Text);
+        assert_eq!(classify("geo:1,2,high"), QrPayload::Text);
+
+        let otp = classify("otpauth://TOTP/Acme%3Aalice?SECRET=JBSWY3DP&issuer=Acme");
+        let expected = QrPayload::Otpauth {
+            kind: "totp".to_string(),
+            label: "Acme:alice".to_string(),
+            secret: "JBSWY3DP".to_string(),
+            issuer: "Acme".to_string(),
+        };
+        assert_eq!(otp, expected);
+        assert_eq!(classify("otpauth:totp"), QrPayload::Text);
+    }
+
+    #[test]
+    fn urls_and_plain_text() {
+        let QrPayload::Url { url, .. } = classify("  https://example.com/a?b=c\n") else {
+            panic!("not a URL");
+        };
+        assert_eq!(url, "https://example.com/a?b=c");
+        assert!(matches!(classify("HTTP://EXAMPLE.COM"), QrPayload::Url { .. }));
+
+        for text in ["hello world", "http:example.com", "ftp://example.com", "12:30", ""] {
+            assert_eq!(classify(text), QrPayload::Text, "{:?}", text);
+        }
+    }
+
+    /// The fields of an `Event` payload
+    struct Event {
+        summary: String,
+        start: Option<EventTime>,
+        end: Option<EventTime>,
+        location: String,
+        description: String,
+        properties: BTreeMap<String, Vec<String>>,
+    }
+
+    fn event(data: &str) -> Event {
+        match classify(data) {
+            QrPayload::Event { summary, start, end, location, description, properties } => {
+                Event { summary, start, end, location, description, properties }
+            }
+            other => panic!("{:?} is not an event", other),
+        }
+    }
+
+    fn time(value: &str, tzid: Option<&str>, floating: bool, all_day: bool) -> Option<EventTime> {
+        let tzid = tzid.map(str::to_string);
+        Some(EventTime { value: value.to_string(), tzid, floating, all_day })
+    }
+
+    #[test]
+    fn events_unfold_unescape_and_keep_other_properties() {
+        let data = "BEGIN:VEVENT\r\n\
+                    SUMMARY:RustConf Keynote\\, Day 1\r\n\
+                    DTSTART;TZID=\"America/New_York\":20240910T090000\r\n\
+                    DTEND:20240910T103000Z\r\n\
+                    LOCATION:Hall A\\; Level 2\r\n\
+                    DESCRIPTION:First line\\nsecond li\r\n ne\\, folded\r\n\
+                    UID:abc@x\r\n\
+                    ATTENDEE;CN=\"Doe: J\":mailto:j@x\r\n\
+                    ATTENDEE:mailto:k@x\r\n\
+                    BEGIN:VALARM\r\nDESCRIPTION:alarm\r\nEND:VALARM\r\n\
+                    END:VEVENT\r\n";
+        let event = event(data);
+        assert_eq!(event.summary, "RustConf Keynote, Day 1");
+        assert_eq!(event.location, "Hall A; Level 2");
+        assert_eq!(event.description, "First line\nsecond line, folded");
+        let start = time("2024-09-10T09:00:00", Some("America/New_York"), false, false);
+        assert_eq!(event.start, start);
+        assert_eq!(event.end, time("2024-09-10T10:30:00Z", None, false, false));
+        // A quoted `:` in a parameter does not end the property name
+        assert_eq!(event.properties["ATTENDEE"], ["mailto:j@x", "mailto:k@x"]);
+        assert_eq!(event.properties["UID"], ["abc@x"]);
+        assert_eq!(event.properties.len(), 2);
+    }
+
+    #[test]
+    fn calendars_yield_their_first_event() {
+        let data = "BEGIN:VCALENDAR\nVERSION:2.0\n\
+                    BEGIN:VEVENT\nSUMMARY:Holiday\nDTSTART;VALUE=DATE:20241225\n\
+                    DTEND:20241226T000000\nEND:VEVENT\n\
+                    BEGIN:VEVENT\nSUMMARY:Other\nEND:VEVENT\n\
+                    END:VCALENDAR";
+        let event = event(data);
+        assert_eq!(event.summary, "Holiday");
+        assert_eq!(event.start, time("2024-12-25", None, true, true));
+        assert_eq!(event.end, time("2024-12-26T00:00:00", None, true, false));
+        assert!(event.properties.is_empty());
+
+        assert_eq!(classify("BEGIN:VCALENDAR\nVERSION:2.0\nEND:VCALENDAR"), QrPayload::Text);
+        assert!(matches!(classify("BEGIN:VCARD\nFN:x\nEND:VCARD"), QrPayload::Vcard { .. }));
+    }
+
+    #[test]
+    fn event_times_outside_the_formats_are_kept_as_properties() {
+        let data = "BEGIN:VEVENT\nDTSTART:tomorrow\nDTEND;TZID=UTC:20240910T0900Z\nEND:VEVENT";
+        let event = event(data);
+        assert_eq!((event.start, event.end), (None, None));
+        assert_eq!(event.properties["DTSTART"], ["tomorrow"]);
+        assert_eq!(event.properties["DTEND"], ["20240910T0900Z"]);
+
+        // The `Z` wins over a TZID, which UTC times may not carry
+        let utc = parse_event_time("20240910T090000z", Some("Europe/Paris".to_string()));
+        assert_eq!(utc, time("2024-09-10T09:00:00Z", None, false, false));
+        assert_eq!(parse_event_time("2024091", None), None);
+        assert_eq!(parse_event_time("20240910T09000a", None), None);
+    }
+
+    #[test]
+    fn percent_decoding_keeps_malformed_escapes() {
+        assert_eq!(percent_decode("a%20b%2Fc"), "a b/c");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%4"), "%zz%4");
+        assert_eq!(percent_decode("caf%C3%A9"), "café");
+        assert_eq!(percent_decode("%FF"), "\u{fffd}");
+    }
+
+    #[test]
+    fn payloads_serialize_tagged_by_type() {
+        let json = serde_json::to_value(classify("tel:+1555")).unwrap();
+        assert_eq!(json, serde_json::json!({ "type": "phone", "number": "+1555" }));
+        let json = serde_json::to_value(classify("plain")).unwrap();
+        assert_eq!(json, serde_json::json!({ "type": "text" }));
+    }
+}
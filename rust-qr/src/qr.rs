@@ -0,0 +1,5028 @@
+//! QR code detection, decoding and generation on plain grayscale images.
+//! Everything here is target independent; `wasm.rs` wraps it for JS.
+
+use image::imageops;
+use image::{GrayImage, ImageBuffer, ImageError, ImageReader, Limits};
+use rqrr::{BitGrid, PreparedImage};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Cursor;
+use std::sync::atomic::{AtomicU32, Ordering};
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+pub use crate::aamva::{AamvaAddress, AamvaData, AamvaSubfile};
+pub use crate::bcbp::{BcbpBoardingPass, BcbpLeg};
+#[cfg(feature = "cbor")]
+pub use crate::cbor::SCHEMA_VERSION as CBOR_SCHEMA_VERSION;
+pub use crate::emvco::{EmvcoField, EmvcoMerchantAccount, EmvcoQr};
+pub use crate::epc::{EpcPayment, EpcViolation};
+pub use crate::gs1::{Gs1Data, Gs1Element};
+pub use crate::otpauth::{OtpauthParams, OtpauthUri};
+pub use crate::payload::{EventTime, QrPayload};
+pub use crate::timing::{Clock, StageTimings};
+pub use crate::url_safety::UrlSafety;
+pub use crate::vds::{VdsFeature, VdsSeal};
+
+use crate::mrz::{self, CivilDate, MrzRegion};
+#[cfg(feature = "aztec")]
+use crate::aztec;
+#[cfg(feature = "datamatrix")]
+use crate::datamatrix;
+#[cfg(feature = "linear")]
+use crate::linear;
+#[cfg(feature = "microqr")]
+use crate::microqr;
+#[cfg(feature = "pdf417")]
+use crate::pdf417;
+#[cfg(feature = "cbor")]
+use crate::cbor;
+use crate::timing::{expired, now_ms, timed, Stage, StageTimer};
+use crate::{
+    aamva, bcbp, emvco, epc, gs1, otpauth, payload, qr_stream, url_safety, vds, VeloQrError,
+};
+
+/// Largest width or height accepted when decoding encoded (PNG/JPEG) images
+const MAX_ENCODED_DIMENSION: u32 = 16384;
+
+/// Default largest width x height accepted by any image entry point (64 MP)
+const DEFAULT_MAX_PIXELS: u32 = 64 * 1024 * 1024;
+
+/// Current pixel limit, changed through `set_max_image_pixels`
+static MAX_PIXELS: AtomicU32 = AtomicU32::new(DEFAULT_MAX_PIXELS);
+
+/// Barcode family of a decoded symbol
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Symbology {
+    #[default]
+    Qr,
+    #[cfg(feature = "datamatrix")]
+    DataMatrix,
+    #[cfg(feature = "aztec")]
+    Aztec,
+    #[cfg(feature = "microqr")]
+    MicroQr,
+    #[cfg(feature = "linear")]
+    Ean13,
+    #[cfg(feature = "linear")]
+    UpcA,
+    #[cfg(feature = "linear")]
+    Code128,
+    #[cfg(feature = "pdf417")]
+    Pdf417,
+}
+
+impl Symbology {
+    /// Every symbology compiled into this build
+    pub const ALL: &'static [Symbology] = &[
+        Symbology::Qr,
+        #[cfg(feature = "datamatrix")]
+        Symbology::DataMatrix,
+        #[cfg(feature = "aztec")]
+        Symbology::Aztec,
+        #[cfg(feature = "microqr")]
+        Symbology::MicroQr,
+        #[cfg(feature = "linear")]
+        Symbology::Ean13,
+        #[cfg(feature = "linear")]
+        Symbology::UpcA,
+        #[cfg(feature = "linear")]
+        Symbology::Code128,
+        #[cfg(feature = "pdf417")]
+        Symbology::Pdf417,
+    ];
+
+    /// `qr`, `datamatrix`, `aztec`, `microqr`, `ean13`, `upca`, `code128` or
+    /// `pdf417`, as used in `decode_symbols` format lists
+    pub fn parse(name: &str) -> Option<Symbology> {
+        Symbology::ALL.iter().copied().find(|s| s.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Symbology::Qr => "qr",
+            #[cfg(feature = "datamatrix")]
+            Symbology::DataMatrix => "datamatrix",
+            #[cfg(feature = "aztec")]
+            Symbology::Aztec => "aztec",
+            #[cfg(feature = "microqr")]
+            Symbology::MicroQr => "microqr",
+            #[cfg(feature = "linear")]
+            Symbology::Ean13 => "ean13",
+            #[cfg(feature = "linear")]
+            Symbology::UpcA => "upca",
+            #[cfg(feature = "linear")]
+            Symbology::Code128 => "code128",
+            #[cfg(feature = "pdf417")]
+            Symbology::Pdf417 => "pdf417",
+        }
+    }
+}
+
+/// A decoded symbol. Other symbologies reuse the QR layout: `bounds` are the
+/// outer corners starting at the top-left of the symbol's own frame, and
+/// `version` / `ecc_level` follow that symbology's conventions.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct QRCodeResult {
+    pub data: String,
+    pub version: i32,
+    pub bounds: Vec<(f64, f64)>,
+    pub ecc_level: String, // L, M, Q, H; ECC200 for Data Matrix, Aztec check words %, PDF417 0-8
+    pub mask: i32,
+    #[serde(with = "serde_bytes")]
+    pub raw_bytes: Vec<u8>, // Undecoded payload, empty `data` when not valid text
+    pub inverted: bool,     // Found on the light-on-dark retry pass
+    pub mirrored: bool,     // Found on the horizontally flipped retry pass
+    pub gs1: bool,          // Started with the FNC1 (GS1) mode indicator
+    #[serde(default)]
+    pub strategy: String, // `decode_qr_robust` pass that found the code, else empty
+    #[serde(default)]
+    pub orientation_degrees: f64, // Clockwise rotation of the code in the image
+    #[serde(default)]
+    pub center: (f64, f64), // Where the diagonals of `bounds` cross
+    #[serde(default)]
+    pub area: f64, // Pixels enclosed by `bounds`
+    /// Rotation in (-180, 180], clockwise positive (image y points down),
+    /// averaged over the top and bottom edges so skew cancels out
+    #[serde(default)]
+    pub angle_degrees: f64,
+    #[serde(default)]
+    pub module_size_px: f64, // Mean side length divided by the modules per side
+    /// Present when the symbol is one part of a structured-append sequence
+    #[serde(default)]
+    pub structured_append: Option<StructuredAppend>,
+    #[serde(default)]
+    pub encoding: String, // Source charset of `data`, e.g. "UTF-8", "Shift_JIS"
+    /// The payload could not be converted to text; `data` is empty and the
+    /// bytes are only in `raw_bytes`
+    #[serde(default)]
+    pub encoding_error: bool,
+    /// Upright grayscale PNG of the code when `return_crop` is set, else empty
+    #[serde(default, with = "serde_bytes")]
+    pub crop_png: Vec<u8>,
+    #[serde(default)]
+    pub symbology: Symbology,
+    /// Check digit (EAN/UPC) or check character (Code 128) result of 1D
+    /// symbols, which are reported even when it fails
+    #[serde(default)]
+    pub check_digit_valid: Option<bool>,
+    /// Codewords fixed by Reed-Solomon correction, over all blocks; 0 for 1D
+    #[serde(default)]
+    pub corrected_codewords: u32,
+    /// 1.0 without corrections, falling toward 0 as the most corrected block
+    /// nears what it can correct; 1D symbols have no correction and read 1.0
+    #[serde(default = "full_quality")]
+    pub quality: f64,
+    /// `hash_payload` of the payload bytes, set even when `raw_bytes` is not
+    /// returned; the same code gives the same hash across frames and sessions
+    #[serde(default)]
+    pub payload_hash: String,
+    /// Modules resampled from the image when `return_module_grid` is set;
+    /// QR codes only
+    #[serde(default)]
+    pub modules: Option<ModuleGrid>,
+    /// `QrScanner` track following this payload across frames while
+    /// smoothing is on or from `scan_tracked`, else `None`
+    #[serde(default)]
+    pub track_id: Option<u32>,
+    /// `bounds` averaged over the track's frames while `QrScanner`
+    /// tracks them, else empty; `bounds` stays this frame's estimate
+    #[serde(default)]
+    pub smoothed_bounds: Vec<(f64, f64)>,
+}
+
+fn full_quality() -> f64 {
+    1.0
+}
+
+/// Module matrix of a detected code, as the decoder reads it: in the code's
+/// own frame (so mirrored codes come out unmirrored) and with 1 = dark
+/// module (light pixels for codes found on the inverted pass)
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ModuleGrid {
+    pub size: u32, // Modules per side
+    #[serde(with = "serde_bytes")]
+    pub bits: Vec<u8>, // Row-major
+}
+
+/// Lowercase hex SHA-256 of a decoded payload, as in `QRCodeResult::payload_hash`.
+/// The algorithm is part of the API: stored hashes stay comparable.
+pub fn hash_payload(data: &[u8]) -> String {
+    crate::sha256::digest(data).iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// `QRCodeResult::quality` from the (corrected, correctable) codewords of
+/// every Reed-Solomon block
+pub(crate) fn correction_quality(blocks: impl IntoIterator<Item = (usize, usize)>) -> f64 {
+    let worst = blocks
+        .into_iter()
+        .map(|(corrected, capacity)| match capacity {
+            0 => 0.0,
+            _ => corrected as f64 / capacity as f64,
+        })
+        .fold(0.0, f64::max);
+    (1.0 - worst).clamp(0.0, 1.0)
+}
+
+impl QRCodeResult {
+    /// A freshly decoded code: no retry pass flags, geometry from `bounds`
+    fn decoded(
+        bounds: Vec<(f64, f64)>,
+        version: i32,
+        ecc_level: u16,
+        mask: i32,
+        raw_bytes: Vec<u8>,
+        text: PayloadText,
+    ) -> QRCodeResult {
+        let payload_hash = hash_payload(&raw_bytes);
+        let mut result = QRCodeResult {
+            symbology: Symbology::Qr,
+            data: text.data,
+            version,
+            bounds,
+            ecc_level: ecc_level_name(ecc_level).to_string(),
+            mask,
+            raw_bytes,
+            inverted: false,
+            mirrored: false,
+            gs1: false,
+            strategy: String::new(),
+            orientation_degrees: 0.0,
+            center: (0.0, 0.0),
+            area: 0.0,
+            angle_degrees: 0.0,
+            module_size_px: 0.0,
+            structured_append: None,
+            encoding: text.encoding,
+            encoding_error: text.failed,
+            check_digit_valid: None,
+            crop_png: Vec::new(),
+            modules: None,
+            track_id: None,
+            smoothed_bounds: Vec::new(),
+            corrected_codewords: 0,
+            quality: 1.0,
+            payload_hash,
+        };
+        result.update_geometry();
+        result
+    }
+
+    /// Recompute the fields derived from `bounds` and `version`
+    pub(crate) fn update_geometry(&mut self) {
+        self.orientation_degrees = orientation_degrees(&self.bounds);
+        let shape = match self.symbology {
+            Symbology::Qr => code_corners(&self.bounds, self.version).map(|corners| {
+                let modules = modules_per_side(self.version);
+                (corners, modules, modules)
+            }),
+            #[cfg(feature = "datamatrix")]
+            Symbology::DataMatrix => {
+                match (&self.bounds[..], datamatrix::dimensions(self.version)) {
+                    (&[a, b, c, d], Some((columns, rows))) => {
+                        Some(([a, b, c, d], columns as f64, rows as f64))
+                    }
+                    _ => None,
+                }
+            }
+            #[cfg(feature = "aztec")]
+            Symbology::Aztec => match (&self.bounds[..], aztec::dimensions(self.version)) {
+                (&[a, b, c, d], Some(modules)) => {
+                    Some(([a, b, c, d], modules as f64, modules as f64))
+                }
+                _ => None,
+            },
+            #[cfg(feature = "microqr")]
+            Symbology::MicroQr => match (&self.bounds[..], microqr::dimensions(self.version)) {
+                (&[a, b, c, d], Some(modules)) => {
+                    Some(([a, b, c, d], modules as f64, modules as f64))
+                }
+                _ => None,
+            },
+            #[cfg(feature = "linear")]
+            Symbology::Ean13 | Symbology::UpcA | Symbology::Code128 => match &self.bounds[..] {
+                // Bars have no rows of modules; the height counts module widths
+                &[a, b, c, d] => {
+                    let length = |p: (f64, f64), q: (f64, f64)| (q.0 - p.0).hypot(q.1 - p.1);
+                    let module = (length(a, b) + length(d, c)) / 2.0 / self.version as f64;
+                    let rows = (length(b, c) + length(a, d)) / 2.0 / module;
+                    Some(([a, b, c, d], self.version as f64, rows))
+                }
+                _ => None,
+            },
+            #[cfg(feature = "pdf417")]
+            Symbology::Pdf417 => match &self.bounds[..] {
+                // Rows are several modules tall; the height counts module widths
+                &[a, b, c, d] => {
+                    let length = |p: (f64, f64), q: (f64, f64)| (q.0 - p.0).hypot(q.1 - p.1);
+                    let columns = pdf417::modules(self.version) as f64;
+                    let module = (length(a, b) + length(d, c)) / 2.0 / columns;
+                    let rows = (length(b, c) + length(a, d)) / 2.0 / module;
+                    Some(([a, b, c, d], columns, rows))
+                }
+                _ => None,
+            },
+        };
+        if let Some((corners, columns, rows)) = shape {
+            self.update_shape(corners, columns, rows);
+        }
+    }
+
+    /// Area, center, angle and module size of a symbol with the given outer
+    /// corners (top-left first) and module counts
+    fn update_shape(&mut self, corners: [(f64, f64); 4], columns: f64, rows: f64) {
+        let [a, b, c, d] = corners;
+        self.area = quad_area(corners);
+        self.center = quad_center(corners);
+
+        let (dx, dy) = ((b.0 - a.0) + (c.0 - d.0), (b.1 - a.1) + (c.1 - d.1));
+        self.angle_degrees = if dx == 0.0 && dy == 0.0 {
+            0.0
+        } else {
+            let angle = dy.atan2(dx).to_degrees();
+            if angle <= -180.0 { angle + 360.0 } else { angle }
+        };
+
+        let length = |p: (f64, f64), q: (f64, f64)| (q.0 - p.0).hypot(q.1 - p.1);
+        let across = (length(a, b) + length(c, d)) / columns;
+        let down = (length(b, c) + length(d, a)) / rows;
+        self.module_size_px = (across + down) / 4.0;
+    }
+}
+
+/// Pixels enclosed by a quadrilateral, by the shoelace formula; the absolute
+/// value tolerates either winding
+fn quad_area(corners: [(f64, f64); 4]) -> f64 {
+    let doubled: f64 = (0..4)
+        .map(|i| {
+            let (p, q) = (corners[i], corners[(i + 1) % 4]);
+            p.0 * q.1 - q.0 * p.1
+        })
+        .sum();
+    doubled.abs() / 2.0
+}
+
+/// Where diagonals a-c and b-d cross; parallel ones (degenerate quad) fall
+/// back to the centroid
+fn quad_center([a, b, c, d]: [(f64, f64); 4]) -> (f64, f64) {
+    let (r, s) = ((c.0 - a.0, c.1 - a.1), (d.0 - b.0, d.1 - b.1));
+    let denominator = r.0 * s.1 - r.1 * s.0;
+    if denominator.abs() > f64::EPSILON {
+        let t = ((b.0 - a.0) * s.1 - (b.1 - a.1) * s.0) / denominator;
+        (a.0 + t * r.0, a.1 + t * r.1)
+    } else {
+        ((a.0 + b.0 + c.0 + d.0) / 4.0, (a.1 + b.1 + c.1 + d.1) / 4.0)
+    }
+}
+
+fn modules_per_side(version: i32) -> f64 {
+    (version * 4 + 17).max(1) as f64
+}
+
+/// Corners of the symbol itself, top-left first. rqrr's `bounds` reach one
+/// module past the right and bottom edges (it maps grid coordinate
+/// `size + 1`), so they are pulled back to `size` in the code's own frame.
+fn code_corners(bounds: &[(f64, f64)], version: i32) -> Option<[(f64, f64); 4]> {
+    let quad = match bounds[..] {
+        [a, b, c, d] => [a, b, c, d],
+        _ => return None,
+    };
+    let modules = modules_per_side(version);
+    let extent = modules / (modules + 1.0);
+    let map = Homography::square_to_quad(quad);
+    Some([
+        map.apply(0.0, 0.0),
+        map.apply(extent, 0.0),
+        map.apply(extent, extent),
+        map.apply(0.0, extent),
+    ])
+}
+
+/// A point and its image, `((u, v), (x, y))`
+#[cfg(any(feature = "aztec", feature = "microqr"))]
+pub(crate) type PointPair = ((f64, f64), (f64, f64));
+
+/// Projective map from the unit square onto a quadrilateral whose corners
+/// are given in the order (0,0), (1,0), (1,1), (0,1) (Heckbert's square-to-quad)
+#[derive(Clone, Copy)]
+pub(crate) struct Homography {
+    a: f64,
+    b: f64,
+    c: f64,
+    d: f64,
+    e: f64,
+    f: f64,
+    g: f64,
+    h: f64,
+}
+
+impl Homography {
+    pub(crate) fn square_to_quad([p0, p1, p2, p3]: [(f64, f64); 4]) -> Homography {
+        let (dx1, dy1) = (p1.0 - p2.0, p1.1 - p2.1);
+        let (dx2, dy2) = (p3.0 - p2.0, p3.1 - p2.1);
+        let (dx3, dy3) = (p0.0 - p1.0 + p2.0 - p3.0, p0.1 - p1.1 + p2.1 - p3.1);
+        let denominator = dx1 * dy2 - dx2 * dy1;
+        // Parallelograms (and degenerate quads) have no projective part
+        let (g, h) = if denominator.abs() > f64::EPSILON {
+            ((dx3 * dy2 - dx2 * dy3) / denominator, (dx1 * dy3 - dx3 * dy1) / denominator)
+        } else {
+            (0.0, 0.0)
+        };
+        Homography {
+            a: p1.0 - p0.0 + g * p1.0,
+            b: p3.0 - p0.0 + h * p3.0,
+            c: p0.0,
+            d: p1.1 - p0.1 + g * p1.1,
+            e: p3.1 - p0.1 + h * p3.1,
+            f: p0.1,
+            g,
+            h,
+        }
+    }
+
+    pub(crate) fn apply(&self, u: f64, v: f64) -> (f64, f64) {
+        let w = self.g * u + self.h * v + 1.0;
+        ((self.a * u + self.b * v + self.c) / w, (self.d * u + self.e * v + self.f) / w)
+    }
+
+    /// Least-squares fit through `(u, v) -> (x, y)` pairs, at least four of
+    /// them in general position
+    #[cfg(any(feature = "aztec", feature = "microqr"))]
+    pub(crate) fn fit(pairs: &[PointPair]) -> Option<Homography> {
+        if pairs.len() < 4 {
+            return None;
+        }
+        // Image coordinates are centered and scaled for a well-conditioned system
+        let n = pairs.len() as f64;
+        let (sx, sy) = pairs.iter().fold((0.0, 0.0), |acc, (_, p)| (acc.0 + p.0, acc.1 + p.1));
+        let (cx, cy) = (sx / n, sy / n);
+        let scale = pairs.iter().map(|(_, p)| (p.0 - cx).hypot(p.1 - cy)).sum::<f64>() / n;
+        if scale <= f64::EPSILON {
+            return None;
+        }
+
+        let mut system = [[0.0f64; 9]; 8];
+        for &((u, v), (x, y)) in pairs {
+            let (x, y) = ((x - cx) / scale, (y - cy) / scale);
+            let rows = [
+                ([u, v, 1.0, 0.0, 0.0, 0.0, -u * x, -v * x], x),
+                ([0.0, 0.0, 0.0, u, v, 1.0, -u * y, -v * y], y),
+            ];
+            for (row, target) in rows {
+                for (equation, &ri) in system.iter_mut().zip(&row) {
+                    for (value, &rj) in equation.iter_mut().zip(&row) {
+                        *value += ri * rj;
+                    }
+                    equation[8] += ri * target;
+                }
+            }
+        }
+        let [a, b, c, d, e, f, g, h] = solve_normal_equations(system)?;
+        Some(Homography {
+            a: scale * a + cx * g,
+            b: scale * b + cx * h,
+            c: scale * c + cx,
+            d: scale * d + cy * g,
+            e: scale * e + cy * h,
+            f: scale * f + cy,
+            g,
+            h,
+        })
+    }
+
+    /// Least-squares affine fit, for pairs that leave the perspective
+    /// undetermined; at least three of them not on one line
+    #[cfg(feature = "microqr")]
+    pub(crate) fn fit_affine(pairs: &[PointPair]) -> Option<Homography> {
+        let mut normal = [[0.0f64; 3]; 3];
+        let mut targets = [[0.0f64; 3]; 2];
+        for &((u, v), (x, y)) in pairs {
+            let row = [u, v, 1.0];
+            for (equation, &ri) in normal.iter_mut().zip(&row) {
+                for (value, &rj) in equation.iter_mut().zip(&row) {
+                    *value += ri * rj;
+                }
+            }
+            for (target, image) in targets.iter_mut().zip([x, y]) {
+                for (value, &ri) in target.iter_mut().zip(&row) {
+                    *value += ri * image;
+                }
+            }
+        }
+        // Cramer's rule, one column of the system replaced at a time
+        let determinant = |m: [[f64; 3]; 3]| {
+            m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+                - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+                + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+        };
+        let whole = determinant(normal);
+        if whole.abs() < 1e-12 {
+            return None;
+        }
+        let solve = |target: [f64; 3]| {
+            [0, 1, 2].map(|col| {
+                let mut m = normal;
+                for (row, &value) in m.iter_mut().zip(&target) {
+                    row[col] = value;
+                }
+                determinant(m) / whole
+            })
+        };
+        let ([a, b, c], [d, e, f]) = (solve(targets[0]), solve(targets[1]));
+        Some(Homography { a, b, c, d, e, f, g: 0.0, h: 0.0 })
+    }
+}
+
+/// Gauss-Jordan elimination with partial pivoting of an augmented 8 x 8 system
+#[cfg(any(feature = "aztec", feature = "microqr"))]
+fn solve_normal_equations(mut system: [[f64; 9]; 8]) -> Option<[f64; 8]> {
+    for col in 0..8 {
+        let pivot =
+            (col..8).max_by(|&a, &b| system[a][col].abs().total_cmp(&system[b][col].abs()))?;
+        if system[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        system.swap(col, pivot);
+        let pivot_row = system[col];
+        for (r, row) in system.iter_mut().enumerate() {
+            if r != col {
+                let factor = row[col] / pivot_row[col];
+                for (value, p) in row.iter_mut().zip(pivot_row) {
+                    *value -= factor * p;
+                }
+            }
+        }
+    }
+    let mut solution = [0.0; 8];
+    for (i, value) in solution.iter_mut().enumerate() {
+        *value = system[i][8] / system[i][i];
+    }
+    Some(solution)
+}
+
+/// Whether `point` lies inside a convex quadrilateral of either winding
+#[cfg(any(feature = "datamatrix", feature = "aztec", feature = "microqr", feature = "linear"))]
+pub(crate) fn contains(quad: &[(f64, f64)], point: (f64, f64)) -> bool {
+    if quad.len() != 4 {
+        return false;
+    }
+    let signs: Vec<bool> = (0..4)
+        .map(|i| {
+            let (p, q) = (quad[i], quad[(i + 1) % 4]);
+            (q.0 - p.0) * (point.1 - p.1) - (q.1 - p.1) * (point.0 - p.0) >= 0.0
+        })
+        .collect();
+    signs.iter().all(|&s| s) || signs.iter().all(|&s| !s)
+}
+
+/// Thresholded image lookup; everything outside is light
+#[cfg(any(feature = "datamatrix", feature = "aztec", feature = "microqr"))]
+pub(crate) fn is_dark(binary: &GrayImage, (x, y): (f64, f64)) -> bool {
+    if x < 0.0 || y < 0.0 || x >= binary.width() as f64 || y >= binary.height() as f64 {
+        return false;
+    }
+    binary.get_pixel(x as u32, y as u32).0[0] == 0
+}
+
+/// Bilinear sample of the grayscale image at a pixel-space point, clamped
+/// to the border
+#[cfg(any(feature = "datamatrix", feature = "aztec", feature = "microqr", feature = "linear"))]
+pub(crate) fn sample(gray: &GrayImage, (x, y): (f64, f64)) -> f64 {
+    let (x, y) = (x - 0.5, y - 0.5);
+    let (max_x, max_y) = (gray.width() as i64 - 1, gray.height() as i64 - 1);
+    let pixel = |x: i64, y: i64| {
+        gray.get_pixel(x.clamp(0, max_x) as u32, y.clamp(0, max_y) as u32).0[0] as f64
+    };
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let (x0, y0) = (x0 as i64, y0 as i64);
+    let top = pixel(x0, y0) * (1.0 - fx) + pixel(x0 + 1, y0) * fx;
+    let bottom = pixel(x0, y0 + 1) * (1.0 - fx) + pixel(x0 + 1, y0 + 1) * fx;
+    top * (1.0 - fy) + bottom * fy
+}
+
+/// A fitted edge through `point` along the unit `direction`
+#[cfg(any(feature = "datamatrix", feature = "aztec", feature = "microqr", feature = "linear"))]
+pub(crate) struct Line {
+    pub(crate) point: (f64, f64),
+    pub(crate) direction: (f64, f64),
+}
+
+#[cfg(any(feature = "datamatrix", feature = "aztec", feature = "microqr", feature = "linear"))]
+impl Line {
+    /// Total least squares line through at least two points
+    #[cfg(any(feature = "aztec", feature = "microqr", feature = "linear"))]
+    pub(crate) fn fit(points: &[(f64, f64)]) -> Option<Line> {
+        if points.len() < 2 {
+            return None;
+        }
+        let n = points.len() as f64;
+        let (sx, sy) = points.iter().fold((0.0, 0.0), |acc, p| (acc.0 + p.0, acc.1 + p.1));
+        let point = (sx / n, sy / n);
+        let (mut xx, mut xy, mut yy) = (0.0, 0.0, 0.0);
+        for &(x, y) in points {
+            let (dx, dy) = (x - point.0, y - point.1);
+            xx += dx * dx;
+            xy += dx * dy;
+            yy += dy * dy;
+        }
+        // Principal axis of the scatter
+        let angle = 0.5 * (2.0 * xy).atan2(xx - yy);
+        Some(Line { point, direction: (angle.cos(), angle.sin()) })
+    }
+
+    #[cfg(any(feature = "datamatrix", feature = "aztec", feature = "microqr"))]
+    pub(crate) fn intersect(&self, other: &Line) -> Option<(f64, f64)> {
+        let (d, e) = (self.direction, other.direction);
+        let denominator = d.0 * e.1 - d.1 * e.0;
+        if denominator.abs() < 1e-6 {
+            return None;
+        }
+        let (wx, wy) = (other.point.0 - self.point.0, other.point.1 - self.point.1);
+        let t = (wx * e.1 - wy * e.0) / denominator;
+        Some((self.point.0 + t * d.0, self.point.1 + t * d.1))
+    }
+}
+
+/// Rays cast from the center of a finder target to find its edge
+#[cfg(any(feature = "aztec", feature = "microqr"))]
+const RAYS: usize = 64;
+
+/// `(start, length)` runs of alternating color along a line of pixels; the
+/// first run is dark when `first_dark` is set
+#[cfg(any(feature = "aztec", feature = "microqr"))]
+pub(crate) fn runs(pixels: impl Iterator<Item = u8>, runs: &mut Vec<(usize, usize)>) -> bool {
+    runs.clear();
+    let mut first_dark = false;
+    let mut previous = None;
+    for (i, value) in pixels.enumerate() {
+        let dark = value == 0;
+        if previous == Some(dark) {
+            if let Some(run) = runs.last_mut() {
+                run.1 += 1;
+            }
+        } else {
+            if previous.is_none() {
+                first_dark = dark;
+            }
+            runs.push((i, 1));
+            previous = Some(dark);
+        }
+    }
+    first_dark
+}
+
+/// Steps from a dark pixel to the first `N` color changes along a
+/// direction, e.g. the ring edges of a finder target from its center
+#[cfg(any(feature = "aztec", feature = "microqr"))]
+pub(crate) fn color_steps<const N: usize>(
+    binary: &GrayImage,
+    (x, y): (i64, i64),
+    (dx, dy): (i64, i64),
+    limit: i64,
+) -> Option<[i64; N]> {
+    let (width, height) = (binary.width() as i64, binary.height() as i64);
+    let raw = binary.as_raw();
+    let (mut steps, mut changes, mut dark) = ([0; N], 0, true);
+    for step in 0..=limit {
+        let (px, py) = (x + dx * step, y + dy * step);
+        if px < 0 || py < 0 || px >= width || py >= height {
+            return None;
+        }
+        if (raw[(py * width + px) as usize] == 0) != dark {
+            if step == 0 {
+                return None;
+            }
+            dark = !dark;
+            steps[changes] = step;
+            changes += 1;
+            if changes == N {
+                return Some(steps);
+            }
+        }
+    }
+    None
+}
+
+/// First guess of the module frame of a finder target with a dark center:
+/// the smallest rectangle around its third color change, cast along rays
+/// no longer than `limit`, whose corners sit at `+-radius` modules
+#[cfg(any(feature = "aztec", feature = "microqr"))]
+pub(crate) fn square_frame(
+    binary: &GrayImage,
+    (cx, cy): (f64, f64),
+    limit: f64,
+    radius: f64,
+) -> Option<Homography> {
+    let edge: Vec<(f64, f64)> = (0..RAYS)
+        .filter_map(|k| {
+            let angle = k as f64 * std::f64::consts::TAU / RAYS as f64;
+            let (dx, dy) = (angle.cos(), angle.sin());
+            let (mut dark, mut transitions, mut r) = (true, 0, 0.0);
+            while r < limit {
+                let point = (cx + dx * r, cy + dy * r);
+                if is_dark(binary, point) != dark {
+                    dark = !dark;
+                    transitions += 1;
+                    if transitions == 3 {
+                        return Some(point);
+                    }
+                }
+                r += 0.25;
+            }
+            None
+        })
+        .collect();
+    if edge.len() < RAYS * 3 / 4 {
+        return None;
+    }
+
+    // Rotating calipers over the directions between neighbouring ray hits
+    let mut best: Option<(f64, [(f64, f64); 4])> = None;
+    for i in 0..edge.len() {
+        let (p, q) = (edge[i], edge[(i + 1) % edge.len()]);
+        let length = (q.0 - p.0).hypot(q.1 - p.1);
+        if length < f64::EPSILON {
+            continue;
+        }
+        let (ux, uy) = ((q.0 - p.0) / length, (q.1 - p.1) / length);
+        let (mut a_min, mut a_max, mut b_min, mut b_max) = (f64::MAX, f64::MIN, f64::MAX, f64::MIN);
+        for &(x, y) in &edge {
+            let (a, b) = (x * ux + y * uy, -x * uy + y * ux);
+            a_min = a_min.min(a);
+            a_max = a_max.max(a);
+            b_min = b_min.min(b);
+            b_max = b_max.max(b);
+        }
+        let area = (a_max - a_min) * (b_max - b_min);
+        let corner = |a: f64, b: f64| (a * ux - b * uy, a * uy + b * ux);
+        let corners = [
+            corner(a_min, b_min),
+            corner(a_max, b_min),
+            corner(a_max, b_max),
+            corner(a_min, b_max),
+        ];
+        if best.as_ref().is_none_or(|(best_area, _)| area < *best_area) {
+            best = Some((area, corners));
+        }
+    }
+    let (_, corners) = best?;
+    let r = radius;
+    let square = [(-r, -r), (r, -r), (r, r), (-r, r)];
+    Homography::fit(&square.into_iter().zip(corners).collect::<Vec<PointPair>>())
+}
+
+/// Measured image positions of the corners of square ring edges, given as
+/// their radius in modules from the center and whether they get lighter
+/// going out, paired with their module coordinates. Each edge is searched
+/// across its predicted position for the strongest step of the expected
+/// polarity, at least `min_step`; a line through the steps of every side
+/// gives the corners where neighbouring sides meet.
+#[cfg(any(feature = "aztec", feature = "microqr"))]
+pub(crate) fn ring_corners(
+    gray: &GrayImage,
+    map: &Homography,
+    edges: &[(f64, bool)],
+    min_step: f64,
+) -> Vec<PointPair> {
+    // Edges of the same polarity are two modules apart
+    const STEPS: usize = 19;
+    const REACH: f64 = 0.9;
+    let mut pairs = Vec::new();
+    for &(r, rising) in edges {
+        let mut sides: [Vec<(f64, f64)>; 4] = Default::default();
+        // The corner modules are skipped, blur bends the edges there
+        let mut t = -r + 1.0;
+        while t <= r - 1.0 + 1e-9 {
+            let edges = [
+                ((t, -r), (0.0, -1.0)),
+                ((r, t), (1.0, 0.0)),
+                ((t, r), (0.0, 1.0)),
+                ((-r, t), (-1.0, 0.0)),
+            ];
+            for (side, (point, normal)) in sides.iter_mut().zip(edges) {
+                let at = |s: f64| {
+                    let offset = -REACH + 2.0 * REACH * s / (STEPS - 1) as f64;
+                    map.apply(point.0 + normal.0 * offset, point.1 + normal.1 * offset)
+                };
+                let profile: Vec<f64> =
+                    (0..STEPS).map(|s| sample(gray, at(s as f64))).collect();
+                let sign = if rising { 1.0 } else { -1.0 };
+                let steps: Vec<f64> = (1..STEPS - 1)
+                    .map(|s| sign * (profile[s + 1] - profile[s - 1]))
+                    .collect();
+                let Some((best, &strength)) = steps
+                    .iter()
+                    .enumerate()
+                    .max_by(|a, b| a.1.total_cmp(b.1))
+                else {
+                    continue;
+                };
+                if strength < min_step {
+                    continue;
+                }
+                // Parabola through the neighbouring steps for a sub-sample peak
+                let before = if best > 0 { steps[best - 1] } else { strength };
+                let after = steps.get(best + 1).copied().unwrap_or(strength);
+                let curvature = before - 2.0 * strength + after;
+                let shift = if curvature < 0.0 {
+                    (0.5 * (before - after) / curvature).clamp(-0.5, 0.5)
+                } else {
+                    0.0
+                };
+                side.push(at(best as f64 + 1.0 + shift));
+            }
+            t += 0.5;
+        }
+
+        let lines: Vec<Option<Line>> = sides.iter().map(|side| Line::fit(side)).collect();
+        let corners = [(-r, -r), (r, -r), (r, r), (-r, r)];
+        // Corner k joins side k - 1 and side k (top, right, bottom, left)
+        for (k, corner) in corners.into_iter().enumerate() {
+            let (Some(a), Some(b)) = (&lines[(k + 3) % 4], &lines[k]) else {
+                continue;
+            };
+            if let Some(point) = a.intersect(b) {
+                pairs.push((corner, point));
+            }
+        }
+    }
+    pairs
+}
+
+/// The eight symmetries of the square, mapping symbol offsets into the
+/// module frame of the fitted homography; odd indices are reflections
+#[cfg(any(feature = "aztec", feature = "microqr"))]
+pub(crate) fn transform(index: usize, x: f64, y: f64) -> (f64, f64) {
+    let (x, y) = if index % 2 == 1 { (-x, y) } else { (x, y) };
+    match index / 2 {
+        0 => (x, y),
+        1 => (-y, x),
+        2 => (-x, -y),
+        _ => (y, -x),
+    }
+}
+
+/// Position of a symbol in a structured-append sequence
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StructuredAppend {
+    pub index: u8,  // 0-based position in the sequence
+    pub total: u8,  // Number of symbols in the sequence, 1-16
+    pub parity: u8, // XOR of every byte of the complete payload
+}
+
+/// A located QR grid that could not be decoded
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FailedGrid {
+    pub bounds: Vec<(f64, f64)>,
+    pub error: String, // rqrr DeQRError variant, e.g. "DataEcc"
+    /// Mean side of the grid over its modules per side, in pixels
+    #[serde(default)]
+    pub module_size: f64,
+}
+
+/// Successful decodes plus the grids that were found but unreadable
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct DecodeReport {
+    pub results: Vec<QRCodeResult>,
+    pub failed: Vec<FailedGrid>,
+}
+
+/// Why a scan probably found nothing, for UI guidance (e.g. offer the torch)
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanHint {
+    /// Codes were found, or the exposure looks fine
+    #[default]
+    None,
+    TooDark,
+    Glare,
+    LowContrast,
+}
+
+/// Decode results plus a `hint` that is only set when `results` is empty
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct HintedDecode {
+    pub results: Vec<QRCodeResult>,
+    pub hint: ScanHint,
+    /// Luma distribution of the frame when `return_luma_stats` is set
+    #[serde(default)]
+    pub luma_stats: Option<LumaStats>,
+    /// `time_budget_ms` ran out; `results` holds what was found before
+    #[serde(default)]
+    pub timed_out: bool,
+    /// Grids left undecoded because of `max_grid_candidates`
+    #[serde(default)]
+    pub skipped_candidates: u32,
+}
+
+/// Luma distribution of a frame for exposure control, from the same
+/// conversion the detector sees
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LumaStats {
+    /// Pixels per luma value, 256 bins
+    pub histogram: Vec<u32>,
+    pub p5: u8,
+    pub p50: u8,
+    pub p95: u8,
+    /// Fraction of pixels clipped at 255
+    pub clipped_high: f64,
+}
+
+/// Decode results plus where the time went, for the `with_timings` option
+#[derive(Serialize, Clone, Default)]
+pub struct TimedDecode {
+    pub results: Vec<QRCodeResult>,
+    pub timings: StageTimings,
+    /// `time_budget_ms` ran out; `results` holds what was found before
+    pub timed_out: bool,
+    /// Grids left undecoded because of `max_grid_candidates`
+    pub skipped_candidates: u32,
+}
+
+/// Codes and likely MRZ regions of one document photo, see `scan_document`
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct DocumentScan {
+    pub qr: Vec<QRCodeResult>,
+    pub mrz_regions: Vec<MrzRegion>,
+}
+
+/// A located QR grid that has not been decoded
+#[derive(Serialize, Deserialize, Clone)]
+pub struct GridCandidate {
+    pub bounds: Vec<(f64, f64)>,
+    pub size: u32, // Modules per side
+}
+
+/// Cheap per-frame statistics for deciding whether a decode is worth running
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+pub struct FrameQuality {
+    pub sharpness: f64, // Variance of the Laplacian; low for blurred frames
+    pub mean_luma: f64, // 0-255
+    pub contrast: f64,  // Standard deviation of the luma
+}
+
+/// Tuning knobs for `decode_qr_with_options`, deserialized from a JS object.
+/// Every field is optional; missing fields fall back to the defaults and
+/// unknown fields are ignored so older/newer JS bundles interoperate.
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct DecodeOptions {
+    /// Keep at most this many codes, after `min_size_px` and `sort`
+    pub max_results: Option<u32>,
+    /// Drop codes whose `area` is smaller than a square of this side
+    pub min_size_px: Option<u32>,
+    /// Order of the returned codes
+    pub sort: ResultOrder,
+    /// Only search inside this region; bounds stay in full-frame coordinates
+    pub roi: Option<Roi>,
+    /// Downscale the image before detection when its larger side exceeds this
+    pub max_dimension: Option<u32>,
+    /// Retry at full resolution when the downscaled pass finds nothing
+    pub retry_full_resolution: bool,
+    /// Include `raw_bytes` in the results (left empty when false)
+    pub return_raw_bytes: bool,
+    /// Retry on the inverted image when nothing is found (light-on-dark codes)
+    pub try_inverted: bool,
+    /// Retry on the horizontally flipped image when nothing is found (selfie cameras)
+    pub try_mirrored: bool,
+    /// Thresholding applied before detection
+    pub binarization: Binarization,
+    /// Side of the local window for `adaptive` and `sauvola`, in pixels;
+    /// defaults to 1/8 of the smaller image side
+    pub window: Option<u32>,
+    /// Sauvola sensitivity; higher values mark fewer pixels dark
+    pub k: f32,
+    /// Detect in overlapping tiles instead of the whole image (small codes on large scans)
+    pub tiling: Option<Tiling>,
+    /// Retry on the image rotated by 90/180/270 degrees when nothing is found
+    pub try_rotations: bool,
+    /// When nothing is found in a small image, retry it upscaled 2x and then
+    /// 3x with nearest-neighbour sampling (bilinear softens module edges);
+    /// small means a larger side under `upscale_below` or a located grid with
+    /// modules under 2.5 pixels
+    pub try_upscale: bool,
+    /// Larger image side in pixels under which `try_upscale` applies
+    pub upscale_below: u32,
+    /// When nothing is found, retry with a border of this many pixels in the
+    /// background color around the image, for codes cropped to their modules
+    /// (screenshots without a quiet zone); 0 disables. About 4 modules is
+    /// enough, in pixels of the downscaled image when `max_dimension` applies.
+    pub add_quiet_zone: u32,
+    /// Attach a perspective-corrected PNG of each decoded code as `crop_png`
+    pub return_crop: bool,
+    /// Side of the square crop in pixels
+    pub crop_size: u32,
+    /// Attach the module matrix of each decoded QR code as `modules`,
+    /// sampled at the module centers through the perspective of its bounds
+    pub return_module_grid: bool,
+    /// Limits behind the `hint` of `decode_qr_with_hint`
+    pub hint_thresholds: HintThresholds,
+    /// Add the frame's `luma_stats` to `decode_qr_with_hint`, from the same
+    /// conversion pass
+    pub return_luma_stats: bool,
+    /// Background and alpha layout used when converting RGBA input
+    pub alpha: AlphaOptions,
+    /// Return `{ results, timings }` with the milliseconds spent per stage
+    pub with_timings: bool,
+    /// Stop once this many milliseconds have passed, checked between grid
+    /// decodes, retry passes and tiles, and keep the codes found so far;
+    /// non-positive means no limit
+    pub time_budget_ms: Option<f64>,
+    /// Decode at most this many grids per detection pass, the most plausible
+    /// (large, square) ones, so noisy frames full of false candidates stay
+    /// fast; 0 decodes every grid
+    pub max_grid_candidates: u32,
+}
+
+/// Order of the codes returned by `decode_qr_with_options`
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ResultOrder {
+    /// Detection order
+    #[default]
+    None,
+    /// Largest `area` first
+    AreaDesc,
+    /// Nearest the frame center first
+    CenterDistance,
+}
+
+/// Binarization used before QR detection
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Binarization {
+    /// rqrr's own thresholding
+    #[default]
+    Default,
+    /// One global threshold from the histogram (Otsu's method)
+    Otsu,
+    /// Mean of the surrounding window minus a small offset
+    Adaptive,
+    /// Window mean adjusted by the local standard deviation (documents, uneven light)
+    Sauvola,
+}
+
+impl Default for DecodeOptions {
+    fn default() -> Self {
+        DecodeOptions {
+            max_results: None,
+            min_size_px: None,
+            sort: ResultOrder::None,
+            roi: None,
+            max_dimension: None,
+            retry_full_resolution: false,
+            return_raw_bytes: true,
+            try_inverted: false,
+            try_mirrored: false,
+            binarization: Binarization::Default,
+            window: None,
+            k: 0.2,
+            tiling: None,
+            try_rotations: false,
+            try_upscale: false,
+            upscale_below: 400,
+            add_quiet_zone: 0,
+            return_crop: false,
+            crop_size: 256,
+            return_module_grid: false,
+            hint_thresholds: HintThresholds::default(),
+            return_luma_stats: false,
+            alpha: AlphaOptions::default(),
+            with_timings: false,
+            time_budget_ms: None,
+            max_grid_candidates: 16,
+        }
+    }
+}
+
+impl DecodeOptions {
+    /// A timer enforcing `time_budget_ms` and `max_grid_candidates`, or none
+    /// when neither limits the decode
+    fn limits_timer(&self) -> Option<StageTimer<'static>> {
+        if self.budget_ms().is_none() && self.max_grid_candidates == 0 {
+            return None;
+        }
+        Some(self.limits_timer_with(Box::new(now_ms)))
+    }
+
+    fn limits_timer_with<'a>(&self, clock: Box<dyn Clock + 'a>) -> StageTimer<'a> {
+        let mut timer = StageTimer::with_budget(clock, self.budget_ms());
+        timer.max_grids = self.max_grid_candidates as usize;
+        timer
+    }
+
+    fn budget_ms(&self) -> Option<f64> {
+        self.time_budget_ms.filter(|&budget| budget > 0.0)
+    }
+}
+
+/// When to blame the exposure for an empty scan
+#[derive(Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct HintThresholds {
+    /// `too_dark` below this mean luma
+    pub dark_luma: f64,
+    /// `glare` when more than this fraction of pixels is saturated at 255
+    pub saturated_fraction: f64,
+    /// `low_contrast` when the 5th to 95th percentile luma span is narrower
+    pub min_luma_range: u8,
+}
+
+impl Default for HintThresholds {
+    fn default() -> Self {
+        HintThresholds {
+            dark_luma: 40.0,
+            saturated_fraction: 0.25,
+            min_luma_range: 40,
+        }
+    }
+}
+
+/// Rectangular region of interest in image pixels
+#[derive(Deserialize, Clone, Copy)]
+pub struct Roi {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Tile layout for tiled detection, in pixels.
+/// `overlap` should be at least the size of the largest expected code so
+/// every code lies wholly inside some tile.
+#[derive(Deserialize, Clone, Copy)]
+pub struct Tiling {
+    pub tile_size: u32,
+    pub overlap: u32,
+}
+
+/// Options for `decode_qr_robust`; every field is optional
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct RobustOptions {
+    /// Passes to try, in order; defaults to plain, inverted, adaptive,
+    /// quiet_zone and then downscale for large images or upscale for small ones
+    pub strategies: Option<Vec<Strategy>>,
+}
+
+/// One pass of the `decode_qr_robust` cascade
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Strategy {
+    Plain,
+    Inverted,
+    Adaptive,
+    /// Half resolution, for huge inputs
+    Downscale,
+    /// Double resolution with nearest-neighbour sampling, for tiny codes
+    Upscale,
+    /// A background border of 1/5 of the smaller side, for codes cropped to
+    /// their modules: at least the 4-module quiet zone of any version
+    #[serde(rename = "quiet_zone")]
+    QuietZone,
+}
+
+impl Strategy {
+    fn name(self) -> &'static str {
+        match self {
+            Strategy::Plain => "plain",
+            Strategy::Inverted => "inverted",
+            Strategy::Adaptive => "adaptive",
+            Strategy::Downscale => "downscale",
+            Strategy::Upscale => "upscale",
+            Strategy::QuietZone => "quiet_zone",
+        }
+    }
+}
+
+/// Images whose larger side exceeds this get a downscale pass instead of upscale
+const ROBUST_LARGE_SIDE: u32 = 1600;
+
+// ==================== Decoding ====================
+
+/// Decode every QR code in a grayscale image, taken by value since
+/// preparing it for detection consumes the buffer
+pub fn decode_image(image: GrayImage) -> Vec<QRCodeResult> {
+    decode_from_gray_image(image)
+}
+
+/// Decode the requested symbologies in one frame, QR results first; an empty
+/// `formats` list means every symbology compiled into this build
+pub fn decode_symbols(image: GrayImage, formats: &[Symbology]) -> Vec<QRCodeResult> {
+    let formats = if formats.is_empty() { Symbology::ALL } else { formats };
+    let others: Vec<QRCodeResult> = Symbology::ALL
+        .iter()
+        .filter(|symbology| formats.contains(symbology))
+        .flat_map(|symbology| match symbology {
+            // Decoded last, consuming the image
+            Symbology::Qr => Vec::new(),
+            #[cfg(feature = "datamatrix")]
+            Symbology::DataMatrix => datamatrix::decode(&image),
+            #[cfg(feature = "aztec")]
+            Symbology::Aztec => aztec::decode(&image),
+            #[cfg(feature = "microqr")]
+            Symbology::MicroQr => microqr::decode(&image),
+            // Every 1D symbology comes from the same scanlines, read below
+            #[cfg(feature = "linear")]
+            Symbology::Ean13 | Symbology::UpcA | Symbology::Code128 => Vec::new(),
+            #[cfg(feature = "pdf417")]
+            Symbology::Pdf417 => pdf417::decode(&image),
+        })
+        .collect();
+    #[cfg(feature = "linear")]
+    let others = [others, linear::decode(&image, formats)].concat();
+    let mut results =
+        if formats.contains(&Symbology::Qr) { decode_image(image) } else { Vec::new() };
+    results.extend(others);
+    results
+}
+
+/// Set the largest width x height accepted by the image entry points;
+/// 0 restores the default of 64 MP. Larger frames fail with `IMAGE_TOO_LARGE`.
+pub fn set_max_image_pixels(max_pixels: u32) {
+    let max_pixels = if max_pixels == 0 { DEFAULT_MAX_PIXELS } else { max_pixels };
+    MAX_PIXELS.store(max_pixels, Ordering::Relaxed);
+}
+
+/// Decode an RGBA frame with `options`, explaining an empty result with a
+/// `ScanHint` computed from the luma histogram of the conversion pass
+pub fn decode_rgba_with_hint(
+    rgba: &[u8],
+    width: u32,
+    height: u32,
+    stride_bytes: Option<u32>,
+    options: &DecodeOptions,
+) -> Result<HintedDecode, VeloQrError> {
+    let (gray_image, histogram) =
+        rgba_to_gray_with_histogram(rgba, width, height, stride_bytes, &options.alpha)?;
+
+    let mut timer = options.limits_timer();
+    let results = decode_gray_timed(gray_image, options, timer.as_mut())?;
+    let hint = if results.is_empty() {
+        histogram.hint(&options.hint_thresholds)
+    } else {
+        ScanHint::None
+    };
+    let luma_stats = options.return_luma_stats.then(|| histogram.stats());
+    let timed_out = timer.as_ref().is_some_and(StageTimer::timed_out);
+    let skipped_candidates = timer.map_or(0, |timer| timer.skipped_grids);
+
+    Ok(HintedDecode { results, hint, luma_stats, timed_out, skipped_candidates })
+}
+
+/// Decode codes with `options` and locate likely MRZ bands in the same
+/// frame, so a document photo takes one call; OCR can then read only the
+/// region crops instead of the whole image
+pub fn scan_document(
+    gray_image: GrayImage,
+    options: &DecodeOptions,
+) -> Result<DocumentScan, VeloQrError> {
+    let mrz_regions = mrz::find_mrz_regions(&gray_image);
+    let qr = decode_gray_with_options(gray_image, options)?;
+
+    Ok(DocumentScan { qr, mrz_regions })
+}
+
+/// Count the luma values of an RGBA frame, converted (over white) exactly
+/// as for decoding; `stride_bytes` as for `rgba_to_gray_with_stride`
+pub fn rgba_luma_histogram(
+    rgba: &[u8],
+    width: u32,
+    height: u32,
+    stride_bytes: Option<u32>,
+) -> Result<LumaHistogram, VeloQrError> {
+    let mut luma = Vec::new();
+    let mut histogram = LumaHistogram::default();
+    let alpha = AlphaOptions::default();
+    let histogram_out = Some(&mut histogram);
+    rgba_to_luma_with_stride(rgba, width, height, stride_bytes, &alpha, &mut luma, histogram_out)?;
+
+    Ok(histogram)
+}
+
+/// Decode inside a region of interest, clamped to the image; returned bounds
+/// are in full-frame coordinates
+pub fn decode_gray_in_roi(
+    gray_image: &GrayImage,
+    x: u32,
+    y: u32,
+    roi_width: u32,
+    roi_height: u32,
+) -> Result<Vec<QRCodeResult>, VeloQrError> {
+    let roi = clamp_roi(gray_image.width(), gray_image.height(), x, y, roi_width, roi_height)?;
+    Ok(decode_gray_roi(gray_image, roi))
+}
+
+/// Crop the grayscale image to `roi`, decode, and translate bounds back
+fn decode_gray_roi(gray_image: &GrayImage, roi: (u32, u32, u32, u32)) -> Vec<QRCodeResult> {
+    let (x, y, w, h) = roi;
+    let cropped = imageops::crop_imm(gray_image, x, y, w, h).to_image();
+
+    let mut results = decode_from_gray_image(cropped);
+    map_bounds(&mut results, |px, py| (px + x as f64, py + y as f64));
+
+    results
+}
+
+/// Apply `DecodeOptions` around the plain grayscale decode path
+pub fn decode_gray_with_options(
+    gray_image: GrayImage,
+    options: &DecodeOptions,
+) -> Result<Vec<QRCodeResult>, VeloQrError> {
+    decode_gray_timed(gray_image, options, options.limits_timer().as_mut())
+}
+
+/// Convert and decode an RGBA frame like `decode_gray_with_options`,
+/// timing each stage
+pub fn decode_rgba_timed(
+    rgba: &[u8],
+    width: u32,
+    height: u32,
+    stride_bytes: Option<u32>,
+    options: &DecodeOptions,
+) -> Result<TimedDecode, VeloQrError> {
+    decode_rgba_with_clock(rgba, width, height, stride_bytes, options, now_ms)
+}
+
+/// `decode_rgba_timed` reading the time from `clock`, which also decides
+/// when `time_budget_ms` runs out
+pub fn decode_rgba_with_clock(
+    rgba: &[u8],
+    width: u32,
+    height: u32,
+    stride_bytes: Option<u32>,
+    options: &DecodeOptions,
+    clock: impl Clock,
+) -> Result<TimedDecode, VeloQrError> {
+    let mut timer = options.limits_timer_with(Box::new(clock));
+    let gray_image = timed(Some(&mut timer), Stage::Grayscale, || {
+        rgba_to_gray_with_alpha(rgba, width, height, stride_bytes, &options.alpha)
+    })?;
+    let results = decode_gray_timed(gray_image, options, Some(&mut timer))?;
+
+    Ok(TimedDecode {
+        results,
+        timed_out: timer.timed_out(),
+        skipped_candidates: timer.skipped_grids,
+        timings: timer.finish(),
+    })
+}
+
+/// `decode_gray_with_options`, adding stage durations to `timer` when given
+fn decode_gray_timed(
+    gray_image: GrayImage,
+    options: &DecodeOptions,
+    timer: Option<&mut StageTimer>,
+) -> Result<Vec<QRCodeResult>, VeloQrError> {
+    if options.return_crop && !(1..=MAX_ENCODED_DIMENSION).contains(&options.crop_size) {
+        return Err(VeloQrError::InvalidOptions(format!(
+            "crop_size must be between 1 and {}",
+            MAX_ENCODED_DIMENSION
+        )));
+    }
+    // Detection binarizes its buffers, so crops and module grids are taken
+    // from a pristine copy
+    let original =
+        (options.return_crop || options.return_module_grid).then(|| gray_image.clone());
+    let (width, height) = gray_image.dimensions();
+    let frame_center = (width as f64 / 2.0, height as f64 / 2.0);
+    let region = match options.roi {
+        Some(roi) => clamp_roi(width, height, roi.x, roi.y, roi.width, roi.height)?,
+        None => (0, 0, width, height),
+    };
+    let min_area = options.min_size_px.map_or(0.0, |side| side as f64 * side as f64);
+
+    // A single code nearest the center is known as soon as the nearest
+    // readable grid decodes, so the others are left alone
+    let (x, y, w, h) = region;
+    let nearest = (options.max_results == Some(1)
+        && options.sort == ResultOrder::CenterDistance
+        && options.tiling.is_none())
+    .then(|| NearestGrid {
+        target: ((frame_center.0 - x as f64) / w as f64, (frame_center.1 - y as f64) / h as f64),
+        min_area: min_area / (w as f64 * h as f64),
+    });
+
+    let mut results = if options.roi.is_some() {
+        let cropped = imageops::crop_imm(&gray_image, x, y, w, h).to_image();
+        let mut results = decode_gray_region(cropped, options, nearest, timer)?;
+        map_bounds(&mut results, |px, py| (px + x as f64, py + y as f64));
+        results
+    } else {
+        decode_gray_region(gray_image, options, nearest, timer)?
+    };
+
+    results.retain(|result| result.area >= min_area);
+    let distance = |result: &QRCodeResult| {
+        (result.center.0 - frame_center.0).hypot(result.center.1 - frame_center.1)
+    };
+    match options.sort {
+        ResultOrder::None => {}
+        ResultOrder::AreaDesc => results.sort_by(|a, b| b.area.total_cmp(&a.area)),
+        ResultOrder::CenterDistance => {
+            results.sort_by(|a, b| distance(a).total_cmp(&distance(b)))
+        }
+    }
+    if let Some(max_results) = options.max_results {
+        results.truncate(max_results as usize);
+    }
+
+    if !options.return_raw_bytes {
+        for result in &mut results {
+            result.raw_bytes = Vec::new();
+        }
+    }
+
+    if let Some(original) = original.as_ref().filter(|_| options.return_module_grid) {
+        // Thresholded as the decoder's own pass was, on the full frame
+        let prepared = PreparedImage::prepare(original.clone());
+        for result in &mut results {
+            if result.symbology == Symbology::Qr {
+                result.modules = sample_modules(&prepared, result);
+            }
+        }
+    }
+
+    if let Some(original) = original.filter(|_| options.return_crop) {
+        for result in &mut results {
+            let Some(corners) = code_corners(&result.bounds, result.version) else {
+                continue;
+            };
+            let crop = warp_quad(&original, corners, options.crop_size);
+            let mut png = Vec::new();
+            crop.write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+                .map_err(|e| VeloQrError::SerializationFailed(e.to_string()))?;
+            result.crop_png = png;
+        }
+    }
+
+    Ok(results)
+}
+
+/// Decode tiled when `options.tiling` is set, otherwise whole (and maybe downscaled)
+fn decode_gray_region(
+    gray_image: GrayImage,
+    options: &DecodeOptions,
+    nearest: Option<NearestGrid>,
+    timer: Option<&mut StageTimer>,
+) -> Result<Vec<QRCodeResult>, VeloQrError> {
+    match options.tiling {
+        Some(tiling) => decode_gray_tiled(&gray_image, tiling, options, timer),
+        None => Ok(decode_gray_scaled(gray_image, options, nearest, timer)),
+    }
+}
+
+/// Tiles whose sampled luminance variance is below this hold no code and are skipped
+const TILE_MIN_VARIANCE: f64 = 64.0;
+
+/// Decode every overlapping tile separately, dropping the repeat detections
+/// of codes that fall into more than one tile
+fn decode_gray_tiled(
+    gray_image: &GrayImage,
+    tiling: Tiling,
+    options: &DecodeOptions,
+    mut timer: Option<&mut StageTimer>,
+) -> Result<Vec<QRCodeResult>, VeloQrError> {
+    if tiling.tile_size == 0 || tiling.overlap >= tiling.tile_size {
+        return Err(VeloQrError::InvalidOptions(format!(
+            "tiling overlap {} must be smaller than tile_size {}",
+            tiling.overlap, tiling.tile_size
+        )));
+    }
+
+    let (width, height) = gray_image.dimensions();
+    let step = tiling.tile_size - tiling.overlap;
+    let origins = |length: u32| {
+        let last = length.saturating_sub(tiling.tile_size);
+        let mut origins: Vec<u32> = (0..=last).step_by(step as usize).collect();
+        if origins.last() != Some(&last) {
+            origins.push(last);
+        }
+        origins
+    };
+
+    let mut results: Vec<QRCodeResult> = Vec::new();
+    'tiles: for y in origins(height) {
+        for x in origins(width) {
+            if expired(&mut timer) {
+                log_info!("Time budget exhausted, skipping the remaining tiles");
+                break 'tiles;
+            }
+            let (w, h) = (tiling.tile_size.min(width - x), tiling.tile_size.min(height - y));
+            let tile = imageops::crop_imm(gray_image, x, y, w, h).to_image();
+            if sampled_variance(&tile) < TILE_MIN_VARIANCE {
+                continue;
+            }
+
+            let mut found = decode_gray_with_retries(tile, options, None, timer.as_deref_mut());
+            map_bounds(&mut found, |px, py| (px + x as f64, py + y as f64));
+            for result in found {
+                if !results.iter().any(|seen| bounds_overlap(&seen.bounds, &result.bounds)) {
+                    results.push(result);
+                }
+            }
+        }
+    }
+
+    log_debug!("Tiled detection found {} codes", results.len());
+    Ok(results)
+}
+
+/// Luminance variance over every 4th pixel of every 4th row
+fn sampled_variance(gray: &GrayImage) -> f64 {
+    let (mut count, mut sum, mut squares) = (0u64, 0u64, 0u64);
+    for y in (0..gray.height()).step_by(4) {
+        for x in (0..gray.width()).step_by(4) {
+            let v = gray.get_pixel(x, y).0[0] as u64;
+            count += 1;
+            sum += v;
+            squares += v * v;
+        }
+    }
+    if count == 0 {
+        return 0.0;
+    }
+    let mean = sum as f64 / count as f64;
+    squares as f64 / count as f64 - mean * mean
+}
+
+/// Laplacian variance and luma statistics over every 4th pixel of every 4th
+/// row; the Laplacian uses the full-resolution neighbours so fine blur shows
+pub fn assess_gray(gray: &GrayImage) -> FrameQuality {
+    let width = gray.width() as usize;
+    let rows: Vec<&[u8]> = if width == 0 { Vec::new() } else { gray.chunks(width).collect() };
+
+    let (mut count, mut sum, mut squares) = (0u64, 0u64, 0u64);
+    let (mut edges, mut edge_sum, mut edge_squares) = (0u64, 0i64, 0i64);
+    for (y, row) in rows.iter().enumerate().step_by(4) {
+        let neighbours = (y > 0 && y + 1 < rows.len()).then(|| (rows[y - 1], rows[y + 1]));
+        for x in (0..width).step_by(4) {
+            let v = row[x] as i64;
+            count += 1;
+            sum += v as u64;
+            squares += (v * v) as u64;
+            if let (Some((above, below)), true) = (neighbours, x > 0 && x + 1 < width) {
+                let around = [row[x - 1], row[x + 1], above[x], below[x]];
+                let laplacian = around.iter().map(|&n| n as i64).sum::<i64>() - 4 * v;
+                edges += 1;
+                edge_sum += laplacian;
+                edge_squares += laplacian * laplacian;
+            }
+        }
+    }
+
+    let variance = |n: u64, sum: f64, squares: f64| {
+        if n == 0 {
+            return (0.0, 0.0);
+        }
+        let mean = sum / n as f64;
+        (mean, (squares / n as f64 - mean * mean).max(0.0))
+    };
+    let (mean_luma, luma_variance) = variance(count, sum as f64, squares as f64);
+    let (_, sharpness) = variance(edges, edge_sum as f64, edge_squares as f64);
+    FrameQuality { sharpness, mean_luma, contrast: luma_variance.sqrt() }
+}
+
+/// Whether the bounding boxes of two quadrilaterals share more than half of
+/// the smaller box, i.e. they are the same physical code
+fn bounds_overlap(a: &[(f64, f64)], b: &[(f64, f64)]) -> bool {
+    let bbox = |points: &[(f64, f64)]| {
+        points.iter().fold(
+            (f64::MAX, f64::MAX, f64::MIN, f64::MIN),
+            |(x0, y0, x1, y1), &(x, y)| (x0.min(x), y0.min(y), x1.max(x), y1.max(y)),
+        )
+    };
+    let (a, b) = (bbox(a), bbox(b));
+    let overlap_width = a.2.min(b.2) - a.0.max(b.0);
+    let overlap_height = a.3.min(b.3) - a.1.max(b.1);
+    if overlap_width <= 0.0 || overlap_height <= 0.0 {
+        return false;
+    }
+    let area = |(x0, y0, x1, y1): (f64, f64, f64, f64)| (x1 - x0) * (y1 - y0);
+    overlap_width * overlap_height > 0.5 * area(a).min(area(b))
+}
+
+/// Decode with the optional `max_dimension` downscale and full-resolution retry
+fn decode_gray_scaled(
+    gray_image: GrayImage,
+    options: &DecodeOptions,
+    nearest: Option<NearestGrid>,
+    mut timer: Option<&mut StageTimer>,
+) -> Vec<QRCodeResult> {
+    let factor = match options.max_dimension {
+        Some(max) if max > 0 => gray_image.width().max(gray_image.height()).div_ceil(max),
+        _ => 1,
+    };
+
+    if factor <= 1 {
+        return decode_gray_with_retries(gray_image, options, nearest, timer);
+    }
+
+    log_debug!("Downscaling by {}x before detection", factor);
+    let downscaled = downscale_gray(&gray_image, factor);
+    let mut results = decode_gray_with_retries(downscaled, options, nearest, timer.as_deref_mut());
+    let scale = factor as f64;
+    map_bounds(&mut results, |px, py| (px * scale, py * scale));
+
+    if results.is_empty() && options.retry_full_resolution && !expired(&mut timer) {
+        log_debug!("Nothing found after downscaling, retrying at full resolution");
+        return decode_gray_with_retries(gray_image, options, nearest, timer);
+    }
+
+    results
+}
+
+/// Plain decode, falling back to the retry passes enabled in `options`
+fn decode_gray_with_retries(
+    mut gray_image: GrayImage,
+    options: &DecodeOptions,
+    nearest: Option<NearestGrid>,
+    mut timer: Option<&mut StageTimer>,
+) -> Vec<QRCodeResult> {
+    // (inverted, mirrored) state of the buffer for each pass, in order
+    let mut passes = vec![(false, false)];
+    if options.try_inverted {
+        passes.push((true, false));
+    }
+    if options.try_mirrored {
+        passes.push((false, true));
+        if options.try_inverted {
+            passes.push((true, true));
+        }
+    }
+
+    timed(timer.as_deref_mut(), Stage::Prepare, || binarize(&mut gray_image, options));
+    let retries = options.try_rotations || options.add_quiet_zone > 0 || options.try_upscale;
+    // Grids of the plain pass, whose module size tells whether to upscale
+    let mut failed = Vec::new();
+
+    // Preparation binarizes its buffer in place, so each pass decodes a copy
+    // refilled into one scratch buffer, while the original is inverted and
+    // flipped in place between passes. Without later retries the last pass
+    // takes the original instead of copying it.
+    let (width, height) = gray_image.dimensions();
+    let mut scratch = Vec::new();
+    let mut state = (false, false);
+    let last = passes.len() - 1;
+    for (i, &(inverted, mirrored)) in passes.iter().enumerate() {
+        if i > 0 && expired(&mut timer) {
+            return Vec::new();
+        }
+        if inverted != state.0 {
+            invert_gray(&mut gray_image);
+        }
+        if mirrored != state.1 {
+            imageops::flip_horizontal_in_place(&mut gray_image);
+        }
+        state = (inverted, mirrored);
+
+        if i == last && !retries {
+            scratch = std::mem::take(&mut gray_image).into_raw();
+        } else {
+            scratch.clear();
+            scratch.extend_from_slice(gray_image.as_raw());
+        }
+        let timer = timer.as_deref_mut();
+        let mut results = match nearest {
+            Some(NearestGrid { target: (x, y), min_area }) => {
+                // The flipped buffer shows the target mirrored as well
+                let x = if mirrored { 1.0 - x } else { x };
+                let nearest = NearestGrid { target: (x, y), min_area };
+                decode_nearest_luma(&mut scratch, width, height, nearest, timer)
+            }
+            None => {
+                let failed = (i == 0 && options.try_upscale).then_some(&mut failed);
+                let mut results = Vec::new();
+                decode_luma_timed(&mut scratch, width, height, &mut results, failed, timer);
+                results
+            }
+        };
+        if results.is_empty() {
+            continue;
+        }
+
+        log_debug!("Found codes on pass inverted={} mirrored={}", inverted, mirrored);
+        if mirrored {
+            map_bounds(&mut results, |px, py| (width as f64 - 1.0 - px, py));
+        }
+        for result in &mut results {
+            result.inverted = inverted;
+            result.mirrored = mirrored;
+        }
+        return results;
+    }
+
+    if !retries {
+        return Vec::new();
+    }
+    // Back to the frame as given, for the retries below
+    if state.0 {
+        invert_gray(&mut gray_image);
+    }
+    if state.1 {
+        imageops::flip_horizontal_in_place(&mut gray_image);
+    }
+    let upright = gray_image;
+    if options.add_quiet_zone > 0 && !expired(&mut timer) {
+        let border = options.add_quiet_zone;
+        let results = decode_gray_padded(&upright, border, nearest, timer.as_deref_mut());
+        if !results.is_empty() {
+            log_debug!("Found codes on pass quiet_zone={}", border);
+            return results;
+        }
+    }
+    if options.try_upscale && !expired(&mut timer) {
+        let (width, height) = upright.dimensions();
+        let small = width.max(height) < options.upscale_below
+            || failed.iter().any(|grid| grid.module_size < UPSCALE_MODULE_SIZE);
+        if small {
+            let results = decode_gray_upscaled(&upright, nearest, timer.as_deref_mut());
+            if !results.is_empty() {
+                return results;
+            }
+        }
+    }
+    if options.try_rotations && !expired(&mut timer) {
+        return decode_gray_rotated(&upright, timer);
+    }
+    Vec::new()
+}
+
+/// Located grids with modules narrower than this many pixels make
+/// `try_upscale` retry however large the image
+const UPSCALE_MODULE_SIZE: f64 = 2.5;
+
+/// Retry on the image upscaled 2x and then 3x with nearest-neighbour
+/// sampling, mapping the bounds of the first that finds anything back
+fn decode_gray_upscaled(
+    gray_image: &GrayImage,
+    nearest: Option<NearestGrid>,
+    mut timer: Option<&mut StageTimer>,
+) -> Vec<QRCodeResult> {
+    let (width, height) = gray_image.dimensions();
+    for factor in [2, 3] {
+        if width.max(height) * factor > MAX_ENCODED_DIMENSION || factor > 2 && expired(&mut timer) {
+            break;
+        }
+        let upscaled = imageops::resize(
+            gray_image,
+            width * factor,
+            height * factor,
+            imageops::FilterType::Nearest,
+        );
+        // Fractions of the frame are the same at any scale
+        let mut results = match nearest {
+            Some(nearest) => decode_nearest_grid(upscaled, nearest, timer.as_deref_mut()),
+            None => decode_gray_timed_plain(upscaled, timer.as_deref_mut()),
+        };
+        if results.is_empty() {
+            continue;
+        }
+
+        log_debug!("Found codes on pass upscale={}", factor);
+        let scale = factor as f64;
+        map_bounds(&mut results, |px, py| (px / scale, py / scale));
+        return results;
+    }
+    Vec::new()
+}
+
+/// Retry with a `border` pixels wide frame of the background around the
+/// image, mapping the bounds back to the original image
+fn decode_gray_padded(
+    gray_image: &GrayImage,
+    border: u32,
+    nearest: Option<NearestGrid>,
+    timer: Option<&mut StageTimer>,
+) -> Vec<QRCodeResult> {
+    let (width, height) = gray_image.dimensions();
+    if width == 0 || height == 0 {
+        return Vec::new();
+    }
+    let padded_side = width.max(height) as u64 + 2 * border as u64;
+    if padded_side > MAX_ENCODED_DIMENSION as u64 {
+        log_debug!("Skipping a {}px quiet zone, the image would get too large", border);
+        return Vec::new();
+    }
+    let padded = pad_gray(gray_image, border);
+
+    let border = border as f64;
+    let mut results = match nearest {
+        Some(NearestGrid { target: (x, y), min_area }) => {
+            // Fractions of the original frame, rescaled to the padded one
+            let (w, h) = (width as f64, height as f64);
+            let (padded_w, padded_h) = (w + 2.0 * border, h + 2.0 * border);
+            let target = ((x * w + border) / padded_w, (y * h + border) / padded_h);
+            let min_area = min_area * w * h / (padded_w * padded_h);
+            decode_nearest_grid(padded, NearestGrid { target, min_area }, timer)
+        }
+        None => decode_gray_timed_plain(padded, timer),
+    };
+    map_bounds(&mut results, |px, py| (px - border, py - border));
+    results
+}
+
+/// Copy of `gray_image` inside a `border` pixels wide frame of its background:
+/// the median of the lighter half of the edge pixels, since the edges of a
+/// code cropped to its modules are mostly dark finder patterns
+fn pad_gray(gray_image: &GrayImage, border: u32) -> GrayImage {
+    let (width, height) = gray_image.dimensions();
+    let mut edge: Vec<u8> = (0..width)
+        .flat_map(|x| [(x, 0), (x, height - 1)])
+        .chain((0..height).flat_map(|y| [(0, y), (width - 1, y)]))
+        .map(|(x, y)| gray_image.get_pixel(x, y)[0])
+        .collect();
+    edge.sort_unstable();
+    let lighter = &edge[edge.len() / 2..];
+    let background = lighter.get(lighter.len() / 2).copied().unwrap_or(255);
+
+    let mut padded = GrayImage::from_pixel(
+        width + 2 * border,
+        height + 2 * border,
+        image::Luma([background]),
+    );
+    imageops::replace(&mut padded, gray_image, border as i64, border as i64);
+    padded
+}
+
+/// Retry on the image turned 90, 180 and 270 degrees clockwise, mapping the
+/// bounds of the first pass that finds anything back to the original image
+fn decode_gray_rotated(
+    gray_image: &GrayImage,
+    mut timer: Option<&mut StageTimer>,
+) -> Vec<QRCodeResult> {
+    let (width, height) = gray_image.dimensions();
+    let (w, h) = (width as f64 - 1.0, height as f64 - 1.0);
+
+    for degrees in [90, 180, 270] {
+        if degrees > 90 && expired(&mut timer) {
+            break;
+        }
+        let rotated = match degrees {
+            90 => imageops::rotate90(gray_image),
+            180 => imageops::rotate180(gray_image),
+            _ => imageops::rotate270(gray_image),
+        };
+        let mut results = decode_gray_timed_plain(rotated, timer.as_deref_mut());
+        if results.is_empty() {
+            continue;
+        }
+
+        log_debug!("Found codes on pass rotated={}", degrees);
+        map_bounds(&mut results, |px, py| match degrees {
+            90 => (py, h - px),
+            180 => (w - px, h - py),
+            _ => (w - py, px),
+        });
+        return results;
+    }
+
+    Vec::new()
+}
+
+/// Clockwise rotation of a code in degrees, [0, 360), from the direction of
+/// its top edge: rqrr lists corners starting at the top-left finder pattern
+/// and going clockwise in the code's own frame
+fn orientation_degrees(bounds: &[(f64, f64)]) -> f64 {
+    match bounds {
+        [(x0, y0), (x1, y1), ..] => (y1 - y0).atan2(x1 - x0).to_degrees().rem_euclid(360.0),
+        _ => 0.0,
+    }
+}
+
+/// Run the `decode_qr_robust` cascade, reading the time from `clock`; the
+/// budget is checked between passes and between the grids of a pass
+pub fn decode_gray_robust(
+    gray_image: &GrayImage,
+    budget_ms: f64,
+    options: &RobustOptions,
+    clock: impl Clock,
+) -> Vec<QRCodeResult> {
+    let large = gray_image.width().max(gray_image.height()) > ROBUST_LARGE_SIDE;
+    let strategies = options.strategies.clone().unwrap_or_else(|| {
+        let resize = if large { Strategy::Downscale } else { Strategy::Upscale };
+        vec![Strategy::Plain, Strategy::Inverted, Strategy::Adaptive, Strategy::QuietZone, resize]
+    });
+
+    let budget = (budget_ms > 0.0).then_some(budget_ms);
+    let mut timer = StageTimer::with_budget(Box::new(clock), budget);
+    for (i, &strategy) in strategies.iter().enumerate() {
+        if i > 0 && timer.expired() {
+            log_info!("Robust decode budget exhausted before {}", strategy.name());
+            break;
+        }
+
+        let mut results = decode_strategy(gray_image, strategy, &mut timer);
+        if results.is_empty() {
+            continue;
+        }
+
+        log_debug!("Found codes with strategy {}", strategy.name());
+        for result in &mut results {
+            result.strategy = strategy.name().to_string();
+            result.inverted = strategy == Strategy::Inverted;
+        }
+        return results;
+    }
+
+    Vec::new()
+}
+
+/// Decode one cascade pass on a copy of the image, bounds in original coordinates
+fn decode_strategy(
+    gray_image: &GrayImage,
+    strategy: Strategy,
+    timer: &mut StageTimer,
+) -> Vec<QRCodeResult> {
+    let timer = Some(timer);
+    match strategy {
+        Strategy::Plain => decode_gray_timed_plain(gray_image.clone(), timer),
+        Strategy::Inverted => {
+            let mut inverted = gray_image.clone();
+            invert_gray(&mut inverted);
+            decode_gray_timed_plain(inverted, timer)
+        }
+        Strategy::Adaptive => {
+            let mut binarized = gray_image.clone();
+            let options = DecodeOptions {
+                binarization: Binarization::Adaptive,
+                ..DecodeOptions::default()
+            };
+            binarize(&mut binarized, &options);
+            decode_gray_timed_plain(binarized, timer)
+        }
+        Strategy::Downscale => {
+            let mut results = decode_gray_timed_plain(downscale_gray(gray_image, 2), timer);
+            map_bounds(&mut results, |px, py| (px * 2.0, py * 2.0));
+            results
+        }
+        Strategy::Upscale => {
+            let (width, height) = gray_image.dimensions();
+            if width.max(height) * 2 > MAX_ENCODED_DIMENSION {
+                return Vec::new();
+            }
+            let upscaled =
+                imageops::resize(gray_image, width * 2, height * 2, imageops::FilterType::Nearest);
+            let mut results = decode_gray_timed_plain(upscaled, timer);
+            map_bounds(&mut results, |px, py| (px / 2.0, py / 2.0));
+            results
+        }
+        Strategy::QuietZone => {
+            let border = (gray_image.width().min(gray_image.height()) / 5).max(1);
+            decode_gray_padded(gray_image, border, None, timer)
+        }
+    }
+}
+
+/// Rewrite every bounds point of every result, e.g. to undo a crop or resize
+fn map_bounds<F>(results: &mut [QRCodeResult], f: F)
+where
+    F: Fn(f64, f64) -> (f64, f64),
+{
+    for result in results {
+        for point in &mut result.bounds {
+            *point = f(point.0, point.1);
+        }
+        result.update_geometry();
+    }
+}
+
+/// Run QR detection and decoding on a grayscale image
+pub(crate) fn decode_from_gray_image(gray_image: GrayImage) -> Vec<QRCodeResult> {
+    decode_gray_timed_plain(gray_image, None)
+}
+
+/// `decode_from_gray_image`, adding stage durations to `timer` when given
+fn decode_gray_timed_plain(
+    gray_image: GrayImage,
+    timer: Option<&mut StageTimer>,
+) -> Vec<QRCodeResult> {
+    decode_gray_reporting(gray_image, None, timer)
+}
+
+/// `decode_gray_timed_plain`, also appending unreadable grids to `failed`
+fn decode_gray_reporting(
+    gray_image: GrayImage,
+    failed: Option<&mut Vec<FailedGrid>>,
+    timer: Option<&mut StageTimer>,
+) -> Vec<QRCodeResult> {
+    let (width, height) = gray_image.dimensions();
+    let mut luma = gray_image.into_raw();
+
+    let mut results: Vec<QRCodeResult> = Vec::new();
+    decode_luma_timed(&mut luma, width, height, &mut results, failed, timer);
+
+    results
+}
+
+/// Run QR detection on a luminance buffer, appending decoded codes to `results`
+/// and, when given, unreadable grids to `failed`.
+/// The buffer is binarized in place by the preparation step.
+pub fn decode_luma_into(
+    luma: &mut [u8],
+    width: u32,
+    height: u32,
+    results: &mut Vec<QRCodeResult>,
+    failed: Option<&mut Vec<FailedGrid>>,
+) {
+    decode_luma_timed(luma, width, height, results, failed, None);
+}
+
+/// `decode_luma_into`, adding stage durations to `timer` when given
+fn decode_luma_timed(
+    luma: &mut [u8],
+    width: u32,
+    height: u32,
+    results: &mut Vec<QRCodeResult>,
+    mut failed: Option<&mut Vec<FailedGrid>>,
+    mut timer: Option<&mut StageTimer>,
+) {
+    // Prepare image for QR detection, borrowing the caller's buffer
+    let view = ImageBuffer::<image::Luma<u8>, &mut [u8]>::from_raw(width, height, luma)
+        .expect("buffer matches dimensions");
+    let mut prepared = timed(timer.as_deref_mut(), Stage::Prepare, || PreparedImage::prepare(view));
+
+    // Find QR codes
+    let grids = timed(timer.as_deref_mut(), Stage::Detect, || prepared.detect_grids());
+    log_debug!("Detected {} QR codes", grids.len());
+    let grids = cap_grids(grids, &mut timer);
+
+    // A time budget is checked between grids, so only grids without one
+    // decode concurrently
+    #[cfg(feature = "threads")]
+    if grids.len() > 1 && !timer.as_deref().is_some_and(StageTimer::has_budget) {
+        timed(timer.as_deref_mut(), Stage::Decode, || {
+            decode_grids_parallel(&grids, results, failed)
+        });
+        return;
+    }
+
+    // Checked between grids only, so a budget spent on detection still
+    // decodes the first candidate
+    for (i, grid) in grids.into_iter().enumerate() {
+        if i > 0 && expired(&mut timer) {
+            log_info!("Time budget exhausted after {} grids", i);
+            break;
+        }
+        timed(timer.as_deref_mut(), Stage::Decode, || {
+            decode_grid(&grid, results, failed.as_deref_mut())
+        });
+    }
+}
+
+/// `decode_image` split into steps (prepare, detect and sample, then one
+/// grid per step) so a caller can yield between them; running every step gives the
+/// same results as `decode_image`
+pub struct StagedDecode {
+    gray: Option<GrayImage>,
+    grids: std::vec::IntoIter<rqrr::Grid<rqrr::SimpleGrid>>,
+    results: Vec<QRCodeResult>,
+}
+
+impl StagedDecode {
+    pub fn new(gray: GrayImage) -> Self {
+        StagedDecode {
+            gray: Some(gray),
+            grids: Vec::new().into_iter(),
+            results: Vec::new(),
+        }
+    }
+
+    /// Run the next step; false once every grid has been decoded
+    pub fn step(&mut self) -> bool {
+        if let Some(gray) = self.gray.take() {
+            let mut prepared = PreparedImage::prepare(gray);
+            let grids = prepared.detect_grids();
+            log_debug!("Detected {} QR codes", grids.len());
+            // Grids borrow the prepared image; their modules are sampled
+            // now so the image need not outlive this step
+            let sampled: Vec<_> = grids
+                .iter()
+                .map(|grid| rqrr::Grid {
+                    grid: rqrr::SimpleGrid::from_func(grid.grid.size(), |x, y| {
+                        grid.grid.bit(y, x)
+                    }),
+                    bounds: grid.bounds,
+                })
+                .collect();
+            self.grids = sampled.into_iter();
+            return true;
+        }
+        match self.grids.next() {
+            Some(grid) => {
+                decode_grid(&grid, &mut self.results, None);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn into_results(self) -> Vec<QRCodeResult> {
+        self.results
+    }
+}
+
+/// Which grid `decode_nearest_grid` decodes; both fields are relative to the
+/// buffer so they survive cropping and downscaling
+#[derive(Clone, Copy)]
+struct NearestGrid {
+    /// Point to be nearest to, as fractions of the buffer width and height
+    target: (f64, f64),
+    /// Smallest code area, as a fraction of the buffer area
+    min_area: f64,
+}
+
+/// Decode grids nearest `nearest.target` first and stop at the first that
+/// reads, skipping grids too small to be kept
+fn decode_nearest_grid(
+    gray_image: GrayImage,
+    nearest: NearestGrid,
+    timer: Option<&mut StageTimer>,
+) -> Vec<QRCodeResult> {
+    let (width, height) = gray_image.dimensions();
+    decode_nearest_luma(&mut gray_image.into_raw(), width, height, nearest, timer)
+}
+
+/// `decode_nearest_grid` on a luminance buffer, binarized in place
+fn decode_nearest_luma(
+    luma: &mut [u8],
+    width: u32,
+    height: u32,
+    nearest: NearestGrid,
+    mut timer: Option<&mut StageTimer>,
+) -> Vec<QRCodeResult> {
+    let view = ImageBuffer::<image::Luma<u8>, &mut [u8]>::from_raw(width, height, luma)
+        .expect("buffer matches dimensions");
+    let (width, height) = (width as f64, height as f64);
+    let target = (nearest.target.0 * width, nearest.target.1 * height);
+    let min_area = nearest.min_area * width * height;
+
+    let mut prepared =
+        timed(timer.as_deref_mut(), Stage::Prepare, || PreparedImage::prepare(view));
+    let detected = timed(timer.as_deref_mut(), Stage::Detect, || prepared.detect_grids());
+    let detected = cap_grids(detected, &mut timer);
+    let mut grids: Vec<(f64, _)> = detected
+        .into_iter()
+        .filter_map(|grid| {
+            // The same corners `update_geometry` measures the result with
+            let bounds: Vec<(f64, f64)> =
+                grid.bounds.iter().map(|p| (p.x as f64, p.y as f64)).collect();
+            let version = (grid.grid.size() as i32 - 17) / 4;
+            let corners = code_corners(&bounds, version)?;
+            let center = quad_center(corners);
+            let distance = (center.0 - target.0).hypot(center.1 - target.1);
+            (quad_area(corners) >= min_area).then_some((distance, grid))
+        })
+        .collect();
+    log_debug!("Detected {} QR codes large enough", grids.len());
+    grids.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut results = Vec::new();
+    for (i, (_, grid)) in grids.into_iter().enumerate() {
+        if i > 0 && expired(&mut timer) {
+            log_info!("Time budget exhausted after {} grids", i);
+            break;
+        }
+        let decoded =
+            timed(timer.as_deref_mut(), Stage::Decode, || decode_grid(&grid, &mut results, None));
+        if decoded {
+            break;
+        }
+    }
+    results
+}
+
+/// Keep the `max_grids` of `timer` most plausible `grids`, in detection
+/// order, counting the others as skipped
+fn cap_grids<G: BitGrid>(
+    grids: Vec<rqrr::Grid<G>>,
+    timer: &mut Option<&mut StageTimer>,
+) -> Vec<rqrr::Grid<G>> {
+    let Some(timer) = timer.as_deref_mut() else {
+        return grids;
+    };
+    if timer.max_grids == 0 || grids.len() <= timer.max_grids {
+        return grids;
+    }
+
+    let mut ranked: Vec<(usize, f64)> =
+        grids.iter().map(grid_plausibility).enumerate().collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+    let mut keep = vec![false; grids.len()];
+    for &(index, _) in &ranked[..timer.max_grids] {
+        keep[index] = true;
+    }
+    let skipped = grids.len() - timer.max_grids;
+    log_info!("Skipping the {} least plausible of {} grids", skipped, grids.len());
+    timer.skipped_grids += skipped as u32;
+    grids.into_iter().zip(keep).filter_map(|(grid, keep)| keep.then_some(grid)).collect()
+}
+
+/// Modules narrower than this many pixels cannot be sampled, so their grids
+/// are false candidates
+const MIN_MODULE_PIXELS: f64 = 1.0;
+
+/// How likely a detected grid is a real code, from its corners alone: the
+/// square root of its area times its squareness (shortest over longest
+/// side, and the same for the diagonals), squared so skew weighs more than
+/// size. Grids with sub-pixel modules score 0.
+fn grid_plausibility<G: BitGrid>(grid: &rqrr::Grid<G>) -> f64 {
+    let corners = grid.bounds.map(|p| (p.x as f64, p.y as f64));
+    let distance = |a: (f64, f64), b: (f64, f64)| (b.0 - a.0).hypot(b.1 - a.1);
+    let sides: Vec<f64> = (0..4).map(|i| distance(corners[i], corners[(i + 1) % 4])).collect();
+    let diagonals = [distance(corners[0], corners[2]), distance(corners[1], corners[3])];
+    let ratio = |lengths: &[f64]| {
+        let longest = lengths.iter().copied().fold(0.0, f64::max);
+        let shortest = lengths.iter().copied().fold(f64::MAX, f64::min);
+        if longest > 0.0 { shortest / longest } else { 0.0 }
+    };
+
+    let shortest_side = sides.iter().copied().fold(f64::MAX, f64::min);
+    if shortest_side / (grid.grid.size() as f64) < MIN_MODULE_PIXELS {
+        return 0.0;
+    }
+    let squareness = ratio(&sides) * ratio(&diagonals);
+    squareness * squareness * quad_area(corners).sqrt()
+}
+
+/// Decode `grids` on the rayon pool, appending results and failures in
+/// grid order like the serial loop
+#[cfg(feature = "threads")]
+fn decode_grids_parallel<G: BitGrid + Sync>(
+    grids: &[rqrr::Grid<G>],
+    results: &mut Vec<QRCodeResult>,
+    failed: Option<&mut Vec<FailedGrid>>,
+) {
+    use rayon::prelude::*;
+
+    let keep_failed = failed.is_some();
+    let decoded: Vec<(Vec<QRCodeResult>, Vec<FailedGrid>)> = grids
+        .par_iter()
+        .map(|grid| {
+            let (mut decoded, mut unreadable) = (Vec::new(), Vec::new());
+            decode_grid(grid, &mut decoded, keep_failed.then_some(&mut unreadable));
+            (decoded, unreadable)
+        })
+        .collect();
+
+    let (decoded, unreadable): (Vec<_>, Vec<_>) = decoded.into_iter().unzip();
+    results.extend(decoded.into_iter().flatten());
+    if let Some(failed) = failed {
+        failed.extend(unreadable.into_iter().flatten());
+    }
+}
+
+/// Decode one grid into `results`, or record it in `failed`; returns whether
+/// it decoded
+fn decode_grid<G: BitGrid>(
+    grid: &rqrr::Grid<G>,
+    results: &mut Vec<QRCodeResult>,
+    failed: Option<&mut Vec<FailedGrid>>,
+) -> bool {
+    let bounds: Vec<(f64, f64)> = grid
+        .bounds
+        .iter()
+        .map(|p| (p.x as f64, p.y as f64))
+        .collect();
+
+    // One pass of our own decoder gives the payload with its ECI, Kanji,
+    // FNC1 and structured-append headers and the corrections, which rqrr
+    // does not report; rqrr only gets a second look at grids it rejects.
+    // The charset headers only matter when the bytes are not plain ASCII.
+    let stream_error = match qr_stream::decode(&grid.grid) {
+        Ok(symbol) => {
+            let ascii = symbol.payload.is_ascii();
+            let (eci, kanji) =
+                if ascii { (None, false) } else { (symbol.eci, symbol.kanji) };
+            let text = payload_text(&symbol.payload, eci, kanji);
+            let mut result = QRCodeResult::decoded(
+                bounds,
+                symbol.version as i32,
+                symbol.ecc_level,
+                symbol.mask as i32,
+                symbol.payload,
+                text,
+            );
+            result.gs1 = symbol.gs1;
+            result.structured_append = symbol.structured_append;
+            result.corrected_codewords = symbol.corrected_errors as u32;
+            result.quality = symbol.quality;
+            results.push(result);
+            return true;
+        }
+        Err(e) => e,
+    };
+
+    let mut raw_bytes = Vec::new();
+    match grid.decode_to(&mut raw_bytes) {
+        Ok(meta) => {
+            let text = payload_text(&raw_bytes, None, false);
+            results.push(QRCodeResult::decoded(
+                bounds,
+                meta.version.0 as i32,
+                meta.ecc_level,
+                meta.mask as i32,
+                raw_bytes,
+                text,
+            ));
+            true
+        }
+        Err(e) => {
+            log_info!("Failed to decode QR code: {:?} ({:?})", e, stream_error);
+            if let Some(failed) = failed {
+                let sides: f64 = (0..bounds.len())
+                    .map(|i| {
+                        let (a, b) = (bounds[i], bounds[(i + 1) % bounds.len()]);
+                        (b.0 - a.0).hypot(b.1 - a.1)
+                    })
+                    .sum();
+                let module_size = sides / bounds.len().max(1) as f64 / grid.grid.size() as f64;
+                failed.push(FailedGrid {
+                    bounds,
+                    error: format!("{:?}", e),
+                    module_size,
+                });
+            }
+            false
+        }
+    }
+}
+
+/// `data` of a decoded QR and the charset it was read with
+pub(crate) struct PayloadText {
+    pub(crate) data: String,
+    pub(crate) encoding: String,
+    pub(crate) failed: bool,
+}
+
+/// Convert payload bytes to UTF-8 following the symbol's ECI designator.
+/// Without one, Kanji segments mean Shift-JIS and anything else must already
+/// be UTF-8; other bytes are treated as binary and only kept in `raw_bytes`.
+pub(crate) fn payload_text(bytes: &[u8], eci: Option<u32>, kanji: bool) -> PayloadText {
+    let encoding = match eci {
+        None if kanji => Some(encoding_rs::SHIFT_JIS),
+        None => Some(encoding_rs::UTF_8),
+        Some(eci) => match eci_encoding(eci) {
+            Ok(encoding) => encoding,
+            Err(()) => {
+                return PayloadText {
+                    data: String::new(),
+                    encoding: format!("ECI {}", eci),
+                    failed: true,
+                }
+            }
+        },
+    };
+
+    match encoding {
+        Some(encoding) => {
+            let (text, had_errors) = encoding.decode_without_bom_handling(bytes);
+            PayloadText {
+                data: if had_errors { String::new() } else { text.into_owned() },
+                encoding: encoding.name().to_string(),
+                failed: had_errors,
+            }
+        }
+        // ISO-8859-1 maps every byte to the code point of the same value
+        None => PayloadText {
+            data: bytes.iter().map(|&b| b as char).collect(),
+            encoding: "ISO-8859-1".to_string(),
+            failed: false,
+        },
+    }
+}
+
+/// Charset for an ECI assignment number; `None` is ISO-8859-1
+fn eci_encoding(eci: u32) -> Result<Option<&'static encoding_rs::Encoding>, ()> {
+    use encoding_rs::*;
+    Ok(Some(match eci {
+        1 | 3 | 27 => return Ok(None),
+        4 => ISO_8859_2,
+        5 => ISO_8859_3,
+        6 => ISO_8859_4,
+        7 => ISO_8859_5,
+        8 => ISO_8859_6,
+        9 => ISO_8859_7,
+        10 => ISO_8859_8,
+        11 => WINDOWS_1254, // ISO-8859-9
+        12 => ISO_8859_10,
+        13 => WINDOWS_874, // ISO-8859-11
+        15 => ISO_8859_13,
+        16 => ISO_8859_14,
+        17 => ISO_8859_15,
+        18 => ISO_8859_16,
+        20 => SHIFT_JIS,
+        21 => WINDOWS_1250,
+        22 => WINDOWS_1251,
+        23 => WINDOWS_1252,
+        24 => WINDOWS_1256,
+        25 => UTF_16BE,
+        26 => UTF_8,
+        28 => BIG5,
+        29 => GB18030,
+        30 => EUC_KR,
+        _ => return Err(()),
+    }))
+}
+
+/// Run grid detection only on a luminance buffer (binarized in place)
+pub fn detect_luma_candidates(luma: &mut [u8], width: u32, height: u32) -> Vec<GridCandidate> {
+    let view = ImageBuffer::<image::Luma<u8>, &mut [u8]>::from_raw(width, height, luma)
+        .expect("buffer matches dimensions");
+    let mut prepared = PreparedImage::prepare(view);
+
+    prepared
+        .detect_grids()
+        .iter()
+        .map(|grid| GridCandidate {
+            bounds: grid.bounds.iter().map(|p| (p.x as f64, p.y as f64)).collect(),
+            size: grid.grid.size() as u32,
+        })
+        .collect()
+}
+
+/// Map rqrr's raw format-information ECC bits to the level letter
+fn ecc_level_name(ecc_level: u16) -> &'static str {
+    // Format bits order is M=00, L=01, H=10, Q=11
+    match ecc_level {
+        0 => "M",
+        1 => "L",
+        2 => "H",
+        _ => "Q",
+    }
+}
+
+/// Byte length of a `width` x `height` buffer with `bytes_per_pixel`
+/// channels, computed without overflow and checked against the pixel limit
+pub(crate) fn image_buffer_len(
+    width: u32,
+    height: u32,
+    bytes_per_pixel: u32,
+) -> Result<usize, VeloQrError> {
+    let pixels = width as u64 * height as u64;
+    let max_pixels = MAX_PIXELS.load(Ordering::Relaxed);
+    if pixels > max_pixels as u64 {
+        return Err(VeloQrError::ImageTooLarge(format!(
+            "{}x{} exceeds the limit of {} pixels",
+            width, height, max_pixels
+        )));
+    }
+    pixels
+        .checked_mul(bytes_per_pixel as u64)
+        .and_then(|len| usize::try_from(len).ok())
+        .ok_or(VeloQrError::DimensionOverflow { width, height })
+}
+
+/// Byte length of `height` rows `stride` bytes apart. Only the pixels count
+/// toward the pixel limit, so padding is not checked against it.
+fn strided_len(stride: u32, height: u32) -> Result<usize, VeloQrError> {
+    (stride as usize)
+        .checked_mul(height as usize)
+        .ok_or(VeloQrError::DimensionOverflow { width: stride, height })
+}
+
+/// How RGBA conversion treats the alpha channel: every pixel is composited
+/// over `background`, so transparent areas read as background instead of
+/// whatever RGB values they happen to carry
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(default)]
+pub struct AlphaOptions {
+    /// RGB color behind the image
+    pub background: [u8; 3],
+    /// The color channels are already multiplied by alpha (WebGL readbacks,
+    /// some native bridges)
+    pub premultiplied: bool,
+}
+
+impl Default for AlphaOptions {
+    fn default() -> Self {
+        AlphaOptions {
+            background: [255, 255, 255],
+            premultiplied: false,
+        }
+    }
+}
+
+/// Convert RGBA image data to grayscale, over a white background
+pub fn rgba_to_gray(rgba: &[u8], width: u32, height: u32) -> Result<GrayImage, VeloQrError> {
+    let mut luma = Vec::new();
+    rgba_to_luma_into(rgba, width, height, &mut luma, None)?;
+
+    GrayImage::from_raw(width, height, luma)
+        .ok_or(VeloQrError::InvalidImageBuffer)
+}
+
+/// `rgba_to_gray` for rows `stride_bytes` apart, as `VideoFrame.copyTo` and
+/// native bridges deliver them with row padding; `None` means packed rows
+pub fn rgba_to_gray_with_stride(
+    rgba: &[u8],
+    width: u32,
+    height: u32,
+    stride_bytes: Option<u32>,
+) -> Result<GrayImage, VeloQrError> {
+    rgba_to_gray_with_alpha(rgba, width, height, stride_bytes, &AlphaOptions::default())
+}
+
+/// `rgba_to_gray_with_stride` compositing over `alpha.background`
+pub fn rgba_to_gray_with_alpha(
+    rgba: &[u8],
+    width: u32,
+    height: u32,
+    stride_bytes: Option<u32>,
+    alpha: &AlphaOptions,
+) -> Result<GrayImage, VeloQrError> {
+    let mut luma = Vec::new();
+    rgba_to_luma_with_stride(rgba, width, height, stride_bytes, alpha, &mut luma, None)?;
+
+    GrayImage::from_raw(width, height, luma)
+        .ok_or(VeloQrError::InvalidImageBuffer)
+}
+
+/// `rgba_to_gray_with_alpha` that also counts the luma values in the same pass
+pub(crate) fn rgba_to_gray_with_histogram(
+    rgba: &[u8],
+    width: u32,
+    height: u32,
+    stride_bytes: Option<u32>,
+    alpha: &AlphaOptions,
+) -> Result<(GrayImage, LumaHistogram), VeloQrError> {
+    let mut luma = Vec::new();
+    let mut histogram = LumaHistogram::default();
+    let counts = Some(&mut histogram);
+    rgba_to_luma_with_stride(rgba, width, height, stride_bytes, alpha, &mut luma, counts)?;
+
+    let gray = GrayImage::from_raw(width, height, luma).ok_or(VeloQrError::InvalidImageBuffer)?;
+    Ok((gray, histogram))
+}
+
+/// Convert RGBA image data to luminance over a white background, reusing
+/// `out`'s allocation
+pub fn rgba_to_luma_into(
+    rgba: &[u8],
+    width: u32,
+    height: u32,
+    out: &mut Vec<u8>,
+    histogram: Option<&mut LumaHistogram>,
+) -> Result<(), VeloQrError> {
+    rgba_to_luma_with_stride(rgba, width, height, None, &AlphaOptions::default(), out, histogram)
+}
+
+/// `rgba_to_luma_into` for rows `stride_bytes` apart, compositing over
+/// `alpha.background`; the padding after each row is skipped. Packed rows
+/// (or `None`) are converted in one pass, or in parallel bands with `threads`.
+pub fn rgba_to_luma_with_stride(
+    rgba: &[u8],
+    width: u32,
+    height: u32,
+    stride_bytes: Option<u32>,
+    alpha: &AlphaOptions,
+    out: &mut Vec<u8>,
+    histogram: Option<&mut LumaHistogram>,
+) -> Result<(), VeloQrError> {
+    let blend = Blend::new(alpha);
+    let packed = image_buffer_len(width, height, 4)?;
+    let row = (width as usize)
+        .checked_mul(4)
+        .ok_or(VeloQrError::DimensionOverflow { width, height })?;
+    let stride = match stride_bytes {
+        Some(stride) if stride as usize != row => stride,
+        _ => {
+            if rgba.len() != packed {
+                return Err(VeloQrError::InvalidBufferLength {
+                    expected: packed,
+                    actual: rgba.len(),
+                });
+            }
+
+            out.clear();
+            out.reserve(packed / 4);
+            extend_luma_rows(rgba, row, row, out, blend, histogram);
+            return Ok(());
+        }
+    };
+    if (stride as usize) < row {
+        return Err(VeloQrError::InvalidStride { stride, width: row as u32 });
+    }
+
+    let expected = strided_len(stride, height)?;
+    if rgba.len() < expected {
+        return Err(VeloQrError::InvalidBufferLength {
+            expected,
+            actual: rgba.len(),
+        });
+    }
+
+    out.clear();
+    out.reserve(packed / 4);
+    extend_luma_rows(&rgba[..expected], row, stride as usize, out, blend, histogram);
+
+    Ok(())
+}
+
+/// Append the luma of whole rows `stride` bytes apart, each starting with
+/// `row` bytes of pixels, to `out`
+#[cfg(not(feature = "threads"))]
+fn extend_luma_rows(
+    rgba: &[u8],
+    row: usize,
+    stride: usize,
+    out: &mut Vec<u8>,
+    blend: Blend,
+    histogram: Option<&mut LumaHistogram>,
+) {
+    extend_luma_serial(rgba, row, stride, out, blend, histogram);
+}
+
+/// Rows per band of the parallel conversion
+#[cfg(feature = "threads")]
+const LUMA_BAND_ROWS: usize = 64;
+
+/// `extend_luma_rows` on the rayon pool: every band of `LUMA_BAND_ROWS`
+/// rows is converted and counted on its own, then appended in order, so
+/// the luma and histogram match the serial conversion
+#[cfg(feature = "threads")]
+fn extend_luma_rows(
+    rgba: &[u8],
+    row: usize,
+    stride: usize,
+    out: &mut Vec<u8>,
+    blend: Blend,
+    mut histogram: Option<&mut LumaHistogram>,
+) {
+    use rayon::prelude::*;
+
+    let count = histogram.is_some();
+    let bands: Vec<(Vec<u8>, Option<LumaHistogram>)> = rgba
+        .par_chunks(LUMA_BAND_ROWS * stride.max(1))
+        .map(|band| {
+            let mut luma = Vec::with_capacity(band.len() / stride * row / 4);
+            let mut counts = count.then(LumaHistogram::default);
+            extend_luma_serial(band, row, stride, &mut luma, blend, counts.as_mut());
+            (luma, counts)
+        })
+        .collect();
+
+    for (luma, counts) in bands {
+        out.extend_from_slice(&luma);
+        if let (Some(histogram), Some(counts)) = (histogram.as_deref_mut(), counts) {
+            for (total, count) in histogram.counts.iter_mut().zip(counts.counts) {
+                *total += count;
+            }
+        }
+    }
+}
+
+/// `extend_luma_rows` on the calling thread
+fn extend_luma_serial(
+    rgba: &[u8],
+    row: usize,
+    stride: usize,
+    out: &mut Vec<u8>,
+    blend: Blend,
+    mut histogram: Option<&mut LumaHistogram>,
+) {
+    if stride == row {
+        extend_luma(rgba, out, blend, histogram);
+        return;
+    }
+    for line in rgba.chunks_exact(stride) {
+        extend_luma(&line[..row], out, blend, histogram.as_deref_mut());
+    }
+}
+
+/// `AlphaOptions` reduced to what the per-pixel loops need
+#[derive(Clone, Copy)]
+pub(crate) struct Blend {
+    /// Luma of the background color
+    pub(crate) background: u32,
+    pub(crate) premultiplied: bool,
+}
+
+impl Blend {
+    fn new(alpha: &AlphaOptions) -> Self {
+        let [r, g, b] = alpha.background.map(u32::from);
+        Blend {
+            background: (77 * r + 150 * g + 29 * b + 128) >> 8,
+            premultiplied: alpha.premultiplied,
+        }
+    }
+}
+
+/// Append the luma of packed RGBA pixels to `out`
+fn extend_luma(
+    rgba: &[u8],
+    out: &mut Vec<u8>,
+    blend: Blend,
+    mut histogram: Option<&mut LumaHistogram>,
+) {
+    // The vector path leaves the histogram to the scalar loop
+    #[cfg(all(feature = "simd", target_arch = "wasm32", target_feature = "simd128"))]
+    let rgba = if histogram.is_none() { crate::simd::extend_luma(rgba, out, blend) } else { rgba };
+    out.extend(rgba.chunks_exact(4).map(|px| {
+        let r = px[0] as u32;
+        let g = px[1] as u32;
+        let b = px[2] as u32;
+        let a = px[3] as u32;
+
+        // BT.601 weights (0.299, 0.587, 0.114) in 8.8 fixed point; they sum
+        // to 256 so white stays 255, and rounding keeps the result within 1
+        // of the float
+        let color = (77 * r + 150 * g + 29 * b + 128) >> 8;
+        // Premultiplied color already carries its alpha: un-premultiplying
+        // and compositing again is `color + background * (1 - alpha)`
+        let weight = if blend.premultiplied { 255 } else { a };
+        let sum = color * weight + blend.background * (255 - a);
+        // sum / 255 rounded, exact over the whole range; opaque pixels keep
+        // their luma. Premultiplied values above alpha are clamped.
+        let luma = ((sum + 128 + ((sum + 128) >> 8)) >> 8).min(255) as u8;
+        if let Some(histogram) = histogram.as_deref_mut() {
+            histogram.counts[luma as usize] += 1;
+        }
+        luma
+    }));
+}
+
+/// Count of pixels per luma value
+pub struct LumaHistogram {
+    pub counts: [u64; 256],
+}
+
+impl Default for LumaHistogram {
+    fn default() -> Self {
+        LumaHistogram { counts: [0; 256] }
+    }
+}
+
+impl LumaHistogram {
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Smallest luma with at least `fraction` of the pixels at or below it
+    pub fn percentile(&self, fraction: f64) -> u8 {
+        let target = (self.total() as f64 * fraction).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (luma, &count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= target {
+                return luma as u8;
+            }
+        }
+        u8::MAX
+    }
+
+    /// Bins and the percentiles exposure control looks at
+    pub fn stats(&self) -> LumaStats {
+        let total = self.total();
+        LumaStats {
+            // One frame holds at most `u32::MAX` pixels (`set_max_image_pixels`)
+            histogram: self.counts.iter().map(|&count| count as u32).collect(),
+            p5: self.percentile(0.05),
+            p50: self.percentile(0.5),
+            p95: self.percentile(0.95),
+            clipped_high: match total {
+                0 => 0.0,
+                _ => self.counts[255] as f64 / total as f64,
+            },
+        }
+    }
+
+    /// Darkness wins over glare, which wins over a narrow histogram
+    pub fn hint(&self, thresholds: &HintThresholds) -> ScanHint {
+        let total = self.total();
+        if total == 0 {
+            return ScanHint::None;
+        }
+        let weighted: u64 = self.counts.iter().enumerate().map(|(v, &c)| v as u64 * c).sum();
+        let mean = weighted as f64 / total as f64;
+        let saturated = self.counts[255] as f64 / total as f64;
+        if mean < thresholds.dark_luma {
+            ScanHint::TooDark
+        } else if saturated > thresholds.saturated_fraction {
+            ScanHint::Glare
+        } else if self.percentile(0.95) - self.percentile(0.05) < thresholds.min_luma_range {
+            ScanHint::LowContrast
+        } else {
+            ScanHint::None
+        }
+    }
+}
+
+/// Wrap single-channel luminance data in a grayscale image
+pub fn luma_to_gray(luma: &[u8], width: u32, height: u32) -> Result<GrayImage, VeloQrError> {
+    let expected = image_buffer_len(width, height, 1)?;
+    if luma.len() != expected {
+        return Err(VeloQrError::InvalidBufferLength {
+            expected,
+            actual: luma.len(),
+        });
+    }
+
+    GrayImage::from_raw(width, height, luma.to_vec())
+        .ok_or(VeloQrError::InvalidImageBuffer)
+}
+
+/// Copy a (possibly padded) Y plane into a tightly packed grayscale image
+pub fn y_plane_to_gray(
+    y_plane: &[u8],
+    width: u32,
+    height: u32,
+    stride: u32,
+) -> Result<GrayImage, VeloQrError> {
+    if stride < width {
+        return Err(VeloQrError::InvalidStride { stride, width });
+    }
+
+    image_buffer_len(width, height, 1)?;
+    let expected = strided_len(stride, height)?;
+    if y_plane.len() < expected {
+        return Err(VeloQrError::InvalidBufferLength {
+            expected,
+            actual: y_plane.len(),
+        });
+    }
+
+    let (w, s) = (width as usize, stride as usize);
+    let mut luma = Vec::with_capacity(w * height as usize);
+    for row in y_plane[..expected].chunks_exact(s) {
+        luma.extend_from_slice(&row[..w]);
+    }
+
+    GrayImage::from_raw(width, height, luma)
+        .ok_or(VeloQrError::InvalidImageBuffer)
+}
+
+/// Decode PNG/JPEG bytes into a grayscale image
+pub fn encoded_to_gray(bytes: &[u8]) -> Result<GrayImage, VeloQrError> {
+    let mut reader = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(|e| VeloQrError::CorruptImage(e.to_string()))?;
+
+    if reader.format().is_none() {
+        return Err(VeloQrError::UnsupportedImageFormat(
+            "could not detect image format".to_string(),
+        ));
+    }
+
+    // Check the header's dimensions against the pixel limit before decoding
+    let header = ImageReader::new(Cursor::new(bytes)).with_guessed_format();
+    let dimensions = header.map_err(ImageError::IoError).and_then(|h| h.into_dimensions());
+    if let Ok((width, height)) = dimensions {
+        image_buffer_len(width, height, 1)?;
+    }
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_ENCODED_DIMENSION);
+    limits.max_image_height = Some(MAX_ENCODED_DIMENSION);
+    reader.limits(limits);
+
+    let img = reader.decode().map_err(|e| match e {
+        ImageError::Unsupported(_) => VeloQrError::UnsupportedImageFormat(e.to_string()),
+        ImageError::Limits(_) => VeloQrError::ImageTooLarge(e.to_string()),
+        _ => VeloQrError::CorruptImage(e.to_string()),
+    })?;
+
+    // `into_luma8` drops alpha, leaving transparent pixels at whatever color
+    // they store (often black)
+    if img.color().has_alpha() {
+        let rgba = img.into_rgba8();
+        return rgba_to_gray(rgba.as_raw(), rgba.width(), rgba.height());
+    }
+
+    Ok(img.into_luma8())
+}
+
+/// Decode a base64 `data:image/png` or `data:image/jpeg` URL into a grayscale image
+pub fn data_url_to_gray(url: &str) -> Result<GrayImage, VeloQrError> {
+    encoded_to_gray(&crate::data_url::decode(url)?)
+}
+
+/// Shrink a grayscale image by an integer factor, averaging each factor x factor box.
+/// Trailing rows/columns that do not fill a whole box are dropped.
+fn downscale_gray(gray: &GrayImage, factor: u32) -> GrayImage {
+    let (width, height) = gray.dimensions();
+    let out_width = (width / factor).max(1);
+    let out_height = (height / factor).max(1);
+    let src = gray.as_raw();
+    let (w, f) = (width as usize, factor as usize);
+
+    let mut out = Vec::with_capacity((out_width * out_height) as usize);
+    for oy in 0..out_height as usize {
+        let rows = (oy * f)..((oy + 1) * f).min(height as usize);
+        for ox in 0..out_width as usize {
+            let cols = (ox * f)..((ox + 1) * f).min(w);
+            let mut sum = 0u32;
+            for y in rows.clone() {
+                sum += src[y * w + cols.start..y * w + cols.end]
+                    .iter()
+                    .map(|&v| v as u32)
+                    .sum::<u32>();
+            }
+            let count = (rows.len() * cols.len()) as u32;
+            out.push((sum / count) as u8);
+        }
+    }
+
+    GrayImage::from_raw(out_width, out_height, out).expect("buffer matches dimensions")
+}
+
+/// Invert a grayscale image in place so light-on-dark codes read as dark-on-light
+fn invert_gray(gray: &mut GrayImage) {
+    for v in gray.iter_mut() {
+        *v = 255 - *v;
+    }
+}
+
+/// Threshold `gray` to black and white with the method chosen in `options`;
+/// `Binarization::Default` leaves it to rqrr's preparation
+fn binarize(gray: &mut GrayImage, options: &DecodeOptions) {
+    let (width, height) = gray.dimensions();
+    let window = options
+        .window
+        .unwrap_or(width.min(height) / 8)
+        .clamp(3, width.max(height).max(3));
+
+    match options.binarization {
+        Binarization::Default => {}
+        Binarization::Otsu => {
+            let threshold = otsu_threshold(gray);
+            for v in gray.iter_mut() {
+                *v = if *v <= threshold { 0 } else { 255 };
+            }
+        }
+        Binarization::Adaptive => local_threshold(gray, window, |mean, _| mean - ADAPTIVE_OFFSET),
+        Binarization::Sauvola => {
+            let k = options.k as f64;
+            local_threshold(gray, window, |mean, deviation| {
+                mean * (1.0 + k * (deviation / SAUVOLA_RANGE - 1.0))
+            })
+        }
+    }
+}
+
+/// Gray levels below the window mean by more than this are dark in `adaptive`
+pub(crate) const ADAPTIVE_OFFSET: f64 = 7.0;
+
+/// Dynamic range of the standard deviation in Sauvola's formula
+const SAUVOLA_RANGE: f64 = 128.0;
+
+/// Threshold maximizing the between-class variance of the histogram
+pub(crate) fn otsu_threshold(gray: &GrayImage) -> u8 {
+    let mut histogram = [0u64; 256];
+    for &v in gray.iter() {
+        histogram[v as usize] += 1;
+    }
+
+    let total: u64 = histogram.iter().sum();
+    let weighted_total: f64 = histogram.iter().enumerate().map(|(v, &n)| v as f64 * n as f64).sum();
+    let (mut background, mut weighted_background) = (0u64, 0.0);
+    let (mut best, mut best_variance) = (0u8, 0.0);
+    for (v, &n) in histogram.iter().enumerate() {
+        background += n;
+        weighted_background += v as f64 * n as f64;
+        let foreground = total - background;
+        if background == 0 || foreground == 0 {
+            continue;
+        }
+        let mean_background = weighted_background / background as f64;
+        let mean_foreground = (weighted_total - weighted_background) / foreground as f64;
+        let variance = background as f64
+            * foreground as f64
+            * (mean_background - mean_foreground).powi(2);
+        if variance > best_variance {
+            best_variance = variance;
+            best = v as u8;
+        }
+    }
+    best
+}
+
+/// Per-pixel threshold from the mean and standard deviation of the
+/// `window` x `window` neighbourhood, computed with integral images
+pub(crate) fn local_threshold<F>(gray: &mut GrayImage, window: u32, threshold: F)
+where
+    F: Fn(f64, f64) -> f64,
+{
+    let (width, height) = (gray.width() as usize, gray.height() as usize);
+    let stride = width + 1;
+    let mut sum = vec![0u64; stride * (height + 1)];
+    let mut squares = vec![0u64; stride * (height + 1)];
+    for y in 0..height {
+        let (mut row_sum, mut row_squares) = (0u64, 0u64);
+        for x in 0..width {
+            let v = gray.as_raw()[y * width + x] as u64;
+            row_sum += v;
+            row_squares += v * v;
+            sum[(y + 1) * stride + x + 1] = sum[y * stride + x + 1] + row_sum;
+            squares[(y + 1) * stride + x + 1] = squares[y * stride + x + 1] + row_squares;
+        }
+    }
+
+    let half = (window / 2) as usize;
+    let area = |table: &[u64], x0: usize, y0: usize, x1: usize, y1: usize| {
+        table[y1 * stride + x1] + table[y0 * stride + x0]
+            - table[y0 * stride + x1]
+            - table[y1 * stride + x0]
+    };
+    let pixels = gray.as_mut();
+    for y in 0..height {
+        let (y0, y1) = (y.saturating_sub(half), (y + half + 1).min(height));
+        for x in 0..width {
+            let (x0, x1) = (x.saturating_sub(half), (x + half + 1).min(width));
+            let count = ((x1 - x0) * (y1 - y0)) as f64;
+            let mean = area(&sum, x0, y0, x1, y1) as f64 / count;
+            let variance = area(&squares, x0, y0, x1, y1) as f64 / count - mean * mean;
+            let v = &mut pixels[y * width + x];
+            *v = if (*v as f64) < threshold(mean, variance.max(0.0).sqrt()) { 0 } else { 255 };
+        }
+    }
+}
+
+/// Perspective-warp the quadrilateral `corners` (top-left first) into a
+/// `size` x `size` square with bilinear sampling; outside pixels are black.
+/// Counter-clockwise corners (mirrored passes) are reordered so the crop is
+/// never flipped relative to the image.
+fn warp_quad(gray: &GrayImage, corners: [(f64, f64); 4], size: u32) -> GrayImage {
+    let [p0, mut p1, p2, mut p3] = corners;
+    let signed_area = (p1.0 - p0.0) * (p3.1 - p0.1) - (p1.1 - p0.1) * (p3.0 - p0.0);
+    if signed_area < 0.0 {
+        std::mem::swap(&mut p1, &mut p3);
+    }
+    let map = Homography::square_to_quad([p0, p1, p2, p3]);
+
+    let (width, height) = (gray.width() as i64, gray.height() as i64);
+    let pixel = |x: i64, y: i64| {
+        if (0..width).contains(&x) && (0..height).contains(&y) {
+            gray.get_pixel(x as u32, y as u32).0[0] as f64
+        } else {
+            0.0
+        }
+    };
+
+    GrayImage::from_fn(size, size, |i, j| {
+        let (u, v) = ((i as f64 + 0.5) / size as f64, (j as f64 + 0.5) / size as f64);
+        let (x, y) = map.apply(u, v);
+        let (x, y) = (x - 0.5, y - 0.5);
+        if !x.is_finite() || !y.is_finite() {
+            return image::Luma([0]);
+        }
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let top = pixel(x0, y0) * (1.0 - fx) + pixel(x0 + 1, y0) * fx;
+        let bottom = pixel(x0, y0 + 1) * (1.0 - fx) + pixel(x0 + 1, y0 + 1) * fx;
+        image::Luma([(top * (1.0 - fy) + bottom * fy).round() as u8])
+    })
+}
+
+/// Module matrix of a decoded QR code, read from the binarized `prepared`
+/// frame at the module centers mapped through the perspective of its
+/// corners, each center rounded to its pixel as rqrr samples its grids
+fn sample_modules(
+    prepared: &PreparedImage<GrayImage>,
+    result: &QRCodeResult,
+) -> Option<ModuleGrid> {
+    let corners = code_corners(&result.bounds, result.version)?;
+    let map = Homography::square_to_quad(corners);
+    let size = modules_per_side(result.version) as u32;
+    let (max_x, max_y) = (prepared.width() as f64 - 1.0, prepared.height() as f64 - 1.0);
+
+    let mut bits = Vec::with_capacity((size * size) as usize);
+    for row in 0..size {
+        for column in 0..size {
+            let u = (column as f64 + 0.5) / size as f64;
+            let v = (row as f64 + 0.5) / size as f64;
+            let (x, y) = map.apply(u, v);
+            if !x.is_finite() || !y.is_finite() {
+                return None;
+            }
+            let (x, y) = (x.round().clamp(0.0, max_x), y.round().clamp(0.0, max_y));
+            // 0 is white, whatever else rqrr may paint
+            let dark = u8::from(prepared.get_pixel_at(x as usize, y as usize)) != 0;
+            bits.push((dark != result.inverted) as u8);
+        }
+    }
+    Some(ModuleGrid { size, bits })
+}
+
+/// Clamp a region of interest to the image, returning `(x, y, width, height)`
+fn clamp_roi(
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    roi_width: u32,
+    roi_height: u32,
+) -> Result<(u32, u32, u32, u32), VeloQrError> {
+    let x0 = x.min(width);
+    let y0 = y.min(height);
+    let x1 = x.saturating_add(roi_width).min(width);
+    let y1 = y.saturating_add(roi_height).min(height);
+
+    if x1 <= x0 || y1 <= y0 {
+        return Err(VeloQrError::InvalidRoi(format!(
+            "region {}x{} at ({}, {}) does not overlap the {}x{} image",
+            roi_width, roi_height, x, y, width, height
+        )));
+    }
+
+    Ok((x0, y0, x1 - x0, y1 - y0))
+}
+
+/// Serialize results as a CBOR envelope, `{ "schema_version", "data" }`
+/// with text keys and byte-string payloads
+#[cfg(feature = "cbor")]
+pub fn results_to_cbor(results: &[QRCodeResult]) -> Result<Vec<u8>, VeloQrError> {
+    cbor::encode(results)
+}
+
+/// Read back a `results_to_cbor` envelope, e.g. on the native end of a
+/// worker's byte channel
+#[cfg(feature = "cbor")]
+pub fn results_from_cbor(bytes: &[u8]) -> Result<Vec<QRCodeResult>, VeloQrError> {
+    cbor::decode(bytes)
+}
+
+// ==================== Scanner Session ====================
+
+/// Side of the luma thumbnails compared by `QrScanner::should_scan`
+const THUMBNAIL_SIDE: usize = 64;
+
+/// Pixels averaged per thumbnail cell along each axis, at most
+const THUMBNAIL_SAMPLES: usize = 4;
+
+/// Default `QrScanner::skip_threshold`, a little above the sensor noise of
+/// a still camera once averaged into thumbnail cells
+const DEFAULT_SKIP_THRESHOLD: f64 = 2.0;
+
+/// Default `QrScanner::max_missed_frames`
+const DEFAULT_MAX_MISSED_FRAMES: u32 = 5;
+
+/// Default `QrScanner::max_tracks`, well above the codes one frame holds
+const DEFAULT_MAX_TRACKS: u32 = 64;
+
+/// A payload followed across frames by `QrScanner` tracking
+struct Track {
+    id: u32,
+    data: String,
+    payload_hash: String,
+    /// Of the last matched result, to tell copies of one payload apart
+    center: (f64, f64),
+    /// Exponential moving average of the matched results' corners
+    bounds: Vec<(f64, f64)>,
+    /// Consecutive scanned frames without a match
+    missed: u32,
+    frames_seen: u32,
+    /// `QrScanner::tracked_frames` when last matched, for LRU eviction
+    last_seen: u64,
+}
+
+impl Track {
+    fn code(&self) -> TrackedCode {
+        TrackedCode {
+            track_id: self.id,
+            data: self.data.clone(),
+            payload_hash: self.payload_hash.clone(),
+            bounds: self.bounds.clone(),
+            frames_seen: self.frames_seen,
+        }
+    }
+}
+
+/// One track reported by `QrScanner::scan_tracked`
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TrackedCode {
+    pub track_id: u32,
+    pub data: String,
+    pub payload_hash: String,
+    /// Smoothed corners while `smoothing_alpha` is above 0, else the
+    /// latest ones; as last seen for a lost track
+    pub bounds: Vec<(f64, f64)>,
+    /// Frames the payload was decoded in, this one included
+    pub frames_seen: u32,
+}
+
+/// Track changes of one `QrScanner::scan_tracked` frame
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct TrackEvents {
+    /// Codes without a track before this frame
+    pub appeared: Vec<TrackedCode>,
+    /// Tracked codes seen again
+    pub updated: Vec<TrackedCode>,
+    /// Tracks past `max_missed_frames` scans without their code, evicted
+    /// to stay within `max_tracks`, or dropped when the frame size changed
+    pub lost: Vec<TrackedCode>,
+}
+
+/// Long-lived scanner for video frames.
+/// Owns its grayscale and result buffers so steady-state scanning does not
+/// reallocate them every frame.
+#[cfg_attr(all(feature = "wasm", target_arch = "wasm32"), wasm_bindgen)]
+pub struct QrScanner {
+    width: u32,
+    height: u32,
+    gray: Vec<u8>,
+    /// RGBA frame written in place through `input_buffer`
+    input: Vec<u8>,
+    results: Vec<QRCodeResult>,
+    /// Payload bytes -> time (ms) it was last emitted by `scan_dedup`
+    emitted: HashMap<Vec<u8>, f64>,
+    /// Luma thumbnail of the last scanned frame, empty before the first
+    thumbnail: Vec<u8>,
+    /// Dimensions of the frame `thumbnail` was taken from
+    thumbnail_frame: (u32, u32),
+    /// Thumbnail of the frame last passed to `should_scan`
+    probe: Vec<u8>,
+    /// Distance between row starts of the RGBA frames, `None` when packed
+    stride_bytes: Option<u32>,
+    /// The last scanned frame had codes, before `scan_dedup` dropped any
+    last_found: bool,
+    skip_threshold: f64,
+    last_difference: Option<f64>,
+    frames_skipped: u32,
+    /// Weight of each new frame's corners; 0 tracks in `scan_tracked` only
+    smoothing: f64,
+    max_missed_frames: u32,
+    max_tracks: u32,
+    tracks: Vec<Track>,
+    next_track_id: u32,
+    /// Frames tracks were matched against
+    tracked_frames: u64,
+    /// Tracks expired, evicted or dropped by a resize since the last frame
+    /// scanned, until `scan_tracked` reports them
+    lost: Vec<TrackedCode>,
+}
+
+#[cfg_attr(all(feature = "wasm", target_arch = "wasm32"), wasm_bindgen)]
+impl QrScanner {
+    #[cfg_attr(all(feature = "wasm", target_arch = "wasm32"), wasm_bindgen(constructor))]
+    pub fn new(width: u32, height: u32) -> QrScanner {
+        QrScanner {
+            width,
+            height,
+            // Oversized dimensions are reported by the first scan
+            gray: Vec::with_capacity(image_buffer_len(width, height, 1).unwrap_or(0)),
+            input: Vec::new(),
+            results: Vec::new(),
+            emitted: HashMap::new(),
+            thumbnail: Vec::new(),
+            thumbnail_frame: (0, 0),
+            probe: Vec::new(),
+            stride_bytes: None,
+            last_found: false,
+            skip_threshold: DEFAULT_SKIP_THRESHOLD,
+            last_difference: None,
+            frames_skipped: 0,
+            smoothing: 0.0,
+            max_missed_frames: DEFAULT_MAX_MISSED_FRAMES,
+            max_tracks: DEFAULT_MAX_TRACKS,
+            tracks: Vec::new(),
+            next_track_id: 1,
+            tracked_frames: 0,
+            lost: Vec::new(),
+        }
+    }
+
+    /// Change the frame dimensions used by `scan_in_place` (and by `scan`
+    /// calls that omit them); the input buffer must be queried again
+    pub fn set_frame_size(&mut self, width: u32, height: u32) -> Result<(), VeloQrError> {
+        self.resize(width, height);
+        if !self.input.is_empty() {
+            self.ensure_input()?;
+        }
+        Ok(())
+    }
+
+    /// Forget every previously emitted payload, every track, and the last
+    /// scanned frame so the next `scan_adaptive` decodes
+    pub fn reset(&mut self) {
+        self.emitted.clear();
+        self.thumbnail.clear();
+        self.tracks.clear();
+        self.lost.clear();
+    }
+
+    /// Weight of the newest frame in the moving average behind
+    /// `smoothed_bounds`: 1 follows each frame exactly, smaller values
+    /// steady jittering corners at the cost of lag; at 0 (the default) only
+    /// `scan_tracked` tracks codes, following each frame exactly
+    #[cfg_attr(all(feature = "wasm", target_arch = "wasm32"), wasm_bindgen(getter))]
+    pub fn smoothing_alpha(&self) -> f64 {
+        self.smoothing
+    }
+
+    /// Clamped to [0, 1], NaN being 0
+    #[cfg_attr(all(feature = "wasm", target_arch = "wasm32"), wasm_bindgen(setter))]
+    pub fn set_smoothing_alpha(&mut self, alpha: f64) {
+        self.smoothing = alpha.clamp(0.0, 1.0).max(0.0);
+    }
+
+    /// Scanned frames a track survives without its payload before it
+    /// expires and a reappearing code gets a new `track_id`
+    #[cfg_attr(all(feature = "wasm", target_arch = "wasm32"), wasm_bindgen(getter))]
+    pub fn max_missed_frames(&self) -> u32 {
+        self.max_missed_frames
+    }
+
+    #[cfg_attr(all(feature = "wasm", target_arch = "wasm32"), wasm_bindgen(setter))]
+    pub fn set_max_missed_frames(&mut self, frames: u32) {
+        self.max_missed_frames = frames;
+    }
+
+    /// Tracks kept at most; a new code beyond it evicts the track seen
+    /// least recently, so long sessions over many codes stay bounded
+    #[cfg_attr(all(feature = "wasm", target_arch = "wasm32"), wasm_bindgen(getter))]
+    pub fn max_tracks(&self) -> u32 {
+        self.max_tracks
+    }
+
+    /// At least 1; lowering it evicts the least recently seen tracks now,
+    /// without reporting them lost
+    #[cfg_attr(all(feature = "wasm", target_arch = "wasm32"), wasm_bindgen(setter))]
+    pub fn set_max_tracks(&mut self, tracks: u32) {
+        self.max_tracks = tracks.max(1);
+        if self.tracks.len() > self.max_tracks as usize {
+            self.tracks.sort_by_key(|track| std::cmp::Reverse(track.last_seen));
+            self.tracks.truncate(self.max_tracks as usize);
+        }
+    }
+
+    /// Whether the RGBA frame `rgba`, at the current frame size, is worth a
+    /// full scan: its 64x64 luma thumbnail differs from the last scanned
+    /// frame's by at least `skip_threshold` levels per pixel on average, or
+    /// that scan found a code, or there is no such frame to compare with.
+    /// The difference is kept as `last_frame_difference`.
+    pub fn should_scan(&mut self, rgba: &[u8]) -> bool {
+        self.last_difference = self.frame_difference(rgba);
+        match self.last_difference {
+            Some(difference) => self.last_found || difference >= self.skip_threshold,
+            None => true,
+        }
+    }
+
+    /// Mean absolute luma difference per thumbnail pixel below which
+    /// `scan_adaptive` skips a frame; 0 scans every frame
+    #[cfg_attr(all(feature = "wasm", target_arch = "wasm32"), wasm_bindgen(getter))]
+    pub fn skip_threshold(&self) -> f64 {
+        self.skip_threshold
+    }
+
+    /// Negative (or NaN) thresholds are 0
+    #[cfg_attr(all(feature = "wasm", target_arch = "wasm32"), wasm_bindgen(setter))]
+    pub fn set_skip_threshold(&mut self, threshold: f64) {
+        self.skip_threshold = threshold.max(0.0);
+    }
+
+    /// Difference computed by the last `should_scan`, for tuning
+    /// `skip_threshold`; none when there was nothing to compare with
+    #[cfg_attr(all(feature = "wasm", target_arch = "wasm32"), wasm_bindgen(getter))]
+    pub fn last_frame_difference(&self) -> Option<f64> {
+        self.last_difference
+    }
+
+    /// Frames `scan_adaptive` has skipped since the scanner was created
+    #[cfg_attr(all(feature = "wasm", target_arch = "wasm32"), wasm_bindgen(getter))]
+    pub fn frames_skipped(&self) -> u32 {
+        self.frames_skipped
+    }
+
+    /// Distance in bytes between row starts of the frames passed to every
+    /// scan method and written to the input buffer, for padded layouts
+    /// such as `VideoFrame.copyTo`; `None` (the default) means packed
+    /// `width * 4` rows
+    #[cfg_attr(all(feature = "wasm", target_arch = "wasm32"), wasm_bindgen(getter))]
+    pub fn stride_bytes(&self) -> Option<u32> {
+        self.stride_bytes
+    }
+
+    /// The input buffer must be queried again afterwards
+    #[cfg_attr(all(feature = "wasm", target_arch = "wasm32"), wasm_bindgen(setter))]
+    pub fn set_stride_bytes(&mut self, stride_bytes: Option<u32>) {
+        self.stride_bytes = stride_bytes;
+        if !self.input.is_empty() {
+            // Too small a stride is reported by the next scan
+            let _ = self.ensure_input();
+        }
+    }
+
+    #[cfg_attr(all(feature = "wasm", target_arch = "wasm32"), wasm_bindgen(getter))]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[cfg_attr(all(feature = "wasm", target_arch = "wasm32"), wasm_bindgen(getter))]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+}
+
+impl QrScanner {
+    /// Decode QR codes from an RGBA frame.
+    /// Pass `width`/`height` when the frame size changes; buffers are
+    /// reallocated transparently.
+    pub fn scan(
+        &mut self,
+        rgba: &[u8],
+        width: Option<u32>,
+        height: Option<u32>,
+    ) -> Result<&[QRCodeResult], VeloQrError> {
+        self.scan_frame(rgba, width, height)?;
+
+        Ok(&self.results)
+    }
+
+    /// Like `scan`, but only returns codes whose payload has not been
+    /// emitted within the last `ttl_ms` milliseconds (as of `now_ms`).
+    /// Codes are matched by payload, so a code moving in the frame is not re-reported.
+    pub fn scan_dedup(
+        &mut self,
+        rgba: &[u8],
+        ttl_ms: f64,
+        now_ms: f64,
+        width: Option<u32>,
+        height: Option<u32>,
+    ) -> Result<&[QRCodeResult], VeloQrError> {
+        self.scan_frame(rgba, width, height)?;
+
+        self.dedup_results(ttl_ms, now_ms);
+
+        Ok(&self.results)
+    }
+
+    /// Like `scan`, but reports the frame as track changes: codes that
+    /// appeared, tracked codes seen again, and tracks lost after
+    /// `max_missed_frames` scans without their code. Tracks are kept
+    /// whether or not `smoothing_alpha` is on; other scan methods only
+    /// age them while it is, and their losses are not reported here.
+    pub fn scan_tracked(
+        &mut self,
+        rgba: &[u8],
+        width: Option<u32>,
+        height: Option<u32>,
+    ) -> Result<TrackEvents, VeloQrError> {
+        self.decode_frame(rgba, width, height)?;
+        if self.smoothing == 0.0 {
+            self.track_results(1.0);
+        }
+
+        let mut events = TrackEvents {
+            lost: std::mem::take(&mut self.lost),
+            ..TrackEvents::default()
+        };
+        let tracks = self.results.iter().filter_map(|result| {
+            self.tracks.iter().find(|track| Some(track.id) == result.track_id)
+        });
+        for track in tracks {
+            if track.frames_seen == 1 {
+                events.appeared.push(track.code());
+            } else {
+                events.updated.push(track.code());
+            }
+        }
+
+        Ok(events)
+    }
+
+    /// Like `scan`, but skips detection on frames `should_scan` rejects:
+    /// nearly identical to the last scanned frame, which found nothing.
+    /// Skipped frames return no codes and count in `frames_skipped`.
+    pub fn scan_adaptive(
+        &mut self,
+        rgba: &[u8],
+        width: Option<u32>,
+        height: Option<u32>,
+    ) -> Result<&[QRCodeResult], VeloQrError> {
+        self.resize(width.unwrap_or(self.width), height.unwrap_or(self.height));
+        if !self.should_scan(rgba) {
+            self.frames_skipped = self.frames_skipped.saturating_add(1);
+            self.results.clear();
+            return Ok(&self.results);
+        }
+        self.scan_frame(rgba, None, None)?;
+
+        Ok(&self.results)
+    }
+
+    /// The `width * height * 4` byte RGBA buffer read by `scan_in_place`
+    /// (`stride_bytes * height` with a stride), allocated on first use. It
+    /// keeps its address until the frame grows.
+    pub fn input_buffer(&mut self) -> Result<&mut [u8], VeloQrError> {
+        self.ensure_input()?;
+        Ok(&mut self.input)
+    }
+
+    /// Decode the frame written into the input buffer
+    pub fn scan_in_place(&mut self) -> Result<&[QRCodeResult], VeloQrError> {
+        self.ensure_input()?;
+        // Taken out for the duration so `scan_frame` can borrow `self`;
+        // moving the Vec keeps its heap allocation, and so the pointer
+        let input = std::mem::take(&mut self.input);
+        let scanned = self.scan_frame(&input, None, None);
+        self.input = input;
+        scanned?;
+
+        Ok(&self.results)
+    }
+
+    /// Convert and decode one RGBA frame into `self.results`, for the scan
+    /// methods that do not report lost tracks
+    fn scan_frame(
+        &mut self,
+        rgba: &[u8],
+        width: Option<u32>,
+        height: Option<u32>,
+    ) -> Result<(), VeloQrError> {
+        let decoded = self.decode_frame(rgba, width, height);
+        self.lost.clear();
+        decoded
+    }
+
+    /// `scan_frame`, keeping the tracks it loses in `self.lost` until
+    /// `scan_tracked` reports them
+    fn decode_frame(
+        &mut self,
+        rgba: &[u8],
+        width: Option<u32>,
+        height: Option<u32>,
+    ) -> Result<(), VeloQrError> {
+        self.resize(width.unwrap_or(self.width), height.unwrap_or(self.height));
+
+        let (width, height, stride) = (self.width, self.height, self.stride_bytes);
+        let alpha = AlphaOptions::default();
+        rgba_to_luma_with_stride(rgba, width, height, stride, &alpha, &mut self.gray, None)?;
+        // The reference `should_scan` compares later frames with
+        luma_thumbnail(rgba, width, height, stride, &mut self.thumbnail);
+        self.thumbnail_frame = (self.width, self.height);
+
+        self.results.clear();
+        decode_luma_into(&mut self.gray, self.width, self.height, &mut self.results, None);
+        self.last_found = !self.results.is_empty();
+        if self.smoothing > 0.0 {
+            self.track_results(self.smoothing);
+        }
+
+        Ok(())
+    }
+
+    /// Match `self.results` to the tracks with their payload hash, nearest
+    /// center first, averaging the matched tracks' corners with weight
+    /// `alpha` and opening tracks for the rest; unmatched tracks age and
+    /// expire into `self.lost`
+    fn track_results(&mut self, alpha: f64) {
+        self.tracked_frames += 1;
+
+        let mut matched = vec![false; self.tracks.len()];
+        for result in &mut self.results {
+            let distance = |track: &Track| {
+                (track.center.0 - result.center.0).hypot(track.center.1 - result.center.1)
+            };
+            let nearest = (0..self.tracks.len())
+                .filter(|&i| !matched[i])
+                .filter(|&i| self.tracks[i].payload_hash == result.payload_hash)
+                .filter(|&i| self.tracks[i].bounds.len() == result.bounds.len())
+                .min_by(|&a, &b| distance(&self.tracks[a]).total_cmp(&distance(&self.tracks[b])));
+
+            let index = match nearest {
+                Some(index) => {
+                    let track = &mut self.tracks[index];
+                    for (smoothed, raw) in track.bounds.iter_mut().zip(&result.bounds) {
+                        smoothed.0 += alpha * (raw.0 - smoothed.0);
+                        smoothed.1 += alpha * (raw.1 - smoothed.1);
+                    }
+                    track.center = result.center;
+                    track.missed = 0;
+                    track.frames_seen += 1;
+                    track.last_seen = self.tracked_frames;
+                    matched[index] = true;
+                    index
+                }
+                None => {
+                    let track = Track {
+                        id: self.next_track_id,
+                        data: result.data.clone(),
+                        payload_hash: result.payload_hash.clone(),
+                        center: result.center,
+                        bounds: result.bounds.clone(),
+                        missed: 0,
+                        frames_seen: 1,
+                        last_seen: self.tracked_frames,
+                    };
+                    let index = if self.tracks.len() < self.max_tracks as usize {
+                        self.tracks.push(track);
+                        matched.push(true);
+                        self.tracks.len() - 1
+                    } else {
+                        // Evict the least recently seen track not in this frame;
+                        // with none, this code stays untracked
+                        let evicted = (0..self.tracks.len())
+                            .filter(|&i| !matched[i])
+                            .min_by_key(|&i| self.tracks[i].last_seen);
+                        let Some(index) = evicted else {
+                            continue;
+                        };
+                        self.lost.push(self.tracks[index].code());
+                        self.tracks[index] = track;
+                        matched[index] = true;
+                        index
+                    };
+                    self.next_track_id = self.next_track_id.wrapping_add(1).max(1);
+                    index
+                }
+            };
+            result.track_id = Some(self.tracks[index].id);
+            result.smoothed_bounds = self.tracks[index].bounds.clone();
+        }
+
+        for (track, matched) in self.tracks.iter_mut().zip(matched) {
+            if !matched {
+                track.missed += 1;
+            }
+        }
+        let max_missed = self.max_missed_frames;
+        let lost = &mut self.lost;
+        self.tracks.retain(|track| {
+            let keep = track.missed <= max_missed;
+            if !keep {
+                lost.push(track.code());
+            }
+            keep
+        });
+    }
+
+    /// Mean absolute difference between the thumbnails of `rgba` and of
+    /// the last scanned frame, none unless both exist at the same size
+    fn frame_difference(&mut self, rgba: &[u8]) -> Option<f64> {
+        let (width, height, stride) = (self.width, self.height, self.stride_bytes);
+        let taken = luma_thumbnail(rgba, width, height, stride, &mut self.probe);
+        let comparable = taken
+            && self.thumbnail_frame == (self.width, self.height)
+            && self.thumbnail.len() == self.probe.len();
+        if !comparable {
+            return None;
+        }
+        let sum: u64 =
+            self.probe.iter().zip(&self.thumbnail).map(|(&a, &b)| a.abs_diff(b) as u64).sum();
+        Some(sum as f64 / self.probe.len() as f64)
+    }
+
+    /// Drop results emitted within `ttl_ms` and record the remaining ones
+    fn dedup_results(&mut self, ttl_ms: f64, now_ms: f64) {
+        self.emitted.retain(|_, emitted_at| now_ms - *emitted_at < ttl_ms);
+
+        let emitted = &mut self.emitted;
+        self.results.retain(|result| {
+            if emitted.contains_key(&result.raw_bytes) {
+                return false;
+            }
+            emitted.insert(result.raw_bytes.clone(), now_ms);
+            true
+        });
+    }
+
+    /// Size the input buffer for the current dimensions, keeping its
+    /// allocation (and address) unless it has to grow
+    fn ensure_input(&mut self) -> Result<(), VeloQrError> {
+        let packed = image_buffer_len(self.width, self.height, 4)?;
+        let len = match self.stride_bytes {
+            Some(stride) => strided_len(stride, self.height)?,
+            None => packed,
+        };
+        self.input.resize(len, 0);
+        Ok(())
+    }
+
+    /// Switch to new frame dimensions, growing the grayscale buffer if needed
+    fn resize(&mut self, width: u32, height: u32) {
+        if (width, height) == (self.width, self.height) {
+            return;
+        }
+
+        log_debug!("Scanner resized to {}x{}", width, height);
+        self.width = width;
+        self.height = height;
+        // Bounds of the old frame size do not carry over, so every track is lost
+        self.lost.extend(self.tracks.drain(..).map(|track| track.code()));
+        let needed = image_buffer_len(width, height, 1).unwrap_or(0);
+        if self.gray.capacity() < needed {
+            self.gray = Vec::with_capacity(needed);
+        }
+    }
+}
+
+/// Decimate a packed RGBA frame into `out`: a `THUMBNAIL_SIDE` square
+/// (narrower for smaller frames) of cell luma means, each over an evenly
+/// spaced grid of at most `THUMBNAIL_SAMPLES` squared pixels. Alpha is
+/// ignored. Leaves `out` empty and returns false when `rgba` is not a
+/// `width` x `height` frame with rows `stride_bytes` apart (packed when
+/// `None`).
+fn luma_thumbnail(
+    rgba: &[u8],
+    width: u32,
+    height: u32,
+    stride_bytes: Option<u32>,
+    out: &mut Vec<u8>,
+) -> bool {
+    out.clear();
+    let packed = image_buffer_len(width, height, 4);
+    let (Ok(packed), Some(row)) = (packed, (width as usize).checked_mul(4)) else {
+        return false;
+    };
+    // Accepting the frames `rgba_to_luma_with_stride` does
+    let (stride, valid) = match stride_bytes {
+        Some(stride) if stride as usize != row => {
+            let needed = strided_len(stride, height);
+            let valid = stride as usize > row && needed.is_ok_and(|len| rgba.len() >= len);
+            (stride as usize, valid)
+        }
+        _ => (row, rgba.len() == packed),
+    };
+    if width == 0 || height == 0 || !valid {
+        return false;
+    }
+    let (width, height) = (width as usize, height as usize);
+    let (columns, rows) = (width.min(THUMBNAIL_SIDE), height.min(THUMBNAIL_SIDE));
+    // Centers of `THUMBNAIL_SAMPLES` equal parts of [start, end)
+    let spread = |start: usize, end: usize| {
+        let count = THUMBNAIL_SAMPLES.min(end - start);
+        (0..count).map(move |i| start + (2 * i + 1) * (end - start) / (2 * count))
+    };
+
+    for row in 0..rows {
+        let (y0, y1) = (row * height / rows, (row + 1) * height / rows);
+        for column in 0..columns {
+            let (x0, x1) = (column * width / columns, (column + 1) * width / columns);
+            let (mut sum, mut count) = (0u32, 0u32);
+            for y in spread(y0, y1) {
+                for x in spread(x0, x1) {
+                    let px = &rgba[y * stride + x * 4..];
+                    let weighted = 77 * px[0] as u32 + 150 * px[1] as u32 + 29 * px[2] as u32;
+                    sum += (weighted + 128) >> 8;
+                    count += 1;
+                }
+            }
+            out.push(((sum + count / 2) / count) as u8);
+        }
+    }
+    true
+}
+
+// ==================== Structured Append ====================
+
+/// Collects the parts of a structured-append sequence, possibly over many
+/// frames, and joins them once every symbol has been seen.
+/// Parts may arrive in any order; repeated parts are ignored.
+#[cfg_attr(all(feature = "wasm", target_arch = "wasm32"), wasm_bindgen)]
+pub struct StructuredAppendAssembler {
+    /// `(total, parity)` of the sequence, taken from the first part pushed
+    sequence: Option<(u8, u8)>,
+    parts: BTreeMap<u8, Vec<u8>>,
+}
+
+/// The joined payload of a complete structured-append sequence
+#[derive(Serialize, Clone)]
+pub struct AssembledPayload {
+    pub data: String, // Empty when the joined bytes are not valid UTF-8
+    #[serde(with = "serde_bytes")]
+    pub raw_bytes: Vec<u8>,
+    pub total: u8,
+    pub parity: u8,
+}
+
+#[cfg_attr(all(feature = "wasm", target_arch = "wasm32"), wasm_bindgen)]
+impl StructuredAppendAssembler {
+    #[cfg_attr(all(feature = "wasm", target_arch = "wasm32"), wasm_bindgen(constructor))]
+    pub fn new() -> StructuredAppendAssembler {
+        StructuredAppendAssembler {
+            sequence: None,
+            parts: BTreeMap::new(),
+        }
+    }
+
+    /// Drop every collected part and forget the sequence
+    pub fn reset(&mut self) {
+        self.sequence = None;
+        self.parts.clear();
+    }
+
+    #[cfg_attr(all(feature = "wasm", target_arch = "wasm32"), wasm_bindgen(getter))]
+    pub fn part_count(&self) -> u32 {
+        self.parts.len() as u32
+    }
+
+    /// Number of symbols in the sequence, unknown until the first push
+    #[cfg_attr(all(feature = "wasm", target_arch = "wasm32"), wasm_bindgen(getter))]
+    pub fn total(&self) -> Option<u32> {
+        self.sequence.map(|(total, _)| total as u32)
+    }
+}
+
+impl Default for StructuredAppendAssembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StructuredAppendAssembler {
+    /// Add a decoded result; returns true once every part is present.
+    /// Results without a structured-append header, or from another sequence
+    /// than the first part pushed, are rejected with `INVALID_PAYLOAD`.
+    pub fn push_result(&mut self, result: &QRCodeResult) -> Result<bool, VeloQrError> {
+        let header = result.structured_append.ok_or_else(|| {
+            VeloQrError::InvalidPayload("QR code has no structured append header".to_string())
+        })?;
+        if header.index >= header.total {
+            return Err(VeloQrError::InvalidPayload(format!(
+                "structured append part {} of {}",
+                header.index + 1,
+                header.total
+            )));
+        }
+        let sequence = *self.sequence.get_or_insert((header.total, header.parity));
+        if sequence != (header.total, header.parity) {
+            return Err(VeloQrError::InvalidPayload(
+                "QR code belongs to a different structured append sequence".to_string(),
+            ));
+        }
+
+        self.parts
+            .entry(header.index)
+            .or_insert_with(|| result.raw_bytes.clone());
+        Ok(self.parts.len() == sequence.0 as usize)
+    }
+
+    /// The joined payload, or `None` while parts are missing.
+    /// A payload whose XOR does not match the parity byte is an `INVALID_PAYLOAD` error.
+    pub fn assemble(&self) -> Result<Option<AssembledPayload>, VeloQrError> {
+        let Some((total, parity)) = self.sequence else {
+            return Ok(None);
+        };
+        if self.parts.len() != total as usize {
+            return Ok(None);
+        }
+
+        let raw_bytes: Vec<u8> = self.parts.values().flatten().copied().collect();
+        if raw_bytes.iter().fold(0, |acc, byte| acc ^ byte) != parity {
+            return Err(VeloQrError::InvalidPayload(
+                "structured append parity check failed".to_string(),
+            ));
+        }
+        Ok(Some(AssembledPayload {
+            data: String::from_utf8(raw_bytes.clone()).unwrap_or_default(),
+            raw_bytes,
+            total,
+            parity,
+        }))
+    }
+}
+
+// ==================== Payload Parsing ====================
+
+/// Classify a decoded QR payload (WiFi, vCard/MeCard, vEvent, otpauth, URL, ...)
+pub fn classify_payload(data: &str) -> QrPayload {
+    payload::classify(data)
+}
+
+/// Build a `WIFI:` network payload, see `payload::encode_wifi`
+pub fn encode_wifi_payload(
+    ssid: &str,
+    password: &str,
+    security: &str,
+    hidden: bool,
+) -> Result<String, VeloQrError> {
+    payload::encode_wifi(ssid, password, security, hidden)
+}
+
+/// Advisory safety flags of a URL of any scheme, see `UrlSafety`
+pub fn classify_url_safety(data: &str) -> UrlSafety {
+    url_safety::classify(data)
+}
+
+/// Parse an `otpauth://` provisioning URI, see `OtpauthUri`
+pub fn parse_otpauth(uri: &str) -> Result<OtpauthUri, VeloQrError> {
+    otpauth::parse(uri)
+}
+
+/// Build an `otpauth://` provisioning URI, the reverse of `parse_otpauth`
+pub fn build_otpauth(params: &OtpauthParams) -> Result<String, VeloQrError> {
+    otpauth::build(params)
+}
+
+/// Parse an EMVCo merchant-presented payment QR (VietQR, PromptPay, SGQR, ...)
+pub fn parse_emvco(data: &str) -> Result<EmvcoQr, VeloQrError> {
+    emvco::parse(data)
+}
+
+/// Parse a SEPA credit transfer QR (EPC069-12), breaches of the guideline
+/// listed in `violations`
+pub fn parse_epc(data: &str) -> Result<EpcPayment, VeloQrError> {
+    epc::parse(data)
+}
+
+/// Parse GS1 element strings; two-digit years resolve against today
+pub fn parse_gs1(data: &str) -> Result<Gs1Data, VeloQrError> {
+    gs1::parse(data, CivilDate::today())
+}
+
+/// Parse the AAMVA DL/ID payload of a North American driver license PDF417
+pub fn parse_aamva(data: &[u8]) -> Result<AamvaData, VeloQrError> {
+    aamva::parse(data)
+}
+
+/// Parse an IATA boarding pass (BCBP); flight days resolve to full dates
+/// against the issue date or today
+pub fn parse_iata_bcbp(text: &str) -> Result<BcbpBoardingPass, VeloQrError> {
+    bcbp::parse(text, CivilDate::today())
+}
+
+/// Parse an ICAO Visible Digital Seal from the bytes of its barcode
+pub fn parse_vds(data: &[u8]) -> Result<VdsSeal, VeloQrError> {
+    vds::parse(data)
+}
+
+/// Decode a raw seal feature as `c40`, `date` or `masked_date`, for
+/// profiles `parse_vds` returns only as bytes
+pub fn decode_vds_value(bytes: &[u8], encoding: &str) -> Result<String, VeloQrError> {
+    vds::decode_value(bytes, encoding)
+}
+
+// ==================== QR Generation ====================
+
+/// Module matrix of an encoded QR symbol, without quiet zone
+#[derive(Serialize, Clone)]
+pub struct QrMatrix {
+    pub size: u32, // Modules per side
+    pub version: u32,
+    #[serde(with = "serde_bytes")]
+    pub modules: Vec<u8>, // Row-major, 1 = dark
+}
+
+pub fn encode_matrix(data: &str, ecc: &str) -> Result<QrMatrix, VeloQrError> {
+    let ecc_level = parse_ecc_level(ecc)?;
+    let code = qrcode::QrCode::with_error_correction_level(data, ecc_level).map_err(|e| match e {
+        qrcode::types::QrError::DataTooLong => VeloQrError::PayloadTooLong(format!(
+            "{} bytes do not fit a version 40 QR code at ECC level {}",
+            data.len(),
+            ecc.to_ascii_uppercase()
+        )),
+        other => VeloQrError::InvalidPayload(other.to_string()),
+    })?;
+
+    let version = match code.version() {
+        qrcode::Version::Normal(v) | qrcode::Version::Micro(v) => v as u32,
+    };
+    Ok(QrMatrix {
+        size: code.width() as u32,
+        version,
+        modules: code
+            .to_colors()
+            .into_iter()
+            .map(|color| (color == qrcode::Color::Dark) as u8)
+            .collect(),
+    })
+}
+
+/// Encode `data` as a grayscale PNG, `scale` pixels per module with a quiet
+/// zone of `margin` modules
+pub fn encode_png(data: &str, ecc: &str, scale: u32, margin: u32) -> Result<Vec<u8>, VeloQrError> {
+    let matrix = encode_matrix(data, ecc)?;
+    let image = render_matrix(&matrix, scale, margin)?;
+
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+        .map_err(|e| VeloQrError::SerializationFailed(e.to_string()))?;
+    Ok(png)
+}
+
+/// Encode `data` as an SVG document with a `margin`-module quiet zone
+pub fn encode_svg(
+    data: &str,
+    ecc: &str,
+    margin: u32,
+    dark: &str,
+    light: &str,
+) -> Result<String, VeloQrError> {
+    let matrix = encode_matrix(data, ecc)?;
+    render_svg(&matrix, margin, dark, light)
+}
+
+/// Draw a matrix black on white with a quiet zone
+fn render_matrix(matrix: &QrMatrix, scale: u32, margin: u32) -> Result<GrayImage, VeloQrError> {
+    if scale == 0 {
+        return Err(VeloQrError::InvalidOptions("scale must be at least 1".to_string()));
+    }
+    let side = (matrix.size as u64 + 2 * margin as u64).saturating_mul(scale as u64);
+    if side > MAX_ENCODED_DIMENSION as u64 {
+        return Err(VeloQrError::ImageTooLarge(format!(
+            "{}x{} exceeds the {} pixel limit",
+            side, side, MAX_ENCODED_DIMENSION
+        )));
+    }
+
+    let side = side as u32;
+    Ok(GrayImage::from_fn(side, side, |x, y| {
+        let (mx, my) = (x / scale, y / scale);
+        let inside = (margin..margin + matrix.size).contains(&mx)
+            && (margin..margin + matrix.size).contains(&my);
+        let dark = inside
+            && matrix.modules[((my - margin) * matrix.size + mx - margin) as usize] == 1;
+        image::Luma([if dark { 0 } else { 255 }])
+    }))
+}
+
+fn render_svg(
+    matrix: &QrMatrix,
+    margin: u32,
+    dark: &str,
+    light: &str,
+) -> Result<String, VeloQrError> {
+    let dark = svg_color(dark)?;
+    let light = svg_color(light)?;
+    let side = matrix.size as u64 + 2 * margin as u64;
+
+    // Each run is a closed subpath; `z` returns to its start, so the next
+    // run is a short relative move from there
+    let mut path = String::new();
+    let mut pen = (0i64, 0i64);
+    for (y, row) in matrix.modules.chunks(matrix.size as usize).enumerate() {
+        let mut x = 0;
+        while x < row.len() {
+            if row[x] == 0 {
+                x += 1;
+                continue;
+            }
+            let start = x;
+            while x < row.len() && row[x] == 1 {
+                x += 1;
+            }
+            let at = ((start as u64 + margin as u64) as i64, (y as u64 + margin as u64) as i64);
+            let run = x - start;
+            path.push_str(&format!("m{} {}h{}v1h-{}z", at.0 - pen.0, at.1 - pen.1, run, run));
+            pen = at;
+        }
+    }
+
+    Ok(format!(
+        concat!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {side} {side}\" ",
+            "shape-rendering=\"crispEdges\">",
+            "<rect width=\"{side}\" height=\"{side}\" fill=\"{light}\"/>",
+            "<path fill=\"{dark}\" d=\"{path}\"/></svg>"
+        ),
+        side = side,
+        light = light,
+        dark = dark,
+        path = path
+    ))
+}
+
+/// CSS color keywords accepted by `encode_qr_svg`, sorted
+const CSS_COLOR_KEYWORDS: &[&str] = &[
+    "aliceblue", "antiquewhite", "aqua", "aquamarine", "azure", "beige", "bisque", "black",
+    "blanchedalmond", "blue", "blueviolet", "brown", "burlywood", "cadetblue", "chartreuse",
+    "chocolate", "coral", "cornflowerblue", "cornsilk", "crimson", "cyan", "darkblue", "darkcyan",
+    "darkgoldenrod", "darkgray", "darkgreen", "darkgrey", "darkkhaki", "darkmagenta",
+    "darkolivegreen", "darkorange", "darkorchid", "darkred", "darksalmon", "darkseagreen",
+    "darkslateblue", "darkslategray", "darkslategrey", "darkturquoise", "darkviolet", "deeppink",
+    "deepskyblue", "dimgray", "dimgrey", "dodgerblue", "firebrick", "floralwhite", "forestgreen",
+    "fuchsia", "gainsboro", "ghostwhite", "gold", "goldenrod", "gray", "green", "greenyellow",
+    "grey", "honeydew", "hotpink", "indianred", "indigo", "ivory", "khaki", "lavender",
+    "lavenderblush", "lawngreen", "lemonchiffon", "lightblue", "lightcoral", "lightcyan",
+    "lightgoldenrodyellow", "lightgray", "lightgreen", "lightgrey", "lightpink", "lightsalmon",
+    "lightseagreen", "lightskyblue", "lightslategray", "lightslategrey", "lightsteelblue",
+    "lightyellow", "lime", "limegreen", "linen", "magenta", "maroon", "mediumaquamarine",
+    "mediumblue", "mediumorchid", "mediumpurple", "mediumseagreen", "mediumslateblue",
+    "mediumspringgreen", "mediumturquoise", "mediumvioletred", "midnightblue", "mintcream",
+    "mistyrose", "moccasin", "navajowhite", "navy", "oldlace", "olive", "olivedrab", "orange",
+    "orangered", "orchid", "palegoldenrod", "palegreen", "paleturquoise", "palevioletred",
+    "papayawhip", "peachpuff", "peru", "pink", "plum", "powderblue", "purple", "rebeccapurple",
+    "red", "rosybrown", "royalblue", "saddlebrown", "salmon", "sandybrown", "seagreen", "seashell",
+    "sienna", "silver", "skyblue", "slateblue", "slategray", "slategrey", "snow", "springgreen",
+    "steelblue", "tan", "teal", "thistle", "tomato", "transparent", "turquoise", "violet", "wheat",
+    "white", "whitesmoke", "yellow", "yellowgreen",
+];
+
+/// Validate an SVG fill color, returning it lowercased
+fn svg_color(color: &str) -> Result<String, VeloQrError> {
+    let color = color.trim().to_ascii_lowercase();
+    let valid = match color.strip_prefix('#') {
+        Some(hex) => {
+            matches!(hex.len(), 3 | 4 | 6 | 8) && hex.bytes().all(|b| b.is_ascii_hexdigit())
+        }
+        None => CSS_COLOR_KEYWORDS.binary_search(&color.as_str()).is_ok(),
+    };
+    if valid {
+        Ok(color)
+    } else {
+        Err(VeloQrError::InvalidOptions(format!("invalid color {:?}", color)))
+    }
+}
+
+fn parse_ecc_level(ecc: &str) -> Result<qrcode::EcLevel, VeloQrError> {
+    match ecc.to_ascii_uppercase().as_str() {
+        "L" => Ok(qrcode::EcLevel::L),
+        "M" => Ok(qrcode::EcLevel::M),
+        "Q" => Ok(qrcode::EcLevel::Q),
+        "H" => Ok(qrcode::EcLevel::H),
+        _ => Err(VeloQrError::InvalidOptions(format!(
+            "unknown ECC level {:?}, expected L, M, Q or H",
+            ecc
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `rows` rows of `width` pixels with a varying color and alpha, each
+    /// followed by `padding` bytes
+    fn gradient(width: usize, rows: usize, padding: usize) -> Vec<u8> {
+        let mut rgba = Vec::new();
+        for y in 0..rows {
+            for x in 0..width {
+                rgba.extend([(x * 7) as u8, (y * 3) as u8, (x + y) as u8, (x * y) as u8]);
+            }
+            rgba.extend(std::iter::repeat_n(0xAA, padding));
+        }
+        rgba
+    }
+
+    #[test]
+    fn padded_rows_convert_like_packed_rows() {
+        let (width, rows) = (37, 150);
+        let packed = rgba_to_gray(&gradient(width, rows, 0), width as u32, rows as u32).unwrap();
+        let padded = gradient(width, rows, 12);
+        let stride = Some(width as u32 * 4 + 12);
+        let alpha = AlphaOptions::default();
+        let (gray, histogram) =
+            rgba_to_gray_with_histogram(&padded, width as u32, rows as u32, stride, &alpha)
+                .unwrap();
+        assert_eq!(gray, packed);
+        for (luma, &count) in histogram.counts.iter().enumerate() {
+            let expected = packed.pixels().filter(|p| p.0[0] as usize == luma).count();
+            assert_eq!(count, expected as u64, "luma {}", luma);
+        }
+    }
+
+    #[test]
+    fn row_padding_does_not_count_toward_the_pixel_limit() {
+        // 16.8 MP, under the default limit, in 67.5 MB of padded rows
+        let (width, height) = (4200, 4000);
+        let stride = width * 4 + 64;
+        let rgba = vec![0x80; stride as usize * height as usize];
+        let alpha = AlphaOptions::default();
+        let mut luma = Vec::new();
+        rgba_to_luma_with_stride(&rgba, width, height, Some(stride), &alpha, &mut luma, None)
+            .unwrap();
+        assert_eq!(luma.len(), width as usize * height as usize);
+    }
+
+    #[test]
+    fn pixel_limit_errors_name_the_whole_frame() {
+        let alpha = AlphaOptions::default();
+        let mut luma = Vec::new();
+        let width = 100_000_000;
+        for stride in [None, Some(width * 4 + 64)] {
+            let result = rgba_to_luma_with_stride(&[], width, 3, stride, &alpha, &mut luma, None);
+            let error = result.unwrap_err();
+            assert_eq!(error.code(), "IMAGE_TOO_LARGE");
+            assert!(error.to_string().contains(" 100000000x3 exceeds"), "{}", error);
+        }
+        // No pixels at all, however wide the rows
+        rgba_to_luma_with_stride(&[], width, 0, None, &alpha, &mut luma, None).unwrap();
+        assert!(luma.is_empty());
+    }
+
+    #[test]
+    fn buffer_lengths_stop_at_the_pixel_limit() {
+        // 8192 x 8192 is exactly the default limit
+        assert_eq!(image_buffer_len(8192, 8192, 4).unwrap(), DEFAULT_MAX_PIXELS as usize * 4);
+        assert_eq!(image_buffer_len(8192, 8193, 1).unwrap_err().code(), "IMAGE_TOO_LARGE");
+        // u32::MAX squared overflows a u32, and its byte length a 32-bit usize;
+        // the limit rejects it before anything wraps
+        let error = image_buffer_len(u32::MAX, u32::MAX, 4).unwrap_err();
+        assert_eq!(error.code(), "IMAGE_TOO_LARGE");
+        assert_eq!(image_buffer_len(65536, 65536, 1).unwrap_err().code(), "IMAGE_TOO_LARGE");
+        assert_eq!(image_buffer_len(0, u32::MAX, 4).unwrap(), 0);
+        assert_eq!(image_buffer_len(u32::MAX, 0, 4).unwrap(), 0);
+
+        assert_eq!(strided_len(u32::MAX, 1).unwrap(), u32::MAX as usize);
+        #[cfg(target_pointer_width = "32")]
+        assert_eq!(strided_len(u32::MAX, 2).unwrap_err().code(), "DIMENSION_OVERFLOW");
+        #[cfg(target_pointer_width = "64")]
+        assert_eq!(strided_len(u32::MAX, u32::MAX).unwrap(), u32::MAX as usize * u32::MAX as usize);
+    }
+
+    /// A `data` QR code at `scale` pixels per module with a `margin` module
+    /// quiet zone
+    fn code_image(data: &str, scale: u32, margin: u32) -> GrayImage {
+        render_matrix(&encode_matrix(data, "M").unwrap(), scale, margin).unwrap()
+    }
+
+    #[test]
+    fn padded_y_planes_drop_the_row_padding() {
+        let code = code_image("NV12 frame", 4, 4);
+        let (width, height) = code.dimensions();
+        let stride = width + 24;
+        let mut plane = Vec::new();
+        for row in code.rows() {
+            plane.extend(row.map(|pixel| pixel.0[0]));
+            plane.extend([0x10; 24]);
+        }
+        let gray = y_plane_to_gray(&plane, width, height, stride).unwrap();
+        assert_eq!(gray, code);
+        assert_eq!(decode_image(gray)[0].data, "NV12 frame");
+
+        let error = y_plane_to_gray(&plane[..plane.len() - 1], width, height, stride).unwrap_err();
+        assert_eq!(error.code(), "INVALID_BUFFER_LENGTH");
+        let error = y_plane_to_gray(&plane, width, height, width - 1).unwrap_err();
+        assert_eq!(error.code(), "INVALID_STRIDE");
+
+        // Padding past the pixel limit is fine as long as the pixels are not
+        let (width, height, stride) = (100, 1000, 70_000);
+        let plane = vec![0x80; stride as usize * height as usize];
+        let gray = y_plane_to_gray(&plane, width, height, stride).unwrap();
+        assert_eq!(gray.dimensions(), (width, height));
+    }
+
+    #[test]
+    fn encoded_pngs_and_jpegs_decode() {
+        let png = encode_png("Encoded photo", "M", 4, 4).unwrap();
+        let gray = encoded_to_gray(&png).unwrap();
+        assert_eq!(decode_image(gray)[0].data, "Encoded photo");
+
+        let mut jpeg = Vec::new();
+        let encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, 85);
+        code_image("Encoded photo", 4, 4).write_with_encoder(encoder).unwrap();
+        assert_eq!(&jpeg[..2], b"\xff\xd8");
+        let gray = encoded_to_gray(&jpeg).unwrap();
+        assert_eq!(decode_image(gray)[0].data, "Encoded photo");
+
+        let error = encoded_to_gray(b"GIF89a, or anything else").unwrap_err();
+        assert_eq!(error.code(), "UNSUPPORTED_IMAGE_FORMAT");
+        let error = encoded_to_gray(&png[..png.len() / 2]).unwrap_err();
+        assert_eq!(error.code(), "CORRUPT_IMAGE");
+    }
+
+    #[test]
+    fn transparent_png_backgrounds_composite_over_white() {
+        // Black modules on transparent black, as most editors export them
+        let code = code_image("transparent", 4, 4);
+        let rgba = image::RgbaImage::from_fn(code.width(), code.height(), |x, y| {
+            image::Rgba([0, 0, 0, if code.get_pixel(x, y)[0] < 128 { 255 } else { 0 }])
+        });
+        let mut png = Vec::new();
+        rgba.write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png).unwrap();
+
+        // Dropping alpha leaves a black square
+        let ignored = image::load_from_memory(&png).unwrap().into_luma8();
+        assert!(ignored.iter().all(|&v| v == 0));
+        assert!(decode_image(ignored).is_empty());
+        let gray = encoded_to_gray(&png).unwrap();
+        assert_eq!(gray, code);
+        assert_eq!(decode_image(gray)[0].data, "transparent");
+
+        // Nothing opaque is just the background
+        let (width, height) = code.dimensions();
+        let clear = [90, 90, 90, 0].repeat((width * height) as usize);
+        assert!(rgba_to_gray(&clear, width, height).unwrap().iter().all(|&v| v == 255));
+        let black = AlphaOptions { background: [0, 0, 0], premultiplied: false };
+        let gray = rgba_to_gray_with_alpha(&clear, width, height, None, &black).unwrap();
+        assert!(gray.iter().all(|&v| v == 0));
+
+        // Half-covered mid gray: 200 straight or 100 premultiplied both land
+        // halfway between 200 and the white background
+        let premultiplied = AlphaOptions { premultiplied: true, ..AlphaOptions::default() };
+        let straight = rgba_to_gray(&[200, 200, 200, 128], 1, 1).unwrap();
+        let multiplied =
+            rgba_to_gray_with_alpha(&[100, 100, 100, 128], 1, 1, None, &premultiplied).unwrap();
+        assert_eq!(straight.as_raw(), &[227]);
+        assert_eq!(multiplied.as_raw(), &[227]);
+    }
+
+    #[test]
+    fn partial_options_keep_the_other_defaults() {
+        let json = r#"{"max_results":2,"hint_thresholds":{"dark_luma":10},"alpha":{}}"#;
+        let options: DecodeOptions = serde_json::from_str(json).unwrap();
+        let defaults = DecodeOptions::default();
+        assert_eq!(options.max_results, Some(2));
+        let (hints, default_hints) = (options.hint_thresholds, defaults.hint_thresholds);
+        assert_eq!(hints.dark_luma, 10.0);
+        assert_eq!(hints.min_luma_range, default_hints.min_luma_range);
+        assert_eq!(options.alpha, defaults.alpha);
+        assert!(options.return_raw_bytes && !options.try_inverted);
+        assert_eq!(options.sort, ResultOrder::None);
+        assert_eq!(options.binarization, Binarization::Default);
+        assert_eq!((options.k, options.crop_size), (defaults.k, defaults.crop_size));
+        assert_eq!(options.max_grid_candidates, defaults.max_grid_candidates);
+
+        // Fields of other bundle versions are ignored, wrong types are not
+        let json = r#"{"return_raw_bytes":false,"from_a_newer_bundle":[1,2]}"#;
+        let options: DecodeOptions = serde_json::from_str(json).unwrap();
+        let results = decode_gray_with_options(code_image("options", 4, 4), &options).unwrap();
+        assert_eq!(results[0].data, "options");
+        assert!(results[0].raw_bytes.is_empty());
+        assert!(serde_json::from_str::<DecodeOptions>(r#"{"max_results":"two"}"#).is_err());
+    }
+
+    #[test]
+    fn inverted_codes_need_try_inverted() {
+        let mut gray = code_image("light on dark", 4, 4);
+        imageops::invert(&mut gray);
+        let plain = decode_gray_with_options(gray.clone(), &DecodeOptions::default()).unwrap();
+        assert!(plain.is_empty());
+
+        let options = DecodeOptions { try_inverted: true, ..DecodeOptions::default() };
+        let results = decode_gray_with_options(gray, &options).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].data, "light on dark");
+        assert!(results[0].inverted && !results[0].mirrored);
+    }
+
+    #[test]
+    fn mirrored_codes_report_bounds_in_the_given_frame() {
+        let code = code_image("selfie camera", 4, 4);
+        let side = code.width();
+        let mut gray = GrayImage::from_pixel(320, 240, image::Luma([255]));
+        imageops::overlay(&mut gray, &code, 30, 20);
+        imageops::flip_horizontal_in_place(&mut gray);
+        let plain = decode_gray_with_options(gray.clone(), &DecodeOptions::default()).unwrap();
+        assert!(plain.is_empty());
+
+        let options = DecodeOptions { try_mirrored: true, ..DecodeOptions::default() };
+        let results = decode_gray_with_options(gray, &options).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].data, "selfie camera");
+        assert!(results[0].mirrored);
+        // The flipped code spans x from 320 - 30 - side, inside its quiet zone
+        let (left, right) = ((320 - 30 - side) as f64, (320 - 30) as f64);
+        for &(x, y) in &results[0].bounds {
+            assert!((left..=right).contains(&x), "{:?}", results[0].bounds);
+            assert!((20.0..=20.0 + side as f64).contains(&y), "{:?}", results[0].bounds);
+        }
+        assert!((results[0].center.0 - (left + right) / 2.0).abs() < 2.0);
+    }
+
+    /// `gray` turned `degrees` clockwise about its center, nearest-neighbour
+    /// sampled, with white filling the corners
+    fn rotated(gray: &GrayImage, degrees: f64) -> GrayImage {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let (cx, cy) = (gray.width() as f64 / 2.0, gray.height() as f64 / 2.0);
+        GrayImage::from_fn(gray.width(), gray.height(), |x, y| {
+            let (dx, dy) = (x as f64 + 0.5 - cx, y as f64 + 0.5 - cy);
+            let (sx, sy) = (cos * dx + sin * dy + cx, -sin * dx + cos * dy + cy);
+            if sx < 0.0 || sy < 0.0 {
+                return image::Luma([255]);
+            }
+            *gray.get_pixel_checked(sx as u32, sy as u32).unwrap_or(&image::Luma([255]))
+        })
+    }
+
+    #[test]
+    fn angles_follow_the_rotation() {
+        let code = code_image("angle", 6, 4);
+        let mut gray = GrayImage::from_pixel(400, 400, image::Luma([255]));
+        let offset = (400 - code.width() as i64) / 2;
+        imageops::overlay(&mut gray, &code, offset, offset);
+        for (image, expected) in [
+            (gray.clone(), 0.0),
+            (rotated(&gray, 45.0), 45.0),
+            (imageops::rotate90(&gray), 90.0),
+        ] {
+            let results = decode_image(image);
+            assert_eq!(results.len(), 1, "{expected}");
+            let angle = results[0].angle_degrees;
+            assert!((angle - expected).abs() <= 2.0, "{angle} for {expected}");
+        }
+    }
+
+    #[test]
+    fn results_sort_before_they_truncate() {
+        // Large in a corner, small in the center, medium in between
+        let mut gray = GrayImage::from_pixel(480, 480, image::Luma([255]));
+        for (data, scale, offset) in [("LARGE", 6, 10), ("CENTER", 3, 196), ("MEDIUM", 4, 300)] {
+            imageops::overlay(&mut gray, &code_image(data, scale, 4), offset, offset);
+        }
+        let decode = |json: &str| {
+            let options: DecodeOptions = serde_json::from_str(json).unwrap();
+            let results = decode_gray_with_options(gray.clone(), &options).unwrap();
+            results.into_iter().map(|result| result.data).collect::<Vec<_>>()
+        };
+        assert_eq!(decode(r#"{"sort":"area_desc"}"#), ["LARGE", "MEDIUM", "CENTER"]);
+        assert_eq!(decode(r#"{"sort":"center_distance"}"#), ["CENTER", "MEDIUM", "LARGE"]);
+        assert_eq!(decode(r#"{"sort":"area_desc","max_results":2}"#), ["LARGE", "MEDIUM"]);
+        assert_eq!(decode(r#"{"sort":"center_distance","max_results":1}"#), ["CENTER"]);
+        assert_eq!(decode(r#"{"max_results":0}"#), Vec::<String>::new());
+    }
+
+    #[test]
+    fn cropped_codes_need_a_quiet_zone() {
+        // rqrr reads a code cut exactly at its modules, but not one cropped a
+        // few pixels into them as tight screenshot crops are
+        let code = code_image("no margin", 6, 0);
+        let cropped = imageops::crop_imm(&code, 4, 4, code.width() - 8, code.height() - 8);
+        let cropped = cropped.to_image();
+        let side = cropped.width() as f64;
+        assert!(decode_image(cropped.clone()).is_empty());
+
+        let options = DecodeOptions { add_quiet_zone: 16, ..DecodeOptions::default() };
+        let results = decode_gray_with_options(cropped.clone(), &options).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].data, "no margin");
+        // Back in the cropped image's coordinates, not the padded image's
+        let bounds = &results[0].bounds;
+        for &(x, y) in bounds {
+            let inside = |v: f64| (-8.0..=side + 8.0).contains(&v);
+            assert!(inside(x) && inside(y), "{:?}", bounds);
+        }
+        let (cx, cy) = results[0].center;
+        assert!((cx - side / 2.0).abs() < 4.0 && (cy - side / 2.0).abs() < 4.0, "{:?}", bounds);
+
+        let results = decode_gray_robust(&cropped, 0.0, &RobustOptions::default(), || 0.0);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].data, "no margin");
+        assert_eq!(results[0].strategy, "quiet_zone");
+    }
+
+    #[test]
+    fn nearest_upscaling_reads_a_100_px_code() {
+        // A version 3 code shrunk to 100 px: 2.7 px per module, with jagged edges
+        let data = "https://example.com/a/longer/tiny/url?q=1";
+        let code = code_image(data, 10, 4);
+        let small = imageops::resize(&code, 100, 100, imageops::FilterType::Nearest);
+        assert!(decode_image(small.clone()).is_empty());
+
+        let options = DecodeOptions { try_upscale: true, ..DecodeOptions::default() };
+        let results = decode_gray_with_options(small, &options).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].data, data);
+        // Bounds scaled back to the 100 px image
+        let inside = |v: f64| (0.0..=100.0).contains(&v);
+        assert!(results[0].bounds.iter().all(|&(x, y)| inside(x) && inside(y)));
+        let (cx, cy) = results[0].center;
+        assert!((cx - 50.0).abs() < 3.0 && (cy - 50.0).abs() < 3.0);
+    }
+
+    #[test]
+    fn sampled_module_grids_decode_again() {
+        let data = "module grid";
+        let mut gray = GrayImage::from_pixel(300, 300, image::Luma([255]));
+        imageops::overlay(&mut gray, &code_image(data, 6, 4), 60, 60);
+        let options = DecodeOptions { return_module_grid: true, ..DecodeOptions::default() };
+        let encoded = encode_matrix(data, "M").unwrap();
+        // Upright every module is right; turned, the corner rqrr estimates
+        // rather than finds may be off by a few, which error correction covers
+        for (image, max_wrong) in [(gray.clone(), 0), (rotated(&gray, 30.0), 8)] {
+            let results = decode_gray_with_options(image, &options).unwrap();
+            assert_eq!(results.len(), 1);
+            let grid = results[0].modules.clone().expect("module grid");
+            assert_eq!(grid.size, encoded.size);
+            let wrong = grid.bits.iter().zip(&encoded.modules).filter(|(a, b)| a != b).count();
+            assert!(wrong <= max_wrong, "{wrong} modules differ");
+
+            // Drawn again from the sampled bits alone, the code reads the same
+            let matrix = QrMatrix { size: grid.size, version: encoded.version, modules: grid.bits };
+            let redrawn = render_matrix(&matrix, 4, 4).unwrap();
+            assert_eq!(decode_image(redrawn)[0].data, data);
+        }
+        let plain = decode_gray_with_options(gray, &DecodeOptions::default()).unwrap();
+        assert!(plain[0].modules.is_none());
+    }
+
+    #[test]
+    fn adaptive_binarization_reads_an_illumination_gradient() {
+        // Light falls off from left to right, leaving white near the right edge
+        // only a few levels above black
+        let code = code_image("uneven light", 8, 4);
+        let width = code.width() as f64;
+        let shaded = GrayImage::from_fn(code.width(), code.height(), |x, y| {
+            let fade = 0.96 * x as f64 / width;
+            let v = code.get_pixel(x, y)[0] as f64;
+            image::Luma([(v * (1.0 - fade) + 20.0 * fade) as u8])
+        });
+        assert!(decode_image(shaded.clone()).is_empty());
+
+        let options =
+            DecodeOptions { binarization: Binarization::Adaptive, ..DecodeOptions::default() };
+        let results = decode_gray_with_options(shaded, &options).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].data, "uneven light");
+    }
+
+    #[test]
+    fn fixed_point_luma_is_within_one_of_the_float_weights() {
+        let levels: Vec<u8> = (0..=255).step_by(5).collect();
+        let mut rgba = Vec::new();
+        for &r in &levels {
+            for &g in &levels {
+                for &b in &levels {
+                    rgba.extend([r, g, b, 255]);
+                }
+            }
+        }
+        let width = (rgba.len() / 4) as u32;
+        let gray = rgba_to_gray(&rgba, width, 1).unwrap();
+        for (px, &luma) in rgba.chunks_exact(4).zip(gray.as_raw()) {
+            let float = 0.299 * px[0] as f64 + 0.587 * px[1] as f64 + 0.114 * px[2] as f64;
+            assert!((luma as f64 - float).abs() <= 1.0, "{px:?} gives {luma}, not {float}");
+        }
+        assert_eq!(rgba_to_gray(&[255, 255, 255, 255], 1, 1).unwrap().as_raw(), &[255]);
+    }
+
+    #[cfg(feature = "threads")]
+    #[test]
+    fn parallel_luma_matches_serial() {
+        let (width, rows) = (37, 3 * LUMA_BAND_ROWS + 5);
+        let blend = Blend::new(&AlphaOptions::default());
+        for padding in [0, 12] {
+            let rgba = gradient(width, rows, padding);
+            let (row, stride) = (width * 4, width * 4 + padding);
+            let (mut serial, mut serial_counts) = (Vec::new(), LumaHistogram::default());
+            extend_luma_serial(&rgba, row, stride, &mut serial, blend, Some(&mut serial_counts));
+            let (mut parallel, mut counts) = (Vec::new(), LumaHistogram::default());
+            extend_luma_rows(&rgba, row, stride, &mut parallel, blend, Some(&mut counts));
+            assert_eq!(parallel, serial);
+            assert_eq!(counts.counts, serial_counts.counts);
+        }
+    }
+
+    #[test]
+    fn encoded_matrices_decode_back() {
+        let long = "VeloQR ".repeat(40);
+        let samples = ["HELLO WORLD", "https://example.com/?q=1", "héllo ✓", long.as_str()];
+        for data in samples {
+            for ecc in ["L", "m", "Q", "H"] {
+                let matrix = encode_matrix(data, ecc).unwrap();
+                assert_eq!(matrix.size, 17 + 4 * matrix.version);
+                assert_eq!(matrix.modules.len(), (matrix.size * matrix.size) as usize);
+                assert!(matrix.modules.iter().all(|&module| module <= 1));
+
+                let results = decode_image(render_matrix(&matrix, 4, 4).unwrap());
+                assert_eq!(results.len(), 1, "{:?} at {}", data, ecc);
+                assert_eq!(results[0].data, data);
+                assert_eq!(results[0].version, matrix.version as i32);
+                assert_eq!(results[0].ecc_level, ecc.to_ascii_uppercase());
+            }
+        }
+    }
+
+    #[test]
+    fn encode_matrix_rejects_bad_input() {
+        let too_long = "x".repeat(3000);
+        let error = encode_matrix(&too_long, "H").err().unwrap();
+        assert_eq!(error.code(), "PAYLOAD_TOO_LONG");
+        let error = encode_matrix("data", "X").err().unwrap();
+        assert_eq!(error.code(), "INVALID_OPTIONS");
+        // The largest byte payload of version 40-L still fits
+        assert_eq!(encode_matrix(&"x".repeat(2953), "L").unwrap().version, 40);
+    }
+
+    #[test]
+    fn encoded_pngs_decode_back() {
+        let matrix = encode_matrix("PNG round trip", "M").unwrap();
+        for (scale, margin) in [(1, 4), (3, 0), (5, 2)] {
+            let png = encode_png("PNG round trip", "M", scale, margin).unwrap();
+            assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+            let image = image::load_from_memory(&png).unwrap().to_luma8();
+            let side = (matrix.size + 2 * margin) * scale;
+            assert_eq!(image.dimensions(), (side, side));
+            // Light quiet zone, then the dark corner of the top left finder
+            assert_eq!(image.get_pixel(0, 0).0, [if margin > 0 { 255 } else { 0 }]);
+            let finder = margin * scale;
+            assert_eq!(image.get_pixel(finder, finder).0, [0]);
+            if scale > 1 {
+                assert_eq!(decode_image(image)[0].data, "PNG round trip");
+            }
+        }
+    }
+
+    #[test]
+    fn encode_png_rejects_bad_sizes() {
+        let error = encode_png("data", "M", 0, 4).err().unwrap();
+        assert_eq!(error.code(), "INVALID_OPTIONS");
+        let error = encode_png("data", "M", MAX_ENCODED_DIMENSION, 4).err().unwrap();
+        assert_eq!(error.code(), "IMAGE_TOO_LARGE");
+        let error = encode_png("data", "M", 2, u32::MAX).err().unwrap();
+        assert_eq!(error.code(), "IMAGE_TOO_LARGE");
+    }
+
+    /// Dark modules of an `encode_svg` path: relative moves to the start of
+    /// each run, then its length
+    fn svg_modules(path: &str, side: usize) -> Vec<u8> {
+        let mut modules = vec![0; side * side];
+        let mut pen = (0i64, 0i64);
+        for run in path.split('z').filter(|run| !run.is_empty()) {
+            let (moved, run) = run.strip_prefix('m').unwrap().split_once('h').unwrap();
+            let (dx, dy) = moved.split_once(' ').unwrap();
+            pen = (pen.0 + dx.parse::<i64>().unwrap(), pen.1 + dy.parse::<i64>().unwrap());
+            let (length, rest) = run.split_once('v').unwrap();
+            assert_eq!(rest, format!("1h-{}", length));
+            for x in pen.0..pen.0 + length.parse::<i64>().unwrap() {
+                modules[pen.1 as usize * side + x as usize] = 1;
+            }
+        }
+        modules
+    }
+
+    #[test]
+    fn svg_paths_draw_the_matrix() {
+        let matrix = encode_matrix("SVG round trip", "Q").unwrap();
+        let svg = encode_svg("SVG round trip", "Q", 2, "#1A2B3C", "White").unwrap();
+        let side = matrix.size + 4;
+        let header = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {side} {side}\" \
+             shape-rendering=\"crispEdges\"><rect width=\"{side}\" height=\"{side}\" \
+             fill=\"white\"/><path fill=\"#1a2b3c\" d=\"",
+            side = side
+        );
+        let path = svg.strip_prefix(&header).unwrap().strip_suffix("\"/></svg>").unwrap();
+
+        let drawn = svg_modules(path, side as usize);
+        for y in 0..side {
+            for x in 0..side {
+                let inside = (2..matrix.size + 2).contains(&x) && (2..matrix.size + 2).contains(&y);
+                let module = if inside {
+                    matrix.modules[((y - 2) * matrix.size + x - 2) as usize]
+                } else {
+                    0
+                };
+                assert_eq!(drawn[(y * side + x) as usize], module, "module ({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn svg_colors_are_validated() {
+        for color in ["#abc", "#ABCD", "#a1b2c3", "#a1b2c3d4", " Black ", "transparent"] {
+            assert!(svg_color(color).is_ok(), "{:?}", color);
+        }
+        assert_eq!(svg_color(" RebeccaPurple ").unwrap(), "rebeccapurple");
+        for color in ["#ab", "#abcde", "#ggg", "blak", "red;", "url(#x)", ""] {
+            let error = encode_svg("data", "M", 4, color, "white").err().unwrap();
+            assert_eq!(error.code(), "INVALID_OPTIONS", "{:?}", color);
+        }
+        assert!(CSS_COLOR_KEYWORDS.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn results_carry_the_payload_hash() {
+        let hash = "018d8c7e7fb94bed864a62f0f5332178be9f7a5883fc615d851bc37f7b3a11ab";
+        assert_eq!(hash_payload(b"HELLO VELOQR"), hash);
+        let png = encode_png("HELLO VELOQR", "M", 4, 4).unwrap();
+        let image = image::load_from_memory(&png).unwrap().to_luma8();
+        assert_eq!(decode_image(image)[0].payload_hash, hash);
+    }
+
+    /// A decoded QR code of `part` carrying a structured append header
+    fn part(part: &str, index: u8, total: u8, parity: u8) -> QRCodeResult {
+        let matrix = encode_matrix(part, "M").unwrap();
+        let mut result = decode_image(render_matrix(&matrix, 3, 4).unwrap()).remove(0);
+        result.structured_append = Some(StructuredAppend { index, total, parity });
+        result
+    }
+
+    #[test]
+    fn structured_append_parts_join_in_any_order() {
+        let parts = ["Hello, ", "structured ", "append!"];
+        let parity = parts.concat().bytes().fold(0, |parity, byte| parity ^ byte);
+        let mut assembler = StructuredAppendAssembler::new();
+        assert_eq!(assembler.total(), None);
+        assert!(assembler.assemble().unwrap().is_none());
+
+        assert!(!assembler.push_result(&part(parts[2], 2, 3, parity)).unwrap());
+        assert_eq!((assembler.total(), assembler.part_count()), (Some(3), 1));
+        assert!(!assembler.push_result(&part(parts[0], 0, 3, parity)).unwrap());
+        // A part seen again is ignored
+        assert!(!assembler.push_result(&part("other", 0, 3, parity)).unwrap());
+        assert_eq!(assembler.part_count(), 2);
+        assert!(assembler.assemble().unwrap().is_none());
+        assert!(assembler.push_result(&part(parts[1], 1, 3, parity)).unwrap());
+
+        let payload = assembler.assemble().unwrap().unwrap();
+        assert_eq!(payload.data, "Hello, structured append!");
+        assert_eq!(payload.raw_bytes, b"Hello, structured append!");
+        assert_eq!((payload.total, payload.parity), (3, parity));
+
+        assembler.reset();
+        assert_eq!((assembler.total(), assembler.part_count()), (None, 0));
+    }
+
+    #[test]
+    fn structured_append_rejects_foreign_parts() {
+        let mut assembler = StructuredAppendAssembler::default();
+        let mut plain = part("plain", 0, 2, 0);
+        plain.structured_append = None;
+        assert_eq!(assembler.push_result(&plain).unwrap_err().code(), "INVALID_PAYLOAD");
+        assert!(assembler.push_result(&part("past", 2, 2, 0)).is_err());
+        assert_eq!(assembler.total(), None);
+
+        assembler.push_result(&part("AB", 0, 2, 0x41)).unwrap();
+        assert!(assembler.push_result(&part("CD", 1, 3, 0x41)).is_err());
+        assert!(assembler.push_result(&part("CD", 1, 2, 0x42)).is_err());
+        assert_eq!(assembler.part_count(), 1);
+
+        // Complete, but the bytes do not XOR to the parity
+        assert!(assembler.push_result(&part("CD", 1, 2, 0x41)).unwrap());
+        let error = assembler.assemble().err().unwrap();
+        assert_eq!(error.code(), "INVALID_PAYLOAD");
+    }
+
+    /// A `width` x `height` white RGBA frame with each code drawn 3 pixels
+    /// per module, its top left corner at `(x, y)`
+    fn frame(width: u32, height: u32, codes: &[(&str, u32, u32)]) -> Vec<u8> {
+        let mut gray = GrayImage::from_pixel(width, height, image::Luma([255]));
+        for &(data, x, y) in codes {
+            let code = render_matrix(&encode_matrix(data, "M").unwrap(), 3, 0).unwrap();
+            imageops::overlay(&mut gray, &code, x as i64, y as i64);
+        }
+        gray.pixels().flat_map(|pixel| [pixel.0[0], pixel.0[0], pixel.0[0], 255]).collect()
+    }
+
+    fn payloads(results: &[QRCodeResult]) -> Vec<&str> {
+        let mut data: Vec<&str> = results.iter().map(|result| result.data.as_str()).collect();
+        data.sort();
+        data
+    }
+
+    #[test]
+    fn scan_dedup_reports_a_payload_once_per_ttl() {
+        let mut scanner = QrScanner::new(320, 240);
+        let one = frame(320, 240, &[("ONE", 20, 20)]);
+        let both = frame(320, 240, &[("ONE", 20, 20), ("TWO", 200, 120)]);
+        assert_eq!(payloads(scanner.scan_dedup(&one, 1000.0, 0.0, None, None).unwrap()), ["ONE"]);
+        assert!(scanner.scan_dedup(&one, 1000.0, 500.0, None, None).unwrap().is_empty());
+        // Only the new code of a frame is reported, then nothing
+        let found = scanner.scan_dedup(&both, 1000.0, 900.0, None, None).unwrap();
+        assert_eq!(payloads(found), ["TWO"]);
+        assert!(scanner.scan_dedup(&both, 1000.0, 950.0, None, None).unwrap().is_empty());
+        // Past its time to live, counted from when it was emitted
+        let found = scanner.scan_dedup(&both, 1000.0, 1000.0, None, None).unwrap();
+        assert_eq!(payloads(found), ["ONE"]);
+        // Plain scans neither filter nor record
+        assert_eq!(payloads(scanner.scan(&both, None, None).unwrap()), ["ONE", "TWO"]);
+
+        scanner.reset();
+        let found = scanner.scan_dedup(&both, 1000.0, 1001.0, None, None).unwrap();
+        assert_eq!(payloads(found), ["ONE", "TWO"]);
+    }
+
+    #[test]
+    fn scan_in_place_reads_the_input_buffer() {
+        let mut scanner = QrScanner::new(320, 240);
+        let buffer = scanner.input_buffer().unwrap();
+        assert_eq!(buffer.len(), 320 * 240 * 4);
+        buffer.copy_from_slice(&frame(320, 240, &[("IN PLACE", 40, 40)]));
+        let address = buffer.as_ptr();
+        assert_eq!(payloads(scanner.scan_in_place().unwrap()), ["IN PLACE"]);
+        assert_eq!(scanner.input_buffer().unwrap().as_ptr(), address);
+
+        // A smaller frame reuses the allocation
+        scanner.set_frame_size(200, 150).unwrap();
+        let buffer = scanner.input_buffer().unwrap();
+        assert_eq!((buffer.len(), buffer.as_ptr()), (200 * 150 * 4, address));
+        buffer.copy_from_slice(&frame(200, 150, &[("SMALLER", 10, 10)]));
+        assert_eq!(payloads(scanner.scan_in_place().unwrap()), ["SMALLER"]);
+        assert_eq!((scanner.width(), scanner.height()), (200, 150));
+
+        let buffer = scanner.input_buffer().unwrap();
+        buffer.fill(255);
+        assert!(scanner.scan_in_place().unwrap().is_empty());
+    }
+
+    #[test]
+    fn scanners_read_row_padded_frames() {
+        let packed = frame(200, 150, &[("PADDED", 30, 30)]);
+        let stride = 200 * 4 + 64;
+        let padded: Vec<u8> = packed
+            .chunks(200 * 4)
+            .flat_map(|row| row.iter().copied().chain(std::iter::repeat_n(0, 64)))
+            .collect();
+        let mut scanner = QrScanner::new(200, 150);
+        scanner.set_stride_bytes(Some(stride));
+        assert_eq!(payloads(scanner.scan(&padded, None, None).unwrap()), ["PADDED"]);
+        assert_eq!(scanner.input_buffer().unwrap().len(), stride as usize * 150);
+        scanner.input_buffer().unwrap().copy_from_slice(&padded);
+        assert_eq!(payloads(scanner.scan_in_place().unwrap()), ["PADDED"]);
+
+        // Packed frames are now too short, and a stride under a row invalid
+        assert!(scanner.scan(&packed, None, None).is_err());
+        scanner.set_stride_bytes(Some(200 * 4 - 4));
+        assert!(scanner.scan(&padded, None, None).is_err());
+        scanner.set_stride_bytes(None);
+        assert_eq!(payloads(scanner.scan(&packed, None, None).unwrap()), ["PADDED"]);
+    }
+
+    #[test]
+    fn scan_adaptive_skips_unchanged_empty_frames() {
+        let mut scanner = QrScanner::new(240, 180);
+        let blank = frame(240, 180, &[]);
+        let code = frame(240, 180, &[("ADAPTIVE", 60, 40)]);
+        assert!(scanner.scan_adaptive(&blank, None, None).unwrap().is_empty());
+        assert_eq!(scanner.last_frame_difference(), None);
+        assert!(scanner.scan_adaptive(&blank, None, None).unwrap().is_empty());
+        assert_eq!((scanner.last_frame_difference(), scanner.frames_skipped()), (Some(0.0), 1));
+
+        // A code changes the frame, and frames keep being scanned while it is found
+        assert_eq!(payloads(scanner.scan_adaptive(&code, None, None).unwrap()), ["ADAPTIVE"]);
+        assert!(scanner.last_frame_difference().unwrap() >= DEFAULT_SKIP_THRESHOLD);
+        assert_eq!(payloads(scanner.scan_adaptive(&code, None, None).unwrap()), ["ADAPTIVE"]);
+        assert_eq!(scanner.frames_skipped(), 1);
+
+        assert!(scanner.scan_adaptive(&blank, None, None).unwrap().is_empty());
+        assert!(!scanner.should_scan(&blank));
+        scanner.set_skip_threshold(0.0);
+        assert!(scanner.should_scan(&blank));
+        scanner.set_skip_threshold(-1.0);
+        assert_eq!(scanner.skip_threshold(), 0.0);
+
+        // Nothing to compare after a reset or with a new frame size
+        scanner.set_skip_threshold(DEFAULT_SKIP_THRESHOLD);
+        scanner.reset();
+        assert!(scanner.should_scan(&blank));
+        scanner.scan_adaptive(&blank, None, None).unwrap();
+        let smaller = frame(200, 150, &[]);
+        assert!(scanner.scan_adaptive(&smaller, Some(200), Some(150)).unwrap().is_empty());
+        assert_eq!((scanner.last_frame_difference(), scanner.frames_skipped()), (None, 1));
+    }
+
+    #[test]
+    fn thumbnails_average_the_frame() {
+        let mut thumbnail = Vec::new();
+        let gray = |level: u8| [level, level, level, 0].repeat(100 * 80);
+        assert!(luma_thumbnail(&gray(200), 100, 80, None, &mut thumbnail));
+        assert_eq!(thumbnail.len(), 64 * 64);
+        assert!(thumbnail.iter().all(|&luma| luma == 200));
+        assert!(luma_thumbnail(&gray(9)[..400 * 20], 100, 20, None, &mut thumbnail));
+        assert_eq!(thumbnail.len(), 64 * 20);
+        // Not a frame of that size
+        assert!(!luma_thumbnail(&gray(9), 100, 81, None, &mut thumbnail));
+        assert!(thumbnail.is_empty());
+        assert!(!luma_thumbnail(&[], 0, 0, None, &mut thumbnail));
+    }
+
+    #[test]
+    fn smoothed_bounds_follow_moving_codes() {
+        let mut scanner = QrScanner::new(240, 180);
+        let at = |x| frame(240, 180, &[("SMOOTH", x, 40)]);
+        let first = scanner.scan(&at(40), None, None).unwrap()[0].clone();
+        assert!(first.smoothed_bounds.is_empty() && first.track_id.is_none());
+
+        scanner.set_smoothing_alpha(0.5);
+        let first = scanner.scan(&at(40), None, None).unwrap()[0].clone();
+        assert_eq!(first.smoothed_bounds, first.bounds);
+        let moved = scanner.scan(&at(52), None, None).unwrap()[0].clone();
+        assert_eq!(moved.track_id, first.track_id);
+        let corners = moved.smoothed_bounds.iter().zip(&first.bounds).zip(&moved.bounds);
+        for ((smoothed, old), new) in corners {
+            assert!((smoothed.0 - (old.0 + new.0) / 2.0).abs() < 1e-9);
+            assert!((smoothed.1 - (old.1 + new.1) / 2.0).abs() < 1e-9);
+        }
+
+        scanner.set_smoothing_alpha(2.0);
+        assert_eq!(scanner.smoothing_alpha(), 1.0);
+        let followed = scanner.scan(&at(64), None, None).unwrap()[0].clone();
+        assert_eq!(followed.smoothed_bounds, followed.bounds);
+        scanner.set_smoothing_alpha(f64::NAN);
+        assert_eq!(scanner.smoothing_alpha(), 0.0);
+    }
+
+    fn codes(tracked: &[TrackedCode]) -> Vec<(&str, u32, u32)> {
+        tracked.iter().map(|code| (code.data.as_str(), code.track_id, code.frames_seen)).collect()
+    }
+
+    #[test]
+    fn scan_tracked_reports_appeared_updated_and_lost_codes() {
+        let mut scanner = QrScanner::new(320, 240);
+        scanner.set_max_missed_frames(1);
+        let (blank, one) = (frame(320, 240, &[]), frame(320, 240, &[("ONE", 20, 20)]));
+        let both = frame(320, 240, &[("ONE", 20, 20), ("TWO", 200, 120)]);
+
+        let events = scanner.scan_tracked(&one, None, None).unwrap();
+        assert_eq!(codes(&events.appeared), [("ONE", 1, 1)]);
+        assert!(events.updated.is_empty() && events.lost.is_empty());
+        let events = scanner.scan_tracked(&both, None, None).unwrap();
+        assert_eq!(codes(&events.appeared), [("TWO", 2, 1)]);
+        assert_eq!(codes(&events.updated), [("ONE", 1, 2)]);
+        assert_eq!(scanner.scan(&both, None, None).unwrap()[0].track_id, None);
+
+        // Missed once, then lost; seen again it is a new track
+        let events = scanner.scan_tracked(&one, None, None).unwrap();
+        assert_eq!(codes(&events.updated), [("ONE", 1, 3)]);
+        assert!(events.lost.is_empty());
+        let events = scanner.scan_tracked(&blank, None, None).unwrap();
+        assert_eq!(codes(&events.lost), [("TWO", 2, 1)]);
+        scanner.scan_tracked(&blank, None, None).unwrap();
+        let events = scanner.scan_tracked(&one, None, None).unwrap();
+        assert_eq!(codes(&events.appeared), [("ONE", 3, 1)]);
+        assert_eq!(events.appeared[0].bounds.len(), 4);
+    }
+
+    #[test]
+    fn tracks_are_bounded_and_dropped_on_resize() {
+        let mut scanner = QrScanner::new(320, 240);
+        scanner.set_max_tracks(1);
+        scanner.scan_tracked(&frame(320, 240, &[("ONE", 20, 20)]), None, None).unwrap();
+        // The least recently seen track makes way
+        let two = frame(320, 240, &[("TWO", 200, 120)]);
+        let events = scanner.scan_tracked(&two, None, None).unwrap();
+        assert_eq!(codes(&events.appeared), [("TWO", 2, 1)]);
+        assert_eq!(codes(&events.lost), [("ONE", 1, 1)]);
+
+        let smaller = frame(200, 150, &[]);
+        let events = scanner.scan_tracked(&smaller, Some(200), Some(150)).unwrap();
+        assert_eq!(codes(&events.lost), [("TWO", 2, 1)]);
+        let twice = frame(200, 150, &[("SAME", 5, 5), ("SAME", 110, 60)]);
+        let events = scanner.scan_tracked(&twice, None, None).unwrap();
+        assert_eq!(events.appeared.len(), 1);
+        scanner.set_max_tracks(0);
+        assert_eq!(scanner.max_tracks(), 1);
+
+        scanner.set_max_tracks(8);
+        let events = scanner.scan_tracked(&twice, None, None).unwrap();
+        assert_eq!((events.updated.len(), events.appeared.len()), (1, 1));
+        scanner.reset();
+        let events = scanner.scan_tracked(&twice, None, None).unwrap();
+        assert_eq!((events.appeared.len(), events.lost.len()), (2, 0));
+    }
+
+    #[test]
+    fn timed_decodes_match_plain_decodes() {
+        let rgba = frame(640, 480, &[("timed", 40, 40), ("decode", 400, 300)]);
+        let gray = rgba_to_gray(&rgba, 640, 480).unwrap();
+        for json in [
+            r#"{}"#,
+            r#"{"try_inverted":true,"max_results":1,"sort":"center_distance"}"#,
+            r#"{"tiling":{"tile_size":320,"overlap":120}}"#,
+            r#"{"try_rotations":true,"max_dimension":320,"retry_full_resolution":true}"#,
+        ] {
+            let options: DecodeOptions = serde_json::from_str(json).unwrap();
+            let plain = decode_gray_with_options(gray.clone(), &options).unwrap();
+            let timed = decode_rgba_timed(&rgba, 640, 480, None, &options).unwrap();
+            let json_of = |results: &[QRCodeResult]| serde_json::to_string(results).unwrap();
+            assert_eq!(json_of(&timed.results), json_of(&plain), "{}", json);
+            assert!(!plain.is_empty(), "{}", json);
+
+            let timings = timed.timings;
+            assert!(timings.grayscale_ms > 0.0 && timings.detect_ms > 0.0, "{}", json);
+            assert!(timings.decode_ms > 0.0, "{}", json);
+            let stages =
+                timings.grayscale_ms + timings.prepare_ms + timings.detect_ms + timings.decode_ms;
+            assert!(stages <= timings.total_ms, "{}", json);
+        }
+    }
+
+    #[test]
+    fn time_budgets_stop_decodes_on_the_given_clock() {
+        let rgba = frame(640, 480, &[("budget", 40, 40), ("spent", 400, 300)]);
+        let options = DecodeOptions { time_budget_ms: Some(50.0), ..DecodeOptions::default() };
+        // A clock that never moves never runs out
+        let decode = decode_rgba_with_clock(&rgba, 640, 480, None, &options, || 0.0).unwrap();
+        assert!(!decode.timed_out);
+        assert_eq!(payloads(&decode.results), ["budget", "spent"]);
+        assert_eq!(decode.timings.total_ms, 0.0);
+
+        // One that jumps a second per reading runs out after the first grid,
+        // which is always decoded
+        let time = std::cell::Cell::new(0.0);
+        let jumping = || {
+            time.set(time.get() + 1000.0);
+            time.get()
+        };
+        let decode = decode_rgba_with_clock(&rgba, 640, 480, None, &options, jumping).unwrap();
+        assert!(decode.timed_out);
+        assert_eq!(decode.results.len(), 1);
+
+        // Non-positive budgets do not limit the decode
+        let options = DecodeOptions { time_budget_ms: Some(0.0), ..options };
+        let decode = decode_rgba_with_clock(&rgba, 640, 480, None, &options, jumping).unwrap();
+        assert!(!decode.timed_out);
+        assert_eq!(payloads(&decode.results), ["budget", "spent"]);
+    }
+
+    #[test]
+    fn grid_caps_keep_the_most_plausible_candidates() {
+        let mut gray = GrayImage::from_pixel(640, 480, image::Luma([255]));
+        let big = render_matrix(&encode_matrix("BIG", "M").unwrap(), 6, 0).unwrap();
+        imageops::overlay(&mut gray, &big, 300, 200);
+        for (data, x, y) in [("small", 20, 20), ("other", 20, 300)] {
+            let code = render_matrix(&encode_matrix(data, "M").unwrap(), 3, 0).unwrap();
+            imageops::overlay(&mut gray, &code, x, y);
+        }
+        let rgba: Vec<u8> = gray.pixels().flat_map(|p| [p.0[0], p.0[0], p.0[0], 255]).collect();
+        let decode = |max_grid_candidates| {
+            let options = DecodeOptions { max_grid_candidates, ..DecodeOptions::default() };
+            decode_rgba_timed(&rgba, 640, 480, None, &options).unwrap()
+        };
+
+        let capped = decode(1);
+        assert_eq!(payloads(&capped.results), ["BIG"]);
+        assert_eq!(capped.skipped_candidates, 2);
+        let all = decode(0);
+        assert_eq!(payloads(&all.results), ["BIG", "other", "small"]);
+        assert_eq!(all.skipped_candidates, 0);
+        assert_eq!(decode(3).skipped_candidates, 0);
+    }
+}
@@ -0,0 +1,767 @@
+//! Bit-level QR decoding from a sampled module grid: format information,
+//! codeword extraction, Reed-Solomon correction and segment parsing.
+//! Every grid rqrr detects is decoded here first: rqrr rejects modes it
+//! doesn't know (FNC1 for GS1, structured append) and reports neither ECI
+//! designators nor corrections.
+
+use rqrr::BitGrid;
+
+use crate::qr::{self, StructuredAppend};
+
+/// Payload and metadata of a symbol decoded from its module grid
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecodedSymbol {
+    pub version: usize,
+    /// Raw format-information ECC bits, same mapping as rqrr's `MetaData`
+    pub ecc_level: u16,
+    pub mask: u16,
+    pub payload: Vec<u8>,
+    /// FNC1 in first position: the payload is a GS1 element string
+    pub gs1: bool,
+    pub structured_append: Option<StructuredAppend>,
+    /// Last ECI designator in the symbol, e.g. 20 for Shift-JIS
+    pub eci: Option<u32>,
+    /// Contains Kanji mode segments (written to `payload` as Shift-JIS)
+    pub kanji: bool,
+    /// Codewords fixed by Reed-Solomon correction, over all blocks
+    pub corrected_errors: usize,
+    /// `qr::correction_quality` of the blocks
+    pub quality: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StreamError {
+    InvalidGridSize,
+    FormatEcc,
+    DataEcc,
+    DataUnderflow,
+    UnknownMode(u8),
+}
+
+/// Error-correction codewords per block, indexed by [L, M, Q, H][version - 1]
+const ECC_CODEWORDS_PER_BLOCK: [[u8; 40]; 4] = [
+    [
+        7, 10, 15, 20, 26, 18, 20, 24, 30, 18, 20, 24, 26, 30, 22, 24, 28, 30, 28, 28, 28, 28, 30,
+        30, 26, 28, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30,
+    ],
+    [
+        10, 16, 26, 18, 24, 16, 18, 22, 22, 26, 30, 22, 22, 24, 24, 28, 28, 26, 26, 26, 26, 28, 28,
+        28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28,
+    ],
+    [
+        13, 22, 18, 26, 18, 24, 18, 22, 20, 24, 28, 26, 24, 20, 30, 24, 28, 28, 26, 30, 28, 30, 30,
+        30, 30, 28, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30,
+    ],
+    [
+        17, 28, 22, 16, 22, 28, 26, 26, 24, 28, 24, 28, 22, 24, 24, 30, 28, 28, 26, 28, 30, 24, 30,
+        30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30,
+    ],
+];
+
+/// Reed-Solomon blocks, indexed by [L, M, Q, H][version - 1]
+const ECC_BLOCKS: [[u8; 40]; 4] = [
+    [
+        1, 1, 1, 1, 1, 2, 2, 2, 2, 4, 4, 4, 4, 4, 6, 6, 6, 6, 7, 8, 8, 9, 9, 10, 12, 12, 12, 13,
+        14, 15, 16, 17, 18, 19, 19, 20, 21, 22, 24, 25,
+    ],
+    [
+        1, 1, 1, 2, 2, 4, 4, 4, 5, 5, 5, 8, 9, 9, 10, 10, 11, 13, 14, 16, 17, 17, 18, 20, 21, 23,
+        25, 26, 28, 29, 31, 33, 35, 37, 38, 40, 43, 45, 47, 49,
+    ],
+    [
+        1, 1, 2, 2, 4, 4, 6, 6, 8, 8, 8, 10, 12, 16, 12, 17, 16, 18, 21, 20, 23, 23, 25, 27, 29,
+        34, 34, 35, 38, 40, 43, 45, 48, 51, 53, 56, 59, 62, 65, 68,
+    ],
+    [
+        1, 1, 2, 4, 4, 4, 5, 6, 8, 8, 11, 11, 16, 16, 18, 16, 19, 21, 25, 25, 25, 34, 30, 32, 35,
+        37, 40, 42, 45, 48, 51, 54, 57, 60, 63, 66, 70, 74, 77, 81,
+    ],
+];
+
+pub fn decode(grid: &dyn BitGrid) -> Result<DecodedSymbol, StreamError> {
+    let size = grid.size();
+    if !(21..=177).contains(&size) || !(size - 17).is_multiple_of(4) {
+        return Err(StreamError::InvalidGridSize);
+    }
+    let version = (size - 17) / 4;
+
+    let (ecc_level, mask) = read_format(grid)?;
+    let codewords = read_codewords(grid, version, mask);
+    let (data, corrected_errors, quality) = correct_blocks(&codewords, version, ecc_level)?;
+    let segments = parse_segments(&data, version)?;
+
+    Ok(DecodedSymbol {
+        version,
+        ecc_level,
+        mask,
+        payload: segments.payload,
+        gs1: segments.gs1,
+        structured_append: segments.structured_append,
+        eci: segments.eci,
+        kanji: segments.kanji,
+        corrected_errors,
+        quality,
+    })
+}
+
+/// [L, M, Q, H] table index for the raw format ECC bits (M=00, L=01, H=10, Q=11)
+fn ecc_index(ecc_level: u16) -> usize {
+    match ecc_level {
+        1 => 0,
+        0 => 1,
+        3 => 2,
+        _ => 3,
+    }
+}
+
+/// Both copies of the 15 format bits, matched against every valid BCH codeword
+fn read_format(grid: &dyn BitGrid) -> Result<(u16, u16), StreamError> {
+    let size = grid.size();
+    // Positions as (x, y) for bit i, least significant first
+    let first = |i: usize| match i {
+        0..=5 => (8, i),
+        6 => (8, 7),
+        7 => (8, 8),
+        8 => (7, 8),
+        _ => (14 - i, 8),
+    };
+    let second = |i: usize| match i {
+        0..=7 => (size - 1 - i, 8),
+        _ => (8, size - 15 + i),
+    };
+
+    let mut best: Option<(u32, u16)> = None;
+    for position in [&first as &dyn Fn(usize) -> (usize, usize), &second] {
+        let bits = (0..15).fold(0u16, |bits, i| {
+            let (x, y) = position(i);
+            bits | ((grid.bit(y, x) as u16) << i)
+        });
+        for data in 0..32u16 {
+            let distance = (format_bits(data) ^ bits).count_ones();
+            if best.is_none_or(|(d, _)| distance < d) {
+                best = Some((distance, data));
+            }
+        }
+    }
+
+    match best {
+        Some((distance, data)) if distance <= 3 => Ok((data >> 3, data & 7)),
+        _ => Err(StreamError::FormatEcc),
+    }
+}
+
+/// BCH(15,5) codeword for 5 format data bits, XOR-masked as printed
+fn format_bits(data: u16) -> u16 {
+    let mut remainder = data;
+    for _ in 0..10 {
+        remainder = (remainder << 1) ^ ((remainder >> 9) * 0x537);
+    }
+    ((data << 10) | (remainder & 0x3FF)) ^ 0x5412
+}
+
+/// Centre coordinates of the alignment patterns on each axis
+fn alignment_positions(version: usize) -> Vec<usize> {
+    if version == 1 {
+        return Vec::new();
+    }
+    let count = version / 7 + 2;
+    let step = if version == 32 {
+        26
+    } else {
+        (version * 4 + count * 2 + 1) / (count * 2 - 2) * 2
+    };
+    let size = version * 4 + 17;
+    let mut positions: Vec<usize> = (0..count - 1).map(|i| size - 7 - i * step).collect();
+    positions.push(6);
+    positions.reverse();
+    positions
+}
+
+/// Modules not carrying data: finders, separators, timing, alignment,
+/// format and version information. Indexed `y * size + x`.
+fn function_modules(version: usize) -> Vec<bool> {
+    let size = version * 4 + 17;
+    let mut function = vec![false; size * size];
+    let mut mark = |x0: usize, y0: usize, width: usize, height: usize| {
+        for y in y0..y0 + height {
+            for x in x0..x0 + width {
+                function[y * size + x] = true;
+            }
+        }
+    };
+
+    // Finder patterns with separators and format information
+    mark(0, 0, 9, 9);
+    mark(size - 8, 0, 8, 9);
+    mark(0, size - 8, 9, 8);
+    // Timing patterns
+    mark(6, 0, 1, size);
+    mark(0, 6, size, 1);
+
+    let positions = alignment_positions(version);
+    let last = positions.len().saturating_sub(1);
+    for (i, &cy) in positions.iter().enumerate() {
+        for (j, &cx) in positions.iter().enumerate() {
+            let on_finder = (i == 0 && (j == 0 || j == last)) || (i == last && j == 0);
+            if !on_finder {
+                mark(cx - 2, cy - 2, 5, 5);
+            }
+        }
+    }
+
+    if version >= 7 {
+        mark(size - 11, 0, 3, 6);
+        mark(0, size - 11, 6, 3);
+    }
+    function
+}
+
+/// Number of data + ECC codewords in a symbol of `version`
+fn total_codewords(version: usize) -> usize {
+    let mut modules = (16 * version + 128) * version + 64;
+    if version >= 2 {
+        let count = version / 7 + 2;
+        modules -= (25 * count - 10) * count - 55;
+        if version >= 7 {
+            modules -= 36;
+        }
+    }
+    modules / 8
+}
+
+fn mask_bit(mask: u16, x: usize, y: usize) -> bool {
+    match mask {
+        0 => (x + y).is_multiple_of(2),
+        1 => y.is_multiple_of(2),
+        2 => x.is_multiple_of(3),
+        3 => (x + y).is_multiple_of(3),
+        4 => (x / 3 + y / 2).is_multiple_of(2),
+        5 => x * y % 2 + x * y % 3 == 0,
+        6 => (x * y % 2 + x * y % 3).is_multiple_of(2),
+        _ => ((x + y) % 2 + x * y % 3).is_multiple_of(2),
+    }
+}
+
+/// Read codewords in the zig-zag placement order, removing the mask
+fn read_codewords(grid: &dyn BitGrid, version: usize, mask: u16) -> Vec<u8> {
+    let size = version * 4 + 17;
+    let function = function_modules(version);
+    let mut codewords = vec![0u8; total_codewords(version)];
+    let mut bit = 0;
+
+    let mut right = size - 1;
+    while right >= 1 {
+        if right == 6 {
+            right = 5;
+        }
+        for vertical in 0..size {
+            for j in 0..2 {
+                let x = right - j;
+                let upward = (right + 1) & 2 == 0;
+                let y = if upward { size - 1 - vertical } else { vertical };
+                if function[y * size + x] || bit >= codewords.len() * 8 {
+                    continue;
+                }
+                if grid.bit(y, x) != mask_bit(mask, x, y) {
+                    codewords[bit / 8] |= 0x80 >> (bit % 8);
+                }
+                bit += 1;
+            }
+        }
+        if right < 2 {
+            break;
+        }
+        right -= 2;
+    }
+    codewords
+}
+
+/// De-interleave the blocks, correct each one and concatenate their data codewords
+fn correct_blocks(
+    codewords: &[u8],
+    version: usize,
+    ecc_level: u16,
+) -> Result<(Vec<u8>, usize, f64), StreamError> {
+    let level = ecc_index(ecc_level);
+    let ecc_length = ECC_CODEWORDS_PER_BLOCK[level][version - 1] as usize;
+    let block_count = ECC_BLOCKS[level][version - 1] as usize;
+    let short_length = codewords.len() / block_count;
+    let short_blocks = block_count - codewords.len() % block_count;
+
+    let mut blocks: Vec<Vec<u8>> = vec![Vec::with_capacity(short_length + 1); block_count];
+    let mut next = codewords.iter();
+    for i in 0..=short_length {
+        for (j, block) in blocks.iter_mut().enumerate() {
+            // Short blocks have no codeword at the last data position
+            if i != short_length - ecc_length || j >= short_blocks {
+                if let Some(&codeword) = next.next() {
+                    block.push(codeword);
+                }
+            }
+        }
+    }
+
+    let mut data = Vec::new();
+    let mut corrected = Vec::with_capacity(block_count);
+    for mut block in blocks {
+        corrected.push(rs_correct(&mut block, ecc_length).ok_or(StreamError::DataEcc)?);
+        data.extend_from_slice(&block[..block.len() - ecc_length]);
+    }
+    let quality = qr::correction_quality(corrected.iter().map(|&c| (c, ecc_length / 2)));
+    Ok((data, corrected.iter().sum(), quality))
+}
+
+// GF(256) with the QR polynomial x^8 + x^4 + x^3 + x^2 + 1
+struct Gf {
+    exp: [u8; 512],
+    log: [u8; 256],
+}
+
+impl Gf {
+    fn new() -> Self {
+        let mut exp = [0u8; 512];
+        let mut log = [0u8; 256];
+        let mut value: u16 = 1;
+        for (i, slot) in exp.iter_mut().take(255).enumerate() {
+            *slot = value as u8;
+            log[value as usize] = i as u8;
+            value <<= 1;
+            if value & 0x100 != 0 {
+                value ^= 0x11D;
+            }
+        }
+        for i in 255..512 {
+            exp[i] = exp[i - 255];
+        }
+        Gf { exp, log }
+    }
+
+    fn mul(&self, a: u8, b: u8) -> u8 {
+        if a == 0 || b == 0 {
+            0
+        } else {
+            self.exp[self.log[a as usize] as usize + self.log[b as usize] as usize]
+        }
+    }
+
+    fn div(&self, a: u8, b: u8) -> u8 {
+        if a == 0 {
+            0
+        } else {
+            self.exp[self.log[a as usize] as usize + 255 - self.log[b as usize] as usize]
+        }
+    }
+
+    fn pow(&self, power: usize) -> u8 {
+        self.exp[power % 255]
+    }
+
+    /// Evaluate a polynomial stored lowest degree first
+    fn eval(&self, poly: &[u8], x: u8) -> u8 {
+        poly.iter().rev().fold(0, |acc, &c| self.mul(acc, x) ^ c)
+    }
+}
+
+/// Correct a block in place (first codeword = highest degree); returns the
+/// number of corrected codewords, or `None` when there are too many errors
+fn rs_correct(block: &mut [u8], ecc_length: usize) -> Option<usize> {
+    let gf = Gf::new();
+    let n = block.len();
+
+    let syndromes: Vec<u8> = (0..ecc_length)
+        .map(|i| {
+            let x = gf.pow(i);
+            block.iter().fold(0, |acc, &c| gf.mul(acc, x) ^ c)
+        })
+        .collect();
+    if syndromes.iter().all(|&s| s == 0) {
+        return Some(0);
+    }
+
+    // Berlekamp-Massey for the error locator polynomial
+    let mut locator = vec![1u8];
+    let mut previous = vec![1u8];
+    let mut errors = 0;
+    let mut shift = 1;
+    let mut last_discrepancy = 1u8;
+    for step in 0..ecc_length {
+        let mut discrepancy = syndromes[step];
+        for i in 1..=errors.min(locator.len() - 1) {
+            discrepancy ^= gf.mul(locator[i], syndromes[step - i]);
+        }
+        if discrepancy == 0 {
+            shift += 1;
+            continue;
+        }
+        let scale = gf.div(discrepancy, last_discrepancy);
+        let mut updated = locator.clone();
+        if updated.len() < previous.len() + shift {
+            updated.resize(previous.len() + shift, 0);
+        }
+        for (i, &c) in previous.iter().enumerate() {
+            updated[i + shift] ^= gf.mul(scale, c);
+        }
+        if 2 * errors <= step {
+            errors = step + 1 - errors;
+            previous = std::mem::replace(&mut locator, updated);
+            last_discrepancy = discrepancy;
+            shift = 1;
+        } else {
+            locator = updated;
+            shift += 1;
+        }
+    }
+    while locator.len() > 1 && locator.last() == Some(&0) {
+        locator.pop();
+    }
+    if errors * 2 > ecc_length || locator.len() - 1 != errors {
+        return None;
+    }
+
+    // Error evaluator: syndromes * locator mod x^ecc_length
+    let mut evaluator = vec![0u8; ecc_length];
+    for (i, &s) in syndromes.iter().enumerate() {
+        for (j, &l) in locator.iter().enumerate() {
+            if i + j < ecc_length {
+                evaluator[i + j] ^= gf.mul(s, l);
+            }
+        }
+    }
+    // Formal derivative: odd-degree terms shifted down
+    let derivative: Vec<u8> = locator
+        .iter()
+        .enumerate()
+        .skip(1)
+        .map(|(i, &c)| if i % 2 == 1 { c } else { 0 })
+        .collect();
+
+    // Chien search and Forney's formula
+    let mut found = 0;
+    for (index, codeword) in block.iter_mut().enumerate() {
+        let locator_value = gf.pow(n - 1 - index);
+        let inverse = gf.div(1, locator_value);
+        if gf.eval(&locator, inverse) != 0 {
+            continue;
+        }
+        let denominator = gf.eval(&derivative, inverse);
+        if denominator == 0 {
+            return None;
+        }
+        let magnitude = gf.mul(locator_value, gf.div(gf.eval(&evaluator, inverse), denominator));
+        *codeword ^= magnitude;
+        found += 1;
+    }
+    (found == errors).then_some(errors)
+}
+
+/// MSB-first bit reader over the corrected data codewords
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl BitReader<'_> {
+    fn remaining(&self) -> usize {
+        self.data.len() * 8 - self.position
+    }
+
+    fn take(&mut self, bits: usize) -> Result<u32, StreamError> {
+        if bits > self.remaining() {
+            return Err(StreamError::DataUnderflow);
+        }
+        let mut value = 0;
+        for _ in 0..bits {
+            let byte = self.data[self.position / 8];
+            value = (value << 1) | ((byte >> (7 - self.position % 8)) & 1) as u32;
+            self.position += 1;
+        }
+        Ok(value)
+    }
+}
+
+/// Payload and the mode headers seen while parsing it
+struct Segments {
+    payload: Vec<u8>,
+    gs1: bool,
+    structured_append: Option<StructuredAppend>,
+    eci: Option<u32>,
+    kanji: bool,
+}
+
+const ALPHANUMERIC: &[u8; 45] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+
+/// Walk the mode segments, writing payload bytes the way rqrr does
+/// (numeric/alphanumeric as ASCII, byte mode verbatim, Kanji as Shift-JIS)
+fn parse_segments(data: &[u8], version: usize) -> Result<Segments, StreamError> {
+    let class = match version {
+        1..=9 => 0,
+        10..=26 => 1,
+        _ => 2,
+    };
+    let mut reader = BitReader { data, position: 0 };
+    let mut payload = Vec::new();
+    let mut gs1 = false;
+    let mut structured_append = None;
+    let mut eci = None;
+    let mut kanji = false;
+
+    while reader.remaining() >= 4 {
+        match reader.take(4)? {
+            0 => break,
+            1 => {
+                let mut count = reader.take([10, 12, 14][class])? as usize;
+                while count > 0 {
+                    let digits = count.min(3);
+                    let value = reader.take([0, 4, 7, 10][digits])?;
+                    let text = format!("{:0width$}", value, width = digits);
+                    payload.extend_from_slice(text.as_bytes());
+                    count -= digits;
+                }
+            }
+            2 => {
+                let count = reader.take([9, 11, 13][class])? as usize;
+                let mut text = Vec::with_capacity(count);
+                for _ in 0..count / 2 {
+                    let value = reader.take(11)? as usize;
+                    text.push(ALPHANUMERIC[(value / 45).min(44)]);
+                    text.push(ALPHANUMERIC[value % 45]);
+                }
+                if count % 2 == 1 {
+                    text.push(ALPHANUMERIC[(reader.take(6)? as usize).min(44)]);
+                }
+                if gs1 {
+                    payload.extend(gs1_alphanumeric(&text));
+                } else {
+                    payload.extend(text);
+                }
+            }
+            4 => {
+                let count = reader.take([8, 16, 16][class])?;
+                for _ in 0..count {
+                    payload.push(reader.take(8)? as u8);
+                }
+            }
+            8 => {
+                kanji = true;
+                let count = reader.take([8, 10, 12][class])?;
+                for _ in 0..count {
+                    let value = reader.take(13)?;
+                    let combined = ((value / 0xC0) << 8) | (value % 0xC0);
+                    let sjis = if combined + 0x8140 <= 0x9FFC {
+                        combined + 0x8140
+                    } else {
+                        combined + 0xC140
+                    };
+                    payload.extend_from_slice(&[(sjis >> 8) as u8, sjis as u8]);
+                }
+            }
+            7 => {
+                // ECI designator: 1-3 bytes, length given by the leading bits
+                let first = reader.take(8)?;
+                eci = Some(match first {
+                    0..=0x7F => first,
+                    0x80..=0xBF => ((first & 0x3F) << 8) | reader.take(8)?,
+                    _ => ((first & 0x1F) << 16) | reader.take(16)?,
+                });
+            }
+            3 => {
+                let header = reader.take(16)?;
+                structured_append = Some(StructuredAppend {
+                    index: (header >> 12) as u8,
+                    total: ((header >> 8) & 0xF) as u8 + 1,
+                    parity: header as u8,
+                });
+            }
+            5 => gs1 = true,
+            // FNC1 in second position carries an application indicator byte
+            9 => {
+                reader.take(8)?;
+            }
+            mode => return Err(StreamError::UnknownMode(mode as u8)),
+        }
+    }
+    Ok(Segments {
+        payload,
+        gs1,
+        structured_append,
+        eci,
+        kanji,
+    })
+}
+
+/// In FNC1 mode `%` encodes FNC1 (GS, 0x1D) and `%%` a literal `%`
+fn gs1_alphanumeric(text: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(text.len());
+    let mut i = 0;
+    while i < text.len() {
+        if text[i] == b'%' {
+            if text.get(i + 1) == Some(&b'%') {
+                out.push(b'%');
+                i += 2;
+                continue;
+            }
+            out.push(0x1D);
+        } else {
+            out.push(text[i]);
+        }
+        i += 1;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use qrcode::bits::Bits;
+    use qrcode::canvas::Canvas;
+    use qrcode::{Color, EcLevel, QrCode, Version};
+
+    struct Modules {
+        size: usize,
+        dark: Vec<bool>,
+    }
+
+    impl BitGrid for Modules {
+        fn size(&self) -> usize {
+            self.size
+        }
+
+        fn bit(&self, y: usize, x: usize) -> bool {
+            self.dark[y * self.size + x]
+        }
+    }
+
+    fn modules(colors: Vec<Color>) -> Modules {
+        let size = (colors.len() as f64).sqrt() as usize;
+        Modules { size, dark: colors.into_iter().map(|c| c == Color::Dark).collect() }
+    }
+
+    fn with_bits(build: impl FnOnce(&mut Bits)) -> DecodedSymbol {
+        let mut bits = Bits::new(Version::Normal(3));
+        build(&mut bits);
+        bits.push_terminator(EcLevel::M).unwrap();
+        let code = QrCode::with_bits(bits, EcLevel::M).unwrap();
+        decode(&modules(code.to_colors())).unwrap()
+    }
+
+    #[test]
+    fn decodes_every_version_and_level() {
+        let levels = [EcLevel::L, EcLevel::M, EcLevel::Q, EcLevel::H];
+        let mut seed = 12345u32;
+        for version in 1..=40 {
+            for (index, &level) in levels.iter().enumerate() {
+                let length = (version * version) as usize / 2 + 1;
+                let data: Vec<u8> = (0..length)
+                    .map(|_| {
+                        seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                        (seed >> 16) as u8
+                    })
+                    .collect();
+                let code = QrCode::with_version(&data, Version::Normal(version), level).unwrap();
+                let symbol = decode(&modules(code.to_colors())).unwrap();
+                assert_eq!(symbol.payload, data, "version {} {:?}", version, level);
+                assert_eq!(symbol.version, version as usize);
+                assert_eq!(ecc_index(symbol.ecc_level), index);
+                assert_eq!(symbol.corrected_errors, 0);
+                assert_eq!(symbol.quality, 1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn corrects_damaged_codewords() {
+        let data = b"https://example.com/ticket/0123456789";
+        let code = QrCode::with_version(data, Version::Normal(5), EcLevel::Q).unwrap();
+        let mut grid = modules(code.to_colors());
+        // A dark 3x3 blot touches at most a few codewords of each block
+        for y in 20..23 {
+            for x in 20..23 {
+                grid.dark[y * grid.size + x] = !grid.dark[y * grid.size + x];
+            }
+        }
+        let symbol = decode(&grid).unwrap();
+        assert_eq!(symbol.payload, data);
+        assert!(symbol.corrected_errors > 0);
+        assert!(symbol.quality < 1.0);
+
+        for y in 9..grid.size {
+            for x in 9..grid.size / 2 {
+                grid.dark[y * grid.size + x] = true;
+            }
+        }
+        assert_eq!(decode(&grid), Err(StreamError::DataEcc));
+        grid.size = 24;
+        assert_eq!(decode(&grid), Err(StreamError::InvalidGridSize));
+    }
+
+    #[test]
+    fn fnc1_marks_gs1_and_percent_escapes() {
+        let symbol = with_bits(|bits| {
+            bits.push_fnc1_first_position().unwrap();
+            bits.push_alphanumeric_data(b"0109506000134352172603311010ABC%21S1").unwrap();
+        });
+        assert!(symbol.gs1);
+        assert_eq!(symbol.payload, b"0109506000134352172603311010ABC\x1d21S1");
+        let symbol = with_bits(|bits| {
+            bits.push_fnc1_first_position().unwrap();
+            bits.push_alphanumeric_data(b"10A%%B").unwrap();
+        });
+        assert_eq!(symbol.payload, b"10A%B");
+    }
+
+    #[test]
+    fn reports_eci_and_kanji_segments() {
+        let symbol = with_bits(|bits| {
+            bits.push_eci_designator(20).unwrap();
+            bits.push_byte_data(b"\x93\xfa\x96\x7b").unwrap();
+        });
+        assert_eq!(symbol.eci, Some(20));
+        assert!(!symbol.kanji);
+        let symbol = with_bits(|bits| {
+            bits.push_eci_designator(899).unwrap();
+            bits.push_numeric_data(b"0123").unwrap();
+            bits.push_kanji_data(b"\x8a\xbf\x8e\x9a").unwrap();
+        });
+        assert_eq!(symbol.eci, Some(899));
+        assert!(symbol.kanji);
+        assert_eq!(symbol.payload, b"0123\x8a\xbf\x8e\x9a");
+    }
+
+    #[test]
+    fn reads_the_structured_append_header() {
+        // Mode 3, index 1 of 3, parity 0x5A, then "AB" in byte mode
+        let mut bits = Vec::new();
+        let mut push = |count: usize, value: u32| {
+            bits.extend((0..count).rev().map(|i| (value >> i) & 1 == 1));
+        };
+        let fields = [(4, 3), (4, 1), (4, 2), (8, 0x5A), (4, 4), (8, 2), (8, 65), (8, 66)];
+        for (count, value) in fields {
+            push(count, value);
+        }
+        push(4, 0);
+        let mut data: Vec<u8> = bits
+            .chunks(8)
+            .map(|byte| byte.iter().fold(0, |acc, &bit| (acc << 1) | bit as u8) << (8 - byte.len()))
+            .collect();
+        let pad = [0xEC, 0x11];
+        while data.len() < 44 {
+            data.push(pad[data.len() % 2]);
+        }
+        let (data, ecc) =
+            qrcode::ec::construct_codewords(&data, Version::Normal(3), EcLevel::M).unwrap();
+        let mut canvas = Canvas::new(Version::Normal(3), EcLevel::M);
+        canvas.draw_all_functional_patterns();
+        canvas.draw_data(&data, &ecc);
+        let symbol = decode(&modules(canvas.apply_best_mask().into_colors())).unwrap();
+        let header = StructuredAppend { index: 1, total: 3, parity: 0x5A };
+        assert_eq!(symbol.structured_append, Some(header));
+        assert_eq!(symbol.payload, b"AB");
+    }
+
+    #[test]
+    fn rejects_unknown_modes_and_short_data() {
+        assert_eq!(parse_segments(&[0xD0, 0x00], 1).err(), Some(StreamError::UnknownMode(13)));
+        // Byte mode announcing two bytes with none following
+        assert_eq!(parse_segments(&[0x40, 0x20], 1).err(), Some(StreamError::DataUnderflow));
+    }
+}
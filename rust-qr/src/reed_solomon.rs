@@ -0,0 +1,260 @@
+//! Reed-Solomon error correction over GF(2^m) for the symbologies rqrr does
+//! not cover. Codewords are given highest degree first (data, then check
+//! symbols) and the generator polynomial has the roots a^1 .. a^n, or
+//! a^0 .. a^(n-1) for the QR family.
+
+/// Log/antilog tables of GF(2^m) for one primitive polynomial
+pub(crate) struct GaloisField {
+    exp: Vec<u16>,
+    log: Vec<u16>,
+}
+
+impl GaloisField {
+    /// `primitive` includes the x^m term, e.g. 0x12D for GF(256)
+    pub(crate) fn new(primitive: u32, size: usize) -> GaloisField {
+        // Two periods so sums of two logs index it without a modulo
+        let mut exp = vec![0u16; (size - 1) * 2];
+        let mut log = vec![0u16; size];
+        let mut x = 1u32;
+        for (i, value) in exp.iter_mut().take(size - 1).enumerate() {
+            *value = x as u16;
+            log[x as usize] = i as u16;
+            x <<= 1;
+            if x as usize >= size {
+                x ^= primitive;
+            }
+        }
+        exp.copy_within(0..size - 1, size - 1);
+        GaloisField { exp, log }
+    }
+
+    /// Multiplicative group order, 2^m - 1
+    fn order(&self) -> usize {
+        self.log.len() - 1
+    }
+
+    /// a^power
+    pub(crate) fn exp(&self, power: usize) -> u16 {
+        self.exp[power % self.order()]
+    }
+
+    pub(crate) fn mul(&self, a: u16, b: u16) -> u16 {
+        if a == 0 || b == 0 {
+            return 0;
+        }
+        self.exp[self.log[a as usize] as usize + self.log[b as usize] as usize]
+    }
+
+    fn div(&self, a: u16, b: u16) -> u16 {
+        if a == 0 {
+            return 0;
+        }
+        let order = self.order();
+        self.exp[self.log[a as usize] as usize + order - self.log[b as usize] as usize]
+    }
+
+    fn inverse(&self, a: u16) -> u16 {
+        self.div(1, a)
+    }
+}
+
+/// Value at `x` of the polynomial with coefficients highest degree first
+fn evaluate_descending(field: &GaloisField, coefficients: &[u16], x: u16) -> u16 {
+    coefficients
+        .iter()
+        .fold(0, |acc, &c| field.mul(acc, x) ^ c)
+}
+
+/// Value at `x` of the polynomial with coefficients lowest degree first
+fn evaluate_ascending(field: &GaloisField, coefficients: &[u16], x: u16) -> u16 {
+    coefficients
+        .iter()
+        .rev()
+        .fold(0, |acc, &c| field.mul(acc, x) ^ c)
+}
+
+/// Correct one block in place, where the last `check_len` codewords are the
+/// check symbols. Returns the number of corrected codewords, or `None` when
+/// the block has more errors than the code can fix.
+#[cfg(any(feature = "datamatrix", feature = "aztec"))]
+pub(crate) fn correct(
+    field: &GaloisField,
+    codewords: &mut [u16],
+    check_len: usize,
+) -> Option<usize> {
+    correct_from(field, codewords, check_len, 1)
+}
+
+/// [`correct`] for a generator whose roots start at a^`first_root`
+pub(crate) fn correct_from(
+    field: &GaloisField,
+    codewords: &mut [u16],
+    check_len: usize,
+    first_root: usize,
+) -> Option<usize> {
+    let n = codewords.len();
+    if n > field.order() || check_len == 0 || check_len >= n {
+        return None;
+    }
+
+    let roots = || first_root..first_root + check_len;
+    let syndromes: Vec<u16> = roots()
+        .map(|j| evaluate_descending(field, codewords, field.exp(j)))
+        .collect();
+    if syndromes.iter().all(|&s| s == 0) {
+        return Some(0);
+    }
+
+    // Berlekamp-Massey: shortest LFSR (error locator) generating the syndromes
+    let mut locator = vec![1u16];
+    let mut previous = vec![1u16];
+    let (mut errors, mut shift, mut previous_discrepancy) = (0usize, 1usize, 1u16);
+    for r in 0..check_len {
+        let discrepancy = (1..locator.len().min(r + 1))
+            .fold(syndromes[r], |d, i| d ^ field.mul(locator[i], syndromes[r - i]));
+        if discrepancy == 0 {
+            shift += 1;
+            continue;
+        }
+
+        let scale = field.div(discrepancy, previous_discrepancy);
+        let mut next = locator.clone();
+        next.resize(next.len().max(previous.len() + shift), 0);
+        for (i, &p) in previous.iter().enumerate() {
+            next[i + shift] ^= field.mul(scale, p);
+        }
+        if 2 * errors <= r {
+            errors = r + 1 - errors;
+            previous = std::mem::replace(&mut locator, next);
+            previous_discrepancy = discrepancy;
+            shift = 1;
+        } else {
+            locator = next;
+            shift += 1;
+        }
+    }
+    while locator.len() > 1 && locator.last() == Some(&0) {
+        locator.pop();
+    }
+    if 2 * errors > check_len || locator.len() - 1 != errors {
+        return None;
+    }
+
+    // Chien search: position p carries x^(n-1-p), a root of the locator at its inverse
+    let positions: Vec<(usize, u16)> = (0..n)
+        .filter_map(|p| {
+            let inverse = field.inverse(field.exp(n - 1 - p));
+            (evaluate_ascending(field, &locator, inverse) == 0).then_some((p, inverse))
+        })
+        .collect();
+    if positions.len() != errors {
+        return None;
+    }
+
+    // Forney: e = X^(1 - first_root) omega(X^-1) / locator'(X^-1)
+    let mut evaluator = vec![0u16; check_len];
+    for (i, &l) in locator.iter().enumerate() {
+        for (j, &s) in syndromes.iter().enumerate().take(check_len.saturating_sub(i)) {
+            evaluator[i + j] ^= field.mul(l, s);
+        }
+    }
+    let derivative: Vec<u16> = locator
+        .iter()
+        .enumerate()
+        .skip(1)
+        .map(|(i, &l)| if i % 2 == 1 { l } else { 0 })
+        .collect();
+    for &(p, inverse) in &positions {
+        let denominator = evaluate_ascending(field, &derivative, inverse);
+        if denominator == 0 {
+            return None;
+        }
+        let magnitude = field.div(evaluate_ascending(field, &evaluator, inverse), denominator);
+        let power = (n - 1 - p) * (field.order() + 1 - first_root);
+        codewords[p] ^= field.mul(magnitude, field.exp(power));
+    }
+
+    let clean = roots().all(|j| evaluate_descending(field, codewords, field.exp(j)) == 0);
+    clean.then_some(errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check symbols of `data` for the generator with roots a^first .. a^(first + n - 1)
+    fn check_symbols(field: &GaloisField, data: &[u16], n: usize, first: usize) -> Vec<u16> {
+        let mut generator = vec![1u16];
+        for j in first..first + n {
+            let mut next = generator.clone();
+            next.push(0);
+            for (i, &c) in generator.iter().enumerate() {
+                next[i + 1] ^= field.mul(c, field.exp(j));
+            }
+            generator = next;
+        }
+        let mut remainder = vec![0u16; n];
+        for &word in data {
+            let factor = word ^ remainder[0];
+            remainder.rotate_left(1);
+            remainder[n - 1] = 0;
+            for (value, &g) in remainder.iter_mut().zip(&generator[1..]) {
+                *value ^= field.mul(g, factor);
+            }
+        }
+        remainder
+    }
+
+    #[test]
+    fn matches_the_data_matrix_example() {
+        // "123456" in a 10x10 symbol, ISO/IEC 16022 annex O
+        let field = GaloisField::new(0x12D, 256);
+        assert_eq!(check_symbols(&field, &[142, 164, 186], 5, 1), [114, 25, 5, 88, 102]);
+        let mut words = [142, 164, 186, 114, 25, 5, 88, 102];
+        assert_eq!(correct_from(&field, &mut words, 5, 1), Some(0));
+        words[0] = 0;
+        words[6] ^= 0x55;
+        assert_eq!(correct_from(&field, &mut words, 5, 1), Some(2));
+        assert_eq!(words, [142, 164, 186, 114, 25, 5, 88, 102]);
+    }
+
+    #[test]
+    fn matches_the_qr_example() {
+        // "HELLO WORLD" as version 1-M
+        let field = GaloisField::new(0x11D, 256);
+        let data = [32, 91, 11, 120, 209, 114, 220, 77, 67, 64, 236, 17, 236, 17, 236, 17];
+        let check = [196, 35, 39, 119, 235, 215, 231, 226, 93, 23];
+        assert_eq!(check_symbols(&field, &data, 10, 0), check);
+        let clean: Vec<u16> = data.iter().chain(&check).copied().collect();
+        let mut words = clean.clone();
+        for (i, p) in [1, 7, 12, 19, 25].into_iter().enumerate() {
+            words[p] ^= 1 << i;
+        }
+        assert_eq!(correct_from(&field, &mut words, 10, 0), Some(5));
+        assert_eq!(words, clean);
+    }
+
+    #[test]
+    fn gives_up_past_half_the_check_symbols() {
+        for (primitive, size) in [(0x13, 16), (0x43, 64), (0x12D, 256), (0x409, 1024)] {
+            let field = GaloisField::new(primitive, size);
+            // 7 data symbols fit the 15 of GF(16)
+            let data: Vec<u16> = (0..7).map(|i| (i * 37 % size) as u16).collect();
+            let mut clean = data.clone();
+            clean.extend(check_symbols(&field, &data, 8, 1));
+
+            let mut words = clean.clone();
+            for p in [0, 2, 7, 13] {
+                words[p] ^= 3;
+            }
+            assert_eq!(correct_from(&field, &mut words, 8, 1), Some(4), "GF({})", size);
+            assert_eq!(words, clean);
+
+            for p in [0, 2, 4, 7, 13] {
+                words[p] ^= 3;
+            }
+            let corrected = correct_from(&field, &mut words, 8, 1);
+            assert!(corrected.is_none() || words != clean, "GF({})", size);
+        }
+    }
+}
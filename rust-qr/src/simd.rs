@@ -0,0 +1,74 @@
+//! WASM SIMD128 RGBA -> luma conversion for the `simd` feature.
+//! Uses the same 8.8 fixed-point weights and alpha blend as the scalar loop
+//! in `rgba_to_luma_with_stride`, so the output is bit-identical.
+
+use core::arch::wasm32::*;
+
+use crate::qr::Blend;
+
+/// Append the luma of every whole group of 8 pixels in `rgba` to `out` and
+/// return the trailing pixels for the scalar loop
+pub fn extend_luma<'a>(rgba: &'a [u8], out: &mut Vec<u8>, blend: Blend) -> &'a [u8] {
+    let mask = u32x4_splat(0xFF);
+    let (red, green, blue) = (u32x4_splat(77), u32x4_splat(150), u32x4_splat(29));
+    let (background, half) = (u32x4_splat(blend.background), u32x4_splat(128));
+    // Each u32 lane holds one little-endian RGBA pixel
+    let luma = |pixels: v128| {
+        let r = v128_and(pixels, mask);
+        let g = v128_and(u32x4_shr(pixels, 8), mask);
+        let b = v128_and(u32x4_shr(pixels, 16), mask);
+        let a = u32x4_shr(pixels, 24);
+        let color = u32x4_shr(
+            i32x4_add(
+                i32x4_add(i32x4_mul(r, red), i32x4_mul(g, green)),
+                i32x4_add(i32x4_mul(b, blue), half),
+            ),
+            8,
+        );
+        let weight = if blend.premultiplied { mask } else { a };
+        let sum = i32x4_add(
+            i32x4_mul(color, weight),
+            i32x4_mul(background, i32x4_sub(mask, a)),
+        );
+        let rounded = i32x4_add(sum, half);
+        u32x4_min(u32x4_shr(i32x4_add(rounded, u32x4_shr(rounded, 8)), 8), mask)
+    };
+
+    let mut chunks = rgba.chunks_exact(32);
+    for chunk in &mut chunks {
+        // SAFETY: `chunk` holds 32 readable bytes and `v128_load` has no
+        // alignment requirement
+        let (low, high) = unsafe {
+            let ptr = chunk.as_ptr() as *const v128;
+            (v128_load(ptr), v128_load(ptr.add(1)))
+        };
+        // Lanes are at most 255, so the saturating narrows are exact
+        let words = i16x8_narrow_i32x4(luma(low), luma(high));
+        let bytes = u8x16_narrow_i16x8(words, words);
+        out.extend_from_slice(&u64x2_extract_lane::<0>(bytes).to_le_bytes());
+    }
+    chunks.remainder()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::qr::{rgba_to_gray_with_alpha, rgba_to_gray_with_histogram, AlphaOptions};
+
+    #[test]
+    fn vector_luma_matches_the_scalar_loop() {
+        // 8-pixel groups plus a scalar tail, over every byte value per channel
+        let (width, height) = (259, 4);
+        let rgba: Vec<u8> = (0..width * height * 4).map(|i| (i * 97 + i / 1024) as u8).collect();
+        let backgrounds = [[255, 255, 255], [0, 0, 0], [18, 200, 77]];
+        for background in backgrounds {
+            for premultiplied in [false, true] {
+                let alpha = AlphaOptions { background, premultiplied };
+                // The histogram forces the scalar loop
+                let vector = rgba_to_gray_with_alpha(&rgba, width, height, None, &alpha).unwrap();
+                let (scalar, _) =
+                    rgba_to_gray_with_histogram(&rgba, width, height, None, &alpha).unwrap();
+                assert_eq!(vector, scalar, "{:?} {}", background, premultiplied);
+            }
+        }
+    }
+}
@@ -0,0 +1,206 @@
+//! Per-stage wall-clock timing behind the `with_timings` decode option, and
+//! the work limits of `time_budget_ms` and `max_grid_candidates`. Code paths
+//! take an `Option<&mut StageTimer>` and only read the clock when it is
+//! `Some`, so plain decodes pay nothing.
+
+use serde::Serialize;
+
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+/// Milliseconds spent in each stage of one decode. Stages that run once per
+/// retry pass, tile or grid are summed; `total_ms` also covers the work
+/// between stages (scaling, cropping, sorting).
+#[derive(Serialize, Clone, Copy, Default, Debug, PartialEq)]
+pub struct StageTimings {
+    pub grayscale_ms: f64,
+    pub prepare_ms: f64,
+    pub detect_ms: f64,
+    pub decode_ms: f64,
+    pub total_ms: f64,
+}
+
+/// A timed stage; see `StageTimings`
+#[derive(Clone, Copy)]
+pub(crate) enum Stage {
+    Grayscale,
+    Prepare,
+    Detect,
+    Decode,
+}
+
+/// Milliseconds on a monotonic clock with an arbitrary origin. Any
+/// `Fn() -> f64` is one, so tests can pass a fake; decodes default to
+/// `now_ms` (`performance.now()` in the browser, `Instant` natively).
+pub trait Clock {
+    fn now_ms(&self) -> f64;
+}
+
+impl<F: Fn() -> f64> Clock for F {
+    fn now_ms(&self) -> f64 {
+        self()
+    }
+}
+
+/// Accumulates `StageTimings` from its creation to `finish`, and tells the
+/// decode loops when its budget has run out and how many grids to try
+pub(crate) struct StageTimer<'a> {
+    clock: Box<dyn Clock + 'a>,
+    start: f64,
+    timings: StageTimings,
+    /// Clock reading after which `expired` holds
+    deadline: Option<f64>,
+    timed_out: bool,
+    /// Grid candidates decoded per detection pass, 0 for all
+    pub(crate) max_grids: usize,
+    /// Candidates left undecoded because of `max_grids`, over every pass
+    pub(crate) skipped_grids: u32,
+}
+
+impl<'a> StageTimer<'a> {
+    /// A timer whose `expired` holds once `budget_ms` have passed on `clock`
+    pub(crate) fn with_budget(clock: Box<dyn Clock + 'a>, budget_ms: Option<f64>) -> Self {
+        let start = clock.now_ms();
+        StageTimer {
+            clock,
+            start,
+            timings: StageTimings::default(),
+            deadline: budget_ms.map(|budget| start + budget),
+            timed_out: false,
+            max_grids: 0,
+            skipped_grids: 0,
+        }
+    }
+
+    /// Whether the budget has run out; once it has, the decode counts as
+    /// timed out
+    pub(crate) fn expired(&mut self) -> bool {
+        if !self.timed_out {
+            let now = self.clock.now_ms();
+            self.timed_out = self.deadline.is_some_and(|deadline| now >= deadline);
+        }
+        self.timed_out
+    }
+
+    /// Whether `expired` can ever hold
+    #[cfg(feature = "threads")]
+    pub(crate) fn has_budget(&self) -> bool {
+        self.deadline.is_some()
+    }
+
+    pub(crate) fn timed_out(&self) -> bool {
+        self.timed_out
+    }
+
+    pub(crate) fn finish(mut self) -> StageTimings {
+        self.timings.total_ms = self.clock.now_ms() - self.start;
+        self.timings
+    }
+}
+
+/// Whether the budget of `timer`, if any, has run out
+pub(crate) fn expired(timer: &mut Option<&mut StageTimer>) -> bool {
+    timer.as_deref_mut().is_some_and(StageTimer::expired)
+}
+
+/// Run `f`, adding its duration to `stage` when a timer is given
+pub(crate) fn timed<T>(timer: Option<&mut StageTimer>, stage: Stage, f: impl FnOnce() -> T) -> T {
+    let Some(timer) = timer else {
+        return f();
+    };
+
+    let start = timer.clock.now_ms();
+    let value = f();
+    let elapsed = timer.clock.now_ms() - start;
+    let timings = &mut timer.timings;
+    match stage {
+        Stage::Grayscale => timings.grayscale_ms += elapsed,
+        Stage::Prepare => timings.prepare_ms += elapsed,
+        Stage::Detect => timings.detect_ms += elapsed,
+        Stage::Decode => timings.decode_ms += elapsed,
+    }
+    value
+}
+
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+#[wasm_bindgen]
+extern "C" {
+    // Global in both windows and workers, with sub-millisecond resolution
+    #[wasm_bindgen(js_namespace = performance, js_name = now)]
+    fn performance_now() -> f64;
+}
+
+/// Milliseconds on a monotonic clock with an arbitrary origin
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+pub(crate) fn now_ms() -> f64 {
+    performance_now()
+}
+
+/// Milliseconds on a monotonic clock with an arbitrary origin
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn now_ms() -> f64 {
+    static ORIGIN: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+    ORIGIN.get_or_init(std::time::Instant::now).elapsed().as_secs_f64() * 1000.0
+}
+
+/// wasm32 without the JS bindings has no clock; every stage reads 0 and
+/// budgets never run out
+#[cfg(all(not(feature = "wasm"), target_arch = "wasm32"))]
+pub(crate) fn now_ms() -> f64 {
+    0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    /// A clock advancing one millisecond on every reading
+    fn ticking(time: &Cell<f64>) -> impl Fn() -> f64 + '_ {
+        move || {
+            let now = time.get();
+            time.set(now + 1.0);
+            now
+        }
+    }
+
+    #[test]
+    fn stages_sum_their_durations() {
+        let time = Cell::new(0.0);
+        let mut timer = StageTimer::with_budget(Box::new(ticking(&time)), None);
+        assert_eq!(timed(Some(&mut timer), Stage::Grayscale, || 7), 7);
+        timed(Some(&mut timer), Stage::Detect, || ());
+        timed(Some(&mut timer), Stage::Detect, || ());
+        // Time between stages only counts towards the total
+        time.set(time.get() + 10.0);
+        timed(Some(&mut timer), Stage::Decode, || ());
+
+        let expected = StageTimings {
+            grayscale_ms: 1.0,
+            prepare_ms: 0.0,
+            detect_ms: 2.0,
+            decode_ms: 1.0,
+            total_ms: 19.0,
+        };
+        assert_eq!(timer.finish(), expected);
+        assert_eq!(timed(None, Stage::Prepare, || "untimed"), "untimed");
+    }
+
+    #[test]
+    fn budgets_expire_for_good() {
+        let time = Cell::new(0.0);
+        let mut timer = StageTimer::with_budget(Box::new(|| time.get()), Some(5.0));
+        assert!(!timer.expired());
+        time.set(4.9);
+        assert!(!timer.expired() && !timer.timed_out());
+        time.set(5.0);
+        assert!(timer.expired());
+        // Later readings cannot bring it back
+        time.set(0.0);
+        assert!(timer.expired() && timer.timed_out());
+
+        let mut unlimited = StageTimer::with_budget(Box::new(|| f64::MAX), None);
+        assert!(!expired(&mut Some(&mut unlimited)));
+        assert!(!expired(&mut None));
+    }
+}
@@ -0,0 +1,637 @@
+//! TypeScript declarations for the serialized results and options.
+//!
+//! `wasm` appends them to the generated `.d.ts`; the exports reference them
+//! through `typescript_type`. They are written by hand, so the tests below
+//! check every interface against the serde fields of its Rust type.
+
+// Mirrors the serialized Rust structs; keep in step with qr.rs, mrz.rs and
+// the copies in the React package's src/types.ts.
+// `Option` fields are omitted (undefined) when `None`.
+// Only read by `wasm_bindgen`'s custom section, which the lint does not see
+#[allow(dead_code)]
+pub(crate) const TS_TYPES: &str = r#"
+/** Error object thrown by every fallible function */
+export interface VeloQrError {
+  // e.g. INVALID_BUFFER_LENGTH, NO_MRZ_LINES. A panic traps with WebAssembly.RuntimeError
+  // instead; take_last_panic() then returns it as INTERNAL_PANIC.
+  code: string;
+  message: string;
+}
+
+export interface StructuredAppend {
+  index: number; // 0-based
+  total: number;
+  parity: number;
+}
+
+export type Symbology = "qr" | "datamatrix" | "aztec" | "microqr" | "ean13" | "upca" | "code128"
+  | "pdf417";
+
+export interface QRCodeResult {
+  data: string;
+  // Data Matrix: 1-based index of the 30 ECC 200 sizes; Aztec: layers, negative for compact;
+  // Micro QR: -1 to -4 for M1 to M4; 1D: modules from the first bar to the last;
+  // PDF417: data columns
+  version: number;
+  bounds: [number, number][];
+  // Aztec: check words; PDF417: security level 0-8; Micro QR M1 and 1D: empty
+  ecc_level: "L" | "M" | "Q" | "H" | "ECC200" | `${number}%` | `${number}` | "";
+  mask: number;
+  raw_bytes: Uint8Array;
+  inverted: boolean;
+  mirrored: boolean;
+  gs1: boolean;
+  strategy: string; // decode_qr_robust pass, empty for other entry points
+  orientation_degrees: number; // clockwise, 0 = upright
+  center: [number, number];
+  area: number;
+  angle_degrees: number; // (-180, 180], clockwise positive
+  module_size_px: number;
+  structured_append?: StructuredAppend;
+  encoding: string; // e.g. "UTF-8", "Shift_JIS", "ISO-8859-1"
+  encoding_error: boolean; // data is empty, payload only in raw_bytes
+  crop_png: Uint8Array; // perspective-corrected crop, empty unless return_crop
+  symbology: Symbology;
+  check_digit_valid?: boolean; // 1D only, reported even when it fails
+  corrected_codewords: number; // Reed-Solomon corrections; 0 for 1D
+  quality: number; // 1 = no corrections, toward 0 as a block nears its limit
+  payload_hash: string; // hash_payload(raw payload), lowercase hex SHA-256
+  modules?: ModuleGrid; // with return_module_grid, QR codes only
+  track_id?: number; // QrScanner track, while smoothing_alpha > 0 or from scan_tracked
+  smoothed_bounds: [number, number][]; // QrScanner smoothed corners, else empty
+}
+
+/** Modules as the decoder reads them, 1 = dark, unmirrored and uninverted */
+export interface ModuleGrid {
+  size: number; // modules per side
+  bits: Uint8Array; // row-major
+}
+
+/**
+ * What the `*_json` functions return, as a string for `JSON.parse`. `data`
+ * holds the same fields as the object-returning variant, except that byte
+ * fields are number arrays and absent optional fields are null.
+ * `schema_version` (currently 1) changes only when a field is removed,
+ * renamed or changes type; new fields keep it.
+ */
+export interface JsonEnvelope<T> {
+  schema_version: number;
+  data: T;
+}
+
+export interface FailedGrid {
+  bounds: [number, number][];
+  error: string; // e.g. "DataEcc"
+  module_size: number; // Pixels per module
+}
+
+export interface DecodeReport {
+  results: QRCodeResult[];
+  failed: FailedGrid[];
+}
+
+export type ScanHint = "none" | "too_dark" | "glare" | "low_contrast";
+
+export interface HintedDecode {
+  results: QRCodeResult[];
+  hint: ScanHint;
+  luma_stats?: LumaStats; // with return_luma_stats
+  timed_out: boolean; // time_budget_ms ran out, results are partial
+  skipped_candidates: number; // grids past max_grid_candidates, not decoded
+}
+
+export interface MrzRegion {
+  x: number;
+  y: number;
+  w: number;
+  h: number;
+  confidence: number; // 0 to 1
+  lines: number; // 2 (TD2, TD3, visas) or 3 (TD1)
+  angle_degrees: number; // skew of the lines, clockwise positive
+}
+
+export interface DocumentScan {
+  qr: QRCodeResult[];
+  mrz_regions: MrzRegion[]; // most confident first, in image pixels
+}
+
+export interface LumaStats {
+  histogram: number[]; // 256 bins
+  p5: number;
+  p50: number;
+  p95: number;
+  clipped_high: number; // fraction of pixels at 255
+}
+
+/** Milliseconds per stage, summed over retry passes, tiles and grids */
+export interface StageTimings {
+  grayscale_ms: number;
+  prepare_ms: number;
+  detect_ms: number;
+  decode_ms: number;
+  total_ms: number;
+}
+
+export interface TimedDecode {
+  results: QRCodeResult[];
+  timings: StageTimings;
+  timed_out: boolean; // time_budget_ms ran out, results are partial
+  skipped_candidates: number; // grids past max_grid_candidates, not decoded
+}
+
+export interface TrackedCode {
+  track_id: number;
+  data: string;
+  payload_hash: string;
+  bounds: [number, number][]; // smoothed while smoothing_alpha > 0; last seen if lost
+  frames_seen: number;
+}
+
+/** Track changes of one QrScanner.scan_tracked frame */
+export interface TrackEvents {
+  appeared: TrackedCode[];
+  updated: TrackedCode[];
+  lost: TrackedCode[]; // past max_missed_frames, evicted beyond max_tracks, or resized
+}
+
+export interface GridCandidate {
+  bounds: [number, number][];
+  size: number; // modules per side
+}
+
+export interface BatchImage {
+  data: Uint8Array; // RGBA
+  width: number;
+  height: number;
+  stride_bytes?: number; // row pitch of padded frames, packed when omitted
+}
+
+export interface BatchEntry {
+  results: QRCodeResult[];
+  error?: VeloQrError;
+}
+
+export interface FrameQuality {
+  sharpness: number;
+  mean_luma: number;
+  contrast: number;
+}
+
+export interface Roi {
+  x: number;
+  y: number;
+  width: number;
+  height: number;
+}
+
+export interface Tiling {
+  tile_size: number;
+  overlap: number;
+}
+
+export interface HintThresholds {
+  dark_luma?: number;
+  saturated_fraction?: number;
+  min_luma_range?: number;
+}
+
+export interface AlphaOptions {
+  background?: [number, number, number]; // RGB behind transparent pixels, default white
+  premultiplied?: boolean;
+}
+
+export interface DecodeOptions {
+  max_results?: number;
+  min_size_px?: number; // Side of the smallest square area kept
+  sort?: "none" | "area_desc" | "center_distance";
+  roi?: Roi;
+  max_dimension?: number;
+  retry_full_resolution?: boolean;
+  return_raw_bytes?: boolean;
+  try_inverted?: boolean;
+  try_mirrored?: boolean;
+  binarization?: "default" | "otsu" | "adaptive" | "sauvola";
+  window?: number;
+  k?: number;
+  tiling?: Tiling;
+  try_rotations?: boolean;
+  try_upscale?: boolean;
+  upscale_below?: number; // default 400
+  add_quiet_zone?: number; // Border in pixels tried when nothing is found
+  return_crop?: boolean;
+  crop_size?: number;
+  return_module_grid?: boolean; // sampled module matrix as modules on each QR result
+  hint_thresholds?: HintThresholds;
+  return_luma_stats?: boolean; // decode_qr_with_hint adds luma_stats
+  alpha?: AlphaOptions;
+  with_timings?: boolean; // decode_qr_with_options returns a TimedDecode
+  time_budget_ms?: number; // Also returns a TimedDecode, timed_out when it ran out
+  max_grid_candidates?: number; // Most plausible grids decoded per pass, default 16, 0 all
+}
+
+export type Strategy = "plain" | "inverted" | "adaptive" | "downscale" | "upscale" | "quiet_zone";
+
+export interface RobustOptions {
+  strategies?: Strategy[];
+}
+
+export interface MrzOptions {
+  correction_level?: "off" | "safe" | "aggressive";
+  strict?: boolean;
+  min_line_length?: number; // default 20
+  length_tolerance?: number; // default 4; ignored when strict
+  decode_optional_data?: boolean; // default true
+}
+
+export interface FieldSpan {
+  line: number;
+  start: number;
+  end: number;
+}
+
+export interface MRZFieldSpans {
+  document_code: FieldSpan[];
+  issuing_country: FieldSpan[];
+  document_number: FieldSpan[];
+  date_of_birth: FieldSpan[];
+  sex: FieldSpan[];
+  date_of_expiry: FieldSpan[];
+  nationality: FieldSpan[];
+  surname: FieldSpan[];
+  given_names: FieldSpan[];
+  optional_data: FieldSpan[];
+  personal_number: FieldSpan[];
+}
+
+export interface MRZCheckDigits {
+  document_number: boolean;
+  date_of_birth: boolean;
+  date_of_expiry?: boolean; // not on the French CNI
+  personal_number?: boolean; // TD3 only
+  composite?: boolean; // not present on visas
+  valid: boolean;
+}
+
+export interface MrzCheckDigitReport {
+  field: string; // MRZResult field, or "composite"
+  line: number; // where the check digit stands
+  position: number;
+  present: string; // the check digit as read
+  computed?: number; // missing when the data holds characters outside [A-Z0-9<]
+  passed: boolean;
+}
+
+export interface MrzCharsetViolation {
+  line: number;
+  position: number;
+  character: string;
+  field?: string; // the field or check digit it falls in
+  allowed: "A-Z<" | "0-9<" | "A-Z0-9<";
+}
+
+export interface MrzValidation {
+  document_type: string; // detected format: TD1, TD2, TD3, MRV-A, MRV-B or FR_CNI
+  check_digits: MrzCheckDigitReport[];
+  charset_violations: MrzCharsetViolation[];
+  composite_valid?: boolean; // missing on visas
+  length_warnings: string[];
+  valid: boolean;
+}
+
+export interface MrzWarning {
+  code:
+    | "CHECK_DIGIT_MISMATCH"
+    | "INVALID_DATE"
+    | "UNKNOWN_COUNTRY"
+    | "INVALID_CHARSET"
+    | "INVALID_SEX"
+    | "IMPLAUSIBLE_DOCUMENT_NUMBER"
+    | "UNEXPECTED_LAYOUT";
+  field: string; // MRZResult field, "names" or "composite"
+  value: string;
+}
+
+export type MrzOptionalData =
+  | { type: "fra_cni"; department: string; office: string; issued?: string } // issued: YYYY-MM
+  | { type: "nld_bsn"; bsn: string; valid: boolean }
+  | {
+      type: "pol_pesel";
+      pesel: string;
+      date_of_birth?: string; // YYYY-MM-DD
+      sex: "M" | "F";
+      valid: boolean;
+    };
+
+export interface MRZResult {
+  document_type: string; // TD1, TD2, TD3, MRV-A, MRV-B or FR_CNI
+  document_code: string;
+  document_category: string; // passport, id_card, visa or other
+  document_number: string;
+  document_number_plausible?: boolean; // null when the country has no format rule
+  date_of_birth: string;
+  date_of_expiry: string;
+  nationality: string;
+  sex: "M" | "F" | "X" | "unknown"; // X = unspecified
+  sex_raw: string;
+  surname: string;
+  given_names: string;
+  given_names_list: string[];
+  name_truncated: boolean; // the name field is full, so names may be cut short
+  optional_data: string;
+  personal_number: string;
+  optional_parsed?: MrzOptionalData; // country-specific, when a decoder exists
+  issuing_country: string;
+  issuing_country_valid: boolean;
+  issuing_country_name?: string;
+  nationality_valid: boolean;
+  nationality_name?: string;
+  raw_mrz: string[]; // normalized lines the fields are read from
+  raw_input_lines: string[]; // cleaned input lines before padding and correction
+  normalization_applied: string[];
+  confidence: number; // weighted mean of field_confidence
+  field_confidence: Record<string, number>; // 0 to 1, e.g. document_number, names
+  check_digits: MRZCheckDigits;
+  date_of_birth_iso?: string; // YYYY-MM-DD
+  date_of_expiry_iso?: string;
+  warnings: MrzWarning[];
+  status: "valid" | "suspect" | "invalid"; // suspect: one bad field, invalid: two or more
+  is_expired?: boolean;
+  age_years?: number;
+  corrections: string[];
+  length_warnings: string[];
+  field_spans: MRZFieldSpans;
+}
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mrz::{
+        FieldSpan, MRZCheckDigits, MRZFieldSpans, MRZResult, MrzCharsetViolation,
+        MrzCheckDigitReport, MrzOptions, MrzRegion, MrzValidation, MrzWarning,
+    };
+    use crate::mrz_optional::MrzOptionalData;
+    use crate::qr::*;
+    use serde::de::{self, Deserialize, Deserializer, Visitor};
+    use serde::{forward_to_deserialize_any, Serialize};
+
+    /// Declared only in `wasm.rs`, which does not build for the host
+    const WASM_ONLY: &[&str] = &["VeloQrError", "JsonEnvelope", "BatchImage", "BatchEntry"];
+
+    /// Records the field or variant names a derived `Deserialize` asks for
+    struct Names<'a>(&'a mut &'static [&'static str]);
+
+    impl<'de> Deserializer<'de> for Names<'_> {
+        type Error = de::value::Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+            Err(de::Error::custom("not a struct or enum"))
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _: &'static str,
+            fields: &'static [&'static str],
+            _: V,
+        ) -> Result<V::Value, Self::Error> {
+            *self.0 = fields;
+            Err(de::Error::custom("recorded"))
+        }
+
+        fn deserialize_enum<V: Visitor<'de>>(
+            self,
+            _: &'static str,
+            variants: &'static [&'static str],
+            _: V,
+        ) -> Result<V::Value, Self::Error> {
+            *self.0 = variants;
+            Err(de::Error::custom("recorded"))
+        }
+
+        forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes
+            byte_buf option unit unit_struct newtype_struct seq tuple tuple_struct map
+            identifier ignored_any
+        }
+    }
+
+    /// Serde field names of a struct, or variant names of a unit enum, in order
+    fn names<T: for<'de> Deserialize<'de>>() -> Vec<&'static str> {
+        let mut names: &'static [&'static str] = &[];
+        let _ = T::deserialize(Names(&mut names));
+        names.to_vec()
+    }
+
+    /// Keys of a serialized value, sorted, for types that only implement `Serialize`
+    fn keys<T: Serialize>(value: &T) -> Vec<String> {
+        object(value).keys().cloned().collect()
+    }
+
+    /// Keys that are not `None`: serde-wasm-bindgen turns `None` into `undefined`
+    fn present_keys<T: Serialize>(value: &T) -> Vec<String> {
+        let object = object(value);
+        object.into_iter().filter(|(_, value)| !value.is_null()).map(|(key, _)| key).collect()
+    }
+
+    fn object<T: Serialize>(value: &T) -> serde_json::Map<String, serde_json::Value> {
+        match serde_json::to_value(value).unwrap() {
+            serde_json::Value::Object(map) => map,
+            other => panic!("not an object: {}", other),
+        }
+    }
+
+    /// `(name, [(key, optional)])` for every `export interface`
+    fn interfaces(ts: &str) -> Vec<(&str, Vec<(&str, bool)>)> {
+        let mut found = Vec::new();
+        let mut lines = ts.lines();
+        while let Some(line) = lines.next() {
+            let Some(rest) = line.strip_prefix("export interface ") else {
+                continue;
+            };
+            let name = rest.split(['<', ' ']).next().unwrap();
+            let mut fields = Vec::new();
+            for line in lines.by_ref().take_while(|line| *line != "}") {
+                let Some((key, _)) = line.strip_prefix("  ").and_then(|l| l.split_once(':'))
+                else {
+                    continue;
+                };
+                let (key, optional) = match key.strip_suffix('?') {
+                    Some(key) => (key, true),
+                    None => (key, false),
+                };
+                if key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                    fields.push((key, optional));
+                }
+            }
+            found.push((name, fields));
+        }
+        found
+    }
+
+    fn interface<'a>(ts: &'a str, name: &str) -> Vec<(&'a str, bool)> {
+        interfaces(ts)
+            .into_iter()
+            .find(|(found, _)| *found == name)
+            .unwrap_or_else(|| panic!("no interface {}", name))
+            .1
+    }
+
+    fn interface_keys<'a>(ts: &'a str, name: &str) -> Vec<&'a str> {
+        interface(ts, name).into_iter().map(|(key, _)| key).collect()
+    }
+
+    fn sorted(mut keys: Vec<&str>) -> Vec<&str> {
+        keys.sort_unstable();
+        keys
+    }
+
+    /// Members of `export type Name = "a" | "b";`, wrapped or not
+    fn string_union<'a>(ts: &'a str, name: &str) -> Vec<&'a str> {
+        let prefix = format!("export type {} = ", name);
+        let start = ts.find(prefix.as_str()).unwrap_or_else(|| panic!("no type {}", name));
+        let union = &ts[start + prefix.len()..];
+        // Long unions wrap onto `  | "..."` lines
+        union[..union.find(';').unwrap()]
+            .split('|')
+            .map(|member| member.trim().trim_matches('"'))
+            .collect()
+    }
+
+    #[test]
+    fn interfaces_list_the_serde_fields_in_order() {
+        let checked: Vec<(&str, Vec<&str>)> = vec![
+            ("StructuredAppend", names::<StructuredAppend>()),
+            ("QRCodeResult", names::<QRCodeResult>()),
+            ("ModuleGrid", names::<ModuleGrid>()),
+            ("FailedGrid", names::<FailedGrid>()),
+            ("DecodeReport", names::<DecodeReport>()),
+            ("HintedDecode", names::<HintedDecode>()),
+            ("MrzRegion", names::<MrzRegion>()),
+            ("DocumentScan", names::<DocumentScan>()),
+            ("LumaStats", names::<LumaStats>()),
+            ("TrackedCode", names::<TrackedCode>()),
+            ("TrackEvents", names::<TrackEvents>()),
+            ("GridCandidate", names::<GridCandidate>()),
+            ("FrameQuality", names::<FrameQuality>()),
+            ("Roi", names::<Roi>()),
+            ("Tiling", names::<Tiling>()),
+            ("HintThresholds", names::<HintThresholds>()),
+            ("AlphaOptions", names::<AlphaOptions>()),
+            ("DecodeOptions", names::<DecodeOptions>()),
+            ("RobustOptions", names::<RobustOptions>()),
+            ("MrzOptions", names::<MrzOptions>()),
+            ("FieldSpan", names::<FieldSpan>()),
+            ("MRZFieldSpans", names::<MRZFieldSpans>()),
+            ("MRZCheckDigits", names::<MRZCheckDigits>()),
+            ("MrzCheckDigitReport", names::<MrzCheckDigitReport>()),
+            ("MrzCharsetViolation", names::<MrzCharsetViolation>()),
+            ("MrzValidation", names::<MrzValidation>()),
+            ("MrzWarning", names::<MrzWarning>()),
+            ("MRZResult", names::<MRZResult>()),
+        ];
+        let serialized = [
+            ("StageTimings", keys(&StageTimings::default())),
+            ("TimedDecode", keys(&TimedDecode::default())),
+        ];
+        for (name, fields) in &checked {
+            assert!(!fields.is_empty(), "{} has no serde fields", name);
+            assert_eq!(&interface_keys(TS_TYPES, name), fields, "{}", name);
+        }
+        for (name, fields) in &serialized {
+            assert_eq!(&sorted(interface_keys(TS_TYPES, name)), fields, "{}", name);
+        }
+        for (name, _) in interfaces(TS_TYPES) {
+            let covered = checked.iter().any(|(checked, _)| *checked == name)
+                || serialized.iter().any(|(checked, _)| *checked == name)
+                || WASM_ONLY.contains(&name);
+            assert!(covered, "interface {} is not checked against a Rust type", name);
+        }
+    }
+
+    #[test]
+    fn fields_present_by_default_are_not_optional() {
+        let defaults = [
+            ("DecodeReport", present_keys(&DecodeReport::default())),
+            ("HintedDecode", present_keys(&HintedDecode::default())),
+            ("DocumentScan", present_keys(&DocumentScan::default())),
+            ("TrackEvents", present_keys(&TrackEvents::default())),
+            ("FrameQuality", present_keys(&FrameQuality::default())),
+            ("MRZFieldSpans", present_keys(&MRZFieldSpans::default())),
+            ("StageTimings", present_keys(&StageTimings::default())),
+            ("TimedDecode", present_keys(&TimedDecode::default())),
+        ];
+        for (name, present) in &defaults {
+            let required = interface(TS_TYPES, name)
+                .into_iter()
+                .filter(|(_, optional)| !optional)
+                .map(|(key, _)| key);
+            assert_eq!(&sorted(required.collect()), present, "{}", name);
+        }
+    }
+
+    #[test]
+    fn string_unions_list_the_enum_variants() {
+        // The declarations name every symbology, this build only those it decodes
+        let symbologies = string_union(TS_TYPES, "Symbology");
+        let built: Vec<&str> = Symbology::ALL.iter().map(|symbology| symbology.name()).collect();
+        assert_eq!(names::<Symbology>(), built);
+        assert!(built.iter().all(|name| symbologies.contains(name)), "{:?}", symbologies);
+        #[cfg(all(
+            feature = "datamatrix",
+            feature = "aztec",
+            feature = "microqr",
+            feature = "linear",
+            feature = "pdf417"
+        ))]
+        assert_eq!(symbologies, built);
+        assert_eq!(string_union(TS_TYPES, "ScanHint"), names::<ScanHint>());
+        assert_eq!(string_union(TS_TYPES, "Strategy"), names::<Strategy>());
+    }
+
+    #[test]
+    fn optional_data_members_match_the_variants() {
+        let start = TS_TYPES.find("export type MrzOptionalData =").unwrap();
+        let union = &TS_TYPES[start..start + TS_TYPES[start..].find(";\n\n").unwrap()];
+        // One `{ type: "..."; ... }` member per variant
+        let members = union
+            .split('{')
+            .skip(1)
+            .map(|member| {
+                let body = &member[..member.find('}').unwrap()];
+                let fields = body.split(';').filter_map(|field| {
+                    let field = field.trim().lines().last().unwrap_or_default();
+                    field.split_once(':')
+                });
+                sorted(fields.map(|(key, _)| key.trim().trim_end_matches('?')).collect())
+            });
+        let variants = [
+            MrzOptionalData::FraCni {
+                department: "932".into(),
+                office: "1".into(),
+                issued: Some("2014-05".into()),
+            },
+            MrzOptionalData::NldBsn { bsn: "999999990".into(), valid: true },
+            MrzOptionalData::PolPesel {
+                pesel: "44051401458".into(),
+                date_of_birth: Some("1944-05-14".into()),
+                sex: "M".into(),
+                valid: true,
+            },
+        ];
+        let members: Vec<Vec<&str>> = members.collect();
+        assert_eq!(members.len(), variants.len());
+        for (member, variant) in members.iter().zip(&variants) {
+            assert_eq!(member, &keys(variant));
+        }
+    }
+
+    #[test]
+    fn react_types_match_the_wasm_declarations() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../src/types.ts");
+        let react = std::fs::read_to_string(path).unwrap().replace('\'', "\"");
+        for name in ["QRCodeResult", "ModuleGrid", "StructuredAppend", "VeloQrError"] {
+            assert_eq!(interface(&react, name), interface(TS_TYPES, name), "{}", name);
+        }
+        assert_eq!(string_union(&react, "Symbology"), string_union(TS_TYPES, "Symbology"));
+    }
+}
@@ -0,0 +1,499 @@
+//! ICAO Visible Digital Seals (Doc 9303 Part 13) as carried in 2D barcodes:
+//! a header naming the issuing country, signer and document type, a message
+//! zone of tag-length-value features and a signature zone. The signature is
+//! only extracted; hosts verify it over `signed_data_span` (with WebCrypto,
+//! for instance) against the signer's certificate.
+
+use serde::Serialize;
+
+use crate::mrz::CivilDate;
+use crate::VeloQrError;
+
+/// First byte of every seal
+const MAGIC: u8 = 0xDC;
+
+/// Tag of the signature zone, which ends the message zone
+const SIGNATURE_TAG: u8 = 0xFF;
+
+/// C40 pairs starting with this byte hold a single ASCII character plus one
+const C40_SINGLE: u8 = 0xFE;
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct VdsSeal {
+    /// Header version: 3 (byte `02`) or 4 (byte `03`)
+    pub version: u32,
+    pub issuing_country: String, // Without filler, `D` for Germany
+    /// Country and issuer of the signing certificate, e.g. `DETS`
+    pub signer_identifier: String,
+    pub certificate_reference: String, // Hex serial of the signing certificate
+    pub issue_date: Option<String>,     // YYYY-MM-DD, null when not a calendar date
+    pub signature_date: Option<String>, // YYYY-MM-DD, null when not a calendar date
+    pub feature_definition_reference: u8,
+    pub document_type_category: u8,
+    /// Profile known to this crate, e.g. `icao_visa`; null for others, whose
+    /// features are only returned as bytes
+    pub document_type: Option<String>,
+    pub features: Vec<VdsFeature>,
+    /// Byte range of the input the signature covers: header and message zone
+    pub signed_data_span: (usize, usize),
+    /// ECDSA signature as `r || s` (IEEE P1363), the form WebCrypto verifies
+    #[serde(with = "serde_bytes")]
+    pub signature: Vec<u8>,
+}
+
+/// One document feature of the message zone
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct VdsFeature {
+    pub tag: u8,
+    pub name: Option<String>, // From the profile, e.g. `passport_number`
+    /// `c40`, `mrz` (C40 with `<` filler and one line per row), `integer`
+    /// or `bytes` (lowercase hex)
+    pub encoding: String,
+    pub value: String,
+    #[serde(with = "serde_bytes")]
+    pub raw: Vec<u8>,
+}
+
+/// How a profile encodes one feature
+#[derive(Clone, Copy)]
+enum FeatureKind {
+    C40,
+    /// C40 of a machine readable zone with lines of this many characters
+    Mrz(usize),
+    Integer,
+    Bytes,
+}
+
+/// Document profile, identified by feature definition reference and document
+/// type category, with the name and encoding of each feature tag
+struct Profile {
+    reference: u8,
+    category: u8,
+    name: &'static str,
+    features: &'static [(u8, &'static str, FeatureKind)],
+}
+
+const PROFILES: &[Profile] = &[
+    Profile {
+        reference: 0x5D,
+        category: 0x01,
+        name: "icao_visa",
+        features: &[
+            (0x01, "mrz_mrv_a", FeatureKind::Mrz(44)),
+            (0x02, "mrz_mrv_b", FeatureKind::Mrz(36)),
+            (0x03, "number_of_entries", FeatureKind::Integer),
+            (0x04, "duration_of_stay", FeatureKind::Bytes),
+            (0x05, "passport_number", FeatureKind::C40),
+            (0x06, "visa_type", FeatureKind::Bytes),
+            (0x07, "additional_features", FeatureKind::Bytes),
+        ],
+    },
+    Profile {
+        reference: 0x5E,
+        category: 0x03,
+        name: "icao_emergency_travel_document",
+        features: &[(0x02, "mrz", FeatureKind::Mrz(44))],
+    },
+];
+
+/// Parse a seal from the bytes of its barcode
+pub fn parse(data: &[u8]) -> Result<VdsSeal, VeloQrError> {
+    let mut reader = Reader { data, pos: 0 };
+    if reader.byte() != Some(MAGIC) {
+        return Err(invalid("missing magic constant 0xDC"));
+    }
+    let version = match reader.byte() {
+        Some(0x02) => 3,
+        Some(0x03) => 4,
+        Some(other) => return Err(invalid(&format!("unsupported header version {:#04x}", other))),
+        None => return Err(truncated()),
+    };
+    let issuing_country = reader.c40(2)?.trim_end().to_string();
+
+    // Version 3: 4 characters of signer and 5 of certificate reference.
+    // Version 4: signer, then the reference's length as 2 hex characters.
+    let (signer_identifier, certificate_reference) = if version == 3 {
+        let signer = reader.c40(6)?;
+        if signer.len() != 9 {
+            return Err(invalid("bad signer identifier"));
+        }
+        (signer[..4].to_string(), signer[4..].to_string())
+    } else {
+        let signer = reader.c40(4)?;
+        if signer.len() != 6 {
+            return Err(invalid("bad signer identifier"));
+        }
+        let length = usize::from_str_radix(&signer[4..], 16)
+            .map_err(|_| invalid("bad certificate reference length"))?;
+        let reference = reader.c40(length.div_ceil(3) * 2)?;
+        (signer[..4].to_string(), reference.chars().take(length).collect())
+    };
+
+    let issue_date = reader.take(3).map(decode_date).ok_or_else(truncated)?;
+    let signature_date = reader.take(3).map(decode_date).ok_or_else(truncated)?;
+    let feature_definition_reference = reader.byte().ok_or_else(truncated)?;
+    let document_type_category = reader.byte().ok_or_else(truncated)?;
+    let profile = PROFILES.iter().find(|profile| {
+        profile.reference == feature_definition_reference
+            && profile.category == document_type_category
+    });
+
+    let mut features = Vec::new();
+    let (signed_data_end, signature) = loop {
+        let start = reader.pos;
+        let tag = reader.byte().ok_or_else(|| invalid("missing signature zone"))?;
+        let length = reader.length()?;
+        let value = reader.take(length).ok_or_else(truncated)?;
+        if tag == SIGNATURE_TAG {
+            break (start, value);
+        }
+        features.push(decode_feature(tag, value, profile)?);
+    };
+
+    Ok(VdsSeal {
+        version,
+        issuing_country,
+        signer_identifier,
+        certificate_reference,
+        issue_date,
+        signature_date,
+        feature_definition_reference,
+        document_type_category,
+        document_type: profile.map(|profile| profile.name.to_string()),
+        features,
+        signed_data_span: (0, signed_data_end),
+        signature: signature.to_vec(),
+    })
+}
+
+fn decode_feature(
+    tag: u8,
+    raw: &[u8],
+    profile: Option<&Profile>,
+) -> Result<VdsFeature, VeloQrError> {
+    let known = profile.and_then(|profile| profile.features.iter().find(|(t, _, _)| *t == tag));
+    let (name, kind) = match known {
+        Some(&(_, name, kind)) => (Some(name.to_string()), kind),
+        None => (None, FeatureKind::Bytes),
+    };
+    let c40 = || decode_c40(raw).ok_or_else(|| invalid(&format!("feature {} is not C40", tag)));
+
+    let (encoding, value) = match kind {
+        FeatureKind::C40 => ("c40", c40()?),
+        FeatureKind::Mrz(line_length) => {
+            // The MRZ filler is encoded as a space, C40 having no `<`
+            let text = c40()?.replace(' ', "<");
+            let chars: Vec<char> = text.chars().collect();
+            let lines: Vec<String> = chars
+                .chunks(line_length)
+                .map(|line| {
+                    let line: String = line.iter().collect();
+                    format!("{:<<width$}", line, width = line_length)
+                })
+                .collect();
+            ("mrz", lines.join("\n"))
+        }
+        FeatureKind::Integer => {
+            let value = raw.iter().fold(0u64, |value, &b| value << 8 | b as u64);
+            ("integer", value.to_string())
+        }
+        FeatureKind::Bytes => ("bytes", raw.iter().map(|b| format!("{:02x}", b)).collect()),
+    };
+
+    Ok(VdsFeature { tag, name, encoding: encoding.to_string(), value, raw: raw.to_vec() })
+}
+
+/// Decode a feature of a profile this crate has no table for, given its
+/// `encoding`: `c40`, `date` (3 bytes) or `masked_date` (4 bytes)
+pub(crate) fn decode_value(bytes: &[u8], encoding: &str) -> Result<String, VeloQrError> {
+    let decoded = match encoding {
+        "c40" => decode_c40(bytes),
+        "date" => decode_date(bytes),
+        "masked_date" => decode_masked_date(bytes),
+        _ => return Err(VeloQrError::InvalidOptions(format!("unknown encoding {:?}", encoding))),
+    };
+    decoded.ok_or_else(|| invalid(&format!("not a valid {} value", encoding)))
+}
+
+/// Decode C40 as used by seals: byte pairs holding three values
+/// `1600 * c1 + 40 * c2 + c3 + 1`, for space, digits and capital letters,
+/// with shift 1 (0) padding a last pair of two characters, and a last
+/// single character as `0xFE` and its ASCII code plus one
+fn decode_c40(bytes: &[u8]) -> Option<String> {
+    if !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+    let mut text = String::new();
+    for pair in bytes.chunks(2) {
+        if pair[0] == C40_SINGLE {
+            text.push(char::from(pair[1].checked_sub(1).filter(u8::is_ascii)?));
+            continue;
+        }
+        let value = (u16::from(pair[0]) << 8 | u16::from(pair[1])).checked_sub(1)?;
+        for c in [value / 1600, value / 40 % 40, value % 40] {
+            match c {
+                0 => {}
+                3 => text.push(' '),
+                4..=13 => text.push(char::from(b'0' + (c - 4) as u8)),
+                14..=39 => text.push(char::from(b'A' + (c - 14) as u8)),
+                // Shift 2 and 3 never occur in seals, and values over
+                // 40 * 1600 only in corrupt data
+                _ => return None,
+            }
+        }
+    }
+    Some(text)
+}
+
+/// Decode a 3-byte date, the integer `MMDDYYYY`, as YYYY-MM-DD
+fn decode_date(bytes: &[u8]) -> Option<String> {
+    let digits = date_digits(bytes)?;
+    let (month, day, year) = (&digits[0..2], &digits[2..4], &digits[4..8]);
+    let date = CivilDate::new(year.parse().ok()?, month.parse().ok()?, day.parse().ok()?)?;
+    Some(date.to_iso())
+}
+
+/// Decode a 4-byte masked date as YYYY-MM-DD with `x` for every unknown
+/// digit: the first byte flags the unknown digits of `MMDDYYYY`, most
+/// significant bit first, and the other three hold the date with those
+/// digits set to zero
+fn decode_masked_date(bytes: &[u8]) -> Option<String> {
+    let (&mask, date) = bytes.split_first()?;
+    if mask == 0 {
+        return decode_date(date);
+    }
+    let digits: Vec<char> = date_digits(date)?
+        .chars()
+        .enumerate()
+        .map(|(index, digit)| if mask & (0x80 >> index) != 0 { 'x' } else { digit })
+        .collect();
+    let part = |range: std::ops::Range<usize>| digits[range].iter().collect::<String>();
+    Some(format!("{}-{}-{}", part(4..8), part(0..2), part(2..4)))
+}
+
+/// The 8 digits `MMDDYYYY` of a 3-byte date
+fn date_digits(bytes: &[u8]) -> Option<String> {
+    let &[a, b, c] = bytes else {
+        return None;
+    };
+    let value = u32::from(a) << 16 | u32::from(b) << 8 | u32::from(c);
+    (value <= 99_999_999).then(|| format!("{:08}", value))
+}
+
+/// Cursor over the seal bytes
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Option<u8> {
+        let byte = *self.data.get(self.pos)?;
+        self.pos += 1;
+        Some(byte)
+    }
+
+    fn take(&mut self, length: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(length)?)?;
+        self.pos += length;
+        Some(bytes)
+    }
+
+    /// `length` bytes of C40 text
+    fn c40(&mut self, length: usize) -> Result<String, VeloQrError> {
+        let bytes = self.take(length).ok_or_else(truncated)?;
+        decode_c40(bytes).ok_or_else(|| invalid("bad C40 in header"))
+    }
+
+    /// BER length: one byte below 0x80, else 0x81 or 0x82 and 1 or 2 bytes
+    fn length(&mut self) -> Result<usize, VeloQrError> {
+        let first = self.byte().ok_or_else(truncated)?;
+        let bytes = match first {
+            0x00..=0x7F => return Ok(first as usize),
+            0x81 => 1,
+            0x82 => 2,
+            _ => return Err(invalid(&format!("unsupported length {:#04x}", first))),
+        };
+        let length = self.take(bytes).ok_or_else(truncated)?;
+        Ok(length.iter().fold(0, |length, &b| length << 8 | b as usize))
+    }
+}
+
+fn invalid(message: &str) -> VeloQrError {
+    VeloQrError::InvalidPayload(format!("VDS: {}", message))
+}
+
+fn truncated() -> VeloQrError {
+    invalid("truncated seal")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// MRV-B specimen visa
+    const MRZ: [&str; 2] =
+        ["VCD<<DENT<<ARTHUR<PHILIP<<<<<<<<<<<<", "1234567XY7GBR5203116M2005250<<<<<<<<"];
+
+    fn c40_value(c: char) -> u16 {
+        match c {
+            ' ' | '<' => 3,
+            '0'..='9' => c as u16 - '0' as u16 + 4,
+            _ => c as u16 - 'A' as u16 + 14,
+        }
+    }
+
+    /// Seal C40: triples in byte pairs, shift 1 padding a last pair and a
+    /// last single character behind `0xFE`
+    fn c40(text: &str) -> Vec<u8> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut bytes = Vec::new();
+        for triple in chars.chunks(3) {
+            if let [single] = triple {
+                bytes.extend([C40_SINGLE, *single as u8 + 1]);
+                break;
+            }
+            let third = triple.get(2).map_or(0, |&c| c40_value(c));
+            let value = 1600 * c40_value(triple[0]) + 40 * c40_value(triple[1]) + third + 1;
+            bytes.extend(value.to_be_bytes());
+        }
+        bytes
+    }
+
+    fn date(year: u32, month: u32, day: u32) -> Vec<u8> {
+        (month * 1_000_000 + day * 10_000 + year).to_be_bytes()[1..].to_vec()
+    }
+
+    fn tlv(tag: u8, value: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![tag];
+        if value.len() >= 0x80 {
+            bytes.push(0x81);
+        }
+        bytes.push(value.len() as u8);
+        bytes.extend(value);
+        bytes
+    }
+
+    /// Header and message zone of a visa seal, without the signature zone
+    fn visa(version: u32) -> Vec<u8> {
+        let mut seal = vec![MAGIC, version as u8 - 1];
+        seal.extend(c40("UTO"));
+        if version == 4 {
+            seal.extend(c40("UTSS05"));
+            seal.extend(c40("1A2B3"));
+        } else {
+            seal.extend(c40("UTSS00001"));
+        }
+        seal.extend(date(2024, 3, 25));
+        seal.extend(date(2024, 3, 26));
+        seal.extend([0x5D, 0x01]);
+        seal.extend(tlv(0x02, &c40(&MRZ.concat())));
+        seal.extend(tlv(0x03, &[2]));
+        seal.extend(tlv(0x04, &[0x0E, 0x00, 0x00]));
+        seal.extend(tlv(0x05, &c40("C01X00T47")));
+        seal.extend(tlv(0x09, &[1, 2, 3]));
+        seal
+    }
+
+    fn signed(mut seal: Vec<u8>) -> Vec<u8> {
+        seal.extend(tlv(SIGNATURE_TAG, &[7; 64]));
+        seal
+    }
+
+    #[test]
+    fn c40_values_decode() {
+        assert_eq!(decode_value(&[0x59, 0xE9], "c40").unwrap(), "ABC");
+        assert_eq!(decode_value(&[0x59, 0xD9], "c40").unwrap(), "AB");
+        assert_eq!(decode_value(&[0x59, 0xE9, 0xFE, 0x42], "c40").unwrap(), "ABCA");
+        // Germany's `D` padded with filler
+        assert_eq!(decode_value(&[0x6A, 0xBC], "c40").unwrap(), "D  ");
+        assert_eq!(decode_value(&c40("C01X00T47"), "c40").unwrap(), "C01X00T47");
+
+        assert!(decode_value(&[0x59], "c40").is_err());
+        // Over 40 * 1600, and a single character that is not ASCII
+        assert!(decode_value(&[0xFA, 0x01], "c40").is_err());
+        assert!(decode_value(&[0xFE, 0xC4], "c40").is_err());
+        assert!(decode_value(&[1, 2, 3], "base64").is_err());
+    }
+
+    #[test]
+    fn dates_and_masked_dates_decode() {
+        assert_eq!(decode_value(&[0x31, 0x9E, 0xF5], "date").unwrap(), "1957-03-25");
+        assert!(decode_value(&date(2023, 2, 29), "date").is_err());
+        assert!(decode_value(&[0x31, 0x9E], "date").is_err());
+
+        assert_eq!(decode_value(&[0x00, 0x31, 0x9E, 0xF5], "masked_date").unwrap(), "1957-03-25");
+        // Day digits unknown, then month and day
+        let december = [0x30, 0xB7, 0x22, 0xCF];
+        assert_eq!(decode_value(&december, "masked_date").unwrap(), "1999-12-xx");
+        let year = [&[0xF0][..], &date(1985, 0, 0)].concat();
+        assert_eq!(decode_value(&year, "masked_date").unwrap(), "1985-xx-xx");
+    }
+
+    #[test]
+    fn visa_seals_parse_in_both_header_versions() {
+        for version in [3, 4] {
+            let message = visa(version);
+            let seal = parse(&signed(message.clone())).unwrap();
+            assert_eq!(seal.version, version);
+            assert_eq!(seal.issuing_country, "UTO");
+            assert_eq!(seal.signer_identifier, "UTSS");
+            let reference = if version == 4 { "1A2B3" } else { "00001" };
+            assert_eq!(seal.certificate_reference, reference);
+            assert_eq!(seal.issue_date.as_deref(), Some("2024-03-25"));
+            assert_eq!(seal.signature_date.as_deref(), Some("2024-03-26"));
+            assert_eq!(seal.document_type.as_deref(), Some("icao_visa"));
+            assert_eq!(seal.signed_data_span, (0, message.len()));
+            assert_eq!(seal.signature, [7; 64]);
+
+            let features = &seal.features;
+            assert_eq!(features.len(), 5);
+            assert_eq!(features[0].name.as_deref(), Some("mrz_mrv_b"));
+            assert_eq!(features[0].encoding, "mrz");
+            assert_eq!(features[0].value, MRZ.join("\n"));
+            assert_eq!(crate::mrz::parse(&features[0].value).unwrap().surname, "DENT");
+            let entries = (features[1].encoding.as_str(), features[1].value.as_str());
+            assert_eq!(entries, ("integer", "2"));
+            let stay = (features[2].encoding.as_str(), features[2].value.as_str());
+            assert_eq!(stay, ("bytes", "0e0000"));
+            assert_eq!(features[3].name.as_deref(), Some("passport_number"));
+            assert_eq!(features[3].value, "C01X00T47");
+            // Not in the profile
+            assert_eq!((features[4].name.as_deref(), features[4].value.as_str()), (None, "010203"));
+        }
+    }
+
+    #[test]
+    fn unknown_profiles_keep_their_bytes() {
+        let mut seal = vec![MAGIC, 0x03, 0x6A, 0xBC];
+        seal.extend(c40("DETS0A"));
+        seal.extend(c40("00000000ABC"));
+        seal.extend(date(2020, 1, 2));
+        seal.extend(date(2020, 1, 2));
+        seal.extend([0xFB, 0x06]);
+        seal.extend(tlv(0x02, &[0xAB; 200]));
+        let seal = parse(&signed(seal)).unwrap();
+        assert_eq!(seal.issuing_country, "D");
+        assert_eq!(seal.certificate_reference, "00000000AB");
+        assert_eq!(seal.document_type, None);
+        assert_eq!(seal.features[0].raw.len(), 200);
+        assert_eq!(seal.features[0].encoding, "bytes");
+    }
+
+    #[test]
+    fn malformed_seals_are_rejected() {
+        let good = signed(visa(4));
+        assert!(parse(&good).is_ok());
+        assert!(parse(&good[..good.len() - 1]).is_err());
+        assert!(parse(&visa(4)).is_err());
+        for (index, byte) in [(0, 0xDD), (1, 0x05)] {
+            let mut bad = good.clone();
+            bad[index] = byte;
+            assert!(parse(&bad).is_err());
+        }
+        for length in 0..good.len() {
+            assert!(parse(&good[..length]).is_err(), "{} bytes", length);
+        }
+        assert_eq!(parse(&[MAGIC]).unwrap_err().code(), "INVALID_PAYLOAD");
+    }
+}
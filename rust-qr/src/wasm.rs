@@ -0,0 +1,1163 @@
+//! `wasm-bindgen` exports used by the JavaScript package
+//! Thin wrappers converting between JS values and the [`crate::qr`] and
+//! [`crate::mrz`] APIs; only compiled for `wasm32` with the `wasm` feature.
+
+use std::cell::RefCell;
+
+use image::imageops;
+use image::{DynamicImage, RgbaImage};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::logging::{self, LogLevel};
+
+use crate::mrz::{
+    self, BacFields, CivilDate, MRZResult, MrzAccumulator, MrzError, MrzFields, MrzOptions,
+};
+use crate::panics;
+use crate::qr::{
+    self, DecodeOptions, DecodeReport, QRCodeResult, QrScanner, RobustOptions,
+    StructuredAppendAssembler, Symbology,
+};
+use crate::timing;
+use crate::VeloQrError;
+
+/// Shape of an error object as seen from JavaScript
+#[derive(Serialize)]
+struct ErrorObject<'a> {
+    code: &'a str,
+    message: String,
+}
+
+impl ErrorObject<'static> {
+    fn new(e: &VeloQrError) -> Self {
+        ErrorObject {
+            code: e.code(),
+            message: e.to_string(),
+        }
+    }
+}
+
+impl From<VeloQrError> for JsValue {
+    fn from(e: VeloQrError) -> JsValue {
+        let object = ErrorObject::new(&e);
+        serde_wasm_bindgen::to_value(&object).unwrap_or_else(|_| JsValue::from_str(&object.message))
+    }
+}
+
+impl From<MrzError> for JsValue {
+    fn from(e: MrzError) -> JsValue {
+        VeloQrError::from(e).into()
+    }
+}
+
+/// Serialize a result for JavaScript, reporting failures as `SERIALIZATION_FAILED`
+/// Maps are serialized as plain objects so results keyed by tag read naturally in JS
+fn to_js_value<T: Serialize + ?Sized>(value: &T) -> Result<JsValue, JsValue> {
+    let serializer = serde_wasm_bindgen::Serializer::new().serialize_maps_as_objects(true);
+    value
+        .serialize(&serializer)
+        .map_err(|e| VeloQrError::SerializationFailed(e.to_string()).into())
+}
+
+/// Serialize a result as one of the TypeScript types declared below
+fn to_typed<T: JsCast, V: Serialize + ?Sized>(value: &V) -> Result<T, JsValue> {
+    to_js_value(value).map(JsCast::unchecked_into)
+}
+
+/// Version of the `JsonEnvelope` written by the `*_json` exports. Bump it
+/// when a field is removed, renamed or changes type; new fields keep it.
+const JSON_SCHEMA_VERSION: u32 = 1;
+
+/// Top-level object of the `*_json` exports
+#[derive(Serialize)]
+struct JsonEnvelope<'a, T: Serialize + ?Sized> {
+    schema_version: u32,
+    data: &'a T,
+}
+
+/// Serialize a result as a `JsonEnvelope` string, for embedders whose
+/// structured cloning cannot carry `serde_wasm_bindgen` values
+fn to_json<T: Serialize + ?Sized>(data: &T) -> Result<String, JsValue> {
+    let envelope = JsonEnvelope { schema_version: JSON_SCHEMA_VERSION, data };
+    serde_json::to_string(&envelope)
+        .map_err(|e| VeloQrError::SerializationFailed(e.to_string()).into())
+}
+
+// ==================== TypeScript Definitions ====================
+
+#[wasm_bindgen(typescript_custom_section)]
+const TS_TYPES: &str = crate::ts_types::TS_TYPES;
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(typescript_type = "QRCodeResult")]
+    pub type QRCodeResultJs;
+    #[wasm_bindgen(typescript_type = "QRCodeResult[]")]
+    pub type QRCodeResultArray;
+    #[wasm_bindgen(typescript_type = "Promise<QRCodeResult[]>")]
+    pub type QRCodeResultPromise;
+    #[wasm_bindgen(typescript_type = "DecodeReport")]
+    pub type DecodeReportJs;
+    #[wasm_bindgen(typescript_type = "HintedDecode")]
+    pub type HintedDecodeJs;
+    #[wasm_bindgen(typescript_type = "DocumentScan")]
+    pub type DocumentScanJs;
+    #[wasm_bindgen(typescript_type = "QRCodeResult[] | TimedDecode")]
+    pub type MaybeTimedDecodeJs;
+    #[wasm_bindgen(typescript_type = "TrackEvents")]
+    pub type TrackEventsJs;
+    #[wasm_bindgen(typescript_type = "GridCandidate[]")]
+    pub type GridCandidateArray;
+    #[wasm_bindgen(typescript_type = "BatchImage[]")]
+    pub type BatchImageArray;
+    #[wasm_bindgen(typescript_type = "BatchEntry[]")]
+    pub type BatchEntryArray;
+    #[wasm_bindgen(typescript_type = "FrameQuality")]
+    pub type FrameQualityJs;
+    #[wasm_bindgen(typescript_type = "LumaStats")]
+    pub type LumaStatsJs;
+    #[wasm_bindgen(typescript_type = "DecodeOptions")]
+    pub type DecodeOptionsJs;
+    #[wasm_bindgen(typescript_type = "RobustOptions")]
+    pub type RobustOptionsJs;
+    #[wasm_bindgen(typescript_type = "MrzOptions")]
+    pub type MrzOptionsJs;
+    #[wasm_bindgen(typescript_type = "number | string")]
+    pub type ReferenceDateJs;
+    #[wasm_bindgen(typescript_type = "MRZResult")]
+    pub type MRZResultJs;
+    #[wasm_bindgen(typescript_type = "MrzValidation")]
+    pub type MrzValidationJs;
+    #[wasm_bindgen(typescript_type = "MRZResult | null")]
+    pub type MaybeMRZResultJs;
+    #[wasm_bindgen(typescript_type = "VeloQrError | null")]
+    pub type MaybeErrorJs;
+    #[wasm_bindgen(typescript_type = "AbortSignal")]
+    pub type AbortSignalJs;
+    #[wasm_bindgen(method, getter)]
+    fn aborted(this: &AbortSignalJs) -> bool;
+
+    // Global in both windows and workers
+    #[wasm_bindgen(js_name = setTimeout)]
+    fn set_timeout(handler: &JsValue, delay_ms: i32);
+}
+
+/// Deserialize an options object, `undefined`/`null` meaning the defaults
+fn options_from_js<T: serde::de::DeserializeOwned + Default>(
+    options: Option<impl Into<JsValue>>,
+) -> Result<T, JsValue> {
+    let options: JsValue = match options {
+        Some(options) => options.into(),
+        None => return Ok(T::default()),
+    };
+    if options.is_undefined() || options.is_null() {
+        return Ok(T::default());
+    }
+    serde_wasm_bindgen::from_value(options)
+        .map_err(|e| VeloQrError::InvalidOptions(e.to_string()).into())
+}
+
+// ==================== Logging ====================
+
+// Console fallback when no callback is set, only in debug builds
+#[cfg(debug_assertions)]
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = console)]
+    fn log(s: &str);
+}
+
+thread_local! {
+    static LOG_CALLBACK: RefCell<Option<js_sys::Function>> = const { RefCell::new(None) };
+}
+
+/// Log event passed to the `set_log_callback` callback
+#[derive(Serialize)]
+struct LogEvent<'a> {
+    level: &'a str,
+    module: &'a str,
+    message: &'a str,
+}
+
+/// Receive log events as `{ level, module, message }` objects, also in
+/// release builds. Nothing is emitted until `set_log_level` enables a level
+/// (debug builds start at `debug`); `null` removes the callback.
+#[wasm_bindgen]
+pub fn set_log_callback(callback: Option<js_sys::Function>) {
+    LOG_CALLBACK.with(|slot| *slot.borrow_mut() = callback);
+}
+
+/// Set the log verbosity: `off` (the release default), `error`, `warn`,
+/// `info` or `debug`. Anything else is an `INVALID_OPTIONS` error.
+#[wasm_bindgen]
+pub fn set_log_level(level: &str) -> Result<(), JsValue> {
+    let level = LogLevel::parse(level)
+        .ok_or_else(|| VeloQrError::InvalidOptions(format!("unknown log level {:?}", level)))?;
+    logging::set_log_level(level);
+    Ok(())
+}
+
+/// Deliver an enabled event to the callback, or the console in debug builds
+pub(crate) fn forward_log(level: LogLevel, module: &str, message: &str) {
+    // Cloned out so the callback may itself call back into the module
+    let callback = LOG_CALLBACK.with(|slot| slot.borrow().clone());
+    let Some(callback) = callback else {
+        #[cfg(debug_assertions)]
+        log(&format!("[{}] {}: {}", level.name(), module, message));
+        return;
+    };
+
+    let event = LogEvent {
+        level: level.name(),
+        module,
+        message,
+    };
+    // A throwing or unserializable callback must not fail the decode
+    if let Ok(event) = to_js_value(&event) {
+        let _ = callback.call1(&JsValue::NULL, &event);
+    }
+}
+
+// ==================== QR Decoding ====================
+
+/// Decode QR codes from image data (RGBA format)
+/// Returns a JSON string containing an array of detected QR codes.
+/// `stride_bytes` is the distance between row starts for padded frames
+/// (e.g. `VideoFrame.copyTo` layouts); omit it for packed `width * 4` rows.
+/// Every other RGBA entry point takes the same trailing argument (for
+/// `decode_qr_async`, after `signal`); `QrScanner` has a `stride_bytes`
+/// property and `decode_qr_batch` images an optional `stride_bytes` field.
+#[wasm_bindgen]
+pub fn decode_qr_from_image(
+    image_data: &[u8],
+    width: u32,
+    height: u32,
+    stride_bytes: Option<u32>,
+) -> Result<QRCodeResultArray, JsValue> {
+    to_typed(&decode_rgba(image_data, width, height, stride_bytes)?)
+}
+
+/// `decode_qr_from_image` returning a `JsonEnvelope<QRCodeResult[]>` string
+#[wasm_bindgen]
+pub fn decode_qr_from_image_json(
+    image_data: &[u8],
+    width: u32,
+    height: u32,
+    stride_bytes: Option<u32>,
+) -> Result<String, JsValue> {
+    to_json(&decode_rgba(image_data, width, height, stride_bytes)?)
+}
+
+/// `decode_qr_from_image` as the CBOR envelope of `qr::results_to_cbor`:
+/// a map `{ "schema_version": 1, "data": [...] }` with the same fields as
+/// `QRCodeResult`, byte fields as CBOR byte strings
+#[cfg(feature = "cbor")]
+#[wasm_bindgen]
+pub fn decode_qr_from_image_cbor(
+    image_data: &[u8],
+    width: u32,
+    height: u32,
+    stride_bytes: Option<u32>,
+) -> Result<Vec<u8>, JsValue> {
+    Ok(qr::results_to_cbor(&decode_rgba(image_data, width, height, stride_bytes)?)?)
+}
+
+fn decode_rgba(
+    image_data: &[u8],
+    width: u32,
+    height: u32,
+    stride_bytes: Option<u32>,
+) -> Result<Vec<QRCodeResult>, VeloQrError> {
+    log_debug!("Processing image: {}x{}", width, height);
+
+    // Convert RGBA to grayscale
+    let gray_image = qr::rgba_to_gray_with_stride(image_data, width, height, stride_bytes)?;
+
+    Ok(qr::decode_image(gray_image))
+}
+
+/// Decode like `decode_qr_from_image` without blocking the event loop for
+/// the whole frame: the work runs in steps (detection, then one grid each)
+/// on zero-delay timers. The promise resolves to the same results and
+/// rejects with the same error objects; an aborted `signal` stops the
+/// remaining steps and rejects with `ABORTED`.
+#[wasm_bindgen]
+pub fn decode_qr_async(
+    image_data: &[u8],
+    width: u32,
+    height: u32,
+    signal: Option<AbortSignalJs>,
+    stride_bytes: Option<u32>,
+) -> QRCodeResultPromise {
+    log_debug!("Processing image asynchronously: {}x{}", width, height);
+
+    // The input is only borrowed for this call, so convert it up front
+    let mut converted = Some(qr::rgba_to_gray_with_stride(image_data, width, height, stride_bytes));
+    let mut signal = Some(signal);
+    // The executor runs synchronously, exactly once
+    let promise = js_sys::Promise::new(&mut |resolve, reject| {
+        match converted.take().expect("executor runs once") {
+            Ok(gray_image) => {
+                let staged = qr::StagedDecode::new(gray_image);
+                schedule_step(staged, resolve, reject, signal.take().flatten());
+            }
+            Err(e) => {
+                let _ = reject.call1(&JsValue::NULL, &e.into());
+            }
+        }
+    });
+
+    promise.unchecked_into()
+}
+
+/// Run the next `decode_qr_async` step on a zero-delay timer, settling the
+/// promise after the last one
+fn schedule_step(
+    mut staged: qr::StagedDecode,
+    resolve: js_sys::Function,
+    reject: js_sys::Function,
+    signal: Option<AbortSignalJs>,
+) {
+    let step = Closure::once_into_js(move || {
+        if signal.as_ref().is_some_and(AbortSignalJs::aborted) {
+            let _ = reject.call1(&JsValue::NULL, &VeloQrError::Aborted.into());
+        } else if staged.step() {
+            schedule_step(staged, resolve, reject, signal);
+        } else {
+            let _ = match to_js_value(&staged.into_results()) {
+                Ok(results) => resolve.call1(&JsValue::NULL, &results),
+                Err(e) => reject.call1(&JsValue::NULL, &e),
+            };
+        }
+    });
+    set_timeout(&step, 0);
+}
+
+/// Decode the symbologies named in `formats` (`"qr"`, `"datamatrix"`,
+/// `"aztec"`, `"microqr"`, `"ean13"`, `"upca"`, `"code128"`, `"pdf417"`) from RGBA
+/// image data; an empty list means every symbology in this build. Each result names its
+/// symbology in `symbology`.
+#[wasm_bindgen]
+pub fn decode_symbols(
+    image_data: &[u8],
+    width: u32,
+    height: u32,
+    formats: Vec<String>,
+    stride_bytes: Option<u32>,
+) -> Result<QRCodeResultArray, JsValue> {
+    log_debug!("Processing image for {:?}: {}x{}", formats, width, height);
+
+    let formats = formats
+        .iter()
+        .map(|name| {
+            Symbology::parse(name)
+                .ok_or_else(|| VeloQrError::InvalidOptions(format!("unknown symbology {name:?}")))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let gray_image = qr::rgba_to_gray_with_stride(image_data, width, height, stride_bytes)?;
+
+    let results = qr::decode_symbols(gray_image, &formats);
+
+    to_typed(&results)
+}
+
+/// Decode QR codes from image data (RGBA format) with a `DecodeOptions` object
+/// `options` may be `undefined`/`null` to use the defaults. With
+/// `with_timings` or a `time_budget_ms` the results come wrapped as
+/// `{ results, timings, timed_out, skipped_candidates }`; `timed_out` means
+/// the budget ran out and `results` holds the codes found until then.
+#[wasm_bindgen]
+pub fn decode_qr_with_options(
+    image_data: &[u8],
+    width: u32,
+    height: u32,
+    options: Option<DecodeOptionsJs>,
+    stride_bytes: Option<u32>,
+) -> Result<MaybeTimedDecodeJs, JsValue> {
+    log_debug!("Processing image with options: {}x{}", width, height);
+
+    let options: DecodeOptions = options_from_js(options)?;
+
+    if options.with_timings || options.time_budget_ms.is_some() {
+        let timed = qr::decode_rgba_timed(image_data, width, height, stride_bytes, &options)?;
+        return to_typed(&timed);
+    }
+
+    let gray_image =
+        qr::rgba_to_gray_with_alpha(image_data, width, height, stride_bytes, &options.alpha)?;
+
+    let results = qr::decode_gray_with_options(gray_image, &options)?;
+
+    to_typed(&results)
+}
+
+/// Decode like `decode_qr_with_options`, returning `{ results, hint }`
+/// `hint` is `"too_dark"`, `"glare"` or `"low_contrast"` when nothing was found
+/// and the frame statistics explain why, `"none"` otherwise
+#[wasm_bindgen]
+pub fn decode_qr_with_hint(
+    image_data: &[u8],
+    width: u32,
+    height: u32,
+    options: Option<DecodeOptionsJs>,
+    stride_bytes: Option<u32>,
+) -> Result<HintedDecodeJs, JsValue> {
+    log_debug!("Processing image with hint: {}x{}", width, height);
+
+    let options: DecodeOptions = options_from_js(options)?;
+
+    let decoded = qr::decode_rgba_with_hint(image_data, width, height, stride_bytes, &options)?;
+
+    to_typed(&decoded)
+}
+
+/// Decode like `decode_qr_with_options` and locate likely MRZ bands in the
+/// same frame, returning `{ qr, mrz_regions }`. Crop the regions for OCR
+/// instead of reading the whole document; they come most confident first.
+#[wasm_bindgen]
+pub fn scan_document(
+    image_data: &[u8],
+    width: u32,
+    height: u32,
+    options: Option<DecodeOptionsJs>,
+    stride_bytes: Option<u32>,
+) -> Result<DocumentScanJs, JsValue> {
+    log_debug!("Scanning document: {}x{}", width, height);
+
+    let options: DecodeOptions = options_from_js(options)?;
+
+    let gray_image =
+        qr::rgba_to_gray_with_alpha(image_data, width, height, stride_bytes, &options.alpha)?;
+
+    let scan = qr::scan_document(gray_image, &options)?;
+
+    to_typed(&scan)
+}
+
+/// Decode QR codes trying a cascade of passes until one finds something or
+/// `budget_ms` milliseconds have passed, checked between passes and between
+/// the grids of a pass (the first pass always starts; a non-positive budget
+/// means no limit). Each result names its pass in `strategy`.
+#[wasm_bindgen]
+pub fn decode_qr_robust(
+    image_data: &[u8],
+    width: u32,
+    height: u32,
+    budget_ms: f64,
+    options: Option<RobustOptionsJs>,
+    stride_bytes: Option<u32>,
+) -> Result<QRCodeResultArray, JsValue> {
+    log_debug!("Processing image with robust cascade: {}x{}", width, height);
+
+    let options: RobustOptions = options_from_js(options)?;
+
+    let gray_image = qr::rgba_to_gray_with_stride(image_data, width, height, stride_bytes)?;
+
+    let results = qr::decode_gray_robust(&gray_image, budget_ms, &options, timing::now_ms);
+
+    to_typed(&results)
+}
+
+/// Decode QR codes from image data (RGBA format), also reporting unreadable grids
+/// Returns `{ results, failed }` so callers can tell "no QR" from "QR found but unreadable"
+#[wasm_bindgen]
+pub fn decode_qr_with_failures(
+    image_data: &[u8],
+    width: u32,
+    height: u32,
+    stride_bytes: Option<u32>,
+) -> Result<DecodeReportJs, JsValue> {
+    log_debug!("Processing image with failure report: {}x{}", width, height);
+
+    let mut luma =
+        qr::rgba_to_gray_with_stride(image_data, width, height, stride_bytes)?.into_raw();
+
+    let mut report = DecodeReport::default();
+    qr::decode_luma_into(&mut luma, width, height, &mut report.results, Some(&mut report.failed));
+
+    to_typed(&report)
+}
+
+/// Locate QR grids without decoding them
+/// Cheaper than a full decode; useful for drawing a viewfinder highlight early
+#[wasm_bindgen]
+pub fn detect_qr_bounds(
+    image_data: &[u8],
+    width: u32,
+    height: u32,
+    stride_bytes: Option<u32>,
+) -> Result<GridCandidateArray, JsValue> {
+    log_debug!("Detecting grids: {}x{}", width, height);
+
+    let mut luma =
+        qr::rgba_to_gray_with_stride(image_data, width, height, stride_bytes)?.into_raw();
+
+    let candidates = qr::detect_luma_candidates(&mut luma, width, height);
+
+    to_typed(&candidates)
+}
+
+/// One `decode_qr_batch` input
+#[derive(Deserialize)]
+struct BatchImage {
+    #[serde(with = "serde_bytes")]
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+    #[serde(default)]
+    stride_bytes: Option<u32>,
+}
+
+/// One `decode_qr_batch` output; `error` is set instead of failing the batch
+#[derive(Serialize)]
+struct BatchEntry {
+    results: Vec<QRCodeResult>,
+    error: Option<ErrorObject<'static>>,
+}
+
+/// Decode an array of `{ data, width, height }` RGBA images in one call.
+/// Returns one `{ results, error }` per image, in order; a bad image only sets
+/// its own `error`. Images are copied in and decoded one at a time, reusing
+/// the grayscale buffer, so memory stays bounded by the largest image.
+#[wasm_bindgen]
+pub fn decode_qr_batch(images: BatchImageArray) -> Result<BatchEntryArray, JsValue> {
+    let images: js_sys::Array = JsValue::from(images)
+        .dyn_into()
+        .map_err(|_| VeloQrError::InvalidOptions("images must be an array".to_string()))?;
+    log_debug!("Processing batch of {} images", images.length());
+
+    let mut scanner = QrScanner::new(0, 0);
+    let entries: Vec<BatchEntry> = images
+        .iter()
+        .map(|image| {
+            let decoded = serde_wasm_bindgen::from_value::<BatchImage>(image)
+                .map_err(|e| VeloQrError::InvalidOptions(e.to_string()))
+                .and_then(|image| {
+                    scanner.set_stride_bytes(image.stride_bytes);
+                    let results = scanner.scan(&image.data, Some(image.width), Some(image.height))?;
+                    Ok(results.to_vec())
+                });
+            match decoded {
+                Ok(results) => BatchEntry { results, error: None },
+                Err(e) => BatchEntry {
+                    results: Vec::new(),
+                    error: Some(ErrorObject::new(&e)),
+                },
+            }
+        })
+        .collect();
+
+    to_typed(&entries)
+}
+
+/// Set the largest width x height accepted by the image entry points;
+/// 0 restores the default of 64 MP. Larger frames fail with `IMAGE_TOO_LARGE`.
+#[wasm_bindgen]
+pub fn set_max_image_pixels(max_pixels: u32) {
+    qr::set_max_image_pixels(max_pixels);
+}
+
+/// Measure blur and exposure of an RGBA frame without running detection
+/// Returns `{ sharpness, mean_luma, contrast }` sampled on every 4th pixel
+#[wasm_bindgen]
+pub fn assess_frame(
+    image_data: &[u8],
+    width: u32,
+    height: u32,
+    stride_bytes: Option<u32>,
+) -> Result<FrameQualityJs, JsValue> {
+    let gray_image = qr::rgba_to_gray_with_stride(image_data, width, height, stride_bytes)?;
+
+    to_typed(&qr::assess_gray(&gray_image))
+}
+
+/// Count the pixels of an RGBA frame per luma value (256 bins), using the
+/// conversion the detector sees, for manual exposure control
+#[wasm_bindgen]
+pub fn compute_luma_histogram(
+    image_data: &[u8],
+    width: u32,
+    height: u32,
+    stride_bytes: Option<u32>,
+) -> Result<Vec<u32>, JsValue> {
+    let histogram = qr::rgba_luma_histogram(image_data, width, height, stride_bytes)?;
+
+    Ok(histogram.stats().histogram)
+}
+
+/// `compute_luma_histogram` plus its 5th/50th/95th percentiles and the
+/// fraction of pixels clipped at 255. `decode_qr_with_hint` returns the same
+/// object with `return_luma_stats`, without a second pass over the frame.
+#[wasm_bindgen]
+pub fn compute_luma_stats(
+    image_data: &[u8],
+    width: u32,
+    height: u32,
+    stride_bytes: Option<u32>,
+) -> Result<LumaStatsJs, JsValue> {
+    let histogram = qr::rgba_luma_histogram(image_data, width, height, stride_bytes)?;
+
+    to_typed(&histogram.stats())
+}
+
+/// Lowercase hex SHA-256 of `data`, matching `payload_hash` of the results
+/// decoded from it; server-side code can compute the same value
+#[wasm_bindgen]
+pub fn hash_payload(data: &[u8]) -> String {
+    qr::hash_payload(data)
+}
+
+/// Classify a decoded QR payload: WiFi, vCard/MeCard, vEvent, otpauth, email,
+/// phone, SMS, geo or URL. Returns a `{ type, ... }` object, `{ type: "text" }` otherwise.
+/// URLs carry their `classify_url_safety` flags as `url_safety`; events their
+/// `start`/`end` as ISO 8601 `{ value, tzid, floating, all_day }` and other
+/// properties under `properties`.
+#[wasm_bindgen]
+pub fn classify_qr_payload(data: &str) -> Result<JsValue, JsValue> {
+    to_js_value(&qr::classify_payload(data))
+}
+
+/// Build the `WIFI:T:WPA;S:...;P:...;H:true;;` payload of a network, the
+/// reverse of the `wifi` type of `classify_qr_payload`. `security` is
+/// `WEP`, `WPA`, `SAE` or `nopass`; `\`, `;`, `,`, `"` and `:` are escaped,
+/// and control characters are an `INVALID_PAYLOAD` error.
+#[wasm_bindgen]
+pub fn encode_wifi_payload(
+    ssid: &str,
+    password: &str,
+    security: &str,
+    hidden: bool,
+) -> Result<String, JsValue> {
+    Ok(qr::encode_wifi_payload(ssid, password, security, hidden)?)
+}
+
+/// Advisory safety flags of a URL of any scheme, for kiosks that must not
+/// open whatever a code holds: the scheme (`javascript:`, `data:`, `file:`
+/// flagged), IP, localhost and private-network hosts, `user:pass@`
+/// credentials, punycode and mixed-script host labels, excessive length.
+/// Flags only: the policy is the caller's.
+#[wasm_bindgen]
+pub fn classify_url_safety(data: &str) -> Result<JsValue, JsValue> {
+    to_js_value(&qr::classify_url_safety(data))
+}
+
+/// Parse an `otpauth://totp/Issuer:account?secret=...` provisioning URI:
+/// `kind`, `issuer` and `account` (the `issuer` parameter wins over the label
+/// prefix, `issuer_mismatch` set when they differ), the base32 `secret` and
+/// its `secret_bytes`, `algorithm`, `digits` and `period` or `counter`.
+/// No secret is a `MISSING_OTP_SECRET` error, anything else malformed
+/// `INVALID_PAYLOAD`.
+#[wasm_bindgen]
+pub fn parse_otpauth(uri: &str) -> Result<JsValue, JsValue> {
+    to_js_value(&qr::parse_otpauth(uri)?)
+}
+
+/// Build a provisioning URI from `{ kind, issuer, account, secret, algorithm,
+/// digits, period, counter }`, the reverse of `parse_otpauth`. Only `account`
+/// and `secret` are required; the label and issuer are percent-encoded.
+#[wasm_bindgen]
+pub fn build_otpauth(params: JsValue) -> Result<String, JsValue> {
+    let params: qr::OtpauthParams = serde_wasm_bindgen::from_value(params)
+        .map_err(|e| VeloQrError::InvalidOptions(e.to_string()))?;
+    Ok(qr::build_otpauth(&params)?)
+}
+
+/// Parse an EMVCo merchant-presented payment QR (VietQR, PromptPay, SGQR, ...)
+/// Returns the well-known fields plus every TLV record keyed by tag under `tags`.
+/// A wrong CRC only clears `crc_valid`; malformed TLV is an `INVALID_PAYLOAD` error.
+#[wasm_bindgen]
+pub fn parse_emvco_qr(data: &str) -> Result<JsValue, JsValue> {
+    to_js_value(&qr::parse_emvco(data)?)
+}
+
+/// Parse a SEPA credit transfer QR (EPC069-12, "GiroCode"): BIC, beneficiary
+/// name, IBAN (`iban_valid` for the mod-97 checksum), `EUR` amount, purpose
+/// and remittance. Version 001 and 002, LF or CRLF. Breaches of the guideline
+/// are listed in `violations` (`valid` when none); only a payload without the
+/// `BCD` service tag or an IBAN line is an `INVALID_PAYLOAD` error.
+#[wasm_bindgen]
+pub fn parse_epc_qr(data: &str) -> Result<JsValue, JsValue> {
+    to_js_value(&qr::parse_epc(data)?)
+}
+
+/// Parse GS1 element strings, bracketed `(01)...(17)...` or raw with FNC1/GS
+/// separators as decoded from a GS1 QR (`gs1: true` on the decode result).
+/// GTIN/SSCC/GLN check digits are reported per element; dates come back as ISO.
+#[wasm_bindgen]
+pub fn parse_gs1(data: &str) -> Result<JsValue, JsValue> {
+    to_js_value(&qr::parse_gs1(data)?)
+}
+
+/// Parse the AAMVA DL/ID card payload of a driver license PDF417, given as
+/// the decoded bytes. Returns the name, birth/expiry/issue dates (ISO),
+/// license number and address of the DL or ID subfile, plus every subfile's
+/// elements keyed by element ID under `subfiles`.
+#[wasm_bindgen]
+pub fn parse_aamva(data: &[u8]) -> Result<JsValue, JsValue> {
+    to_js_value(&qr::parse_aamva(data)?)
+}
+
+/// Parse the IATA Bar Coded Boarding Pass text of an Aztec, PDF417 or QR
+/// boarding pass: passenger name, every leg's flight, seat and date (ISO)
+/// and the optional conditional items, bag tags and security data.
+#[wasm_bindgen]
+pub fn parse_iata_bcbp(text: &str) -> Result<JsValue, JsValue> {
+    to_js_value(&qr::parse_iata_bcbp(text)?)
+}
+
+/// Parse an ICAO Visible Digital Seal (visas, emergency travel documents)
+/// from the bytes of its barcode: header, document features (decoded for
+/// known profiles, as bytes otherwise), and the `signature` (`r || s`) with
+/// the `signed_data_span` it covers, for verifying with WebCrypto.
+#[wasm_bindgen]
+pub fn parse_vds(data: &[u8]) -> Result<JsValue, JsValue> {
+    to_js_value(&qr::parse_vds(data)?)
+}
+
+/// Decode the raw bytes of a seal feature as `"c40"`, `"date"` (YYYY-MM-DD)
+/// or `"masked_date"` (YYYY-MM-DD with `x` for unknown digits)
+#[wasm_bindgen]
+pub fn decode_vds_value(bytes: &[u8], encoding: &str) -> Result<String, JsValue> {
+    Ok(qr::decode_vds_value(bytes, encoding)?)
+}
+
+/// Decode QR codes from single-channel luminance data (one byte per pixel)
+/// Returns the same array of detected QR codes as `decode_qr_from_image`
+#[wasm_bindgen]
+pub fn decode_qr_from_gray(
+    image_data: &[u8],
+    width: u32,
+    height: u32,
+) -> Result<QRCodeResultArray, JsValue> {
+    log_debug!("Processing grayscale image: {}x{}", width, height);
+
+    let gray_image = qr::luma_to_gray(image_data, width, height)?;
+
+    let results = qr::decode_image(gray_image);
+
+    to_typed(&results)
+}
+
+/// Decode QR codes from the Y (luminance) plane of an NV12/I420 camera frame
+/// `stride` is the number of bytes per row in the plane, which may exceed `width`.
+/// Chroma planes may follow the Y plane in the buffer and are ignored.
+#[wasm_bindgen]
+pub fn decode_qr_from_yuv(
+    y_plane: &[u8],
+    width: u32,
+    height: u32,
+    stride: u32,
+) -> Result<QRCodeResultArray, JsValue> {
+    log_debug!("Processing YUV frame: {}x{} (stride {})", width, height, stride);
+
+    let gray_image = qr::y_plane_to_gray(y_plane, width, height, stride)?;
+
+    let results = qr::decode_image(gray_image);
+
+    to_typed(&results)
+}
+
+/// Decode QR codes from an encoded image file (PNG or JPEG bytes)
+/// Returns the same array of detected QR codes as `decode_qr_from_image`
+#[wasm_bindgen]
+pub fn decode_qr_from_encoded(bytes: &[u8]) -> Result<QRCodeResultArray, JsValue> {
+    log_debug!("Processing encoded image: {} bytes", bytes.len());
+
+    let gray_image = qr::encoded_to_gray(bytes)?;
+
+    let results = qr::decode_image(gray_image);
+
+    to_typed(&results)
+}
+
+/// Decode QR codes from a `data:image/png;base64,...` (or JPEG) URL
+/// Whitespace and URL-safe base64 are accepted. Errors are `INVALID_DATA_URL`,
+/// `UNSUPPORTED_MEDIA_TYPE` or `INVALID_BASE64` before any image decoding.
+#[wasm_bindgen]
+pub fn decode_qr_from_data_url(url: &str) -> Result<QRCodeResultArray, JsValue> {
+    log_debug!("Processing data URL: {} bytes", url.len());
+
+    let gray_image = qr::data_url_to_gray(url)?;
+
+    let results = qr::decode_image(gray_image);
+
+    to_typed(&results)
+}
+
+/// Decode QR codes inside a region of interest of an RGBA image
+/// The region is clamped to the image; returned bounds are in full-frame coordinates.
+// Positional, like the other RGBA entry points
+#[allow(clippy::too_many_arguments)]
+#[wasm_bindgen]
+pub fn decode_qr_in_roi(
+    image_data: &[u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    roi_width: u32,
+    roi_height: u32,
+    stride_bytes: Option<u32>,
+) -> Result<QRCodeResultArray, JsValue> {
+    log_debug!(
+        "Processing ROI {}x{} at ({}, {}) of {}x{}",
+        roi_width, roi_height, x, y, width, height
+    );
+
+    let gray_image = qr::rgba_to_gray_with_stride(image_data, width, height, stride_bytes)?;
+
+    let results = qr::decode_gray_in_roi(&gray_image, x, y, roi_width, roi_height)?;
+
+    to_typed(&results)
+}
+
+/// Initialize the WASM module
+#[wasm_bindgen(start)]
+pub fn init() {
+    std::panic::set_hook(Box::new(record_panic));
+    log_debug!("QR Scanner WASM module initialized");
+}
+
+// ==================== Threads ====================
+
+/// Start `threads` Web Workers for the rayon pool of the `threads` build,
+/// e.g. `await init_thread_pool(navigator.hardwareConcurrency)`. Call it
+/// once, before the first decode; until then everything runs on the
+/// calling thread. Only events logged on that thread reach the callback.
+#[cfg(feature = "threads")]
+#[wasm_bindgen]
+pub fn init_thread_pool(threads: usize) -> js_sys::Promise {
+    wasm_bindgen_rayon::init_thread_pool(threads)
+}
+
+// ==================== Panics ====================
+
+// WASM builds abort on panic, so a panic cannot be caught and returned
+// as an error: the call traps with `RuntimeError: unreachable`. The hook
+// keeps the message so the caller can fetch it after the trap.
+fn record_panic(info: &std::panic::PanicHookInfo<'_>) {
+    #[cfg(feature = "panic_hook")]
+    console_error_panic_hook::hook(info);
+    #[cfg(not(feature = "panic_hook"))]
+    web_sys::console::error_1(&JsValue::from_str(&info.to_string()));
+    panics::record(info);
+}
+
+/// The panic behind the last `RuntimeError: unreachable`, as an error object
+/// with code `INTERNAL_PANIC`, or `null` if nothing panicked since the last call.
+/// Call it from the `catch` of any export: the trap itself carries no message.
+/// The instance should be re-created after a panic: its state may be inconsistent.
+#[wasm_bindgen]
+pub fn take_last_panic() -> MaybeErrorJs {
+    let error = match panics::take_last_panic() {
+        Some(error) => error.into(),
+        None => JsValue::NULL,
+    };
+    error.unchecked_into()
+}
+
+/// Panic with `message`, to exercise `take_last_panic` (debug builds only)
+#[cfg(debug_assertions)]
+#[doc(hidden)]
+#[wasm_bindgen]
+pub fn debug_panic(message: &str) {
+    panic!("{}", message);
+}
+
+#[wasm_bindgen]
+impl QrScanner {
+    /// Decode QR codes from an RGBA frame.
+    /// Pass `width`/`height` when the frame size changes; buffers are
+    /// reallocated transparently.
+    #[wasm_bindgen(js_name = scan)]
+    pub fn scan_js(
+        &mut self,
+        rgba: &[u8],
+        width: Option<u32>,
+        height: Option<u32>,
+    ) -> Result<QRCodeResultArray, JsValue> {
+        to_typed(self.scan(rgba, width, height)?)
+    }
+
+    /// Like `scan`, but only returns codes whose payload has not been
+    /// emitted within the last `ttl_ms` milliseconds (as of `now_ms`).
+    /// Codes are matched by payload, so a code moving in the frame is not re-reported.
+    #[wasm_bindgen(js_name = scan_dedup)]
+    pub fn scan_dedup_js(
+        &mut self,
+        rgba: &[u8],
+        ttl_ms: f64,
+        now_ms: f64,
+        width: Option<u32>,
+        height: Option<u32>,
+    ) -> Result<QRCodeResultArray, JsValue> {
+        to_typed(self.scan_dedup(rgba, ttl_ms, now_ms, width, height)?)
+    }
+
+    /// Like `scan`, but skips detection on frames `should_scan` rejects:
+    /// nearly identical to the last scanned frame, which found nothing.
+    /// Skipped frames return no codes and count in `frames_skipped`.
+    #[wasm_bindgen(js_name = scan_adaptive)]
+    pub fn scan_adaptive_js(
+        &mut self,
+        rgba: &[u8],
+        width: Option<u32>,
+        height: Option<u32>,
+    ) -> Result<QRCodeResultArray, JsValue> {
+        to_typed(self.scan_adaptive(rgba, width, height)?)
+    }
+
+    /// Like `scan`, but reports the frame as track changes: codes that
+    /// appeared, tracked codes seen again, and tracks lost after
+    /// `max_missed_frames` scans without their code.
+    #[wasm_bindgen(js_name = scan_tracked)]
+    pub fn scan_tracked_js(
+        &mut self,
+        rgba: &[u8],
+        width: Option<u32>,
+        height: Option<u32>,
+    ) -> Result<TrackEventsJs, JsValue> {
+        to_typed(&self.scan_tracked(rgba, width, height)?)
+    }
+
+    /// Address of a `width * height * 4` byte RGBA buffer inside WASM memory
+    /// (`stride_bytes * height` with a stride), allocated on first use. Write a frame through
+    /// `new Uint8Array(memory.buffer, ptr, len)` and call `scan_in_place` to
+    /// skip copying the frame across the boundary.
+    ///
+    /// The view must be recreated from a fresh pointer after `set_frame_size`
+    /// or `stride_bytes` changes the layout (the buffer may move) and whenever
+    /// `memory.buffer` is a different object than the one the view was made
+    /// from: any allocation can grow WASM memory, which detaches old views.
+    pub fn input_buffer_ptr(&mut self) -> Result<u32, JsValue> {
+        Ok(self.input_buffer()?.as_mut_ptr() as usize as u32)
+    }
+
+    /// Length in bytes of the buffer at `input_buffer_ptr`
+    pub fn input_buffer_len(&mut self) -> Result<u32, JsValue> {
+        Ok(self.input_buffer()?.len() as u32)
+    }
+
+    /// Decode the frame JS wrote into the input buffer
+    #[wasm_bindgen(js_name = scan_in_place)]
+    pub fn scan_in_place_js(&mut self) -> Result<QRCodeResultArray, JsValue> {
+        to_typed(self.scan_in_place()?)
+    }
+}
+
+#[wasm_bindgen]
+impl StructuredAppendAssembler {
+    /// Add a decoded `QRCodeResult`; returns true once every part is present.
+    /// Results without a structured-append header, or from another sequence
+    /// than the first part pushed, are rejected with `INVALID_PAYLOAD`.
+    #[wasm_bindgen(js_name = push)]
+    pub fn push_js(&mut self, result: QRCodeResultJs) -> Result<bool, JsValue> {
+        let result: QRCodeResult = serde_wasm_bindgen::from_value(result.into())
+            .map_err(|e| VeloQrError::InvalidOptions(e.to_string()))?;
+        Ok(self.push_result(&result)?)
+    }
+
+    /// The joined payload, or `null` while parts are missing.
+    /// A payload whose XOR does not match the parity byte is an `INVALID_PAYLOAD` error.
+    pub fn assembled(&self) -> Result<JsValue, JsValue> {
+        match self.assemble()? {
+            Some(payload) => to_js_value(&payload),
+            None => Ok(JsValue::NULL),
+        }
+    }
+}
+
+/// Encode `data` as a QR code in the smallest version that fits.
+/// `ecc` is one of L, M, Q or H. Returns `{ size, version, modules }`;
+/// payloads beyond version 40 capacity are a `PAYLOAD_TOO_LONG` error.
+#[wasm_bindgen]
+pub fn encode_qr(data: &str, ecc: &str) -> Result<JsValue, JsValue> {
+    to_js_value(&qr::encode_matrix(data, ecc)?)
+}
+
+/// Encode `data` as a grayscale PNG, `scale` pixels per module with a quiet
+/// zone of `margin` modules (4 is the spec minimum). Images wider than
+/// 16384 px or a zero scale are rejected with `IMAGE_TOO_LARGE`/`INVALID_OPTIONS`.
+#[wasm_bindgen]
+pub fn encode_qr_png(data: &str, ecc: &str, scale: u32, margin: u32) -> Result<Vec<u8>, JsValue> {
+    Ok(qr::encode_png(data, ecc, scale, margin)?)
+}
+
+/// Encode `data` as an SVG document with a `margin`-module quiet zone.
+/// Dark modules are drawn as one path, merging horizontal runs, over a
+/// background rect. Colors are hex (`#rgb`, `#rrggbb`, with optional alpha)
+/// or CSS color keywords; anything else is an `INVALID_OPTIONS` error.
+#[wasm_bindgen]
+pub fn encode_qr_svg(
+    data: &str,
+    ecc: &str,
+    margin: u32,
+    dark: &str,
+    light: &str,
+) -> Result<String, JsValue> {
+    Ok(qr::encode_svg(data, ecc, margin, dark, light)?)
+}
+
+/// Parse MRZ text lines to extract structured data
+/// `reference_date` is "today" for century inference, expiry and age: either epoch
+/// milliseconds or a YYYY-MM-DD string. Defaults to the current date.
+/// `options` is an `MrzOptions` object, or `undefined`/`null` for the defaults.
+#[wasm_bindgen]
+pub fn parse_mrz_text(
+    mrz_text: &str,
+    reference_date: Option<ReferenceDateJs>,
+    options: Option<MrzOptionsJs>,
+) -> Result<MRZResultJs, JsValue> {
+    to_typed(&parse_mrz(mrz_text, reference_date, options)?)
+}
+
+/// `parse_mrz_text` returning a `JsonEnvelope<MRZResult>` string
+#[wasm_bindgen]
+pub fn parse_mrz_text_json(
+    mrz_text: &str,
+    reference_date: Option<ReferenceDateJs>,
+    options: Option<MrzOptionsJs>,
+) -> Result<String, JsValue> {
+    to_json(&parse_mrz(mrz_text, reference_date, options)?)
+}
+
+fn parse_mrz(
+    mrz_text: &str,
+    reference_date: Option<ReferenceDateJs>,
+    options: Option<MrzOptionsJs>,
+) -> Result<MRZResult, JsValue> {
+    let reference_date: JsValue = reference_date.map_or(JsValue::UNDEFINED, Into::into);
+
+    let reference = if reference_date.is_undefined() || reference_date.is_null() {
+        CivilDate::today()
+    } else if let Some(millis) = reference_date.as_f64() {
+        CivilDate::from_epoch_millis(millis)
+    } else {
+        reference_date
+            .as_string()
+            .and_then(|date| CivilDate::parse_iso(&date))
+            .ok_or_else(|| {
+                VeloQrError::InvalidOptions(
+                    "reference_date must be epoch milliseconds or YYYY-MM-DD".to_string(),
+                )
+            })?
+    };
+
+    let options: MrzOptions = options_from_js(options)?;
+
+    Ok(mrz::parse_with_options(mrz_text, reference, &options)?)
+}
+
+#[wasm_bindgen]
+impl MrzAccumulator {
+    /// The fused `MRZResult`, or `null` until it passes every check digit or
+    /// `min_frames` frames have been pushed. `confidence` is scaled by how
+    /// strongly the frames agreed on each character.
+    #[wasm_bindgen(js_name = best)]
+    pub fn best_js(&self) -> Result<MaybeMRZResultJs, JsValue> {
+        match self.best() {
+            Some(result) => to_typed(&result),
+            None => Ok(JsValue::NULL.unchecked_into()),
+        }
+    }
+}
+
+/// Validate MRZ lines (an array of 2 or 3 strings) whose fields another OCR
+/// engine already extracted: detected format, every check digit as read and
+/// as computed, characters out of place and the composite verdict, from the
+/// same code as `parse_mrz_text`
+#[wasm_bindgen]
+pub fn validate_mrz(lines: JsValue) -> Result<MrzValidationJs, JsValue> {
+    let lines: Vec<String> = serde_wasm_bindgen::from_value(lines)
+        .map_err(|e| VeloQrError::InvalidOptions(e.to_string()))?;
+
+    to_typed(&mrz::validate_mrz_lines(&lines)?)
+}
+
+/// Build TD1, TD2 or TD3 lines from structured fields, computing every check digit
+#[wasm_bindgen]
+pub fn generate_mrz(fields: JsValue, format: &str) -> Result<JsValue, JsValue> {
+    let fields: MrzFields = serde_wasm_bindgen::from_value(fields)
+        .map_err(|e| VeloQrError::InvalidOptions(e.to_string()))?;
+
+    to_js_value(&mrz::generate_mrz_lines(&fields, format)?)
+}
+
+/// ICAO 9303 Part 3 transliteration of a name into MRZ characters, e.g.
+/// `Müller-Weiß` -> `MUELLER<WEISS`. Characters without a mapping are dropped.
+#[wasm_bindgen]
+pub fn transliterate_mrz_name(name: &str) -> String {
+    mrz::transliterate_name(name).0
+}
+
+/// BAC/PACE key seed: the first 16 bytes of SHA-1 over document number,
+/// birth date and expiry date, each followed by its check digit.
+/// Accepts MRZ text or an object with those three fields (e.g. an `MRZResult`).
+#[wasm_bindgen]
+pub fn derive_bac_key_seed(mrz_result_or_text: JsValue) -> Result<Vec<u8>, JsValue> {
+    let fields = bac_fields_from_js(mrz_result_or_text)?;
+    Ok(mrz::bac_key_seed(&fields)?.to_vec())
+}
+
+/// Like `derive_bac_key_seed`, also returning the derived `k_enc` and `k_mac`
+#[wasm_bindgen]
+pub fn derive_bac_keys(mrz_result_or_text: JsValue) -> Result<JsValue, JsValue> {
+    let fields = bac_fields_from_js(mrz_result_or_text)?;
+
+    to_js_value(&mrz::derive_bac_keys(&fields)?)
+}
+
+fn bac_fields_from_js(value: JsValue) -> Result<BacFields, VeloQrError> {
+    match value.as_string() {
+        Some(text) => Ok(mrz::bac_fields_from_text(&text)?),
+        None => serde_wasm_bindgen::from_value(value)
+            .map_err(|e| VeloQrError::InvalidOptions(e.to_string())),
+    }
+}
+
+// ==================== Image Processing Implementation ====================
+
+#[wasm_bindgen]
+pub fn crop_image(
+    image_data: &[u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    crop_width: u32,
+    crop_height: u32,
+) -> Result<Vec<u8>, JsValue> {
+    qr::image_buffer_len(width, height, 4)?;
+    let img_buffer = match RgbaImage::from_raw(width, height, image_data.to_vec()) {
+        Some(buffer) => buffer,
+        None => return Err(VeloQrError::InvalidImageBuffer.into()),
+    };
+    let img = DynamicImage::ImageRgba8(img_buffer);
+
+    let cropped_img = imageops::crop_imm(&img, x, y, crop_width, crop_height).to_image();
+
+    Ok(cropped_img.into_raw())
+}
+
+#[wasm_bindgen]
+pub fn sharpen_image(
+    image_data: &[u8],
+    width: u32,
+    height: u32,
+    amount: f32,
+) -> Result<Vec<u8>, JsValue> {
+    qr::image_buffer_len(width, height, 4)?;
+    let img_buffer = match RgbaImage::from_raw(width, height, image_data.to_vec()) {
+        Some(buffer) => buffer,
+        None => return Err(VeloQrError::InvalidImageBuffer.into()),
+    };
+    let img = DynamicImage::ImageRgba8(img_buffer);
+
+    // The unsharpen function in the image crate is actually a sharpen function.
+    // The amount is the sigma value for the gaussian blur, and threshold is for the mask.
+    let sharpened_img = imageops::unsharpen(&img, amount, 1);
+
+    Ok(sharpened_img.into_raw())
+}
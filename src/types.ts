@@ -15,10 +15,61 @@ export enum SimpleCameraFacing {
 export type CameraFacing = CameraFacingMode | `${CameraFacingMode}`;
 export type SimpleFacing = SimpleCameraFacing | `${SimpleCameraFacing}`;
 
+export type Symbology = 'qr' | 'datamatrix' | 'aztec' | 'microqr' | 'ean13' | 'upca' | 'code128'
+  | 'pdf417';
+
 export interface QRCodeResult {
   data: string;
+  // Data Matrix: 1-based index of the 30 ECC 200 sizes; Aztec: layers, negative for compact;
+  // Micro QR: -1 to -4 for M1 to M4; 1D: modules from the first bar to the last;
+  // PDF417: data columns
   version: number;
   bounds: [number, number][];
+  // Aztec: check words; PDF417: security level 0-8; Micro QR M1 and 1D: empty
+  ecc_level: 'L' | 'M' | 'Q' | 'H' | 'ECC200' | `${number}%` | `${number}` | '';
+  mask: number;
+  raw_bytes: Uint8Array;
+  inverted: boolean;
+  mirrored: boolean;
+  gs1: boolean;
+  strategy: string; // decode_qr_robust pass, empty for other entry points
+  orientation_degrees: number; // clockwise, 0 = upright
+  center: [number, number];
+  area: number;
+  angle_degrees: number; // (-180, 180], clockwise positive
+  module_size_px: number;
+  structured_append?: StructuredAppend;
+  encoding: string; // e.g. "UTF-8", "Shift_JIS", "ISO-8859-1"
+  encoding_error: boolean; // data is empty, payload only in raw_bytes
+  crop_png: Uint8Array; // perspective-corrected crop, empty unless return_crop
+  symbology: Symbology;
+  check_digit_valid?: boolean; // 1D only, reported even when it fails
+  corrected_codewords: number; // Reed-Solomon corrections; 0 for 1D
+  quality: number; // 1 = no corrections, toward 0 as a block nears its limit
+  payload_hash: string; // hash_payload(raw payload), lowercase hex SHA-256
+  modules?: ModuleGrid; // with return_module_grid, QR codes only
+  track_id?: number; // QrScanner track, while smoothing_alpha > 0 or from scan_tracked
+  smoothed_bounds: [number, number][]; // QrScanner smoothed corners, else empty
+}
+
+// Modules as the decoder reads them, 1 = dark, unmirrored and uninverted
+export interface ModuleGrid {
+  size: number; // modules per side
+  bits: Uint8Array; // row-major
+}
+
+export interface StructuredAppend {
+  index: number; // 0-based
+  total: number;
+  parity: number;
+}
+
+// Error object thrown by the WASM functions
+export interface VeloQrError {
+  // e.g. INVALID_BUFFER_LENGTH, NO_MRZ_LINES. A panic traps with WebAssembly.RuntimeError
+  // instead; take_last_panic() then returns it as INTERNAL_PANIC.
+  code: string;
+  message: string;
 }
 
 export interface WasmConfig {